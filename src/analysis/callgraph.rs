@@ -0,0 +1,165 @@
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// The kind of a node in a [`CallGraph`].
+#[derive(Debug, Clone, Copy)]
+pub enum CallGraphNodeKind<'a> {
+    /// A custom block, where `entity` is the index of the defining entity, or `None` for global blocks.
+    Function { entity: Option<usize>, func: &'a Function },
+    /// A script of the entity with the given index.
+    Script { entity: usize, script: &'a Script },
+}
+#[derive(Debug, Clone)]
+pub struct CallGraphNode<'a> {
+    pub kind: CallGraphNodeKind<'a>,
+    /// The nodes (always custom blocks) called by this node, in order of first call.
+    pub succs: Vec<usize>,
+    /// The nodes that call this node.
+    pub preds: Vec<usize>,
+}
+
+/// The graph of calls among the custom blocks and scripts in a [`Role`].
+///
+/// Calls inside closures are attributed to the code that creates the closure, since it may be invoked from there.
+/// Calls to unknown blocks and closures stored in variables are not represented.
+#[derive(Debug, Clone)]
+pub struct CallGraph<'a> {
+    /// All global custom blocks, followed by the custom blocks and scripts of each entity (in order).
+    pub nodes: Vec<CallGraphNode<'a>>,
+}
+impl<'a> CallGraph<'a> {
+    pub fn new(role: &'a Role) -> Self {
+        let mut nodes = vec![];
+        for func in role.funcs.iter() {
+            nodes.push(CallGraphNode { kind: CallGraphNodeKind::Function { entity: None, func }, succs: vec![], preds: vec![] });
+        }
+        for (entity, info) in role.entities.iter().enumerate() {
+            for func in info.funcs.iter() {
+                nodes.push(CallGraphNode { kind: CallGraphNodeKind::Function { entity: Some(entity), func }, succs: vec![], preds: vec![] });
+            }
+            for script in info.scripts.iter() {
+                nodes.push(CallGraphNode { kind: CallGraphNodeKind::Script { entity, script }, succs: vec![], preds: vec![] });
+            }
+        }
+        let mut res = Self { nodes };
+
+        for node in 0..res.nodes.len() {
+            let (entity, stmts) = match res.nodes[node].kind {
+                CallGraphNodeKind::Function { entity, func } => (entity, &func.stmts),
+                CallGraphNodeKind::Script { entity, script } => (Some(entity), &script.stmts),
+            };
+            let mut calls = vec![];
+            collect_calls_stmts(stmts, &mut calls);
+            for call in calls {
+                let target = match call.location {
+                    FnLocation::Global => res.find_function(None, &call.trans_name),
+                    FnLocation::Method => entity.and_then(|e| res.find_function(Some(e), &call.trans_name)),
+                };
+                if let Some(target) = target {
+                    if !res.nodes[node].succs.contains(&target) {
+                        res.nodes[node].succs.push(target);
+                        res.nodes[target].preds.push(node);
+                    }
+                }
+            }
+        }
+
+        res
+    }
+    /// Finds the node of a custom block by the index of its defining entity (or `None` for global blocks) and its translated name.
+    pub fn find_function(&self, entity: Option<usize>, trans_name: &str) -> Option<usize> {
+        self.nodes.iter().position(|x| matches!(x.kind, CallGraphNodeKind::Function { entity: e, func } if e == entity && func.trans_name == trans_name))
+    }
+    /// Gets the strongly connected components of the graph.
+    /// Components are returned in reverse topological order, i.e., every component comes after all the components it calls into.
+    pub fn sccs(&self) -> Vec<Vec<usize>> {
+        struct Tarjan<'b, 'a> {
+            graph: &'b CallGraph<'a>,
+            index: Vec<Option<usize>>,
+            lowlink: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<usize>,
+            next: usize,
+            res: Vec<Vec<usize>>,
+        }
+        impl Tarjan<'_, '_> {
+            fn visit(&mut self, node: usize) {
+                self.index[node] = Some(self.next);
+                self.lowlink[node] = self.next;
+                self.next += 1;
+                self.stack.push(node);
+                self.on_stack[node] = true;
+
+                for &succ in self.graph.nodes[node].succs.iter() {
+                    match self.index[succ] {
+                        None => {
+                            self.visit(succ);
+                            self.lowlink[node] = self.lowlink[node].min(self.lowlink[succ]);
+                        }
+                        Some(index) => if self.on_stack[succ] {
+                            self.lowlink[node] = self.lowlink[node].min(index);
+                        }
+                    }
+                }
+
+                if Some(self.lowlink[node]) == self.index[node] {
+                    let mut component = vec![];
+                    loop {
+                        let x = self.stack.pop().unwrap();
+                        self.on_stack[x] = false;
+                        component.push(x);
+                        if x == node { break }
+                    }
+                    component.sort_unstable();
+                    self.res.push(component);
+                }
+            }
+        }
+
+        let n = self.nodes.len();
+        let mut tarjan = Tarjan { graph: self, index: vec![None; n], lowlink: vec![0; n], on_stack: vec![false; n], stack: vec![], next: 0, res: vec![] };
+        for node in 0..n {
+            if tarjan.index[node].is_none() {
+                tarjan.visit(node);
+            }
+        }
+        tarjan.res
+    }
+    /// Checks if a node can (directly or indirectly) call itself.
+    pub fn is_recursive(&self, node: usize) -> bool {
+        self.nodes[node].succs.contains(&node) || self.sccs().iter().any(|x| x.len() > 1 && x.contains(&node))
+    }
+}
+
+fn collect_calls_stmts<'a>(stmts: &'a [Stmt], calls: &mut Vec<&'a FnRef>) {
+    for stmt in stmts {
+        if let StmtKind::CallFn { function, .. } = &stmt.kind {
+            calls.push(function);
+        }
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for expr in exprs {
+            collect_calls_expr(expr, calls);
+        }
+        for body in bodies {
+            collect_calls_stmts(body, calls);
+        }
+    }
+}
+fn collect_calls_expr<'a>(expr: &'a Expr, calls: &mut Vec<&'a FnRef>) {
+    if let ExprKind::CallFn { function, .. } = &expr.kind {
+        calls.push(function);
+    }
+    let mut exprs = vec![];
+    let mut bodies = vec![];
+    expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    for expr in exprs {
+        collect_calls_expr(expr, calls);
+    }
+    for body in bodies {
+        collect_calls_stmts(body, calls);
+    }
+}
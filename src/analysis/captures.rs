@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// How a closure uses one of its captured variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// The closure only reads the variable, so it may be captured by value.
+    ReadOnly,
+    /// The closure (or a closure nested in it) may assign to the variable, so it must be captured by reference.
+    Mutated,
+}
+
+/// A variable captured by a closure, as given by the `captures` of [`ExprKind::Closure`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture<'a> {
+    /// The captured variable, as seen from the code which creates the closure.
+    pub var: &'a VariableRef,
+    pub mode: CaptureMode,
+}
+
+/// Gets the captures of a closure and how each one is used, in the same order as `captures`.
+/// Returns [`None`] if the expression is not a closure.
+///
+/// Within the closure body, captured variables are referenced as locals of the closure,
+/// so a capture is considered mutated if the body contains an assignment to a local of the same (translated) name.
+/// This is conservative in the presence of a local declared inside the closure which reuses the name of a capture.
+pub fn captures_of(closure: &Expr) -> Option<Vec<Capture<'_>>> {
+    let (captures, stmts) = match &closure.kind {
+        ExprKind::Closure { captures, stmts, .. } => (captures, stmts),
+        _ => return None,
+    };
+
+    let mut assigned = Vec::new();
+    assigned_locals(stmts, &mut assigned);
+
+    Some(captures.iter().map(|var| {
+        let mode = if assigned.contains(&&var.trans_name) { CaptureMode::Mutated } else { CaptureMode::ReadOnly };
+        Capture { var, mode }
+    }).collect())
+}
+
+/// Collects the translated names of all local variables assigned in some code, including in nested closures.
+fn assigned_locals<'a>(stmts: &'a [Stmt], res: &mut Vec<&'a CompactString>) {
+    for stmt in stmts {
+        if let StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } = &stmt.kind {
+            if var.location == VarLocation::Local {
+                res.push(&var.trans_name);
+            }
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { assigned_locals_expr(x, res) }
+        for x in bodies { assigned_locals(x, res) }
+    }
+}
+fn assigned_locals_expr<'a>(expr: &'a Expr, res: &mut Vec<&'a CompactString>) {
+    let mut exprs = Vec::new();
+    let mut bodies = Vec::new();
+    expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    for x in exprs { assigned_locals_expr(x, res) }
+    for x in bodies { assigned_locals(x, res) }
+}
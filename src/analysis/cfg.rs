@@ -0,0 +1,118 @@
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// The kind of a node in a [`Cfg`].
+#[derive(Debug, Clone, Copy)]
+pub enum CfgNodeKind<'a> {
+    /// The unique entry point of the graph.
+    Entry,
+    /// The unique exit point of the graph.
+    /// This is reached by falling off the end of the code, as well as by [`StmtKind::Return`], [`StmtKind::Throw`], and terminating [`StmtKind::Stop`] modes.
+    Exit,
+    /// A single statement.
+    /// For compound statements (loops, branches, etc.), this node only represents the evaluation of the header (e.g., a loop condition).
+    /// The nested bodies have their own nodes.
+    Stmt(&'a Stmt),
+    /// The start of the handler of a [`StmtKind::TryCatch`] statement, which defines the error variable.
+    Catch(&'a Stmt),
+}
+#[derive(Debug, Clone)]
+pub struct CfgNode<'a> {
+    pub kind: CfgNodeKind<'a>,
+    pub succs: Vec<usize>,
+    pub preds: Vec<usize>,
+}
+
+/// A control flow graph over a sequence of statements (e.g., a [`Script`] or [`Function`] body).
+///
+/// Nodes are stored in order of creation, which is a pre-order traversal of the statements.
+/// Closures are not expanded; their bodies can be analyzed by building a separate graph.
+#[derive(Debug, Clone)]
+pub struct Cfg<'a> {
+    pub nodes: Vec<CfgNode<'a>>,
+}
+impl<'a> Cfg<'a> {
+    /// The index of the [`CfgNodeKind::Entry`] node.
+    pub const ENTRY: usize = 0;
+    /// The index of the [`CfgNodeKind::Exit`] node.
+    pub const EXIT: usize = 1;
+
+    pub fn new(stmts: &'a [Stmt]) -> Self {
+        let mut res = Self { nodes: vec![] };
+        res.add_node(CfgNodeKind::Entry, &[]);
+        res.add_node(CfgNodeKind::Exit, &[]);
+        for pred in res.build_seq(stmts, vec![Self::ENTRY]) {
+            res.add_edge(pred, Self::EXIT);
+        }
+        res
+    }
+    fn add_node(&mut self, kind: CfgNodeKind<'a>, preds: &[usize]) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(CfgNode { kind, succs: vec![], preds: vec![] });
+        for &pred in preds {
+            self.add_edge(pred, node);
+        }
+        node
+    }
+    fn add_edge(&mut self, from: usize, to: usize) {
+        if !self.nodes[from].succs.contains(&to) {
+            self.nodes[from].succs.push(to);
+            self.nodes[to].preds.push(from);
+        }
+    }
+    /// Builds the nodes for a sequence of statements and returns the nodes that fall through to whatever comes next.
+    fn build_seq(&mut self, stmts: &'a [Stmt], mut preds: Vec<usize>) -> Vec<usize> {
+        for stmt in stmts {
+            preds = self.build_stmt(stmt, preds);
+        }
+        preds
+    }
+    fn build_loop_body(&mut self, header: usize, stmts: &'a [Stmt]) {
+        for pred in self.build_seq(stmts, vec![header]) {
+            self.add_edge(pred, header);
+        }
+    }
+    fn build_stmt(&mut self, stmt: &'a Stmt, preds: Vec<usize>) -> Vec<usize> {
+        let node = self.add_node(CfgNodeKind::Stmt(stmt), &preds);
+        match &stmt.kind {
+            StmtKind::Warp { stmts } => self.build_seq(stmts, vec![node]),
+            StmtKind::If { then, .. } => {
+                let mut res = self.build_seq(then, vec![node]);
+                res.push(node);
+                res
+            }
+            StmtKind::IfElse { then, otherwise, .. } => {
+                let mut res = self.build_seq(then, vec![node]);
+                res.extend(self.build_seq(otherwise, vec![node]));
+                res
+            }
+            StmtKind::Repeat { stmts, .. } | StmtKind::ForLoop { stmts, .. } | StmtKind::ForeachLoop { stmts, .. } | StmtKind::UntilLoop { stmts, .. } => {
+                self.build_loop_body(node, stmts);
+                vec![node]
+            }
+            StmtKind::InfLoop { stmts } => {
+                self.build_loop_body(node, stmts);
+                vec![]
+            }
+            StmtKind::TryCatch { code, handler, .. } => {
+                let code_start = self.nodes.len();
+                let mut res = self.build_seq(code, vec![node]);
+                let code_stop = self.nodes.len();
+
+                // any statement in the protected code could throw, so they can all jump to the handler
+                let catch = self.add_node(CfgNodeKind::Catch(stmt), &[node]);
+                for pred in code_start..code_stop {
+                    self.add_edge(pred, catch);
+                }
+                res.extend(self.build_seq(handler, vec![catch]));
+                res
+            }
+            StmtKind::Return { .. } | StmtKind::Throw { .. } | StmtKind::Stop { mode: StopMode::All | StopMode::AllScenes | StopMode::ThisScript | StopMode::ThisBlock } => {
+                self.add_edge(node, Self::EXIT);
+                vec![]
+            }
+            _ => vec![node],
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+use super::*;
+
+/// A key that uniquely identifies a variable within a [`Cfg`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarKey {
+    pub trans_name: CompactString,
+    pub location: VarLocation,
+}
+impl From<&VariableRef> for VarKey {
+    fn from(var: &VariableRef) -> Self {
+        Self { trans_name: var.trans_name.clone(), location: var.location }
+    }
+}
+impl VarKey {
+    fn local(def: &VariableDef) -> Self {
+        Self { trans_name: def.trans_name.clone(), location: VarLocation::Local }
+    }
+}
+
+/// The variables read and written by a single [`Cfg`] node.
+/// Reads are considered to happen before writes (e.g., `change x by 1` both reads and writes `x`).
+#[derive(Debug, Clone, Default)]
+pub struct NodeEffects {
+    pub defs: Vec<VarKey>,
+    pub uses: Vec<VarKey>,
+    /// The subset of `uses` that come from closure captures.
+    /// Closures may be invoked later, so these variables must be assumed to be read at any future point.
+    pub captures: Vec<VarKey>,
+    /// Whether the node calls a custom block or runs a closure, which may read or write any global or field, as well as any local captured by a closure.
+    /// These reads and writes are not included in `uses` and `defs`, since the variables involved are not known from the node alone.
+    pub calls: bool,
+}
+impl NodeEffects {
+    pub fn new(kind: &CfgNodeKind) -> Self {
+        let mut res = Self::default();
+        match kind {
+            CfgNodeKind::Entry | CfgNodeKind::Exit => (),
+            CfgNodeKind::Catch(stmt) => if let StmtKind::TryCatch { var, .. } = &stmt.kind {
+                res.defs.push(var.into());
+            }
+            CfgNodeKind::Stmt(stmt) => {
+                match &stmt.kind {
+                    StmtKind::DeclareLocals { vars } => res.defs.extend(vars.iter().map(VarKey::local)),
+                    StmtKind::Assign { var, value } => {
+                        res.add_expr(value);
+                        res.defs.push(var.into());
+                    }
+                    StmtKind::AddAssign { var, value } => {
+                        res.add_expr(value);
+                        res.uses.push(var.into());
+                        res.defs.push(var.into());
+                    }
+                    StmtKind::ShowVar { var } | StmtKind::HideVar { var } => res.uses.push(var.into()),
+                    StmtKind::ForLoop { var, .. } | StmtKind::ForeachLoop { var, .. } | StmtKind::Repeat { counter: Some(var), .. }
+                    | StmtKind::DefineBlock { var, .. } => {
+                        stmt_children(&stmt.kind, &mut |x| res.add_expr(x), &mut |_| ());
+                        res.defs.push(var.into());
+                    }
+                    StmtKind::CallFn { upvars, .. } => {
+                        stmt_children(&stmt.kind, &mut |x| res.add_expr(x), &mut |_| ());
+                        res.defs.extend(upvars.iter().map(Into::into));
+                        res.calls = true;
+                    }
+                    StmtKind::CallClosure { .. } | StmtKind::ForkClosure { .. } => {
+                        stmt_children(&stmt.kind, &mut |x| res.add_expr(x), &mut |_| ());
+                        res.calls = true;
+                    }
+                    _ => stmt_children(&stmt.kind, &mut |x| res.add_expr(x), &mut |_| ()),
+                }
+            }
+        }
+        res
+    }
+    fn add_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable { var } => self.uses.push(var.into()),
+            ExprKind::Closure { captures, .. } => for var in captures {
+                self.uses.push(var.into());
+                self.captures.push(var.into());
+            }
+            ExprKind::CallFn { upvars, .. } => {
+                expr_children(&expr.kind, &mut |x| self.add_expr(x), &mut |_| ());
+                self.defs.extend(upvars.iter().map(Into::into));
+                self.calls = true;
+            }
+            ExprKind::CallClosure { .. } => {
+                expr_children(&expr.kind, &mut |x| self.add_expr(x), &mut |_| ());
+                self.calls = true;
+            }
+            _ => expr_children(&expr.kind, &mut |x| self.add_expr(x), &mut |_| ()),
+        }
+    }
+}
+
+/// A single definition (write) of a variable in a [`Cfg`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// The node that performs the write.
+    /// If this is [`Cfg::ENTRY`], this is a pseudo-definition representing the value on entry,
+    /// which comes from a parameter, field, global, or an uninitialized local.
+    pub node: usize,
+    pub var: VarKey,
+}
+
+/// The result of a reaching definitions analysis over a [`Cfg`].
+///
+/// A node which calls other code (see [`NodeEffects::calls`]) may define every global and field, as well as every local captured by a closure in the graph.
+/// Since these definitions may not happen, they do not replace the definitions which reach the node.
+#[derive(Debug, Clone)]
+pub struct ReachingDefs {
+    /// All definitions in the graph, including one entry pseudo-definition per variable.
+    pub defs: Vec<Definition>,
+    /// For each node, the indices of the definitions (in [`ReachingDefs::defs`]) that may reach the start of the node.
+    pub reach_in: Vec<BTreeSet<usize>>,
+    /// For each node, the indices of the definitions (in [`ReachingDefs::defs`]) that may reach the end of the node.
+    pub reach_out: Vec<BTreeSet<usize>>,
+}
+impl ReachingDefs {
+    pub fn new(cfg: &Cfg) -> Self {
+        let effects: Vec<_> = cfg.nodes.iter().map(|x| NodeEffects::new(&x.kind)).collect();
+
+        let vars: BTreeSet<&VarKey> = effects.iter().flat_map(|x| x.defs.iter().chain(&x.uses)).collect();
+        let captured: BTreeSet<&VarKey> = effects.iter().flat_map(|x| x.captures.iter()).collect();
+        let clobbered: Vec<&VarKey> = vars.iter().copied().filter(|x| x.location != VarLocation::Local || captured.contains(x)).collect();
+        let mut defs: Vec<_> = vars.into_iter().map(|var| Definition { node: Cfg::ENTRY, var: var.clone() }).collect();
+        for (node, effects) in effects.iter().enumerate() {
+            let clobbers = if effects.calls { clobbered.as_slice() } else { &[] };
+            for var in effects.defs.iter().chain(clobbers.iter().copied()) {
+                if !defs.iter().any(|x| x.node == node && x.var == *var) {
+                    defs.push(Definition { node, var: var.clone() });
+                }
+            }
+        }
+
+        let gen: Vec<BTreeSet<usize>> = (0..cfg.nodes.len()).map(|node| defs.iter().enumerate().filter(|x| x.1.node == node).map(|x| x.0).collect()).collect();
+        let mut reach_in = vec![BTreeSet::new(); cfg.nodes.len()];
+        let mut reach_out = gen.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in 0..cfg.nodes.len() {
+                let input: BTreeSet<usize> = cfg.nodes[node].preds.iter().flat_map(|&x| reach_out[x].iter().copied()).collect();
+                let mut output = gen[node].clone();
+                for &def in input.iter() {
+                    if !effects[node].defs.contains(&defs[def].var) {
+                        output.insert(def);
+                    }
+                }
+                if output != reach_out[node] {
+                    reach_out[node] = output;
+                    changed = true;
+                }
+                reach_in[node] = input;
+            }
+        }
+
+        Self { defs, reach_in, reach_out }
+    }
+    /// Gets all the definitions of `var` that may reach the start of `node`.
+    pub fn reaching<'a>(&'a self, node: usize, var: &'a VarKey) -> impl Iterator<Item = &'a Definition> + 'a {
+        self.reach_in[node].iter().map(|&x| &self.defs[x]).filter(move |x| x.var == *var)
+    }
+}
+
+/// The result of a live variables analysis over a [`Cfg`].
+///
+/// Fields and globals are considered live on exit since they are visible outside of the analyzed code.
+/// Likewise, any variable captured by a closure is considered live throughout the code, since the closure may be run at any time.
+/// These variables are also read by any node which calls other code (see [`NodeEffects::calls`]).
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    /// For each node, the variables that are live at the start of the node.
+    pub live_in: Vec<BTreeSet<VarKey>>,
+    /// For each node, the variables that are live at the end of the node.
+    pub live_out: Vec<BTreeSet<VarKey>>,
+}
+impl Liveness {
+    pub fn new(cfg: &Cfg) -> Self {
+        let effects: Vec<_> = cfg.nodes.iter().map(|x| NodeEffects::new(&x.kind)).collect();
+
+        let captured: BTreeSet<VarKey> = effects.iter().flat_map(|x| x.captures.iter().cloned()).collect();
+        let mut escaping = captured.clone();
+        escaping.extend(effects.iter().flat_map(|x| x.defs.iter().chain(&x.uses)).filter(|x| x.location != VarLocation::Local).cloned());
+
+        let mut live_in = vec![BTreeSet::new(); cfg.nodes.len()];
+        let mut live_out = vec![BTreeSet::new(); cfg.nodes.len()];
+        live_in[Cfg::EXIT] = escaping.clone();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in (0..cfg.nodes.len()).rev() {
+                if node == Cfg::EXIT { continue }
+
+                let mut output: BTreeSet<VarKey> = cfg.nodes[node].succs.iter().flat_map(|&x| live_in[x].iter().cloned()).collect();
+                output.extend(captured.iter().cloned());
+                let mut input: BTreeSet<VarKey> = output.iter().filter(|x| !effects[node].defs.contains(x)).cloned().collect();
+                input.extend(effects[node].uses.iter().cloned());
+                if effects[node].calls {
+                    input.extend(escaping.iter().cloned());
+                }
+
+                if input != live_in[node] || output != live_out[node] {
+                    live_in[node] = input;
+                    live_out[node] = output;
+                    changed = true;
+                }
+            }
+        }
+
+        Self { live_in, live_out }
+    }
+    /// Gets the nodes which assign (via [`StmtKind::Assign`] or [`StmtKind::AddAssign`]) a value that is never read.
+    /// These are typically mistakes in the original program and can be safely removed if the assigned value has no side effects.
+    pub fn dead_stores(&self, cfg: &Cfg) -> Vec<usize> {
+        let mut res = vec![];
+        for (node, info) in cfg.nodes.iter().enumerate() {
+            if let CfgNodeKind::Stmt(Stmt { kind: StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. }, .. }) = info.kind {
+                if !self.live_out[node].contains(&var.into()) {
+                    res.push(node);
+                }
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,181 @@
+use core::ops::{BitOr, BitOrAssign};
+
+use crate::*;
+use crate::visit::*;
+
+/// A conservative summary of the side effects that may occur when evaluating some code.
+///
+/// Code with no effects at all (see [`Effects::is_pure`]) always produces an equivalent result and can be freely reordered, deduplicated, or cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Effects {
+    /// Uses a random number generator.
+    pub random: bool,
+    /// Interacts with the outside world (e.g., user input, time, geolocation, graphics, sounds).
+    pub io: bool,
+    /// Sends or receives network traffic (e.g., RPCs and message replies).
+    pub network: bool,
+    /// Writes to variables, lists, or entity state.
+    pub writes: bool,
+    /// Reads variables, lists, or entity state, which may be changed by other code.
+    pub reads: bool,
+    /// Creates a new mutable object (e.g., a list or a clone).
+    /// Such code may be reordered, but not cached, since each evaluation must produce a distinct object.
+    pub allocates: bool,
+}
+impl BitOr for Effects {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            random: self.random | other.random,
+            io: self.io | other.io,
+            network: self.network | other.network,
+            writes: self.writes | other.writes,
+            reads: self.reads | other.reads,
+            allocates: self.allocates | other.allocates,
+        }
+    }
+}
+impl BitOrAssign for Effects {
+    fn bitor_assign(&mut self, other: Self) {
+        *self = *self | other;
+    }
+}
+impl Effects {
+    /// No effects.
+    pub const NONE: Self = Self { random: false, io: false, network: false, writes: false, reads: false, allocates: false };
+    /// Every effect, which is used for code whose behavior is unknown (e.g., calls to custom blocks or closures).
+    pub const ALL: Self = Self { random: true, io: true, network: true, writes: true, reads: true, allocates: true };
+
+    const RANDOM: Self = Self { random: true, ..Self::NONE };
+    const IO: Self = Self { io: true, ..Self::NONE };
+    const NETWORK: Self = Self { network: true, ..Self::NONE };
+    const WRITES: Self = Self { writes: true, ..Self::NONE };
+    const READS: Self = Self { reads: true, ..Self::NONE };
+    const ALLOCATES: Self = Self { allocates: true, ..Self::NONE };
+
+    /// Checks if this is [`Effects::NONE`].
+    pub fn is_pure(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Computes the effects of evaluating an expression, including all of its sub-expressions.
+    /// Creating a closure does not run its body, so only the captured variables are considered.
+    pub fn of_expr(expr: &Expr) -> Self {
+        Self::of_expr_with(expr, &mut |_| Self::READS)
+    }
+    /// Computes the effects of evaluating an expression as in [`Effects::of_expr`], but with the effects of reading each variable given by `var`
+    /// (e.g., to ignore reads of variables which are known not to change).
+    /// This only applies to variables read directly by the expression, not those read by the bodies of closures.
+    pub fn of_expr_with(expr: &Expr, var: &mut dyn FnMut(&VariableRef) -> Self) -> Self {
+        if let ExprKind::Add { values } | ExprKind::Mul { values } | ExprKind::Min { values } | ExprKind::Max { values } | ExprKind::StrCat { values } = &expr.kind {
+            // the argument list of a variadic operator is never observable, so it does not count as an allocation
+            match &values.kind {
+                ExprKind::MakeList { values } => return values.iter().fold(Self::NONE, |res, x| res | Self::of_expr_with(x, var)),
+                ExprKind::Value(Value::List(..)) => return Self::NONE,
+                _ => (),
+            }
+        }
+
+        let own = match &expr.kind {
+            ExprKind::Value(Value::List(..) | Value::RawList(..) | Value::Ref(_)) => Self::ALLOCATES,
+            ExprKind::Value(_) | ExprKind::EmptySlot => Self::NONE,
+            ExprKind::Variable { var: x } => var(x),
+
+            ExprKind::Random { .. } | ExprKind::ListGetRandom { .. } | ExprKind::StrGetRandom { .. } => Self::RANDOM,
+
+            ExprKind::MakeList { .. } | ExprKind::CopyList { .. } | ExprKind::Range { .. } | ExprKind::ListCat { .. } | ExprKind::ListCombinations { .. }
+            | ExprKind::ListDims { .. } | ExprKind::ListFlatten { .. } | ExprKind::ListColumns { .. } | ExprKind::ListRev { .. } | ExprKind::ListReshape { .. }
+            | ExprKind::ListCdr { .. } | ExprKind::ListCons { .. } | ExprKind::TextSplit { .. } => Self::ALLOCATES,
+
+            ExprKind::CostumeList | ExprKind::SoundList => Self::READS | Self::ALLOCATES,
+            ExprKind::CostumePixels { .. } | ExprKind::SoundSamples { .. } => Self::ALLOCATES,
+
+            ExprKind::Closure { captures, .. } => if captures.is_empty() { Self::ALLOCATES } else { Self::READS | Self::ALLOCATES },
+            ExprKind::JsFunction { .. } => Self::ALLOCATES,
+
+            ExprKind::YPos | ExprKind::XPos | ExprKind::Heading | ExprKind::PenDown | ExprKind::Size | ExprKind::IsVisible
+            | ExprKind::Effect { .. } | ExprKind::PenAttr { .. } | ExprKind::GlobalFlag { .. } | ExprKind::Tempo | ExprKind::Costume | ExprKind::CostumeNumber
+            | ExprKind::Answer | ExprKind::Message | ExprKind::RpcError => Self::READS,
+
+            ExprKind::MouseX | ExprKind::MouseY | ExprKind::Latitude | ExprKind::Longitude | ExprKind::KeyDown { .. } | ExprKind::Timer | ExprKind::RealTime { .. }
+            | ExprKind::IsTouchingEntity { .. } | ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingDrawings
+            | ExprKind::ImageOfEntity { .. } | ExprKind::ImageOfDrawings => Self::IO | Self::READS,
+
+            ExprKind::CallRpc { .. } | ExprKind::NetworkMessageReply { .. } => Self::NETWORK,
+
+            ExprKind::Clone { .. } => Self::WRITES | Self::ALLOCATES,
+
+            ExprKind::Map { f, .. } | ExprKind::Keep { f, .. } | ExprKind::FindFirst { f, .. } | ExprKind::Combine { f, .. } => match &f.kind {
+                ExprKind::Closure { stmts, .. } => Self::of_stmts(stmts) | Self::ALLOCATES,
+                _ => Self::ALL,
+            }
+            ExprKind::Pipe { functions, .. } => match &functions.kind {
+                ExprKind::MakeList { values } => values.iter().fold(Self::NONE, |res, f| res | match &f.kind {
+                    ExprKind::Closure { stmts, .. } => Self::of_stmts(stmts),
+                    _ => Self::ALL,
+                }),
+                _ => Self::ALL,
+            }
+
+            ExprKind::BlockAttribute { .. } | ExprKind::MappedCode { .. } => Self::READS,
+
+            ExprKind::CallFn { .. } | ExprKind::CallClosure { .. } | ExprKind::UnknownBlock { .. } => Self::ALL,
+
+            _ => Self::NONE,
+        };
+
+        let mut res = own;
+        expr_children(&expr.kind, &mut |x| res |= Self::of_expr_with(x, var), &mut |_| ());
+        res
+    }
+
+    /// Computes the effects of executing a statement, including any nested statements.
+    pub fn of_stmt(stmt: &Stmt) -> Self {
+        let own = match &stmt.kind {
+            StmtKind::DeclareLocals { .. } | StmtKind::Assign { .. } => Self::WRITES,
+            StmtKind::AddAssign { .. } => Self::READS | Self::WRITES,
+            StmtKind::ForLoop { .. } | StmtKind::ForeachLoop { .. } | StmtKind::TryCatch { .. } | StmtKind::Repeat { counter: Some(_), .. } => Self::WRITES,
+
+            StmtKind::If { .. } | StmtKind::IfElse { .. } | StmtKind::Warp { .. } | StmtKind::InfLoop { .. }
+            | StmtKind::UntilLoop { .. } | StmtKind::Repeat { .. } | StmtKind::Return { .. } | StmtKind::Throw { .. } => Self::NONE,
+
+            StmtKind::ListInsert { .. } | StmtKind::ListInsertLast { .. } | StmtKind::ListAssign { .. } | StmtKind::ListAssignLast { .. }
+            | StmtKind::ListRemove { .. } | StmtKind::ListRemoveLast { .. } | StmtKind::ListRemoveAll { .. } => Self::READS | Self::WRITES,
+            StmtKind::ListInsertRandom { .. } | StmtKind::ListAssignRandom { .. } => Self::RANDOM | Self::READS | Self::WRITES,
+
+            StmtKind::Sleep { .. } | StmtKind::WaitUntil { .. } | StmtKind::ResetTimer | StmtKind::Pause => Self::IO,
+
+            StmtKind::GotoRandom | StmtKind::SetHeadingRandom => Self::RANDOM | Self::IO | Self::WRITES,
+            StmtKind::GotoMouse | StmtKind::Ask { .. } => Self::IO | Self::READS | Self::WRITES,
+
+            StmtKind::SendNetworkMessage { .. } | StmtKind::SendNetworkReply { .. } | StmtKind::CallRpc { .. } => Self::NETWORK,
+            StmtKind::SendLocalMessage { .. } => Self::WRITES,
+
+            StmtKind::DefineBlock { .. } => Self::WRITES | Self::ALLOCATES,
+            StmtKind::MapCode { .. } => Self::WRITES,
+            StmtKind::SetBlockAttribute { .. } => Self::ALL, // modifying a block can change the behavior of any code that uses it
+
+            StmtKind::CallFn { .. } | StmtKind::CallClosure { .. } | StmtKind::ForkClosure { .. } | StmtKind::UnknownBlock { .. } | StmtKind::Stop { .. } => Self::ALL,
+
+            _ => Self::IO | Self::READS | Self::WRITES, // everything else modifies the state of the entity or the display
+        };
+
+        let mut exprs = Self::NONE;
+        let mut stmts = Self::NONE;
+        stmt_children(&stmt.kind, &mut |x| exprs |= Self::of_expr(x), &mut |x| stmts |= Self::of_stmts(x));
+        own | exprs | stmts
+    }
+
+    /// Computes the combined effects of executing a sequence of statements.
+    pub fn of_stmts(stmts: &[Stmt]) -> Self {
+        stmts.iter().fold(Self::NONE, |a, b| a | Self::of_stmt(b))
+    }
+}
+
+impl Expr {
+    /// Checks if evaluating this expression has no side effects and always produces an equivalent result.
+    /// See [`Effects`] for more details.
+    pub fn is_pure(&self) -> bool {
+        Effects::of_expr(self).is_pure()
+    }
+}
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// A variable bound by a loop (e.g., the `i` of `for i = 1 to 10`) or by an upvar of a custom block call,
+/// along with how it is used relative to the code that binds it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopBinding<'a> {
+    pub var: &'a VariableRef,
+    /// The statement or expression (for reporter calls with upvars) which binds the variable.
+    pub declared_by: NodeId,
+    /// The variable is captured by a closure in the loop body (or in the arguments of the custom block call).
+    pub captured: bool,
+    /// The variable is read or captured after the loop (or custom block call) completes.
+    pub read_after: bool,
+}
+impl LoopBinding<'_> {
+    /// Checks if each iteration needs a fresh binding of the variable.
+    /// Otherwise, a single slot which is overwritten on each iteration is sufficient.
+    pub fn needs_fresh_binding(&self) -> bool {
+        self.captured
+    }
+}
+
+/// Gets all the loop and upvar variables bound in some code (including inside closures), in order of appearance.
+///
+/// References are matched by (translated) name, so a later declaration of a local with the same name is conservatively treated as the same variable.
+pub fn loop_bindings(stmts: &[Stmt]) -> Vec<LoopBinding<'_>> {
+    let mut res = Vec::new();
+    visit_stmts(stmts, &[], &mut res);
+    res
+}
+
+/// Visits a sequence of statements, where `after` holds the statements following each enclosing sequence in the current scope.
+fn visit_stmts<'a>(stmts: &'a [Stmt], after: &[&'a [Stmt]], res: &mut Vec<LoopBinding<'a>>) {
+    for (i, stmt) in stmts.iter().enumerate() {
+        let mut tails = after.to_vec();
+        tails.push(&stmts[i + 1..]);
+
+        let (exprs, bodies) = stmt_parts(stmt);
+        let mut bind = |var: &'a VariableRef| res.push(LoopBinding {
+            var,
+            declared_by: stmt.info.id,
+            captured: exprs.iter().any(|x| captures_expr(x, var)) || bodies.iter().any(|x| captures_stmts(x, var)),
+            read_after: tails.iter().any(|x| reads_stmts(x, var)),
+        });
+        match &stmt.kind {
+            StmtKind::ForLoop { var, .. } | StmtKind::ForeachLoop { var, .. } | StmtKind::Repeat { counter: Some(var), .. } => bind(var),
+            StmtKind::CallFn { upvars, .. } => for var in upvars { bind(var) },
+            _ => (),
+        }
+
+        for x in exprs { visit_expr(x, &tails, res) }
+        for x in bodies { visit_stmts(x, &tails, res) }
+    }
+}
+fn visit_expr<'a>(expr: &'a Expr, after: &[&'a [Stmt]], res: &mut Vec<LoopBinding<'a>>) {
+    let (exprs, bodies) = expr_parts(expr);
+    match &expr.kind {
+        ExprKind::Closure { stmts, .. } => return visit_stmts(stmts, &[], res),
+        ExprKind::CallFn { upvars, .. } => for var in upvars {
+            res.push(LoopBinding {
+                var,
+                declared_by: expr.info.id,
+                captured: exprs.iter().any(|x| captures_expr(x, var)),
+                read_after: after.iter().any(|x| reads_stmts(x, var)),
+            });
+        }
+        _ => (),
+    }
+
+    for x in exprs { visit_expr(x, after, res) }
+    for x in bodies { visit_stmts(x, after, res) }
+}
+
+fn stmt_parts(stmt: &Stmt) -> (Vec<&Expr>, Vec<&Vec<Stmt>>) {
+    let mut exprs = Vec::new();
+    let mut bodies = Vec::new();
+    stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    (exprs, bodies)
+}
+fn expr_parts(expr: &Expr) -> (Vec<&Expr>, Vec<&Vec<Stmt>>) {
+    let mut exprs = Vec::new();
+    let mut bodies = Vec::new();
+    expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    (exprs, bodies)
+}
+fn is_var(x: &VariableRef, var: &VariableRef) -> bool {
+    x.location == VarLocation::Local && x.trans_name == var.trans_name
+}
+
+/// Checks if a closure in the given code (outside of any nested closure) captures the variable.
+fn captures_stmts(stmts: &[Stmt], var: &VariableRef) -> bool {
+    stmts.iter().any(|stmt| {
+        let (exprs, bodies) = stmt_parts(stmt);
+        exprs.iter().any(|x| captures_expr(x, var)) || bodies.iter().any(|x| captures_stmts(x, var))
+    })
+}
+fn captures_expr(expr: &Expr, var: &VariableRef) -> bool {
+    if let ExprKind::Closure { captures, .. } = &expr.kind {
+        return captures.iter().any(|x| is_var(x, var));
+    }
+    let (exprs, bodies) = expr_parts(expr);
+    exprs.iter().any(|x| captures_expr(x, var)) || bodies.iter().any(|x| captures_stmts(x, var))
+}
+
+/// Checks if the given code (outside of any nested closure) reads or captures the variable.
+fn reads_stmts(stmts: &[Stmt], var: &VariableRef) -> bool {
+    stmts.iter().any(|stmt| {
+        let direct = match &stmt.kind {
+            StmtKind::AddAssign { var: x, .. } | StmtKind::ShowVar { var: x } | StmtKind::HideVar { var: x } => is_var(x, var),
+            _ => false,
+        };
+        let (exprs, bodies) = stmt_parts(stmt);
+        direct || exprs.iter().any(|x| reads_expr(x, var)) || bodies.iter().any(|x| reads_stmts(x, var))
+    })
+}
+fn reads_expr(expr: &Expr, var: &VariableRef) -> bool {
+    match &expr.kind {
+        ExprKind::Variable { var: x } => is_var(x, var),
+        ExprKind::Closure { captures, .. } => captures.iter().any(|x| is_var(x, var)),
+        _ => {
+            let (exprs, bodies) = expr_parts(expr);
+            exprs.iter().any(|x| reads_expr(x, var)) || bodies.iter().any(|x| reads_stmts(x, var))
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Static analyses over the parsed AST.
+//!
+//! These are not needed for basic code generation, but are provided as building blocks for optimizing compilers and tooling.
+
+mod callgraph;
+mod captures;
+mod cfg;
+mod dataflow;
+mod effects;
+mod escapes;
+mod scopes;
+mod symbols;
+mod types;
+
+pub use callgraph::*;
+pub use captures::*;
+pub use cfg::*;
+pub use dataflow::*;
+pub use effects::*;
+pub use escapes::*;
+pub use scopes::*;
+pub use symbols::*;
+pub use types::*;
@@ -0,0 +1,146 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::*;
+use crate::visit::*;
+
+/// The construct which introduces a [`Scope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Script,
+    Function,
+    Closure,
+}
+
+/// A variable declared in a [`Scope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeVar {
+    pub name: CompactString,
+    pub trans_name: CompactString,
+    /// The node which declares the variable, or `None` for the parameters of a custom block.
+    /// Variables which are declared more than once in the same scope refer to the first declaration.
+    pub declared_by: Option<NodeId>,
+    /// The nodes in which references to the name resolve to this variable, namely everything from the declaration to the end of the scope.
+    pub extent: RangeInclusive<NodeId>,
+}
+
+/// A declaration scope, holding the script variables, loop and upvar variables, and parameters (for closures and custom blocks) declared in it.
+///
+/// Extents are given as inclusive ranges of [`NodeId`], which works because ids are assigned in pre-order (see [`Project::assign_node_ids`]),
+/// so the nodes of any subtree have consecutive ids.
+/// Empty scopes have the extent `NodeId(0)..=NodeId(0)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub extent: RangeInclusive<NodeId>,
+    pub vars: Vec<ScopeVar>,
+    /// The scopes of the closures (rings) nested directly in this scope, in order of appearance.
+    pub children: Vec<Scope>,
+}
+impl Scope {
+    /// Computes the scope tree of a script.
+    pub fn of_script(script: &Script) -> Self {
+        let mut builder = ScopeBuilder { last: NodeId(0) };
+        let start = script.hat.as_ref().map(|x| x.info.id).or_else(|| script.stmts.first().map(|x| x.info.id)).unwrap_or_default();
+        let mut res = Scope::new(ScopeKind::Script, start);
+        if let Some(hat) = script.hat.as_deref() {
+            builder.last = hat.info.id;
+            match &hat.kind {
+                HatKind::When { condition } => builder.expr(condition, &mut res),
+                HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields {
+                    res.declare(&field.name, &field.trans_name, Some(hat.info.id));
+                }
+                _ => (),
+            }
+        }
+        builder.stmts(&script.stmts, &mut res);
+        builder.finish(res)
+    }
+    /// Computes the scope tree of a custom block.
+    pub fn of_function(func: &Function) -> Self {
+        let mut builder = ScopeBuilder { last: NodeId(0) };
+        let mut res = Scope::new(ScopeKind::Function, func.stmts.first().map(|x| x.info.id).unwrap_or_default());
+        for param in func.params.iter() {
+            res.declare(&param.name, &param.trans_name, None);
+        }
+        builder.stmts(&func.stmts, &mut res);
+        builder.finish(res)
+    }
+    /// Gets the innermost scope (this one or a nested one) whose extent contains the given node.
+    pub fn innermost(&self, node: NodeId) -> Option<&Scope> {
+        if !self.extent.contains(&node) {
+            return None;
+        }
+        Some(self.children.iter().find_map(|x| x.innermost(node)).unwrap_or(self))
+    }
+
+    fn new(kind: ScopeKind, start: NodeId) -> Self {
+        Self { kind, extent: start..=start, vars: vec![], children: vec![] }
+    }
+    fn declare(&mut self, name: &CompactString, trans_name: &CompactString, declared_by: Option<NodeId>) {
+        if !self.vars.iter().any(|x| x.trans_name == *trans_name) {
+            let start = declared_by.unwrap_or(*self.extent.start());
+            self.vars.push(ScopeVar { name: name.clone(), trans_name: trans_name.clone(), declared_by, extent: start..=start });
+        }
+    }
+}
+
+struct ScopeBuilder {
+    /// The most recently visited node, which (due to pre-order numbering) is the end of every scope that is currently open.
+    last: NodeId,
+}
+impl ScopeBuilder {
+    fn finish(&self, mut scope: Scope) -> Scope {
+        scope.extent = *scope.extent.start()..=self.last.max(*scope.extent.start());
+        for var in scope.vars.iter_mut() {
+            var.extent = *var.extent.start()..=*scope.extent.end();
+        }
+        scope
+    }
+    fn stmts(&mut self, stmts: &[Stmt], scope: &mut Scope) {
+        for stmt in stmts {
+            self.last = stmt.info.id;
+            match &stmt.kind {
+                StmtKind::DeclareLocals { vars } => for var in vars {
+                    scope.declare(&var.name, &var.trans_name, Some(stmt.info.id));
+                }
+                StmtKind::ForLoop { var, .. } | StmtKind::ForeachLoop { var, .. } | StmtKind::Repeat { counter: Some(var), .. }
+                | StmtKind::DefineBlock { var, .. } | StmtKind::TryCatch { var, .. } => scope.declare(&var.name, &var.trans_name, Some(stmt.info.id)),
+                StmtKind::CallFn { upvars, .. } => for var in upvars {
+                    scope.declare(&var.name, &var.trans_name, Some(stmt.info.id));
+                }
+                _ => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { self.expr(x, scope) }
+            for x in bodies { self.stmts(x, scope) }
+        }
+    }
+    fn expr(&mut self, expr: &Expr, scope: &mut Scope) {
+        self.last = expr.info.id;
+        match &expr.kind {
+            ExprKind::Closure { params, stmts, .. } => {
+                let mut child = Scope::new(ScopeKind::Closure, expr.info.id);
+                for param in params {
+                    child.declare(&param.name, &param.trans_name, Some(expr.info.id));
+                }
+                self.stmts(stmts, &mut child);
+                scope.children.push(self.finish(child));
+                return;
+            }
+            ExprKind::CallFn { upvars, .. } => for var in upvars {
+                scope.declare(&var.name, &var.trans_name, Some(expr.info.id));
+            }
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { self.expr(x, scope) }
+        for x in bodies { self.stmts(x, scope) }
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+use crate::analysis::*;
+
+/// The code which contains a local variable of a [`Symbol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolContainer {
+    /// A custom block, where `entity` is the index of the defining entity, or `None` for global blocks.
+    Function { entity: Option<usize>, func: usize },
+    /// A script of the entity with the given index.
+    Script { entity: usize, script: usize },
+}
+impl SymbolContainer {
+    /// Gets the index of the entity which owns the code, or `None` for global blocks.
+    pub fn entity(&self) -> Option<usize> {
+        match self {
+            SymbolContainer::Function { entity, .. } => *entity,
+            SymbolContainer::Script { entity, .. } => Some(*entity),
+        }
+    }
+}
+
+/// A variable or custom block in a [`Role`], identified by its translated name and the location that defines it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol {
+    Global { trans_name: CompactString },
+    /// A field of the entity with the given index.
+    Field { entity: usize, trans_name: CompactString },
+    /// A local variable, identified by the node which declares it (see [`ScopeVar::declared_by`]).
+    /// The parameters of a custom block have no declaring node.
+    Local { container: SymbolContainer, trans_name: CompactString, declared_by: Option<NodeId> },
+    /// A custom block, where `entity` is the index of the defining entity, or `None` for global blocks.
+    Function { entity: Option<usize>, trans_name: CompactString },
+}
+
+/// The definition of a [`Symbol`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolDef {
+    pub symbol: Symbol,
+    /// The original (untransformed) name of the symbol.
+    pub name: CompactString,
+    /// The node which declares the symbol, or `None` for symbols which are not declared by a block
+    /// (globals, fields, custom blocks, and custom block parameters).
+    pub node: Option<NodeId>,
+}
+
+/// A reference to a [`Symbol`] from within some code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolReference {
+    pub symbol: Symbol,
+    /// The block which holds the reference, such as a variable block, a setter, or a custom block call.
+    pub node: NodeId,
+    pub container: SymbolContainer,
+}
+
+/// An index of the definitions and references of every variable and custom block in a [`Role`],
+/// as used by tooling such as language servers to implement go-to-definition and find-references.
+///
+/// Nodes are given by [`NodeId`], which can be mapped back to the source xml with a [`SourceMap`] (see [`Parser::parse_with_source_map`]).
+/// References to local variables which are not in scope (and to fields from global custom blocks) are not included.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SymbolIndex {
+    pub defs: Vec<SymbolDef>,
+    pub refs: Vec<SymbolReference>,
+}
+impl SymbolIndex {
+    pub fn new(role: &Role) -> Self {
+        let mut res = Self::default();
+        for var in role.globals.iter() {
+            res.defs.push(SymbolDef { symbol: Symbol::Global { trans_name: var.def.trans_name.clone() }, name: var.def.name.clone(), node: None });
+        }
+        for func in role.funcs.iter() {
+            res.defs.push(SymbolDef { symbol: Symbol::Function { entity: None, trans_name: func.trans_name.clone() }, name: func.name.clone(), node: None });
+        }
+        for (i, entity) in role.entities.iter().enumerate() {
+            for var in entity.fields.iter() {
+                res.defs.push(SymbolDef { symbol: Symbol::Field { entity: i, trans_name: var.def.trans_name.clone() }, name: var.def.name.clone(), node: None });
+            }
+            for func in entity.funcs.iter() {
+                res.defs.push(SymbolDef { symbol: Symbol::Function { entity: Some(i), trans_name: func.trans_name.clone() }, name: func.name.clone(), node: None });
+            }
+        }
+
+        for (i, func) in role.funcs.iter().enumerate() {
+            res.add_function(SymbolContainer::Function { entity: None, func: i }, func);
+        }
+        for (i, entity) in role.entities.iter().enumerate() {
+            for (j, func) in entity.funcs.iter().enumerate() {
+                res.add_function(SymbolContainer::Function { entity: Some(i), func: j }, func);
+            }
+            for (j, script) in entity.scripts.iter().enumerate() {
+                let container = SymbolContainer::Script { entity: i, script: j };
+                let scope = Scope::of_script(script);
+                res.add_locals(container, &scope);
+
+                let mut indexer = Indexer { res: &mut res, container, scope: &scope };
+                if let Some(hat) = script.hat.as_deref() {
+                    match &hat.kind {
+                        HatKind::When { condition } => indexer.expr(condition),
+                        HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields {
+                            indexer.add_ref(SymbolRef::Var(field), hat.info.id);
+                        }
+                        _ => (),
+                    }
+                }
+                indexer.stmts(&script.stmts);
+            }
+        }
+        res
+    }
+    /// Gets the definition of a symbol, if it exists.
+    pub fn definition(&self, symbol: &Symbol) -> Option<&SymbolDef> {
+        self.defs.iter().find(|x| x.symbol == *symbol)
+    }
+    /// Gets all references to a symbol, in order of appearance.
+    pub fn references<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = &'a SymbolReference> + 'a {
+        self.refs.iter().filter(move |x| x.symbol == *symbol)
+    }
+    /// Gets all references held by the given node.
+    /// For example, a custom block call references the block itself along with any upvars it declares.
+    pub fn references_at(&self, node: NodeId) -> impl Iterator<Item = &SymbolReference> + '_ {
+        self.refs.iter().filter(move |x| x.node == node)
+    }
+
+    fn add_function(&mut self, container: SymbolContainer, func: &Function) {
+        let scope = Scope::of_function(func);
+        self.add_locals(container, &scope);
+        Indexer { res: self, container, scope: &scope }.stmts(&func.stmts);
+    }
+    fn add_locals(&mut self, container: SymbolContainer, scope: &Scope) {
+        for var in scope.vars.iter() {
+            let symbol = Symbol::Local { container, trans_name: var.trans_name.clone(), declared_by: var.declared_by };
+            self.defs.push(SymbolDef { symbol, name: var.name.clone(), node: var.declared_by });
+        }
+        for child in scope.children.iter() {
+            self.add_locals(container, child);
+        }
+    }
+}
+
+/// Finds the variable that a local variable reference from the given node resolves to.
+fn resolve_local<'a>(scope: &'a Scope, node: NodeId, trans_name: &str) -> Option<&'a ScopeVar> {
+    if !scope.extent.contains(&node) {
+        return None;
+    }
+    scope.children.iter().find_map(|x| resolve_local(x, node, trans_name))
+        .or_else(|| scope.vars.iter().find(|x| x.trans_name == trans_name && x.extent.contains(&node)))
+}
+
+struct Indexer<'a, 'b> {
+    res: &'a mut SymbolIndex,
+    container: SymbolContainer,
+    scope: &'b Scope,
+}
+impl Indexer<'_, '_> {
+    fn add_ref(&mut self, x: SymbolRef, node: NodeId) {
+        let symbol = match x {
+            SymbolRef::Var(var) => match var.location {
+                VarLocation::Global => Symbol::Global { trans_name: var.trans_name.clone() },
+                VarLocation::Field => match self.container.entity() {
+                    Some(entity) => Symbol::Field { entity, trans_name: var.trans_name.clone() },
+                    None => return,
+                }
+                VarLocation::Local => match resolve_local(self.scope, node, &var.trans_name) {
+                    Some(def) => Symbol::Local { container: self.container, trans_name: def.trans_name.clone(), declared_by: def.declared_by },
+                    None => return,
+                }
+            }
+            SymbolRef::Fn(function) => match function.location {
+                FnLocation::Global => Symbol::Function { entity: None, trans_name: function.trans_name.clone() },
+                FnLocation::Method => Symbol::Function { entity: self.container.entity(), trans_name: function.trans_name.clone() },
+            }
+            SymbolRef::Entity { .. } => return,
+        };
+        self.res.refs.push(SymbolReference { symbol, node, container: self.container });
+    }
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            let node = stmt.info.id;
+            match &stmt.kind {
+                StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var }
+                | StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. } => self.add_ref(SymbolRef::Var(var), node),
+                StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } => self.add_ref(SymbolRef::Var(var), node),
+                StmtKind::CallFn { function, upvars, .. } => {
+                    self.add_ref(SymbolRef::Fn(function), node);
+                    for var in upvars { self.add_ref(SymbolRef::Var(var), node) }
+                }
+                _ => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { self.expr(x) }
+            for x in bodies { self.stmts(x) }
+        }
+    }
+    fn expr(&mut self, expr: &Expr) {
+        let node = expr.info.id;
+        match &expr.kind {
+            ExprKind::Variable { var } => self.add_ref(SymbolRef::Var(var), node),
+            ExprKind::CallFn { function, upvars, .. } => {
+                self.add_ref(SymbolRef::Fn(function), node);
+                for var in upvars { self.add_ref(SymbolRef::Var(var), node) }
+            }
+            ExprKind::Closure { captures, .. } => for var in captures { self.add_ref(SymbolRef::Var(var), node) },
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { self.expr(x) }
+        for x in bodies { self.stmts(x) }
+    }
+}
@@ -0,0 +1,319 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::*;
+use crate::visit::*;
+
+/// A (very coarse) static type, as determined by [`TypeInfo::new`].
+///
+/// These form a simple lattice where [`InferredType::Any`] is the top element.
+/// Note that Snap! operators are hyperized, so an arithmetic operation on lists results in a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InferredType {
+    Number, String, Bool, List, Any,
+}
+impl InferredType {
+    /// Gets the least upper bound of two types.
+    pub fn join(self, other: Self) -> Self {
+        if self == other { self } else { InferredType::Any }
+    }
+}
+fn join(a: Option<InferredType>, b: Option<InferredType>) -> Option<InferredType> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.join(b)),
+        (x, None) | (None, x) => x,
+    }
+}
+/// The result type of a hyperized operation with the given operand types.
+fn hyper(base: InferredType, operands: &[Option<InferredType>]) -> Option<InferredType> {
+    let mut res = base;
+    for operand in operands {
+        match operand {
+            Some(InferredType::Any) => res = InferredType::Any,
+            Some(InferredType::List) if res != InferredType::Any => res = InferredType::List,
+            _ => (),
+        }
+    }
+    Some(res)
+}
+fn value_type(value: &Value) -> InferredType {
+    match value {
+        Value::Bool(_) => InferredType::Bool,
+        Value::Number(_) | Value::Constant(_) => InferredType::Number,
+        Value::String(_) | Value::SharedString(_) => match value.as_str().unwrap_or_default().trim().parse::<f64>() { // snap treats numeric text as numbers
+            Ok(_) => InferredType::Number,
+            Err(_) => InferredType::String,
+        }
+        Value::List(_, _) | Value::RawList(_, _) | Value::Ref(_) => InferredType::List,
+        Value::Image(_) | Value::Audio(_) | Value::Color { .. } => InferredType::Any,
+    }
+}
+
+/// The results of type inference over a [`Role`].
+///
+/// Inference is flow-insensitive: each variable is given the join of the types of every value assigned to it anywhere in the role,
+/// and each custom block is given the join of the types of every value it reports.
+/// The type of an expression can then be looked up by reference, which acts as a parallel annotation map over the (unmodified) AST.
+#[derive(Debug, Clone)]
+pub struct TypeInfo<'a> {
+    exprs: BTreeMap<*const Expr, InferredType>,
+    globals: BTreeMap<CompactString, InferredType>,
+    fields: Vec<BTreeMap<CompactString, InferredType>>,
+    returns: BTreeMap<(Option<usize>, CompactString), InferredType>,
+    _role: PhantomData<&'a Role>,
+}
+impl<'a> TypeInfo<'a> {
+    pub fn new(role: &'a Role) -> Self {
+        let mut inferrer = Inferrer {
+            globals: role.globals.iter().map(|x| (x.def.trans_name.clone(), value_type(&x.init))).collect(),
+            fields: role.entities.iter().map(|e| e.fields.iter().map(|x| (x.def.trans_name.clone(), value_type(&x.init))).collect()).collect(),
+            returns: Default::default(),
+            locals: vec![],
+            body: 0,
+            entity: None,
+            current_fn: None,
+            exprs: None,
+            changed: true,
+        };
+        while inferrer.changed {
+            inferrer.changed = false;
+            inferrer.visit_role(role);
+        }
+        inferrer.exprs = Some(Default::default());
+        inferrer.visit_role(role);
+        debug_assert!(!inferrer.changed);
+
+        Self {
+            exprs: inferrer.exprs.unwrap(),
+            globals: inferrer.globals,
+            fields: inferrer.fields,
+            returns: inferrer.returns,
+            _role: PhantomData,
+        }
+    }
+    /// Gets the inferred type of an expression in the role.
+    /// Returns [`InferredType::Any`] for expressions that are not part of the analyzed role.
+    pub fn expr_type(&self, expr: &'a Expr) -> InferredType {
+        self.exprs.get(&(expr as *const Expr)).copied().unwrap_or(InferredType::Any)
+    }
+    /// Gets the inferred type of a global variable by its translated name.
+    pub fn global_type(&self, trans_name: &str) -> Option<InferredType> {
+        self.globals.get(trans_name).copied()
+    }
+    /// Gets the inferred type of a field by the index of its entity within the role and the field's translated name.
+    pub fn field_type(&self, entity: usize, trans_name: &str) -> Option<InferredType> {
+        self.fields.get(entity)?.get(trans_name).copied()
+    }
+    /// Gets the inferred type of the value reported by a custom block.
+    /// `entity` is the index of the defining entity, or `None` for global blocks.
+    /// Blocks that never report a value will have no type.
+    pub fn return_type(&self, entity: Option<usize>, trans_name: &str) -> Option<InferredType> {
+        self.returns.get(&(entity, CompactString::new(trans_name))).copied()
+    }
+}
+
+struct Inferrer {
+    globals: BTreeMap<CompactString, InferredType>,
+    fields: Vec<BTreeMap<CompactString, InferredType>>,
+    returns: BTreeMap<(Option<usize>, CompactString), InferredType>,
+    locals: Vec<BTreeMap<CompactString, InferredType>>, // one per body, in visitation order
+    body: usize,
+    entity: Option<usize>,
+    current_fn: Option<(Option<usize>, CompactString)>,
+    exprs: Option<BTreeMap<*const Expr, InferredType>>,
+    changed: bool,
+}
+impl Inferrer {
+    fn visit_role(&mut self, role: &Role) {
+        self.body = 0;
+        self.entity = None;
+        for func in role.funcs.iter() {
+            self.visit_fn(func);
+        }
+        for (i, entity) in role.entities.iter().enumerate() {
+            self.entity = Some(i);
+            for func in entity.funcs.iter() {
+                self.visit_fn(func);
+            }
+            for script in entity.scripts.iter() {
+                self.begin_body();
+                if let Some(hat) = &script.hat {
+                    match &hat.kind {
+                        HatKind::When { condition } => { self.infer(condition); }
+                        HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields {
+                            self.assign(field, Some(InferredType::Any));
+                        }
+                        _ => (),
+                    }
+                }
+                self.visit_stmts(&script.stmts);
+            }
+        }
+    }
+    fn visit_fn(&mut self, func: &Function) {
+        self.begin_body();
+        for param in func.params.iter() {
+            self.assign_local(&param.trans_name, Some(InferredType::Any));
+        }
+        self.current_fn = Some((self.entity, func.trans_name.clone()));
+        self.visit_stmts(&func.stmts);
+        self.current_fn = None;
+    }
+    fn begin_body(&mut self) {
+        if self.locals.len() <= self.body {
+            self.locals.push(Default::default());
+        }
+        self.body += 1;
+    }
+    fn update(changed: &mut bool, slot: &mut BTreeMap<CompactString, InferredType>, name: &str, ty: Option<InferredType>) {
+        let prev = slot.get(name).copied();
+        let new = join(prev, ty);
+        if new != prev {
+            slot.insert(name.into(), new.unwrap());
+            *changed = true;
+        }
+    }
+    fn assign_local(&mut self, trans_name: &str, ty: Option<InferredType>) {
+        Self::update(&mut self.changed, &mut self.locals[self.body - 1], trans_name, ty);
+    }
+    fn assign(&mut self, var: &VariableRef, ty: Option<InferredType>) {
+        match var.location {
+            VarLocation::Global => Self::update(&mut self.changed, &mut self.globals, &var.trans_name, ty),
+            VarLocation::Field => if let Some(entity) = self.entity {
+                Self::update(&mut self.changed, &mut self.fields[entity], &var.trans_name, ty);
+            }
+            VarLocation::Local => self.assign_local(&var.trans_name, ty),
+        }
+    }
+    fn lookup(&self, var: &VariableRef) -> Option<InferredType> {
+        match var.location {
+            VarLocation::Global => self.globals.get(&var.trans_name).copied(),
+            VarLocation::Field => self.entity.and_then(|e| self.fields[e].get(&var.trans_name).copied()),
+            VarLocation::Local => self.locals[self.body - 1].get(&var.trans_name).copied(),
+        }
+    }
+    fn visit_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+    }
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+
+        let types: Vec<_> = exprs.iter().map(|x| self.infer(x)).collect();
+        match &stmt.kind {
+            StmtKind::DeclareLocals { vars } => for var in vars {
+                self.assign_local(&var.trans_name, Some(InferredType::Number)); // locals are initialized to zero
+            }
+            StmtKind::Assign { var, .. } => self.assign(var, types[0]),
+            StmtKind::AddAssign { var, .. } => self.assign(var, hyper(InferredType::Number, &types)),
+            StmtKind::ForLoop { var, .. } | StmtKind::Repeat { counter: Some(var), .. } => self.assign(var, Some(InferredType::Number)),
+            StmtKind::ForeachLoop { var, .. } | StmtKind::TryCatch { var, .. } | StmtKind::DefineBlock { var, .. } => self.assign(var, Some(InferredType::Any)),
+            StmtKind::CallFn { upvars, .. } => for var in upvars {
+                self.assign(var, Some(InferredType::Any));
+            }
+            StmtKind::Return { .. } => if let Some(key) = self.current_fn.clone() {
+                let prev = self.returns.get(&key).copied();
+                let new = join(prev, types[0]);
+                if new != prev {
+                    self.returns.insert(key, new.unwrap());
+                    self.changed = true;
+                }
+            }
+            _ => (),
+        }
+
+        for body in bodies {
+            self.visit_stmts(body);
+        }
+    }
+    fn infer(&mut self, expr: &Expr) -> Option<InferredType> {
+        let res = match &expr.kind {
+            ExprKind::Add { values } | ExprKind::Mul { values } | ExprKind::Min { values } | ExprKind::Max { values } => match &values.kind {
+                ExprKind::MakeList { values: items } => {
+                    let types: Vec<_> = items.iter().map(|x| self.infer(x)).collect();
+                    self.record(values, Some(InferredType::List));
+                    hyper(InferredType::Number, &types)
+                }
+                _ => {
+                    self.infer(values);
+                    Some(InferredType::Any)
+                }
+            }
+            ExprKind::Closure { stmts, .. } => {
+                let current_fn = self.current_fn.take(); // reports in a closure don't return from the enclosing block
+                self.visit_stmts(stmts);
+                self.current_fn = current_fn;
+                Some(InferredType::Any)
+            }
+            _ => {
+                let mut exprs = vec![];
+                expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |_| ());
+                let types: Vec<_> = exprs.iter().map(|x| self.infer(x)).collect();
+
+                match &expr.kind {
+                    ExprKind::Value(x) => Some(value_type(x)),
+                    ExprKind::Variable { var } => self.lookup(var),
+
+                    ExprKind::Sub { .. } | ExprKind::Div { .. } | ExprKind::Mod { .. } | ExprKind::Pow { .. } | ExprKind::Log { .. } | ExprKind::Atan2 { .. }
+                    | ExprKind::Random { .. } | ExprKind::StrLen { .. } | ExprKind::CharToUnicode { .. }
+                    | ExprKind::Neg { .. } | ExprKind::Abs { .. } | ExprKind::Sign { .. } | ExprKind::Sqrt { .. }
+                    | ExprKind::Floor { .. } | ExprKind::Ceil { .. } | ExprKind::Round { .. }
+                    | ExprKind::Sin { .. } | ExprKind::Cos { .. } | ExprKind::Tan { .. }
+                    | ExprKind::Asin { .. } | ExprKind::Acos { .. } | ExprKind::Atan { .. } => hyper(InferredType::Number, &types),
+
+                    ExprKind::Less { .. } | ExprKind::LessEq { .. } | ExprKind::Greater { .. } | ExprKind::GreaterEq { .. } => hyper(InferredType::Bool, &types),
+                    ExprKind::UnicodeToChar { .. } => hyper(InferredType::String, &types),
+
+                    ExprKind::Conditional { .. } => join(types[1], types[2]),
+
+                    ExprKind::And { .. } | ExprKind::Or { .. } | ExprKind::Not { .. }
+                    | ExprKind::Identical { .. } | ExprKind::Eq { .. } | ExprKind::Neq { .. }
+                    | ExprKind::ListIsEmpty { .. } | ExprKind::ListContains { .. } | ExprKind::KeyDown { .. }
+                    | ExprKind::IsTouchingEntity { .. } | ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingDrawings
+                    | ExprKind::PenDown | ExprKind::IsVisible | ExprKind::TypeQuery { .. } | ExprKind::GlobalFlag { .. } => Some(InferredType::Bool),
+
+                    ExprKind::StageWidth | ExprKind::StageHeight | ExprKind::MouseX | ExprKind::MouseY | ExprKind::Latitude | ExprKind::Longitude
+                    | ExprKind::XPos | ExprKind::YPos | ExprKind::Heading | ExprKind::Size | ExprKind::Timer | ExprKind::Tempo
+                    | ExprKind::Effect { .. } | ExprKind::PenAttr { .. } | ExprKind::CostumeNumber | ExprKind::CostumeWidth { .. } | ExprKind::CostumeHeight { .. }
+                    | ExprKind::SoundDuration { .. } | ExprKind::SoundSampleRate { .. } | ExprKind::SoundSamplesLength { .. } | ExprKind::SoundChannelCount { .. }
+                    | ExprKind::ListLen { .. } | ExprKind::ListRank { .. } | ExprKind::ListFind { .. } | ExprKind::RealTime { .. } => Some(InferredType::Number),
+
+                    ExprKind::StrCat { .. } | ExprKind::StrGet { .. } | ExprKind::StrGetLast { .. } | ExprKind::StrGetRandom { .. }
+                    | ExprKind::ListLines { .. } | ExprKind::ListCsv { .. } | ExprKind::ListJson { .. }
+                    | ExprKind::CostumeName { .. } | ExprKind::SoundName { .. } | ExprKind::Answer
+                    | ExprKind::MappedCode { .. } | ExprKind::TextFunction { .. } => Some(InferredType::String),
+
+                    ExprKind::Range { .. } | ExprKind::MakeList { .. } | ExprKind::CopyList { .. } | ExprKind::ListCat { .. }
+                    | ExprKind::ListDims { .. } | ExprKind::ListFlatten { .. } | ExprKind::ListColumns { .. } | ExprKind::ListRev { .. }
+                    | ExprKind::ListReshape { .. } | ExprKind::ListCombinations { .. } | ExprKind::ListCdr { .. } | ExprKind::ListCons { .. }
+                    | ExprKind::Map { .. } | ExprKind::Keep { .. } | ExprKind::TextSplit { .. }
+                    | ExprKind::CostumeList | ExprKind::SoundList | ExprKind::CostumePixels { .. } | ExprKind::SoundSamples { .. } => Some(InferredType::List),
+
+                    ExprKind::CallFn { function, upvars, .. } => {
+                        for var in upvars {
+                            self.assign(var, Some(InferredType::Any));
+                        }
+                        let entity = match function.location {
+                            FnLocation::Global => None,
+                            FnLocation::Method => self.entity,
+                        };
+                        self.returns.get(&(entity, function.trans_name.clone())).copied()
+                    }
+
+                    _ => Some(InferredType::Any),
+                }
+            }
+        };
+        self.record(expr, res);
+        res
+    }
+    fn record(&mut self, expr: &Expr, ty: Option<InferredType>) {
+        if let Some(exprs) = &mut self.exprs {
+            exprs.insert(expr as *const Expr, ty.unwrap_or(InferredType::Any));
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Transforming the annotations attached to the nodes of an AST.
+//!
+//! Every AST type is generic over an annotation type `M` (defaulting to `()`), which is stored in [`BlockInfo::annotation`].
+//! The parser always produces `()` annotations, which can then be replaced with (e.g.) source spans, inferred types, or codegen state
+//! by calling `map_annotations` on any of the top-level AST types.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+
+trait Reannotate<M, N> {
+    type Output;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output;
+}
+
+impl<M, N, T: Reannotate<M, N>> Reannotate<M, N> for Box<T> {
+    type Output = Box<T::Output>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        Box::new((*self).reannotate(mapper))
+    }
+}
+impl<M, N, T: Reannotate<M, N>> Reannotate<M, N> for Vec<T> {
+    type Output = Vec<T::Output>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        self.into_iter().map(|x| x.reannotate(mapper)).collect()
+    }
+}
+impl<M, N, T: Reannotate<M, N>> Reannotate<M, N> for Option<T> {
+    type Output = Option<T::Output>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        self.map(|x| x.reannotate(mapper))
+    }
+}
+impl<M, N, T: Reannotate<M, N>> Reannotate<M, N> for (CompactString, T) {
+    type Output = (CompactString, T::Output);
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        (self.0, self.1.reannotate(mapper))
+    }
+}
+
+impl<M, N> Reannotate<M, N> for BlockInfo<M> {
+    type Output = BlockInfo<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let BlockInfo { comment, location, opcode, id, annotation } = self;
+        BlockInfo { comment, location, opcode, id, annotation: mapper(annotation) }
+    }
+}
+impl<M, N> Reannotate<M, N> for Project<M> {
+    type Output = Project<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let Project { name, meta, roles } = self;
+        Project { name, meta, roles: roles.reannotate(mapper) }
+    }
+}
+impl<M, N> Reannotate<M, N> for Role<M> {
+    type Output = Role<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let Role { name, notes, stage_size, globals, funcs, entities, code_mappings, code_headers, palette, thumbnail, pen_trails, source_hash, global_index } = self;
+        Role { name, notes, stage_size, globals, funcs: funcs.reannotate(mapper), entities: entities.reannotate(mapper), code_mappings, code_headers, palette, thumbnail, pen_trails, source_hash, global_index }
+    }
+}
+impl<M, N> Reannotate<M, N> for Function<M> {
+    type Output = Function<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let Function { name, trans_name, params, param_info, upvars, returns, stmts, category, location, help, code_mapping, code_header } = self;
+        Function { name, trans_name, params, param_info, upvars, returns, stmts: stmts.reannotate(mapper), category, location, help, code_mapping, code_header }
+    }
+}
+impl<M, N> Reannotate<M, N> for Entity<M> {
+    type Output = Entity<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let Entity { name, trans_name, fields, costumes, sounds, funcs, scripts, canvas_comments, floating_reporters, active_costume, visible, color, pos, heading, scale, field_index } = self;
+        Entity { name, trans_name, fields, costumes, sounds, funcs: funcs.reannotate(mapper), scripts: scripts.reannotate(mapper), canvas_comments, floating_reporters: floating_reporters.reannotate(mapper), active_costume, visible, color, pos, heading, scale, field_index }
+    }
+}
+impl<M, N> Reannotate<M, N> for Script<M> {
+    type Output = Script<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        Script { hat: self.hat.reannotate(mapper), stmts: self.stmts.reannotate(mapper), locals: self.locals }
+    }
+}
+impl<M, N> Reannotate<M, N> for Hat<M> {
+    type Output = Hat<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let info = self.info.reannotate(mapper);
+        Hat { kind: self.kind.reannotate(mapper), info }
+    }
+}
+impl<M, N> Reannotate<M, N> for Stmt<M> {
+    type Output = Stmt<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let info = self.info.reannotate(mapper);
+        Stmt { kind: self.kind.reannotate(mapper), info }
+    }
+}
+impl<M, N> Reannotate<M, N> for Expr<M> {
+    type Output = Expr<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        let info = self.info.reannotate(mapper);
+        Expr { kind: self.kind.reannotate(mapper), info }
+    }
+}
+
+impl<M, N> Reannotate<M, N> for HatKind<M> {
+    type Output = HatKind<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        match self {
+            HatKind::OnFlag => HatKind::OnFlag,
+            HatKind::OnClone => HatKind::OnClone,
+            HatKind::OnKey { key } => HatKind::OnKey { key },
+            HatKind::MouseDown => HatKind::MouseDown,
+            HatKind::MouseUp => HatKind::MouseUp,
+            HatKind::MouseEnter => HatKind::MouseEnter,
+            HatKind::MouseLeave => HatKind::MouseLeave,
+            HatKind::ScrollUp => HatKind::ScrollUp,
+            HatKind::ScrollDown => HatKind::ScrollDown,
+            HatKind::Dropped => HatKind::Dropped,
+            HatKind::Stopped => HatKind::Stopped,
+            HatKind::When { condition } => HatKind::When { condition: condition.reannotate(mapper) },
+            HatKind::LocalMessage { msg_type } => HatKind::LocalMessage { msg_type },
+            HatKind::NetworkMessage { msg_type, fields } => HatKind::NetworkMessage { msg_type, fields },
+            HatKind::Unknown { name, fields } => HatKind::Unknown { name, fields },
+            HatKind::Custom { name, args, fields } => HatKind::Custom { name, args, fields },
+        }
+    }
+}
+impl<M, N> Reannotate<M, N> for StmtKind<M> {
+    type Output = StmtKind<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        match self {
+            StmtKind::DeclareLocals { vars } => StmtKind::DeclareLocals { vars },
+            StmtKind::Assign { var, value } => StmtKind::Assign { var, value: value.reannotate(mapper) },
+            StmtKind::AddAssign { var, value } => StmtKind::AddAssign { var, value: value.reannotate(mapper) },
+            StmtKind::ShowVar { var } => StmtKind::ShowVar { var },
+            StmtKind::HideVar { var } => StmtKind::HideVar { var },
+            StmtKind::Warp { stmts } => StmtKind::Warp { stmts: stmts.reannotate(mapper) },
+            StmtKind::InfLoop { stmts } => StmtKind::InfLoop { stmts: stmts.reannotate(mapper) },
+            StmtKind::ForeachLoop { var, items, stmts } => StmtKind::ForeachLoop { var, items: items.reannotate(mapper), stmts: stmts.reannotate(mapper) },
+            StmtKind::ForLoop { var, start, stop, stmts } => StmtKind::ForLoop { var, start: start.reannotate(mapper), stop: stop.reannotate(mapper), stmts: stmts.reannotate(mapper) },
+            StmtKind::UntilLoop { condition, stmts } => StmtKind::UntilLoop { condition: condition.reannotate(mapper), stmts: stmts.reannotate(mapper) },
+            StmtKind::Repeat { times, counter, stmts } => StmtKind::Repeat { times: times.reannotate(mapper), counter, stmts: stmts.reannotate(mapper) },
+            StmtKind::If { condition, then } => StmtKind::If { condition: condition.reannotate(mapper), then: then.reannotate(mapper) },
+            StmtKind::IfElse { condition, then, otherwise } => StmtKind::IfElse { condition: condition.reannotate(mapper), then: then.reannotate(mapper), otherwise: otherwise.reannotate(mapper) },
+            StmtKind::TryCatch { code, var, handler } => StmtKind::TryCatch { code: code.reannotate(mapper), var, handler: handler.reannotate(mapper) },
+            StmtKind::Throw { error } => StmtKind::Throw { error: error.reannotate(mapper) },
+            StmtKind::ListInsert { list, value, index } => StmtKind::ListInsert { list: list.reannotate(mapper), value: value.reannotate(mapper), index: index.reannotate(mapper) },
+            StmtKind::ListInsertLast { list, value } => StmtKind::ListInsertLast { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            StmtKind::ListInsertRandom { list, value } => StmtKind::ListInsertRandom { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            StmtKind::ListRemove { list, index } => StmtKind::ListRemove { list: list.reannotate(mapper), index: index.reannotate(mapper) },
+            StmtKind::ListRemoveLast { list } => StmtKind::ListRemoveLast { list: list.reannotate(mapper) },
+            StmtKind::ListRemoveAll { list } => StmtKind::ListRemoveAll { list: list.reannotate(mapper) },
+            StmtKind::ListAssign { list, value, index } => StmtKind::ListAssign { list: list.reannotate(mapper), value: value.reannotate(mapper), index: index.reannotate(mapper) },
+            StmtKind::ListAssignLast { list, value } => StmtKind::ListAssignLast { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            StmtKind::ListAssignRandom { list, value } => StmtKind::ListAssignRandom { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            StmtKind::Return { value } => StmtKind::Return { value: value.reannotate(mapper) },
+            StmtKind::Sleep { seconds } => StmtKind::Sleep { seconds: seconds.reannotate(mapper) },
+            StmtKind::WaitUntil { condition } => StmtKind::WaitUntil { condition: condition.reannotate(mapper) },
+            StmtKind::SetCostume { costume } => StmtKind::SetCostume { costume: costume.reannotate(mapper) },
+            StmtKind::NextCostume => StmtKind::NextCostume,
+            StmtKind::PlaySound { sound, blocking } => StmtKind::PlaySound { sound: sound.reannotate(mapper), blocking },
+            StmtKind::PlayNotes { notes, beats, blocking } => StmtKind::PlayNotes { notes: notes.reannotate(mapper), beats: beats.reannotate(mapper), blocking },
+            StmtKind::Rest { beats } => StmtKind::Rest { beats: beats.reannotate(mapper) },
+            StmtKind::StopSounds => StmtKind::StopSounds,
+            StmtKind::Forward { distance } => StmtKind::Forward { distance: distance.reannotate(mapper) },
+            StmtKind::SetX { value } => StmtKind::SetX { value: value.reannotate(mapper) },
+            StmtKind::ChangeX { delta } => StmtKind::ChangeX { delta: delta.reannotate(mapper) },
+            StmtKind::SetY { value } => StmtKind::SetY { value: value.reannotate(mapper) },
+            StmtKind::ChangeY { delta } => StmtKind::ChangeY { delta: delta.reannotate(mapper) },
+            StmtKind::GotoXY { x, y } => StmtKind::GotoXY { x: x.reannotate(mapper), y: y.reannotate(mapper) },
+            StmtKind::GotoMouse => StmtKind::GotoMouse,
+            StmtKind::GotoRandom => StmtKind::GotoRandom,
+            StmtKind::Goto { target } => StmtKind::Goto { target: target.reannotate(mapper) },
+            StmtKind::PointTowards { target } => StmtKind::PointTowards { target: target.reannotate(mapper) },
+            StmtKind::PointTowardsXY { x, y } => StmtKind::PointTowardsXY { x: x.reannotate(mapper), y: y.reannotate(mapper) },
+            StmtKind::TurnRight { angle } => StmtKind::TurnRight { angle: angle.reannotate(mapper) },
+            StmtKind::TurnLeft { angle } => StmtKind::TurnLeft { angle: angle.reannotate(mapper) },
+            StmtKind::SetHeading { value } => StmtKind::SetHeading { value: value.reannotate(mapper) },
+            StmtKind::SetHeadingRandom => StmtKind::SetHeadingRandom,
+            StmtKind::BounceOffEdge => StmtKind::BounceOffEdge,
+            StmtKind::SetPenDown { value } => StmtKind::SetPenDown { value },
+            StmtKind::PenClear => StmtKind::PenClear,
+            StmtKind::Stamp => StmtKind::Stamp,
+            StmtKind::Write { content, font_size } => StmtKind::Write { content: content.reannotate(mapper), font_size: font_size.reannotate(mapper) },
+            StmtKind::SetPenColor { color } => StmtKind::SetPenColor { color },
+            StmtKind::Say { content, duration } => StmtKind::Say { content: content.reannotate(mapper), duration: duration.reannotate(mapper) },
+            StmtKind::Think { content, duration } => StmtKind::Think { content: content.reannotate(mapper), duration: duration.reannotate(mapper) },
+            StmtKind::SetVisible { value } => StmtKind::SetVisible { value },
+            StmtKind::ChangeSize { delta } => StmtKind::ChangeSize { delta: delta.reannotate(mapper) },
+            StmtKind::SetSize { value } => StmtKind::SetSize { value: value.reannotate(mapper) },
+            StmtKind::ChangePenSize { delta } => StmtKind::ChangePenSize { delta: delta.reannotate(mapper) },
+            StmtKind::SetPenSize { value } => StmtKind::SetPenSize { value: value.reannotate(mapper) },
+            StmtKind::CallRpc { host, service, rpc, args } => StmtKind::CallRpc { host, service, rpc, args: args.reannotate(mapper) },
+            StmtKind::CallFn { function, args, upvars } => StmtKind::CallFn { function, args: args.reannotate(mapper), upvars },
+            StmtKind::CallClosure { new_entity, closure, args } => StmtKind::CallClosure { new_entity: new_entity.reannotate(mapper), closure: closure.reannotate(mapper), args: args.reannotate(mapper) },
+            StmtKind::ForkClosure { closure, args } => StmtKind::ForkClosure { closure: closure.reannotate(mapper), args: args.reannotate(mapper) },
+            StmtKind::Clone { target } => StmtKind::Clone { target: target.reannotate(mapper) },
+            StmtKind::DeleteClone => StmtKind::DeleteClone,
+            StmtKind::SendLocalMessage { target, msg_type, wait } => StmtKind::SendLocalMessage { target: target.reannotate(mapper), msg_type: msg_type.reannotate(mapper), wait },
+            StmtKind::SendNetworkMessage { target, msg_type, values } => StmtKind::SendNetworkMessage { target: target.reannotate(mapper), msg_type, values: values.reannotate(mapper) },
+            StmtKind::SendNetworkReply { value } => StmtKind::SendNetworkReply { value: value.reannotate(mapper) },
+            StmtKind::Ask { prompt } => StmtKind::Ask { prompt: prompt.reannotate(mapper) },
+            StmtKind::ResetTimer => StmtKind::ResetTimer,
+            StmtKind::Pause => StmtKind::Pause,
+            StmtKind::SetEffect { kind, value } => StmtKind::SetEffect { kind, value: value.reannotate(mapper) },
+            StmtKind::ChangeEffect { kind, delta } => StmtKind::ChangeEffect { kind, delta: delta.reannotate(mapper) },
+            StmtKind::ClearEffects => StmtKind::ClearEffects,
+            StmtKind::SetPenAttr { attr, value } => StmtKind::SetPenAttr { attr, value: value.reannotate(mapper) },
+            StmtKind::ChangePenAttr { attr, delta } => StmtKind::ChangePenAttr { attr, delta: delta.reannotate(mapper) },
+            StmtKind::SetGlobalFlag { flag, value } => StmtKind::SetGlobalFlag { flag, value: value.reannotate(mapper) },
+            StmtKind::SetTempo { value } => StmtKind::SetTempo { value: value.reannotate(mapper) },
+            StmtKind::ChangeTempo { delta } => StmtKind::ChangeTempo { delta: delta.reannotate(mapper) },
+            StmtKind::Stop { mode } => StmtKind::Stop { mode },
+            StmtKind::DefineBlock { var, definition } => StmtKind::DefineBlock { var, definition: definition.reannotate(mapper) },
+            StmtKind::SetBlockAttribute { attr, block, value } => StmtKind::SetBlockAttribute { attr: attr.reannotate(mapper), block: block.reannotate(mapper), value: value.reannotate(mapper) },
+            StmtKind::MapCode { block, kind, code } => StmtKind::MapCode { block: block.reannotate(mapper), kind, code: code.reannotate(mapper) },
+            StmtKind::UnknownBlock { name, args } => StmtKind::UnknownBlock { name, args: args.reannotate(mapper) },
+        }
+    }
+}
+impl<M, N> Reannotate<M, N> for TextSplitMode<M> {
+    type Output = TextSplitMode<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        match self {
+            TextSplitMode::Letter => TextSplitMode::Letter,
+            TextSplitMode::Word => TextSplitMode::Word,
+            TextSplitMode::Tab => TextSplitMode::Tab,
+            TextSplitMode::CR => TextSplitMode::CR,
+            TextSplitMode::LF => TextSplitMode::LF,
+            TextSplitMode::Csv => TextSplitMode::Csv,
+            TextSplitMode::Json => TextSplitMode::Json,
+            TextSplitMode::Custom(x) => TextSplitMode::Custom(x.reannotate(mapper)),
+        }
+    }
+}
+impl<M, N> Reannotate<M, N> for ExprKind<M> {
+    type Output = ExprKind<N>;
+    fn reannotate(self, mapper: &mut dyn FnMut(M) -> N) -> Self::Output {
+        match self {
+            ExprKind::Value(x) => ExprKind::Value(x),
+            ExprKind::Variable { var } => ExprKind::Variable { var },
+            ExprKind::Add { values } => ExprKind::Add { values: values.reannotate(mapper) },
+            ExprKind::Mul { values } => ExprKind::Mul { values: values.reannotate(mapper) },
+            ExprKind::Min { values } => ExprKind::Min { values: values.reannotate(mapper) },
+            ExprKind::Max { values } => ExprKind::Max { values: values.reannotate(mapper) },
+            ExprKind::Sub { left, right } => ExprKind::Sub { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Div { left, right } => ExprKind::Div { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Mod { left, right } => ExprKind::Mod { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Pow { base, power } => ExprKind::Pow { base: base.reannotate(mapper), power: power.reannotate(mapper) },
+            ExprKind::Log { value, base } => ExprKind::Log { value: value.reannotate(mapper), base: base.reannotate(mapper) },
+            ExprKind::Atan2 { y, x } => ExprKind::Atan2 { y: y.reannotate(mapper), x: x.reannotate(mapper) },
+            ExprKind::And { left, right } => ExprKind::And { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Or { left, right } => ExprKind::Or { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Conditional { condition, then, otherwise } => ExprKind::Conditional { condition: condition.reannotate(mapper), then: then.reannotate(mapper), otherwise: otherwise.reannotate(mapper) },
+            ExprKind::Identical { left, right } => ExprKind::Identical { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Eq { left, right } => ExprKind::Eq { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Neq { left, right } => ExprKind::Neq { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Less { left, right } => ExprKind::Less { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::LessEq { left, right } => ExprKind::LessEq { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Greater { left, right } => ExprKind::Greater { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::GreaterEq { left, right } => ExprKind::GreaterEq { left: left.reannotate(mapper), right: right.reannotate(mapper) },
+            ExprKind::Random { a, b } => ExprKind::Random { a: a.reannotate(mapper), b: b.reannotate(mapper) },
+            ExprKind::Range { start, stop } => ExprKind::Range { start: start.reannotate(mapper), stop: stop.reannotate(mapper) },
+            ExprKind::MakeList { values } => ExprKind::MakeList { values: values.reannotate(mapper) },
+            ExprKind::CopyList { list } => ExprKind::CopyList { list: list.reannotate(mapper) },
+            ExprKind::ListCat { lists } => ExprKind::ListCat { lists: lists.reannotate(mapper) },
+            ExprKind::ListLen { value } => ExprKind::ListLen { value: value.reannotate(mapper) },
+            ExprKind::ListRank { value } => ExprKind::ListRank { value: value.reannotate(mapper) },
+            ExprKind::ListDims { value } => ExprKind::ListDims { value: value.reannotate(mapper) },
+            ExprKind::ListFlatten { value } => ExprKind::ListFlatten { value: value.reannotate(mapper) },
+            ExprKind::ListColumns { value } => ExprKind::ListColumns { value: value.reannotate(mapper) },
+            ExprKind::ListRev { value } => ExprKind::ListRev { value: value.reannotate(mapper) },
+            ExprKind::ListLines { value } => ExprKind::ListLines { value: value.reannotate(mapper) },
+            ExprKind::ListCsv { value } => ExprKind::ListCsv { value: value.reannotate(mapper) },
+            ExprKind::ListJson { value } => ExprKind::ListJson { value: value.reannotate(mapper) },
+            ExprKind::ListReshape { value, dims } => ExprKind::ListReshape { value: value.reannotate(mapper), dims: dims.reannotate(mapper) },
+            ExprKind::ListCombinations { sources } => ExprKind::ListCombinations { sources: sources.reannotate(mapper) },
+            ExprKind::ListIsEmpty { value } => ExprKind::ListIsEmpty { value: value.reannotate(mapper) },
+            ExprKind::ListCdr { value } => ExprKind::ListCdr { value: value.reannotate(mapper) },
+            ExprKind::ListCons { item, list } => ExprKind::ListCons { item: item.reannotate(mapper), list: list.reannotate(mapper) },
+            ExprKind::ListFind { list, value } => ExprKind::ListFind { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            ExprKind::ListContains { list, value } => ExprKind::ListContains { list: list.reannotate(mapper), value: value.reannotate(mapper) },
+            ExprKind::ListGet { list, index } => ExprKind::ListGet { list: list.reannotate(mapper), index: index.reannotate(mapper) },
+            ExprKind::ListGetLast { list } => ExprKind::ListGetLast { list: list.reannotate(mapper) },
+            ExprKind::ListGetRandom { list } => ExprKind::ListGetRandom { list: list.reannotate(mapper) },
+            ExprKind::StrGet { string, index } => ExprKind::StrGet { string: string.reannotate(mapper), index: index.reannotate(mapper) },
+            ExprKind::StrGetLast { string } => ExprKind::StrGetLast { string: string.reannotate(mapper) },
+            ExprKind::StrGetRandom { string } => ExprKind::StrGetRandom { string: string.reannotate(mapper) },
+            ExprKind::StrCat { values } => ExprKind::StrCat { values: values.reannotate(mapper) },
+            ExprKind::StrLen { value } => ExprKind::StrLen { value: value.reannotate(mapper) },
+            ExprKind::UnicodeToChar { value } => ExprKind::UnicodeToChar { value: value.reannotate(mapper) },
+            ExprKind::CharToUnicode { value } => ExprKind::CharToUnicode { value: value.reannotate(mapper) },
+            ExprKind::Not { value } => ExprKind::Not { value: value.reannotate(mapper) },
+            ExprKind::Neg { value } => ExprKind::Neg { value: value.reannotate(mapper) },
+            ExprKind::Abs { value } => ExprKind::Abs { value: value.reannotate(mapper) },
+            ExprKind::Sign { value } => ExprKind::Sign { value: value.reannotate(mapper) },
+            ExprKind::Sqrt { value } => ExprKind::Sqrt { value: value.reannotate(mapper) },
+            ExprKind::Floor { value } => ExprKind::Floor { value: value.reannotate(mapper) },
+            ExprKind::Ceil { value } => ExprKind::Ceil { value: value.reannotate(mapper) },
+            ExprKind::Round { value } => ExprKind::Round { value: value.reannotate(mapper) },
+            ExprKind::Sin { value } => ExprKind::Sin { value: value.reannotate(mapper) },
+            ExprKind::Cos { value } => ExprKind::Cos { value: value.reannotate(mapper) },
+            ExprKind::Tan { value } => ExprKind::Tan { value: value.reannotate(mapper) },
+            ExprKind::Asin { value } => ExprKind::Asin { value: value.reannotate(mapper) },
+            ExprKind::Acos { value } => ExprKind::Acos { value: value.reannotate(mapper) },
+            ExprKind::Atan { value } => ExprKind::Atan { value: value.reannotate(mapper) },
+            ExprKind::CallRpc { host, service, rpc, args } => ExprKind::CallRpc { host, service, rpc, args: args.reannotate(mapper) },
+            ExprKind::CallFn { function, args, upvars } => ExprKind::CallFn { function, args: args.reannotate(mapper), upvars },
+            ExprKind::CallClosure { new_entity, closure, args } => ExprKind::CallClosure { new_entity: new_entity.reannotate(mapper), closure: closure.reannotate(mapper), args: args.reannotate(mapper) },
+            ExprKind::StageWidth => ExprKind::StageWidth,
+            ExprKind::StageHeight => ExprKind::StageHeight,
+            ExprKind::MouseX => ExprKind::MouseX,
+            ExprKind::MouseY => ExprKind::MouseY,
+            ExprKind::Latitude => ExprKind::Latitude,
+            ExprKind::Longitude => ExprKind::Longitude,
+            ExprKind::KeyDown { key } => ExprKind::KeyDown { key: key.reannotate(mapper) },
+            ExprKind::YPos => ExprKind::YPos,
+            ExprKind::XPos => ExprKind::XPos,
+            ExprKind::Heading => ExprKind::Heading,
+            ExprKind::PenDown => ExprKind::PenDown,
+            ExprKind::Size => ExprKind::Size,
+            ExprKind::IsVisible => ExprKind::IsVisible,
+            ExprKind::This => ExprKind::This,
+            ExprKind::Entity { name, trans_name } => ExprKind::Entity { name, trans_name },
+            ExprKind::ImageOfEntity { entity } => ExprKind::ImageOfEntity { entity: entity.reannotate(mapper) },
+            ExprKind::ImageOfDrawings => ExprKind::ImageOfDrawings,
+            ExprKind::IsTouchingEntity { entity } => ExprKind::IsTouchingEntity { entity: entity.reannotate(mapper) },
+            ExprKind::IsTouchingMouse => ExprKind::IsTouchingMouse,
+            ExprKind::IsTouchingEdge => ExprKind::IsTouchingEdge,
+            ExprKind::IsTouchingDrawings => ExprKind::IsTouchingDrawings,
+            ExprKind::RpcError => ExprKind::RpcError,
+            ExprKind::Closure { kind, params, captures, stmts } => ExprKind::Closure { kind, params, captures, stmts: stmts.reannotate(mapper) },
+            ExprKind::TextSplit { text, mode } => ExprKind::TextSplit { text: text.reannotate(mapper), mode: mode.reannotate(mapper) },
+            ExprKind::TextFunction { function, value } => ExprKind::TextFunction { function, value: value.reannotate(mapper) },
+            ExprKind::Answer => ExprKind::Answer,
+            ExprKind::Message => ExprKind::Message,
+            ExprKind::Timer => ExprKind::Timer,
+            ExprKind::Map { f, list } => ExprKind::Map { f: f.reannotate(mapper), list: list.reannotate(mapper) },
+            ExprKind::Keep { f, list } => ExprKind::Keep { f: f.reannotate(mapper), list: list.reannotate(mapper) },
+            ExprKind::FindFirst { f, list } => ExprKind::FindFirst { f: f.reannotate(mapper), list: list.reannotate(mapper) },
+            ExprKind::Combine { f, list } => ExprKind::Combine { f: f.reannotate(mapper), list: list.reannotate(mapper) },
+            ExprKind::Pipe { value, functions } => ExprKind::Pipe { value: value.reannotate(mapper), functions: functions.reannotate(mapper) },
+            ExprKind::NetworkMessageReply { target, msg_type, values } => ExprKind::NetworkMessageReply { target: target.reannotate(mapper), msg_type, values: values.reannotate(mapper) },
+            ExprKind::Effect { kind } => ExprKind::Effect { kind },
+            ExprKind::PenAttr { attr } => ExprKind::PenAttr { attr },
+            ExprKind::GlobalFlag { flag } => ExprKind::GlobalFlag { flag },
+            ExprKind::Tempo => ExprKind::Tempo,
+            ExprKind::CostumeList => ExprKind::CostumeList,
+            ExprKind::Costume => ExprKind::Costume,
+            ExprKind::CostumeNumber => ExprKind::CostumeNumber,
+            ExprKind::CostumeName { costume } => ExprKind::CostumeName { costume: costume.reannotate(mapper) },
+            ExprKind::CostumeWidth { costume } => ExprKind::CostumeWidth { costume: costume.reannotate(mapper) },
+            ExprKind::CostumeHeight { costume } => ExprKind::CostumeHeight { costume: costume.reannotate(mapper) },
+            ExprKind::CostumePixels { costume } => ExprKind::CostumePixels { costume: costume.reannotate(mapper) },
+            ExprKind::SoundList => ExprKind::SoundList,
+            ExprKind::SoundName { sound } => ExprKind::SoundName { sound: sound.reannotate(mapper) },
+            ExprKind::SoundDuration { sound } => ExprKind::SoundDuration { sound: sound.reannotate(mapper) },
+            ExprKind::SoundSampleRate { sound } => ExprKind::SoundSampleRate { sound: sound.reannotate(mapper) },
+            ExprKind::SoundSamples { sound } => ExprKind::SoundSamples { sound: sound.reannotate(mapper) },
+            ExprKind::SoundSamplesLength { sound } => ExprKind::SoundSamplesLength { sound: sound.reannotate(mapper) },
+            ExprKind::SoundChannelCount { sound } => ExprKind::SoundChannelCount { sound: sound.reannotate(mapper) },
+            ExprKind::Clone { target } => ExprKind::Clone { target: target.reannotate(mapper) },
+            ExprKind::TypeQuery { value, ty } => ExprKind::TypeQuery { value: value.reannotate(mapper), ty },
+            ExprKind::RealTime { query } => ExprKind::RealTime { query },
+            ExprKind::BlockAttribute { attr, block } => ExprKind::BlockAttribute { attr: attr.reannotate(mapper), block: block.reannotate(mapper) },
+            ExprKind::MappedCode { value } => ExprKind::MappedCode { value: value.reannotate(mapper) },
+            ExprKind::JsFunction { params, body } => ExprKind::JsFunction { params, body },
+            ExprKind::EmptySlot => ExprKind::EmptySlot,
+            ExprKind::UnknownBlock { name, args } => ExprKind::UnknownBlock { name, args: args.reannotate(mapper) },
+        }
+    }
+}
+
+macro_rules! impl_map_annotations {
+    ($($t:ident),*$(,)?) => {$(
+        impl<M> $t<M> {
+            /// Replaces every annotation in this tree (see [`BlockInfo::annotation`]) with the result of calling `f` on it.
+            /// Annotations are visited in pre-order; that is, a block's annotation is visited before those of its inputs and nested scripts.
+            pub fn map_annotations<N>(self, mut f: impl FnMut(M) -> N) -> $t<N> {
+                self.reannotate(&mut f)
+            }
+        }
+    )*}
+}
+impl_map_annotations! { Project, Role, Function, Entity, Script, Hat, Stmt, Expr }
@@ -0,0 +1,211 @@
+//! De-identification of projects for publishing datasets of student code.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// The mapping from canonical identifiers back to the original names, as produced by [`Project::anonymize`].
+///
+/// Each table holds `(canonical, original)` pairs in order of first appearance in the project.
+/// This can be used with [`Project::deanonymize`] to restore the original names, so it should be kept private when publishing the anonymized project.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Anonymization {
+    /// The original name of the project.
+    pub project: CompactString,
+    /// The names of the roles, as `(canonical, original)` pairs in the same order as [`Project::roles`].
+    pub roles: Vec<(CompactString, CompactString)>,
+    /// Variables of all kinds, including globals, fields, locals, parameters, and upvars.
+    /// Every use of a given name maps to the same canonical name, regardless of scope.
+    pub vars: Vec<(CompactString, CompactString)>,
+    /// Custom blocks, where the argument slots (tabs) of [`Function::name`] are kept in the canonical name.
+    pub funcs: Vec<(CompactString, CompactString)>,
+}
+impl Anonymization {
+    /// Gets the original name of the variable with the given canonical name.
+    pub fn original_var(&self, canonical: &str) -> Option<&str> {
+        self.vars.iter().find(|x| x.0 == canonical).map(|x| x.1.as_str())
+    }
+    /// Gets the original name of the custom block with the given canonical name.
+    pub fn original_func(&self, canonical: &str) -> Option<&str> {
+        self.funcs.iter().find(|x| x.0 == canonical).map(|x| x.1.as_str())
+    }
+}
+
+/// An identifier, comment, or collab id anywhere in a project.
+enum Name<'a> {
+    Var { name: &'a mut CompactString, trans_name: &'a mut CompactString },
+    Fn { name: &'a mut CompactString, trans_name: &'a mut CompactString },
+    Comment(&'a mut Option<CompactString>),
+    CollabId(&'a mut Option<CompactString>),
+}
+
+/// Calls `f` on every variable and custom block definition or reference, and every block comment and collab id, in a role.
+fn visit_names(role: &mut Role, f: &mut dyn FnMut(Name)) {
+    fn var(def: &mut VariableDef) -> Name<'_> {
+        Name::Var { name: &mut def.name, trans_name: &mut def.trans_name }
+    }
+    fn code(stmts: &mut Vec<Stmt>, f: &mut dyn FnMut(Name)) {
+        for stmt in stmts {
+            f(Name::Comment(&mut stmt.info.comment));
+            f(Name::CollabId(&mut stmt.info.location));
+            if let StmtKind::DeclareLocals { vars } = &mut stmt.kind {
+                for x in vars { f(var(x)) }
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { code_expr(x, f) }
+            for x in bodies { code(x, f) }
+        }
+    }
+    fn code_expr(expr: &mut Expr, f: &mut dyn FnMut(Name)) {
+        f(Name::Comment(&mut expr.info.comment));
+        f(Name::CollabId(&mut expr.info.location));
+        if let ExprKind::Closure { params, .. } = &mut expr.kind {
+            for x in params { f(var(x)) }
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { code_expr(x, f) }
+        for x in bodies { code(x, f) }
+    }
+    fn func(func: &mut Function, f: &mut dyn FnMut(Name)) {
+        f(Name::Fn { name: &mut func.name, trans_name: &mut func.trans_name });
+        f(Name::Comment(&mut func.help));
+        for x in func.params.iter_mut() { f(var(x)) }
+        for x in func.upvars.iter_mut() { f(Name::Var { name: &mut x.name, trans_name: &mut x.trans_name }) }
+        code(&mut func.stmts, f);
+    }
+
+    for x in role.globals.iter_mut() { f(var(&mut x.def)) }
+    for x in role.funcs.iter_mut() { func(x, f) }
+    for entity in role.entities.iter_mut() {
+        for x in entity.fields.iter_mut() { f(var(&mut x.def)) }
+        for x in entity.funcs.iter_mut() { func(x, f) }
+        for script in entity.scripts.iter_mut() {
+            for x in script.locals.iter_mut() { f(var(x)) }
+            if let Some(hat) = script.hat.as_deref_mut() {
+                f(Name::Comment(&mut hat.info.comment));
+                f(Name::CollabId(&mut hat.info.location));
+                if let HatKind::When { condition } = &mut hat.kind {
+                    code_expr(condition, f);
+                }
+            }
+            code(&mut script.stmts, f);
+        }
+        for x in entity.floating_reporters.iter_mut() { code_expr(x, f) }
+    }
+    role_refs_mut(role, &mut |_, x| match x {
+        SymbolRefMut::Var(x) => f(Name::Var { name: &mut x.name, trans_name: &mut x.trans_name }),
+        SymbolRefMut::Fn(x) => f(Name::Fn { name: &mut x.name, trans_name: &mut x.trans_name }),
+        SymbolRefMut::Entity { .. } => (),
+    });
+}
+
+/// Renames variables and custom blocks according to tables from old names to new (name, trans name) pairs.
+/// If `strip` is set, all block comments and collab ids are removed as well.
+fn rename(role: &mut Role, vars: &BTreeMap<CompactString, VariableDef>, funcs: &BTreeMap<CompactString, VariableDef>, strip: bool) {
+    visit_names(role, &mut |x| match x {
+        Name::Var { name, trans_name } => if let Some(new) = vars.get(name.as_str()) {
+            *name = new.name.clone();
+            *trans_name = new.trans_name.clone();
+        }
+        Name::Fn { name, trans_name } => if let Some(new) = funcs.get(name.as_str()) {
+            *name = new.name.clone();
+            *trans_name = new.trans_name.clone();
+        }
+        Name::Comment(comment) => if strip { *comment = None },
+        Name::CollabId(location) => if strip { *location = None },
+    });
+    role.reindex();
+}
+
+fn transform_name<T: NameTransformer>(parser: &Parser<T>, name: &str) -> Result<CompactString, Box<Error>> {
+    parser.name_transformer.transform_name(name).map_err(|()| Box::new(Error {
+        kind: CompileError::NameTransformError { name: name.into() }.into(),
+        location: Location { role: None, entity: None, collab_id: None, block_type: None },
+    }))
+}
+
+impl Project {
+    /// De-identifies the project so that it can be published as part of a dataset.
+    ///
+    /// This removes all comments (on blocks, on the scripting area, and custom block help text), role notes, and block collab ids ([`BlockInfo::location`]),
+    /// and replaces the project name, role names, and all variable and custom block names with canonical identifiers
+    /// (e.g., `"role 1"`, `"var 1"`, and `"block 1"`), where translated names are generated by [`Parser::name_transformer`].
+    ///
+    /// Sprite names, costume and sound names (and their content), and literal values are NOT modified, since scripts may refer to them by value.
+    /// Callers publishing a dataset should review these separately if they may contain identifying data.
+    ///
+    /// The returned [`Anonymization`] can be used to restore the original names with [`Project::deanonymize`].
+    /// On failure, the project is not modified.
+    pub fn anonymize<T: NameTransformer>(&mut self, parser: &Parser<T>) -> Result<Anonymization, Box<Error>> {
+        let mut res = Anonymization { project: self.name.clone(), ..Default::default() };
+        let mut vars = BTreeMap::new();
+        let mut funcs = BTreeMap::new();
+        let mut project = self.clone();
+
+        for role in project.roles.iter_mut() {
+            // collect the names first, since transforming them may fail
+            let mut names = (Vec::new(), Vec::new());
+            visit_names(role, &mut |x| match x {
+                Name::Var { name, .. } => if !vars.contains_key(name.as_str()) && !names.0.contains(name) { names.0.push(name.clone()) },
+                Name::Fn { name, .. } => if !funcs.contains_key(name.as_str()) && !names.1.contains(name) { names.1.push(name.clone()) },
+                Name::Comment(_) | Name::CollabId(_) => (),
+            });
+            for name in names.0 {
+                let new = format_compact!("var {}", res.vars.len() + 1);
+                vars.insert(name.clone(), VariableDef { trans_name: transform_name(parser, &new)?, name: new.clone() });
+                res.vars.push((new, name));
+            }
+            for name in names.1 {
+                let mut new = format_compact!("block {}", res.funcs.len() + 1);
+                for _ in name.chars().filter(|&x| x == '\t') { new.push_str(" \t") }
+                funcs.insert(name.clone(), VariableDef { trans_name: transform_name(parser, &new)?, name: new.clone() });
+                res.funcs.push((new, name));
+            }
+
+            rename(role, &vars, &funcs, true);
+
+            let new = format_compact!("role {}", res.roles.len() + 1);
+            res.roles.push((new.clone(), core::mem::replace(&mut role.name, new)));
+            role.notes = CompactString::default();
+            role.source_hash = None;
+            for entity in role.entities.iter_mut() {
+                entity.canvas_comments.clear();
+            }
+        }
+        project.name = "project".into();
+
+        *self = project;
+        Ok(res)
+    }
+    /// Restores the original project, role, variable, and custom block names of a project anonymized by [`Project::anonymize`].
+    /// Names which do not appear in the mapping are left unchanged, and removed comments and collab ids are not restored.
+    /// On failure, the project is not modified.
+    pub fn deanonymize<T: NameTransformer>(&mut self, parser: &Parser<T>, mapping: &Anonymization) -> Result<(), Box<Error>> {
+        let mut vars = BTreeMap::new();
+        for (canonical, original) in mapping.vars.iter() {
+            vars.insert(canonical.clone(), VariableDef { name: original.clone(), trans_name: transform_name(parser, original)? });
+        }
+        let mut funcs = BTreeMap::new();
+        for (canonical, original) in mapping.funcs.iter() {
+            funcs.insert(canonical.clone(), VariableDef { name: original.clone(), trans_name: transform_name(parser, original)? });
+        }
+
+        for role in self.roles.iter_mut() {
+            rename(role, &vars, &funcs, false);
+            if let Some((_, original)) = mapping.roles.iter().find(|x| x.0 == role.name) {
+                role.name = original.clone();
+            }
+        }
+        self.name = mapping.project.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Builders for constructing ASTs programmatically.
+//!
+//! These take care of registering symbols and generating translated names with [`Parser::name_transformer`],
+//! so that every [`VariableRef`] and [`FnRef`] handed out is consistent with the definitions in the resulting AST.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+
+impl From<StmtKind> for Stmt {
+    fn from(kind: StmtKind) -> Self {
+        Stmt { kind, info: BlockInfo::none() }
+    }
+}
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr { kind, info: BlockInfo::none() }
+    }
+}
+impl From<VariableRef> for Expr {
+    fn from(var: VariableRef) -> Self {
+        ExprKind::Variable { var }.into()
+    }
+}
+
+/// Builds a [`Role`].
+pub struct RoleBuilder<'a, T = DefaultNameTransformer> {
+    parser: &'a Parser<T>,
+    name: CompactString,
+    globals: SymbolTable<'a, T>,
+    funcs: Vec<Function>,
+    func_names: SymbolTable<'a, T>,
+    entities: Vec<Entity>,
+    entity_names: SymbolTable<'a, T>,
+}
+impl<'a, T: NameTransformer> RoleBuilder<'a, T> {
+    pub fn new(parser: &'a Parser<T>, name: CompactString) -> Self {
+        Self { parser, name, globals: SymbolTable::new(parser), funcs: vec![], func_names: SymbolTable::new(parser), entities: vec![], entity_names: SymbolTable::new(parser) }
+    }
+    /// Defines a new global variable and returns a reference to it.
+    pub fn global(&mut self, name: CompactString, init: Value) -> Result<VariableRef, SymbolError> {
+        self.globals.define(name.clone(), init)?;
+        Ok(var_ref(&self.globals.get(&name).unwrap().def, VarLocation::Global))
+    }
+    /// Creates a builder for a new global custom block.
+    /// The block is not added to the role until it is passed to [`RoleBuilder::add_func`].
+    pub fn func(&self, name: CompactString, returns: bool) -> Result<FunctionBuilder<'a, T>, SymbolError> {
+        FunctionBuilder::new(self.parser, name, FnLocation::Global, returns)
+    }
+    pub fn add_func(&mut self, func: Function) -> Result<(), SymbolError> {
+        self.func_names.define(func.name.clone(), 0f64.into())?;
+        self.funcs.push(func);
+        Ok(())
+    }
+    /// Creates a builder for a new entity.
+    /// The entity is not added to the role until it is passed to [`RoleBuilder::add_entity`].
+    pub fn entity(&self, name: CompactString) -> Result<EntityBuilder<'a, T>, SymbolError> {
+        EntityBuilder::new(self.parser, name)
+    }
+    pub fn add_entity(&mut self, entity: Entity) -> Result<(), SymbolError> {
+        self.entity_names.define(entity.name.clone(), 0f64.into())?;
+        self.entities.push(entity);
+        Ok(())
+    }
+    /// Gets an expression which refers to a previously added entity.
+    pub fn entity_ref(&self, name: &str) -> Option<Expr> {
+        let def = &self.entity_names.get(name)?.def;
+        Some(ExprKind::Entity { name: def.name.clone(), trans_name: def.trans_name.clone() }.into())
+    }
+    pub fn build(self) -> Role {
+        let globals = self.globals.into_def_inits();
+        Role {
+            name: self.name,
+            notes: CompactString::default(),
+            stage_size: (480, 360),
+            global_index: VarIndexCache::new(&globals),
+            globals,
+            funcs: self.funcs,
+            entities: self.entities,
+            code_mappings: vec![],
+            code_headers: vec![],
+            palette: Palette::default(),
+            thumbnail: None,
+            pen_trails: None,
+            source_hash: None,
+        }
+    }
+}
+
+/// Builds an [`Entity`] with default attributes, which can be modified after building.
+pub struct EntityBuilder<'a, T = DefaultNameTransformer> {
+    parser: &'a Parser<T>,
+    name: CompactString,
+    trans_name: CompactString,
+    fields: SymbolTable<'a, T>,
+    funcs: Vec<Function>,
+    func_names: SymbolTable<'a, T>,
+    scripts: Vec<Script>,
+}
+impl<'a, T: NameTransformer> EntityBuilder<'a, T> {
+    pub fn new(parser: &'a Parser<T>, name: CompactString) -> Result<Self, SymbolError> {
+        let fields = SymbolTable::new(parser);
+        let trans_name = fields.transform_name(&name)?;
+        Ok(Self { parser, name, trans_name, fields, funcs: vec![], func_names: SymbolTable::new(parser), scripts: vec![] })
+    }
+    /// Defines a new field and returns a reference to it.
+    pub fn field(&mut self, name: CompactString, init: Value) -> Result<VariableRef, SymbolError> {
+        self.fields.define(name.clone(), init)?;
+        Ok(var_ref(&self.fields.get(&name).unwrap().def, VarLocation::Field))
+    }
+    /// Creates a builder for a new custom block owned by this entity.
+    /// The block is not added to the entity until it is passed to [`EntityBuilder::add_func`].
+    pub fn func(&self, name: CompactString, returns: bool) -> Result<FunctionBuilder<'a, T>, SymbolError> {
+        FunctionBuilder::new(self.parser, name, FnLocation::Method, returns)
+    }
+    pub fn add_func(&mut self, func: Function) -> Result<(), SymbolError> {
+        self.func_names.define(func.name.clone(), 0f64.into())?;
+        self.funcs.push(func);
+        Ok(())
+    }
+    /// Creates a builder for a new script.
+    /// The script is not added to the entity until it is passed to [`EntityBuilder::add_script`].
+    pub fn script(&self) -> ScriptBuilder<'a, T> {
+        ScriptBuilder::new(self.parser)
+    }
+    pub fn add_script(&mut self, script: Script) {
+        self.scripts.push(script);
+    }
+    pub fn build(self) -> Entity {
+        let fields = self.fields.into_def_inits();
+        Entity {
+            name: self.name,
+            trans_name: self.trans_name,
+            field_index: VarIndexCache::new(&fields),
+            fields,
+            costumes: vec![],
+            sounds: vec![],
+            funcs: self.funcs,
+            scripts: self.scripts,
+            canvas_comments: vec![],
+            floating_reporters: vec![],
+
+            active_costume: None,
+            visible: true,
+            color: (0, 0, 0, 255),
+            pos: (0.0, 0.0),
+            heading: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Builds a sequence of statements along with the local variables they define.
+pub struct ScriptBuilder<'a, T = DefaultNameTransformer> {
+    locals: SymbolTable<'a, T>,
+    hat: Option<Box<Hat>>,
+    stmts: Vec<Stmt>,
+}
+impl<'a, T: NameTransformer> ScriptBuilder<'a, T> {
+    pub fn new(parser: &'a Parser<T>) -> Self {
+        Self { locals: SymbolTable::new(parser), hat: None, stmts: vec![] }
+    }
+    /// Sets the hat block of the script.
+    pub fn hat(&mut self, kind: HatKind) -> &mut Self {
+        self.hat = Some(Box::new(Hat { kind, info: BlockInfo::none() }));
+        self
+    }
+    /// Declares a new local variable (via [`StmtKind::DeclareLocals`]) and returns a reference to it.
+    pub fn local(&mut self, name: CompactString) -> Result<VariableRef, SymbolError> {
+        self.locals.define(name.clone(), 0f64.into())?;
+        let def = self.locals.get(&name).unwrap().def.clone();
+        let var = var_ref(&def, VarLocation::Local);
+        self.stmts.push(StmtKind::DeclareLocals { vars: vec![def] }.into());
+        Ok(var)
+    }
+    /// Appends a statement to the script.
+    pub fn stmt(&mut self, stmt: impl Into<Stmt>) -> &mut Self {
+        self.stmts.push(stmt.into());
+        self
+    }
+    pub fn build(self) -> Script {
+        Script { hat: self.hat, stmts: self.stmts, locals: self.locals.into_defs() }
+    }
+}
+
+/// Builds a [`Function`], which is created by [`RoleBuilder::func`] or [`EntityBuilder::func`].
+pub struct FunctionBuilder<'a, T = DefaultNameTransformer> {
+    name: CompactString,
+    trans_name: CompactString,
+    location: FnLocation,
+    returns: bool,
+    params: Vec<VariableDef>,
+    param_info: Vec<ParamInfo>,
+    category: CompactString,
+    help: Option<CompactString>,
+    body: ScriptBuilder<'a, T>,
+}
+impl<'a, T: NameTransformer> FunctionBuilder<'a, T> {
+    fn new(parser: &'a Parser<T>, name: CompactString, location: FnLocation, returns: bool) -> Result<Self, SymbolError> {
+        let body = ScriptBuilder::new(parser);
+        let trans_name = body.locals.transform_name(&name)?;
+        Ok(Self { name, trans_name, location, returns, params: vec![], param_info: vec![], category: CompactString::default(), help: None, body })
+    }
+    /// Defines a new parameter with the given input metadata and returns a reference to it.
+    pub fn param_with_info(&mut self, name: CompactString, info: ParamInfo) -> Result<VariableRef, SymbolError> {
+        self.body.locals.define(name.clone(), 0f64.into())?;
+        let def = self.body.locals.get(&name).unwrap().def.clone();
+        let var = var_ref(&def, VarLocation::Local);
+        self.params.push(def);
+        self.param_info.push(info);
+        Ok(var)
+    }
+    /// Defines a new parameter (of type [`InputType::Any`] with no default value) and returns a reference to it.
+    pub fn param(&mut self, name: CompactString) -> Result<VariableRef, SymbolError> {
+        self.param_with_info(name, ParamInfo { kind: ParamKind::Single, input_type: InputType::Any, default: CompactString::default() })
+    }
+    /// Defines a new variadic parameter (see [`ParamKind::Variadic`]) and returns a reference to it.
+    pub fn variadic_param(&mut self, name: CompactString) -> Result<VariableRef, SymbolError> {
+        self.param_with_info(name, ParamInfo { kind: ParamKind::Variadic, input_type: InputType::Any, default: CompactString::default() })
+    }
+    /// Sets the palette category of the function.
+    pub fn category(&mut self, category: CompactString) -> &mut Self {
+        self.category = category;
+        self
+    }
+    /// Sets the help text of the function.
+    pub fn help(&mut self, help: CompactString) -> &mut Self {
+        self.help = Some(help);
+        self
+    }
+    /// Gets the body of the function, which can be used to declare locals and add statements.
+    pub fn body(&mut self) -> &mut ScriptBuilder<'a, T> {
+        &mut self.body
+    }
+    /// Gets a reference to the function, which can be used to call it.
+    pub fn fn_ref(&self) -> FnRef {
+        FnRef { name: self.name.clone(), trans_name: self.trans_name.clone(), location: self.location }
+    }
+    pub fn build(self) -> Function {
+        Function {
+            name: self.name,
+            trans_name: self.trans_name,
+            params: self.params,
+            param_info: self.param_info,
+            upvars: vec![],
+            returns: self.returns,
+            stmts: self.body.stmts,
+            category: self.category,
+            location: self.location,
+            help: self.help,
+            code_mapping: CompactString::default(),
+            code_header: CompactString::default(),
+        }
+    }
+}
+
+fn var_ref(def: &VariableDef, location: VarLocation) -> VariableRef {
+    VariableRef { name: def.name.clone(), trans_name: def.trans_name.clone(), location, slot: None }
+}
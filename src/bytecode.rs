@@ -0,0 +1,319 @@
+//! A portable stack bytecode, compiled from the core IR (see [`crate::ir`]).
+//!
+//! A [`Program`] is a set of [`Chunk`]s of [`Op`]s, along with the tables which the ops refer to by index (constants, variables, primitives, and closures).
+//! Each op pops its inputs from the value stack and pushes its result (if any).
+//! Chunks are built with an [`Assembler`], which resolves jump targets, and can be listed with the [`fmt::Display`] implementation of [`Program`].
+//!
+//! This module is only available with the `bytecode` feature.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::*;
+use crate::ir::*;
+use crate::query::{expr_opcode, stmt_opcode};
+
+/// A bytecode instruction.
+/// Addresses are indices into the code of the same [`Chunk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    /// Pushes [`Program::consts`]`[i]`.
+    Const(usize),
+    /// Pushes the value of [`Program::vars`]`[i]`.
+    Load(usize),
+    /// Pops a value into [`Program::vars`]`[i]`.
+    Store(usize),
+    /// Pushes the value of a temporary.
+    LoadTemp(usize),
+    /// Pops a value into a temporary.
+    StoreTemp(usize),
+    /// Pops the inputs of [`Program::prims`]`[i]` (which were pushed in order) and runs it.
+    /// Reporters push their result, while commands do not.
+    Prim(usize),
+    /// Pushes a new closure for [`Program::closures`]`[i]`, which captures the current values of its [`ClosureInfo::captures`].
+    Closure(usize),
+    Jump(usize),
+    /// Pops a value and jumps if it is false.
+    JumpIfFalse(usize),
+    /// Starts a try block, which lasts until the matching [`Op::EndTry`].
+    /// If an error is thrown in the block, the stack is restored, the error message is stored in [`Program::vars`]`[var]`, and execution continues at `handler`.
+    Try { handler: usize, var: usize },
+    EndTry,
+    /// Starts running without yielding, until the matching [`Op::EndWarp`].
+    Warp,
+    EndWarp,
+    /// Pops a value and returns it, which also ends any try or warp blocks of the chunk.
+    Return,
+    /// Ends the chunk without a value.
+    End,
+}
+
+/// A primitive block of a [`Program`], as in [`Instr::Eval`] and [`Instr::Exec`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prim {
+    Expr { op: ExprKind, args: usize },
+    Stmt { op: StmtKind, args: usize },
+}
+
+/// A closure of a [`Program`], as in [`Instr::Closure`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureInfo {
+    pub kind: ClosureKind,
+    pub params: Vec<VariableDef>,
+    pub captures: Vec<VariableRef>,
+    /// The index of the body in [`Program::chunks`].
+    pub chunk: usize,
+}
+
+/// A sequence of ops, along with the number of temporaries it uses.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub temps: usize,
+}
+
+/// Compiled bytecode (see [`compile`]).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    /// The code, where the first chunk is the entry point and the rest are closure bodies.
+    pub chunks: Vec<Chunk>,
+    /// The constant pool, which has no duplicates.
+    pub consts: Vec<Value>,
+    /// The variables referenced by the code, which has no duplicates.
+    pub vars: Vec<VariableRef>,
+    pub prims: Vec<Prim>,
+    pub closures: Vec<ClosureInfo>,
+}
+
+/// A jump target in an [`Assembler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+/// Builds the code of a [`Chunk`], where jumps can refer to [`Label`]s before their addresses are known.
+#[derive(Debug, Default)]
+pub struct Assembler {
+    code: Vec<Op>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label)>,
+}
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Makes a new label, which must be placed with [`Assembler::bind`] before calling [`Assembler::finish`].
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+    /// Places a label at the address of the next op.
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+    pub fn emit(&mut self, op: Op) {
+        self.code.push(op);
+    }
+    /// Emits an op with an address ([`Op::Jump`], [`Op::JumpIfFalse`], or [`Op::Try`]), whose address is replaced by that of the label in [`Assembler::finish`].
+    pub fn emit_to(&mut self, label: Label, op: Op) {
+        self.fixups.push((self.code.len(), label));
+        self.code.push(op);
+    }
+    /// Resolves the labels and returns the code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a label was used but never placed.
+    pub fn finish(mut self) -> Vec<Op> {
+        for (pos, label) in self.fixups {
+            let addr = self.labels[label.0].expect("label was never bound");
+            self.code[pos] = match self.code[pos] {
+                Op::Jump(_) => Op::Jump(addr),
+                Op::JumpIfFalse(_) => Op::JumpIfFalse(addr),
+                Op::Try { var, .. } => Op::Try { handler: addr, var },
+                x => x,
+            };
+        }
+        self.code
+    }
+}
+
+/// The blocks of code which enclose an op, which must be ended when jumping out of them.
+enum Scope {
+    Loop(Label),
+    Try,
+    Warp,
+}
+
+struct Emitter {
+    program: Program,
+}
+impl Emitter {
+    fn intern<T: PartialEq>(table: &mut Vec<T>, value: T) -> usize {
+        match table.iter().position(|x| *x == value) {
+            Some(i) => i,
+            None => {
+                table.push(value);
+                table.len() - 1
+            }
+        }
+    }
+    fn var(&mut self, var: &VariableRef) -> usize {
+        Self::intern(&mut self.program.vars, var.clone())
+    }
+    fn chunk(&mut self, body: &Body) -> usize {
+        let index = self.program.chunks.len();
+        self.program.chunks.push(Chunk::default());
+        let mut asm = Assembler::new();
+        self.instrs(&body.instrs, &mut asm, &mut vec![]);
+        asm.emit(Op::End);
+        self.program.chunks[index] = Chunk { code: asm.finish(), temps: body.temps };
+        index
+    }
+    fn operand(&mut self, operand: &Operand, asm: &mut Assembler) {
+        match operand {
+            Operand::Value(x) => asm.emit(Op::Const(Self::intern(&mut self.program.consts, x.clone()))),
+            Operand::Var(x) => asm.emit(Op::Load(self.var(x))),
+            Operand::Temp(x) => asm.emit(Op::LoadTemp(x.0)),
+        }
+    }
+    fn instrs(&mut self, instrs: &[Instr], asm: &mut Assembler, scopes: &mut Vec<Scope>) {
+        for instr in instrs {
+            match instr {
+                Instr::Eval { dest, op, args, .. } => {
+                    for arg in args { self.operand(arg, asm) }
+                    self.program.prims.push(Prim::Expr { op: op.clone(), args: args.len() });
+                    asm.emit(Op::Prim(self.program.prims.len() - 1));
+                    asm.emit(Op::StoreTemp(dest.0));
+                }
+                Instr::Exec { op, args, .. } => {
+                    for arg in args { self.operand(arg, asm) }
+                    self.program.prims.push(Prim::Stmt { op: op.clone(), args: args.len() });
+                    asm.emit(Op::Prim(self.program.prims.len() - 1));
+                }
+                Instr::Move { dest, value } => {
+                    self.operand(value, asm);
+                    asm.emit(Op::StoreTemp(dest.0));
+                }
+                Instr::Assign { var, value } => {
+                    self.operand(value, asm);
+                    asm.emit(Op::Store(self.var(var)));
+                }
+                Instr::Closure { dest, kind, params, captures, body } => {
+                    let chunk = self.chunk(body);
+                    self.program.closures.push(ClosureInfo { kind: kind.clone(), params: params.clone(), captures: captures.clone(), chunk });
+                    asm.emit(Op::Closure(self.program.closures.len() - 1));
+                    asm.emit(Op::StoreTemp(dest.0));
+                }
+                Instr::If { condition, then, otherwise } => {
+                    let (otherwise_label, end) = (asm.label(), asm.label());
+                    self.operand(condition, asm);
+                    asm.emit_to(otherwise_label, Op::JumpIfFalse(0));
+                    self.instrs(then, asm, scopes);
+                    asm.emit_to(end, Op::Jump(0));
+                    asm.bind(otherwise_label);
+                    self.instrs(otherwise, asm, scopes);
+                    asm.bind(end);
+                }
+                Instr::Loop { body } => {
+                    let (start, end) = (asm.label(), asm.label());
+                    asm.bind(start);
+                    scopes.push(Scope::Loop(end));
+                    self.instrs(body, asm, scopes);
+                    scopes.pop();
+                    asm.emit_to(start, Op::Jump(0));
+                    asm.bind(end);
+                }
+                Instr::Break => {
+                    // end any blocks between here and the loop, innermost first
+                    for scope in scopes.iter().rev() {
+                        match scope {
+                            Scope::Loop(end) => {
+                                asm.emit_to(*end, Op::Jump(0));
+                                break;
+                            }
+                            Scope::Try => asm.emit(Op::EndTry),
+                            Scope::Warp => asm.emit(Op::EndWarp),
+                        }
+                    }
+                }
+                Instr::Warp { body } => {
+                    asm.emit(Op::Warp);
+                    scopes.push(Scope::Warp);
+                    self.instrs(body, asm, scopes);
+                    scopes.pop();
+                    asm.emit(Op::EndWarp);
+                }
+                Instr::TryCatch { body, var, handler } => {
+                    let (handler_label, end) = (asm.label(), asm.label());
+                    let var = self.var(var);
+                    asm.emit_to(handler_label, Op::Try { handler: 0, var });
+                    scopes.push(Scope::Try);
+                    self.instrs(body, asm, scopes);
+                    scopes.pop();
+                    asm.emit(Op::EndTry);
+                    asm.emit_to(end, Op::Jump(0));
+                    asm.bind(handler_label);
+                    self.instrs(handler, asm, scopes);
+                    asm.bind(end);
+                }
+                Instr::Return { value } => {
+                    self.operand(value, asm);
+                    asm.emit(Op::Return);
+                }
+            }
+        }
+    }
+}
+
+/// Compiles lowered code (see [`ir::lower`]) into bytecode, where the code is the first chunk of the program.
+pub fn compile(body: &Body) -> Program {
+    let mut emitter = Emitter { program: Program::default() };
+    emitter.chunk(body);
+    emitter.program
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Const(x) => write!(f, "const {x}"),
+            Op::Load(x) => write!(f, "load {x}"),
+            Op::Store(x) => write!(f, "store {x}"),
+            Op::LoadTemp(x) => write!(f, "load.tmp {x}"),
+            Op::StoreTemp(x) => write!(f, "store.tmp {x}"),
+            Op::Prim(x) => write!(f, "prim {x}"),
+            Op::Closure(x) => write!(f, "closure {x}"),
+            Op::Jump(x) => write!(f, "jump {x}"),
+            Op::JumpIfFalse(x) => write!(f, "jump.false {x}"),
+            Op::Try { handler, var } => write!(f, "try {handler} {var}"),
+            Op::EndTry => write!(f, "end.try"),
+            Op::Warp => write!(f, "warp"),
+            Op::EndWarp => write!(f, "end.warp"),
+            Op::Return => write!(f, "return"),
+            Op::End => write!(f, "end"),
+        }
+    }
+}
+/// Lists the code of every chunk, with comments describing the table entries that the ops refer to.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            writeln!(f, "chunk {i} ({} temps):", chunk.temps)?;
+            for (addr, op) in chunk.code.iter().enumerate() {
+                let comment = match op {
+                    Op::Const(x) => format_compact!("{}", self.consts[*x]),
+                    Op::Load(x) | Op::Store(x) | Op::Try { var: x, .. } => self.vars[*x].name.clone(),
+                    Op::Prim(x) => match &self.prims[*x] {
+                        Prim::Expr { op, args } => format_compact!("{} ({args} args)", expr_opcode(op).unwrap_or("call")),
+                        Prim::Stmt { op, args } => format_compact!("{} ({args} args)", stmt_opcode(op).unwrap_or("call")),
+                    }
+                    Op::Closure(x) => format_compact!("chunk {}", self.closures[*x].chunk),
+                    _ => {
+                        writeln!(f, "{addr:>6}  {op}")?;
+                        continue;
+                    }
+                };
+                writeln!(f, "{addr:>6}  {:<16}; {comment}", format_compact!("{op}"))?;
+            }
+        }
+        Ok(())
+    }
+}
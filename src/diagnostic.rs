@@ -0,0 +1,197 @@
+//! A uniform, machine-readable representation of errors and warnings, for rendering problem lists in editors and other frontends.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Parsing failed.
+    Error,
+    /// Parsing succeeded, but some information from the project was dropped or replaced.
+    Warning,
+    /// The code is valid, but likely contains a mistake (see [`Project::lint`]).
+    Lint,
+}
+
+/// A structured description of an [`Error`], [`Warning`], or [`Lint`].
+///
+/// The [`Diagnostic::code`] is stable across releases and uniquely identifies the kind of problem,
+/// so frontends can use it to look up a localized message instead of displaying [`Diagnostic::message`] (which is always in English).
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// A short identifier for the kind of problem, such as `"P0009"`.
+    /// The letter denotes the category (`X` for xml, `B` for base64, `P` for project, `C` for compile errors, `W` for warnings, and `L` for lints).
+    pub code: &'static str,
+    pub severity: Severity,
+    /// A human-readable description of the problem.
+    pub message: CompactString,
+    /// Where the problem occurred.
+    pub span: Location,
+    /// Other locations which are relevant to the problem.
+    pub related: Vec<Location>,
+    /// The offending node, for problems found in an existing project (i.e., lints and errors from [`Project::validate`]).
+    pub node: Option<NodeId>,
+}
+
+impl ErrorKind {
+    /// Gets the [`Diagnostic::code`] and [`Diagnostic::message`] for this error.
+    pub fn describe(&self) -> (&'static str, CompactString) {
+        match self {
+            ErrorKind::XmlError(e) => match e {
+                XmlError::Read { error } => ("X0001", format_compact!("failed to read xml: {error}")),
+                XmlError::IllegalSequence { sequence } => ("X0002", format_compact!("illegal xml escape sequence: {sequence}")),
+                XmlError::UnexpectedEof => ("X0003", "unexpected end of xml".into()),
+                XmlError::InvalidEncoding { encoding } => ("X0004", format_compact!("xml is not valid {encoding:?} text")),
+            }
+            ErrorKind::Base64Error(e) => ("B0001", format_compact!("invalid base64 content: {e}")),
+            ErrorKind::ProjectError(e) => match e {
+                ProjectError::NoRoot => ("P0001", "the project has no root element".into()),
+                ProjectError::NoStage => ("P0002", "the role has no stage".into()),
+                ProjectError::RoleNoName => ("P0003", "a role has no name".into()),
+                ProjectError::RoleNoContent => ("P0004", "a role has no content".into()),
+                ProjectError::RefMissingId => ("P0005", "a reference is missing its id".into()),
+                ProjectError::ValueNotEvaluated => ("P0006", "a value could not be evaluated".into()),
+                ProjectError::UpvarNotConst => ("P0007", "an upvar name is not constant".into()),
+                ProjectError::UnnamedGlobal => ("P0008", "a global variable has no name".into()),
+                ProjectError::GlobalsWithSameName { name } => ("P0009", format_compact!("multiple global variables are named '{name}'")),
+                ProjectError::UnnamedEntity => ("P0010", "a sprite has no name".into()),
+                ProjectError::EntitiesWithSameName { name } => ("P0011", format_compact!("multiple sprites are named '{name}'")),
+                ProjectError::UnnamedField => ("P0012", "a sprite variable has no name".into()),
+                ProjectError::FieldNoValue { name } => ("P0013", format_compact!("sprite variable '{name}' has no value")),
+                ProjectError::FieldsWithSameName { name } => ("P0014", format_compact!("multiple sprite variables are named '{name}'")),
+                ProjectError::BlockWithoutType => ("P0015", "a block has no type".into()),
+                ProjectError::BlockUnknownType => ("P0016", "a block has an unknown type".into()),
+                ProjectError::BlockChildCount { needed, got } => ("P0017", format_compact!("a block needs {needed} inputs, but got {got}")),
+                ProjectError::BlockMissingOption => ("P0018", "a block is missing a dropdown option".into()),
+                ProjectError::BlockOptionUnknown { got } => ("P0019", format_compact!("unknown dropdown option '{got}'")),
+                ProjectError::ImageWithoutId => ("P0020", "an image has no id".into()),
+                ProjectError::ImagesWithSameId { id } => ("P0021", format_compact!("multiple images have id '{id}'")),
+                ProjectError::ImageWithoutContent { id } => ("P0022", format_compact!("image '{id}' has no content")),
+                ProjectError::ImageUnknownFormat { id, content } => ("P0023", format_compact!("image '{id}' has an unknown format: {content}")),
+                ProjectError::SoundWithoutId => ("P0024", "a sound has no id".into()),
+                ProjectError::SoundsWithSameId { id } => ("P0025", format_compact!("multiple sounds have id '{id}'")),
+                ProjectError::SoundWithoutContent { id } => ("P0026", format_compact!("sound '{id}' has no content")),
+                ProjectError::SoundUnknownFormat { id, content } => ("P0027", format_compact!("sound '{id}' has an unknown format: {content}")),
+                ProjectError::CostumeIdFormat { id } => ("P0028", format_compact!("invalid costume id '{id}'")),
+                ProjectError::CostumeUndefinedRef { id } => ("P0029", format_compact!("reference to undefined costume '{id}'")),
+                ProjectError::CostumesWithSameName { name } => ("P0030", format_compact!("multiple costumes are named '{name}'")),
+                ProjectError::SoundIdFormat { id } => ("P0031", format_compact!("invalid sound id '{id}'")),
+                ProjectError::SoundUndefinedRef { id } => ("P0032", format_compact!("reference to undefined sound '{id}'")),
+                ProjectError::SoundsWithSameName { name } => ("P0033", format_compact!("multiple sounds are named '{name}'")),
+                ProjectError::BoolNoValue => ("P0034", "a boolean has no value".into()),
+                ProjectError::BoolUnknownValue { got } => ("P0035", format_compact!("unknown boolean value '{got}'")),
+                ProjectError::ColorUnknownValue { color } => ("P0036", format_compact!("unknown color '{color}'")),
+                ProjectError::CustomBlockWithoutName => ("P0037", "a custom block has no name".into()),
+                ProjectError::CustomBlockWithoutInputsMeta => ("P0038", "a custom block has no input information".into()),
+                ProjectError::CustomBlockInputsMetaCorrupted => ("P0039", "a custom block has corrupted input information".into()),
+                ProjectError::CustomBlockWithoutType => ("P0040", "a custom block has no type".into()),
+                ProjectError::CustomBlockUnknownType { ty } => ("P0041", format_compact!("a custom block has unknown type '{ty}'")),
+                ProjectError::MessageTypeMissingName => ("P0042", "a message type has no name".into()),
+                ProjectError::MessageTypeMissingFields { msg_type } => ("P0043", format_compact!("message type '{msg_type}' has no fields")),
+                ProjectError::MessageTypeFieldEmpty { msg_type } => ("P0044", format_compact!("message type '{msg_type}' has an empty field name")),
+                ProjectError::MessageTypeMultiplyDefined { msg_type } => ("P0045", format_compact!("message type '{msg_type}' is defined multiple times")),
+            }
+            ErrorKind::CompileError(e) => match e {
+                CompileError::AutofillGenerateError { input } => ("C0001", format_compact!("failed to generate a name for empty input {input}")),
+                CompileError::NameTransformError { name } => ("C0002", format_compact!("failed to transform name '{name}'")),
+                CompileError::UnknownBlockType => ("C0003", "unknown block type".into()),
+                CompileError::DerefAssignment => ("C0004", "cannot assign to a dereferenced value".into()),
+                CompileError::UndefinedVariable { name } => ("C0005", format_compact!("undefined variable '{name}'")),
+                CompileError::UndefinedFn { name } => ("C0006", format_compact!("undefined block '{name}'")),
+                CompileError::BlockOptionNotConst => ("C0007", "a dropdown option is not constant".into()),
+                CompileError::BlockOptionNotSelected => ("C0008", "no dropdown option is selected".into()),
+                CompileError::UnknownEntity { unknown } => ("C0009", format_compact!("unknown sprite '{unknown}'")),
+                CompileError::UnknownEffect { effect } => ("C0010", format_compact!("unknown effect '{effect}'")),
+                CompileError::UnknownPenAttr { attr } => ("C0011", format_compact!("unknown pen attribute '{attr}'")),
+                CompileError::UnknownMessageType { msg_type } => ("C0012", format_compact!("unknown message type '{msg_type}'")),
+                CompileError::MessageTypeWrongNumberArgs { msg_type, got, expected } => ("C0013", format_compact!("message type '{msg_type}' expects {expected} fields, but got {got}")),
+                CompileError::UnknownService { service } => ("C0014", format_compact!("unknown service '{service}'")),
+                CompileError::UnknownRPC { service, rpc } => ("C0015", format_compact!("unknown rpc '{rpc}' in service '{service}'")),
+                CompileError::GlobalsWithSameTransName { trans_name, names } => ("C0016", format_compact!("global variables '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::EntitiesWithSameTransName { trans_name, names } => ("C0017", format_compact!("sprites '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::FieldsWithSameTransName { trans_name, names } => ("C0018", format_compact!("sprite variables '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::LocalsWithSameTransName { trans_name, names } => ("C0019", format_compact!("local variables '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::CostumesWithSameTransName { trans_name, names } => ("C0020", format_compact!("costumes '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::SoundsWithSameTransName { trans_name, names } => ("C0021", format_compact!("sounds '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::BlocksWithSameTransName { trans_name, names } => ("C0022", format_compact!("blocks '{}' and '{}' both translate to '{trans_name}'", names.0, names.1)),
+                CompileError::InputsWithSameName { name } => ("C0023", format_compact!("multiple inputs are named '{name}'")),
+                CompileError::BlocksWithSameName { name, .. } => ("C0024", format_compact!("multiple blocks are named '{name}'")),
+                CompileError::CurrentlyUnsupported { msg } => ("C0025", format_compact!("currently unsupported: {msg}")),
+                CompileError::MetaprogrammingNotAllowed => ("C0026", "metaprogramming is not allowed".into()),
+                CompileError::EmptySlot => ("C0027", "an input slot is empty".into()),
+                CompileError::UnknownGlobalFlag { flag } => ("C0028", format_compact!("unknown global setting '{flag}'")),
+                CompileError::ListTooLarge { max_items, max_bytes } => ("C0029", format_compact!("a list literal has more than {max_items} items or {max_bytes} bytes of text")),
+                CompileError::FnWrongNumberArgs { name, got, expected } => ("C0030", format_compact!("block '{name}' takes {expected} inputs, but got {got}")),
+                CompileError::FnWrongNumberUpvars { name, got, expected } => ("C0031", format_compact!("block '{name}' has {expected} upvars, but got {got}")),
+                CompileError::InvalidUpvar { name } => ("C0032", format_compact!("'{name}' is not a valid upvar")),
+            }
+        }
+    }
+}
+impl WarningKind {
+    /// Gets the [`Diagnostic::code`] and [`Diagnostic::message`] for this warning.
+    pub fn describe(&self) -> (&'static str, CompactString) {
+        match self {
+            WarningKind::NonHatScriptOmitted => ("W0001", "a script without a hat block was omitted".into()),
+            WarningKind::FloatingReporterOmitted => ("W0002", "a floating reporter was omitted".into()),
+            WarningKind::UnknownMediaIgnored { kind } => ("W0003", format_compact!("unknown media kind '{kind}' was ignored")),
+            WarningKind::MediaWithoutRefIgnored { kind } => ("W0004", format_compact!("{kind} without a media reference was ignored")),
+            WarningKind::InvalidAttribute { name, value } => ("W0005", format_compact!("invalid value '{value}' for attribute '{name}' was replaced by its default")),
+            WarningKind::UnsupportedVersion { version } => ("W0006", format_compact!("unsupported serialization version '{version}'")),
+            WarningKind::InvalidImageIgnored { name } => ("W0007", format_compact!("invalid image '{name}' was ignored")),
+            WarningKind::BlockParsedAsUnknown { error } => ("W0008", format_compact!("block was parsed as an unknown block: {}", error.describe().1)),
+            WarningKind::UnknownOptionPassedThrough { option } => ("W0009", format_compact!("unknown dropdown option '{option}' was kept as-is")),
+            WarningKind::LocalShadowsVariable { name, shadowed, .. } => ("W0010", format_compact!("local variable '{name}' hides a {} variable with the same name", match shadowed {
+                VarLocation::Global => "global",
+                VarLocation::Field => "sprite",
+                VarLocation::Local => "local",
+            })),
+            WarningKind::ListTruncated { kept } => ("W0011", format_compact!("a list literal was too large and was truncated to {kept} items")),
+        }
+    }
+}
+
+impl LintKind {
+    /// Gets the [`Diagnostic::code`] and [`Diagnostic::message`] for this lint.
+    pub fn describe(&self) -> (&'static str, CompactString) {
+        match self {
+            LintKind::WarpedInfLoop => ("L0001", "forever loop inside warp never waits, so the program will freeze".into()),
+            LintKind::AssignComparison { var } => ("L0002", format_compact!("'{var}' is set to the result of comparing it, which is true or false")),
+            LintKind::FloatingComparison => ("L0003", "comparison with '=' does not set a variable".into()),
+            LintKind::MagicNumber { value, count } => ("L0004", format_compact!("the number {value} is used {count} times, consider using a variable")),
+            LintKind::ScriptWithoutHat => ("L0005", "script without a hat block never runs automatically".into()),
+            LintKind::UninitializedRead { var } => ("L0006", format_compact!("script variable '{var}' is used before it is set")),
+        }
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Self {
+        let (code, message) = error.kind.describe();
+        Diagnostic { code, severity: Severity::Error, message, span: error.location, related: vec![], node: None }
+    }
+}
+impl From<Box<Error>> for Diagnostic {
+    fn from(error: Box<Error>) -> Self {
+        (*error).into()
+    }
+}
+impl From<Warning> for Diagnostic {
+    fn from(warning: Warning) -> Self {
+        let (code, message) = warning.kind.describe();
+        let related = match warning.kind {
+            WarningKind::LocalShadowsVariable { shadowed_location, .. } => vec![shadowed_location],
+            _ => vec![],
+        };
+        Diagnostic { code, severity: Severity::Warning, message, span: warning.location, related, node: None }
+    }
+}
+impl From<Lint> for Diagnostic {
+    fn from(lint: Lint) -> Self {
+        let (code, message) = lint.kind.describe();
+        Diagnostic { code, severity: Severity::Lint, message, span: lint.location, related: vec![], node: Some(lint.node) }
+    }
+}
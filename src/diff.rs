@@ -0,0 +1,165 @@
+//! Structural differences between two versions of a project.
+
+use alloc::vec::Vec;
+use core::mem::discriminant;
+
+use crate::*;
+use crate::visit::*;
+
+/// A single change to an item of type `T`, where `D` describes the changes within a modified item.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<'a, T, D = ()> {
+    /// The item only exists in the new version.
+    Added(&'a T),
+    /// The item only exists in the old version.
+    Removed(&'a T),
+    /// The item exists in both versions, but is not identical.
+    Modified { old: &'a T, new: &'a T, diff: D },
+}
+
+/// The changes between two versions of a [`Project`], as generated by [`diff`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProjectDiff<'a> {
+    /// Changes to roles, which are matched by name.
+    pub roles: Vec<Change<'a, Role, RoleDiff<'a>>>,
+}
+impl ProjectDiff<'_> {
+    /// Checks if there were no changes.
+    pub fn is_empty(&self) -> bool {
+        self.roles.is_empty()
+    }
+}
+/// The changes between two versions of a [`Role`].
+/// Other properties (e.g., the notes) may have changed, and can be compared directly.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RoleDiff<'a> {
+    /// Changes to global variables, which are matched by name.
+    pub globals: Vec<Change<'a, VariableDefInit>>,
+    /// Changes to global custom blocks, which are matched by name.
+    pub funcs: Vec<Change<'a, Function, StmtsDiff<'a>>>,
+    /// Changes to entities, which are matched by name.
+    pub entities: Vec<Change<'a, Entity, EntityDiff<'a>>>,
+}
+/// The changes between two versions of an [`Entity`].
+/// Other properties (e.g., position or costumes) may have changed, and can be compared directly.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EntityDiff<'a> {
+    /// Changes to fields, which are matched by name.
+    pub fields: Vec<Change<'a, VariableDefInit>>,
+    /// Changes to custom blocks, which are matched by name.
+    pub funcs: Vec<Change<'a, Function, StmtsDiff<'a>>>,
+    /// Changes to scripts, which are matched by position and hat block.
+    pub scripts: Vec<Change<'a, Script, StmtsDiff<'a>>>,
+}
+/// The changes between two versions of a sequence of statements, in order.
+/// Statements that are unchanged are omitted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StmtsDiff<'a> {
+    pub stmts: Vec<Change<'a, Stmt, StmtDiff<'a>>>,
+}
+/// The changes between two statements of the same kind.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StmtDiff<'a> {
+    /// The changes to each nested body (e.g., the branches of an if/else), in order.
+    /// This is empty if the number of bodies differs.
+    pub bodies: Vec<StmtsDiff<'a>>,
+}
+
+/// Computes the structural changes needed to turn `old` into `new`.
+///
+/// Named items (roles, entities, variables, and custom blocks) are matched by name.
+/// Scripts and statements are aligned by a longest common subsequence, after which unmatched items are paired up if they have the same kind, and are otherwise reported as additions and removals.
+pub fn diff<'a>(old: &'a Project, new: &'a Project) -> ProjectDiff<'a> {
+    ProjectDiff { roles: diff_named(&old.roles, &new.roles, |x| &x.name, diff_role) }
+}
+
+fn diff_role<'a>(old: &'a Role, new: &'a Role) -> RoleDiff<'a> {
+    RoleDiff {
+        globals: diff_named(&old.globals, &new.globals, |x| &x.def.name, |_, _| ()),
+        funcs: diff_named(&old.funcs, &new.funcs, |x| &x.name, |a, b| diff_stmts(&a.stmts, &b.stmts)),
+        entities: diff_named(&old.entities, &new.entities, |x| &x.name, diff_entity),
+    }
+}
+fn diff_entity<'a>(old: &'a Entity, new: &'a Entity) -> EntityDiff<'a> {
+    EntityDiff {
+        fields: diff_named(&old.fields, &new.fields, |x| &x.def.name, |_, _| ()),
+        funcs: diff_named(&old.funcs, &new.funcs, |x| &x.name, |a, b| diff_stmts(&a.stmts, &b.stmts)),
+        scripts: diff_seq(&old.scripts, &new.scripts, |a, b| a.hat == b.hat, |a, b| diff_stmts(&a.stmts, &b.stmts)),
+    }
+}
+fn diff_stmts<'a>(old: &'a [Stmt], new: &'a [Stmt]) -> StmtsDiff<'a> {
+    StmtsDiff { stmts: diff_seq(old, new, |a, b| discriminant(&a.kind) == discriminant(&b.kind), diff_stmt) }
+}
+fn diff_stmt<'a>(old: &'a Stmt, new: &'a Stmt) -> StmtDiff<'a> {
+    let bodies = |stmt: &'a Stmt| {
+        let mut res = vec![];
+        stmt_children(&stmt.kind, &mut |_| (), &mut |x| res.push(x));
+        res
+    };
+    let (old_bodies, new_bodies) = (bodies(old), bodies(new));
+    if old_bodies.len() != new_bodies.len() {
+        return StmtDiff { bodies: vec![] };
+    }
+    StmtDiff { bodies: old_bodies.into_iter().zip(new_bodies).map(|(a, b)| diff_stmts(a, b)).collect() }
+}
+
+fn diff_named<'a, T: PartialEq, D>(old: &'a [T], new: &'a [T], name: fn(&T) -> &CompactString, f: impl Fn(&'a T, &'a T) -> D) -> Vec<Change<'a, T, D>> {
+    let mut res = vec![];
+    for a in old {
+        match new.iter().find(|b| name(b) == name(a)) {
+            Some(b) => if a != b {
+                res.push(Change::Modified { old: a, new: b, diff: f(a, b) });
+            }
+            None => res.push(Change::Removed(a)),
+        }
+    }
+    for b in new {
+        if !old.iter().any(|a| name(a) == name(b)) {
+            res.push(Change::Added(b));
+        }
+    }
+    res
+}
+fn diff_seq<'a, T: PartialEq, D>(old: &'a [T], new: &'a [T], compatible: fn(&T, &T) -> bool, f: impl Fn(&'a T, &'a T) -> D) -> Vec<Change<'a, T, D>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut res = vec![];
+    let mut flush = |removed: &mut Vec<&'a T>, added: &mut Vec<&'a T>| {
+        let mut next = 0;
+        for a in removed.drain(..) {
+            match added[next..].iter().position(|b| compatible(a, b)) {
+                Some(k) => {
+                    res.extend(added[next..next + k].iter().map(|b| Change::Added(*b)));
+                    res.push(Change::Modified { old: a, new: added[next + k], diff: f(a, added[next + k]) });
+                    next += k + 1;
+                }
+                None => res.push(Change::Removed(a)),
+            }
+        }
+        res.extend(added.drain(..).skip(next).map(Change::Added));
+    };
+
+    let (mut i, mut j) = (0, 0);
+    let (mut removed, mut added) = (vec![], vec![]);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            flush(&mut removed, &mut added);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added.push(&new[j]);
+            j += 1;
+        } else {
+            removed.push(&old[i]);
+            i += 1;
+        }
+    }
+    flush(&mut removed, &mut added);
+    res
+}
@@ -0,0 +1,222 @@
+//! The errors and warnings produced by the parser.
+
+#[cfg(feature = "std")] use alloc::boxed::Box;
+
+use base64::DecodeError as Base64Error;
+
+use crate::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub location: Location,
+}
+
+/// An error from [`Parser::parse_file`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FileError {
+    Io(std::io::Error),
+    Parse(Box<Error>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub role: Option<CompactString>,
+    pub entity: Option<CompactString>,
+    pub collab_id: Option<CompactString>,
+    pub block_type: Option<CompactString>,
+}
+
+#[derive(Debug)]
+pub struct LocationRef<'a> {
+    pub role: Option<&'a str>,
+    pub entity: Option<&'a str>,
+    pub collab_id: Option<&'a str>,
+    pub block_type: Option<&'a str>,
+}
+impl LocationRef<'_> {
+    pub fn to_owned(&self) -> Location {
+        Location {
+            role: self.role.map(CompactString::new),
+            entity: self.entity.map(CompactString::new),
+            collab_id: self.collab_id.map(CompactString::new),
+            block_type: self.block_type.map(CompactString::new),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    XmlError(XmlError),
+    Base64Error(Base64Error),
+    ProjectError(ProjectError),
+    CompileError(CompileError),
+}
+impl From<XmlError> for ErrorKind { fn from(e: XmlError) -> Self { Self::XmlError(e) } }
+impl From<Base64Error> for ErrorKind { fn from(e: Base64Error) -> Self { Self::Base64Error(e) } }
+impl From<ProjectError> for ErrorKind { fn from(e: ProjectError) -> Self { Self::ProjectError(e) } }
+impl From<CompileError> for ErrorKind { fn from(e: CompileError) -> Self { Self::CompileError(e) } }
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum XmlError {
+    Read { error: xmlparser::Error },
+    IllegalSequence { sequence: CompactString },
+    UnexpectedEof,
+    /// The raw input was not valid in the given encoding (see [`Parser::parse_bytes`]).
+    InvalidEncoding { encoding: Encoding },
+}
+
+/// A text encoding of raw xml input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8, Utf16Le, Utf16Be,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProjectError {
+    NoRoot,
+    NoStage,
+    RoleNoName,
+    RoleNoContent,
+    RefMissingId,
+    ValueNotEvaluated,
+    UpvarNotConst,
+
+    UnnamedGlobal,
+    GlobalsWithSameName { name: CompactString },
+
+    UnnamedEntity,
+    EntitiesWithSameName { name: CompactString },
+
+    UnnamedField,
+    FieldNoValue { name: CompactString },
+    FieldsWithSameName { name: CompactString },
+
+    BlockWithoutType,
+    BlockUnknownType,
+    BlockChildCount { needed: usize, got: usize },
+    BlockMissingOption,
+    BlockOptionUnknown { got: CompactString },
+
+    ImageWithoutId,
+    ImagesWithSameId { id: CompactString },
+    ImageWithoutContent { id: CompactString },
+    ImageUnknownFormat { id: CompactString, content: CompactString },
+
+    SoundWithoutId,
+    SoundsWithSameId { id: CompactString },
+    SoundWithoutContent { id: CompactString },
+    SoundUnknownFormat { id: CompactString, content: CompactString },
+
+    CostumeIdFormat { id: CompactString },
+    CostumeUndefinedRef { id: CompactString },
+    CostumesWithSameName { name: CompactString },
+
+    SoundIdFormat { id: CompactString },
+    SoundUndefinedRef { id: CompactString },
+    SoundsWithSameName { name: CompactString },
+
+    BoolNoValue,
+    BoolUnknownValue { got: CompactString },
+
+    ColorUnknownValue { color: CompactString },
+
+    CustomBlockWithoutName,
+    CustomBlockWithoutInputsMeta,
+    CustomBlockInputsMetaCorrupted,
+    CustomBlockWithoutType,
+    CustomBlockUnknownType { ty: CompactString },
+
+    MessageTypeMissingName,
+    MessageTypeMissingFields { msg_type: CompactString },
+    MessageTypeFieldEmpty { msg_type: CompactString },
+    MessageTypeMultiplyDefined { msg_type: CompactString },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompileError {
+    AutofillGenerateError { input: usize },
+    NameTransformError { name: CompactString},
+    UnknownBlockType,
+    DerefAssignment,
+    UndefinedVariable { name: CompactString },
+    UndefinedFn { name: CompactString },
+    BlockOptionNotConst,
+    BlockOptionNotSelected,
+    UnknownEntity { unknown: CompactString },
+    UnknownEffect { effect: CompactString },
+    UnknownPenAttr { attr: CompactString },
+    UnknownGlobalFlag { flag: CompactString },
+
+    UnknownMessageType { msg_type: CompactString },
+    MessageTypeWrongNumberArgs { msg_type: CompactString, got: usize, expected: usize },
+
+    UnknownService { service: CompactString },
+    UnknownRPC { service: CompactString, rpc: CompactString },
+
+    GlobalsWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    EntitiesWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    FieldsWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    LocalsWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    CostumesWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    SoundsWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+    BlocksWithSameTransName { trans_name: CompactString, names: (CompactString, CompactString) },
+
+    InputsWithSameName { name: CompactString },
+    BlocksWithSameName { name: CompactString, sigs: (CompactString, CompactString) },
+
+    CurrentlyUnsupported { msg: CompactString },
+    MetaprogrammingNotAllowed,
+    /// An input slot was left empty and [`Parser::empty_slots`] is [`EmptySlots::Error`].
+    EmptySlot,
+    /// A list literal exceeded [`Parser::list_limits`] and truncation was not enabled.
+    ListTooLarge { max_items: usize, max_bytes: usize },
+    /// A custom block was called with the wrong number of arguments (only found by [`Project::validate`]).
+    FnWrongNumberArgs { name: CompactString, got: usize, expected: usize },
+    /// A custom block was called with the wrong number of upvars (only found by [`Project::validate`]).
+    FnWrongNumberUpvars { name: CompactString, got: usize, expected: usize },
+    /// An upvar was not a local variable, or a custom block upvar was not one of its parameters (only found by [`Project::validate`]).
+    InvalidUpvar { name: CompactString },
+}
+
+/// A non-fatal problem encountered while parsing, where some information from the project was dropped or replaced by a default.
+/// These are produced by [`Parser::parse_with_diagnostics`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub location: Location,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// A script without a hat block was skipped due to [`Parser::omit_nonhat_scripts`].
+    NonHatScriptOmitted,
+    /// A free floating reporter or variable block was skipped due to [`Parser::keep_floating_reporters`].
+    FloatingReporterOmitted,
+    /// A media entry of an unsupported kind was ignored.
+    UnknownMediaIgnored { kind: CompactString },
+    /// A costume or sound entry without a media reference was ignored.
+    MediaWithoutRefIgnored { kind: CompactString },
+    /// An attribute had an invalid value and was replaced by its default.
+    InvalidAttribute { name: CompactString, value: CompactString },
+    /// The project was saved with a serialization version newer than [`ProjectMeta::MAX_SUPPORTED_VERSION`], so some content may not be parsed correctly.
+    UnsupportedVersion { version: CompactString },
+    /// An embedded image (e.g., the thumbnail) was not a valid base64 data url and was ignored.
+    InvalidImageIgnored { name: CompactString },
+    /// A block with a missing or unknown option was tolerated due to [`Parser::strictness`] and was parsed as an unknown block instead.
+    BlockParsedAsUnknown { error: ErrorKind },
+    /// An unrecognized dropdown option was kept as-is due to [`Parser::pass_through_unknown_options`].
+    UnknownOptionPassedThrough { option: CompactString },
+    /// A script local (declared by `doDeclareVariables` or bound to an upvar) has the same name as a sprite field or global, which it hides within its scope.
+    /// `shadowed` is the location kind of the hidden variable, and `shadowed_location` is where it is defined (its role and, for fields, its sprite).
+    LocalShadowsVariable { name: CompactString, shadowed: VarLocation, shadowed_location: Location },
+    /// A list literal exceeded [`Parser::list_limits`] and only its first `kept` items were parsed.
+    ListTruncated { kept: usize },
+}
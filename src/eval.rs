@@ -0,0 +1,241 @@
+//! Evaluation of constant expressions, for constant folding and partial evaluation.
+//!
+//! Only pure expressions over literal [`Value`]s can be evaluated; anything which reads state (variables, sprite attributes, etc.),
+//! has side effects, or is nondeterministic (e.g., [`ExprKind::Random`]) results in [`EvalError::NotConstant`].
+//! The semantics follow Snap!, such as text being implicitly converted to numbers, case-insensitive text comparison, and 1-based indexing.
+//! Hyperized operations (e.g., adding two lists) are not supported.
+//!
+//! This module requires the `std` feature, which provides the floating-point math functions.
+
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+use crate::analysis::Effects;
+
+/// The reason that an expression could not be evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The expression depends on something other than literal values.
+    NotConstant,
+    ExpectedNumber { got: Value },
+    ExpectedBool { got: Value },
+    ExpectedList { got: Value },
+}
+
+/// Converts a value to a number, as done by Snap! for arithmetic.
+/// Empty text is treated as zero.
+fn to_number(value: &Value) -> Result<f64, EvalError> {
+    match value {
+        Value::Number(x) => Ok(*x),
+        Value::Constant(Constant::E) => Ok(core::f64::consts::E),
+        Value::Constant(Constant::Pi) => Ok(core::f64::consts::PI),
+        _ => match value.as_str().map(str::trim) {
+            Some("") => Ok(0.0),
+            Some("Infinity" | "+Infinity") => Ok(f64::INFINITY),
+            Some("-Infinity") => Ok(f64::NEG_INFINITY),
+            Some(t) if t.bytes().all(|c| c.is_ascii_digit() || b".eE+-".contains(&c)) => t.parse().map_err(|_| EvalError::ExpectedNumber { got: value.clone() }),
+            _ => Err(EvalError::ExpectedNumber { got: value.clone() }),
+        }
+    }
+}
+/// Gets the numeric value of a value for comparisons, which (unlike [`to_number`]) treats empty text as text.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        _ if value.as_str().is_some_and(|x| x.trim().is_empty()) => None,
+        _ => to_number(value).ok(),
+    }
+}
+fn to_bool(value: &Value) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(x) => Ok(*x),
+        _ => Err(EvalError::ExpectedBool { got: value.clone() }),
+    }
+}
+fn to_list(value: Value) -> Result<Vec<Value>, EvalError> {
+    match value {
+        Value::List(values, _) => Ok(values),
+        Value::RawList(list, _) => Ok(list.to_values()),
+        _ => Err(EvalError::ExpectedList { got: value }),
+    }
+}
+/// Converts a 1-based index into a 0-based index, if it is a positive integer.
+fn to_index(value: &Value) -> Result<Option<usize>, EvalError> {
+    let index = to_number(value)?;
+    Ok(if index >= 1.0 && index.fract() == 0.0 { Some(index as usize - 1) } else { None })
+}
+
+/// Checks if two values are equal, as done by Snap!'s `=` block.
+/// Numbers (and numeric text) are compared numerically, and other text is compared case-insensitively.
+pub fn snap_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::RawList(a, _), _) => snap_eq(&Value::List(a.to_values(), None), b),
+        (_, Value::RawList(b, _)) => snap_eq(a, &Value::List(b.to_values(), None)),
+        (Value::List(a, _), Value::List(b, _)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| snap_eq(a, b)),
+        (Value::List(..), _) | (_, Value::List(..)) => false,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.to_snap_string().to_lowercase() == b.to_snap_string().to_lowercase(),
+        }
+    }
+}
+/// Checks if `a` is less than `b`, as done by Snap!'s `<` block.
+/// Numbers (and numeric text) are compared numerically, and other text is compared case-insensitively.
+fn snap_less(a: &Value, b: &Value) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(a), Some(b)) => a < b,
+        _ => a.to_snap_string().to_lowercase() < b.to_snap_string().to_lowercase(),
+    }
+}
+
+fn unary(value: &Expr, f: fn(f64) -> f64) -> Result<Value, EvalError> {
+    Ok(f(to_number(&eval(value)?)?).into())
+}
+fn binary(a: &Expr, b: &Expr, f: fn(f64, f64) -> f64) -> Result<Value, EvalError> {
+    let a = to_number(&eval(a)?)?;
+    let b = to_number(&eval(b)?)?;
+    Ok(f(a, b).into())
+}
+fn variadic(values: &Expr, init: f64, f: fn(f64, f64) -> f64) -> Result<Value, EvalError> {
+    let mut res = init;
+    for value in to_list(eval(values)?)? {
+        res = f(res, to_number(&value)?);
+    }
+    Ok(res.into())
+}
+fn compare(a: &Expr, b: &Expr, f: fn(&Value, &Value) -> bool) -> Result<Value, EvalError> {
+    Ok(f(&eval(a)?, &eval(b)?).into())
+}
+
+/// Evaluates a constant expression.
+pub fn eval(expr: &Expr) -> Result<Value, EvalError> {
+    Ok(match &expr.kind {
+        ExprKind::Value(Value::Ref(_)) => return Err(EvalError::NotConstant),
+        ExprKind::Value(x) => x.clone(),
+
+        ExprKind::Add { values } => variadic(values, 0.0, |a, b| a + b)?,
+        ExprKind::Mul { values } => variadic(values, 1.0, |a, b| a * b)?,
+        ExprKind::Min { values } => variadic(values, f64::INFINITY, f64::min)?,
+        ExprKind::Max { values } => variadic(values, f64::NEG_INFINITY, f64::max)?,
+        ExprKind::Sub { left, right } => binary(left, right, |a, b| a - b)?,
+        ExprKind::Div { left, right } => binary(left, right, |a, b| a / b)?,
+        ExprKind::Mod { left, right } => binary(left, right, |a, b| ((a % b) + b) % b)?, // the result has the sign of the divisor
+        ExprKind::Pow { base, power } => binary(base, power, f64::powf)?,
+        ExprKind::Log { value, base } => binary(value, base, |a, b| a.ln() / b.ln())?,
+        ExprKind::Atan2 { y, x } => binary(y, x, |y, x| y.atan2(x).to_degrees())?,
+
+        ExprKind::Neg { value } => unary(value, |x| -x)?,
+        ExprKind::Abs { value } => unary(value, f64::abs)?,
+        ExprKind::Sign { value } => unary(value, |x| if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { x })?,
+        ExprKind::Sqrt { value } => unary(value, f64::sqrt)?,
+        ExprKind::Floor { value } => unary(value, f64::floor)?,
+        ExprKind::Ceil { value } => unary(value, f64::ceil)?,
+        ExprKind::Round { value } => unary(value, |x| (x + 0.5).floor())?,
+        ExprKind::Sin { value } => unary(value, |x| x.to_radians().sin())?,
+        ExprKind::Cos { value } => unary(value, |x| x.to_radians().cos())?,
+        ExprKind::Tan { value } => unary(value, |x| x.to_radians().tan())?,
+        ExprKind::Asin { value } => unary(value, |x| x.asin().to_degrees())?,
+        ExprKind::Acos { value } => unary(value, |x| x.acos().to_degrees())?,
+        ExprKind::Atan { value } => unary(value, |x| x.atan().to_degrees())?,
+
+        ExprKind::Eq { left, right } => compare(left, right, snap_eq)?,
+        ExprKind::Neq { left, right } => compare(left, right, |a, b| !snap_eq(a, b))?,
+        ExprKind::Less { left, right } => compare(left, right, snap_less)?,
+        ExprKind::LessEq { left, right } => compare(left, right, |a, b| !snap_less(b, a))?,
+        ExprKind::Greater { left, right } => compare(left, right, |a, b| snap_less(b, a))?,
+        ExprKind::GreaterEq { left, right } => compare(left, right, |a, b| !snap_less(a, b))?,
+        ExprKind::Identical { left, right } => compare(left, right, |a, b| match (a, b) {
+            (Value::List(..) | Value::RawList(..), Value::List(..) | Value::RawList(..)) => false, // lists are only identical to themselves, which we cannot know here
+            _ => match (a.as_str(), b.as_str()) {
+                (Some(a), Some(b)) => a == b,
+                _ => snap_eq(a, b),
+            }
+        })?,
+
+        ExprKind::Not { value } => (!to_bool(&eval(value)?)?).into(),
+        ExprKind::And { left, right } => (to_bool(&eval(left)?)? && to_bool(&eval(right)?)?).into(),
+        ExprKind::Or { left, right } => (to_bool(&eval(left)?)? || to_bool(&eval(right)?)?).into(),
+        ExprKind::Conditional { condition, then, otherwise } => match to_bool(&eval(condition)?)? {
+            true => eval(then)?,
+            false => eval(otherwise)?,
+        }
+
+        ExprKind::StrCat { values } => {
+            let mut res = alloc::string::String::new();
+            for value in to_list(eval(values)?)? {
+                res.push_str(&value.to_snap_string());
+            }
+            CompactString::from(res).into()
+        }
+        ExprKind::StrLen { value } => (eval(value)?.to_snap_string().chars().count() as f64).into(),
+        ExprKind::StrGet { string, index } => {
+            let string = eval(string)?.to_snap_string();
+            match to_index(&eval(index)?)?.and_then(|i| string.chars().nth(i)) {
+                Some(ch) => format_compact!("{ch}").into(),
+                None => "".into(),
+            }
+        }
+        ExprKind::StrGetLast { string } => match eval(string)?.to_snap_string().chars().last() {
+            Some(ch) => format_compact!("{ch}").into(),
+            None => "".into(),
+        }
+        ExprKind::UnicodeToChar { value } => match char::from_u32(to_number(&eval(value)?)? as u32) {
+            Some(ch) => format_compact!("{ch}").into(),
+            None => "".into(),
+        }
+        ExprKind::CharToUnicode { value } => match eval(value)?.to_snap_string().chars().next() {
+            Some(ch) => (ch as u32 as f64).into(),
+            None => 0.0.into(),
+        }
+
+        ExprKind::MakeList { values } => Value::List(values.iter().map(eval).collect::<Result<_, _>>()?, None),
+        ExprKind::ListLen { value } => (to_list(eval(value)?)?.len() as f64).into(),
+        ExprKind::ListIsEmpty { value } => to_list(eval(value)?)?.is_empty().into(),
+        ExprKind::ListGet { list, index } => {
+            let list = to_list(eval(list)?)?;
+            to_index(&eval(index)?)?.and_then(|i| list.into_iter().nth(i)).unwrap_or_else(|| "".into())
+        }
+        ExprKind::ListGetLast { list } => to_list(eval(list)?)?.pop().unwrap_or_else(|| "".into()),
+        ExprKind::ListFind { list, value } => {
+            let value = eval(value)?;
+            (to_list(eval(list)?)?.iter().position(|x| snap_eq(x, &value)).map(|i| i + 1).unwrap_or(0) as f64).into()
+        }
+        ExprKind::ListContains { list, value } => {
+            let value = eval(value)?;
+            to_list(eval(list)?)?.iter().any(|x| snap_eq(x, &value)).into()
+        }
+        ExprKind::ListCons { item, list } => {
+            let item = eval(item)?;
+            let mut list = to_list(eval(list)?)?;
+            list.insert(0, item);
+            Value::List(list, None)
+        }
+        ExprKind::ListCdr { value } => {
+            let mut list = to_list(eval(value)?)?;
+            if !list.is_empty() { list.remove(0); }
+            Value::List(list, None)
+        }
+
+        _ => return Err(EvalError::NotConstant),
+    })
+}
+
+/// Replaces every constant sub-expression of `expr` (including `expr` itself) with its value.
+/// Expressions which fail to evaluate (e.g., due to a type error) are left as-is.
+/// Expressions which allocate (see [`Effects::allocates`]), such as [`ExprKind::MakeList`], are also left as-is,
+/// since they create a new (independently mutable) value on each evaluation rather than a constant.
+/// The contents of closures are not modified.
+pub fn fold_constants(expr: &mut Expr) {
+    let mut children = Vec::new();
+    expr_children_mut(&mut expr.kind, &mut |x| children.push(x), &mut |_| ());
+    for child in children {
+        fold_constants(child);
+    }
+
+    if !matches!(expr.kind, ExprKind::Value(_)) && !Effects::of_expr(expr).allocates {
+        if let Ok(value) = eval(expr) {
+            expr.kind = ExprKind::Value(value);
+        }
+    }
+}
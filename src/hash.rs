@@ -0,0 +1,117 @@
+//! Structural hashing of code, for caching and change detection.
+
+use core::hash::{Hash, Hasher};
+
+use crate::*;
+
+/// The 64-bit FNV-1a hash function, which (unlike the standard library's default hasher) is deterministic across runs.
+/// Integers are hashed as their little-endian bytes, and `usize`/`isize` (e.g., lengths and enum discriminants) are widened to 64 bits,
+/// so the result is the same on every platform (e.g., 32-bit wasm and 64-bit native targets).
+pub(crate) struct FnvHasher(u64);
+impl FnvHasher {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn write_u16(&mut self, value: u16) {
+        self.write(&value.to_le_bytes());
+    }
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+    fn write_u128(&mut self, value: u128) {
+        self.write(&value.to_le_bytes());
+    }
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+    fn write_isize(&mut self, value: isize) {
+        self.write_u64(value as i64 as u64);
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn hash_f64<H: Hasher>(value: f64, state: &mut H) {
+    // normalize zeros so that values which compare equal also hash equal
+    state.write_u64(if value == 0.0 { 0 } else { value.to_bits() });
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            Value::Bool(x) => x.hash(state),
+            Value::Number(x) => hash_f64(*x, state),
+            Value::Constant(x) => x.hash(state),
+            Value::String(x) => x.hash(state),
+            Value::SharedString(x) => x.hash(state),
+            Value::Image(x) => {
+                let (content, center, name) = &**x;
+                content.hash(state);
+                center.is_some().hash(state);
+                if let Some((x, y)) = center {
+                    hash_f64(*x, state);
+                    hash_f64(*y, state);
+                }
+                name.hash(state);
+            }
+            Value::Audio(x) => x.hash(state),
+            Value::List(values, ref_id) => {
+                values.hash(state);
+                ref_id.hash(state);
+            }
+            Value::RawList(list, ref_id) => {
+                list.hash(state);
+                ref_id.hash(state);
+            }
+            Value::Ref(x) => x.hash(state),
+            Value::Color { r, g, b, a } => (r, g, b, a).hash(state),
+        }
+    }
+}
+
+/// Block info is metadata about where and how a block was written, so it does not contribute to the structural hash.
+impl<M> Hash for BlockInfo<M> {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+pub(crate) fn content_hash(value: &impl Hash) -> u64 {
+    let mut hasher = FnvHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<M: Hash> Script<M> {
+    /// Computes a structural hash of the script, which ignores [`BlockInfo`] (comments, ids, etc.).
+    /// The hash is deterministic and does not depend on the platform, so it can be used to detect unchanged code between versions of a project (e.g., for caching compiled scripts).
+    /// However, it is only stable for the same version of this crate, since changes to the ast (such as adding or reordering variants) change the hash,
+    /// so persistent caches should also record the crate version.
+    /// Note that, as a hash, distinct code may (rarely) produce the same value.
+    pub fn content_hash(&self) -> u64 {
+        content_hash(self)
+    }
+}
+impl<M: Hash> Stmt<M> {
+    /// Equivalent to [`Script::content_hash`] for a single statement (including any nested statements).
+    pub fn content_hash(&self) -> u64 {
+        content_hash(self)
+    }
+}
+impl<M: Hash> Expr<M> {
+    /// Equivalent to [`Script::content_hash`] for a single expression.
+    pub fn content_hash(&self) -> u64 {
+        content_hash(self)
+    }
+}
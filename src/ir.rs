@@ -0,0 +1,275 @@
+//! A lowered core IR, for virtual machines and code generators which would rather not handle every kind of AST node.
+//!
+//! Lowering (see [`lower`]) makes the following simplifications:
+//!
+//! - Expressions are flattened: every block is evaluated into an explicit temporary ([`Temp`]) before it is used, so the inputs of every instruction are simple [`Operand`]s.
+//! - Every kind of loop becomes an [`Instr::Loop`], which runs forever and is exited with [`Instr::Break`].
+//! - Short-circuiting and lazy operators ([`ExprKind::And`], [`ExprKind::Or`], and [`ExprKind::Conditional`]) become branches.
+//!
+//! All other blocks are primitives ([`Instr::Eval`] and [`Instr::Exec`]), which keep their AST node (without their inputs), so backends can implement them one at a time.
+
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+
+use crate::*;
+use crate::analysis::Effects;
+use crate::visit::*;
+
+/// A temporary value, numbered from 0 within a [`Body`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Temp(pub usize);
+
+/// An input of an instruction, which can be read without any effects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Value(Value),
+    /// The current value of a variable.
+    /// Variables are only read directly if none of the other inputs of the instruction could change them; otherwise, they are copied into a temporary first.
+    Var(VariableRef),
+    Temp(Temp),
+}
+
+/// An instruction of the core IR.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// Evaluates a reporter block and stores the result in `dest`.
+    /// `op` is the block, where every direct sub-expression (see [`Expr::children`]) has been replaced by [`ExprKind::EmptySlot`],
+    /// and `args` are the values of those sub-expressions in the same order.
+    Eval { dest: Temp, op: ExprKind, args: Vec<Operand>, info: Box<BlockInfo> },
+    /// Runs a command block, whose inputs are given as in [`Instr::Eval`].
+    Exec { op: StmtKind, args: Vec<Operand>, info: Box<BlockInfo> },
+    /// Copies a value into a temporary.
+    Move { dest: Temp, value: Operand },
+    Assign { var: VariableRef, value: Operand },
+    /// Creates a closure (see [`ExprKind::Closure`]) and stores it in `dest`.
+    /// The body is lowered separately, and has its own temporaries.
+    Closure { dest: Temp, kind: ClosureKind, params: Vec<VariableDef>, captures: Vec<VariableRef>, body: Body },
+    If { condition: Operand, then: Vec<Instr>, otherwise: Vec<Instr> },
+    /// Runs the body forever, unless it is exited by [`Instr::Break`] (or by returning, throwing an error, or stopping).
+    Loop { body: Vec<Instr> },
+    /// Exits the innermost [`Instr::Loop`].
+    Break,
+    /// Runs the body without yielding (see [`StmtKind::Warp`]).
+    Warp { body: Vec<Instr> },
+    /// Runs the body, and if it throws an error, stores the message in `var` and runs the handler (see [`StmtKind::TryCatch`]).
+    TryCatch { body: Vec<Instr>, var: VariableRef, handler: Vec<Instr> },
+    Return { value: Operand },
+}
+
+/// Lowered code, along with the number of temporaries it uses.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Body {
+    pub instrs: Vec<Instr>,
+    /// The number of temporaries used by the code, which are numbered from 0.
+    /// This does not include the temporaries of closures, since each closure body has its own.
+    pub temps: usize,
+}
+
+/// Lowers a sequence of statements (e.g., the body of a [`Script`] or [`Function`]).
+pub fn lower(stmts: &[Stmt]) -> Body {
+    let mut lowerer = Lowerer { temps: 0 };
+    let mut instrs = vec![];
+    lowerer.stmts(stmts, &mut instrs);
+    Body { instrs, temps: lowerer.temps }
+}
+/// Lowers an expression, giving the code which evaluates it and the operand which holds its value afterwards.
+pub fn lower_expr(expr: &Expr) -> (Body, Operand) {
+    let mut lowerer = Lowerer { temps: 0 };
+    let mut instrs = vec![];
+    let value = lowerer.expr(expr, &mut instrs);
+    (Body { instrs, temps: lowerer.temps }, value)
+}
+
+fn slot() -> Expr {
+    Expr { kind: ExprKind::EmptySlot, info: BlockInfo::none() }
+}
+
+struct Lowerer {
+    temps: usize,
+}
+impl Lowerer {
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps - 1)
+    }
+    /// Copies a variable into a temporary, so that its value is not affected by later code.
+    fn stable(&mut self, value: Operand, out: &mut Vec<Instr>) -> Operand {
+        match value {
+            Operand::Var(_) => {
+                let dest = self.temp();
+                out.push(Instr::Move { dest, value });
+                Operand::Temp(dest)
+            }
+            x => x,
+        }
+    }
+    /// Lowers the inputs of a block, in order.
+    fn args(&mut self, exprs: Vec<&Expr>, out: &mut Vec<Instr>) -> Vec<Operand> {
+        let mut writes_after = vec![false; exprs.len()];
+        for i in (1..exprs.len()).rev() {
+            writes_after[i - 1] = writes_after[i] || Effects::of_expr(exprs[i]).writes;
+        }
+        exprs.into_iter().zip(writes_after).map(|(expr, writes_after)| {
+            let value = self.expr(expr, out);
+            if writes_after { self.stable(value, out) } else { value }
+        }).collect()
+    }
+    /// Evaluates a block which was introduced by lowering, whose inputs are already [`ExprKind::EmptySlot`].
+    fn eval(&mut self, op: ExprKind, args: Vec<Operand>, out: &mut Vec<Instr>) -> Operand {
+        let dest = self.temp();
+        out.push(Instr::Eval { dest, op, args, info: BlockInfo::none() });
+        Operand::Temp(dest)
+    }
+    fn block(&mut self, stmts: &[Stmt]) -> Vec<Instr> {
+        let mut res = vec![];
+        self.stmts(stmts, &mut res);
+        res
+    }
+    fn stmts(&mut self, stmts: &[Stmt], out: &mut Vec<Instr>) {
+        for stmt in stmts {
+            self.stmt(stmt, out);
+        }
+    }
+    /// Lowers a loop over the (one-based) indices of a list, which is re-measured on each iteration.
+    fn foreach(&mut self, var: &VariableRef, items: Operand, stmts: &[Stmt], out: &mut Vec<Instr>) {
+        let index = self.temp();
+        out.push(Instr::Move { dest: index, value: Operand::Value(0f64.into()) });
+        let mut body = vec![];
+        self.increment(index, &mut body);
+        let len = self.eval(ExprKind::ListLen { value: Box::new(slot()) }, vec![items.clone()], &mut body);
+        let done = self.eval(ExprKind::Greater { left: Box::new(slot()), right: Box::new(slot()) }, vec![Operand::Temp(index), len], &mut body);
+        body.push(Instr::If { condition: done, then: vec![Instr::Break], otherwise: vec![] });
+        let value = self.eval(ExprKind::ListGet { list: Box::new(slot()), index: Box::new(slot()) }, vec![items, Operand::Temp(index)], &mut body);
+        body.push(Instr::Assign { var: var.clone(), value });
+        self.stmts(stmts, &mut body);
+        out.push(Instr::Loop { body });
+    }
+    fn increment(&mut self, temp: Temp, out: &mut Vec<Instr>) {
+        let values = self.eval(ExprKind::MakeList { values: vec![slot(), slot()] }, vec![Operand::Temp(temp), Operand::Value(1f64.into())], out);
+        let value = self.eval(ExprKind::Add { values: Box::new(slot()) }, vec![values], out);
+        out.push(Instr::Move { dest: temp, value });
+    }
+    fn stmt(&mut self, stmt: &Stmt, out: &mut Vec<Instr>) {
+        match &stmt.kind {
+            StmtKind::Assign { var, value } => {
+                let value = self.expr(value, out);
+                out.push(Instr::Assign { var: var.clone(), value });
+            }
+            StmtKind::Return { value } => {
+                let value = self.expr(value, out);
+                out.push(Instr::Return { value });
+            }
+            StmtKind::If { condition, then } => {
+                let condition = self.expr(condition, out);
+                let then = self.block(then);
+                out.push(Instr::If { condition, then, otherwise: vec![] });
+            }
+            StmtKind::IfElse { condition, then, otherwise } => {
+                let condition = self.expr(condition, out);
+                let (then, otherwise) = (self.block(then), self.block(otherwise));
+                out.push(Instr::If { condition, then, otherwise });
+            }
+            StmtKind::Warp { stmts } => {
+                let body = self.block(stmts);
+                out.push(Instr::Warp { body });
+            }
+            StmtKind::TryCatch { code, var, handler } => {
+                let (body, handler) = (self.block(code), self.block(handler));
+                out.push(Instr::TryCatch { body, var: var.clone(), handler });
+            }
+            StmtKind::InfLoop { stmts } => {
+                let body = self.block(stmts);
+                out.push(Instr::Loop { body });
+            }
+            StmtKind::UntilLoop { condition, stmts } => {
+                let mut body = vec![];
+                let condition = self.expr(condition, &mut body);
+                body.push(Instr::If { condition, then: vec![Instr::Break], otherwise: vec![] });
+                self.stmts(stmts, &mut body);
+                out.push(Instr::Loop { body });
+            }
+            StmtKind::Repeat { times, counter, stmts } => {
+                // as in snap, the body runs while the (one-based) iteration number is at most `times`
+                let times = self.expr(times, out);
+                let times = self.stable(times, out);
+                let index = self.temp();
+                out.push(Instr::Move { dest: index, value: Operand::Value(0f64.into()) });
+                let mut body = vec![];
+                self.increment(index, &mut body);
+                let done = self.eval(ExprKind::Greater { left: Box::new(slot()), right: Box::new(slot()) }, vec![Operand::Temp(index), times], &mut body);
+                body.push(Instr::If { condition: done, then: vec![Instr::Break], otherwise: vec![] });
+                if let Some(var) = counter {
+                    body.push(Instr::Assign { var: var.clone(), value: Operand::Temp(index) });
+                }
+                self.stmts(stmts, &mut body);
+                out.push(Instr::Loop { body });
+            }
+            StmtKind::ForLoop { var, start, stop, stmts } => {
+                // the numbers are counted up or down in the same way as the range block
+                let args = self.args(vec![start, stop], out);
+                let items = self.eval(ExprKind::Range { start: Box::new(slot()), stop: Box::new(slot()) }, args, out);
+                self.foreach(var, items, stmts, out);
+            }
+            StmtKind::ForeachLoop { var, items, stmts } => {
+                let items = self.expr(items, out);
+                let items = self.stable(items, out);
+                self.foreach(var, items, stmts, out);
+            }
+            _ => {
+                let mut children = vec![];
+                stmt_children(&stmt.kind, &mut |x| children.push(x), &mut |_| ());
+                let args = self.args(children, out);
+                let mut op = stmt.kind.clone();
+                stmt_children_mut(&mut op, &mut |x| *x = slot(), &mut |_| ());
+                out.push(Instr::Exec { op, args, info: stmt.info.clone() });
+            }
+        }
+    }
+    fn expr(&mut self, expr: &Expr, out: &mut Vec<Instr>) -> Operand {
+        match &expr.kind {
+            ExprKind::Value(value) => Operand::Value(value.clone()),
+            ExprKind::Variable { var } => Operand::Var(var.clone()),
+            ExprKind::And { left, right } | ExprKind::Or { left, right } => {
+                let dest = self.temp();
+                let value = self.expr(left, out);
+                out.push(Instr::Move { dest, value });
+                let mut rest = vec![];
+                let value = self.expr(right, &mut rest);
+                rest.push(Instr::Move { dest, value });
+                let (then, otherwise) = match expr.kind {
+                    ExprKind::And { .. } => (rest, vec![]),
+                    _ => (vec![], rest),
+                };
+                out.push(Instr::If { condition: Operand::Temp(dest), then, otherwise });
+                Operand::Temp(dest)
+            }
+            ExprKind::Conditional { condition, then, otherwise } => {
+                let dest = self.temp();
+                let condition = self.expr(condition, out);
+                let mut branches = [(then, vec![]), (otherwise, vec![])];
+                for (expr, code) in branches.iter_mut() {
+                    let value = self.expr(expr, code);
+                    code.push(Instr::Move { dest, value });
+                }
+                let [(_, then), (_, otherwise)] = branches;
+                out.push(Instr::If { condition, then, otherwise });
+                Operand::Temp(dest)
+            }
+            ExprKind::Closure { kind, params, captures, stmts } => {
+                let dest = self.temp();
+                out.push(Instr::Closure { dest, kind: kind.clone(), params: params.clone(), captures: captures.clone(), body: lower(stmts) });
+                Operand::Temp(dest)
+            }
+            _ => {
+                let mut children = vec![];
+                expr_children(&expr.kind, &mut |x| children.push(x), &mut |_| ());
+                let args = self.args(children, out);
+                let mut op = expr.kind.clone();
+                expr_children_mut(&mut op, &mut |x| *x = slot(), &mut |_| ());
+                let dest = self.temp();
+                out.push(Instr::Eval { dest, op, args, info: expr.info.clone() });
+                Operand::Temp(dest)
+            }
+        }
+    }
+}
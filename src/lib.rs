@@ -2,14 +2,61 @@
 #![no_std]
 
 #[macro_use] extern crate alloc;
+#[cfg(feature = "std")] extern crate std;
 
 pub use compact_str::{self, CompactString, format_compact};
 
 mod ast;
+mod error;
+mod symbols;
+mod parser;
 mod rpcs;
+mod visit;
+mod pattern;
+mod builder;
+mod diff;
+mod merge;
+mod refactor;
+mod opt;
+mod anonymize;
+mod annotate;
+mod hash;
+mod metrics;
+mod diagnostic;
+mod lint;
+mod validate;
+mod query;
+mod similarity;
+mod shared;
+mod normalize;
+mod unparse;
 pub mod util;
+pub mod analysis;
+pub mod ir;
+pub mod prelude;
+#[cfg(feature = "std")] pub mod eval;
+#[cfg(feature = "testing")] pub mod testing;
+#[cfg(feature = "bytecode")] pub mod bytecode;
+#[cfg(feature = "scratch")] pub mod scratch;
+#[cfg(feature = "raw")] pub mod raw;
 
 #[cfg(test)]
 mod test;
 
 pub use ast::*;
+pub use error::*;
+pub use symbols::*;
+pub use parser::*;
+pub use builder::*;
+pub use diff::*;
+pub use merge::*;
+pub use refactor::*;
+pub use anonymize::*;
+pub use opt::*;
+pub use metrics::*;
+pub use diagnostic::*;
+pub use lint::*;
+pub use similarity::*;
+pub use shared::*;
+pub use normalize::*;
+pub use query::*;
@@ -0,0 +1,227 @@
+//! Opt-in lints for common mistakes in student projects.
+//!
+//! These do not affect parsing, and are only computed on request with [`Project::lint`].
+
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::analysis::*;
+use crate::visit::*;
+
+/// The kind of a [`Lint`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintKind {
+    /// A forever loop inside of a warp block never waits, so the program freezes.
+    WarpedInfLoop,
+    /// A variable is set to the result of comparing it with something (e.g., `set x to (x = 5)`),
+    /// which was likely meant to be either a plain assignment or a condition.
+    AssignComparison { var: CompactString },
+    /// A free floating equality comparison, which was likely meant to be a `set` block.
+    /// This is only found if [`Parser::keep_floating_reporters`] was enabled.
+    FloatingComparison,
+    /// The same number appears `count` times in a script or custom block, and should probably be a variable.
+    MagicNumber { value: f64, count: usize },
+    /// A script has no hat block, so it never runs automatically.
+    /// This is only found if [`Parser::omit_nonhat_scripts`] was disabled.
+    ScriptWithoutHat,
+    /// A script variable is read before it is assigned, so it still has its default value of zero.
+    UninitializedRead { var: CompactString },
+}
+
+/// A likely mistake found by [`Project::lint`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub kind: LintKind,
+    /// The role and entity containing the code, along with the collab id and opcode of the offending block.
+    /// For nodes without a collab id of their own (e.g., literals), this is the innermost enclosing block which has one.
+    pub location: Location,
+    /// The offending node (or the first occurrence, for [`LintKind::MagicNumber`]).
+    pub node: NodeId,
+}
+
+/// Selects which lints are reported by [`Project::lint`].
+/// By default, all lints are enabled.
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    pub warped_inf_loops: bool,
+    /// Enables both [`LintKind::AssignComparison`] and [`LintKind::FloatingComparison`].
+    pub assign_comparisons: bool,
+    /// The number of times a number (other than 0, 1, and -1) must be repeated to be reported, or `None` to disable [`LintKind::MagicNumber`].
+    /// Defaults to 3.
+    pub magic_numbers: Option<usize>,
+    pub scripts_without_hats: bool,
+    pub uninitialized_reads: bool,
+}
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self { warped_inf_loops: true, assign_comparisons: true, magic_numbers: Some(3), scripts_without_hats: true, uninitialized_reads: true }
+    }
+}
+
+/// Checks if some code (outside of closures) may wait or stop, which allows other scripts to run.
+fn may_yield(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Sleep { .. } | StmtKind::WaitUntil { .. } | StmtKind::Ask { .. } | StmtKind::Pause | StmtKind::SendLocalMessage { wait: true, .. }
+        | StmtKind::Stop { .. } | StmtKind::Return { .. } | StmtKind::Throw { .. } => true,
+        kind => {
+            let mut res = false;
+            stmt_children(kind, &mut |_| (), &mut |x| res |= may_yield(x));
+            res
+        }
+    })
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(x) => Some(*x),
+        _ => value.as_str().map(str::trim).filter(|x| !x.is_empty()).and_then(|x| x.parse().ok()),
+    }
+}
+
+struct Linter<'a> {
+    options: &'a LintOptions,
+    /// The role and entity being linted, along with the collab id and opcode of the innermost block being linted which has a collab id.
+    location: Location,
+    res: Vec<Lint>,
+    /// The distinct numbers in the current code, along with how many times they occur and their first occurrence (and its location).
+    numbers: Vec<(f64, usize, NodeId, Location)>,
+}
+impl Linter<'_> {
+    fn push(&mut self, kind: LintKind, node: NodeId) {
+        self.res.push(Lint { kind, location: self.location.clone(), node });
+    }
+    /// Makes a block the innermost block (if it has a collab id), returning the previous block to restore with [`Linter::leave`].
+    fn enter(&mut self, info: &BlockInfo, opcode: Option<&str>) -> (Option<CompactString>, Option<CompactString>) {
+        match &info.location {
+            Some(collab_id) => (self.location.collab_id.replace(collab_id.clone()), core::mem::replace(&mut self.location.block_type, opcode.map(CompactString::new))),
+            None => (self.location.collab_id.clone(), self.location.block_type.clone()),
+        }
+    }
+    fn leave(&mut self, prev: (Option<CompactString>, Option<CompactString>)) {
+        (self.location.collab_id, self.location.block_type) = prev;
+    }
+    /// Adds a lint for a block, located at the block itself (see [`Linter::enter`]).
+    fn push_at(&mut self, kind: LintKind, info: &BlockInfo, opcode: Option<&str>) {
+        let prev = self.enter(info, opcode);
+        self.push(kind, info.id);
+        self.leave(prev);
+    }
+    /// Lints the body of a script or custom block.
+    fn code(&mut self, stmts: &[Stmt]) {
+        self.stmts(stmts, false);
+
+        if let Some(threshold) = self.options.magic_numbers {
+            for (value, count, node, location) in core::mem::take(&mut self.numbers) {
+                if count >= threshold {
+                    self.res.push(Lint { kind: LintKind::MagicNumber { value, count }, location, node });
+                }
+            }
+        }
+
+        if self.options.uninitialized_reads {
+            self.uninitialized_reads(stmts);
+        }
+    }
+    fn stmts(&mut self, stmts: &[Stmt], in_warp: bool) {
+        for stmt in stmts {
+            let prev = self.enter(&stmt.info, stmt.opcode());
+            match &stmt.kind {
+                StmtKind::InfLoop { stmts } if in_warp && self.options.warped_inf_loops && !may_yield(stmts) => self.push(LintKind::WarpedInfLoop, stmt.info.id),
+                StmtKind::Assign { var, value } if self.options.assign_comparisons => if let ExprKind::Eq { left, right } = &value.kind {
+                    if [left, right].iter().any(|x| matches!(&x.kind, ExprKind::Variable { var: x } if x.trans_name == var.trans_name && x.location == var.location)) {
+                        self.push(LintKind::AssignComparison { var: var.name.clone() }, stmt.info.id);
+                    }
+                }
+                _ => (),
+            }
+
+            let in_warp = in_warp || matches!(stmt.kind, StmtKind::Warp { .. });
+            let mut exprs = vec![];
+            let mut bodies = vec![];
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { self.expr(expr) }
+            for body in bodies { self.stmts(body, in_warp) }
+            self.leave(prev);
+        }
+    }
+    fn expr(&mut self, expr: &Expr) {
+        let prev = self.enter(&expr.info, expr.opcode());
+        match &expr.kind {
+            ExprKind::Value(value) => if let Some(value) = as_number(value).filter(|x| ![0.0, 1.0, -1.0].contains(x)) {
+                match self.numbers.iter_mut().find(|x| x.0 == value) {
+                    Some(entry) => entry.1 += 1,
+                    None => self.numbers.push((value, 1, expr.info.id, self.location.clone())),
+                }
+            }
+            ExprKind::Closure { stmts, .. } => self.stmts(stmts, false), // closures run on their own, even if created inside warp
+            _ => {
+                let mut exprs = vec![];
+                let mut bodies = vec![];
+                expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+                for x in exprs { self.expr(x) }
+                for x in bodies { self.stmts(x, false) }
+            }
+        }
+        self.leave(prev);
+    }
+    /// Finds reads of script variables which may still hold the value from their declaration.
+    /// Variables captured by a closure are skipped, since running the closure may have assigned them.
+    fn uninitialized_reads(&mut self, stmts: &[Stmt]) {
+        let cfg = Cfg::new(stmts);
+        let reaching = ReachingDefs::new(&cfg);
+        let effects: Vec<_> = cfg.nodes.iter().map(|x| NodeEffects::new(&x.kind)).collect();
+        let captured: Vec<&VarKey> = effects.iter().flat_map(|x| x.captures.iter()).collect();
+        for (i, (node, effects)) in cfg.nodes.iter().zip(effects.iter()).enumerate() {
+            let stmt = match node.kind {
+                CfgNodeKind::Stmt(stmt) => stmt,
+                _ => continue,
+            };
+            for var in effects.uses.iter().filter(|x| x.location == VarLocation::Local && !captured.contains(x)) {
+                let declaration = reaching.reaching(i, var).find_map(|def| match cfg.nodes[def.node].kind {
+                    CfgNodeKind::Stmt(Stmt { kind: StmtKind::DeclareLocals { vars }, .. }) if def.node != Cfg::ENTRY => vars.iter().find(|x| x.trans_name == var.trans_name),
+                    _ => None,
+                });
+                if let Some(declaration) = declaration {
+                    let kind = LintKind::UninitializedRead { var: declaration.name.clone() };
+                    if !self.res.iter().any(|x| x.node == stmt.info.id && x.kind == kind) {
+                        self.push_at(kind, &stmt.info, stmt.opcode());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Project {
+    /// Finds likely mistakes in the code of the project, such as are commonly made by students (see [`LintKind`]).
+    pub fn lint(&self, options: &LintOptions) -> Vec<Lint> {
+        let mut linter = Linter { options, location: Location { role: None, entity: None, collab_id: None, block_type: None }, res: vec![], numbers: vec![] };
+        for role in self.roles.iter() {
+            linter.location = Location { role: Some(role.name.clone()), entity: None, collab_id: None, block_type: None };
+            for func in role.funcs.iter() {
+                linter.code(&func.stmts);
+            }
+            for entity in role.entities.iter() {
+                linter.location.entity = Some(entity.name.clone());
+                for func in entity.funcs.iter() {
+                    linter.code(&func.stmts);
+                }
+                for script in entity.scripts.iter() {
+                    if script.hat.is_none() && options.scripts_without_hats {
+                        match script.stmts.first() {
+                            Some(stmt) => linter.push_at(LintKind::ScriptWithoutHat, &stmt.info, stmt.opcode()),
+                            None => linter.push(LintKind::ScriptWithoutHat, NodeId::default()),
+                        }
+                    }
+                    linter.code(&script.stmts);
+                }
+                for expr in entity.floating_reporters.iter() {
+                    if options.assign_comparisons && matches!(expr.kind, ExprKind::Eq { .. }) {
+                        linter.push_at(LintKind::FloatingComparison, &expr.info, expr.opcode());
+                    }
+                }
+            }
+        }
+        linter.res
+    }
+}
@@ -16,6 +16,17 @@ fn main() {
         let parser = Parser::default();
         let res = parser.parse(&xml).expect("failed to translate");
         println!("{:?}", res);
+    } else if cfg!(feature = "scratch") && input.ends_with(".sb3") {
+        #[cfg(feature = "scratch")]
+        {
+            let sb3 = std::fs::read(input).expect("failed to open file");
+            let name = std::path::Path::new(input).file_stem().and_then(|x| x.to_str()).unwrap_or("untitled");
+            let res = Parser::default().parse_sb3(name, &sb3).expect("failed to translate");
+            for block in res.untranslated.iter() {
+                eprintln!("untranslated block {} ({}) in {}", block.opcode, block.block_id, block.entity);
+            }
+            println!("{:?}", res.project);
+        }
     } else {
         eprintln!("unknown input file type");
         std::process::exit(1);
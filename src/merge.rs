@@ -0,0 +1,219 @@
+//! Merging the contents of one project into another.
+
+use alloc::boxed::Box;
+use core::mem;
+
+use crate::*;
+use crate::visit::*;
+
+/// The strategy used by [`Parser::merge`] when an item from the source project has the same name as an item in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Give the incoming item a fresh name (e.g., `foo(2)`) and update all references to it in the incoming code.
+    Rename,
+    /// Keep the existing item and discard the incoming one.
+    /// Incoming code that referred to the discarded item will refer to the existing item instead.
+    Skip,
+    /// Replace the existing item with the incoming one.
+    /// Existing code that referred to the replaced item will refer to the incoming item instead.
+    Overwrite,
+}
+
+fn fresh_name(name: &str, taken: &dyn Fn(&str) -> bool) -> CompactString {
+    (2..).map(|i| format_compact!("{name}({i})")).find(|x| !taken(x)).unwrap()
+}
+
+impl<T: NameTransformer> Parser<T> {
+    /// Merges the roles of `src` into `dest`.
+    ///
+    /// Roles in `src` that do not exist in `dest` are added as-is.
+    /// Otherwise, the global variables, custom blocks, entities, code mappings, and palette customizations of the incoming role are added to the existing role,
+    /// with any name conflicts resolved by `policy`.
+    /// Note that conflicting entities are handled as a whole; their contents are not merged.
+    ///
+    /// Since `policy` can cause new names to be introduced and references to be rebound,
+    /// all affected roles are re-validated with [`Parser::name_transformer`] after merging, and [`Project::resolve_slots`] is repeated.
+    /// On failure, `dest` may be left partially merged.
+    pub fn merge(&self, dest: &mut Project, src: Project, policy: MergePolicy) -> Result<(), Box<Error>> {
+        for role in src.roles {
+            match dest.roles.iter_mut().find(|x| x.name == role.name) {
+                Some(existing) => self.merge_role(existing, role, policy)?,
+                None => {
+                    self.validate_role(&role)?;
+                    dest.roles.push(role);
+                }
+            }
+        }
+        for role in dest.roles.iter_mut() {
+            role.reindex();
+        }
+        dest.resolve_slots();
+        Ok(())
+    }
+    fn transform_name_for(&self, name: &str, location: &LocationRef) -> Result<CompactString, Box<Error>> {
+        match self.name_transformer.transform_name(name) {
+            Ok(x) => Ok(x),
+            Err(()) => Err(Box::new(Error { kind: CompileError::NameTransformError { name: name.into() }.into(), location: location.to_owned() })),
+        }
+    }
+    fn merge_role(&self, dest: &mut Role, mut src: Role, policy: MergePolicy) -> Result<(), Box<Error>> {
+        let location = LocationRef { role: Some(&dest.name), entity: None, collab_id: None, block_type: None };
+
+        if policy == MergePolicy::Rename {
+            for i in 0..src.globals.len() {
+                if !dest.globals.iter().any(|x| x.def.name == src.globals[i].def.name) { continue }
+                let name = fresh_name(&src.globals[i].def.name, &|x| dest.globals.iter().chain(&src.globals).any(|g| g.def.name == x));
+                let trans_name = self.transform_name_for(&name, &location)?;
+                let old = mem::replace(&mut src.globals[i].def, VariableDef { name, trans_name });
+                let new = src.globals[i].def.clone();
+                role_refs_mut(&mut src, &mut |_, x| if let SymbolRefMut::Var(var) = x {
+                    if var.location == VarLocation::Global && var.trans_name == old.trans_name {
+                        var.name = new.name.clone();
+                        var.trans_name = new.trans_name.clone();
+                    }
+                });
+            }
+            for i in 0..src.funcs.len() {
+                if !dest.funcs.iter().any(|x| x.name == src.funcs[i].name) { continue }
+                let name = fresh_name(&src.funcs[i].name, &|x| dest.funcs.iter().chain(&src.funcs).any(|f| f.name == x));
+                let trans_name = self.transform_name_for(&name, &location)?;
+                let old = mem::replace(&mut src.funcs[i].trans_name, trans_name.clone());
+                src.funcs[i].name = name.clone();
+                role_refs_mut(&mut src, &mut |_, x| if let SymbolRefMut::Fn(function) = x {
+                    if function.location == FnLocation::Global && function.trans_name == old {
+                        function.name = name.clone();
+                        function.trans_name = trans_name.clone();
+                    }
+                });
+            }
+            for i in 0..src.entities.len() {
+                if !dest.entities.iter().any(|x| x.name == src.entities[i].name) { continue }
+                let name = fresh_name(&src.entities[i].name, &|x| dest.entities.iter().chain(&src.entities).any(|e| e.name == x));
+                let trans_name = self.transform_name_for(&name, &location)?;
+                let old = mem::replace(&mut src.entities[i].trans_name, trans_name.clone());
+                src.entities[i].name = name.clone();
+                role_refs_mut(&mut src, &mut |_, x| if let SymbolRefMut::Entity { name: n, trans_name: t } = x {
+                    if *t == old {
+                        *n = name.clone();
+                        *t = trans_name.clone();
+                    }
+                });
+            }
+        }
+
+        for global in src.globals {
+            match dest.globals.iter_mut().find(|x| x.def.name == global.def.name) {
+                None => dest.globals.push(global),
+                Some(existing) => if policy == MergePolicy::Overwrite { existing.init = global.init },
+            }
+        }
+        for func in src.funcs {
+            match dest.funcs.iter_mut().find(|x| x.name == func.name) {
+                None => dest.funcs.push(func),
+                Some(existing) => if policy == MergePolicy::Overwrite { *existing = func },
+            }
+        }
+        for entity in src.entities {
+            match dest.entities.iter_mut().find(|x| x.name == entity.name) {
+                None => dest.entities.push(entity),
+                Some(existing) => if policy == MergePolicy::Overwrite { *existing = entity },
+            }
+        }
+        for block in src.palette.hidden_blocks {
+            if !dest.palette.hidden_blocks.contains(&block) { dest.palette.hidden_blocks.push(block) }
+        }
+        for category in src.palette.categories {
+            match dest.palette.categories.iter_mut().find(|x| x.name == category.name) {
+                None => dest.palette.categories.push(category),
+                Some(existing) => if policy == MergePolicy::Overwrite { *existing = category },
+            }
+        }
+        for (dest, src) in [(&mut dest.code_mappings, src.code_mappings), (&mut dest.code_headers, src.code_headers)] {
+            for (key, code) in src {
+                match dest.iter_mut().find(|x| x.0 == key) {
+                    None => dest.push((key, code)),
+                    Some(existing) => if policy == MergePolicy::Overwrite { existing.1 = code },
+                }
+            }
+        }
+
+        dest.source_hash = None;
+        self.validate_role(dest)
+    }
+
+    /// Checks that all the symbols in a role are uniquely defined (under [`Parser::name_transformer`]) and that all references are to defined symbols.
+    fn validate_role(&self, role: &Role) -> Result<(), Box<Error>> {
+        let mut location = LocationRef { role: Some(&role.name), entity: None, collab_id: None, block_type: None };
+
+        let mut globals = SymbolTable::new(self);
+        for global in role.globals.iter() {
+            match globals.define(global.def.name.clone(), 0f64.into()) {
+                Ok(None) => (),
+                Ok(Some(prev)) => return Err(Box::new(Error { kind: ProjectError::GlobalsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                Err(SymbolError::NameTransformError { name }) => return Err(Box::new(Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new(Error { kind: CompileError::GlobalsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+            }
+        }
+        let mut entities = SymbolTable::new(self);
+        for entity in role.entities.iter() {
+            match entities.define(entity.name.clone(), 0f64.into()) {
+                Ok(None) => (),
+                Ok(Some(prev)) => return Err(Box::new(Error { kind: ProjectError::EntitiesWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                Err(SymbolError::NameTransformError { name }) => return Err(Box::new(Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new(Error { kind: CompileError::EntitiesWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+            }
+        }
+        validate_funcs(self, &role.funcs, &location)?;
+        for entity in role.entities.iter() {
+            location.entity = Some(&entity.name);
+            let mut fields = SymbolTable::new(self);
+            for field in entity.fields.iter() {
+                match fields.define(field.def.name.clone(), 0f64.into()) {
+                    Ok(None) => (),
+                    Ok(Some(prev)) => return Err(Box::new(Error { kind: ProjectError::FieldsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::NameTransformError { name }) => return Err(Box::new(Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new(Error { kind: CompileError::FieldsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                }
+            }
+            validate_funcs(self, &entity.funcs, &location)?;
+        }
+
+        let mut res = Ok(());
+        role_refs(role, &mut |entity, x| {
+            if res.is_err() { return }
+            let entity = entity.map(|i| &role.entities[i]);
+            let location = LocationRef { role: Some(&role.name), entity: entity.map(|x| x.name.as_str()), collab_id: None, block_type: None };
+            let kind: ErrorKind = match x {
+                SymbolRef::Var(var) => match var.location {
+                    VarLocation::Local => return,
+                    VarLocation::Global if role.globals.iter().any(|x| x.def.trans_name == var.trans_name) => return,
+                    VarLocation::Field if entity.is_some_and(|e| e.fields.iter().any(|x| x.def.trans_name == var.trans_name)) => return,
+                    _ => CompileError::UndefinedVariable { name: var.name.clone() }.into(),
+                }
+                SymbolRef::Fn(function) => match function.location {
+                    FnLocation::Global if role.funcs.iter().any(|x| x.trans_name == function.trans_name) => return,
+                    FnLocation::Method if entity.is_some_and(|e| e.funcs.iter().any(|x| x.trans_name == function.trans_name)) => return,
+                    _ => CompileError::UndefinedFn { name: function.name.clone() }.into(),
+                }
+                SymbolRef::Entity { name, trans_name } => match role.entities.iter().any(|x| x.trans_name == *trans_name) {
+                    true => return,
+                    false => CompileError::UnknownEntity { unknown: name.clone() }.into(),
+                }
+            };
+            res = Err(Box::new(Error { kind, location: location.to_owned() }));
+        });
+        res
+    }
+}
+fn validate_funcs<T: NameTransformer>(parser: &Parser<T>, funcs: &[Function], location: &LocationRef) -> Result<(), Box<Error>> {
+    let mut table = SymbolTable::new(parser);
+    for func in funcs {
+        match table.define(func.name.clone(), 0f64.into()) {
+            Ok(None) => (),
+            Ok(Some(prev)) => return Err(Box::new(Error { kind: CompileError::BlocksWithSameName { name: prev.def.name.clone(), sigs: (prev.def.name, func.name.clone()) }.into(), location: location.to_owned() })),
+            Err(SymbolError::NameTransformError { name }) => return Err(Box::new(Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+            Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new(Error { kind: CompileError::BlocksWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! Size and memory statistics for ASTs, for tuning memory usage of large projects.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::AddAssign;
+
+use crate::*;
+use crate::visit::*;
+
+/// Node counts and estimated memory usage of (part of) a [`Project`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Metrics {
+    pub entities: usize,
+    pub funcs: usize,
+    pub scripts: usize,
+    pub hats: usize,
+    pub stmts: usize,
+    pub exprs: usize,
+    /// An estimate of the heap memory (in bytes) owned by the AST, including the nodes themselves, their [`BlockInfo`],
+    /// heap-allocated strings, and media content.
+    /// Allocator overhead and unused capacity are not included, and shared values (e.g., costumes) are counted once per reference.
+    pub heap_bytes: usize,
+}
+impl AddAssign<&Metrics> for Metrics {
+    fn add_assign(&mut self, other: &Metrics) {
+        self.entities += other.entities;
+        self.funcs += other.funcs;
+        self.scripts += other.scripts;
+        self.hats += other.hats;
+        self.stmts += other.stmts;
+        self.exprs += other.exprs;
+        self.heap_bytes += other.heap_bytes;
+    }
+}
+
+/// The [`Metrics`] of a [`Project`] as a whole and of each of its roles (see [`Project::metrics`]).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProjectMetrics {
+    pub total: Metrics,
+    /// The metrics of each role, in the same order as [`Project::roles`].
+    pub roles: Vec<(CompactString, Metrics)>,
+}
+
+fn str_bytes(value: &CompactString) -> usize {
+    if value.is_heap_allocated() { value.len() } else { 0 }
+}
+fn value_bytes(value: &Value) -> usize {
+    match value {
+        Value::Bool(_) | Value::Number(_) | Value::Constant(_) | Value::Ref(_) | Value::Color { .. } => 0,
+        Value::String(x) => str_bytes(x),
+        Value::SharedString(x) => x.len(),
+        Value::Image(x) => size_of::<(Vec<u8>, Option<(f64, f64)>, CompactString)>() + x.0.len() + str_bytes(&x.2),
+        Value::Audio(x) => size_of::<(Vec<u8>, CompactString)>() + x.0.len() + str_bytes(&x.1),
+        Value::List(values, _) => values.iter().map(|x| size_of::<Value>() + value_bytes(x)).sum(),
+        Value::RawList(list, _) => list.text().len(),
+    }
+}
+fn defs_bytes(defs: &[VariableDefInit]) -> usize {
+    defs.iter().map(|x| size_of::<VariableDefInit>() + str_bytes(&x.def.name) + str_bytes(&x.def.trans_name) + value_bytes(&x.init)).sum()
+}
+fn info_bytes(info: &BlockInfo) -> usize {
+    size_of::<BlockInfo>() + [&info.comment, &info.location, &info.opcode].into_iter().flatten().map(str_bytes).sum::<usize>()
+}
+
+impl Metrics {
+    fn add_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmts += 1;
+            self.heap_bytes += size_of::<Stmt>() + info_bytes(&stmt.info);
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { self.add_expr(x) }
+            for x in bodies { self.add_stmts(x) }
+        }
+    }
+    fn add_expr(&mut self, expr: &Expr) {
+        self.exprs += 1;
+        self.heap_bytes += size_of::<Expr>() + info_bytes(&expr.info);
+        if let ExprKind::Value(value) = &expr.kind {
+            self.heap_bytes += value_bytes(value);
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { self.add_expr(x) }
+        for x in bodies { self.add_stmts(x) }
+    }
+    fn add_funcs(&mut self, funcs: &[Function]) {
+        for func in funcs {
+            self.funcs += 1;
+            self.heap_bytes += size_of::<Function>() + str_bytes(&func.name) + str_bytes(&func.trans_name) + str_bytes(&func.code_mapping) + str_bytes(&func.code_header) + str_bytes(&func.category) + func.help.as_ref().map(str_bytes).unwrap_or(0);
+            self.heap_bytes += func.params.iter().map(|x| size_of::<VariableDef>() + str_bytes(&x.name) + str_bytes(&x.trans_name)).sum::<usize>();
+            self.heap_bytes += func.param_info.iter().map(|x| size_of::<ParamInfo>() + str_bytes(&x.default) + match &x.input_type { InputType::Other(t) => str_bytes(t), _ => 0 }).sum::<usize>() + func.upvars.len() * size_of::<VariableRef>();
+            self.add_stmts(&func.stmts);
+        }
+    }
+    fn of_role(role: &Role) -> Self {
+        let mut res = Metrics::default();
+        res.heap_bytes += size_of::<Role>() + str_bytes(&role.name) + str_bytes(&role.notes) + defs_bytes(&role.globals);
+        res.heap_bytes += role.code_mappings.iter().chain(&role.code_headers).map(|(k, v)| size_of::<(CompactString, CompactString)>() + str_bytes(k) + str_bytes(v)).sum::<usize>();
+        res.heap_bytes += role.palette.hidden_blocks.iter().map(|x| size_of::<CompactString>() + str_bytes(x)).sum::<usize>();
+        res.heap_bytes += role.palette.categories.iter().map(|x| size_of::<Category>() + str_bytes(&x.name)).sum::<usize>();
+        res.heap_bytes += [&role.thumbnail, &role.pen_trails].into_iter().flatten().map(Vec::len).sum::<usize>();
+        res.add_funcs(&role.funcs);
+
+        for entity in role.entities.iter() {
+            res.entities += 1;
+            res.heap_bytes += size_of::<Entity>() + str_bytes(&entity.name) + str_bytes(&entity.trans_name);
+            res.heap_bytes += defs_bytes(&entity.fields) + defs_bytes(&entity.costumes) + defs_bytes(&entity.sounds);
+            res.heap_bytes += entity.canvas_comments.iter().map(|x| size_of::<CanvasComment>() + str_bytes(&x.text)).sum::<usize>();
+            res.add_funcs(&entity.funcs);
+            for script in entity.scripts.iter() {
+                res.scripts += 1;
+                res.heap_bytes += size_of::<Script>();
+                if let Some(hat) = script.hat.as_deref() {
+                    res.hats += 1;
+                    res.heap_bytes += size_of::<Hat>() + info_bytes(&hat.info);
+                    if let HatKind::When { condition } = &hat.kind {
+                        res.add_expr(condition);
+                    }
+                }
+                res.add_stmts(&script.stmts);
+            }
+            for expr in entity.floating_reporters.iter() {
+                res.add_expr(expr);
+            }
+        }
+
+        // symbol references each hold their own copy of the (possibly heap-allocated) names they refer to
+        role_refs(role, &mut |_, x| res.heap_bytes += match x {
+            SymbolRef::Var(var) => str_bytes(&var.name) + str_bytes(&var.trans_name),
+            SymbolRef::Fn(function) => str_bytes(&function.name) + str_bytes(&function.trans_name),
+            SymbolRef::Entity { name, trans_name } => str_bytes(name) + str_bytes(trans_name),
+        });
+
+        res
+    }
+}
+
+impl Project {
+    /// Computes node counts and estimated heap usage for the project, along with a breakdown by role.
+    pub fn metrics(&self) -> ProjectMetrics {
+        let mut res = ProjectMetrics::default();
+        res.total.heap_bytes += str_bytes(&self.name);
+        for role in self.roles.iter() {
+            let metrics = Metrics::of_role(role);
+            res.total += &metrics;
+            res.roles.push((role.name.clone(), metrics));
+        }
+        res
+    }
+}
+
+/// Complexity measures of (part of) a [`Project`], for research and grading tools.
+///
+/// When combining the complexity of several scripts or custom blocks (e.g., for an entire sprite), all measures are summed except for [`Complexity::max_depth`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Complexity {
+    /// The cyclomatic complexity, which is one plus the number of decision points (conditions, loops, `and`/`or`, and try/catch) for each script or custom block.
+    pub cyclomatic: usize,
+    /// The maximum nesting depth of statement bodies (C-slots and closures), where top-level statements have depth zero.
+    pub max_depth: usize,
+    /// The number of blocks, including hat blocks, statements, and expressions (e.g., literals and variables).
+    pub blocks: usize,
+    /// The number of calls to custom blocks.
+    pub custom_block_calls: usize,
+    /// The number of closures (rings).
+    pub closures: usize,
+}
+impl Complexity {
+    fn merge(&mut self, other: &Self) {
+        self.cyclomatic += other.cyclomatic;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.blocks += other.blocks;
+        self.custom_block_calls += other.custom_block_calls;
+        self.closures += other.closures;
+    }
+    fn of_code(hat: Option<&Hat>, stmts: &[Stmt]) -> Self {
+        let mut res = Complexity { cyclomatic: 1, ..Default::default() };
+        if let Some(hat) = hat {
+            res.blocks += 1;
+            if let HatKind::When { condition } = &hat.kind {
+                res.add_expr(condition, 0);
+            }
+        }
+        res.add_stmts(stmts, 0);
+        res
+    }
+    fn add_stmts(&mut self, stmts: &[Stmt], depth: usize) {
+        for stmt in stmts {
+            self.blocks += 1;
+            match &stmt.kind {
+                StmtKind::If { .. } | StmtKind::IfElse { .. } | StmtKind::UntilLoop { .. } | StmtKind::Repeat { .. }
+                | StmtKind::ForLoop { .. } | StmtKind::ForeachLoop { .. } | StmtKind::TryCatch { .. } => self.cyclomatic += 1,
+                StmtKind::CallFn { .. } => self.custom_block_calls += 1,
+                _ => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { self.add_expr(x, depth) }
+            for x in bodies {
+                self.max_depth = self.max_depth.max(depth + 1);
+                self.add_stmts(x, depth + 1);
+            }
+        }
+    }
+    fn add_expr(&mut self, expr: &Expr, depth: usize) {
+        self.blocks += 1;
+        match &expr.kind {
+            ExprKind::Conditional { .. } | ExprKind::And { .. } | ExprKind::Or { .. } => self.cyclomatic += 1,
+            ExprKind::CallFn { .. } => self.custom_block_calls += 1,
+            ExprKind::Closure { .. } => self.closures += 1,
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { self.add_expr(x, depth) }
+        for x in bodies {
+            self.max_depth = self.max_depth.max(depth + 1);
+            self.add_stmts(x, depth + 1);
+        }
+    }
+}
+
+impl Script {
+    /// Computes the complexity of the script.
+    pub fn complexity(&self) -> Complexity {
+        Complexity::of_code(self.hat.as_deref(), &self.stmts)
+    }
+}
+impl Function {
+    /// Computes the complexity of the body of the custom block.
+    pub fn complexity(&self) -> Complexity {
+        Complexity::of_code(None, &self.stmts)
+    }
+}
+impl Entity {
+    /// Computes the combined complexity of the scripts and custom blocks of the entity.
+    pub fn complexity(&self) -> Complexity {
+        let mut res = Complexity::default();
+        for func in self.funcs.iter() { res.merge(&func.complexity()) }
+        for script in self.scripts.iter() { res.merge(&script.complexity()) }
+        res
+    }
+}
+impl Role {
+    /// Computes the combined complexity of the global custom blocks and all the entities of the role.
+    pub fn complexity(&self) -> Complexity {
+        let mut res = Complexity::default();
+        for func in self.funcs.iter() { res.merge(&func.complexity()) }
+        for entity in self.entities.iter() { res.merge(&entity.complexity()) }
+        res
+    }
+}
@@ -0,0 +1,130 @@
+//! Normalization of projects, for comparing them modulo differences which do not affect their behavior (e.g., in tests and autograders).
+
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::analysis::*;
+use crate::hash::content_hash;
+use crate::visit::*;
+
+/// Checks if the operands of a commutative operation can be reordered, meaning none of them has effects which could be observed by the others.
+fn reorderable(operands: &[&Expr]) -> bool {
+    operands.iter().all(|x| {
+        let effects = Effects::of_expr(x);
+        !(effects.random || effects.io || effects.network || effects.writes)
+    })
+}
+
+fn normalize_value(value: &mut Value) {
+    match value {
+        Value::SharedString(x) => *value = Value::String((&**x).into()),
+        Value::RawList(list, ref_id) => *value = Value::List(list.to_values(), ref_id.clone()),
+        Value::List(values, _) => for value in values.iter_mut() {
+            normalize_value(value);
+        }
+        _ => (),
+    }
+}
+fn normalize_info(info: &mut BlockInfo) {
+    info.comment = None;
+    info.location = None;
+    info.opcode = None;
+    info.id = NodeId::default();
+}
+fn normalize_stmts(stmts: &mut [Stmt]) {
+    for stmt in stmts {
+        normalize_info(&mut stmt.info);
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { normalize_expr(x) }
+        for x in bodies { normalize_stmts(x) }
+    }
+}
+fn normalize_expr(expr: &mut Expr) {
+    normalize_info(&mut expr.info);
+
+    let mut exprs = Vec::new();
+    let mut bodies = Vec::new();
+    expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    for x in exprs { normalize_expr(x) }
+    for x in bodies { normalize_stmts(x) }
+
+    match &mut expr.kind {
+        ExprKind::Value(value) => normalize_value(value),
+        ExprKind::Add { values } | ExprKind::Mul { values } | ExprKind::Min { values } | ExprKind::Max { values } => {
+            // the operand list is never observable, so constant operands (e.g., from the binary form of a block) are equivalent to a list literal
+            if let ExprKind::MakeList { values: items } = &mut values.kind {
+                if items.iter().all(|x| matches!(x.kind, ExprKind::Value(_))) {
+                    let items = core::mem::take(items).into_iter().map(|x| match x.kind { ExprKind::Value(x) => x, _ => unreachable!() }).collect();
+                    values.kind = ExprKind::Value(Value::List(items, None));
+                }
+            }
+            match &mut values.kind {
+                ExprKind::MakeList { values } if reorderable(&values.iter().collect::<Vec<_>>()) => values.sort_by_cached_key(|x| x.content_hash()),
+                ExprKind::Value(Value::List(values, None)) => values.sort_by_cached_key(content_hash),
+                _ => (),
+            }
+        }
+        ExprKind::And { left, right } | ExprKind::Or { left, right } | ExprKind::Eq { left, right } | ExprKind::Neq { left, right } | ExprKind::Identical { left, right }
+            if reorderable(&[left, right]) && left.content_hash() > right.content_hash() => core::mem::swap(left, right),
+        _ => (),
+    }
+}
+
+/// Rewrites a project into a canonical form, so that projects which differ only in ways that do not affect their behavior compare equal (see [`semantically_eq`]).
+///
+/// This removes comments (on blocks, on the scripting area, and as custom block help text), project notes, the thumbnail and pen trails,
+/// palette customizations, [`ProjectMeta`], and the collab ids, lowering opcodes, and [`NodeId`]s of [`BlockInfo`]
+/// (node ids never affect equality, but are cleared so that normalized projects are also identical when printed).
+/// The operands of commutative operations (e.g., sums and equality tests) are sorted into a canonical order, unless doing so could change the result.
+/// Values which have several representations (e.g., [`Value::SharedString`] and [`Value::RawList`]) are converted to their plain form.
+pub fn normalize(project: &mut Project) {
+    project.meta = ProjectMeta::default();
+    for role in project.roles.iter_mut() {
+        role.notes = CompactString::default();
+        role.thumbnail = None;
+        role.pen_trails = None;
+        role.source_hash = None;
+        role.palette = Palette::default();
+        for global in role.globals.iter_mut() {
+            normalize_value(&mut global.init);
+        }
+        for func in role.funcs.iter_mut() {
+            func.help = None;
+            normalize_stmts(&mut func.stmts);
+        }
+        for entity in role.entities.iter_mut() {
+            entity.canvas_comments.clear();
+            for field in entity.fields.iter_mut() {
+                normalize_value(&mut field.init);
+            }
+            for func in entity.funcs.iter_mut() {
+                func.help = None;
+                normalize_stmts(&mut func.stmts);
+            }
+            for script in entity.scripts.iter_mut() {
+                if let Some(hat) = script.hat.as_deref_mut() {
+                    normalize_info(&mut hat.info);
+                    if let HatKind::When { condition } = &mut hat.kind {
+                        normalize_expr(condition);
+                    }
+                }
+                normalize_stmts(&mut script.stmts);
+            }
+            for expr in entity.floating_reporters.iter_mut() {
+                normalize_expr(expr);
+            }
+        }
+        role.reindex();
+    }
+}
+
+/// Checks if two projects are equal after [`normalize`] (which is applied to copies of the projects).
+pub fn semantically_eq(a: &Project, b: &Project) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    normalize(&mut a);
+    normalize(&mut b);
+    a == b
+}
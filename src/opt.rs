@@ -0,0 +1,485 @@
+//! Optimization passes that rewrite an existing AST into a simpler but equivalent form.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+use crate::analysis::{Effects, CaptureMode, captures_of};
+
+/// Generates names for temporary local variables which do not conflict with any name used in a script or custom block.
+struct Temps<'a> {
+    name_transformer: &'a dyn NameTransformer,
+    /// The names and translated names which are already in use.
+    used: Vec<CompactString>,
+    next: usize,
+}
+impl<'a> Temps<'a> {
+    fn new(name_transformer: &'a dyn NameTransformer, stmts: &Vec<Stmt>, mut used: Vec<CompactString>) -> Self {
+        stmts_refs(stmts, &mut |x| if let SymbolRef::Var(var) = x {
+            used.push(var.name.clone());
+            used.push(var.trans_name.clone());
+        });
+        collect_defs(stmts, &mut used);
+        Self { name_transformer, used, next: 0 }
+    }
+    /// Makes a fresh temporary, or returns `None` if [`Parser::name_transformer`] does not produce a unique name.
+    fn make(&mut self) -> Option<VariableDef> {
+        // if the name transformer is injective, at most one candidate per used name can conflict
+        for _ in 0..=self.used.len() {
+            self.next += 1;
+            let name = format_compact!("tmp {}", self.next);
+            let trans_name = self.name_transformer.transform_name(&name).ok()?;
+            if !self.used.contains(&name) && !self.used.contains(&trans_name) {
+                self.used.push(name.clone());
+                self.used.push(trans_name.clone());
+                return Some(VariableDef { name, trans_name });
+            }
+        }
+        None
+    }
+}
+/// Gets the names and translated names of all locals and closure parameters declared in some code, including in nested closures.
+fn collect_defs(stmts: &[Stmt], res: &mut Vec<CompactString>) {
+    fn collect_expr(expr: &Expr, res: &mut Vec<CompactString>) {
+        if let ExprKind::Closure { params, .. } = &expr.kind {
+            for param in params {
+                res.push(param.name.clone());
+                res.push(param.trans_name.clone());
+            }
+        }
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { collect_expr(x, res) }
+        for x in bodies { collect_defs(x, res) }
+    }
+    for stmt in stmts {
+        if let StmtKind::DeclareLocals { vars } = &stmt.kind {
+            for var in vars {
+                res.push(var.name.clone());
+                res.push(var.trans_name.clone());
+            }
+        }
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { collect_expr(x, res) }
+        for x in bodies { collect_defs(x, res) }
+    }
+}
+
+/// Gets the names of all variables which are captured and assigned by closures in some code, including in nested closures.
+/// Such a variable can change whenever a closure runs, which is not visible from the code that created it.
+fn collect_mutated_captures(stmts: &[Stmt], res: &mut Vec<CompactString>) {
+    fn collect_expr(expr: &Expr, res: &mut Vec<CompactString>) {
+        if let Some(captures) = captures_of(expr) {
+            res.extend(captures.into_iter().filter(|x| x.mode == CaptureMode::Mutated).map(|x| x.var.name.clone()));
+        }
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { collect_expr(x, res) }
+        for x in bodies { collect_mutated_captures(x, res) }
+    }
+    for stmt in stmts {
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { collect_expr(x, res) }
+        for x in bodies { collect_mutated_captures(x, res) }
+    }
+}
+/// Gets the names of all variables which may be written by some code, excluding the contents of closures (which are not run by creating them).
+/// Returns `false` if the code may also modify state which cannot be tracked by name, such as the contents of a list or anything done by a custom block.
+fn collect_writes(stmts: &[Stmt], res: &mut Vec<CompactString>) -> bool {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::ForeachLoop { var, .. }
+            | StmtKind::Repeat { counter: Some(var), .. } | StmtKind::TryCatch { var, .. } | StmtKind::DefineBlock { var, .. } => res.push(var.name.clone()),
+            StmtKind::DeclareLocals { vars } => res.extend(vars.iter().map(|x| x.name.clone())),
+
+            StmtKind::ListInsert { .. } | StmtKind::ListInsertLast { .. } | StmtKind::ListInsertRandom { .. }
+            | StmtKind::ListAssign { .. } | StmtKind::ListAssignLast { .. } | StmtKind::ListAssignRandom { .. }
+            | StmtKind::ListRemove { .. } | StmtKind::ListRemoveLast { .. } | StmtKind::ListRemoveAll { .. }
+            | StmtKind::CallFn { .. } | StmtKind::CallClosure { .. } | StmtKind::ForkClosure { .. } | StmtKind::UnknownBlock { .. }
+            | StmtKind::SetBlockAttribute { .. } => return false,
+
+            _ => (),
+        }
+
+        let mut writes = false;
+        let mut bodies = vec![];
+        stmt_children(&stmt.kind, &mut |x| writes |= Effects::of_expr(x).writes, &mut |x| bodies.push(x));
+        if writes || !bodies.into_iter().all(|x| collect_writes(x, res)) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Checks if an expression should be hoisted out of a loop.
+/// Only compound expressions are considered, since hoisting a single variable or literal gains nothing.
+///
+/// The expression may read local variables which are not in `written` (the variables which may change while the loop runs),
+/// or no variables at all if `written` is `None`.
+/// Globals and fields are never considered invariant, since other scripts can modify them while the loop is running.
+fn is_hoistable(expr: &Expr, written: Option<&[CompactString]>) -> bool {
+    let mut compound = false;
+    expr_children(&expr.kind, &mut |_| compound = true, &mut |_| ());
+    compound && Effects::of_expr_with(expr, &mut |var| match written {
+        Some(written) if var.location == VarLocation::Local && !written.contains(&var.name) => Effects::NONE,
+        _ => Effects { reads: true, ..Effects::NONE },
+    }).is_pure()
+}
+
+struct Hoister<'a> {
+    temps: Temps<'a>,
+    /// The variables which are assigned by closures (see [`collect_mutated_captures`]).
+    captured: Vec<CompactString>,
+}
+impl Hoister<'_> {
+    /// Hoists invariants out of the loops in some code, adding the declared temporaries to `declared`.
+    fn stmts(&mut self, stmts: &mut Vec<Stmt>, declared: &mut Vec<VariableDef>) {
+        let mut i = 0;
+        while i < stmts.len() {
+            let mut hoisted = vec![];
+            let (counter, body) = match &mut stmts[i].kind {
+                StmtKind::Repeat { counter, stmts: body, .. } => (counter.as_ref(), Some(body)),
+                StmtKind::ForLoop { var, stmts: body, .. } => (Some(&*var), Some(body)),
+                StmtKind::InfLoop { stmts: body } => (None, Some(body)),
+                _ => (None, None),
+            };
+            if let Some(body) = body {
+                // the loop itself also writes its counter on every iteration
+                let mut written = self.captured.clone();
+                written.extend(counter.map(|x| x.name.clone()));
+                let written = collect_writes(body, &mut written).then_some(written);
+                self.extract_stmts(body, written.as_deref(), &mut hoisted);
+            }
+
+            let mut exprs = vec![];
+            let mut bodies = vec![];
+            stmt_children_mut(&mut stmts[i].kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { self.closures(expr) }
+            for body in bodies { self.stmts(body, declared) }
+
+            if !hoisted.is_empty() {
+                let vars: Vec<_> = hoisted.iter().map(|x| x.0.clone()).collect();
+                declared.extend_from_slice(&vars);
+                let mut init: Vec<Stmt> = vec![StmtKind::DeclareLocals { vars }.into()];
+                for (def, value) in hoisted {
+                    init.push(StmtKind::Assign { var: local_ref(&def), value: Box::new(value) }.into());
+                }
+                let count = init.len();
+                stmts.splice(i..i, init);
+                i += count;
+            }
+            i += 1;
+        }
+    }
+    /// Hoists invariants out of the loops in all the closures in an expression.
+    /// Temporaries used by a closure are declared in the closure itself.
+    fn closures(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Closure { stmts, .. } => self.stmts(stmts, &mut vec![]),
+            kind => expr_children_mut(kind, &mut |x| self.closures(x), &mut |_| ()),
+        }
+    }
+    /// Replaces the invariants in a loop body with temporaries, excluding the contents of closures.
+    fn extract_stmts(&mut self, stmts: &mut [Stmt], written: Option<&[CompactString]>, hoisted: &mut Vec<(VariableDef, Expr)>) {
+        for stmt in stmts {
+            let mut exprs = vec![];
+            let mut bodies = vec![];
+            stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { self.extract_expr(expr, written, hoisted) }
+            for body in bodies { self.extract_stmts(body, written, hoisted) }
+        }
+    }
+    fn extract_expr(&mut self, expr: &mut Expr, written: Option<&[CompactString]>, hoisted: &mut Vec<(VariableDef, Expr)>) {
+        if is_hoistable(expr, written) {
+            // identical invariants share the same temporary
+            let def = match hoisted.iter().find(|x| x.1 == *expr) {
+                Some(x) => Some(x.0.clone()),
+                None => self.temps.make(),
+            };
+            if let Some(def) = def {
+                let value = core::mem::replace(expr, local_ref(&def).into());
+                if !hoisted.iter().any(|x| x.0 == def) {
+                    hoisted.push((def, value));
+                }
+                return;
+            }
+        }
+        expr_children_mut(&mut expr.kind, &mut |x| self.extract_expr(x, written, hoisted), &mut |_| ());
+    }
+}
+fn local_ref(def: &VariableDef) -> VariableRef {
+    VariableRef { name: def.name.clone(), trans_name: def.trans_name.clone(), location: VarLocation::Local, slot: None }
+}
+
+impl Project {
+    /// Hoists loop-invariant sub-expressions out of the bodies of [`StmtKind::Repeat`], [`StmtKind::ForLoop`], and [`StmtKind::InfLoop`],
+    /// so that they are evaluated once rather than on every iteration.
+    ///
+    /// An expression is invariant if it is pure apart from reading local variables which the loop never writes.
+    /// A loop is assumed to write any variable which it assigns (including its counter), any variable assigned by a closure,
+    /// and, if it contains a list mutation, custom block call, or closure call, any variable at all.
+    /// Lists held by local variables are assumed not to be modified by other scripts while the loop is running.
+    ///
+    /// Each hoisted expression is assigned to a fresh temporary local (named `tmp N`, with translated names generated by [`Parser::name_transformer`])
+    /// which is declared immediately before the loop.
+    /// Hoisted expressions are evaluated even if the loop runs zero times, which is harmless since pure expressions have no side effects,
+    /// but means that an error due to an invalid input (e.g., indexing into a number) is reported before the loop rather than in it.
+    /// The new statements have default [`BlockInfo`], so [`Project::assign_node_ids`] should be used if unique ids are needed.
+    pub fn hoist_loop_invariants<T: NameTransformer>(&mut self, parser: &Parser<T>) {
+        hoist_loop_invariants(self, &parser.name_transformer);
+    }
+}
+fn hoist_loop_invariants(project: &mut Project, name_transformer: &dyn NameTransformer) {
+    fn function(name_transformer: &dyn NameTransformer, func: &mut Function) {
+        let params = func.params.iter().flat_map(|x| [x.name.clone(), x.trans_name.clone()]).collect();
+        let temps = Temps::new(name_transformer, &func.stmts, params);
+        let mut captured = vec![];
+        collect_mutated_captures(&func.stmts, &mut captured);
+        Hoister { temps, captured }.stmts(&mut func.stmts, &mut vec![]);
+    }
+
+    for role in project.roles.iter_mut() {
+        for func in role.funcs.iter_mut() {
+            function(name_transformer, func);
+        }
+        for entity in role.entities.iter_mut() {
+            for func in entity.funcs.iter_mut() {
+                function(name_transformer, func);
+            }
+            for script in entity.scripts.iter_mut() {
+                let mut used: Vec<_> = script.locals.iter().flat_map(|x| [x.name.clone(), x.trans_name.clone()]).collect();
+                if let Some(Hat { kind: HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. }, .. }) = script.hat.as_deref() {
+                    used.extend(fields.iter().flat_map(|x| [x.name.clone(), x.trans_name.clone()]));
+                }
+                let temps = Temps::new(name_transformer, &script.stmts, used);
+                let mut captured = vec![];
+                collect_mutated_captures(&script.stmts, &mut captured);
+                Hoister { temps, captured }.stmts(&mut script.stmts, &mut script.locals);
+            }
+        }
+    }
+}
+
+/// Checks if an expression always evaluates to a boolean.
+fn is_bool(expr: &Expr) -> bool {
+    matches!(expr.kind, ExprKind::Value(Value::Bool(_)) | ExprKind::Not { .. } | ExprKind::And { .. } | ExprKind::Or { .. }
+        | ExprKind::Eq { .. } | ExprKind::Neq { .. } | ExprKind::Identical { .. } | ExprKind::ListContains { .. } | ExprKind::ListIsEmpty { .. }
+        | ExprKind::IsTouchingEntity { .. } | ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingDrawings
+        | ExprKind::KeyDown { .. } | ExprKind::PenDown | ExprKind::IsVisible | ExprKind::TypeQuery { .. })
+}
+fn is_const(expr: &Expr, value: bool) -> bool {
+    expr.kind == ExprKind::Value(Value::Bool(value))
+}
+fn not(value: Box<Expr>) -> Box<Expr> {
+    let mut res = Expr::from(ExprKind::Not { value });
+    simplify_node(&mut res);
+    Box::new(res)
+}
+
+fn simplify_stmts(stmts: &mut Vec<Stmt>) {
+    let mut res = Vec::with_capacity(stmts.len());
+    for mut stmt in stmts.drain(..) {
+        let mut exprs = vec![];
+        let mut bodies = vec![];
+        stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for expr in exprs { simplify_expr(expr) }
+        for body in bodies { simplify_stmts(body) }
+
+        match stmt.kind {
+            StmtKind::If { condition, then } if is_const(&condition, true) || is_const(&condition, false) => {
+                if is_const(&condition, true) { res.extend(then) }
+            }
+            StmtKind::IfElse { condition, then, otherwise } if is_const(&condition, true) || is_const(&condition, false) => {
+                res.extend(if is_const(&condition, true) { then } else { otherwise });
+            }
+            StmtKind::UntilLoop { condition, .. } if is_const(&condition, true) => (),
+            StmtKind::UntilLoop { condition, stmts } if is_const(&condition, false) => res.push(Stmt { kind: StmtKind::InfLoop { stmts }, info: stmt.info }),
+            kind => res.push(Stmt { kind, info: stmt.info }),
+        }
+    }
+    *stmts = res;
+}
+fn simplify_expr(expr: &mut Expr) {
+    let mut exprs = vec![];
+    let mut bodies = vec![];
+    expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+    for x in exprs { simplify_expr(x) }
+    for x in bodies { simplify_stmts(x) }
+
+    simplify_node(expr);
+}
+/// Simplifies the (in)equality of a boolean expression and a constant, or gives back the operands if this is not possible.
+fn bool_comparison(left: Box<Expr>, right: Box<Expr>, equal: bool) -> Result<Expr, (Box<Expr>, Box<Expr>)> {
+    let constant = |x: &Expr| match x.kind { ExprKind::Value(Value::Bool(x)) => Some(x), _ => None };
+    let (value, constant) = match (constant(&left), constant(&right)) {
+        (_, Some(x)) if is_bool(&left) => (left, x),
+        (Some(x), _) if is_bool(&right) => (right, x),
+        _ => return Err((left, right)),
+    };
+    Ok(if constant == equal { *value } else { *not(value) })
+}
+/// Simplifies the root of an expression whose children have already been simplified.
+fn simplify_node(expr: &mut Expr) {
+    let Expr { kind, info } = core::mem::replace(expr, ExprKind::EmptySlot.into());
+    *expr = match kind {
+        ExprKind::Not { value } => {
+            let value = *value;
+            match value.kind {
+                ExprKind::Value(Value::Bool(x)) => Expr { kind: ExprKind::Value(Value::Bool(!x)), info },
+                ExprKind::Not { value } if is_bool(&value) => *value,
+                ExprKind::And { left, right } => Expr { kind: ExprKind::Or { left: not(left), right: not(right) }, info },
+                ExprKind::Or { left, right } => Expr { kind: ExprKind::And { left: not(left), right: not(right) }, info },
+                ExprKind::Eq { left, right } => Expr { kind: ExprKind::Neq { left, right }, info },
+                ExprKind::Neq { left, right } => Expr { kind: ExprKind::Eq { left, right }, info },
+                kind => Expr { kind: ExprKind::Not { value: Box::new(Expr { kind, info: value.info }) }, info },
+            }
+        }
+        ExprKind::And { left, right } => {
+            if is_const(&left, false) || (is_const(&right, false) && left.is_pure()) { Expr { kind: ExprKind::Value(Value::Bool(false)), info } }
+            else if is_const(&left, true) && is_bool(&right) { *right }
+            else if is_const(&right, true) && is_bool(&left) { *left }
+            else { Expr { kind: ExprKind::And { left, right }, info } }
+        }
+        ExprKind::Or { left, right } => {
+            if is_const(&left, true) || (is_const(&right, true) && left.is_pure()) { Expr { kind: ExprKind::Value(Value::Bool(true)), info } }
+            else if is_const(&left, false) && is_bool(&right) { *right }
+            else if is_const(&right, false) && is_bool(&left) { *left }
+            else { Expr { kind: ExprKind::Or { left, right }, info } }
+        }
+        ExprKind::Eq { left, right } => bool_comparison(left, right, true).unwrap_or_else(|(left, right)| Expr { kind: ExprKind::Eq { left, right }, info }),
+        ExprKind::Neq { left, right } => bool_comparison(left, right, false).unwrap_or_else(|(left, right)| Expr { kind: ExprKind::Neq { left, right }, info }),
+        ExprKind::Conditional { condition, then, .. } if is_const(&condition, true) => *then,
+        ExprKind::Conditional { condition, otherwise, .. } if is_const(&condition, false) => *otherwise,
+        kind => Expr { kind, info },
+    };
+}
+
+impl Project {
+    /// Simplifies all boolean expressions and conditions in the project.
+    ///
+    /// This folds constant conditions (including removing [`StmtKind::If`] and [`StmtKind::UntilLoop`] blocks whose outcome is known),
+    /// pushes [`ExprKind::Not`] inward with De Morgan's laws, removes double negations, and replaces comparisons with `true` or `false` by the compared value.
+    /// Rewrites which would change the result for non-boolean inputs (e.g., `not (not x)` where `x` may be text) are only done for expressions that always produce booleans,
+    /// and operands with side effects are never removed.
+    pub fn simplify_conditions(&mut self) {
+        for role in self.roles.iter_mut() {
+            for func in role.funcs.iter_mut() {
+                simplify_stmts(&mut func.stmts);
+            }
+            for entity in role.entities.iter_mut() {
+                for func in entity.funcs.iter_mut() {
+                    simplify_stmts(&mut func.stmts);
+                }
+                for script in entity.scripts.iter_mut() {
+                    if let Some(Hat { kind: HatKind::When { condition }, .. }) = script.hat.as_deref_mut() {
+                        simplify_expr(condition);
+                    }
+                    simplify_stmts(&mut script.stmts);
+                }
+                for expr in entity.floating_reporters.iter_mut() {
+                    simplify_expr(expr);
+                }
+            }
+        }
+    }
+}
+
+fn shrink_stmts(stmts: &mut Vec<Stmt>) {
+    stmts.shrink_to_fit();
+    for stmt in stmts.iter_mut() {
+        stmt_children_mut(&mut stmt.kind, &mut shrink_expr, &mut shrink_stmts);
+    }
+}
+fn shrink_expr(expr: &mut Expr) {
+    if let ExprKind::MakeList { values } = &mut expr.kind {
+        values.shrink_to_fit();
+    }
+    expr_children_mut(&mut expr.kind, &mut shrink_expr, &mut shrink_stmts);
+}
+
+impl Project {
+    /// Releases the spare capacity of every statement list (scripts, block bodies, and rings) and [`ExprKind::MakeList`] (including the arguments of variadic operators) in the project.
+    ///
+    /// The parser already allocates most lists at their exact size, but lists which were built incrementally (e.g., by the other passes of this module, or with a [`ScriptBuilder`]) may have spare capacity.
+    /// This is useful before storing many projects in memory, and can be done automatically by the parser with the `"shrink-to-fit"` pass (see [`builtin_pass`]).
+    pub fn shrink_to_fit(&mut self) {
+        for role in self.roles.iter_mut() {
+            for func in role.funcs.iter_mut() {
+                shrink_stmts(&mut func.stmts);
+            }
+            for entity in role.entities.iter_mut() {
+                for func in entity.funcs.iter_mut() {
+                    shrink_stmts(&mut func.stmts);
+                }
+                for script in entity.scripts.iter_mut() {
+                    if let Some(Hat { kind: HatKind::When { condition }, .. }) = script.hat.as_deref_mut() {
+                        shrink_expr(condition);
+                    }
+                    shrink_stmts(&mut script.stmts);
+                }
+                for expr in entity.floating_reporters.iter_mut() {
+                    shrink_expr(expr);
+                }
+            }
+        }
+    }
+}
+
+/// A transformation which a [`Parser`] applies to every project it produces (see [`Parser::passes`]).
+pub trait AstPass {
+    /// The name of the pass, which is used to select built-in passes with [`builtin_pass`].
+    fn name(&self) -> &str;
+    /// Transforms a project.
+    /// Any new symbol names (e.g., for temporaries) should be translated with `name_transformer`, which is the [`Parser::name_transformer`] of the parser.
+    fn run(&self, project: &mut Project, name_transformer: &dyn NameTransformer);
+}
+
+/// The pass for [`Project::hoist_loop_invariants`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HoistLoopInvariants;
+impl AstPass for HoistLoopInvariants {
+    fn name(&self) -> &str { "hoist-loop-invariants" }
+    fn run(&self, project: &mut Project, name_transformer: &dyn NameTransformer) {
+        hoist_loop_invariants(project, name_transformer);
+    }
+}
+
+/// The pass for [`Project::simplify_conditions`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimplifyConditions;
+impl AstPass for SimplifyConditions {
+    fn name(&self) -> &str { "simplify-conditions" }
+    fn run(&self, project: &mut Project, _: &dyn NameTransformer) {
+        project.simplify_conditions();
+    }
+}
+
+/// The pass for [`Project::shrink_to_fit`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShrinkToFit;
+impl AstPass for ShrinkToFit {
+    fn name(&self) -> &str { "shrink-to-fit" }
+    fn run(&self, project: &mut Project, _: &dyn NameTransformer) {
+        project.shrink_to_fit();
+    }
+}
+
+/// The names of all the built-in passes, in the order they are recommended to run.
+pub const BUILTIN_PASSES: &[&str] = &["simplify-conditions", "hoist-loop-invariants", "shrink-to-fit"];
+
+/// Gets a built-in pass by name (see [`BUILTIN_PASSES`]).
+pub fn builtin_pass(name: &str) -> Option<Box<dyn AstPass + Send + Sync>> {
+    Some(match name {
+        "hoist-loop-invariants" => Box::new(HoistLoopInvariants),
+        "simplify-conditions" => Box::new(SimplifyConditions),
+        "shrink-to-fit" => Box::new(ShrinkToFit),
+        _ => return None,
+    })
+}
@@ -0,0 +1,3450 @@
+//! The parser, which converts project files (xml) into an AST.
+
+use alloc::rc::Rc;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::borrow::{Cow, ToOwned};
+use core::{mem, iter, ptr};
+use core::ops::Range;
+use core::cell::RefCell;
+use core::hash::{Hash, Hasher};
+
+use base64::engine::Engine as Base64Engine;
+use base64::DecodeError as Base64Error;
+
+use crate::*;
+use crate::rpcs::*;
+use crate::util::*;
+use crate::hash::FnvHasher;
+
+fn base64_decode(content: &str) -> Result<Vec<u8>, Base64Error> {
+    base64::engine::general_purpose::STANDARD.decode(content)
+}
+
+// regex equivalent: r"%'([^']*)'"
+struct ParamIter<'a>(iter::Fuse<core::str::CharIndices<'a>>);
+impl<'a> ParamIter<'a> {
+    fn new(src: &'a str) -> Self {
+        Self(src.char_indices().fuse())
+    }
+}
+impl Iterator for ParamIter<'_> {
+    type Item = (usize, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((i, ch)) = self.0.next() {
+            if ch != '%' || self.0.next().map(|x| x.1) != Some('\'') { continue }
+            while let Some((j, ch)) = self.0.next() {
+                if ch == '\'' { return Some((i, j + 1)) }
+            }
+        }
+        None
+    }
+}
+#[test]
+fn test_param_iter() {
+    assert_eq!(ParamIter::new("hello world").collect::<Vec<_>>(), vec![]);
+    assert_eq!(ParamIter::new("hello %'helo' world").collect::<Vec<_>>(), vec![(6, 13)]);
+    assert_eq!(ParamIter::new("hello %'helo'world").collect::<Vec<_>>(), vec![(6, 13)]);
+    assert_eq!(ParamIter::new("hello %'heloworld").collect::<Vec<_>>(), vec![]);
+    assert_eq!(ParamIter::new("hello %'helo' %'world''''").collect::<Vec<_>>(), vec![(6, 13), (14, 22)]);
+}
+
+// regex equivalent: r"%\S*"
+struct ArgIter<'a>(iter::Fuse<core::str::CharIndices<'a>>, usize);
+impl<'a> ArgIter<'a> {
+    fn new(src: &'a str) -> Self {
+        Self(src.char_indices().fuse(), src.len())
+    }
+}
+impl Iterator for ArgIter<'_> {
+    type Item = (usize, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((i, ch)) = self.0.next() {
+            if ch != '%' { continue }
+            while let Some((j, ch)) = self.0.next() {
+                if ch.is_whitespace() { return Some((i, j)) }
+            }
+            return Some((i, self.1));
+        }
+        None
+    }
+}
+#[test]
+fn test_arg_iter() {
+    assert_eq!(ArgIter::new("hello world").collect::<Vec<_>>(), vec![]);
+    assert_eq!(ArgIter::new("hello %world").collect::<Vec<_>>(), vec![(6, 12)]);
+    assert_eq!(ArgIter::new("hello %world ").collect::<Vec<_>>(), vec![(6, 12)]);
+    assert_eq!(ArgIter::new("hello %world      %gjherg3495830_ ").collect::<Vec<_>>(), vec![(6, 12), (18, 33)]);
+}
+
+#[derive(Clone)]
+pub(crate) struct InlineListIter<'a>(iter::Peekable<iter::Fuse<core::str::Chars<'a>>>);
+impl<'a> InlineListIter<'a> {
+    pub(crate) fn new(s: &'a str) -> Self {
+        Self(s.chars().fuse().peekable())
+    }
+}
+impl<'a> Iterator for InlineListIter<'a> {
+    type Item = CompactString;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut res = CompactString::default();
+        let mut in_quote = false;
+        while let Some(ch) = self.0.next() {
+            if ch == '"' {
+                if !in_quote {
+                    in_quote = true;
+                    continue;
+                }
+
+                if let Some('"') = self.0.peek() {
+                    res.push(self.0.next().unwrap());
+                } else {
+                    in_quote = false;
+                }
+            }
+            else if ch == ',' && !in_quote {
+                return Some(res.into());
+            } else {
+                res.push(ch);
+            }
+        }
+        if !res.is_empty() { Some(res.into()) } else { None }
+    }
+}
+#[test]
+fn test_inline_list_iter() {
+    assert_eq!(InlineListIter::new(r#""#).collect::<Vec<_>>(), &[] as &[&str]);
+    assert_eq!(InlineListIter::new(r#"1"#).collect::<Vec<_>>(), &["1"]);
+    assert_eq!(InlineListIter::new(r#"1,2"#).collect::<Vec<_>>(), &["1", "2"]);
+    assert_eq!(InlineListIter::new(r#"1,2,test,53"#).collect::<Vec<_>>(), &["1", "2", "test", "53"]);
+    assert_eq!(InlineListIter::new(r#""""test","test""","""test""","""","""""","test""test""#).collect::<Vec<_>>(), &["\"test", "test\"", "\"test\"", "\"", "\"\"", "test\"test"]);
+    assert_eq!(InlineListIter::new(r#",,",",",,",""",",",""",""",""",","","",""#).collect::<Vec<_>>(), &["", "", ",", ",,", "\",", ",\"", "\",\"", ",\",\","]);
+}
+
+/// Replaces the heap-allocated strings in a list with shared strings, so that identical strings use a single allocation (see [`Parser::share_list_strings`]).
+/// Strings which are short enough to be stored inline are left as is, since sharing them would not save any memory.
+#[inline(never)]
+fn share_strings(values: &mut [Value]) {
+    let mut seen: BTreeSet<Rc<str>> = BTreeSet::new();
+    for value in values.iter_mut() {
+        if let Value::String(x) = value {
+            if x.is_heap_allocated() {
+                let shared = match seen.get(x.as_str()) {
+                    Some(shared) => shared.clone(),
+                    None => {
+                        let shared: Rc<str> = x.as_str().into();
+                        seen.insert(shared.clone());
+                        shared
+                    }
+                };
+                *value = Value::SharedString(shared);
+            }
+        }
+    }
+}
+
+/// Parses the first (up to) `max_items` items of an atomic list literal.
+#[inline(never)]
+fn parse_atomic_list(text: &str, max_items: usize) -> Vec<Expr> {
+    InlineListIter::new(text).take(max_items).map(Into::into).collect()
+}
+
+/// Parses the first (up to) `max_items` rows of a linewise list literal, where each line is an atomic list of the items in that row.
+#[inline(never)]
+fn parse_linewise_list(text: &str, max_items: usize) -> Vec<Expr> {
+    text.lines().take(max_items).map(|line| Value::List(InlineListIter::new(line).map(Value::String).collect(), None).into()).collect()
+}
+
+#[inline(never)]
+fn clean_newlines(s: &str) -> Cow<'_, str> {
+    if !s.contains('\r') { return Cow::Borrowed(s) }
+    let mut res = alloc::string::String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('\r') => {
+                res.push('\n');
+                if chars.peek().copied() == Some('\n') { chars.next(); }
+            }
+            Some('\n') => res.push('\n'),
+            Some(x) => res.push(x),
+            None => break,
+        }
+    }
+    Cow::Owned(res)
+}
+#[test]
+fn test_clean_newlines() {
+    assert_eq!(clean_newlines("hello world"), "hello world");
+    assert_eq!(clean_newlines("hello\nworld"), "hello\nworld");
+    assert_eq!(clean_newlines("hello\rworld"), "hello\nworld");
+    assert_eq!(clean_newlines("hello\r\nworld"), "hello\nworld");
+    assert_eq!(clean_newlines("hello\r\n\nworld"), "hello\n\nworld");
+    assert_eq!(clean_newlines("hello\r\n\n\rworld"), "hello\n\n\nworld");
+    assert_eq!(clean_newlines("hello\r\n\n\rworld\n"), "hello\n\n\nworld\n");
+    assert_eq!(clean_newlines("hello\r\n\n\rworld\r"), "hello\n\n\nworld\n");
+    assert_eq!(clean_newlines("hello\r\n\n\rworld\r\n"), "hello\n\n\nworld\n");
+    assert_eq!(clean_newlines("hello,\"one\rtwo\rthree\"\rworld,test,\"one\rtwo\r\"\ragain,\"\rtwo\",\"\rtwo\r\""), "hello,\"one\ntwo\nthree\"\nworld,test,\"one\ntwo\n\"\nagain,\"\ntwo\",\"\ntwo\n\"");
+}
+
+#[inline(never)]
+fn get_collab_id<'a>(block: &'a Xml) -> Option<&'a str> {
+    block.attr("collabId").map(|x| &*x.value).filter(|x| !x.is_empty())
+}
+
+/// Gets the temporary [`NodeId`] of a block parsed from the given element, which is replaced by [`Parser::finish_project`] (see [`SourceMap`]).
+/// This is the address of the element, which is stable once the tree is built and is never 0.
+#[inline(always)]
+fn source_id(block: &Xml) -> NodeId {
+    NodeId(block as *const Xml as usize)
+}
+
+/// An attribute of an [`Xml`] element.
+/// The value borrows from the source unless it had to be unescaped.
+#[derive(Debug)]
+pub(crate) struct XmlAttr<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: Cow<'a, str>,
+}
+/// An element of the xml tree, which borrows from the source so that parsing does not copy every name, attribute, and text node.
+#[derive(Debug)]
+pub(crate) struct Xml<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) text: Cow<'a, str>,
+    pub(crate) attrs: Vec<XmlAttr<'a>>,
+    pub(crate) children: Vec<Xml<'a>>,
+}
+impl<'a> Xml<'a> {
+    /// Computes a deterministic hash of the entire tree.
+    /// This is done iteratively, since the tree can be much deeper than the parser's stack budget allows for recursion.
+    fn content_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::new();
+        let mut stack = vec![self];
+        while let Some(xml) = stack.pop() {
+            xml.name.hash(&mut hasher);
+            xml.text.hash(&mut hasher);
+            for attr in xml.attrs.iter() {
+                attr.name.hash(&mut hasher);
+                attr.value.hash(&mut hasher);
+            }
+            xml.attrs.len().hash(&mut hasher);
+            xml.children.len().hash(&mut hasher);
+            stack.extend(xml.children.iter().rev());
+        }
+        hasher.finish()
+    }
+    fn get(&self, path: &[&str]) -> Option<&Xml<'a>> {
+        match path {
+            [] => Some(self),
+            [first, rest @ ..] => self.children.iter().find(|x| x.name == *first).map(|x| x.get(rest)).flatten(),
+        }
+    }
+    fn attr(&self, name: &str) -> Option<&XmlAttr<'a>> {
+        self.attrs.iter().find(|a| a.name == name)
+    }
+}
+/// Parses the xml tree for the element `root_name` (whose start tag, beginning at byte offset `start`, was already consumed).
+/// Any elements named in `skip` (and their content) are consumed without being parsed or stored.
+///
+/// If `spans` is given, it receives the byte range of every stored element (from the start of its start tag to the end of its end tag), in document order.
+/// These are kept out of the tree itself, since the size of [`Xml`] affects the parser's stack usage.
+pub(crate) fn parse_xml_root<'a>(xml: &mut xmlparser::Tokenizer<'a>, root_name: &'a str, start: usize, skip: &[&str], mut spans: Option<&mut Vec<Range<usize>>>) -> Result<Xml<'a>, XmlError> {
+    let mut stack = vec![Xml { name: root_name, text: Cow::Borrowed(""), attrs: vec![], children: vec![] }];
+    let mut open = vec![]; // indices into spans of the elements on the stack
+    if let Some(spans) = spans.as_deref_mut() {
+        open.push(spans.len());
+        spans.push(start..start);
+    }
+    let mut skip_depth = 0usize;
+    loop {
+        match xml.next() {
+            Some(e) => match e {
+                Err(e) => return Err(XmlError::Read { error: e }),
+                Ok(e) if skip_depth > 0 => match e {
+                    xmlparser::Token::ElementStart { .. } => skip_depth += 1,
+                    xmlparser::Token::ElementEnd { end: xmlparser::ElementEnd::Close(_, _) | xmlparser::ElementEnd::Empty, .. } => skip_depth -= 1,
+                    _ => (),
+                }
+                Ok(xmlparser::Token::ElementStart { local, .. }) if skip.contains(&local.as_str()) => skip_depth = 1,
+                Ok(e) => match e {
+                    xmlparser::Token::Attribute { local, value, .. } => stack.last_mut().unwrap().attrs.push(XmlAttr { name: local.as_str(), value: xml_unescape_cow(value.as_str())? }),
+                    xmlparser::Token::Text { text: t } => {
+                        let text = &mut stack.last_mut().unwrap().text;
+                        let t = xml_unescape_cow(t.as_str())?;
+                        if text.is_empty() { *text = t } else { text.to_mut().push_str(&t) }
+                    }
+                    xmlparser::Token::ElementStart { local, span, .. } => {
+                        stack.push(Xml { name: local.as_str(), text: Cow::Borrowed(""), attrs: vec![], children: vec![] });
+                        if let Some(spans) = spans.as_deref_mut() {
+                            open.push(spans.len());
+                            spans.push(span.start()..span.start());
+                        }
+                    }
+                    xmlparser::Token::ElementEnd { end, span } => match end {
+                        xmlparser::ElementEnd::Close(_, _) | xmlparser::ElementEnd::Empty => {
+                            let mut res = stack.pop().unwrap();
+                            if let Cow::Owned(text) = clean_newlines(&res.text) { res.text = Cow::Owned(text) }
+                            if let Some(spans) = spans.as_deref_mut() {
+                                spans[open.pop().unwrap()].end = span.end();
+                            }
+                            match stack.last_mut() {
+                                Some(parent) => parent.children.push(res),
+                                None => return Ok(res),
+                            }
+                        }
+                        xmlparser::ElementEnd::Open => (),
+                    }
+                    _ => (),
+                }
+            }
+            None => return Err(XmlError::UnexpectedEof),
+        }
+    }
+}
+
+/// The results of [`Parser::parse_dir`], with one entry per project file in order of path.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct DirResults {
+    pub files: Vec<(std::path::PathBuf, Result<Project, FileError>)>,
+}
+#[cfg(feature = "std")]
+impl DirResults {
+    /// Gets the files which failed to be read or parsed, along with the reasons.
+    pub fn failures(&self) -> impl Iterator<Item = (&std::path::Path, &FileError)> {
+        self.files.iter().filter_map(|(path, res)| res.as_ref().err().map(|e| (path.as_path(), e)))
+    }
+    /// Counts the results of the batch.
+    pub fn summary(&self) -> DirSummary {
+        let mut res = DirSummary { files: self.files.len(), parsed: 0, io_errors: 0, parse_errors: 0 };
+        for (_, x) in self.files.iter() {
+            match x {
+                Ok(_) => res.parsed += 1,
+                Err(FileError::Io(_)) => res.io_errors += 1,
+                Err(FileError::Parse(_)) => res.parse_errors += 1,
+            }
+        }
+        res
+    }
+}
+/// A summary of [`DirResults`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirSummary {
+    pub files: usize,
+    pub parsed: usize,
+    /// The number of files that could not be read.
+    pub io_errors: usize,
+    /// The number of files that were read, but failed to parse.
+    pub parse_errors: usize,
+}
+
+/// Settings for [`Parser::parse_dir_parallel`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelOptions {
+    /// The maximum number of threads to parse on (at least one is always used).
+    /// Defaults to the available parallelism of the system.
+    pub threads: usize,
+    /// The stack size of each thread, in bytes.
+    /// Defaults to [`ParallelOptions::DEFAULT_STACK_SIZE`], which is larger than the default for new threads to handle deeply nested projects.
+    pub stack_size: usize,
+}
+#[cfg(feature = "std")]
+impl ParallelOptions {
+    pub const DEFAULT_STACK_SIZE: usize = 8 * 1024 * 1024;
+}
+#[cfg(feature = "std")]
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self { threads: std::thread::available_parallelism().map_or(1, |x| x.get()), stack_size: Self::DEFAULT_STACK_SIZE }
+    }
+}
+
+/// Controls how the parser handles blocks with missing or unknown dropdown options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail with an error.
+    Strict,
+    /// Parse the block as [`StmtKind::UnknownBlock`] or [`ExprKind::UnknownBlock`] and emit a [`WarningKind::BlockParsedAsUnknown`] warning.
+    Lenient,
+    /// Parse the block as [`StmtKind::UnknownBlock`] or [`ExprKind::UnknownBlock`] without a warning.
+    Permissive,
+}
+
+/// Controls how the parser represents input slots which were left empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptySlots {
+    /// Parse them as empty strings, which is how Snap! evaluates them.
+    EmptyString,
+    /// Parse them as [`ExprKind::EmptySlot`], so that the consumer can apply block-specific defaults.
+    Marker,
+    /// Fail with [`CompileError::EmptySlot`].
+    Error,
+}
+
+/// Limits on the size of list literals, as set by [`Parser::list_limits`].
+/// Nested lists are checked separately, so these limits apply to each list literal on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListLimits {
+    /// The maximum number of items in a list literal, or of rows in a linewise list literal.
+    pub max_items: usize,
+    /// The maximum total length (in bytes) of the text items in a list literal.
+    pub max_bytes: usize,
+    /// If `true`, list literals which exceed a limit are truncated to fit with a [`WarningKind::ListTruncated`] warning,
+    /// rather than failing with [`CompileError::ListTooLarge`].
+    pub truncate: bool,
+}
+
+#[derive(Debug)]
+struct Rpc {
+    host: Option<CompactString>,
+    service: CompactString,
+    rpc: CompactString,
+    args: Vec<(CompactString, Expr)>,
+    info: Box<BlockInfo>,
+}
+#[derive(Debug)]
+struct FnCall {
+    function: FnRef,
+    args: Vec<Expr>,
+    upvars: Vec<VariableRef>,
+    info: Box<BlockInfo>,
+}
+
+impl From<Rpc> for Stmt {
+    fn from(rpc: Rpc) -> Stmt {
+        let Rpc { host, service, rpc, args, info } = rpc;
+        Stmt { kind: StmtKind::CallRpc { host, service, rpc, args }, info }
+    }
+}
+impl From<Rpc> for Expr {
+    fn from(rpc: Rpc) -> Expr {
+        let Rpc { host, service, rpc, args, info } = rpc;
+        Expr { kind: ExprKind::CallRpc { host, service, rpc, args }, info }
+    }
+}
+
+impl ProjectMeta {
+    fn parse(root: &Xml) -> Self {
+        let attr = |name: &str| [Some(root), root.get(&["project"]), root.get(&["role", "project"])].into_iter().flatten().find_map(|x| x.attr(name)).map(|x| &*x.value);
+        let app = attr("app").and_then(|x| x.split(',').next()).map(str::trim).filter(|x| !x.is_empty());
+        let (app_name, app_version) = match app.and_then(|x| x.rsplit_once(' ')) {
+            Some((name, version)) => (Some(name.into()), Some(version.into())),
+            None => (app.map(CompactString::new), None),
+        };
+        Self { app_name, app_version, version: attr("version").map(CompactString::new) }
+    }
+    fn is_supported(&self) -> bool {
+        match self.version.as_deref().and_then(|x| x.split('.').next()).and_then(|x| x.parse::<u32>().ok()) {
+            Some(major) => major <= Self::MAX_SUPPORTED_VERSION,
+            None => true,
+        }
+    }
+}
+impl BlockInfo {
+    fn lowered_from(mut self: Box<Self>, opcode: &str) -> Box<Self> {
+        self.opcode = Some(CompactString::new(opcode));
+        self
+    }
+}
+
+/// Maps the [`NodeId`]s of a parsed project to the byte ranges of the xml elements they were parsed from, as produced by [`Parser::parse_with_source_map`].
+/// This allows, e.g., editor integrations to highlight the original block when a compiler reports an error on an ast node.
+///
+/// Only nodes which correspond to a block element in the xml are included (e.g., not literal values or nodes which were lowered from other blocks).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceMap {
+    spans: BTreeMap<NodeId, Range<usize>>,
+}
+impl SourceMap {
+    /// Gets the byte range (from the start of the start tag to the end of the end tag) of the xml element that a node was parsed from.
+    pub fn get(&self, id: NodeId) -> Option<Range<usize>> {
+        self.spans.get(&id).cloned()
+    }
+    /// Iterates over the nodes in the map and their byte ranges, in order of id.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, Range<usize>)> + '_ {
+        self.spans.iter().map(|(id, span)| (*id, span.clone()))
+    }
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+    /// Records the span of every element in the tree (other than the root), keyed by the temporary ids from [`source_id`].
+    /// The spans are given in document order, as produced by [`parse_xml_root`].
+    #[inline(never)]
+    fn add_elements(&mut self, root: &Xml, spans: &[Range<usize>]) {
+        let mut stack = vec![root];
+        let mut spans = spans.iter();
+        while let Some(xml) = stack.pop() {
+            let span = spans.next().unwrap();
+            if !ptr::eq(xml, root) {
+                self.spans.insert(source_id(xml), span.clone());
+            }
+            stack.extend(xml.children.iter().rev());
+        }
+    }
+}
+
+/// Checks if an if block (or `reportIfElse`) has the extra list of else-if branches used by Snap 9.
+fn is_if_chain(block: &Xml, has_else: bool) -> bool {
+    block.children.get(2).is_some_and(|x| x.name == "list") && (!has_else || block.children.len() >= 4)
+}
+/// Gets the (condition, body) pairs of the else-if branches of an if chain.
+fn if_chain_branches<'a>(block: &'a Xml<'a>, location: &LocationRef) -> Result<core::slice::ChunksExact<'a, Xml<'a>>, Box<Error>> {
+    let branches = &block.children[2].children;
+    if !branches.len().is_multiple_of(2) {
+        return Err(Box::new_with(|| Error { kind: ProjectError::BlockChildCount { needed: branches.len() + 1, got: branches.len() }.into(), location: location.to_owned() }));
+    }
+    Ok(branches.chunks_exact(2))
+}
+
+fn parse_color(value: &str) -> Option<(u8, u8, u8, u8)> {
+    let vals: Vec<_> = value.split(',').map(|v| v.parse::<f64>().ok()).flatten().collect();
+    match vals.as_slice() {
+        [r, g, b] => Some((*r as u8, *g as u8, *b as u8, 255)),
+        [r, g, b, a] => Some((*r as u8, *g as u8, *b as u8, (*a * 255.0) as u8)),
+        _ => None,
+    }
+}
+
+struct NetworkMessage {
+    target: Box<Expr>,
+    msg_type: CompactString,
+    values: Vec<(CompactString, Expr)>,
+    info: Box<BlockInfo>,
+}
+
+impl<T: NameTransformer> BlockScope for ScriptInfo<'_, '_, '_, T> {
+    fn variable(&mut self, name: &str, location: &LocationRef) -> Result<Box<VariableRef>, Box<Error>> {
+        self.reference_var(name, location)
+    }
+    fn declare(&mut self, name: &str, location: &LocationRef) -> Result<Box<VariableRef>, Box<Error>> {
+        Ok(self.decl_local(name.into(), 0f64.into(), location)?.def.ref_at(VarLocation::Local))
+    }
+}
+
+struct ScriptInfo<'a, 'b, 'c, T> {
+    parser: &'a Parser<T>,
+    role: &'c RoleInfo<'a, T>,
+    entity: &'c EntityInfo<'a, 'b, T>,
+    locals: Vec<(SymbolTable<'a, T>, Vec<VariableRef>)>, // tuples of (locals, captures)
+    autofill_args: Option<Vec<VariableRef>>,
+}
+impl<'a, 'b, 'c, T: NameTransformer> ScriptInfo<'a, 'b, 'c, T> {
+    fn new(entity: &'c EntityInfo<'a, 'b, T>) -> Box<Self> {
+        Box::new_with(|| Self {
+            parser: entity.parser,
+            role: entity.role,
+            entity,
+            locals: vec![(SymbolTable::new(entity.parser), Default::default())],
+            autofill_args: None,
+        })
+    }
+    #[inline(never)]
+    fn check_children_get_info(&self, expr: &Xml, req: usize, location: &LocationRef) -> Result<Box<BlockInfo>, Box<Error>> {
+        if expr.children.len() < req {
+            return Err(Box::new_with(|| Error { kind: ErrorKind::ProjectError(ProjectError::BlockChildCount { needed: req, got: expr.children.len() }), location: location.to_owned() }));
+        }
+        let comment = match expr.children.get(req) {
+            Some(comment) => if comment.name == "comment" { Some(CompactString::new(&comment.text)) } else { None },
+            None => None,
+        };
+        Ok(Box::new_with(|| BlockInfo { comment, location: location.collab_id.map(CompactString::new), opcode: None, id: source_id(expr), annotation: () }))
+    }
+    #[inline(never)]
+    fn decl_local(&mut self, name: CompactString, value: Value, location: &LocationRef) -> Result<&VariableDefInit, Box<Error>> {
+        let locals = &mut self.locals.last_mut().unwrap().0;
+        match locals.define(name.clone(), value) {
+            Ok(_) => (), // redefining locals is fine
+            Err(SymbolError::ConflictingTrans { trans_name, names }) => if names.0 != names.1 { // redefining locals is fine
+                return Err(Box::new_with(|| Error { kind: CompileError::LocalsWithSameTransName { trans_name, names }.into(), location: location.to_owned() }));
+            }
+            Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+        }
+        Ok(locals.get(&name).unwrap())
+    }
+    /// Emits a warning if declaring a local with the given name would hide a field or global.
+    #[inline(never)]
+    fn check_shadowing(&self, name: &str, location: &LocationRef) {
+        let shadowed = if self.entity.fields.get(name).is_some() {
+            (VarLocation::Field, Some(self.entity.name.clone()))
+        } else if self.role.globals.get(name).is_some() {
+            (VarLocation::Global, None)
+        } else {
+            return
+        };
+        let shadowed_location = Location { role: Some(self.role.name.clone()), entity: shadowed.1, collab_id: None, block_type: None };
+        self.role.warn(WarningKind::LocalShadowsVariable { name: name.into(), shadowed: shadowed.0, shadowed_location }, location);
+    }
+    #[inline(never)]
+    fn grab_option<'x>(&self, child: &'x Xml, location: &LocationRef) -> Result<&'x str, Box<Error>> {
+        let res = match child.get(&["option"]) {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::BlockMissingOption.into(), location: location.to_owned() })),
+            Some(f) => {
+                if f.children.len() != 0 { return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() })) }
+                &*f.text
+            }
+        };
+        if res == "" { return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })) }
+        Ok(res)
+    }
+    #[inline(never)]
+    fn grab_entity(&mut self, child: &Xml, info: Box<BlockInfo>, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        match &*child.text {
+            "" => match child.get(&["option"]) {
+                Some(x) => match &*x.text {
+                    "myself" => Ok(Box::new_with(|| Expr { kind: ExprKind::This, info })),
+                    x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                }
+                None => self.parse_expr(child, location),
+            }
+            name => match self.role.entities.get(name) {
+                None => Err(Box::new_with(|| Error { kind: CompileError::UnknownEntity { unknown: name.into() }.into(), location: location.to_owned() })),
+                Some(entity) => Ok(Box::new_with(|| Expr { kind: ExprKind::Entity { name: entity.def.name.clone(), trans_name: entity.def.trans_name.clone() }, info })),
+            }
+        }
+    }
+    #[inline(never)]
+    fn parse(&mut self, script_xml: &Xml) -> Result<Box<Script>, Box<Error>> {
+        let mut script = match script_xml.children.first() {
+            Some(x) => Box::try_new_with(|| Ok::<_, Box<Error>>(Script { hat: self.parse_hat(x)?, stmts: vec![], locals: vec![] }))?,
+            None => Box::new_with(|| Script { hat: None, stmts: vec![], locals: vec![] }),
+        };
+
+        // nearly every block is a single statement, so reserving up front avoids regrowing (and over-allocating) the typically short statement list
+        let stmts = &script_xml.children[if script.hat.is_some() { 1 } else { 0 }..];
+        script.stmts.reserve_exact(stmts.len());
+        for stmt in stmts {
+            let location = Box::new_with(|| LocationRef {
+                role: Some(&self.role.name),
+                entity: Some(&self.entity.name),
+                collab_id: get_collab_id(stmt),
+                block_type: Some(stmt.name),
+            });
+            match stmt.name {
+                "block" => {
+                    script.stmts.append(&mut self.parse_block(stmt)?);
+                }
+                "custom-block" => {
+                    let res = self.parse_fn_call(stmt, &location)?;
+                    script.stmts.push_with(|| {
+                        let FnCall { function, args, upvars, info } = *res;
+                        Stmt { kind: StmtKind::CallFn { function, args, upvars }, info }
+                    });
+                }
+                _ => return Err(Box::new_with(|| Error { kind: ProjectError::BlockUnknownType.into(), location: location.to_owned() })),
+            }
+        }
+
+        Ok(script)
+    }
+    /// Records the locals defined while parsing a top level script (see [`Script::locals`]).
+    /// This is done separately from parsing, since nested scripts do not record locals.
+    #[inline(never)]
+    fn finish_top_level(&mut self, script: &mut Script) {
+        script.locals = self.locals.pop().unwrap().0.into_defs();
+    }
+    #[inline(never)]
+    fn parse_hat(&mut self, stmt: &Xml) -> Result<Option<Box<Hat>>, Box<Error>> {
+        let mut location = Box::new_with(|| LocationRef {
+            role: Some(&self.role.name),
+            entity: Some(&self.entity.name),
+            collab_id: get_collab_id(stmt),
+            block_type: None,
+        });
+        let s = match stmt.attr("s") {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::BlockWithoutType.into(), location: location.to_owned() })),
+            Some(v) => &*v.value,
+        };
+        location.block_type = Some(s);
+
+        if let Some(res) = self.handle_hat(stmt, s, &location) {
+            return res.map(Some);
+        }
+
+        fn parse_fields<T: NameTransformer>(script: &mut ScriptInfo<T>, children: &[Xml], location: &LocationRef) -> Result<(Vec<VariableRef>, Option<CompactString>), Box<Error>> {
+            let mut fields = vec![];
+            let mut comment = None;
+            for child in children {
+                if child.name == "comment" {
+                    comment = Some(CompactString::new(&child.text));
+                }
+                if child.name != "l" { break }
+                let var = script.decl_local(CompactString::new(&child.text), 0f64.into(), &location)?.def.ref_at(VarLocation::Local);
+                fields.push_boxed(var);
+            }
+            Ok((fields, comment))
+        }
+
+        Ok(Some(match s {
+            "receiveGo" => {
+                let info = self.check_children_get_info(stmt, 0, &location)?;
+                Box::new_with(|| Hat { kind: HatKind::OnFlag, info })
+            }
+            "receiveOnClone" => {
+                let info = self.check_children_get_info(stmt, 0, &location)?;
+                Box::new_with(|| Hat { kind: HatKind::OnClone, info })
+            }
+            "receiveCondition" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let condition = self.parse_expr(&stmt.children[0], &location)?;
+                Box::new_with(|| Hat { kind: HatKind::When { condition }, info })
+            }
+            "receiveKey" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let key = KeyCode::parse(self.grab_option(&stmt.children[0], &location)?);
+                Box::new_with(|| Hat { kind: HatKind::OnKey { key }, info })
+            }
+            "receiveInteraction" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                match self.grab_option(&stmt.children[0], &location)? {
+                    "pressed" => Box::new_with(|| Hat { kind: HatKind::MouseDown, info }),
+                    "clicked" => Box::new_with(|| Hat { kind: HatKind::MouseUp, info }),
+                    "mouse-entered" => Box::new_with(|| Hat { kind: HatKind::MouseEnter, info }),
+                    "mouse-departed" => Box::new_with(|| Hat { kind: HatKind::MouseLeave, info }),
+                    "scrolled-up" => Box::new_with(|| Hat { kind: HatKind::ScrollUp, info }),
+                    "scrolled-down" => Box::new_with(|| Hat { kind: HatKind::ScrollDown, info }),
+                    "dropped" => Box::new_with(|| Hat { kind: HatKind::Dropped, info }),
+                    "stopped" => Box::new_with(|| Hat { kind: HatKind::Stopped, info }),
+                    x => return Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                }
+            }
+            "receiveMessage" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let child = &stmt.children[0];
+                if child.name != "l" { return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() })) }
+                let msg_type = match &*child.text {
+                    "" => match child.get(&["option"]) {
+                        Some(opt) => match &*opt.text {
+                            "any message" => None,
+                            x => return Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                        }
+                        None => return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                    }
+                    x => Some(CompactString::new(x)),
+                };
+                Box::new_with(|| Hat { kind: HatKind::LocalMessage { msg_type }, info })
+            }
+            "receiveSocketMessage" => {
+                if stmt.children.is_empty() { return Err(Box::new_with(|| Error { kind: ProjectError::BlockChildCount { needed: 1, got: 0 }.into(), location: location.to_owned() })) }
+                if stmt.children[0].name != "l" { return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() })) }
+
+                let msg_type = match &*stmt.children[0].text {
+                    "" => return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                    x => CompactString::new(x),
+                };
+
+                let (fields, comment) = parse_fields(self, &stmt.children[1..], &location)?;
+                let info = Box::new_with(|| BlockInfo { comment, location: location.collab_id.map(CompactString::new), opcode: None, id: source_id(stmt), annotation: () });
+                Box::new_with(|| Hat { kind: HatKind::NetworkMessage { msg_type, fields }, info })
+            }
+            x if x.starts_with("receive") => {
+                let (fields, comment) = parse_fields(self, &stmt.children, &location)?;
+                let info = Box::new_with(|| BlockInfo { comment, location: location.collab_id.map(CompactString::new), opcode: None, id: source_id(stmt), annotation: () });
+                Box::new_with(|| Hat { kind: HatKind::Unknown { fields, name: x.into() }, info })
+            }
+            _ => return Ok(None),
+        }))
+    }
+    #[inline(never)]
+    fn parse_effect(&mut self, effect: &Xml, location: &LocationRef) -> Result<EffectKind, Box<Error>> {
+        Ok(match self.grab_option(effect, location)? {
+            "color" => EffectKind::Color,
+            "saturation" => EffectKind::Saturation,
+            "brightness" => EffectKind::Brightness,
+            "ghost" => EffectKind::Ghost,
+            "fisheye" => EffectKind::Fisheye,
+            "whirl" => EffectKind::Whirl,
+            "pixelate" => EffectKind::Pixelate,
+            "mosaic" => EffectKind::Mosaic,
+            "negative" => EffectKind::Negative,
+            x => return self.unknown_option(x, EffectKind::Other, CompileError::UnknownEffect { effect: CompactString::new(x) }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_pen_attr(&mut self, attr: &Xml, location: &LocationRef) -> Result<PenAttribute, Box<Error>> {
+        Ok(match self.grab_option(attr, location)? {
+            "size" => PenAttribute::Size,
+            "hue" => PenAttribute::Hue,
+            "saturation" => PenAttribute::Saturation,
+            "brightness" => PenAttribute::Brightness,
+            "transparency" => PenAttribute::Transparency,
+            x => return self.unknown_option(x, PenAttribute::Other, CompileError::UnknownPenAttr { attr: CompactString::new(x) }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_global_flag(&mut self, flag: &Xml, location: &LocationRef) -> Result<GlobalFlag, Box<Error>> {
+        Ok(match self.grab_option(flag, location)? {
+            "turbo mode" => GlobalFlag::TurboMode,
+            "flat line ends" => GlobalFlag::FlatLineEnds,
+            "log pen vectors" => GlobalFlag::LogPenVectors,
+            "video capture" => GlobalFlag::VideoCapture,
+            "mirror video" => GlobalFlag::MirrorVideo,
+            x => return self.unknown_option(x, GlobalFlag::Other, CompileError::UnknownGlobalFlag { flag: CompactString::new(x) }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_text_function(&self, option: &Xml, location: &LocationRef) -> Result<TextFunction, Box<Error>> {
+        Ok(match self.grab_option(option, location)? {
+            "upper case" => TextFunction::Upper,
+            "lower case" => TextFunction::Lower,
+            "trim" => TextFunction::Trim,
+            "encode URI" => TextFunction::EncodeUri,
+            "decode URI" => TextFunction::DecodeUri,
+            "encode URI component" => TextFunction::EncodeUriComponent,
+            "decode URI component" => TextFunction::DecodeUriComponent,
+            "XML escape" => TextFunction::XmlEscape,
+            "XML unescape" => TextFunction::XmlUnescape,
+            "JS escape" => TextFunction::JsEscape,
+            "hex sha512 hash" => TextFunction::HexSha512,
+            x => return self.unknown_option(x, TextFunction::Other, ProjectError::BlockOptionUnknown { got: x.into() }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_value_type(&self, option: &Xml, location: &LocationRef) -> Result<ValueType, Box<Error>> {
+        Ok(match self.grab_option(option, location)? {
+            "number" => ValueType::Number,
+            "text" => ValueType::Text,
+            "Boolean" => ValueType::Bool,
+            "list" => ValueType::List,
+            "sprite" => ValueType::Sprite,
+            "costume" => ValueType::Costume,
+            "sound" => ValueType::Sound,
+            "command" => ValueType::Command,
+            "reporter" => ValueType::Reporter,
+            "predicate" => ValueType::Predicate,
+            x => return self.unknown_option(x, ValueType::Other, ProjectError::BlockOptionUnknown { got: x.into() }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_time_query(&self, option: &Xml, location: &LocationRef) -> Result<TimeQuery, Box<Error>> {
+        Ok(match self.grab_option(option, location)? {
+            "year" => TimeQuery::Year,
+            "month" => TimeQuery::Month,
+            "date" => TimeQuery::Date,
+            "day of week" => TimeQuery::DayOfWeek,
+            "hour" => TimeQuery::Hour,
+            "minute" => TimeQuery::Minute,
+            "second" => TimeQuery::Second,
+            "time in milliseconds" => TimeQuery::UnixTimestampMs,
+            x => return self.unknown_option(x, TimeQuery::Other, ProjectError::BlockOptionUnknown { got: x.into() }.into(), location),
+        })
+    }
+    #[inline(never)]
+    fn parse_rpc(&mut self, stmt: &Xml, location: &LocationRef) -> Result<Box<Rpc>, Box<Error>> {
+        if stmt.children.len() < 2 { return Err(Box::new_with(|| Error { kind: ProjectError::BlockChildCount { needed: 2, got: stmt.children.len() }.into(), location: location.to_owned() })) }
+        for child in &stmt.children[..2] {
+            if child.name != "l" || child.text.is_empty() { return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() })) }
+        }
+
+        let (host, service) = match stmt.children[0].text.rsplit_once('/') {
+            Some((host, service)) => (Some(CompactString::new(host)), CompactString::new(service)),
+            None => (None, CompactString::new(&stmt.children[0].text)),
+        };
+        let rpc = CompactString::new(&stmt.children[1].text);
+
+        let arg_names = match stmt.attr("inputNames").map(|x| x.value.split(';').map(str::trim).filter(|v| !v.is_empty()).collect::<Vec<_>>()) {
+            Some(x) => x,
+            None => match SERVICE_INFO.iter().find(|x| x.0 == service) {
+                None => return Err(Box::new_with(|| Error { kind: CompileError::UnknownService { service }.into(), location: location.to_owned() })),
+                Some(x) => match x.1.iter().find(|x| x.0 == rpc) {
+                    None => return Err(Box::new_with(|| Error { kind: CompileError::UnknownRPC { service, rpc }.into(), location: location.to_owned() })),
+                    Some(x) => x.1.to_owned(),
+                }
+            }
+        };
+
+        let info = self.check_children_get_info(stmt, 2 + arg_names.len(), location)?;
+        let mut args = Vec::with_capacity(arg_names.len());
+        for (&arg_name, child) in arg_names.iter().zip(&stmt.children[2 .. 2 + arg_names.len()]) {
+            let val = self.parse_expr(child, location)?;
+            args.push_with(|| (CompactString::new(arg_name), *val));
+        }
+        Ok(Box::new_with(|| Rpc { host, service, rpc, args, info }))
+    }
+    #[inline(never)]
+    fn parse_fn_call(&mut self, stmt: &Xml, location: &LocationRef) -> Result<Box<FnCall>, Box<Error>> {
+        let s = match stmt.attr("s") {
+            Some(v) => &*v.value,
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockWithoutName.into(), location: location.to_owned() })),
+        };
+        let location = Box::new_with(|| LocationRef {
+            role: location.role,
+            entity: location.entity,
+            collab_id: location.collab_id,
+            block_type: Some(s),
+        });
+
+        let name = block_name_from_ref(s);
+        let function = self.reference_fn(&name, &location)?;
+        let block_info = get_block_info(&function.1);
+        let argc = block_info.params.len();
+        debug_assert_eq!(ArgIter::new(s).count(), argc);
+        let info = self.check_children_get_info(stmt, argc, &location)?;
+
+        let mut upvars = vec![];
+        for upvar in block_info.upvars.iter() {
+            let i = match block_info.params.iter().position(|x| x.0 == *upvar) {
+                Some(x) => x,
+                None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockInputsMetaCorrupted.into(), location: location.to_owned() })),
+            };
+            let upvar_target = match stmt.children.get(i) {
+                Some(x) if x.name == "l" && !x.text.is_empty() => &*x.text,
+                _ => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockInputsMetaCorrupted.into(), location: location.to_owned() })),
+            };
+            self.check_shadowing(upvar_target, &location);
+            let def = self.decl_local(upvar_target.into(), Value::from(0.0f64), &location)?;
+            upvars.push_boxed(def.def.ref_at(VarLocation::Local));
+        }
+
+        let mut args = Vec::with_capacity(argc);
+        for (param, expr) in iter::zip(&block_info.params, &stmt.children[..argc]) {
+            match param.1 {
+                ParamType::Evaluated => args.push_boxed(self.parse_expr(expr, &location)?),
+                ParamType::Unevaluated => args.push_boxed(self.parse_closure(expr, ClosureKind::Reporter, true, &location)?),
+                ParamType::Variadic { evaluated } => args.push_boxed(self.parse_variadic_arg(expr, evaluated, &location)?),
+            }
+        }
+
+        Ok(Box::new_with(|| FnCall { function: function.0, args, upvars, info }))
+    }
+    #[inline(never)]
+    fn parse_variadic_arg(&mut self, expr: &Xml, evaluated: bool, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        if expr.name != "list" || expr.attr("struct").is_some() {
+            return self.parse_expr(expr, location); // a single reporter dropped onto the variadic slot
+        }
+
+        let mut values = Vec::with_capacity(expr.children.len());
+        for item in expr.children.iter() {
+            values.push_boxed(match evaluated {
+                true => self.parse_expr(item, location)?,
+                false => self.parse_closure(item, ClosureKind::Reporter, true, location)?,
+            });
+        }
+        Ok(Box::new_with(|| Expr { kind: ExprKind::MakeList { values }, info: BlockInfo::none() }))
+    }
+    #[inline(never)]
+    fn parse_send_message_common(&mut self, stmt: &Xml, location: &LocationRef) -> Result<Box<NetworkMessage>, Box<Error>> {
+        let msg_type = match stmt.children.get(0) {
+            Some(value) if value.name != "comment" => &*value.text,
+            _ => return Err(Box::new_with(|| Error { kind: ProjectError::BlockMissingOption.into(), location: location.to_owned() })),
+        };
+        let fields = match self.role.msg_types.get(msg_type) {
+            None => return Err(Box::new_with(|| Error { kind: CompileError::UnknownMessageType { msg_type: msg_type.into() }.into(), location: location.to_owned() })),
+            Some(x) => x,
+        };
+
+        let (argc, comment) = stmt.children.iter().enumerate().find(|(_, x)| x.name == "comment").map(|(i, x)| (i, Some(&*x.text))).unwrap_or((stmt.children.len(), None));
+        assert!(argc >= 1); // due to msg_type from above
+
+        let values = stmt.children[1..argc - 1].iter().map(|x| self.parse_expr(x, location)).collect::<Result<Vec<_>,_>>()?;
+        if fields.len() != values.len() {
+            return Err(Box::new_with(|| Error { kind: CompileError::MessageTypeWrongNumberArgs { msg_type: msg_type.into(), got: values.len(), expected: fields.len() }.into(), location: location.to_owned() }));
+        }
+
+        let target_xml = &stmt.children[argc - 1];
+        let target = match target_xml.get(&["option"]) {
+            Some(x) => match &*x.text {
+                "" => return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                x => Box::new_with(|| x.into()),
+            }
+            None => self.parse_expr(target_xml, location)?,
+        };
+
+        let info = Box::new_with(|| BlockInfo { comment: comment.map(CompactString::new), location: location.collab_id.map(CompactString::new), opcode: None, id: source_id(stmt), annotation: () });
+        Ok(Box::new_with(|| NetworkMessage { target, msg_type: msg_type.into(), values: fields.iter().map(|&x| CompactString::new(x)).zip(values.into_iter().map(|x| *x)).collect(), info }))
+    }
+    #[inline(never)]
+    fn parse_unknown_common(&mut self, stmt: &Xml, location: &LocationRef) -> Result<(Vec<Expr>, Box<BlockInfo>), Box<Error>> {
+        let (argc, comment) = stmt.children.iter().enumerate().find(|(_, x)| x.name == "comment").map(|(i, x)| (i, Some(&*x.text))).unwrap_or((stmt.children.len(), None));
+        let mut args = Vec::with_capacity(argc);
+        for arg in stmt.children[..argc].iter() {
+            args.push_boxed(self.parse_expr(arg, &location)?);
+        }
+        Ok((args, Box::new_with(|| BlockInfo { comment: comment.map(CompactString::new), location: location.collab_id.map(CompactString::new), opcode: None, id: source_id(stmt), annotation: () })))
+    }
+    fn check_metaprogramming(&self, location: &LocationRef) -> Result<(), Box<Error>> {
+        match self.parser.allow_metaprogramming {
+            true => Ok(()),
+            false => Err(Box::new_with(|| Error { kind: CompileError::MetaprogrammingNotAllowed.into(), location: location.to_owned() })),
+        }
+    }
+    /// Checks if an error from parsing a block can be tolerated under [`Parser::strictness`], in which case a warning is emitted (if requested).
+    /// Otherwise, the error is returned unchanged.
+    fn tolerate(&self, error: Box<Error>) -> Result<(), Box<Error>> {
+        let tolerable = matches!(error.kind,
+            ErrorKind::CompileError(CompileError::BlockOptionNotSelected) | ErrorKind::ProjectError(ProjectError::BlockOptionUnknown { .. } | ProjectError::BlockMissingOption));
+        match self.parser.strictness {
+            _ if !tolerable => Err(error),
+            Strictness::Strict => Err(error),
+            Strictness::Lenient => {
+                let Error { kind, location } = *error;
+                self.role.warnings.borrow_mut().push(Warning { kind: WarningKind::BlockParsedAsUnknown { error: kind }, location });
+                Ok(())
+            }
+            Strictness::Permissive => Ok(()),
+        }
+    }
+    /// Gets the number of items of a list literal which should be parsed according to [`Parser::list_limits`], or `usize::MAX` if the whole list fits.
+    /// If the list does not fit, this either fails or emits a [`WarningKind::ListTruncated`] warning, depending on [`ListLimits::truncate`].
+    #[inline(never)]
+    fn check_list_limits(&self, expr: &Xml, location: &LocationRef) -> Result<usize, Box<Error>> {
+        fn fitting_items(sizes: impl Iterator<Item = usize>, limits: &ListLimits) -> Option<usize> {
+            let mut bytes = 0;
+            for (i, size) in sizes.enumerate() {
+                bytes += size;
+                if i >= limits.max_items || bytes > limits.max_bytes { return Some(i) }
+            }
+            None
+        }
+        fn item_bytes(item: &Xml) -> usize {
+            match item.name {
+                "item" => match item.children.first() {
+                    Some(x) => item_bytes(x),
+                    None => item.text.len(),
+                }
+                "l" => item.text.len(),
+                _ => 0,
+            }
+        }
+
+        let limits = match &self.parser.list_limits {
+            Some(x) => x,
+            None => return Ok(usize::MAX),
+        };
+        let kept = match expr.attr("struct").map(|x| &*x.value) {
+            // the serialized text is longer than its items combined and has at most one more item than its length
+            Some("atomic" | "linewise") if expr.text.len() < limits.max_items && expr.text.len() <= limits.max_bytes => None,
+            Some("atomic") => fitting_items(InlineListIter::new(&expr.text).map(|x| x.len()), limits),
+            Some("linewise") => fitting_items(expr.text.lines().map(|x| x.len()), limits),
+            _ => fitting_items(expr.children.iter().map(item_bytes), limits),
+        };
+        match kept {
+            None => Ok(usize::MAX),
+            Some(kept) if limits.truncate => {
+                self.role.warn(WarningKind::ListTruncated { kept }, location);
+                Ok(kept)
+            }
+            Some(_) => Err(Box::new_with(|| Error { kind: CompileError::ListTooLarge { max_items: limits.max_items, max_bytes: limits.max_bytes }.into(), location: location.to_owned() })),
+        }
+    }
+    /// Handles an unrecognized dropdown option, which is kept as `other(got)` if [`Parser::pass_through_unknown_options`] is enabled.
+    /// Otherwise, `error` is returned.
+    #[inline(never)]
+    fn unknown_option<V>(&self, got: &str, other: fn(CompactString) -> V, error: ErrorKind, location: &LocationRef) -> Result<V, Box<Error>> {
+        match self.parser.pass_through_unknown_options {
+            true => {
+                self.role.warn(WarningKind::UnknownOptionPassedThrough { option: got.into() }, location);
+                Ok(other(got.into()))
+            }
+            false => Err(Box::new_with(|| Error { kind: error, location: location.to_owned() })),
+        }
+    }
+    #[inline(always)] // avoid an extra stack frame per level of nesting
+    fn parse_block(&mut self, stmt: &Xml) -> Result<Vec<Stmt>, Box<Error>> {
+        match self.parse_block_inner(stmt) {
+            Ok(x) => Ok(x),
+            Err(e) => self.recover_block(stmt, e),
+        }
+    }
+    #[inline(never)]
+    fn recover_block(&mut self, stmt: &Xml, error: Box<Error>) -> Result<Vec<Stmt>, Box<Error>> {
+        match stmt.attr("s") {
+            None => Err(error),
+            Some(s) => {
+                self.tolerate(error)?;
+                let location = LocationRef { role: Some(&self.role.name), entity: Some(&self.entity.name), collab_id: get_collab_id(stmt), block_type: Some(&s.value) };
+                let (args, info) = self.parse_unknown_common(stmt, &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::UnknownBlock { name: CompactString::new(&s.value), args }, info }))
+            }
+        }
+    }
+    #[inline(never)]
+    fn parse_block_inner(&mut self, stmt: &Xml) -> Result<Vec<Stmt>, Box<Error>> {
+        let mut location = Box::new_with(|| LocationRef {
+            role: Some(&self.role.name),
+            entity: Some(&self.entity.name),
+            collab_id: get_collab_id(stmt),
+            block_type: None,
+        });
+        let s = match stmt.attr("s") {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::BlockWithoutType.into(), location: location.to_owned() })),
+            Some(v) => &*v.value,
+        };
+        location.block_type = Some(s);
+
+        if let Some(res) = self.handle_stmt(stmt, s, &location) {
+            return res;
+        }
+
+        match s {
+            "doDeclareVariables" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let mut vars = Vec::with_capacity(stmt.children[0].children.len());
+                for var in stmt.children[0].children.iter() {
+                    self.check_shadowing(&var.text, &location);
+                    let entry = self.decl_local(CompactString::new(&var.text), 0f64.into(), &location)?;
+                    vars.push(entry.def.clone());
+                }
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::DeclareLocals { vars }, info }))
+            }
+            "doSetVar" | "doChangeVar" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let var = match stmt.children[0].name {
+                    "l" => self.reference_var(&stmt.children[0].text, &location)?,
+                    _ => return Err(Box::new_with(|| Error { kind: CompileError::DerefAssignment.into(), location: location.to_owned() })),
+                };
+                let value = self.parse_expr(&stmt.children[1], &location)?;
+                match s {
+                    "doSetVar" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Assign { var: *var, value }, info })),
+                    "doChangeVar" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::AddAssign { var: *var, value }, info })),
+                    _ => unreachable!(),
+                }
+            }
+            "doShowVar" | "doHideVar" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let var = match stmt.children[0].name {
+                    "l" => self.reference_var(&stmt.children[0].text, &location)?,
+                    _ => return Err(Box::new_with(|| Error { kind: CompileError::DerefAssignment.into(), location: location.to_owned() })),
+                };
+                match s {
+                    "doShowVar" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ShowVar { var: *var }, info })),
+                    "doHideVar" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::HideVar { var: *var }, info })),
+                    _ => unreachable!(),
+                }
+            }
+            "doFor" => {
+                let info = self.check_children_get_info(stmt, 4, &location)?;
+
+                let var = match stmt.children[0].name {
+                    "l" => &*stmt.children[0].text,
+                    _ => return Err(Box::new_with(|| Error { kind: ProjectError::UpvarNotConst.into(), location: location.to_owned() })),
+                };
+                let start = self.parse_expr(&stmt.children[1], &location)?;
+                let stop = self.parse_expr(&stmt.children[2], &location)?;
+                let var = self.decl_local(CompactString::new(var), 0f64.into(), &location)?.def.ref_at(VarLocation::Local); // define after bounds, but before loop body
+                let script = self.parse(&stmt.children[3])?;
+
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ForLoop { var: *var, start, stop, stmts: script.stmts }, info }))
+            }
+            "doForEach" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+
+                let var = match stmt.children[0].name {
+                    "l" => &*stmt.children[0].text,
+                    _ => return Err(Box::new_with(|| Error { kind: ProjectError::UpvarNotConst.into(), location: location.to_owned() })),
+                };
+                let items = self.parse_expr(&stmt.children[1], &location)?;
+                let var = self.decl_local(CompactString::new(var), 0f64.into(), &location)?.def.ref_at(VarLocation::Local); // define after bounds, but before loop body
+                let script = self.parse(&stmt.children[2])?;
+
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ForeachLoop { var: *var, items, stmts: script.stmts }, info }))
+            }
+            "doRepeat" => {
+                let has_counter = stmt.children.get(1).is_some_and(|x| x.name == "l");
+                let info = self.check_children_get_info(stmt, if has_counter { 3 } else { 2 }, &location)?;
+                let times = self.parse_expr(&stmt.children[0], &location)?;
+                let counter = match has_counter {
+                    true => Some(self.decl_local(CompactString::new(&stmt.children[1].text), 0f64.into(), &location)?.def.ref_at(VarLocation::Local)), // define after count, but before loop body
+                    false => None,
+                };
+                let script = self.parse(&stmt.children[if has_counter { 2 } else { 1 }])?;
+
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Repeat { times, counter: counter.map(|x| *x), stmts: script.stmts }, info }))
+            }
+            "doIf" | "doIfElse" if is_if_chain(stmt, s == "doIfElse") => self.parse_if_chain(stmt, s == "doIfElse", &location),
+            "doUntil" | "doIf" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let expr = self.parse_expr(&stmt.children[0], &location)?;
+                let script = self.parse(&stmt.children[1])?;
+
+                match s {
+                    "doUntil" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::UntilLoop { condition: expr, stmts: script.stmts }, info })),
+                    "doIf" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::If { condition: expr, then: script.stmts }, info })),
+                    _ => unreachable!(),
+                }
+            }
+            "doForever" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let script = self.parse(&stmt.children[0])?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::InfLoop { stmts: script.stmts }, info }))
+            }
+            "doIfElse" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let condition = self.parse_expr(&stmt.children[0], &location)?;
+                let then_script = self.parse(&stmt.children[1])?;
+                let otherwise_script = self.parse(&stmt.children[2])?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::IfElse { condition, then: then_script.stmts, otherwise: otherwise_script.stmts }, info }))
+            }
+            "doTryCatch" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let code_script = self.parse(&stmt.children[0])?;
+                let var = match stmt.children[1].name {
+                    "l" => self.decl_local(CompactString::new(&stmt.children[1].text), 0f64.into(), &location)?.def.ref_at(VarLocation::Local),
+                    _ => return Err(Box::new_with(|| Error { kind: ProjectError::UpvarNotConst.into(), location: location.to_owned() })),
+                };
+                let handler_script = self.parse(&stmt.children[2])?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::TryCatch { code: code_script.stmts, var: *var, handler: handler_script.stmts }, info }))
+            }
+            "doWarp" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let script = self.parse(&stmt.children[0])?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Warp { stmts: script.stmts }, info }))
+            }
+            "doDeleteFromList" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let list = self.parse_expr(&stmt.children[1], &location)?;
+                match stmt.children[0].get(&["option"]) {
+                    Some(opt) => match &*opt.text {
+                        "last" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListRemoveLast { list }, info })),
+                        "all" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListRemoveAll { list }, info })),
+                        "" => Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                        x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                    }
+                    None => {
+                        let index = self.parse_expr(&stmt.children[0], &location)?;
+                        Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListRemove { list, index }, info }))
+                    }
+                }
+            }
+            "doInsertInList" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let value = self.parse_expr(&stmt.children[0], &location)?;
+                let list = self.parse_expr(&stmt.children[2], &location)?;
+                match stmt.children[1].get(&["option"]) {
+                    Some(opt) => match &*opt.text {
+                        "last" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListInsertLast { list, value }, info })),
+                        "random" | "any" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListInsertRandom { list, value }, info })),
+                        "" => Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                        x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                    }
+                    None => {
+                        let index = self.parse_expr(&stmt.children[1], &location)?;
+                        Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListInsert { list, value, index }, info }))
+                    }
+                }
+            }
+            "doReplaceInList" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let value = self.parse_expr(&stmt.children[2], &location)?;
+                let list = self.parse_expr(&stmt.children[1], &location)?;
+                match stmt.children[0].get(&["option"]) {
+                    Some(opt) => match &*opt.text {
+                        "last" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListAssignLast { list, value }, info })),
+                        "random" | "any" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListAssignRandom { list, value }, info })),
+                        "" => Err(Box::new_with(|| Error{ kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                        x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                    }
+                    None => {
+                        let index = self.parse_expr(&stmt.children[0], &location)?;
+                        Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ListAssign { list, value, index }, info }))
+                    }
+                }
+            }
+            "doStopThis" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let mode = match self.grab_option(&stmt.children[0], &location)? {
+                    "all" => StopMode::All,
+                    "all scenes" => StopMode::AllScenes,
+                    "this script" => StopMode::ThisScript,
+                    "this block" => StopMode::ThisBlock,
+                    "all but this script" => StopMode::AllButThisScript,
+                    "other scripts in sprite" => StopMode::OtherScriptsInSprite,
+                    x => return Err(Box::new_with(|| Error { kind: CompileError::CurrentlyUnsupported { msg: format_compact!("{s} with stop mode {x:?} is currently not supported") }.into(), location: location.to_owned() })),
+                };
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Stop { mode }, info }))
+            }
+            "doSwitchToCostume" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let val = &stmt.children[0];
+
+                if val.name == "l" && val.get(&["option"]).is_some() {
+                    match self.grab_option(val, &location)? {
+                        "Turtle" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetCostume { costume: Box::new_with(|| "".into()) }, info })),
+                        x => Err(Box::new_with(|| Error { kind: CompileError::CurrentlyUnsupported { msg: format_compact!("{s} with builtin project costume ({x}) currently not supported") }.into(), location: location.to_owned() })),
+                    }
+                } else {
+                    let costume = self.parse_expr(val, &location)?;
+                    Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetCostume { costume }, info }))
+                }
+            }
+            "playSound" | "doPlaySoundUntilDone" => {
+                let blocking = s == "doPlaySoundUntilDone";
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let sound = self.parse_expr(&stmt.children[0], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::PlaySound { sound, blocking }, info }))
+            }
+            "doPlayNote" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let notes = self.parse_expr(&stmt.children[0], &location)?;
+                let beats = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::PlayNotes { notes, beats, blocking: true }, info }))
+            }
+            "doRest" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let beats = self.parse_expr(&stmt.children[0], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Rest { beats }, info }))
+            }
+            "setHeading" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let child = &stmt.children[0];
+
+                if child.name == "l" && child.get(&["option"]).is_some() {
+                    let opt = self.grab_option(child, &location)?;
+                    match opt {
+                        "random" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetHeadingRandom, info })),
+                        _ => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: opt.into() }.into(), location: location.to_owned() })),
+                    }
+                } else {
+                    let value = self.parse_expr(child, &location)?;
+                    Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetHeading { value }, info }))
+                }
+            }
+            "doGotoObject" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let child = &stmt.children[0];
+
+                if child.name == "l" && child.get(&["option"]).is_some() {
+                    let opt = self.grab_option(child, &location)?;
+                    match opt {
+                        "random position" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::GotoRandom, info })),
+                        "mouse-pointer" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::GotoMouse, info })),
+                        "center" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::GotoXY { x: Box::new_with(|| 0f64.into()), y: Box::new_with(|| 0f64.into()) }, info })),
+                        _ => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: opt.into() }.into(), location: location.to_owned() })),
+                    }
+                }
+                else {
+                    let target = self.parse_expr(child, &location)?;
+                    Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Goto { target }, info }))
+                }
+            }
+            "doFaceTowards" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let child = &stmt.children[0];
+
+                if child.name == "l" && child.get(&["option"]).is_some() {
+                    let opt = self.grab_option(child, &location)?;
+                    match opt {
+                        "center" => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::PointTowardsXY { x: Box::new_with(|| 0.0.into()), y: Box::new_with(|| 0.0.into()) }, info })),
+                        _ => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: opt.into() }.into(), location: location.to_owned() })),
+                    }
+                } else {
+                    let target = self.parse_expr(child, &location)?;
+                    Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::PointTowards { target }, info }))
+                }
+            }
+            "setColor" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                match stmt.get(&["color"]) {
+                    Some(color) => match parse_color(&color.text) {
+                        Some(color) => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetPenColor { color }, info })),
+                        None => Err(Box::new_with(|| Error { kind: ProjectError::ColorUnknownValue { color: CompactString::new(&color.text) }.into(), location: location.to_owned() })),
+                    }
+                    None => Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() })),
+                }
+            }
+            "doSocketMessage" => {
+                let res = self.parse_send_message_common(stmt, &location)?;
+                Ok(Vec::new_with_single(|| {
+                    let NetworkMessage { target, msg_type, values, info } = *res;
+                    Stmt { kind: StmtKind::SendNetworkMessage { target, msg_type, values }, info }
+                }))
+            }
+            "doRun" | "fork" => {
+                let is_run = s == "doRun";
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let closure = self.parse_expr(&stmt.children[0], &location)?;
+                let mut args = Vec::with_capacity(stmt.children[1].children.len());
+                for arg in stmt.children[1].children.iter() {
+                    args.push_boxed(self.parse_expr(arg, &location)?);
+                }
+                match is_run {
+                    true => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::CallClosure { new_entity: None, closure, args }, info })),
+                    false => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ForkClosure { closure, args }, info })),
+                }
+            }
+            "doTellTo" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let entity = self.grab_entity(&stmt.children[0], BlockInfo::none(), &location)?;
+                let closure = self.parse_expr(&stmt.children[1], &location)?;
+                let mut args = Vec::with_capacity(stmt.children[2].children.len());
+                for arg in stmt.children[2].children.iter() {
+                    args.push_boxed(self.parse_expr(arg, &location)?);
+                }
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::CallClosure { new_entity: Some(entity), closure, args }, info }))
+            }
+            "setEffect" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let effect = self.parse_effect(&stmt.children[0], &location)?;
+                let value = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetEffect { kind: effect, value }, info }))
+            }
+            "doSetGlobalFlag" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let flag = self.parse_global_flag(&stmt.children[0], &location)?;
+                let value = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetGlobalFlag { flag, value }, info }))
+            }
+            "doSetFastTracking" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetGlobalFlag { flag: GlobalFlag::TurboMode, value }, info: info.lowered_from("doSetFastTracking") })),
+            "doSetTempo" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetTempo { value }, info })),
+            "doChangeTempo" => self.parse_1_args(stmt, &location).map(|(delta, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ChangeTempo { delta }, info })),
+            "changeEffect" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let effect = self.parse_effect(&stmt.children[0], &location)?;
+                let delta = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ChangeEffect { kind: effect, delta }, info }))
+            }
+            "setPenHSVA" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let attr = self.parse_pen_attr(&stmt.children[0], &location)?;
+                let value = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetPenAttr { attr, value }, info }))
+            }
+            "changePenHSVA" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let attr = self.parse_pen_attr(&stmt.children[0], &location)?;
+                let delta = self.parse_expr(&stmt.children[1], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::ChangePenAttr { attr, delta }, info }))
+            }
+            "doRunRPC" => {
+                let rpc = self.parse_rpc(stmt, &location)?;
+                Ok(Vec::new_with_single(|| (*rpc).into()))
+            }
+            "createClone" => {
+                let info = self.check_children_get_info(stmt, 1, &location)?;
+                let target = self.grab_entity(&stmt.children[0], BlockInfo::none(), &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::Clone { target }, info }))
+            }
+            "doSend" => {
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let msg_type = self.parse_expr(&stmt.children[0], &location)?;
+                let target = Some(self.grab_entity(&stmt.children[1], BlockInfo::none(), &location)?);
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SendLocalMessage { msg_type, target, wait: false }, info }))
+            }
+            "doStopAllSounds" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::StopSounds, info })),
+            "doBroadcast" => self.parse_1_args(stmt, &location).map(|(msg_type, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SendLocalMessage { msg_type, target: None, wait: false }, info })),
+            "doBroadcastAndWait" => self.parse_1_args(stmt, &location).map(|(msg_type, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SendLocalMessage { msg_type, target: None, wait: true }, info })),
+            "doPauseAll" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::Pause, info })),
+            "write" => self.parse_2_args(stmt, &location).map(|(content, font_size, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Write { content, font_size }, info })),
+            "doSocketResponse" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SendNetworkReply { value }, info })),
+            "changeScale" => self.parse_1_args(stmt, &location).map(|(delta, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ChangeSize { delta, }, info })),
+            "setScale" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetSize { value }, info })),
+            "doSayFor" => self.parse_2_args(stmt, &location).map(|(content, duration, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Say { content, duration: Some(duration) }, info })),
+            "doThinkFor" => self.parse_2_args(stmt, &location).map(|(content, duration, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Think { content, duration: Some(duration) }, info })),
+            "bubble" => self.parse_1_args(stmt, &location).map(|(content, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Say { content, duration: None }, info })),
+            "doThink" => self.parse_1_args(stmt, &location).map(|(content, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Think { content, duration: None }, info })),
+            "doThrow" => self.parse_1_args(stmt, &location).map(|(error, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Throw { error }, info })),
+            "hide" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::SetVisible { value: false }, info })),
+            "show" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::SetVisible { value: true }, info })),
+            "removeClone" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::DeleteClone, info })),
+            "doWaitUntil" => self.parse_1_args(stmt, &location).map(|(condition, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::WaitUntil { condition, }, info })),
+            "changeSize" => self.parse_1_args(stmt, &location).map(|(delta, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ChangePenSize { delta, }, info })),
+            "setSize" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetPenSize { value }, info })),
+            "doDefineBlock" => {
+                self.check_metaprogramming(&location)?;
+                let info = self.check_children_get_info(stmt, 2, &location)?;
+                let var = match stmt.children[0].name {
+                    "l" => &*stmt.children[0].text,
+                    _ => return Err(Box::new_with(|| Error { kind: ProjectError::UpvarNotConst.into(), location: location.to_owned() })),
+                };
+                let definition = self.parse_expr(&stmt.children[1], &location)?;
+                let var = self.decl_local(CompactString::new(var), 0f64.into(), &location)?.def.ref_at(VarLocation::Local); // define after definition
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::DefineBlock { var: *var, definition }, info }))
+            }
+            "doSetBlockAttribute" => {
+                self.check_metaprogramming(&location)?;
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let attr = self.parse_expr(&stmt.children[0], &location)?;
+                let block = self.parse_expr(&stmt.children[1], &location)?;
+                let value = self.parse_expr(&stmt.children[2], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::SetBlockAttribute { attr, block, value }, info }))
+            }
+            "doMapCodeOrHeader" => {
+                let info = self.check_children_get_info(stmt, 3, &location)?;
+                let block = self.parse_expr(&stmt.children[0], &location)?;
+                let kind = match self.grab_option(&stmt.children[1], &location)? {
+                    "code" => CodeKind::Code,
+                    "header" => CodeKind::Header,
+                    x => return Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                };
+                let code = self.parse_expr(&stmt.children[2], &location)?;
+                Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::MapCode { block, kind, code }, info }))
+            }
+            "doAddToList" => self.parse_2_args(stmt, &location).map(|(value, list, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ListInsertLast { value, list }, info })),
+            "doReport" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Return { value }, info })),
+            "doStamp" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::Stamp, info })),
+            "doWait" => self.parse_1_args(stmt, &location).map(|(seconds, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Sleep { seconds, }, info })),
+            "forward" => self.parse_1_args(stmt, &location).map(|(distance, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Forward { distance, }, info })),
+            "turn" => self.parse_1_args(stmt, &location).map(|(angle, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::TurnRight { angle, }, info })),
+            "turnLeft" => self.parse_1_args(stmt, &location).map(|(angle, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::TurnLeft { angle, }, info })),
+            "setXPosition" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetX { value }, info })),
+            "setYPosition" => self.parse_1_args(stmt, &location).map(|(value, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::SetY { value }, info })),
+            "changeXPosition" => self.parse_1_args(stmt, &location).map(|(delta, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ChangeX { delta }, info })),
+            "changeYPosition" => self.parse_1_args(stmt, &location).map(|(delta, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::ChangeY { delta }, info })),
+            "gotoXY" => self.parse_2_args(stmt, &location).map(|(x, y, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::GotoXY { x, y }, info })),
+            "bounceOffEdge" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::BounceOffEdge, info })),
+            "down" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::SetPenDown { value: true }, info })),
+            "up" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::SetPenDown { value: false }, info })),
+            "clear" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::PenClear, info })),
+            "doAsk" => self.parse_1_args(stmt, &location).map(|(prompt, info)| Vec::new_with_single(|| Stmt { kind: StmtKind::Ask { prompt, }, info })),
+            "doResetTimer" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::ResetTimer, info })),
+            "clearEffects" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::ClearEffects, info })),
+            "doWearNextCostume" => self.parse_0_args(stmt, &location).map(|info| Vec::new_with_single(|| Stmt { kind: StmtKind::NextCostume, info })),
+            x => {
+                let (args, info) = self.parse_unknown_common(stmt, &location)?;
+                match self.parser.stmt_replacements.iter().find(|r| r.0 == x) {
+                    Some(f) => f.1(args, info, &location),
+                    None => Ok(Vec::new_with_single(|| Stmt { kind: StmtKind::UnknownBlock { name: x.into(), args }, info }))
+                }
+            }
+        }
+    }
+    /// Parses a block used as a command with [`Parser::block_handlers`], if there is a handler for it.
+    #[inline(never)]
+    fn handle_stmt(&mut self, stmt: &Xml, s: &str, location: &LocationRef) -> Option<Result<Vec<Stmt>, Box<Error>>> {
+        let parser = self.parser;
+        let handler = parser.block_handlers.iter().find(|x| x.0 == s)?.1.stmt.as_ref()?;
+        Some(self.parse_unknown_common(stmt, location).and_then(|(args, info)| handler(args, info.lowered_from(s), &mut BlockContext { location, script: self })))
+    }
+    /// Parses a block used as a reporter with [`Parser::block_handlers`], if there is a handler for it.
+    #[inline(never)]
+    fn handle_expr(&mut self, expr: &Xml, s: &str, location: &LocationRef) -> Option<Result<Box<Expr>, Box<Error>>> {
+        let parser = self.parser;
+        let handler = parser.block_handlers.iter().find(|x| x.0 == s)?.1.expr.as_ref()?;
+        Some(self.parse_unknown_common(expr, location).and_then(|(args, info)| handler(args, info.lowered_from(s), &mut BlockContext { location, script: self })))
+    }
+    /// Parses a hat block with [`Parser::block_handlers`], if there is a handler for it.
+    #[inline(never)]
+    fn handle_hat(&mut self, hat: &Xml, s: &str, location: &LocationRef) -> Option<Result<Box<Hat>, Box<Error>>> {
+        let parser = self.parser;
+        let handler = parser.block_handlers.iter().find(|x| x.0 == s)?.1.hat.as_ref()?;
+        Some(self.parse_unknown_common(hat, location).and_then(|(args, info)| handler(args, info.lowered_from(s), &mut BlockContext { location, script: self })))
+    }
+    #[inline(never)]
+    fn reference_var(&mut self, name: &str, location: &LocationRef) -> Result<Box<VariableRef>, Box<Error>> {
+        for (i, locals) in self.locals.iter().rev().enumerate() {
+            if let Some(x) = locals.0.get(name) {
+                let local_ref = x.def.ref_at(VarLocation::Local);
+                if i != 0 {
+                    let (locals, captures) = self.locals.last_mut().unwrap();
+                    locals.define(local_ref.name.clone(), 0.0.into()).unwrap();
+                    captures.push_boxed(local_ref.clone());
+                }
+                return Ok(local_ref);
+            }
+        }
+        if let Some(x) = self.entity.fields.get(name) {
+            let field_ref = x.def.ref_at(VarLocation::Field);
+            if self.locals.len() >= 2 {
+                let (locals, captures) = self.locals.last_mut().unwrap();
+                locals.define(field_ref.name.clone(), 0.0.into()).unwrap();
+                captures.push_boxed(field_ref);
+                return Ok(x.def.ref_at(VarLocation::Local));
+            } else {
+                return Ok(field_ref);
+            }
+        }
+        if let Some(x) = self.role.globals.get(name) {
+            return Ok(x.def.ref_at(VarLocation::Global));
+        }
+        Err(Box::new_with(|| Error { kind: CompileError::UndefinedVariable { name: name.into() }.into(), location: location.to_owned() }))
+    }
+    #[inline(never)]
+    fn reference_fn(&self, name: &str, location: &LocationRef) -> Result<Box<(FnRef, Value)>, Box<Error>> {
+        let locs = [(&self.entity.funcs, FnLocation::Method), (&self.role.funcs, FnLocation::Global)];
+        match locs.iter().find_map(|v| v.0.get(name).map(|x| (v, x))) {
+            Some((v, x)) => Ok(Box::new_with(|| (*x.def.fn_ref_at(v.1), x.init.clone()))),
+            None => Err(Box::new_with(|| Error { kind: CompileError::UndefinedFn { name: name.into() }.into(), location: location.to_owned() }))
+        }
+    }
+    #[inline(always)]
+    fn parse_0_args(&mut self, expr: &Xml, location: &LocationRef) -> Result<Box<BlockInfo>, Box<Error>> {
+        self.check_children_get_info(expr, 0, location)
+    }
+    /// Parses a (Snap 9) variadic if block, whose else-if branches are desugared into nested [`StmtKind::IfElse`] blocks.
+    #[inline(never)]
+    fn parse_if_chain(&mut self, stmt: &Xml, has_else: bool, location: &LocationRef) -> Result<Vec<Stmt>, Box<Error>> {
+        let info = self.check_children_get_info(stmt, if has_else { 4 } else { 3 }, location)?;
+        let branches = if_chain_branches(stmt, location)?;
+
+        let mut conditions = vec![self.parse_expr(&stmt.children[0], location)?];
+        let mut thens = vec![self.parse(&stmt.children[1])?.stmts];
+        for branch in branches {
+            conditions.push(self.parse_expr(&branch[0], location)?);
+            thens.push(self.parse(&branch[1])?.stmts);
+        }
+        let mut otherwise = match has_else {
+            true => Some(self.parse(&stmt.children[3])?.stmts),
+            false => None,
+        };
+
+        let opcode = stmt.attr("s").map(|x| &*x.value).unwrap_or_default();
+        while let (Some(condition), Some(then)) = (conditions.pop(), thens.pop()) {
+            let kind = match otherwise.take() {
+                Some(otherwise) => StmtKind::IfElse { condition, then, otherwise },
+                None => StmtKind::If { condition, then },
+            };
+            otherwise = Some(vec![Stmt { kind, info: BlockInfo::none().lowered_from(opcode) }]);
+        }
+        let mut res = otherwise.unwrap();
+        res[0].info = info.lowered_from(opcode);
+        Ok(res)
+    }
+    /// Parses a (Snap 9) variadic [`ExprKind::Conditional`], whose else-if branches are desugared into nested conditionals.
+    #[inline(never)]
+    fn parse_conditional_chain(&mut self, expr: &Xml, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        let info = self.check_children_get_info(expr, 4, location)?;
+        let branches = if_chain_branches(expr, location)?;
+
+        let mut pairs = vec![(self.parse_expr(&expr.children[0], location)?, self.parse_expr(&expr.children[1], location)?)];
+        for branch in branches {
+            pairs.push((self.parse_expr(&branch[0], location)?, self.parse_expr(&branch[1], location)?));
+        }
+        let mut otherwise = self.parse_expr(&expr.children[3], location)?;
+        while let Some((condition, then)) = pairs.pop() {
+            otherwise = Box::new_with(|| Expr { kind: ExprKind::Conditional { condition, then, otherwise }, info: BlockInfo::none().lowered_from("reportIfElse") });
+        }
+        otherwise.info = info.lowered_from("reportIfElse");
+        Ok(otherwise)
+    }
+    #[inline(always)]
+    fn parse_1_args(&mut self, expr: &Xml, location: &LocationRef) -> Result<(Box<Expr>, Box<BlockInfo>), Box<Error>> {
+        let info = self.check_children_get_info(expr, 1, location)?;
+        let a = self.parse_expr(&expr.children[0], location)?;
+        Ok((a, info))
+    }
+    #[inline(always)]
+    fn parse_2_args(&mut self, expr: &Xml, location: &LocationRef) -> Result<(Box<Expr>, Box<Expr>, Box<BlockInfo>), Box<Error>> {
+        let info = self.check_children_get_info(expr, 1, location)?;
+        let a = self.parse_expr(&expr.children[0], location)?;
+        let b = self.parse_expr(&expr.children[1], location)?;
+        Ok((a, b, info))
+    }
+    #[inline(never)]
+    fn parse_bool(&self, val: &str, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        match val {
+            "true" => Ok(Box::new_with(|| true.into())),
+            "false" => Ok(Box::new_with(|| false.into())),
+            _ => Err(Box::new_with(|| Error { kind: ProjectError::BoolUnknownValue { got: val.into() }.into(), location: location.to_owned() })),
+        }
+    }
+    #[inline(never)]
+    fn parse_color_value(&self, val: &str, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        match parse_color(val) {
+            Some((r, g, b, a)) => Ok(Box::new_with(|| Value::Color { r, g, b, a }.into())),
+            None => Err(Box::new_with(|| Error { kind: ProjectError::ColorUnknownValue { color: val.into() }.into(), location: location.to_owned() })),
+        }
+    }
+    #[inline(never)]
+    fn parse_empty_slot(&self, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        match self.parser.empty_slots {
+            EmptySlots::EmptyString => Ok(Box::new_with(|| "".into())),
+            EmptySlots::Marker => Ok(Box::new_with(|| Expr { kind: ExprKind::EmptySlot, info: BlockInfo::none() })),
+            EmptySlots::Error => Err(Box::new_with(|| Error { kind: CompileError::EmptySlot.into(), location: location.to_owned() })),
+        }
+    }
+    /// Parses a variadic and/or block, which is desugared into a (left-associative) chain of short-circuiting [`ExprKind::And`] or [`ExprKind::Or`] nodes.
+    /// If the input is not a literal list of slots (e.g., a list-valued variable was dropped in), the block is kept as an [`ExprKind::UnknownBlock`].
+    #[inline(never)]
+    fn parse_variadic_bool(&mut self, expr: &Xml, is_and: bool, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        let info = self.check_children_get_info(expr, 1, location)?;
+        let opcode = if is_and { "reportVariadicAnd" } else { "reportVariadicOr" };
+        if expr.children[0].name != "list" {
+            let args = vec![*self.parse_expr(&expr.children[0], location)?];
+            return Ok(Box::new_with(|| Expr { kind: ExprKind::UnknownBlock { name: opcode.into(), args }, info }));
+        }
+
+        let mut res: Option<Box<Expr>> = None;
+        for item in expr.children[0].children.iter() {
+            let right = self.parse_expr(item, location)?;
+            res = Some(match res {
+                None => right,
+                Some(left) => Box::new_with(|| Expr { kind: if is_and { ExprKind::And { left, right } } else { ExprKind::Or { left, right } }, info: BlockInfo::none().lowered_from(opcode) }),
+            });
+        }
+        match res {
+            // a single item is the user's own block, so it keeps its own info (but not at the cost of losing the comment on the outer block)
+            Some(mut res) if expr.children[0].children.len() == 1 => {
+                if res.info.comment.is_none() {
+                    res.info.comment = info.comment;
+                }
+                Ok(res)
+            }
+            res => {
+                let mut res = res.unwrap_or_else(|| Box::new_with(|| is_and.into()));
+                res.info = info.lowered_from(opcode);
+                Ok(res)
+            }
+        }
+    }
+    #[inline(never)]
+    fn parse_js_function(&mut self, expr: &Xml, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        let info = self.check_children_get_info(expr, 2, location)?;
+        let not_const = || Box::new_with(|| Error { kind: CompileError::BlockOptionNotConst.into(), location: location.to_owned() });
+        let mut params = Vec::with_capacity(expr.children[0].children.len());
+        for param in expr.children[0].children.iter() {
+            if param.name != "l" { return Err(not_const()) }
+            params.push(CompactString::new(&param.text));
+        }
+        if expr.children[1].name != "l" { return Err(not_const()) }
+        let body = CompactString::new(&expr.children[1].text);
+        Ok(Box::new_with(|| Expr { kind: ExprKind::JsFunction { params, body }, info }))
+    }
+    #[inline(never)]
+    fn parse_closure(&mut self, expr: &Xml, kind: ClosureKind, inline_script: bool, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        let (info, script) = match inline_script {
+            false => (self.check_children_get_info(expr, 2, location)?, &expr.children[0]),
+            true => (BlockInfo::none(), expr),
+        };
+
+        let mut params = SymbolTable::new(self.parser);
+        fn define_param<T: NameTransformer>(params: &mut SymbolTable<T>, name: CompactString, location: &LocationRef) -> Result<(), Box<Error>> {
+            match params.define(name, 0.0.into()) {
+                Ok(None) => Ok(()),
+                Ok(Some(prev)) => Err(Box::new_with(|| Error { kind: CompileError::InputsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                Err(SymbolError::ConflictingTrans { trans_name, names }) => Err(Box::new_with(|| Error { kind: CompileError::LocalsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                Err(SymbolError::NameTransformError { name }) => Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+            }
+        }
+        if !inline_script {
+            for input in expr.children[1].children.iter() {
+                define_param(&mut params, CompactString::new(&input.text), location)?;
+            }
+        }
+
+        let prev_autofill_args_len = self.autofill_args.as_ref().map(|x| x.len()).unwrap_or_default();
+        let prev_autofill_args = match params.is_empty() && !inline_script {
+            true => Some(mem::replace(&mut self.autofill_args, Some(vec![]))),
+            false => None,
+        };
+
+        self.locals.push((params.clone(), Default::default()));
+        let locals_len = self.locals.len();
+        let stmts = match kind {
+            ClosureKind::Command => self.parse(script)?.stmts,
+            ClosureKind::Reporter | ClosureKind::Predicate => {
+                let value = match script.name {
+                    "autolambda" => {
+                        let _ = self.check_children_get_info(script, 1, location)?;
+                        self.parse_expr(&script.children[0], location)?
+                    }
+                    _ => self.parse_expr(script, location)?,
+                };
+                Vec::new_with_single(|| Stmt { kind: StmtKind::Return { value }, info: BlockInfo::none() })
+            }
+        };
+        assert_eq!(locals_len, self.locals.len());
+        let (_, mut captures) = self.locals.pop().unwrap();
+        for var in captures.iter() {
+            self.reference_var(&var.name, location)?;
+        }
+
+        match prev_autofill_args {
+            Some(prev_autofill_args) => for autofill_arg in mem::replace(&mut self.autofill_args, prev_autofill_args).unwrap_or_default() {
+                define_param(&mut params, autofill_arg.name, location)?;
+            }
+            None => for autofill_arg in self.autofill_args.as_deref().map(|x| &x[prev_autofill_args_len..]).unwrap_or_default() {
+                captures.push(autofill_arg.clone());
+            }
+        }
+
+        Ok(Box::new_with(|| Expr { kind: ExprKind::Closure { params: params.into_defs(), captures, kind, stmts }, info }))
+    }
+    #[inline(always)] // avoid an extra stack frame per level of nesting
+    fn parse_expr(&mut self, expr: &Xml, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        match self.parse_expr_inner(expr, location) {
+            Ok(x) => Ok(x),
+            Err(e) => self.recover_expr(expr, location, e),
+        }
+    }
+    #[inline(never)]
+    fn recover_expr(&mut self, expr: &Xml, location: &LocationRef, error: Box<Error>) -> Result<Box<Expr>, Box<Error>> {
+        match expr.attr("s") {
+            Some(s) if expr.name == "block" => {
+                self.tolerate(error)?;
+                let location = LocationRef { collab_id: get_collab_id(expr).or(location.collab_id), block_type: Some(&s.value), ..*location };
+                let (args, info) = self.parse_unknown_common(expr, &location)?;
+                Ok(Box::new_with(|| Expr { kind: ExprKind::UnknownBlock { name: CompactString::new(&s.value), args }, info }))
+            }
+            _ => Err(error),
+        }
+    }
+    #[inline(never)]
+    fn parse_expr_inner(&mut self, expr: &Xml, location: &LocationRef) -> Result<Box<Expr>, Box<Error>> {
+        let mut location = Box::new_with(|| LocationRef {
+            role: location.role,
+            entity: location.entity,
+            collab_id: get_collab_id(expr).or(location.collab_id),
+            block_type: location.block_type,
+        });
+
+        match expr.name {
+            "l" => match expr.children.first() {
+                Some(child) if child.name == "bool" => self.parse_bool(&child.text, &location),
+                Some(child) if child.name == "option" => Ok(Box::new_with(|| Expr { kind: ExprKind::Value(CompactString::new(&child.text).into()), info: BlockInfo::none() })),
+                _ => match self.autofill_args.as_mut() {
+                    Some(autofill_args) if expr.text.is_empty() => {
+                        let var = Box::try_new_with(|| {
+                            let input = autofill_args.len() + 1;
+                            let name = match self.parser.autofill_generator.as_ref()(input) {
+                                Ok(x) => x,
+                                Err(()) => return Err(Box::new_with(|| Error { kind: CompileError::AutofillGenerateError { input }.into(), location: location.to_owned() })),
+                            };
+                            let trans_name = match self.parser.name_transformer.transform_name(&name) {
+                                Ok(x) => x,
+                                Err(()) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                            };
+                            Ok(VariableRef { name, trans_name, location: VarLocation::Local, slot: None })
+                        })?;
+
+                        autofill_args.push_with(|| (*var).clone());
+
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::Variable { var: *var }, info: BlockInfo::none() }))
+                    }
+                    _ if expr.text.is_empty() => self.parse_empty_slot(&location),
+                    _ => Ok(Box::new_with(|| CompactString::new(&expr.text).into())),
+                }
+            }
+            "bool" => self.parse_bool(&expr.text, &location),
+            "color" => self.parse_color_value(&expr.text, &location),
+            "list" => {
+                let ref_id = expr.attr("id").and_then(|x| x.value.parse().ok()).map(RefId);
+                let kept = self.check_list_limits(expr, &location)?;
+                let values = match expr.attr("struct").map(|x| &*x.value) {
+                    Some("atomic") => match self.parser.defer_atomic_lists {
+                        Some(min_len) if kept == usize::MAX && expr.text.len() >= min_len => return Ok(Box::new_with(|| Value::RawList(RawList::new(CompactString::new(&expr.text)), ref_id).into())),
+                        _ => parse_atomic_list(&expr.text, kept),
+                    }
+                    Some("linewise") => parse_linewise_list(&expr.text, kept),
+                    _ => {
+                        let items = &expr.children[..expr.children.len().min(kept)];
+                        let mut values = Vec::with_capacity(items.len());
+                        for item in items {
+                            values.push_boxed(match item.name {
+                                "item" => match item.children.get(0) {
+                                    Some(x) => self.parse_expr(x, &location)?,
+                                    None => Box::new_with(|| Expr { kind: ExprKind::Value(Value::String(CompactString::new(&item.text))), info: BlockInfo::none() }),
+                                }
+                                _ => self.parse_expr(item, &location)?,
+                            });
+                        }
+                        values
+                    }
+                };
+
+                let mut evaluated = Vec::with_capacity(values.len());
+                for value in values.iter() {
+                    match &value.kind {
+                        ExprKind::Value(x) => evaluated.push_with(|| x.clone()),
+                        _ => match ref_id {
+                            Some(_) => return Err(Box::new_with(|| Error { kind: ProjectError::ValueNotEvaluated.into(), location: location.to_owned() })),
+                            None => return Ok(Box::new_with(|| Expr { kind: ExprKind::MakeList { values }, info: BlockInfo::none() })),
+                        }
+                    }
+                }
+                if self.parser.share_list_strings { share_strings(&mut evaluated) }
+                Ok(Box::new_with(|| Value::List(evaluated, ref_id).into()))
+            }
+            "ref" => match expr.attr("id").and_then(|x| x.value.parse().ok()).map(RefId) {
+                Some(ref_id) => Ok(Box::new_with(|| Value::Ref(ref_id).into())),
+                None => return Err(Box::new_with(|| Error { kind: ProjectError::RefMissingId.into(), location: location.to_owned() })),
+            }
+            "custom-block" => {
+                let res = self.parse_fn_call(expr, &location)?;
+                Ok(Box::new_with(|| {
+                    let FnCall { function, args, upvars, info } = *res;
+                    Expr { kind: ExprKind::CallFn { function, args, upvars }, info }
+                }))
+            }
+            "script" => self.parse_closure(expr, ClosureKind::Command, true, &location),
+            "block" => {
+                if let Some(var) = expr.attr("var") {
+                    let info = self.check_children_get_info(expr, 0, &location)?;
+                    let var = self.reference_var(&var.value, &location)?;
+                    return Ok(Box::new_with(|| Expr { kind: ExprKind::Variable { var: *var }, info }));
+                }
+                let s = match expr.attr("s") {
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::BlockWithoutType.into(), location: location.to_owned() })),
+                    Some(v) => &*v.value,
+                };
+                location.block_type = Some(s);
+
+                if let Some(res) = self.handle_expr(expr, s, &location) {
+                    return res;
+                }
+
+                match s {
+                    "reportVariadicSum" => self.parse_1_args(expr, &location).map(|(values, info)| Box::new_with(|| Expr { kind: ExprKind::Add { values }, info })),
+                    "reportVariadicProduct" => self.parse_1_args(expr, &location).map(|(values, info)| Box::new_with(|| Expr { kind: ExprKind::Mul { values }, info })),
+                    "reportVariadicMin" => self.parse_1_args(expr, &location).map(|(values, info)| Box::new_with(|| Expr { kind: ExprKind::Min { values }, info })),
+                    "reportVariadicMax" => self.parse_1_args(expr, &location).map(|(values, info)| Box::new_with(|| Expr { kind: ExprKind::Max { values }, info })),
+
+                    "reportSum" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Add { values: Box::new_with(|| Expr { kind: ExprKind::MakeList { values: vec![*left, *right] }, info: BlockInfo::none() }) }, info: info.lowered_from("reportSum") })),
+                    "reportProduct" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Mul { values: Box::new_with(|| Expr { kind: ExprKind::MakeList { values: vec![*left, *right] }, info: BlockInfo::none() }) }, info: info.lowered_from("reportProduct") })),
+                    "reportMin" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Min { values: Box::new_with(|| Expr { kind: ExprKind::MakeList { values: vec![*left, *right] }, info: BlockInfo::none() }) }, info: info.lowered_from("reportMin") })),
+                    "reportMax" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Max { values: Box::new_with(|| Expr { kind: ExprKind::MakeList { values: vec![*left, *right] }, info: BlockInfo::none() }) }, info: info.lowered_from("reportMax") })),
+
+                    "reportDifference" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Sub { left, right }, info })),
+                    "reportQuotient" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Div { left, right }, info })),
+                    "reportModulus" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Mod { left, right }, info })),
+                    "reportPower" => self.parse_2_args(expr, &location).map(|(base, power, info)| Box::new_with(|| Expr { kind: ExprKind::Pow { base, power }, info })),
+                    "reportAtan2" => self.parse_2_args(expr, &location).map(|(y, x, info)| Box::new_with(|| Expr { kind: ExprKind::Atan2 { y, x }, info })),
+
+                    "reportAnd" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::And { left, right }, info })),
+                    "reportOr" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Or { left, right }, info })),
+                    "reportVariadicAnd" | "reportVariadicOr" => self.parse_variadic_bool(expr, s == "reportVariadicAnd", &location),
+
+                    "reportIsIdentical" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Identical { left, right }, info })),
+                    "reportEquals" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Eq { left, right }, info })),
+                    "reportNotEquals" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Neq { left, right }, info })),
+                    "reportLessThan" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Less { left, right }, info })),
+                    "reportLessThanOrEquals" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::LessEq { left, right }, info })),
+                    "reportGreaterThan" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::Greater { left, right }, info })),
+                    "reportGreaterThanOrEquals" => self.parse_2_args(expr, &location).map(|(left, right, info)| Box::new_with(|| Expr { kind: ExprKind::GreaterEq { left, right }, info })),
+
+                    "reportRandom" => self.parse_2_args(expr, &location).map(|(a, b, info)| Box::new_with(|| Expr { kind: ExprKind::Random { a, b }, info })),
+                    "reportNumbers" => self.parse_2_args(expr, &location).map(|(start, stop, info)| Box::new_with(|| Expr { kind: ExprKind::Range { start, stop }, info })),
+
+                    "reportNot" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::Not { value }, info })),
+                    "reportRound" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::Round { value }, info })),
+
+                    "reportListLength" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::ListLen { value }, info })),
+                    "reportListIsEmpty" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::ListIsEmpty { value }, info })),
+
+                    "reportStringSize" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::StrLen { value }, info })),
+                    "reportUnicodeAsLetter" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::UnicodeToChar { value }, info })),
+                    "reportUnicode" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::CharToUnicode { value }, info })),
+
+                    "reportCDR" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::ListCdr { value }, info })),
+                    "reportCONS" => self.parse_2_args(expr, &location).map(|(item, list, info)| Box::new_with(|| Expr { kind: ExprKind::ListCons { item, list }, info })),
+
+                    "reportJoinWords" => self.parse_1_args(expr, &location).map(|(values, info)| Box::new_with(|| Expr { kind: ExprKind::StrCat { values }, info })),
+                    "reportConcatenatedLists" => self.parse_1_args(expr, &location).map(|(lists, info)| Box::new_with(|| Expr { kind: ExprKind::ListCat { lists }, info })),
+                    "reportCrossproduct" => self.parse_1_args(expr, &location).map(|(sources, info)| Box::new_with(|| Expr { kind: ExprKind::ListCombinations { sources }, info })),
+
+                    "reportStageWidth" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::StageWidth, info })),
+                    "reportStageHeight" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::StageHeight, info })),
+
+                    "reportMouseX" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::MouseX, info })),
+                    "reportMouseY" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::MouseY, info })),
+
+                    "reportLatitude" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Latitude, info })),
+                    "reportLongitude" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Longitude, info })),
+
+                    "reportKeyPressed" => self.parse_1_args(expr, &location).map(|(key, info)| Box::new_with(|| Expr { kind: ExprKind::KeyDown { key }, info })),
+
+                    "reportPenTrailsAsCostume" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::ImageOfDrawings, info })),
+
+                    "reportListContainsItem" => self.parse_2_args(expr, &location).map(|(list, value, info)| Box::new_with(|| Expr { kind: ExprKind::ListContains { list, value }, info })),
+
+                    "reportRPCError" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::RpcError, info })),
+
+                    "getScale" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Size, info })),
+                    "reportShown" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::IsVisible, info })),
+
+                    "xPosition" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::XPos, info })),
+                    "yPosition" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::YPos, info })),
+                    "direction" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Heading, info })),
+
+                    "getPenDown" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::PenDown, info })),
+
+                    "getLastAnswer" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Answer, info })),
+                    "getLastMessage" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Message, info })),
+
+                    "getTimer" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Timer, info })),
+                    "getTempo" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::Tempo, info })),
+
+                    "reportMap" => self.parse_2_args(expr, &location).map(|(f, list, info)| Box::new_with(|| Expr { kind: ExprKind::Map { f, list }, info })),
+                    "reportKeep" => self.parse_2_args(expr, &location).map(|(f, list, info)| Box::new_with(|| Expr { kind: ExprKind::Keep { f, list }, info })),
+                    "reportFindFirst" => self.parse_2_args(expr, &location).map(|(f, list, info)| Box::new_with(|| Expr { kind: ExprKind::FindFirst { f, list }, info })),
+                    "reportCombine" => self.parse_2_args(expr, &location).map(|(list, f, info)| Box::new_with(|| Expr { kind: ExprKind::Combine { list, f }, info })),
+                    "reportBlockAttribute" => {
+                        self.check_metaprogramming(&location)?;
+                        self.parse_2_args(expr, &location).map(|(attr, block, info)| Box::new_with(|| Expr { kind: ExprKind::BlockAttribute { attr, block }, info }))
+                    }
+                    "reportMappedCode" => self.parse_1_args(expr, &location).map(|(value, info)| Box::new_with(|| Expr { kind: ExprKind::MappedCode { value }, info })),
+                    "reportPipe" => self.parse_2_args(expr, &location).map(|(value, functions, info)| Box::new_with(|| Expr { kind: ExprKind::Pipe { value, functions }, info })),
+
+                    "reifyScript" => self.parse_closure(expr, ClosureKind::Command, false, &location),
+                    "reifyReporter" => self.parse_closure(expr, ClosureKind::Reporter, false, &location),
+                    "reportJSFunction" => self.parse_js_function(expr, &location),
+                    "reifyPredicate" => self.parse_closure(expr, ClosureKind::Predicate, false, &location),
+
+                    "getCostumeIdx" => self.parse_0_args(expr, &location).map(|info| Box::new_with(|| Expr { kind: ExprKind::CostumeNumber, info })),
+
+                    "reportNewList" => {
+                        let (mut list, info) = self.parse_1_args(expr, &location)?;
+                        let already_owning = match &list.kind {
+                            ExprKind::Value(Value::List( .. ) | Value::RawList( .. )) => true,
+                            ExprKind::MakeList { .. } => true,
+                            _ => false,
+                        };
+                        Ok(match already_owning {
+                            true => {
+                                list.info = info;
+                                list
+                            }
+                            false => Box::new_with(|| Expr { kind: ExprKind::CopyList { list }, info }),
+                        })
+                    }
+
+                    "reportGetImageAttribute" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let costume = if expr.children[1].name == "l" && expr.children[1].get(&["option"]).is_some() {
+                            match self.grab_option(&expr.children[1], &location)? {
+                                "Turtle" => Box::new_with(|| Expr { kind: ExprKind::Value(Value::String(CompactString::default())), info: BlockInfo::none() }),
+                                "current" => Box::new_with(|| Expr { kind: ExprKind::Costume, info: BlockInfo::none() }),
+                                x => return Err(Box::new_with(|| Error { kind: CompileError::CurrentlyUnsupported { msg: format_compact!("{s} with builtin project costume ({x}) currently not supported") }.into(), location: location.to_owned() })),
+                            }
+                        } else {
+                            self.parse_expr(&expr.children[1], &location)?
+                        };
+                        match self.grab_option(&expr.children[0], &location)? {
+                            "name" => Ok(Box::new_with(|| Expr { kind: ExprKind::CostumeName { costume }, info })),
+                            "width" => Ok(Box::new_with(|| Expr { kind: ExprKind::CostumeWidth { costume }, info })),
+                            "height" => Ok(Box::new_with(|| Expr { kind: ExprKind::CostumeHeight { costume }, info })),
+                            "pixels" => Ok(Box::new_with(|| Expr { kind: ExprKind::CostumePixels { costume }, info })),
+                            x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                        }
+                    }
+                    "reportGetSoundAttribute" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let sound = self.parse_expr(&expr.children[1], &location)?;
+                        match self.grab_option(&expr.children[0], &location)? {
+                            "name" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundName { sound }, info })),
+                            "duration" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundDuration { sound }, info })),
+                            "length" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundSamplesLength { sound }, info })),
+                            "number of channels" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundChannelCount { sound }, info })),
+                            "sample rate" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundSampleRate { sound }, info })),
+                            "samples" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundSamples { sound }, info })),
+                            x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                        }
+                    }
+
+                    "reportListIndex" => self.parse_2_args(expr, &location).map(|(value, list, info)| Box::new_with(|| Expr { kind: ExprKind::ListFind { value, list }, info })),
+                    "reportListItem" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let list = self.parse_expr(&expr.children[1], &location)?.into();
+                        match expr.children[0].get(&["option"]) {
+                            Some(opt) => match &*opt.text {
+                                "last" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListGetLast { list }, info })),
+                                "any" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListGetRandom { list }, info })),
+                                "" => Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                                x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                            }
+                            None => {
+                                let index = self.parse_expr(&expr.children[0], &location)?;
+                                Ok(Box::new_with(|| Expr { kind: ExprKind::ListGet { list, index }, info }))
+                            }
+                        }
+                    }
+                    "reportLetter" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let string = self.parse_expr(&expr.children[1], &location)?.into();
+                        match expr.children[0].get(&["option"]) {
+                            Some(opt) => match &*opt.text {
+                                "last" => Ok(Box::new_with(|| Expr { kind: ExprKind::StrGetLast { string }, info })),
+                                "any" => Ok(Box::new_with(|| Expr { kind: ExprKind::StrGetRandom { string }, info })),
+                                "" => Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                                x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                            }
+                            None => {
+                                let index = self.parse_expr(&expr.children[0], &location)?;
+                                Ok(Box::new_with(|| Expr { kind: ExprKind::StrGet { string, index }, info }))
+                            }
+                        }
+                    }
+                    "reportTextSplit" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let text = self.parse_expr(&expr.children[0], &location)?.into();
+                        let mode = match expr.children[1].get(&["option"]) {
+                            Some(opt) => match &*opt.text {
+                                "letter" => TextSplitMode::Letter,
+                                "word" => TextSplitMode::Word,
+                                "line" => TextSplitMode::LF,
+                                "tab" => TextSplitMode::Tab,
+                                "cr" => TextSplitMode::CR,
+                                "csv" => TextSplitMode::Csv,
+                                "json" => TextSplitMode::Json,
+                                "" => return Err(Box::new_with(|| Error { kind: CompileError::BlockOptionNotSelected.into(), location: location.to_owned() })),
+                                x => return Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                            }
+                            None => TextSplitMode::Custom(self.parse_expr(&expr.children[1], &location)?.into()),
+                        };
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::TextSplit { text, mode }, info }))
+                    }
+                    "reportTextFunction" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let function = self.parse_text_function(&expr.children[0], &location)?;
+                        let value = self.parse_expr(&expr.children[1], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::TextFunction { function, value }, info }))
+                    }
+                    "reportBoolean" => match expr.get(&["l", "bool"]) {
+                        Some(x) => self.parse_bool(&x.text, &location),
+                        None => Err(Box::new_with(|| Error { kind: ProjectError::BoolNoValue.into(), location: location.to_owned() })),
+                    }
+                    "reportMonadic" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?.lowered_from("reportMonadic");
+                        let func = self.grab_option(&expr.children[0], &location)?;
+                        let value = self.parse_expr(&expr.children[1], &location)?;
+                        match func {
+                            "id" => Ok(value),
+
+                            "neg" => Ok(Box::new_with(|| Expr { kind: ExprKind::Neg { value }, info })),
+                            "abs" => Ok(Box::new_with(|| Expr { kind: ExprKind::Abs { value }, info })),
+                            "sign" => Ok(Box::new_with(|| Expr { kind: ExprKind::Sign { value }, info })),
+                            "sqrt" => Ok(Box::new_with(|| Expr { kind: ExprKind::Sqrt { value }, info })),
+                            "floor" => Ok(Box::new_with(|| Expr { kind: ExprKind::Floor { value }, info })),
+                            "ceiling" => Ok(Box::new_with(|| Expr { kind: ExprKind::Ceil { value }, info })),
+
+                            "sin" => Ok(Box::new_with(|| Expr { kind: ExprKind::Sin { value }, info })),
+                            "cos" => Ok(Box::new_with(|| Expr { kind: ExprKind::Cos { value }, info })),
+                            "tan" => Ok(Box::new_with(|| Expr { kind: ExprKind::Tan { value }, info })),
+
+                            "asin" => Ok(Box::new_with(|| Expr { kind: ExprKind::Asin { value }, info })),
+                            "acos" => Ok(Box::new_with(|| Expr { kind: ExprKind::Acos { value }, info })),
+                            "atan" => Ok(Box::new_with(|| Expr { kind: ExprKind::Atan { value }, info })),
+
+                            "ln" => Ok(Box::new_with(|| Expr { kind: ExprKind::Log { value, base: Box::new_with(|| Constant::E.into()) }, info })),
+                            "lg" => Ok(Box::new_with(|| Expr { kind: ExprKind::Log { value, base: Box::new_with(|| 2f64.into()) }, info })),
+                            "log" => Ok(Box::new_with(|| Expr { kind: ExprKind::Log { value, base: Box::new_with(|| 10f64.into()) }, info })),
+
+                            "e^" => Ok(Box::new_with(|| Expr { kind: ExprKind::Pow { base: Box::new_with(|| Constant::E.into()), power: value }, info })),
+                            "2^" => Ok(Box::new_with(|| Expr { kind: ExprKind::Pow { base: Box::new_with(|| 2f64.into()), power: value }, info })),
+                            "10^" => Ok(Box::new_with(|| Expr { kind: ExprKind::Pow { base: Box::new_with(|| 10f64.into()), power: value }, info })),
+
+                            _ => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: func.into() }.into(), location: location.to_owned() })),
+                        }
+                    }
+                    "reportListAttribute" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let func = self.grab_option(&expr.children[0], &location)?;
+                        let value = self.parse_expr(&expr.children[1], &location)?;
+                        match func {
+                            "length" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListLen { value }, info })),
+                            "rank" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListRank { value }, info })),
+                            "dimensions" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListDims { value }, info })),
+                            "flatten" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListFlatten { value }, info })),
+                            "columns" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListColumns { value }, info })),
+                            "reverse" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListRev { value }, info })),
+
+                            "lines" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListLines { value }, info })),
+                            "csv" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListCsv { value }, info })),
+                            "json" => Ok(Box::new_with(|| Expr { kind: ExprKind::ListJson { value }, info })),
+
+                            _ => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: func.into() }.into(), location: location.to_owned() })),
+                        }
+                    }
+                    "reportReshape" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let value = self.parse_expr(&expr.children[0], &location)?;
+                        let dims = self.parse_expr(&expr.children[1], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::ListReshape { value, dims }, info }))
+                    }
+                    "reportIfElse" if is_if_chain(expr, true) => self.parse_conditional_chain(expr, &location),
+                    "reportIfElse" => {
+                        let info = self.check_children_get_info(expr, 3, &location)?;
+                        let condition = self.parse_expr(&expr.children[0], &location)?;
+                        let then = self.parse_expr(&expr.children[1], &location)?;
+                        let otherwise = self.parse_expr(&expr.children[2], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::Conditional { condition, then, otherwise }, info }))
+                    }
+                    "getJSFromRPCStruct" => {
+                        let rpc = self.parse_rpc(expr, &location)?;
+                        Ok(Box::new_with(|| (*rpc).into()))
+                    }
+                    "reportImageOfObject" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let entity = self.grab_entity(&expr.children[0], BlockInfo::none(), &location)?.into();
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::ImageOfEntity { entity }, info }))
+                    }
+                    "reportTouchingObject" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let child = &expr.children[0];
+                        if child.name == "l" && child.get(&["option"]).is_some() {
+                            match self.grab_option(child, &location)? {
+                                "mouse-pointer" => Ok(Box::new_with(|| Expr { kind: ExprKind::IsTouchingMouse, info })),
+                                "pen trails" => Ok(Box::new_with(|| Expr { kind: ExprKind::IsTouchingDrawings, info })),
+                                "edge" => Ok(Box::new_with(|| Expr { kind: ExprKind::IsTouchingEdge, info })),
+                                x => Err(Box::new_with(|| Error { kind: ProjectError::BlockOptionUnknown { got: x.into() }.into(), location: location.to_owned() })),
+                            }
+                        }
+                        else {
+                            let entity = self.grab_entity(child, BlockInfo::none(), &location)?.into();
+                            Ok(Box::new_with(|| Expr { kind: ExprKind::IsTouchingEntity { entity }, info }))
+                        }
+                    }
+                    "evaluate" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let closure = self.parse_expr(&expr.children[0], &location)?;
+                        let mut args = Vec::with_capacity(expr.children[1].children.len());
+                        for input in expr.children[1].children.iter() {
+                            args.push_boxed(self.parse_expr(input, &location)?);
+                        }
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::CallClosure { new_entity: None, closure, args }, info }))
+                    }
+                    "reportAskFor" => {
+                        let info = self.check_children_get_info(expr, 3, &location)?;
+                        let entity = self.grab_entity(&expr.children[0], BlockInfo::none(), &location)?;
+                        let closure = self.parse_expr(&expr.children[1], &location)?;
+                        let mut args = Vec::with_capacity(expr.children[2].children.len());
+                        for input in expr.children[2].children.iter() {
+                            args.push_boxed(self.parse_expr(input, &location)?);
+                        }
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::CallClosure { new_entity: Some(entity), closure, args }, info }))
+                    }
+                    "doSocketRequest" => {
+                        let res = self.parse_send_message_common(expr, &location)?;
+                        Ok(Box::new_with(|| {
+                            let NetworkMessage { target, msg_type, values, info } = *res;
+                            Expr { kind: ExprKind::NetworkMessageReply { target, msg_type, values }, info }
+                        }))
+                    }
+                    "getEffect" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let effect = self.parse_effect(&expr.children[0], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::Effect { kind: effect }, info }))
+                    }
+                    "reportGlobalFlag" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let flag = self.parse_global_flag(&expr.children[0], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::GlobalFlag { flag }, info }))
+                    }
+                    "getPenAttribute" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let attr = self.parse_pen_attr(&expr.children[0], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::PenAttr { attr }, info }))
+                    }
+                    "reportGet" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        match self.grab_option(&expr.children[0], &location)? {
+                            "costumes" => Ok(Box::new_with(|| Expr { kind: ExprKind::CostumeList, info })),
+                            "costume" => Ok(Box::new_with(|| Expr { kind: ExprKind::Costume, info })),
+                            "sounds" => Ok(Box::new_with(|| Expr { kind: ExprKind::SoundList, info })),
+                            m => Err(Box::new_with(|| Error { kind: CompileError::CurrentlyUnsupported { msg: format_compact!("the {s} block with option '{m}' is currently not supported") }.into(), location: location.to_owned() })),
+                        }
+                    }
+                    "reportObject" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        self.grab_entity(&expr.children[0], info, &location)
+                    }
+                    "newClone" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let target = self.grab_entity(&expr.children[0], BlockInfo::none(), &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::Clone { target }, info }))
+                    }
+                    "reportIsA" => {
+                        let info = self.check_children_get_info(expr, 2, &location)?;
+                        let value = self.parse_expr(&expr.children[0], &location)?;
+                        let ty = self.parse_value_type(&expr.children[1], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::TypeQuery { value, ty }, info }))
+                    }
+                    "reportDate" => {
+                        let info = self.check_children_get_info(expr, 1, &location)?;
+                        let query = self.parse_time_query(&expr.children[0], &location)?;
+                        Ok(Box::new_with(|| Expr { kind: ExprKind::RealTime { query }, info }))
+                    }
+                    x => {
+                        let (args, info) = self.parse_unknown_common(expr, &location)?;
+                        match self.parser.expr_replacements.iter().find(|r| r.0 == x) {
+                            Some(f) => f.1(args, info, &location),
+                            None => Ok(Box::new_with(|| Expr { kind: ExprKind::UnknownBlock { name: x.into(), args }, info })),
+                        }
+                    }
+                }
+            }
+            _ => Err(Box::new_with(|| Error { kind: CompileError::UnknownBlockType.into(), location: location.to_owned() })),
+        }
+    }
+}
+
+struct EntityInfo<'a, 'b, T> {
+    parser: &'a Parser<T>,
+    role: &'b RoleInfo<'a, T>,
+    name: CompactString,
+    trans_name: CompactString,
+    fields: SymbolTable<'a, T>,
+    funcs: SymbolTable<'a, T>,
+    costumes: SymbolTable<'a, T>,
+    sounds: SymbolTable<'a, T>,
+}
+impl<'a, 'b, T: NameTransformer> EntityInfo<'a, 'b, T> {
+    fn new(role: &'b RoleInfo<'a, T>, name: VariableRef) -> Box<Self> {
+        Box::new_with(|| Self {
+            parser: role.parser,
+            role,
+            name: name.name,
+            trans_name: name.trans_name,
+            fields: SymbolTable::new(role.parser),
+            funcs: SymbolTable::new(role.parser),
+            costumes: SymbolTable::new(role.parser),
+            sounds: SymbolTable::new(role.parser),
+        })
+    }
+    fn parse(mut self, entity: &'a Xml) -> Result<Entity, Box<Error>> {
+        let location = Box::new_with(|| LocationRef {
+            role: Some(&self.role.name),
+            entity: Some(&self.name),
+            collab_id: None,
+            block_type: None,
+        });
+
+        for costume in entity.get(&["costumes", "list"]).map(|c| c.children.as_slice()).unwrap_or(&[]) {
+            let ident = costume.get(&["ref"]).and_then(|r| r.attr("mediaID"));
+            if ident.is_none() {
+                self.role.warn(WarningKind::MediaWithoutRefIgnored { kind: "costume".into() }, &location);
+            }
+            if let Some(ident) = ident {
+                let ident = &*ident.value;
+                if !ident.starts_with(self.name.as_str()) || !ident[self.name.len()..].starts_with("_cst_") {
+                    return Err(Box::new_with(|| Error { kind: ProjectError::CostumeIdFormat { id: ident.into() }.into(), location: location.to_owned() }));
+                }
+                let name = &ident[self.name.len() + 5..];
+
+                let img = match self.role.images.get(ident) {
+                    Some(x) => x.clone(),
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::CostumeUndefinedRef { id: ident.into() }.into(), location: location.to_owned() })),
+                };
+
+                match self.costumes.define(name.into(), Value::Image(img)) {
+                    Ok(None) => (),
+                    Ok(Some(prev)) => return Err(Box::new_with(|| Error { kind: ProjectError::CostumesWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new_with(|| Error { kind: CompileError::CostumesWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                }
+            }
+        }
+
+        for sound in entity.get(&["sounds", "list"]).map(|c| c.children.as_slice()).unwrap_or(&[]) {
+            let ident = sound.get(&["ref"]).and_then(|r| r.attr("mediaID"));
+            if ident.is_none() {
+                self.role.warn(WarningKind::MediaWithoutRefIgnored { kind: "sound".into() }, &location);
+            }
+            if let Some(ident) = ident {
+                let ident = &*ident.value;
+                if !ident.starts_with(self.name.as_str()) || !ident[self.name.len()..].starts_with("_snd_") {
+                    return Err(Box::new_with(|| Error { kind: ProjectError::SoundIdFormat { id: ident.into() }.into(), location: location.to_owned() }));
+                }
+                let name = &ident[self.name.len() + 5..];
+
+                let sound = match self.role.sounds.get(ident) {
+                    Some(x) => x.clone(),
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::SoundUndefinedRef { id: ident.into() }.into(), location: location.to_owned() })),
+                };
+
+                match self.sounds.define(name.into(), Value::Audio(sound)) {
+                    Ok(None) => (),
+                    Ok(Some(prev)) => return Err(Box::new_with(|| Error { kind: ProjectError::SoundsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new_with(|| Error { kind: CompileError::SoundsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                }
+            }
+        }
+
+        let blocks = entity.get(&["blocks"]).map(|v| v.children.as_slice()).unwrap_or(&[]);
+        for block in blocks {
+            parse_block_header(block, &mut self.funcs, &location)?;
+        }
+
+        let active_costume = self.role.parse_attr(entity, "costume", |x| match x.parse::<usize>().ok()? {
+            0 => Some(None), // costume 0 means no costume
+            idx if idx <= self.costumes.len() => Some(Some(idx - 1)),
+            _ => None,
+        }, &location).flatten();
+        let color = self.role.parse_attr(entity, "color", parse_color, &location).unwrap_or((0, 0, 0, 255));
+        let visible = !self.role.parse_attr(entity, "hidden", |x| x.parse::<bool>().ok(), &location).unwrap_or(false);
+
+        let float_attr = |attr: &str| self.role.parse_attr(entity, attr, |x| x.parse::<f64>().ok().filter(|v| v.is_finite()), &location);
+        let pos = (float_attr("x").unwrap_or(0.0), float_attr("y").unwrap_or(0.0));
+        let heading = float_attr("heading").unwrap_or(0.0);
+        let scale = float_attr("scale").unwrap_or(1.0);
+
+        if let Some(fields) = entity.get(&["variables"]) {
+            let mut dummy_script = ScriptInfo::new(&self);
+
+            let mut defs = vec![];
+            for def in fields.children.iter().filter(|v| v.name == "variable") {
+                let name = match def.attr("name") {
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::UnnamedField.into(), location: location.to_owned() })),
+                    Some(x) => CompactString::new(&x.value),
+                };
+                let value = match def.children.get(0) {
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::FieldNoValue { name }.into(), location: location.to_owned() })),
+                    Some(x) => match dummy_script.parse_expr(x, &location)?.kind {
+                        ExprKind::Value(v) => v,
+                        _ => return Err(Box::new_with(|| Error { kind: ProjectError::ValueNotEvaluated.into(), location: location.to_owned() })),
+                    }
+                };
+                defs.push((name, value));
+            }
+
+            for (name, value) in defs {
+                match self.fields.define(name.clone(), value) {
+                    Ok(None) => (),
+                    Ok(Some(prev)) => return Err(Box::new_with(|| Error { kind: ProjectError::FieldsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new_with(|| Error { kind: CompileError::FieldsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                }
+            }
+        }
+
+        let mut funcs = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            funcs.push_boxed(parse_block(block, &self.funcs, self.role, Some(&self))?);
+        }
+
+        let mut scripts = vec![];
+        let mut canvas_comments = vec![];
+        let mut floating_reporters = vec![];
+        if let Some(scripts_xml) = entity.get(&["scripts"]) {
+            for script_xml in scripts_xml.children.iter() {
+                if script_xml.name == "comment" {
+                    let coord = |attr: &str| self.role.parse_attr(script_xml, attr, |x| x.parse::<f64>().ok().filter(|v| v.is_finite()), &location).unwrap_or(0.0);
+                    canvas_comments.push(CanvasComment { text: CompactString::new(&script_xml.text), pos: (coord("x"), coord("y")) });
+                    continue
+                }
+                match script_xml.children.as_slice() {
+                    [] => continue,
+                    [stmt, rest @ ..] => {
+                        let location = LocationRef { collab_id: get_collab_id(stmt), ..*location };
+                        if rest.is_empty() && (stmt.attr("var").is_some() || stmt.attr("s").map(|s| s.value.starts_with("report")).unwrap_or(false)) {
+                            match self.parser.keep_floating_reporters {
+                                true => floating_reporters.push_boxed(ScriptInfo::new(&self).parse_expr(stmt, &location)?),
+                                false => self.role.warn(WarningKind::FloatingReporterOmitted, &location),
+                            }
+                            continue
+                        }
+                        if self.parser.omit_nonhat_scripts && ScriptInfo::new(&self).parse_hat(stmt)?.is_none() {
+                            self.role.warn(WarningKind::NonHatScriptOmitted, &location);
+                            continue
+                        }
+                    }
+                }
+
+                let mut script_info = ScriptInfo::new(&self);
+                scripts.push_boxed(script_info.parse(script_xml)?);
+                script_info.finish_top_level(scripts.last_mut().unwrap());
+            }
+        }
+
+        Ok(Entity {
+            name: self.name,
+            trans_name: self.trans_name,
+            fields: self.fields.into_def_inits(),
+            costumes: self.costumes.into_def_inits(),
+            sounds: self.sounds.into_def_inits(),
+            funcs,
+            scripts,
+            canvas_comments,
+            floating_reporters,
+
+            active_costume,
+            visible,
+            color,
+            pos,
+            heading,
+            scale,
+            field_index: VarIndexCache::default(), // built by Parser::finish_project()
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ParamType {
+    Evaluated, Unevaluated, Variadic { evaluated: bool },
+}
+struct BlockHeaderInfo<'a> {
+    s: &'a str,
+    returns: bool,
+    params: Vec<(CompactString, ParamType)>,
+    upvars: Vec<CompactString>,
+}
+
+// returns the signature and returns flag of the block header value
+#[inline(never)]
+fn get_block_info(value: &Value) -> Box<BlockHeaderInfo> {
+    match value {
+        Value::List(vals, _) => {
+            assert_eq!(vals.len(), 4);
+            let s = match &vals[0] {
+                Value::String(v) => v.as_str(),
+                _ => panic!(),
+            };
+            let returns = match &vals[1] {
+                Value::Bool(v) => *v,
+                _ => panic!(),
+            };
+            let params = match &vals[2] {
+                Value::List(x, None) => x.iter().map(|x| match x {
+                    Value::List(x, None) => match x.as_slice() {
+                        [Value::String(v1), Value::Bool(v2), Value::Bool(v3)] => (v1.clone(), match (*v2, *v3) {
+                            (true, false) => ParamType::Evaluated,
+                            (false, false) => ParamType::Unevaluated,
+                            (evaluated, true) => ParamType::Variadic { evaluated },
+                        }),
+                        _ => panic!(),
+                    }
+                    _ => panic!(),
+                }).collect(),
+                _ => panic!(),
+            };
+            let upvars = match &vals[3] {
+                Value::List(x, None) => x.iter().map(|x| match x {
+                    Value::String(x) => x.clone(),
+                    _ => panic!(),
+                }).collect(),
+                _ => panic!(),
+            };
+            Box::new_with(|| BlockHeaderInfo { s, returns, params, upvars })
+        }
+        _ => panic!(), // header parser would never do this
+    }
+}
+
+fn replace_ranges<It>(s: &str, ranges: It, with: &str) -> CompactString where It: Iterator<Item = (usize, usize)>{
+    let mut res = alloc::string::String::with_capacity(s.len());
+    let mut last_stop = 0;
+    for (a, b) in ranges {
+        res += &s[last_stop..a];
+        last_stop = b;
+        res += with;
+    }
+    res += &s[last_stop..];
+    res.into()
+}
+
+#[inline(never)]
+fn block_name_from_def(s: &str) -> CompactString {
+    replace_ranges(s, ParamIter::new(s), "\t") // tabs leave a marker for args which disappears after ident renaming
+}
+#[inline(never)]
+fn block_name_from_ref(s: &str) -> CompactString {
+    replace_ranges(s, ArgIter::new(s), "\t") // tabs leave a marker for args which disappears after ident renaming
+}
+
+#[test]
+fn test_block_name_from_def() {
+    assert_eq!(block_name_from_def("hello world"), "hello world");
+    assert_eq!(block_name_from_def("hello %'wor'ld"), "hello \tld");
+    assert_eq!(block_name_from_def("hello %'wor' ld "), "hello \t ld ");
+    assert_eq!(block_name_from_def("hello %'wor'l%'d'"), "hello \tl\t");
+    assert_eq!(block_name_from_def("hello %'wor'l%'d' "), "hello \tl\t ");
+    assert_eq!(block_name_from_def("hello %'wor'l%'d'%' "), "hello \tl\t%' ");
+}
+#[test]
+fn test_block_name_from_ref() {
+    assert_eq!(block_name_from_ref("hello world"), "hello world");
+    assert_eq!(block_name_from_ref("hello %world"), "hello \t");
+    assert_eq!(block_name_from_ref("hello %world "), "hello \t ");
+}
+
+fn parse_block_header<'a, T: NameTransformer>(block: &'a Xml, funcs: &mut SymbolTable<'a, T>, location: &LocationRef) -> Result<(), Box<Error>> {
+    let mut location = Box::new_with(|| LocationRef {
+        role: location.role,
+        entity: location.entity,
+        collab_id: get_collab_id(block),
+        block_type: None,
+    });
+    let s = match block.attr("s") {
+        Some(v) => &*v.value,
+        None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockWithoutName.into(), location: location.to_owned() })),
+    };
+    location.block_type = Some(s);
+
+    let returns = match block.attr("type") {
+        Some(v) => match &*v.value {
+            "command" => false,
+            "reporter" | "predicate" => true,
+            x => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockUnknownType { ty: x.into() }.into(), location: location.to_owned() })),
+        }
+        None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockWithoutType.into(), location: location.to_owned() })),
+    };
+
+    let (params, upvars) = match block.get(&["inputs"]) {
+        Some(inputs) => {
+            let mut params = vec![];
+            let mut upvars = vec![];
+
+            let param_names: Vec<_> = ParamIter::new(s).map(|(a, b)| &s[a+2..b-1]).collect();
+            if param_names.len() != inputs.children.len() {
+                return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockInputsMetaCorrupted.into(), location: location.to_owned() }));
+            }
+            for (param, input) in iter::zip(param_names, &inputs.children) {
+                let t = match input.attr("type") {
+                    Some(x) if !x.value.is_empty() => &*x.value,
+                    _ => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockInputsMetaCorrupted.into(), location: location.to_owned() })),
+                };
+                let (variadic, t) = match t.strip_prefix("%mult") {
+                    Some(x) => (true, x),
+                    None => (false, t),
+                };
+                let evaluated = match t {
+                    "%anyUE" | "%boolUE" => false,
+                    _ => true,
+                };
+
+                params.push(Value::List(vec![CompactString::new(param).into(), evaluated.into(), variadic.into()], None));
+                if t == "%upvar" {
+                    upvars.push(Value::String(CompactString::new(param)));
+                }
+            }
+
+            (params, upvars)
+        }
+        None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockWithoutInputsMeta.into(), location: location.to_owned() })),
+    };
+
+    let name = block_name_from_def(s);
+    match funcs.define(name, Value::List(vec![Value::from(s), Value::from(returns), Value::List(params, None), Value::List(upvars, None)], None)) {
+        Ok(None) => Ok(()),
+        Ok(Some(prev)) => Err(Box::new_with(|| Error { kind: CompileError::BlocksWithSameName { name: prev.def.name, sigs: (get_block_info(&prev.init).s.into(), s.into()) }.into(), location: location.to_owned() })),
+        Err(SymbolError::NameTransformError { name }) => Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+        Err(SymbolError::ConflictingTrans { trans_name, names }) => Err(Box::new_with(|| Error { kind: CompileError::BlocksWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+    }
+}
+pub(crate) fn no_root_error() -> Box<Error> {
+    Box::new_with(|| Error { kind: ProjectError::NoRoot.into(), location: Location { role: None, entity: None, collab_id: None, block_type: None } })
+}
+fn parse_param_info(input: &Xml) -> ParamInfo {
+    let t = input.attr("type").map(|x| &*x.value).unwrap_or_default();
+    let (kind, t) = match t.strip_prefix("%mult") {
+        Some(x) => (ParamKind::Variadic, x),
+        None => (ParamKind::Single, t),
+    };
+    let input_type = match t {
+        "%s" => InputType::Any,
+        "%n" => InputType::Number,
+        "%b" => InputType::Bool,
+        "%l" => InputType::List,
+        "%txt" => InputType::Text,
+        "%mlt" => InputType::MultilineText,
+        "%code" => InputType::Code,
+        "%obj" => InputType::Object,
+        "%anyUE" => InputType::UnevaluatedAny,
+        "%boolUE" => InputType::UnevaluatedBool,
+        "%cmdRing" => InputType::CommandRing,
+        "%repRing" => InputType::ReporterRing,
+        "%predRing" => InputType::PredicateRing,
+        "%cmd" => InputType::Command,
+        "%cs" => InputType::CommandSlot,
+        "%ca" => InputType::LoopSlot,
+        "%upvar" => InputType::Upvar,
+        x => InputType::Other(x.into()),
+    };
+    ParamInfo { kind, input_type, default: CompactString::new(&input.text) }
+}
+fn parse_block<'a, T: NameTransformer>(block: &'a Xml, funcs: &SymbolTable<'a, T>, role: &RoleInfo<T>, entity: Option<&EntityInfo<T>>) -> Result<Box<Function>, Box<Error>> {
+    let s = &*block.attr("s").unwrap().value; // unwrap ok because we assume parse_block_header() was called before
+    let entry = funcs.get(&block_name_from_def(s)).unwrap();
+    let block_header = get_block_info(&entry.init);
+    assert_eq!(s, block_header.s);
+
+    let location = Box::new_with(|| LocationRef {
+        role: Some(&role.name),
+        entity: entity.map(|x| x.name.as_str()),
+        collab_id: get_collab_id(block),
+        block_type: Some(s),
+    });
+
+    let finalize = |entity_info: &EntityInfo<T>| {
+        let mut script_info = ScriptInfo::new(entity_info);
+        let param_info = block.get(&["inputs"]).map(|x| x.children.iter().map(parse_param_info).collect()).unwrap_or_default(); // inputs were validated by parse_block_header()
+        for param in block_header.params {
+            script_info.decl_local(param.0, 0f64.into(), &location)?;
+        }
+        debug_assert_eq!(script_info.locals.len(), 1);
+        debug_assert_eq!(script_info.locals[0].1.len(), 0);
+        let params = script_info.locals[0].0.clone().into_defs();
+
+        let stmts = match block.get(&["script"]) {
+            Some(script) => script_info.parse(script)?.stmts,
+            None => vec![],
+        };
+
+        let upvars = {
+            let mut res = vec![];
+            for upvar in block_header.upvars.iter() {
+                match params.iter().find(|x| x.name == *upvar) {
+                    Some(x) => res.push_boxed(x.ref_at(VarLocation::Local)),
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::CustomBlockInputsMetaCorrupted.into(), location: location.to_owned() })),
+                };
+            }
+            res
+        };
+
+        Ok(Box::new_with(|| Function {
+            name: entry.def.name.clone(),
+            trans_name: entry.def.trans_name.clone(),
+            upvars,
+            params,
+            param_info,
+            returns: block_header.returns,
+            stmts,
+            category: block.attr("category").map(|x| CompactString::new(&x.value)).unwrap_or_default(),
+            location: if entity.is_some() { FnLocation::Method } else { FnLocation::Global },
+            help: block.get(&["comment"]).map(|x| CompactString::new(&x.text)),
+            code_mapping: block.get(&["code"]).map(|x| CompactString::new(&x.text)).unwrap_or_default(),
+            code_header: block.get(&["header"]).map(|x| CompactString::new(&x.text)).unwrap_or_default(),
+        }))
+    };
+    match entity {
+        Some(v) => finalize(v),
+        None => {
+            let entity = EntityInfo::new(role, VariableRef { name: "global".into(), trans_name: "global".into(), location: VarLocation::Global, slot: None });
+            finalize(&entity)
+        }
+    }
+}
+
+struct RoleInfo<'a, T> {
+    parser: &'a Parser<T>,
+    name: CompactString,
+    globals: SymbolTable<'a, T>,
+    entities: SymbolTable<'a, T>,
+    funcs: SymbolTable<'a, T>,
+    images: VecMap<&'a str, Rc<(Vec<u8>, Option<(f64, f64)>, CompactString)>>,
+    sounds: VecMap<&'a str, Rc<(Vec<u8>, CompactString)>>,
+    msg_types: VecMap<&'a str, Vec<&'a str>>,
+    warnings: RefCell<Vec<Warning>>,
+}
+impl<'a, T: NameTransformer> RoleInfo<'a, T> {
+    fn new(parser: &'a Parser<T>, name: CompactString) -> Box<Self> {
+        Box::new_with(|| Self {
+            parser,
+            name,
+            globals: SymbolTable::new(parser),
+            entities: SymbolTable::new(parser),
+            funcs: SymbolTable::new(parser),
+            images: Default::default(),
+            sounds: Default::default(),
+            msg_types: Default::default(),
+            warnings: Default::default(),
+        })
+    }
+    fn warn(&self, kind: WarningKind, location: &LocationRef) {
+        self.warnings.borrow_mut().push(Warning { kind, location: location.to_owned() });
+    }
+    /// Parses an optional attribute, emitting a warning if it is present but invalid.
+    fn parse_attr<V>(&self, xml: &Xml, name: &str, f: impl FnOnce(&str) -> Option<V>, location: &LocationRef) -> Option<V> {
+        let attr = xml.attr(name)?;
+        let res = f(&attr.value);
+        if res.is_none() {
+            self.warn(WarningKind::InvalidAttribute { name: name.into(), value: CompactString::new(&attr.value) }, location);
+        }
+        res
+    }
+    /// Decodes an optional embedded image (a base64 data url), emitting a warning if it is present but invalid.
+    fn parse_image_data(&self, xml: Option<&Xml>, location: &LocationRef) -> Option<Vec<u8>> {
+        let xml = xml.filter(|x| !x.text.is_empty())?;
+        let res = xml.text.strip_prefix("data:image/").and_then(|x| x.split(";base64,").nth(1)).and_then(|x| base64_decode(x).ok());
+        if res.is_none() {
+            self.warn(WarningKind::InvalidImageIgnored { name: xml.name.into() }, location);
+        }
+        res
+    }
+    fn parse(mut self, role_root: &'a Xml, source_hash: Option<u64>) -> Result<(Role, Vec<Warning>), Box<Error>> {
+        let mut location = Box::new_with(|| LocationRef {
+            role: None,
+            entity: None,
+            collab_id: None,
+            block_type: None,
+        });
+
+        assert_eq!(role_root.name, "role");
+        let role = match role_root.attr("name") {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::RoleNoName.into(), location: location.to_owned() })),
+            Some(x) => CompactString::new(&x.value),
+        };
+        location.role = Some(&role);
+
+        let content = match role_root.get(&["project"]) {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::RoleNoContent.into(), location: location.to_owned() })),
+            Some(x) => x,
+        };
+        let notes = CompactString::new(content.get(&["notes"]).map(|v| &*v.text).unwrap_or(""));
+        let stage = match content.get(&["stage"]) {
+            None => return Err(Box::new_with(|| Error { kind: ProjectError::NoStage.into(), location: location.to_owned() })),
+            Some(x) => x,
+        };
+        let code_map = |name: &str| content.get(&[name]).map(|x| x.children.iter().map(|x| (x.name.into(), CompactString::new(&x.text))).collect()).unwrap_or_default();
+        let thumbnail = self.parse_image_data(content.get(&["thumbnail"]), &location);
+        let pen_trails = self.parse_image_data(stage.get(&["pentrails"]), &location);
+
+        let code_mappings = code_map("code");
+        let code_headers = code_map("headers");
+
+        let mut palette = Palette::default();
+        if let Some(hidden) = content.get(&["hidden"]) {
+            palette.hidden_blocks.extend(hidden.text.split_whitespace().map(CompactString::new));
+            palette.hidden_blocks.extend(hidden.children.iter().filter_map(|x| x.attr("s")).map(|x| CompactString::new(&x.value)));
+        }
+        for category in content.get(&["palette"]).map(|x| x.children.as_slice()).unwrap_or(&[]) {
+            if category.name != "category" { continue }
+            let name = match category.attr("name") {
+                Some(x) => CompactString::new(&x.value),
+                None => continue,
+            };
+            let color = self.parse_attr(category, "color", parse_color, &location).unwrap_or((0, 0, 0, 255));
+            palette.categories.push(Category { name, color });
+        }
+
+        let stage_width = self.parse_attr(stage, "width", |x| x.parse::<usize>().ok(), &location).unwrap_or(480);
+        let stage_height = self.parse_attr(stage, "height", |x| x.parse::<usize>().ok(), &location).unwrap_or(360);
+
+        let msg_types = stage.get(&["messageTypes"]).map(|x| x.children.as_slice()).unwrap_or(&[]);
+        for msg_type in msg_types {
+            let name = match msg_type.get(&["name"]) {
+                None => return Err(Box::new_with(|| Error { kind: ProjectError::MessageTypeMissingName.into(), location: location.to_owned() })),
+                Some(x) => &*x.text,
+            };
+            let fields = match msg_type.get(&["fields"]) {
+                None => return Err(Box::new_with(|| Error { kind: ProjectError::MessageTypeMissingFields { msg_type: name.into() }.into(), location: location.to_owned() })),
+                Some(x) => {
+                    let mut res = vec![];
+                    for field in x.children.iter() {
+                        if field.name != "field" { continue }
+                        res.push(match &*field.text {
+                            "" => return Err(Box::new_with(|| Error { kind: ProjectError::MessageTypeFieldEmpty { msg_type: name.into() }.into(), location: location.to_owned() })),
+                            x => x,
+                        });
+                    }
+                    res
+                }
+            };
+
+            if self.msg_types.insert(name, fields).is_some() {
+                return Err(Box::new_with(|| Error { kind: ProjectError::MessageTypeMultiplyDefined { msg_type: name.into() }.into(), location: location.to_owned() }));
+            }
+        }
+
+        for entry in role_root.get(&["media"]).map(|v| v.children.as_slice()).unwrap_or(&[]) {
+            match entry.name {
+                "costume" => {
+                    let id = match entry.attr("mediaID") {
+                        Some(x) => &*x.value,
+                        None => return Err(Box::new_with(|| Error { kind: ProjectError::ImageWithoutId.into(), location: location.to_owned() })),
+                    };
+
+                    let name = match entry.attr("name") {
+                        Some(x) => CompactString::new(&x.value),
+                        None => "untitled".into(),
+                    };
+
+                    let center = match (entry.attr("center-x").and_then(|x| x.value.parse().ok()), entry.attr("center-y").and_then(|y| y.value.parse().ok())) {
+                        (Some(x), Some(y)) => Some((x, y)),
+                        _ => None,
+                    };
+
+                    let content = match entry.attr("image") {
+                        Some(x) => match x.value.starts_with("data:image/").then(|| x.value.split(";base64,").nth(1)).flatten() {
+                            Some(x) => match base64_decode(x) {
+                                Ok(x) => x,
+                                Err(e) => return Err(Box::new_with(|| Error { kind: e.into(), location: location.to_owned() })),
+                            }
+                            _ => return Err(Box::new_with(|| Error { kind: ProjectError::ImageUnknownFormat { id: id.into(), content: CompactString::new(&x.value) }.into(), location: location.to_owned() })),
+                        }
+                        None => return Err(Box::new_with(|| Error { kind: ProjectError::ImageWithoutContent { id: id.into() }.into(), location: location.to_owned() })),
+                    };
+
+                    if self.images.insert(id, Rc::new((content, center, name))).is_some() {
+                        return Err(Box::new_with(|| Error { kind: ProjectError::ImagesWithSameId { id: id.into() }.into(), location: location.to_owned() }));
+                    }
+                }
+                "sound" => {
+                    let id = match entry.attr("mediaID") {
+                        Some(x) => &*x.value,
+                        None => return Err(Box::new_with(|| Error { kind: ProjectError::SoundWithoutId.into(), location: location.to_owned() })),
+                    };
+
+                    let name = match entry.attr("name") {
+                        Some(x) => CompactString::new(&x.value),
+                        None => "untitled".into(),
+                    };
+
+                    let content = match entry.attr("sound") {
+                        Some(x) => match x.value.starts_with("data:audio/").then(|| x.value.split(";base64,").nth(1)).flatten() {
+                            Some(x) => match base64_decode(x) {
+                                Ok(x) => x,
+                                Err(e) => return Err(Box::new_with(|| Error { kind: e.into(), location: location.to_owned() })),
+                            }
+                            _ => return Err(Box::new_with(|| Error { kind: ProjectError::SoundUnknownFormat { id: id.into(), content: CompactString::new(&x.value) }.into(), location: location.to_owned() })),
+                        }
+                        None => return Err(Box::new_with(|| Error { kind: ProjectError::SoundWithoutContent { id: id.into() }.into(), location: location.to_owned() })),
+                    };
+
+                    if self.sounds.insert(id, Rc::new((content, name))).is_some() {
+                        return Err(Box::new_with(|| Error { kind: ProjectError::SoundsWithSameId { id: id.into() }.into(), location: location.to_owned() }));
+                    }
+                }
+                x => self.warn(WarningKind::UnknownMediaIgnored { kind: x.into() }, &location),
+            }
+        }
+
+        if let Some(globals) = content.get(&["variables"]) {
+            let dummy_name = VariableRef { name: "global".into(), trans_name: "global".into(), location: VarLocation::Global, slot: None };
+            let dummy_entity = EntityInfo::new(&self, dummy_name); // fine to do before entities/blocks/etc. since globals are just values (not stmts or exprs)
+            let mut dummy_script = ScriptInfo::new(&dummy_entity);
+
+            let mut defs = vec![];
+            for def in globals.children.iter().filter(|v| v.name == "variable") {
+                let name = match def.attr("name") {
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::UnnamedGlobal.into(), location: location.to_owned() })),
+                    Some(x) => CompactString::new(&x.value),
+                };
+                let value = match def.children.get(0) {
+                    None => Value::Number(0.0),
+                    Some(x) => match dummy_script.parse_expr(x, &location)?.kind {
+                        ExprKind::Value(v) => v,
+                        _ => return Err(Box::new_with(|| Error { kind: ProjectError::ValueNotEvaluated.into(), location: location.to_owned() })),
+                    }
+                };
+                defs.push((name, value));
+            }
+
+            for (name, value) in defs {
+                match self.globals.define(name.clone(), value) {
+                    Ok(None) => (),
+                    Ok(Some(prev)) => return Err(Box::new_with(|| Error { kind: ProjectError::GlobalsWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                    Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new_with(|| Error { kind: CompileError::GlobalsWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                }
+            }
+        }
+
+        let mut entities_raw = vec![];
+        if let Some(entities_xml) = stage.get(&["sprites"]) {
+            for entity in iter::once(stage).chain(entities_xml.children.iter().filter(|s| s.name == "sprite")) {
+                let name = match entity.attr("name") {
+                    None => return Err(Box::new_with(|| Error { kind: ProjectError::UnnamedEntity.into(), location: location.to_owned() })),
+                    Some(x) => match self.entities.define(CompactString::new(&x.value), 0f64.into()) {
+                        Ok(None) => self.entities.get(&x.value).unwrap().def.ref_at(VarLocation::Global),
+                        Ok(Some(prev)) => return Err(Box::new_with(|| Error { kind: ProjectError::EntitiesWithSameName { name: prev.def.name }.into(), location: location.to_owned() })),
+                        Err(SymbolError::NameTransformError { name }) => return Err(Box::new_with(|| Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                        Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new_with(|| Error { kind: CompileError::EntitiesWithSameTransName { trans_name, names }.into(), location: location.to_owned() })),
+                    }
+                };
+                entities_raw.push((entity, name));
+            }
+        }
+
+        let blocks = content.get(&["blocks"]).map(|v| v.children.as_slice()).unwrap_or(&[]);
+        for block in blocks {
+            parse_block_header(block, &mut self.funcs, &location)?;
+        }
+
+        // ----------------------------------------------------------------------------------- //
+        // -- we now have all the necessary items defined to parse exprs, stmts, and entity -- //
+        // ----------------------------------------------------------------------------------- //
+
+        let mut funcs = Vec::with_capacity(blocks.len());
+        for block in blocks.iter() {
+            funcs.push_boxed(parse_block(block, &self.funcs, &self, None)?);
+        }
+        let entities = entities_raw.into_iter().map(|(entity, name)| EntityInfo::new(&self, *name).parse(entity)).collect::<Result<Vec<_>,_>>()?;
+
+        Ok((Role {
+            name: role,
+            notes,
+            stage_size: (stage_width, stage_height),
+            globals: self.globals.into_def_inits(),
+            funcs,
+            entities,
+            code_mappings,
+            code_headers,
+            palette,
+            thumbnail,
+            pen_trails,
+            source_hash,
+            global_index: VarIndexCache::default(), // built by Parser::finish_project()
+        }, self.warnings.into_inner()))
+    }
+}
+
+/// The context of a block which is being parsed by a [`BlockHandler`].
+pub struct BlockContext<'a> {
+    /// The location of the block.
+    pub location: &'a LocationRef<'a>,
+    script: &'a mut dyn BlockScope,
+}
+impl BlockContext<'_> {
+    /// Looks up a variable which is visible from the block (a local, field, or global), as if it were referenced by a variable block.
+    /// As usual, referring to a local variable from inside of a closure captures it.
+    pub fn variable(&mut self, name: &str) -> Result<VariableRef, Box<Error>> {
+        self.script.variable(name, self.location).map(|x| *x)
+    }
+    /// Declares a local variable in the enclosing script (e.g., for an upvar of the block or a field of a hat block), which is visible to all later blocks.
+    pub fn declare(&mut self, name: &str) -> Result<VariableRef, Box<Error>> {
+        self.script.declare(name, self.location).map(|x| *x)
+    }
+}
+/// The operations on the enclosing script which are exposed by [`BlockContext`].
+trait BlockScope {
+    fn variable(&mut self, name: &str, location: &LocationRef) -> Result<Box<VariableRef>, Box<Error>>;
+    fn declare(&mut self, name: &str, location: &LocationRef) -> Result<Box<VariableRef>, Box<Error>>;
+}
+
+/// A custom parser for a block (see [`Parser::register_block`]).
+///
+/// Each function receives the arguments of the block (already parsed as expressions, where scripts become command closures),
+/// the block info (with [`BlockInfo::opcode`] set to the opcode of the block), and the [`BlockContext`].
+/// If there is no function for the position the block is used in (as a command, a reporter, or a hat), the block is parsed as usual.
+/// Blocks which have no equivalent in the AST can be represented by [`StmtKind::UnknownBlock`], [`ExprKind::UnknownBlock`], or [`HatKind::Custom`] with a consumer-chosen name.
+/// Blocks which should be rejected can return a structured [`Error`] (e.g., using [`BlockContext::location`]).
+#[derive(Default)]
+pub struct BlockHandler {
+    /// Parses the block when it is used as a command, producing a sequence of zero or more statements.
+    pub stmt: Option<StmtHandler>,
+    /// Parses the block when it is used as a reporter.
+    pub expr: Option<ExprHandler>,
+    /// Parses the block when it is used as the hat block of a script.
+    pub hat: Option<HatHandler>,
+}
+/// The function which parses a command block for a [`BlockHandler`].
+pub type StmtHandler = Box<dyn Fn(Vec<Expr>, Box<BlockInfo>, &mut BlockContext) -> Result<Vec<Stmt>, Box<Error>> + Send + Sync>;
+/// The function which parses a reporter block for a [`BlockHandler`].
+pub type ExprHandler = Box<dyn Fn(Vec<Expr>, Box<BlockInfo>, &mut BlockContext) -> Result<Box<Expr>, Box<Error>> + Send + Sync>;
+/// The function which parses a hat block for a [`BlockHandler`].
+pub type HatHandler = Box<dyn Fn(Vec<Expr>, Box<BlockInfo>, &mut BlockContext) -> Result<Box<Hat>, Box<Error>> + Send + Sync>;
+/// A function which is applied to each parsed role (see [`Parser::post_hooks`]).
+pub type PostHook = Box<dyn Fn(&mut Role) -> Result<(), Box<Error>> + Send + Sync>;
+
+pub struct Parser<T = DefaultNameTransformer> {
+    /// If `true`, the parser will skip script blocks that lack a hat block.
+    /// This is typically desirable since free floating blocks are never automatically executed,
+    /// and thus are typically not needed for translation efforts.
+    /// Defaults to `true`.
+    pub omit_nonhat_scripts: bool,
+
+    /// If `true`, free floating reporter and variable blocks are parsed into [`Entity::floating_reporters`] rather than being skipped.
+    /// This is useful for analysis tools that need to see everything on the scripting area.
+    /// Defaults to `false`.
+    pub keep_floating_reporters: bool,
+
+    /// Controls whether blocks with missing or unknown dropdown options are errors or are tolerated.
+    /// Warnings generated by [`Strictness::Lenient`] are available through [`Parser::parse_with_diagnostics`].
+    /// Defaults to [`Strictness::Strict`].
+    pub strictness: Strictness,
+
+    /// If `true`, unrecognized dropdown options for effects, pen attributes, type queries, text functions, and time queries
+    /// are kept as the `Other` variant of the corresponding enum (with a [`WarningKind::UnknownOptionPassedThrough`] warning) rather than being errors.
+    /// This allows projects from newer versions of NetsBlox to be parsed, but consumers must be prepared to handle the unknown values.
+    /// Defaults to `false`.
+    pub pass_through_unknown_options: bool,
+
+    /// Controls how input slots which were left empty are represented.
+    /// Defaults to [`EmptySlots::EmptyString`].
+    pub empty_slots: EmptySlots,
+
+    /// If `true`, metaprogramming blocks (which create or modify custom blocks at runtime) are parsed into
+    /// [`StmtKind::DefineBlock`], [`StmtKind::SetBlockAttribute`], and [`ExprKind::BlockAttribute`].
+    /// If `false`, they are rejected with [`CompileError::MetaprogrammingNotAllowed`], which is useful for backends that cannot support them.
+    /// Defaults to `true`.
+    pub allow_metaprogramming: bool,
+
+    /// If `true`, the (potentially very large) `<history>` and `<replay>` sections of a project are skipped
+    /// while reading the xml, rather than being loaded into memory.
+    /// These sections are not represented in the AST, so this only affects performance.
+    /// Defaults to `true`.
+    pub skip_history: bool,
+
+    /// If `true`, identical strings within a list literal (e.g., a dataset embedded in a project) share a single allocation as [`Value::SharedString`],
+    /// which can greatly reduce memory usage for lists with many repeated values.
+    /// Short strings are stored inline and are never shared.
+    /// Defaults to `false`.
+    pub share_list_strings: bool,
+
+    /// If set, atomic list literals (e.g., datasets embedded in a project) whose serialized text is at least this many bytes
+    /// are kept as [`Value::RawList`] rather than being parsed into a [`Value::List`], which is much faster for projects with large datasets.
+    /// Consumers can then parse or stream the items of such lists on demand.
+    /// Defaults to `None`.
+    pub defer_atomic_lists: Option<usize>,
+
+    /// If set, list literals (including atomic lists) which exceed these limits are rejected or truncated,
+    /// which protects services that parse untrusted projects from pathologically large uploads.
+    /// Truncated atomic lists are never deferred by [`Parser::defer_atomic_lists`].
+    /// Defaults to `None`.
+    pub list_limits: Option<ListLimits>,
+
+    /// All symbol names in the program will be passed through this function,
+    /// allowing easy conversion of Snap! names to, e.g., valid C-like identifiers.
+    /// The default operation performs no conversion.
+    /// Note that non-default transform strategies may also require a custom [`Parser::autofill_generator`].
+    /// To use a transformer of a different type (avoiding dynamic dispatch), see [`Parser::with_name_transformer`].
+    pub name_transformer: T,
+
+    /// A generator used to produce symbol names for auto-fill closure arguments.
+    /// The function receives a number that can be used to differentiate different generated arguments.
+    /// It is expected that multiple calls to this function with the same input will produce the same output symbol name.
+    /// The default is to produce a string of format `%n` where `n` is the input number.
+    /// Note that, after generation, symbol names are still passed through [`Parser::name_transformer`] as usual.
+    pub autofill_generator: Box<dyn Fn(usize) -> Result<CompactString, ()> + Send + Sync>,
+
+    /// A mapping of unknown stmt blocks to functions that replace them with a sequence of zero or more other statements.
+    /// The mapping function receives as input the arguments list to the original block with replacements already recursively applied, as well as the block info for the original block and its code location.
+    /// Note that replacements are not further applied to the result of this function.
+    pub stmt_replacements: Vec<(CompactString, Box<dyn Fn(Vec<Expr>, Box<BlockInfo>, &LocationRef) -> Result<Vec<Stmt>, Box<Error>> + Send + Sync>)>,
+
+    /// A mapping of unknown expr blocks to functions that replace them with another expression, which could be composed of several sub-expressions.
+    /// The mapping function receives as input the arguments list to the original block with replacements already recursively applied, as well as the block info for the original block and its code location.
+    /// Note that replacements are not further applied to the result of this function.
+    pub expr_replacements: Vec<(CompactString, Box<dyn Fn(Vec<Expr>, Box<BlockInfo>, &LocationRef) -> Result<Box<Expr>, Box<Error>> + Send + Sync>)>,
+
+    /// Custom parsers for blocks, keyed by opcode (see [`Parser::register_block`]).
+    /// These take precedence over the built-in parsing of a block (and over [`Parser::stmt_replacements`] and [`Parser::expr_replacements`]),
+    /// which allows consumers to support new or institution-specific blocks, or to change how existing blocks are parsed.
+    /// Defaults to empty.
+    pub block_handlers: Vec<(CompactString, BlockHandler)>,
+
+    /// Functions which are applied, in order, to each parsed role (see [`Parser::with_post_hook`]).
+    /// These run before [`Parser::passes`], and can transform the role or reject it by returning an error, which aborts parsing.
+    /// Node ids are assigned in parse order before the hooks run, and again after all passes have been run.
+    /// Roles which are reused by [`Parser::reparse`] were already transformed, so the hooks should produce the same result when run again.
+    /// Defaults to empty.
+    pub post_hooks: Vec<PostHook>,
+
+    /// Transformations which are applied, in order, to every parsed project (see [`Parser::with_pass`]).
+    /// Node ids are assigned before the passes run (see [`Parser::post_hooks`]), and again after all passes have been run.
+    /// Roles which are reused by [`Parser::reparse`] were already transformed, so the passes should produce the same result when run again.
+    /// Defaults to empty.
+    pub passes: Vec<Box<dyn AstPass + Send + Sync>>,
+}
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            omit_nonhat_scripts: true,
+            keep_floating_reporters: false,
+            strictness: Strictness::Strict,
+            pass_through_unknown_options: false,
+            empty_slots: EmptySlots::EmptyString,
+            allow_metaprogramming: true,
+            skip_history: true,
+            share_list_strings: false,
+            defer_atomic_lists: None,
+            list_limits: None,
+            name_transformer: Box::new(|v| Ok(v.into())),
+            autofill_generator: Box::new(|v| Ok(format_compact!("%{}", v))),
+            stmt_replacements: vec![],
+            expr_replacements: vec![],
+            block_handlers: vec![],
+            post_hooks: vec![],
+            passes: vec![],
+        }
+    }
+}
+impl Parser {
+    /// Replaces [`Parser::name_transformer`] with a transformer of a different type, keeping all other settings.
+    pub fn with_name_transformer<T: NameTransformer>(self, name_transformer: T) -> Parser<T> {
+        let Parser { omit_nonhat_scripts, keep_floating_reporters, strictness, pass_through_unknown_options, empty_slots, allow_metaprogramming, skip_history, share_list_strings, defer_atomic_lists, list_limits, name_transformer: _, autofill_generator, stmt_replacements, expr_replacements, block_handlers, post_hooks, passes } = self;
+        Parser { omit_nonhat_scripts, keep_floating_reporters, strictness, pass_through_unknown_options, empty_slots, allow_metaprogramming, skip_history, share_list_strings, defer_atomic_lists, list_limits, name_transformer, autofill_generator, stmt_replacements, expr_replacements, block_handlers, post_hooks, passes }
+    }
+}
+
+/// Assigns new [`NodeId`]s to a project (see [`Project::assign_node_ids`]), updating the ids in a source map to match.
+fn assign_node_ids(project: &mut Project, source_map: Option<&mut SourceMap>) {
+    match source_map {
+        Some(source_map) => {
+            let elements = mem::take(&mut source_map.spans);
+            project.assign_node_ids_with(&mut |old, new| if old != NodeId::default() {
+                if let Some(span) = elements.get(&old) {
+                    source_map.spans.insert(new, span.clone());
+                }
+            });
+        }
+        None => project.assign_node_ids(),
+    }
+}
+
+/// Copies an unchanged role for [`Parser::reparse`].
+/// This is kept out of line since the temporary role would otherwise bloat the stack frame of the parser.
+#[inline(never)]
+fn reuse_role(role: &Role) -> (Role, Vec<Warning>) {
+    (role.clone(), vec![])
+}
+
+/// Gets the paths of all the project files in a directory (recursively), in sorted order.
+#[cfg(feature = "std")]
+fn project_files(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    let mut res = vec![];
+    let mut pending = vec![dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|x| x.eq_ignore_ascii_case("xml")) {
+                res.push(path);
+            }
+        }
+    }
+    res.sort_unstable();
+    Ok(res)
+}
+
+impl<T: NameTransformer> Parser<T> {
+    /// Adds a transformation to the end of [`Parser::passes`].
+    /// Built-in passes can be selected by name with [`builtin_pass`].
+    pub fn with_pass(mut self, pass: Box<dyn AstPass + Send + Sync>) -> Self {
+        self.passes.push(pass);
+        self
+    }
+    /// Adds a function to the end of [`Parser::post_hooks`].
+    pub fn with_post_hook(mut self, hook: impl Fn(&mut Role) -> Result<(), Box<Error>> + Send + Sync + 'static) -> Self {
+        self.post_hooks.push(Box::new(hook));
+        self
+    }
+    /// Adds a custom parser for blocks with the given opcode to [`Parser::block_handlers`].
+    /// If a handler was already registered for the opcode, it is replaced.
+    pub fn register_block(mut self, opcode: &str, handler: BlockHandler) -> Self {
+        self.block_handlers.retain(|x| x.0 != opcode);
+        self.block_handlers.push((opcode.into(), handler));
+        self
+    }
+    pub fn parse(&self, xml: &str) -> Result<Project, Box<Error>> {
+        self.parse_with_diagnostics(xml).map(|x| x.0)
+    }
+    /// Equivalent to [`Parser::parse`], but additionally returns a list of warnings for any information that was dropped or replaced by a default during parsing.
+    #[inline(always)] // avoid an extra stack frame on the path to the role parser
+    pub fn parse_with_diagnostics(&self, xml: &str) -> Result<(Project, Vec<Warning>), Box<Error>> {
+        self.parse_impl(xml, None, None)
+    }
+    /// Equivalent to [`Parser::parse`], but additionally returns a [`SourceMap`] from the [`NodeId`]s of the project to byte ranges in `xml`.
+    pub fn parse_with_source_map(&self, xml: &str) -> Result<(Project, SourceMap), Box<Error>> {
+        let mut source_map = SourceMap::default();
+        let (project, _) = self.parse_impl(xml, None, Some(&mut source_map))?;
+        let bom = xml.len() - xml.strip_prefix('\u{feff}').unwrap_or(xml).len(); // the parser sees the xml without the byte order mark
+        for span in source_map.spans.values_mut() {
+            *span = span.start + bom..span.end + bom;
+        }
+        Ok((project, source_map))
+    }
+    /// Equivalent to [`Parser::parse`], but takes raw bytes whose encoding is detected automatically (see [`util::detect_encoding`]).
+    /// This handles projects which were saved as UTF-16 or with a byte order mark.
+    pub fn parse_bytes(&self, xml: &[u8]) -> Result<Project, Box<Error>> {
+        match util::decode_xml(xml, util::detect_encoding(xml)) {
+            Ok(xml) => self.parse(&xml),
+            Err(e) => Err(Box::new_with(|| Error { kind: e.into(), location: Location { role: None, entity: None, collab_id: None, block_type: None } })),
+        }
+    }
+    /// Reads and parses a project file, detecting its encoding as in [`Parser::parse_bytes`].
+    #[cfg(feature = "std")]
+    pub fn parse_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<Project, FileError> {
+        let xml = std::fs::read(path).map_err(FileError::Io)?;
+        self.parse_bytes(&xml).map_err(FileError::Parse)
+    }
+    /// Parses every project file (with an `.xml` extension) in a directory and its subdirectories, as in [`Parser::parse_file`].
+    /// Failures of individual files are collected in the results rather than aborting the batch,
+    /// so this fails only if the directory structure itself cannot be read.
+    #[cfg(feature = "std")]
+    pub fn parse_dir<P: AsRef<std::path::Path>>(&self, path: P) -> Result<DirResults, std::io::Error> {
+        let files = project_files(path.as_ref())?.into_iter().map(|path| {
+            let res = self.parse_file(&path);
+            (path, res)
+        }).collect();
+        Ok(DirResults { files })
+    }
+    /// Equivalent to [`Parser::parse_dir`], but parses the files on several threads at once (see [`ParallelOptions`]).
+    /// Because [`Project`] cannot be sent between threads (see [`Value`]), each result is processed by `f` on the thread that parsed it,
+    /// and the outputs of `f` are returned in order of path.
+    ///
+    /// If a thread panics (e.g., in `f`), the panic is reported as an error of kind [`std::io::ErrorKind::Other`] once the other threads are done.
+    #[cfg(feature = "std")]
+    pub fn parse_dir_parallel<P, F, R>(&self, path: P, options: &ParallelOptions, f: F) -> Result<Vec<(std::path::PathBuf, R)>, std::io::Error>
+    where T: Sync, P: AsRef<std::path::Path>, F: Fn(&std::path::Path, Result<Project, FileError>) -> R + Sync, R: Send
+    {
+        let paths = project_files(path.as_ref())?;
+        let next = core::sync::atomic::AtomicUsize::new(0);
+        let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..options.threads.clamp(1, paths.len().max(1))).map(|_| {
+                std::thread::Builder::new().stack_size(options.stack_size).spawn_scoped(scope, || {
+                    let mut res = vec![];
+                    loop {
+                        let i = next.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+                        match paths.get(i) {
+                            Some(path) => res.push((i, f(path, self.parse_file(path)))),
+                            None => return res,
+                        }
+                    }
+                })
+            }).collect::<Result<_, _>>()?;
+            let mut res = vec![];
+            let mut panicked = false;
+            for worker in workers {
+                match worker.join() {
+                    Ok(x) => res.extend(x),
+                    Err(_) => panicked = true,
+                }
+            }
+            match panicked {
+                false => Ok(res),
+                true => Err(std::io::Error::other("a thread panicked while parsing a directory")),
+            }
+        })?;
+        results.sort_unstable_by_key(|x| x.0);
+        Ok(iter::zip(paths, results).map(|(path, (_, res))| (path, res)).collect())
+    }
+    /// Equivalent to [`Parser::parse_with_diagnostics`], but reuses the roles of a `previous` parse result whose xml is unchanged (see [`Role::source_hash`]).
+    /// Only the modified roles are parsed again, which is useful for services that repeatedly parse a project while it is being edited.
+    /// Warnings are only reported for the roles that were parsed again.
+    ///
+    /// Hashing the xml is only done by this method, so roles from [`Parser::parse`] (which have no [`Role::source_hash`]) are always parsed again.
+    /// To start a chain of reparses, `previous` can be a project with no roles.
+    ///
+    /// The result is only equivalent to a full parse if `previous` was produced by a parser with the same settings.
+    pub fn reparse(&self, previous: &Project, xml: &str) -> Result<(Project, Vec<Warning>), Box<Error>> {
+        self.parse_impl(xml, Some(previous), None)
+    }
+    /// Parses a role, or reuses the role of the same name from `previous` if its xml is unchanged.
+    fn parse_role(&self, role_xml: &Xml, name: CompactString, previous: Option<&Project>) -> Result<(Role, Vec<Warning>), Box<Error>> {
+        let source_hash = match previous {
+            Some(previous) => {
+                let hash = role_xml.content_hash();
+                if let Some(role) = previous.role(&name).filter(|x| x.source_hash == Some(hash)) {
+                    return Ok(reuse_role(role));
+                }
+                Some(hash)
+            }
+            None => None,
+        };
+        RoleInfo::new(self, name).parse(role_xml, source_hash)
+    }
+    /// Parses every project in a stream of concatenated project files (e.g., an archive dump), in order of appearance.
+    /// This is an error if the stream contains no projects.
+    pub fn parse_all(&self, xml: &str) -> Result<Vec<Project>, Box<Error>> {
+        self.parse_all_with_diagnostics(xml).map(|x| x.into_iter().map(|x| x.0).collect())
+    }
+    /// Equivalent to [`Parser::parse_all`], but additionally returns the warnings for each project (see [`Parser::parse_with_diagnostics`]).
+    pub fn parse_all_with_diagnostics(&self, xml: &str) -> Result<Vec<(Project, Vec<Warning>)>, Box<Error>> {
+        let mut res = vec![];
+        let mut rest = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+        loop {
+            // each project gets a fresh tokenizer, since the xml reader rejects content after the first root element
+            let mut xml = xmlparser::Tokenizer::from(rest);
+            match self.parse_next(&mut xml, None, None)? {
+                Some(project) => res.push(project),
+                None => break,
+            }
+            rest = &rest[xml.stream().pos()..];
+        }
+        if res.is_empty() {
+            return Err(no_root_error());
+        }
+        Ok(res)
+    }
+    #[inline(always)] // avoid an extra stack frame on the path to the role parser
+    fn parse_impl(&self, xml: &str, previous: Option<&Project>, source_map: Option<&mut SourceMap>) -> Result<(Project, Vec<Warning>), Box<Error>> {
+        let xml = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+        match self.parse_next(&mut xmlparser::Tokenizer::from(xml), previous, source_map)? {
+            Some(x) => Ok(x),
+            None => Err(no_root_error()),
+        }
+    }
+    /// Runs [`Parser::post_hooks`] and [`Parser::passes`], and computes the parts of the project which are derived from the rest of the ast.
+    /// If a source map is given, its temporary ids (see [`source_id`]) are replaced by the final ids of the nodes.
+    /// This is done separately from parsing to keep the parser's stack frames small.
+    #[inline(never)]
+    fn finish_project(&self, project: &mut Project, mut source_map: Option<&mut SourceMap>) -> Result<(), Box<Error>> {
+        // hooks and passes should never see the temporary ids, but may add or remove nodes, so ids are assigned again afterwards
+        let transformed = !self.post_hooks.is_empty() || !self.passes.is_empty();
+        if transformed {
+            assign_node_ids(project, source_map.as_deref_mut());
+        }
+        for role in project.roles.iter_mut() {
+            for hook in self.post_hooks.iter() {
+                hook(role)?;
+            }
+            role.reindex();
+        }
+        for pass in self.passes.iter() {
+            pass.run(project, &self.name_transformer);
+        }
+        assign_node_ids(project, source_map);
+        project.resolve_slots();
+        Ok(())
+    }
+    /// Parses the next project from the stream, or returns [`None`] if there are no more projects.
+    fn parse_next(&self, xml: &mut xmlparser::Tokenizer, previous: Option<&Project>, mut source_map: Option<&mut SourceMap>) -> Result<Option<(Project, Vec<Warning>)>, Box<Error>> {
+        let location = Box::new_with(|| LocationRef {
+            role: None,
+            entity: None,
+            collab_id: None,
+            block_type: None,
+        });
+
+        let skip: &[&str] = if self.skip_history { &["history", "replay"] } else { &[] };
+        let mut spans = source_map.is_some().then(Vec::new);
+        while let Some(Ok(e)) = xml.next() {
+            if let xmlparser::Token::ElementStart { local, span, .. } = e {
+                let (proj_name, meta, roles, mut warnings) = match local.as_str() {
+                    "room" => {
+                        let project_xml = match parse_xml_root(xml, local.as_str(), span.start(), skip, spans.as_mut()) {
+                            Ok(x) => x,
+                            Err(e) => return Err(Box::new_with(|| Error { kind: e.into(), location: location.to_owned() })),
+                        };
+                        if let (Some(source_map), Some(spans)) = (source_map.as_deref_mut(), spans.as_deref()) { source_map.add_elements(&project_xml, spans) }
+                        let proj_name = CompactString::new(project_xml.attr("name").map(|v| &*v.value).unwrap_or("untitled"));
+
+                        let mut roles = Vec::with_capacity(project_xml.children.len());
+                        let mut warnings = vec![];
+                        for child in project_xml.children.iter() {
+                            if child.name == "role" {
+                                let role_name = match child.attr("name") {
+                                    None => return Err(Box::new_with(|| Error { kind: ProjectError::RoleNoName.into(), location: location.to_owned() })),
+                                    Some(x) => CompactString::new(&x.value),
+                                };
+                                let (role, role_warnings) = self.parse_role(child, role_name, previous)?;
+                                roles.push(role);
+                                warnings.extend(role_warnings);
+                            }
+                        }
+
+                        (proj_name, ProjectMeta::parse(&project_xml), roles, warnings)
+                    }
+                    "role" => {
+                        let role_xml = match parse_xml_root(xml, local.as_str(), span.start(), skip, spans.as_mut()) {
+                            Ok(x) => x,
+                            Err(e) => return Err(Box::new_with(|| Error { kind: e.into(), location: location.to_owned() })),
+                        };
+                        if let (Some(source_map), Some(spans)) = (source_map.as_deref_mut(), spans.as_deref()) { source_map.add_elements(&role_xml, spans) }
+                        let proj_name = CompactString::new(role_xml.attr("name").map(|v| &*v.value).unwrap_or("untitled"));
+
+                        let (role, warnings) = self.parse_role(&role_xml, proj_name.clone(), previous)?;
+
+                        (proj_name, ProjectMeta::parse(&role_xml), vec![role], warnings)
+                    }
+                    "project" => {
+                        let project_xml = match parse_xml_root(xml, local.as_str(), span.start(), skip, spans.as_mut()) {
+                            Ok(x) => x,
+                            Err(e) => return Err(Box::new_with(|| Error { kind: e.into(), location: location.to_owned() })),
+                        };
+                        if let (Some(source_map), Some(spans)) = (source_map.as_deref_mut(), spans.as_deref()) { source_map.add_elements(&project_xml, spans) }
+                        let proj_name = CompactString::new(project_xml.attr("name").map(|v| &*v.value).unwrap_or("untitled").to_owned());
+
+                        let role_xml = Xml {
+                            name: "role",
+                            text: Cow::Borrowed(""),
+                            attrs: vec![XmlAttr { name: "name", value: Cow::Owned(proj_name.as_str().into()) }],
+                            children: vec![project_xml],
+                        };
+                        let (role, warnings) = self.parse_role(&role_xml, proj_name.clone(), previous)?;
+
+                        (proj_name, ProjectMeta::parse(&role_xml), vec![role], warnings)
+                    }
+                    _ => continue,
+                };
+
+                if !meta.is_supported() {
+                    warnings.push(Warning { kind: WarningKind::UnsupportedVersion { version: meta.version.clone().unwrap_or_default() }, location: location.to_owned() });
+                }
+                let mut project = Project { name: proj_name, meta, roles };
+                self.finish_project(&mut project, source_map)?;
+                return Ok(Some((project, warnings)))
+            }
+        }
+        Ok(None)
+    }
+}
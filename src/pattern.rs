@@ -0,0 +1,91 @@
+//! Pattern matching over nested [`Expr`](crate::Expr) trees (see [`expr_pat!`](crate::expr_pat)).
+
+/// Matches an [`Expr`](crate::Expr) against a nested pattern, looking through the [`Box`](alloc::boxed::Box)es which hold sub-expressions.
+///
+/// Patterns are written like the [`ExprKind`](crate::ExprKind) variants they match, but without the `ExprKind::` prefix or the surrounding [`Expr`](crate::Expr),
+/// and fields which are not mentioned are ignored (there is no need for `..`).
+/// Each field (or the whole pattern) can be one of the following:
+///
+/// - `_`, which matches anything.
+/// - A name, which binds the (borrowed) field. Shorthand fields such as `Variable { var }` bind the field to its own name.
+/// - `Variant { fields }`, which matches a sub-expression. Variants without fields are written with empty braces, such as `This {}`.
+/// - `Value(pattern)`, which matches a constant with an ordinary pattern over [`Value`](crate::Value).
+/// - Any other pattern, which is matched against the (borrowed) field as usual, such as `ClosureKind::Command`.
+///
+/// `expr_pat!(expr, pattern)` evaluates to a `bool`, while `expr_pat!(expr, pattern => result)` evaluates to `Some(result)` (with the bindings in scope) if the pattern matches, and `None` otherwise.
+/// Either form can have a guard, as in `expr_pat!(expr, pattern if condition => result)`.
+///
+/// For instance, `expr_pat!(expr, Sub { left: Value(Value::Number(x)), right: Variable { var } } => (*x, var))` gives the number and variable of `5 - v`,
+/// while `expr_pat!(expr, Not { value: Not { value: inner } } => inner)` gives the operand of a double negation.
+#[macro_export]
+macro_rules! expr_pat {
+    (@split $e:expr, [$($p:tt)*], if $guard:expr => $out:expr) => {{
+        let expr: &$crate::Expr = &$e;
+        $crate::expr_pat!(@expr expr, [$($p)*], { if $guard { Some($out) } else { None } }, { None })
+    }};
+    (@split $e:expr, [$($p:tt)*], => $out:expr) => {{
+        let expr: &$crate::Expr = &$e;
+        $crate::expr_pat!(@expr expr, [$($p)*], { Some($out) }, { None })
+    }};
+    (@split $e:expr, [$($p:tt)*], if $guard:expr) => {{
+        let expr: &$crate::Expr = &$e;
+        $crate::expr_pat!(@expr expr, [$($p)*], { $guard }, { false })
+    }};
+    (@split $e:expr, [$($p:tt)*],) => {{
+        let expr: &$crate::Expr = &$e;
+        $crate::expr_pat!(@expr expr, [$($p)*], { true }, { false })
+    }};
+    (@split $e:expr, [$($p:tt)*], $t:tt $($rest:tt)*) => {
+        $crate::expr_pat!(@split $e, [$($p)* $t], $($rest)*)
+    };
+
+    // matches a single (possibly boxed) expression
+    (@expr $x:ident, [_], $k:tt, $fail:tt) => { $k };
+    (@expr $x:ident, [$b:ident], $k:tt, $fail:tt) => {{ let $b = $x; $k }};
+    (@expr $x:ident, [Value($($p:tt)*)], $k:tt, $fail:tt) => {
+        match &$x.kind {
+            $crate::ExprKind::Value($($p)*) => $k,
+            #[allow(unreachable_patterns)]
+            _ => $fail,
+        }
+    };
+    (@expr $x:ident, [$v:ident { $($fields:tt)* }], $k:tt, $fail:tt) => {
+        match &$x.kind {
+            $crate::ExprKind::$v { .. } => $crate::expr_pat!(@fields $x, $v, [$($fields)*], $k, $fail),
+            #[allow(unreachable_patterns)]
+            _ => $fail,
+        }
+    };
+
+    // matches the fields of a variant one at a time, so that each gets a fresh (hygienic) binding
+    (@fields $x:ident, $v:ident, [], $k:tt, $fail:tt) => { $k };
+    (@fields $x:ident, $v:ident, [..], $k:tt, $fail:tt) => { $k };
+    (@fields $x:ident, $v:ident, [$f:ident], $k:tt, $fail:tt) => { $crate::expr_pat!(@fields $x, $v, [$f: $f], $k, $fail) };
+    (@fields $x:ident, $v:ident, [$f:ident, $($rest:tt)*], $k:tt, $fail:tt) => { $crate::expr_pat!(@fields $x, $v, [$f: $f, $($rest)*], $k, $fail) };
+    (@fields $x:ident, $v:ident, [$f:ident : $($rest:tt)*], $k:tt, $fail:tt) => { $crate::expr_pat!(@take $x, $v, $f, [], [$($rest)*], $k, $fail) };
+    (@take $x:ident, $v:ident, $f:ident, [$($p:tt)*], [$(, $($rest:tt)*)?], $k:tt, $fail:tt) => {
+        match &$x.kind {
+            $crate::ExprKind::$v { $f: field, .. } => $crate::expr_pat!(@field field, [$($p)*], { $crate::expr_pat!(@fields $x, $v, [$($($rest)*)?], $k, $fail) }, $fail),
+            #[allow(unreachable_patterns)]
+            _ => $fail,
+        }
+    };
+    (@take $x:ident, $v:ident, $f:ident, [$($p:tt)*], [$t:tt $($rest:tt)*], $k:tt, $fail:tt) => {
+        $crate::expr_pat!(@take $x, $v, $f, [$($p)* $t], [$($rest)*], $k, $fail)
+    };
+
+    // matches a field, which is either a sub-expression or an ordinary value
+    (@field $x:ident, [_], $k:tt, $fail:tt) => { $k };
+    (@field $x:ident, [$b:ident], $k:tt, $fail:tt) => {{ let $b = $x; $k }};
+    (@field $x:ident, [Value($($p:tt)*)], $k:tt, $fail:tt) => { $crate::expr_pat!(@expr $x, [Value($($p)*)], $k, $fail) };
+    (@field $x:ident, [$v:ident { $($fields:tt)* }], $k:tt, $fail:tt) => { $crate::expr_pat!(@expr $x, [$v { $($fields)* }], $k, $fail) };
+    (@field $x:ident, [$($p:tt)*], $k:tt, $fail:tt) => {
+        match $x {
+            $($p)* => $k,
+            #[allow(unreachable_patterns)]
+            _ => $fail,
+        }
+    };
+
+    ($e:expr, $($t:tt)+) => { $crate::expr_pat!(@split $e, [], $($t)+) };
+}
@@ -0,0 +1,9 @@
+//! The most commonly used items of this crate, for use with `use netsblox_ast::prelude::*;`.
+//!
+//! Everything here is also exported from the crate root.
+
+pub use crate::{CompactString, format_compact};
+pub use crate::{Parser, Strictness, EmptySlots, NameTransformer, SymbolTable};
+pub use crate::{Error, ErrorKind, XmlError, ProjectError, CompileError, Warning, WarningKind, Location};
+pub use crate::{Project, Role, Entity, Function, Script, Hat, HatKind, Stmt, StmtKind, Expr, ExprKind, Value, BlockInfo, NodeId};
+pub use crate::{VariableRef, VariableDef, VariableDefInit, VarLocation, FnRef, FnLocation};
@@ -0,0 +1,529 @@
+//! A small combinator API for finding nodes in a project, so that analyses do not need to hand-roll their own traversals.
+//!
+//! A query starts with every block in the project (see [`Project::query`]), and each filter narrows it down.
+//! For instance, `project.query().blocks("doSetVar").in_sprite("Player")` finds all the `set` blocks in the sprite named `Player`.
+
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+pub(crate) fn hat_opcode(kind: &HatKind) -> &str {
+    match kind {
+        HatKind::OnFlag => "receiveGo",
+        HatKind::OnClone => "receiveOnClone",
+        HatKind::OnKey { .. } => "receiveKey",
+        HatKind::MouseDown | HatKind::MouseUp | HatKind::MouseEnter | HatKind::MouseLeave
+        | HatKind::ScrollUp | HatKind::ScrollDown | HatKind::Dropped | HatKind::Stopped => "receiveInteraction",
+        HatKind::When { .. } => "receiveCondition",
+        HatKind::LocalMessage { .. } => "receiveMessage",
+        HatKind::NetworkMessage { .. } => "receiveSocketMessage",
+        HatKind::Unknown { name, .. } | HatKind::Custom { name, .. } => name,
+    }
+}
+pub(crate) fn stmt_opcode(kind: &StmtKind) -> Option<&str> {
+    Some(match kind {
+        StmtKind::DeclareLocals { .. } => "doDeclareVariables",
+        StmtKind::Assign { .. } => "doSetVar",
+        StmtKind::AddAssign { .. } => "doChangeVar",
+        StmtKind::ShowVar { .. } => "doShowVar",
+        StmtKind::HideVar { .. } => "doHideVar",
+        StmtKind::Warp { .. } => "doWarp",
+        StmtKind::InfLoop { .. } => "doForever",
+        StmtKind::ForeachLoop { .. } => "doForEach",
+        StmtKind::ForLoop { .. } => "doFor",
+        StmtKind::UntilLoop { .. } => "doUntil",
+        StmtKind::Repeat { .. } => "doRepeat",
+        StmtKind::If { .. } => "doIf",
+        StmtKind::IfElse { .. } => "doIfElse",
+        StmtKind::TryCatch { .. } => "doTryCatch",
+        StmtKind::Throw { .. } => "doThrow",
+        StmtKind::ListInsert { .. } | StmtKind::ListInsertRandom { .. } => "doInsertInList",
+        StmtKind::ListInsertLast { .. } => "doAddToList",
+        StmtKind::ListRemove { .. } | StmtKind::ListRemoveLast { .. } | StmtKind::ListRemoveAll { .. } => "doDeleteFromList",
+        StmtKind::ListAssign { .. } | StmtKind::ListAssignLast { .. } | StmtKind::ListAssignRandom { .. } => "doReplaceInList",
+        StmtKind::Return { .. } => "doReport",
+        StmtKind::Sleep { .. } => "doWait",
+        StmtKind::WaitUntil { .. } => "doWaitUntil",
+        StmtKind::SetCostume { .. } => "doSwitchToCostume",
+        StmtKind::NextCostume => "doWearNextCostume",
+        StmtKind::PlaySound { blocking, .. } => if *blocking { "doPlaySoundUntilDone" } else { "playSound" },
+        StmtKind::PlayNotes { .. } => "doPlayNote",
+        StmtKind::Rest { .. } => "doRest",
+        StmtKind::StopSounds => "doStopAllSounds",
+        StmtKind::Forward { .. } => "forward",
+        StmtKind::SetX { .. } => "setXPosition",
+        StmtKind::ChangeX { .. } => "changeXPosition",
+        StmtKind::SetY { .. } => "setYPosition",
+        StmtKind::ChangeY { .. } => "changeYPosition",
+        StmtKind::GotoXY { .. } => "gotoXY",
+        StmtKind::GotoMouse | StmtKind::GotoRandom | StmtKind::Goto { .. } => "doGotoObject",
+        StmtKind::PointTowards { .. } | StmtKind::PointTowardsXY { .. } => "doFaceTowards",
+        StmtKind::TurnRight { .. } => "turn",
+        StmtKind::TurnLeft { .. } => "turnLeft",
+        StmtKind::SetHeading { .. } | StmtKind::SetHeadingRandom => "setHeading",
+        StmtKind::BounceOffEdge => "bounceOffEdge",
+        StmtKind::SetPenDown { value } => if *value { "down" } else { "up" },
+        StmtKind::PenClear => "clear",
+        StmtKind::Stamp => "doStamp",
+        StmtKind::Write { .. } => "write",
+        StmtKind::SetPenColor { .. } => "setColor",
+        StmtKind::Say { duration, .. } => if duration.is_some() { "doSayFor" } else { "bubble" },
+        StmtKind::Think { duration, .. } => if duration.is_some() { "doThinkFor" } else { "doThink" },
+        StmtKind::SetVisible { value } => if *value { "show" } else { "hide" },
+        StmtKind::ChangeSize { .. } => "changeScale",
+        StmtKind::SetSize { .. } => "setScale",
+        StmtKind::ChangePenSize { .. } => "changeSize",
+        StmtKind::SetPenSize { .. } => "setSize",
+        StmtKind::CallRpc { .. } => "doRunRPC",
+        StmtKind::CallFn { .. } => return None,
+        StmtKind::CallClosure { new_entity, .. } => if new_entity.is_some() { "doTellTo" } else { "doRun" },
+        StmtKind::ForkClosure { .. } => "fork",
+        StmtKind::Clone { .. } => "createClone",
+        StmtKind::DeleteClone => "removeClone",
+        StmtKind::SendLocalMessage { target, wait, .. } => match (target, wait) {
+            (Some(_), _) => "doSend",
+            (None, true) => "doBroadcastAndWait",
+            (None, false) => "doBroadcast",
+        }
+        StmtKind::SendNetworkMessage { .. } => "doSocketMessage",
+        StmtKind::SendNetworkReply { .. } => "doSocketResponse",
+        StmtKind::Ask { .. } => "doAsk",
+        StmtKind::ResetTimer => "doResetTimer",
+        StmtKind::Pause => "doPauseAll",
+        StmtKind::SetEffect { .. } => "setEffect",
+        StmtKind::ChangeEffect { .. } => "changeEffect",
+        StmtKind::ClearEffects => "clearEffects",
+        StmtKind::SetPenAttr { .. } => "setPenHSVA",
+        StmtKind::ChangePenAttr { .. } => "changePenHSVA",
+        StmtKind::SetGlobalFlag { .. } => "doSetGlobalFlag",
+        StmtKind::SetTempo { .. } => "doSetTempo",
+        StmtKind::ChangeTempo { .. } => "doChangeTempo",
+        StmtKind::Stop { .. } => "doStopThis",
+        StmtKind::DefineBlock { .. } => "doDefineBlock",
+        StmtKind::SetBlockAttribute { .. } => "doSetBlockAttribute",
+        StmtKind::MapCode { .. } => "doMapCodeOrHeader",
+        StmtKind::UnknownBlock { name, .. } => name,
+    })
+}
+pub(crate) fn expr_opcode(kind: &ExprKind) -> Option<&str> {
+    Some(match kind {
+        ExprKind::Value(_) | ExprKind::Variable { .. } | ExprKind::CallFn { .. } | ExprKind::EmptySlot => return None,
+        ExprKind::Add { .. } => "reportVariadicSum",
+        ExprKind::Mul { .. } => "reportVariadicProduct",
+        ExprKind::Min { .. } => "reportVariadicMin",
+        ExprKind::Max { .. } => "reportVariadicMax",
+        ExprKind::Sub { .. } => "reportDifference",
+        ExprKind::Div { .. } => "reportQuotient",
+        ExprKind::Mod { .. } => "reportModulus",
+        ExprKind::Pow { .. } => "reportPower",
+        ExprKind::Atan2 { .. } => "reportAtan2",
+        ExprKind::Log { .. } | ExprKind::Neg { .. } | ExprKind::Abs { .. } | ExprKind::Sign { .. } | ExprKind::Sqrt { .. }
+        | ExprKind::Floor { .. } | ExprKind::Ceil { .. } | ExprKind::Sin { .. } | ExprKind::Cos { .. } | ExprKind::Tan { .. }
+        | ExprKind::Asin { .. } | ExprKind::Acos { .. } | ExprKind::Atan { .. } => "reportMonadic",
+        ExprKind::And { .. } => "reportAnd",
+        ExprKind::Or { .. } => "reportOr",
+        ExprKind::Conditional { .. } => "reportIfElse",
+        ExprKind::Identical { .. } => "reportIsIdentical",
+        ExprKind::Eq { .. } => "reportEquals",
+        ExprKind::Neq { .. } => "reportNotEquals",
+        ExprKind::Less { .. } => "reportLessThan",
+        ExprKind::LessEq { .. } => "reportLessThanOrEquals",
+        ExprKind::Greater { .. } => "reportGreaterThan",
+        ExprKind::GreaterEq { .. } => "reportGreaterThanOrEquals",
+        ExprKind::Random { .. } => "reportRandom",
+        ExprKind::Range { .. } => "reportNumbers",
+        ExprKind::MakeList { .. } | ExprKind::CopyList { .. } => "reportNewList",
+        ExprKind::ListCat { .. } => "reportConcatenatedLists",
+        ExprKind::ListLen { .. } | ExprKind::ListRank { .. } | ExprKind::ListDims { .. } | ExprKind::ListFlatten { .. } | ExprKind::ListColumns { .. }
+        | ExprKind::ListRev { .. } | ExprKind::ListLines { .. } | ExprKind::ListCsv { .. } | ExprKind::ListJson { .. } => "reportListAttribute",
+        ExprKind::ListReshape { .. } => "reportReshape",
+        ExprKind::ListCombinations { .. } => "reportCrossproduct",
+        ExprKind::ListIsEmpty { .. } => "reportListIsEmpty",
+        ExprKind::ListCdr { .. } => "reportCDR",
+        ExprKind::ListCons { .. } => "reportCONS",
+        ExprKind::ListFind { .. } => "reportListIndex",
+        ExprKind::ListContains { .. } => "reportListContainsItem",
+        ExprKind::ListGet { .. } | ExprKind::ListGetLast { .. } | ExprKind::ListGetRandom { .. } => "reportListItem",
+        ExprKind::StrGet { .. } | ExprKind::StrGetLast { .. } | ExprKind::StrGetRandom { .. } => "reportLetter",
+        ExprKind::StrCat { .. } => "reportJoinWords",
+        ExprKind::StrLen { .. } => "reportStringSize",
+        ExprKind::UnicodeToChar { .. } => "reportUnicodeAsLetter",
+        ExprKind::CharToUnicode { .. } => "reportUnicode",
+        ExprKind::Not { .. } => "reportNot",
+        ExprKind::Round { .. } => "reportRound",
+        ExprKind::CallRpc { .. } => "getJSFromRPCStruct",
+        ExprKind::CallClosure { new_entity, .. } => if new_entity.is_some() { "reportAskFor" } else { "evaluate" },
+        ExprKind::StageWidth => "reportStageWidth",
+        ExprKind::StageHeight => "reportStageHeight",
+        ExprKind::MouseX => "reportMouseX",
+        ExprKind::MouseY => "reportMouseY",
+        ExprKind::Latitude => "reportLatitude",
+        ExprKind::Longitude => "reportLongitude",
+        ExprKind::KeyDown { .. } => "reportKeyPressed",
+        ExprKind::YPos => "yPosition",
+        ExprKind::XPos => "xPosition",
+        ExprKind::Heading => "direction",
+        ExprKind::PenDown => "getPenDown",
+        ExprKind::Size => "getScale",
+        ExprKind::IsVisible => "reportShown",
+        ExprKind::This | ExprKind::Entity { .. } => "reportObject",
+        ExprKind::ImageOfEntity { .. } => "reportImageOfObject",
+        ExprKind::ImageOfDrawings => "reportPenTrailsAsCostume",
+        ExprKind::IsTouchingEntity { .. } | ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingDrawings => "reportTouchingObject",
+        ExprKind::RpcError => "reportRPCError",
+        ExprKind::Closure { kind, .. } => match kind {
+            ClosureKind::Command => "reifyScript",
+            ClosureKind::Reporter => "reifyReporter",
+            ClosureKind::Predicate => "reifyPredicate",
+        }
+        ExprKind::TextSplit { .. } => "reportTextSplit",
+        ExprKind::TextFunction { .. } => "reportTextFunction",
+        ExprKind::Answer => "getLastAnswer",
+        ExprKind::Message => "getLastMessage",
+        ExprKind::Timer => "getTimer",
+        ExprKind::Map { .. } => "reportMap",
+        ExprKind::Keep { .. } => "reportKeep",
+        ExprKind::FindFirst { .. } => "reportFindFirst",
+        ExprKind::Combine { .. } => "reportCombine",
+        ExprKind::Pipe { .. } => "reportPipe",
+        ExprKind::NetworkMessageReply { .. } => "doSocketRequest",
+        ExprKind::Effect { .. } => "getEffect",
+        ExprKind::PenAttr { .. } => "getPenAttribute",
+        ExprKind::GlobalFlag { .. } => "reportGlobalFlag",
+        ExprKind::Tempo => "getTempo",
+        ExprKind::CostumeList | ExprKind::Costume | ExprKind::SoundList => "reportGet",
+        ExprKind::CostumeNumber => "getCostumeIdx",
+        ExprKind::CostumeName { .. } | ExprKind::CostumeWidth { .. } | ExprKind::CostumeHeight { .. } | ExprKind::CostumePixels { .. } => "reportGetImageAttribute",
+        ExprKind::SoundName { .. } | ExprKind::SoundDuration { .. } | ExprKind::SoundSampleRate { .. } | ExprKind::SoundSamples { .. }
+        | ExprKind::SoundSamplesLength { .. } | ExprKind::SoundChannelCount { .. } => "reportGetSoundAttribute",
+        ExprKind::Clone { .. } => "newClone",
+        ExprKind::TypeQuery { .. } => "reportIsA",
+        ExprKind::RealTime { .. } => "reportDate",
+        ExprKind::BlockAttribute { .. } => "reportBlockAttribute",
+        ExprKind::MappedCode { .. } => "reportMappedCode",
+        ExprKind::JsFunction { .. } => "reportJSFunction",
+        ExprKind::UnknownBlock { name, .. } => name,
+    })
+}
+
+impl Hat {
+    /// Gets the (Snap) opcode of the hat block, such as `"receiveGo"`.
+    /// As with [`Stmt::opcode`], this is the opcode that was written if the block was lowered into a different AST shape.
+    pub fn opcode(&self) -> &str {
+        self.info.opcode.as_deref().unwrap_or_else(|| hat_opcode(&self.kind))
+    }
+    /// Gets the comment attached to the hat block, if any.
+    pub fn comment(&self) -> Option<&str> {
+        self.info.comment.as_deref()
+    }
+    /// Gets the direct children of the hat block, which is the condition of a [`HatKind::When`] (the script body is not included).
+    pub fn children(&self) -> Vec<QueryNode<'_>> {
+        match &self.kind {
+            HatKind::When { condition } => vec![QueryNode::Expr(condition)],
+            _ => vec![],
+        }
+    }
+}
+impl Stmt {
+    /// Gets the (Snap) opcode of the block, such as `"doSetVar"`, or `None` for custom block calls.
+    ///
+    /// If the block was lowered into a different AST shape, this is the opcode that was written (see [`BlockInfo::opcode`]).
+    /// Otherwise, blocks which have several equivalent opcodes give the one Snap currently uses.
+    pub fn opcode(&self) -> Option<&str> {
+        self.info.opcode.as_deref().or_else(|| stmt_opcode(&self.kind))
+    }
+    /// Gets the comment attached to the block, if any.
+    pub fn comment(&self) -> Option<&str> {
+        self.info.comment.as_deref()
+    }
+    /// Gets the direct children of the block: its sub-expressions, followed by the statements in its nested scripts (e.g., a loop body).
+    /// This works for every kind of block, so it can be used to traverse code without matching on [`StmtKind`].
+    pub fn children(&self) -> Vec<QueryNode<'_>> {
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        stmt_children(&self.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        children(exprs, bodies)
+    }
+}
+impl Expr {
+    /// Gets the (Snap) opcode of the block, such as `"reportVariadicSum"`, or `None` for values, variables, custom block calls, and empty slots.
+    ///
+    /// If the block was lowered into a different AST shape, this is the opcode that was written (see [`BlockInfo::opcode`]).
+    /// Otherwise, blocks which have several equivalent opcodes give the one Snap currently uses.
+    pub fn opcode(&self) -> Option<&str> {
+        self.info.opcode.as_deref().or_else(|| expr_opcode(&self.kind))
+    }
+    /// Gets the comment attached to the block, if any.
+    pub fn comment(&self) -> Option<&str> {
+        self.info.comment.as_deref()
+    }
+    /// Gets the direct children of the block: its sub-expressions, followed by the statements in its nested scripts (e.g., a closure body).
+    /// This works for every kind of block, so it can be used to traverse code without matching on [`ExprKind`].
+    pub fn children(&self) -> Vec<QueryNode<'_>> {
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&self.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        children(exprs, bodies)
+    }
+}
+fn children<'a>(exprs: Vec<&'a Expr>, bodies: Vec<&'a Vec<Stmt>>) -> Vec<QueryNode<'a>> {
+    exprs.into_iter().map(QueryNode::Expr).chain(bodies.into_iter().flatten().map(QueryNode::Stmt)).collect()
+}
+
+/// A node found by a [`Query`].
+#[derive(Debug, Clone, Copy)]
+pub enum QueryNode<'a> {
+    Hat(&'a Hat),
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+impl<'a> QueryNode<'a> {
+    pub fn info(&self) -> &'a BlockInfo {
+        match self {
+            QueryNode::Hat(x) => &x.info,
+            QueryNode::Stmt(x) => &x.info,
+            QueryNode::Expr(x) => &x.info,
+        }
+    }
+    pub fn id(&self) -> NodeId {
+        self.info().id
+    }
+    /// Gets the opcode of the node (see [`Stmt::opcode`] and [`Expr::opcode`]).
+    pub fn opcode(&self) -> Option<&'a str> {
+        match self {
+            QueryNode::Hat(x) => Some(x.opcode()),
+            QueryNode::Stmt(x) => x.opcode(),
+            QueryNode::Expr(x) => x.opcode(),
+        }
+    }
+    /// Gets the comment attached to the node, if any.
+    pub fn comment(&self) -> Option<&'a str> {
+        self.info().comment.as_deref()
+    }
+    /// Gets the direct children of the node (see [`Stmt::children`] and [`Expr::children`]).
+    pub fn children(&self) -> Vec<QueryNode<'a>> {
+        match self {
+            QueryNode::Hat(x) => x.children(),
+            QueryNode::Stmt(x) => x.children(),
+            QueryNode::Expr(x) => x.children(),
+        }
+    }
+    /// Calls `f` on the name of every variable directly referenced or declared by this node (not including its children).
+    fn vars(&self, f: &mut dyn FnMut(&'a CompactString)) {
+        match self {
+            QueryNode::Hat(hat) => match &hat.kind {
+                HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for var in fields { f(&var.name) },
+                _ => (),
+            }
+            QueryNode::Stmt(stmt) => match &stmt.kind {
+                StmtKind::DeclareLocals { vars } => for var in vars { f(&var.name) },
+                StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var }
+                | StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. }
+                | StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } => f(&var.name),
+                StmtKind::CallFn { upvars, .. } => for var in upvars { f(&var.name) },
+                _ => (),
+            }
+            QueryNode::Expr(expr) => match &expr.kind {
+                ExprKind::Variable { var } => f(&var.name),
+                ExprKind::CallFn { upvars, .. } => for var in upvars { f(&var.name) },
+                ExprKind::Closure { params, captures, .. } => {
+                    for var in params { f(&var.name) }
+                    for var in captures { f(&var.name) }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// A node found by a [`Query`], along with the code that contains it.
+#[derive(Clone)]
+pub struct QueryMatch<'a> {
+    pub node: QueryNode<'a>,
+    pub role: &'a Role,
+    /// The entity containing the node, or `None` for global custom blocks.
+    pub entity: Option<&'a Entity>,
+    /// The custom block containing the node, or `None` for scripts and floating reporters.
+    pub func: Option<&'a Function>,
+    /// The nodes enclosing this one, from outermost to innermost (starting with the hat block, if any).
+    pub ancestors: Vec<QueryNode<'a>>,
+}
+impl QueryMatch<'_> {
+    /// Gets the location of the node, as used for diagnostics.
+    pub fn location(&self) -> Location {
+        Location {
+            role: Some(self.role.name.clone()),
+            entity: self.entity.map(|x| x.name.clone()),
+            collab_id: self.node.info().location.clone(),
+            block_type: self.node.opcode().map(CompactString::new),
+        }
+    }
+}
+
+struct Collector<'a> {
+    role: &'a Role,
+    entity: Option<&'a Entity>,
+    func: Option<&'a Function>,
+    ancestors: Vec<QueryNode<'a>>,
+    res: Vec<QueryMatch<'a>>,
+}
+impl<'a> Collector<'a> {
+    fn node(&mut self, node: QueryNode<'a>, exprs: Vec<&'a Expr>, bodies: Vec<&'a Vec<Stmt>>) {
+        self.res.push(QueryMatch { node, role: self.role, entity: self.entity, func: self.func, ancestors: self.ancestors.clone() });
+        self.ancestors.push(node);
+        for x in exprs { self.expr(x) }
+        for x in bodies { self.stmts(x) }
+        self.ancestors.pop();
+    }
+    fn script(&mut self, script: &'a Script) {
+        match script.hat.as_deref() {
+            Some(hat) => {
+                let condition = match &hat.kind {
+                    HatKind::When { condition } => vec![&**condition],
+                    _ => vec![],
+                };
+                self.node(QueryNode::Hat(hat), condition, vec![&script.stmts]);
+            }
+            None => self.stmts(&script.stmts),
+        }
+    }
+    fn stmts(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts {
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            self.node(QueryNode::Stmt(stmt), exprs, bodies);
+        }
+    }
+    fn expr(&mut self, expr: &'a Expr) {
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        self.node(QueryNode::Expr(expr), exprs, bodies);
+    }
+}
+
+/// A set of nodes in a project, which can be narrowed down by chaining filters (see [`Project::query`]).
+///
+/// Nodes are listed in pre-order: each node comes before its children, and code is visited in the same order as [`Role::funcs`] and [`Role::entities`].
+#[derive(Clone)]
+pub struct Query<'a> {
+    matches: Vec<QueryMatch<'a>>,
+}
+impl<'a> Query<'a> {
+    /// Keeps only the nodes for which `f` returns `true`.
+    /// This is the most general filter, and can be used for arbitrary structural patterns.
+    pub fn matching(mut self, mut f: impl FnMut(&QueryMatch<'a>) -> bool) -> Self {
+        self.matches.retain(|x| f(x));
+        self
+    }
+    /// Keeps only the blocks with the given opcode (see [`Stmt::opcode`] and [`Expr::opcode`]).
+    pub fn blocks(self, opcode: &str) -> Self {
+        self.matching(|x| x.node.opcode() == Some(opcode))
+    }
+    /// Keeps only hat blocks.
+    pub fn hats(self) -> Self {
+        self.matching(|x| matches!(x.node, QueryNode::Hat(_)))
+    }
+    /// Keeps only statements (command blocks).
+    pub fn stmts(self) -> Self {
+        self.matching(|x| matches!(x.node, QueryNode::Stmt(_)))
+    }
+    /// Keeps only expressions (reporters, values, and variables).
+    pub fn exprs(self) -> Self {
+        self.matching(|x| matches!(x.node, QueryNode::Expr(_)))
+    }
+    /// Keeps only the nodes which directly reference or declare a variable with the given (untransformed) name.
+    /// This includes variable blocks, setters, loops, script variable declarations, upvars, and closure parameters and captures.
+    pub fn variable(self, name: &str) -> Self {
+        self.matching(|x| {
+            let mut found = false;
+            x.node.vars(&mut |var| found |= var == name);
+            found
+        })
+    }
+    /// Keeps only the calls to the custom block with the given (untransformed) name.
+    pub fn calls(self, name: &str) -> Self {
+        self.matching(|x| match x.node {
+            QueryNode::Stmt(Stmt { kind: StmtKind::CallFn { function, .. }, .. }) | QueryNode::Expr(Expr { kind: ExprKind::CallFn { function, .. }, .. }) => function.name == name,
+            _ => false,
+        })
+    }
+    /// Keeps only the nodes which are (transitively) inside of a block with the given opcode.
+    pub fn inside(self, opcode: &str) -> Self {
+        self.matching(|x| x.ancestors.iter().any(|x| x.opcode() == Some(opcode)))
+    }
+    /// Keeps only the nodes in the role with the given name.
+    pub fn in_role(self, name: &str) -> Self {
+        self.matching(|x| x.role.name == name)
+    }
+    /// Keeps only the nodes in the sprite (or stage) with the given name.
+    /// This does not include global custom blocks, even if the sprite calls them.
+    pub fn in_sprite(self, name: &str) -> Self {
+        self.matching(|x| x.entity.is_some_and(|x| x.name == name))
+    }
+    /// Keeps only the nodes in the definition of a custom block with the given (untransformed) name.
+    pub fn in_function(self, name: &str) -> Self {
+        self.matching(|x| x.func.is_some_and(|x| x.name == name))
+    }
+    /// Keeps only the nodes in scripts (including floating reporters), as opposed to custom block definitions.
+    pub fn in_scripts(self) -> Self {
+        self.matching(|x| x.func.is_none())
+    }
+
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+    pub fn iter(&self) -> core::slice::Iter<'_, QueryMatch<'a>> {
+        self.matches.iter()
+    }
+    /// Gets the nodes which were found.
+    pub fn nodes(&self) -> impl Iterator<Item = QueryNode<'a>> + '_ {
+        self.matches.iter().map(|x| x.node)
+    }
+    /// Gets the ids of the nodes which were found.
+    pub fn ids(&self) -> Vec<NodeId> {
+        self.matches.iter().map(|x| x.node.id()).collect()
+    }
+}
+impl<'a> IntoIterator for Query<'a> {
+    type Item = QueryMatch<'a>;
+    type IntoIter = alloc::vec::IntoIter<QueryMatch<'a>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.matches.into_iter()
+    }
+}
+
+impl Project {
+    /// Starts a [`Query`] over every block in the project (hat blocks, statements, and expressions), including custom block definitions and floating reporters.
+    pub fn query(&self) -> Query<'_> {
+        let mut res = Vec::new();
+        for role in self.roles.iter() {
+            let mut collector = Collector { role, entity: None, func: None, ancestors: Vec::new(), res };
+            for func in role.funcs.iter() {
+                collector.func = Some(func);
+                collector.stmts(&func.stmts);
+            }
+            for entity in role.entities.iter() {
+                collector.entity = Some(entity);
+                for func in entity.funcs.iter() {
+                    collector.func = Some(func);
+                    collector.stmts(&func.stmts);
+                }
+                collector.func = None;
+                for script in entity.scripts.iter() {
+                    collector.script(script);
+                }
+                for expr in entity.floating_reporters.iter() {
+                    collector.expr(expr);
+                }
+            }
+            res = collector.res;
+        }
+        Query { matches: res }
+    }
+}
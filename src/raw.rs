@@ -0,0 +1,277 @@
+//! A raw tree of the xml of a project, which refers to the source xml rather than copying it (see [`Parser::parse_raw`]).
+//!
+//! This is not an ast: the tree keeps the shape of the xml, with blocks given by their selectors and inputs as written in the source,
+//! and none of the lowering, name resolution, or validation done by [`Parser::parse`] is applied.
+//! It is meant for tools which only need to inspect the blocks of a project (e.g., scanning a corpus for particular selectors),
+//! and anything which needs the semantics of the code should use the [`Project`](crate::Project) ast instead.
+//!
+//! Every string in the tree is a [`Cow`] which borrows from the source, and is only owned if it had to be unescaped (e.g., text containing `&amp;`).
+//! Media is kept as the (still encoded) data url from the source, which can be decoded on demand with [`Media::decode`].
+//! This avoids copying the source for large projects whose xml outlives the tree.
+//! Variables are referred to by name, and no validation is done beyond what is needed to find the roles, entities, and scripts.
+//!
+//! This module is only available with the `raw` feature.
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use base64::engine::Engine as Base64Engine;
+
+use crate::*;
+use crate::util::*;
+use crate::parser::{Xml, parse_xml_root, no_root_error};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project<'src> {
+    pub name: Cow<'src, str>,
+    pub roles: Vec<Role<'src>>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Role<'src> {
+    pub name: Cow<'src, str>,
+    pub notes: Cow<'src, str>,
+    pub globals: Vec<Variable<'src>>,
+    /// The global custom block definitions.
+    pub funcs: Vec<Function<'src>>,
+    /// The stage, followed by the sprites.
+    pub entities: Vec<Entity<'src>>,
+    /// The costumes and sounds of every entity, which are referred to by [`Media::id`].
+    pub media: Vec<Media<'src>>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity<'src> {
+    pub name: Cow<'src, str>,
+    pub fields: Vec<Variable<'src>>,
+    /// The sprite-local custom block definitions.
+    pub funcs: Vec<Function<'src>>,
+    pub scripts: Vec<Script<'src>>,
+}
+/// A global or field, along with its initial value (if any).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable<'src> {
+    pub name: Cow<'src, str>,
+    pub init: Option<Input<'src>>,
+}
+/// A custom block definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function<'src> {
+    /// The block spec, such as `"foo %'x'"`, which includes the names of the parameters.
+    pub spec: Cow<'src, str>,
+    /// The kind of the block, which is `"command"`, `"reporter"`, or `"predicate"`.
+    pub kind: Cow<'src, str>,
+    pub category: Cow<'src, str>,
+    pub script: Script<'src>,
+}
+/// A sequence of blocks, such as a script in the scripting area or the body of a C-shaped block.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script<'src> {
+    pub blocks: Vec<Block<'src>>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind<'src> {
+    /// A primitive block with the given selector (e.g., `"forward"`).
+    Primitive(Cow<'src, str>),
+    /// A call to the custom block with the given spec (e.g., `"foo %n"`).
+    Custom(Cow<'src, str>),
+    /// A variable block, which reads the variable with the given name.
+    Variable(Cow<'src, str>),
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block<'src> {
+    pub kind: BlockKind<'src>,
+    pub inputs: Vec<Input<'src>>,
+    /// The text of the comment attached to the block, if present.
+    pub comment: Option<Cow<'src, str>>,
+}
+/// An input of a block, or the initial value of a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input<'src> {
+    Text(Cow<'src, str>),
+    /// A value chosen from a dropdown, such as `"sqrt"` for the function of `reportMonadic`.
+    Option(Cow<'src, str>),
+    Bool(bool),
+    Color(Cow<'src, str>),
+    Block(Box<Block<'src>>),
+    Script(Script<'src>),
+    List(Vec<Input<'src>>),
+    /// Any other element, such as the `autolambda` of a ring, holding the inputs nested in it.
+    Other { name: &'src str, inputs: Vec<Input<'src>> },
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Costume, Sound,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Media<'src> {
+    pub kind: MediaKind,
+    /// The id which entities use to refer to the media, such as `"Sprite_cst_airplane"`.
+    pub id: Cow<'src, str>,
+    pub name: Cow<'src, str>,
+    /// The encoded content as a data url, such as `"data:image/png;base64,..."`.
+    pub data: Cow<'src, str>,
+}
+impl Media<'_> {
+    /// Decodes the content of the media, or returns [`None`] if it is not a valid base64 data url.
+    pub fn decode(&self) -> Option<Vec<u8>> {
+        let (_, content) = self.data.strip_prefix("data:")?.split_once(";base64,")?;
+        base64::engine::general_purpose::STANDARD.decode(content).ok()
+    }
+}
+
+fn error(kind: ProjectError, role: Option<&str>) -> Box<Error> {
+    Box::new_with(|| Error { kind: kind.into(), location: Location { role: role.map(CompactString::new), entity: None, collab_id: None, block_type: None } })
+}
+/// Removes the first child of an element with the given name.
+fn take<'src>(xml: &mut Xml<'src>, name: &str) -> Option<Xml<'src>> {
+    let i = xml.children.iter().position(|x| x.name == name)?;
+    Some(xml.children.remove(i))
+}
+/// Removes the first attribute of an element with the given name.
+fn take_attr<'src>(xml: &mut Xml<'src>, name: &str) -> Option<Cow<'src, str>> {
+    let i = xml.attrs.iter().position(|x| x.name == name)?;
+    Some(xml.attrs.remove(i).value)
+}
+
+impl<'src> Project<'src> {
+    fn from_xml(mut xml: Xml<'src>) -> Result<Self, Box<Error>> {
+        let name = take_attr(&mut xml, "name").unwrap_or(Cow::Borrowed("untitled"));
+        let roles = match xml.name {
+            "room" => xml.children.into_iter().filter(|x| x.name == "role").map(|mut x| match take_attr(&mut x, "name") {
+                Some(name) => Role::from_xml(name, x),
+                None => Err(error(ProjectError::RoleNoName, None)),
+            }).collect::<Result<_, _>>()?,
+            "role" => vec![Role::from_xml(name.clone(), xml)?],
+            _ => {
+                // a bare project is the content of a role of the same name
+                let role = Xml { name: "role", text: Cow::Borrowed(""), attrs: vec![], children: vec![xml] };
+                vec![Role::from_xml(name.clone(), role)?]
+            }
+        };
+        Ok(Project { name, roles })
+    }
+}
+impl<'src> Role<'src> {
+    fn from_xml(name: Cow<'src, str>, mut xml: Xml<'src>) -> Result<Self, Box<Error>> {
+        let mut content = match take(&mut xml, "project") {
+            Some(x) => x,
+            None => return Err(error(ProjectError::RoleNoContent, Some(&name))),
+        };
+        let mut stage = match take(&mut content, "stage") {
+            Some(x) => x,
+            None => return Err(error(ProjectError::NoStage, Some(&name))),
+        };
+
+        let notes = take(&mut content, "notes").map(|x| x.text).unwrap_or_default();
+        let globals = variables(take(&mut content, "variables"));
+        let funcs = functions(take(&mut content, "blocks"));
+
+        let sprites = take(&mut stage, "sprites").map(|x| x.children).unwrap_or_default();
+        let mut entities = Vec::with_capacity(sprites.len() + 1);
+        entities.push(Entity::from_xml(stage));
+        entities.extend(sprites.into_iter().filter(|x| x.name == "sprite").map(Entity::from_xml));
+
+        let media = take(&mut xml, "media").map(|x| x.children).unwrap_or_default().into_iter().filter_map(|mut x| {
+            let (kind, data) = match x.name {
+                "costume" => (MediaKind::Costume, take_attr(&mut x, "image")?),
+                "sound" => (MediaKind::Sound, take_attr(&mut x, "sound")?),
+                _ => return None,
+            };
+            let id = take_attr(&mut x, "mediaID")?;
+            let name = take_attr(&mut x, "name").unwrap_or_default();
+            Some(Media { kind, id, name, data })
+        }).collect();
+
+        Ok(Role { name, notes, globals, funcs, entities, media })
+    }
+}
+impl<'src> Entity<'src> {
+    fn from_xml(mut xml: Xml<'src>) -> Self {
+        let name = take_attr(&mut xml, "name").unwrap_or_default();
+        let fields = variables(take(&mut xml, "variables"));
+        let funcs = functions(take(&mut xml, "blocks"));
+        let scripts = take(&mut xml, "scripts").map(|x| x.children).unwrap_or_default().into_iter().filter(|x| x.name == "script").map(Script::from_xml).collect();
+        Entity { name, fields, funcs, scripts }
+    }
+}
+fn variables(xml: Option<Xml>) -> Vec<Variable> {
+    xml.map(|x| x.children).unwrap_or_default().into_iter().filter(|x| x.name == "variable").filter_map(|mut x| {
+        let name = take_attr(&mut x, "name")?;
+        let init = x.children.into_iter().next().map(Input::from_xml);
+        Some(Variable { name, init })
+    }).collect()
+}
+fn functions(xml: Option<Xml>) -> Vec<Function> {
+    xml.map(|x| x.children).unwrap_or_default().into_iter().filter(|x| x.name == "block-definition").filter_map(|mut x| {
+        let spec = take_attr(&mut x, "s")?;
+        let kind = take_attr(&mut x, "type").unwrap_or(Cow::Borrowed("command"));
+        let category = take_attr(&mut x, "category").unwrap_or_default();
+        let script = take(&mut x, "script").map(Script::from_xml).unwrap_or_default();
+        Some(Function { spec, kind, category, script })
+    }).collect()
+}
+impl<'src> Script<'src> {
+    fn from_xml(xml: Xml<'src>) -> Self {
+        Script { blocks: xml.children.into_iter().filter_map(|x| Block::from_xml(x).ok()).collect() }
+    }
+}
+impl<'src> Block<'src> {
+    /// Converts a block element, or gives it back if it is not a block.
+    fn from_xml(mut xml: Xml<'src>) -> Result<Self, Xml<'src>> {
+        let kind = match xml.name {
+            "block" => match take_attr(&mut xml, "var") {
+                Some(name) => BlockKind::Variable(name),
+                None => match take_attr(&mut xml, "s") {
+                    Some(s) => BlockKind::Primitive(s),
+                    None => return Err(xml),
+                }
+            }
+            "custom-block" => match take_attr(&mut xml, "s") {
+                Some(s) => BlockKind::Custom(s),
+                None => return Err(xml),
+            }
+            _ => return Err(xml),
+        };
+        let comment = take(&mut xml, "comment").map(|x| x.text);
+        let inputs = xml.children.into_iter().map(Input::from_xml).collect();
+        Ok(Block { kind, inputs, comment })
+    }
+}
+impl<'src> Input<'src> {
+    fn from_xml(xml: Xml<'src>) -> Self {
+        match xml.name {
+            "l" => match xml.children.into_iter().next() {
+                Some(x) if x.name == "option" => Input::Option(x.text),
+                Some(x) if x.name == "bool" => Input::Bool(x.text == "true"),
+                _ => Input::Text(xml.text),
+            }
+            "color" => Input::Color(xml.text),
+            "script" => Input::Script(Script::from_xml(xml)),
+            "list" => Input::List(xml.children.into_iter().map(Input::from_xml).collect()),
+            _ => match Block::from_xml(xml) {
+                Ok(x) => Input::Block(Box::new_with(|| x)),
+                Err(xml) => Input::Other { name: xml.name, inputs: xml.children.into_iter().map(Input::from_xml).collect() },
+            }
+        }
+    }
+}
+
+impl<T: NameTransformer> Parser<T> {
+    /// Parses a project into a [`raw`](crate::raw) xml tree, which refers to `xml` rather than copying it.
+    /// This accepts the same kinds of input as [`Parser::parse`], but only [`Parser::skip_history`] is used, since the tree is not lowered into an ast.
+    pub fn parse_raw<'src>(&self, xml: &'src str) -> Result<Project<'src>, Box<Error>> {
+        let xml = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+        let skip: &[&str] = if self.skip_history { &["history", "replay"] } else { &[] };
+        let mut tokens = xmlparser::Tokenizer::from(xml);
+        while let Some(Ok(e)) = tokens.next() {
+            if let xmlparser::Token::ElementStart { local, span, .. } = e {
+                if !matches!(local.as_str(), "room" | "role" | "project") { continue }
+                return match parse_xml_root(&mut tokens, local.as_str(), span.start(), skip, None) {
+                    Ok(root) => Project::from_xml(root),
+                    Err(e) => Err(Box::new_with(|| Error { kind: e.into(), location: Location { role: None, entity: None, collab_id: None, block_type: None } })),
+                };
+            }
+        }
+        Err(no_root_error())
+    }
+}
@@ -0,0 +1,383 @@
+//! Refactoring operations that rewrite an existing AST in place.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// Identifies the scope of a variable for [`Project::rename_variable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarScope<'a> {
+    /// A global variable of a role.
+    Global { role: &'a str },
+    /// A field of an entity.
+    Field { role: &'a str, entity: &'a str },
+    /// A parameter or local variable of a custom block, where `entity` is `None` for global blocks.
+    /// The block is identified by its [`Function::name`].
+    FnLocal { role: &'a str, entity: Option<&'a str>, func: &'a str },
+    /// A local variable of the script with the given index in an entity.
+    ScriptLocal { role: &'a str, entity: &'a str, script: usize },
+}
+
+struct Rename {
+    old: CompactString,
+    new: VariableDef,
+}
+impl Rename {
+    fn apply(&self, var: &mut VariableRef, location: VarLocation) {
+        if var.location == location && var.trans_name == self.old {
+            var.name = self.new.name.clone();
+            var.trans_name = self.new.trans_name.clone();
+        }
+    }
+    fn apply_def(&self, def: &mut VariableDef) {
+        if def.trans_name == self.old {
+            *def = self.new.clone();
+        }
+    }
+    /// Renames all references at the given location in some code.
+    /// Inside closures, captured fields and locals become locals, whereas globals are referenced directly.
+    fn stmts(&self, stmts: &mut Vec<Stmt>, location: VarLocation) {
+        for stmt in stmts {
+            match &mut stmt.kind {
+                StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var }
+                | StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. }
+                | StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } => self.apply(var, location),
+                StmtKind::CallFn { upvars, .. } => for var in upvars { self.apply(var, location) },
+                StmtKind::DeclareLocals { vars } if location == VarLocation::Local => for var in vars { self.apply_def(var) },
+                _ => (),
+            }
+
+            let mut exprs = vec![];
+            let mut bodies = vec![];
+            stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { self.expr(expr, location) }
+            for body in bodies { self.stmts(body, location) }
+        }
+    }
+    fn expr(&self, expr: &mut Expr, location: VarLocation) {
+        match &mut expr.kind {
+            ExprKind::Variable { var } => self.apply(var, location),
+            ExprKind::CallFn { upvars, .. } => for var in upvars { self.apply(var, location) },
+            ExprKind::Closure { captures, stmts, .. } => {
+                if location == VarLocation::Global {
+                    self.stmts(stmts, location);
+                } else if captures.iter().any(|x| x.location == location && x.trans_name == self.old) {
+                    for var in captures { self.apply(var, location) }
+                    self.stmts(stmts, VarLocation::Local);
+                }
+                return;
+            }
+            _ => (),
+        }
+
+        let mut exprs = vec![];
+        expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |_| ());
+        for expr in exprs { self.expr(expr, location) }
+    }
+}
+
+/// Gets the names of all locals defined directly in some code (not in nested closures).
+fn collect_locals(stmts: &[Stmt], res: &mut Vec<CompactString>) {
+    let mut add = |name: &CompactString| if !res.contains(name) {
+        res.push(name.clone());
+    };
+    let mut nested = vec![];
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::DeclareLocals { vars } => for var in vars { add(&var.name) },
+            StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. }
+            | StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } if var.location == VarLocation::Local => add(&var.name),
+            StmtKind::CallFn { upvars, .. } => for var in upvars { add(&var.name) },
+            _ => (),
+        }
+        stmt_children(&stmt.kind, &mut |_| (), &mut |x| nested.push(x));
+    }
+    for body in nested {
+        collect_locals(body, res);
+    }
+}
+
+impl Project {
+    /// Renames a variable and updates all references to it.
+    ///
+    /// `old` and `new` are the original (untransformed) names of the variable, and the new translated name is generated by [`Parser::name_transformer`].
+    /// This fails if the variable does not exist or if the new name (or its translated name) conflicts with another variable in the same scope.
+    /// On failure, the project is not modified.
+    pub fn rename_variable<T: NameTransformer>(&mut self, parser: &Parser<T>, scope: VarScope, old: &str, new: &str) -> Result<(), Box<Error>> {
+        let role_name = match scope {
+            VarScope::Global { role } | VarScope::Field { role, .. } | VarScope::FnLocal { role, .. } | VarScope::ScriptLocal { role, .. } => role,
+        };
+        let entity_name = match scope {
+            VarScope::Global { .. } => None,
+            VarScope::Field { entity, .. } | VarScope::ScriptLocal { entity, .. } => Some(entity),
+            VarScope::FnLocal { entity, .. } => entity,
+        };
+        let location = LocationRef { role: Some(role_name), entity: entity_name, collab_id: None, block_type: None };
+        let undefined = || Box::new(Error { kind: CompileError::UndefinedVariable { name: old.into() }.into(), location: location.to_owned() });
+
+        let role = self.roles.iter_mut().find(|x| x.name == role_name).ok_or_else(undefined)?;
+        let entity = match entity_name {
+            Some(name) => Some(role.entities.iter().position(|x| x.name == name).ok_or_else(undefined)?),
+            None => None,
+        };
+
+        // gather the existing names in the scope to check for conflicts
+        let existing: Vec<CompactString> = match scope {
+            VarScope::Global { .. } => role.globals.iter().map(|x| x.def.name.clone()).collect(),
+            VarScope::Field { .. } => role.entities[entity.unwrap()].fields.iter().map(|x| x.def.name.clone()).collect(),
+            VarScope::FnLocal { func, .. } => {
+                let funcs = match entity { Some(i) => &role.entities[i].funcs, None => &role.funcs };
+                let func = funcs.iter().find(|x| x.name == func).ok_or_else(undefined)?;
+                let mut res: Vec<_> = func.params.iter().map(|x| x.name.clone()).collect();
+                collect_locals(&func.stmts, &mut res);
+                res
+            }
+            VarScope::ScriptLocal { script, .. } => {
+                let script = role.entities[entity.unwrap()].scripts.get(script).ok_or_else(undefined)?;
+                let mut res = vec![];
+                if let Some(Hat { kind: HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. }, .. }) = script.hat.as_deref() {
+                    res.extend(fields.iter().map(|x| x.name.clone()));
+                }
+                collect_locals(&script.stmts, &mut res);
+                res
+            }
+        };
+        if !existing.iter().any(|x| x == old) {
+            return Err(undefined());
+        }
+
+        let mut symbols = SymbolTable::new(parser);
+        for name in existing.iter().filter(|x| *x != old).chain(core::iter::once(&CompactString::new(new))) {
+            match symbols.define(name.clone(), 0f64.into()) {
+                Ok(None) => (),
+                Ok(Some(prev)) => return Err(Box::new(Error { kind: match scope {
+                    VarScope::Global { .. } => ProjectError::GlobalsWithSameName { name: prev.def.name }.into(),
+                    VarScope::Field { .. } => ProjectError::FieldsWithSameName { name: prev.def.name }.into(),
+                    VarScope::FnLocal { .. } | VarScope::ScriptLocal { .. } => CompileError::LocalsWithSameTransName { trans_name: prev.def.trans_name, names: (prev.def.name, name.clone()) }.into(),
+                }, location: location.to_owned() })),
+                Err(SymbolError::NameTransformError { name }) => return Err(Box::new(Error { kind: CompileError::NameTransformError { name }.into(), location: location.to_owned() })),
+                Err(SymbolError::ConflictingTrans { trans_name, names }) => return Err(Box::new(Error { kind: match scope {
+                    VarScope::Global { .. } => CompileError::GlobalsWithSameTransName { trans_name, names },
+                    VarScope::Field { .. } => CompileError::FieldsWithSameTransName { trans_name, names },
+                    VarScope::FnLocal { .. } | VarScope::ScriptLocal { .. } => CompileError::LocalsWithSameTransName { trans_name, names },
+                }.into(), location: location.to_owned() })),
+            }
+        }
+        let old_trans = symbols.transform_name(old).map_err(|_| Box::new(Error { kind: CompileError::NameTransformError { name: old.into() }.into(), location: location.to_owned() }))?;
+        let rename = Rename { old: old_trans, new: symbols.get(new).unwrap().def.clone() };
+
+        // everything has been validated, so now we can apply the changes
+        match scope {
+            VarScope::Global { .. } => {
+                for global in role.globals.iter_mut() { rename.apply_def(&mut global.def) }
+                for func in role.funcs.iter_mut() { rename.stmts(&mut func.stmts, VarLocation::Global) }
+                for entity in role.entities.iter_mut() {
+                    rename_entity(&rename, entity, VarLocation::Global);
+                }
+            }
+            VarScope::Field { .. } => {
+                let entity = &mut role.entities[entity.unwrap()];
+                for field in entity.fields.iter_mut() { rename.apply_def(&mut field.def) }
+                rename_entity(&rename, entity, VarLocation::Field);
+            }
+            VarScope::FnLocal { func, .. } => {
+                let funcs = match entity { Some(i) => &mut role.entities[i].funcs, None => &mut role.funcs };
+                let func = funcs.iter_mut().find(|x| x.name == func).unwrap();
+                for param in func.params.iter_mut() { rename.apply_def(param) }
+                rename.stmts(&mut func.stmts, VarLocation::Local);
+            }
+            VarScope::ScriptLocal { script, .. } => {
+                let script = &mut role.entities[entity.unwrap()].scripts[script];
+                for local in script.locals.iter_mut() { rename.apply_def(local) }
+                rename_hat(&rename, script, VarLocation::Local);
+                rename.stmts(&mut script.stmts, VarLocation::Local);
+            }
+        }
+        role.reindex();
+        Ok(())
+    }
+}
+fn rename_entity(rename: &Rename, entity: &mut Entity, location: VarLocation) {
+    for func in entity.funcs.iter_mut() {
+        rename.stmts(&mut func.stmts, location);
+    }
+    for script in entity.scripts.iter_mut() {
+        rename_hat(rename, script, location);
+        rename.stmts(&mut script.stmts, location);
+    }
+    for expr in entity.floating_reporters.iter_mut() {
+        rename.expr(expr, location);
+    }
+}
+fn rename_hat(rename: &Rename, script: &mut Script, location: VarLocation) {
+    if let Some(hat) = script.hat.as_deref_mut() {
+        match &mut hat.kind {
+            HatKind::When { condition } => rename.expr(condition, location),
+            HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields { rename.apply(field, location) },
+            _ => (),
+        }
+    }
+}
+
+impl Project {
+    /// Renumbers the [`NodeId`] of every block in the project, starting from 1, in a deterministic pre-order traversal.
+    /// Roles, custom blocks, entities, and scripts are visited in order, and each node is numbered before its inputs and nested scripts.
+    ///
+    /// This is done automatically by the parser, but can be used to give fresh ids to code that was modified or constructed programmatically.
+    pub fn assign_node_ids(&mut self) {
+        self.assign_node_ids_with(&mut |_, _| ());
+    }
+    /// Equivalent to [`Project::assign_node_ids`], but calls `f` with the old and new id of every node.
+    pub(crate) fn assign_node_ids_with(&mut self, f: &mut dyn FnMut(NodeId, NodeId)) {
+        fn number(info: &mut BlockInfo, next: &mut usize, f: &mut dyn FnMut(NodeId, NodeId)) {
+            let old = core::mem::replace(&mut info.id, NodeId(*next));
+            f(old, info.id);
+            *next += 1;
+        }
+        fn number_stmts(stmts: &mut [Stmt], next: &mut usize, f: &mut dyn FnMut(NodeId, NodeId)) {
+            for stmt in stmts {
+                number(&mut stmt.info, next, f);
+
+                let mut exprs = Vec::new();
+                let mut bodies = Vec::new();
+                stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+                for x in exprs { number_expr(x, next, f) }
+                for x in bodies { number_stmts(x, next, f) }
+            }
+        }
+        fn number_expr(expr: &mut Expr, next: &mut usize, f: &mut dyn FnMut(NodeId, NodeId)) {
+            number(&mut expr.info, next, f);
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { number_expr(x, next, f) }
+            for x in bodies { number_stmts(x, next, f) }
+        }
+
+        let mut next = 1;
+        for role in self.roles.iter_mut() {
+            for func in role.funcs.iter_mut() {
+                number_stmts(&mut func.stmts, &mut next, f);
+            }
+            for entity in role.entities.iter_mut() {
+                for func in entity.funcs.iter_mut() {
+                    number_stmts(&mut func.stmts, &mut next, f);
+                }
+                for script in entity.scripts.iter_mut() {
+                    if let Some(hat) = script.hat.as_deref_mut() {
+                        number(&mut hat.info, &mut next, f);
+                        if let HatKind::When { condition } = &mut hat.kind {
+                            number_expr(condition, &mut next, f);
+                        }
+                    }
+                    number_stmts(&mut script.stmts, &mut next, f);
+                }
+                for x in entity.floating_reporters.iter_mut() {
+                    number_expr(x, &mut next, f);
+                }
+            }
+        }
+    }
+}
+
+/// The names of the variables in each table, in slot order, as seen from some code.
+struct Slots<'a> {
+    globals: &'a [CompactString],
+    fields: &'a [CompactString],
+    locals: Vec<CompactString>,
+}
+impl Slots<'_> {
+    fn resolve(&self, var: &mut VariableRef) {
+        let table = match var.location {
+            VarLocation::Global => self.globals,
+            VarLocation::Field => self.fields,
+            VarLocation::Local => &self.locals,
+        };
+        var.slot = table.iter().position(|x| *x == var.name);
+    }
+    fn stmts(&self, stmts: &mut Vec<Stmt>) {
+        for stmt in stmts {
+            match &mut stmt.kind {
+                StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var }
+                | StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. }
+                | StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } => self.resolve(var),
+                StmtKind::CallFn { upvars, .. } => for var in upvars { self.resolve(var) },
+                _ => (),
+            }
+
+            let mut exprs = vec![];
+            let mut bodies = vec![];
+            stmt_children_mut(&mut stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { self.expr(expr) }
+            for body in bodies { self.stmts(body) }
+        }
+    }
+    fn expr(&self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Variable { var } => self.resolve(var),
+            ExprKind::CallFn { upvars, .. } => for var in upvars { self.resolve(var) },
+            ExprKind::Closure { params, captures, stmts, .. } => {
+                for var in captures.iter_mut() { self.resolve(var) }
+                let mut locals: Vec<_> = params.iter().map(|x| x.name.clone()).chain(captures.iter().map(|x| x.name.clone())).collect();
+                collect_locals(stmts, &mut locals);
+                Slots { globals: self.globals, fields: self.fields, locals }.stmts(stmts);
+                return;
+            }
+            _ => (),
+        }
+
+        let mut exprs = vec![];
+        expr_children_mut(&mut expr.kind, &mut |x| exprs.push(x), &mut |_| ());
+        for expr in exprs { self.expr(expr) }
+    }
+}
+
+impl Project {
+    /// Sets the [`VariableRef::slot`] of every variable reference in the project to the index of the variable in its table.
+    ///
+    /// Globals are indexed by their position in [`Role::globals`] and fields by their position in [`Entity::fields`].
+    /// Locals are indexed within the frame of the enclosing script, custom block, or closure:
+    /// for scripts this is [`Script::locals`], for custom blocks it is the parameters followed by the other locals in order of first definition,
+    /// and for closures it is the parameters, then the captures, then the other locals in order of first definition.
+    ///
+    /// This is done automatically by the parser, but should be repeated after adding or removing variables.
+    pub fn resolve_slots(&mut self) {
+        for role in self.roles.iter_mut() {
+            let globals: Vec<_> = role.globals.iter().map(|x| x.def.name.clone()).collect();
+            for func in role.funcs.iter_mut() {
+                resolve_func(&Slots { globals: &globals, fields: &[], locals: vec![] }, func);
+            }
+            for entity in role.entities.iter_mut() {
+                let fields: Vec<_> = entity.fields.iter().map(|x| x.def.name.clone()).collect();
+                let slots = Slots { globals: &globals, fields: &fields, locals: vec![] };
+                for func in entity.funcs.iter_mut() {
+                    resolve_func(&slots, func);
+                }
+                for script in entity.scripts.iter_mut() {
+                    let slots = Slots { locals: script.locals.iter().map(|x| x.name.clone()).collect(), ..slots };
+                    if let Some(hat) = script.hat.as_deref_mut() {
+                        match &mut hat.kind {
+                            HatKind::When { condition } => slots.expr(condition),
+                            HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields { slots.resolve(field) },
+                            _ => (),
+                        }
+                    }
+                    slots.stmts(&mut script.stmts);
+                }
+                for expr in entity.floating_reporters.iter_mut() {
+                    slots.expr(expr);
+                }
+            }
+        }
+    }
+}
+fn resolve_func(slots: &Slots, func: &mut Function) {
+    let mut locals: Vec<_> = func.params.iter().map(|x| x.name.clone()).collect();
+    collect_locals(&func.stmts, &mut locals);
+    let slots = Slots { globals: slots.globals, fields: slots.fields, locals };
+    for var in func.upvars.iter_mut() { slots.resolve(var) }
+    slots.stmts(&mut func.stmts);
+}
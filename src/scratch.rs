@@ -0,0 +1,1500 @@
+//! Import and export of Scratch 3 projects (`.sb3` files).
+//!
+//! A Scratch project is converted into NetsBlox project xml (see [`sb3_to_xml`]), which is then parsed as usual (see [`Parser::parse_sb3`]).
+//! The stage and its sprites become the entities of a single role, stage variables and lists become globals, and sprite variables and lists become fields.
+//! Custom blocks are defined on the sprite which owns them, as in Scratch.
+//!
+//! Blocks which have no NetsBlox equivalent are kept as unknown blocks named by their Scratch opcode
+//! (e.g., [`StmtKind::UnknownBlock`] with name `"motion_glidesecstoxy"`), and are listed in [`Sb3Import::untranslated`].
+//! Scripts whose hat block has no NetsBlox equivalent are kept without a hat block, so they are omitted unless [`Parser::omit_nonhat_scripts`] is disabled.
+//!
+//! Conversely, a role can be exported as a Scratch project (see [`role_to_sb3`]).
+//! Blocks which have no Scratch equivalent are left out and listed in [`Sb3Export::untranslated`].
+//!
+//! This module is only available with the `scratch` feature.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use std::io::{Cursor, Read, Write as _};
+
+use base64::engine::Engine as _;
+use serde_json::{Map, Value as Json};
+
+use crate::*;
+use crate::util::xml_escape;
+
+/// An error from reading a Scratch project.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Sb3Error {
+    /// The file is not a valid zip archive.
+    Archive(zip::result::ZipError),
+    /// The project could not be read from the archive.
+    Io(std::io::Error),
+    /// The project is not valid json.
+    Json(serde_json::Error),
+    /// The project json does not have the expected structure.
+    Format { msg: CompactString },
+    /// The converted project failed to parse.
+    Parse(Box<Error>),
+}
+
+/// A Scratch block which has no NetsBlox equivalent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Untranslated {
+    /// The name of the sprite (or stage) holding the block.
+    pub entity: CompactString,
+    /// The Scratch opcode of the block (e.g., `motion_glidesecstoxy`).
+    pub opcode: CompactString,
+    /// The Scratch id of the block.
+    pub block_id: CompactString,
+}
+
+/// The result of [`Parser::parse_sb3`].
+#[derive(Debug)]
+pub struct Sb3Import {
+    pub project: Project,
+    pub untranslated: Vec<Untranslated>,
+    /// Warnings from parsing the converted project (see [`Parser::parse_with_diagnostics`]).
+    pub warnings: Vec<Warning>,
+}
+
+/// Scratch blocks which map onto a NetsBlox block with the same inputs, listed in NetsBlox order.
+const SIMPLE_BLOCKS: &[(&str, &str, &[&str])] = &[
+    ("motion_movesteps", "forward", &["STEPS"]),
+    ("motion_turnright", "turn", &["DEGREES"]),
+    ("motion_turnleft", "turnLeft", &["DEGREES"]),
+    ("motion_gotoxy", "gotoXY", &["X", "Y"]),
+    ("motion_pointindirection", "setHeading", &["DIRECTION"]),
+    ("motion_changexby", "changeXPosition", &["DX"]),
+    ("motion_setx", "setXPosition", &["X"]),
+    ("motion_changeyby", "changeYPosition", &["DY"]),
+    ("motion_sety", "setYPosition", &["Y"]),
+    ("motion_ifonedgebounce", "bounceOffEdge", &[]),
+    ("motion_xposition", "xPosition", &[]),
+    ("motion_yposition", "yPosition", &[]),
+    ("motion_direction", "direction", &[]),
+    ("looks_sayforsecs", "doSayFor", &["MESSAGE", "SECS"]),
+    ("looks_say", "bubble", &["MESSAGE"]),
+    ("looks_thinkforsecs", "doThinkFor", &["MESSAGE", "SECS"]),
+    ("looks_think", "doThink", &["MESSAGE"]),
+    ("looks_switchcostumeto", "doSwitchToCostume", &["COSTUME"]),
+    ("looks_nextcostume", "doWearNextCostume", &[]),
+    ("looks_changesizeby", "changeScale", &["CHANGE"]),
+    ("looks_setsizeto", "setScale", &["SIZE"]),
+    ("looks_cleargraphiceffects", "clearEffects", &[]),
+    ("looks_show", "show", &[]),
+    ("looks_hide", "hide", &[]),
+    ("looks_size", "getScale", &[]),
+    ("sound_play", "playSound", &["SOUND_MENU"]),
+    ("sound_playuntildone", "doPlaySoundUntilDone", &["SOUND_MENU"]),
+    ("sound_stopallsounds", "doStopAllSounds", &[]),
+    ("event_broadcast", "doBroadcast", &["BROADCAST_INPUT"]),
+    ("event_broadcastandwait", "doBroadcastAndWait", &["BROADCAST_INPUT"]),
+    ("control_wait", "doWait", &["DURATION"]),
+    ("control_wait_until", "doWaitUntil", &["CONDITION"]),
+    ("control_delete_this_clone", "removeClone", &[]),
+    ("sensing_askandwait", "doAsk", &["QUESTION"]),
+    ("sensing_answer", "getLastAnswer", &[]),
+    ("sensing_mousex", "reportMouseX", &[]),
+    ("sensing_mousey", "reportMouseY", &[]),
+    ("sensing_timer", "getTimer", &[]),
+    ("sensing_resettimer", "doResetTimer", &[]),
+    ("operator_subtract", "reportDifference", &["NUM1", "NUM2"]),
+    ("operator_divide", "reportQuotient", &["NUM1", "NUM2"]),
+    ("operator_mod", "reportModulus", &["NUM1", "NUM2"]),
+    ("operator_random", "reportRandom", &["FROM", "TO"]),
+    ("operator_gt", "reportGreaterThan", &["OPERAND1", "OPERAND2"]),
+    ("operator_lt", "reportLessThan", &["OPERAND1", "OPERAND2"]),
+    ("operator_equals", "reportEquals", &["OPERAND1", "OPERAND2"]),
+    ("operator_and", "reportAnd", &["OPERAND1", "OPERAND2"]),
+    ("operator_or", "reportOr", &["OPERAND1", "OPERAND2"]),
+    ("operator_not", "reportNot", &["OPERAND"]),
+    ("operator_letter_of", "reportLetter", &["LETTER", "STRING"]),
+    ("operator_length", "reportStringSize", &["STRING"]),
+    ("operator_round", "reportRound", &["NUM"]),
+    ("pen_clear", "clear", &[]),
+    ("pen_stamp", "doStamp", &[]),
+    ("pen_penDown", "down", &[]),
+    ("pen_penUp", "up", &[]),
+    ("pen_changePenSizeBy", "changeSize", &["SIZE"]),
+    ("pen_setPenSizeTo", "setSize", &["SIZE"]),
+    ("pen_setPenColorToColor", "setColor", &["COLOR"]),
+];
+
+fn json_text(value: &Json) -> CompactString {
+    match value {
+        Json::String(x) => x.as_str().into(),
+        Json::Number(x) => format_compact!("{x}"),
+        Json::Bool(x) => format_compact!("{x}"),
+        _ => CompactString::default(),
+    }
+}
+fn key_name(key: &str) -> &str {
+    match key {
+        "any" => "any key",
+        x => x,
+    }
+}
+/// Splits a custom block spec (proccode) into its words, which are either labels or inputs (`%s`, `%n`, or `%b`).
+fn proc_words(proccode: &str) -> impl Iterator<Item = &str> {
+    proccode.split(' ').filter(|x| !x.is_empty())
+}
+
+struct Target<'a> {
+    name: &'a str,
+    blocks: &'a Map<String, Json>,
+    untranslated: &'a mut Vec<Untranslated>,
+    out: String,
+    /// The ids of the blocks which have been written, since each block can only appear once (otherwise the project has a cycle).
+    visited: BTreeSet<&'a str>,
+    /// The first block which was reached more than once, if any.
+    repeated: Option<&'a str>,
+}
+impl<'a> Target<'a> {
+    fn text(&mut self, text: &str) {
+        write!(self.out, "<l>{}</l>", xml_escape(text)).unwrap();
+    }
+    fn option(&mut self, option: &str) {
+        write!(self.out, "<l><option>{}</option></l>", xml_escape(option)).unwrap();
+    }
+    fn var(&mut self, name: &str) {
+        write!(self.out, "<block var=\"{}\"/>", xml_escape(name)).unwrap();
+    }
+    fn untranslated(&mut self, id: &str, opcode: &str) {
+        self.untranslated.push(Untranslated { entity: self.name.into(), opcode: opcode.into(), block_id: id.into() });
+    }
+
+    fn input_value(&self, block: &'a Json, name: &str) -> Option<&'a Json> {
+        block["inputs"][name].get(1).filter(|x| !x.is_null())
+    }
+    /// Gets the selected option of a dropdown input, or `None` if a reporter was dropped onto it.
+    fn menu(&self, block: &'a Json, name: &str) -> Option<&'a str> {
+        let menu = &self.blocks.get(self.input_value(block, name)?.as_str()?)?;
+        if menu["shadow"] != Json::Bool(true) { return None }
+        let fields = menu["fields"].as_object()?;
+        if fields.len() != 1 { return None }
+        fields.values().next()?[0].as_str()
+    }
+    fn input(&mut self, block: &'a Json, name: &str) {
+        match self.input_value(block, name) {
+            Some(Json::String(id)) => match self.menu(block, name) {
+                Some(x) => self.text(x),
+                None => self.block(id),
+            }
+            Some(Json::Array(prim)) => match (prim.first().and_then(Json::as_u64), prim.get(1)) {
+                (Some(4..=8 | 10 | 11), Some(value)) => self.text(&json_text(value)),
+                (Some(9), Some(value)) => {
+                    let color = value.as_str().and_then(|x| x.strip_prefix('#')).and_then(|x| u32::from_str_radix(x, 16).ok()).unwrap_or(0);
+                    write!(self.out, "<color>{},{},{},1</color>", (color >> 16) & 0xff, (color >> 8) & 0xff, color & 0xff).unwrap();
+                }
+                (Some(12 | 13), Some(name)) => self.var(&json_text(name)),
+                _ => self.out.push_str("<l></l>"),
+            }
+            _ => self.out.push_str("<l></l>"),
+        }
+    }
+    /// Writes an input which takes a sprite name or one of the given special options (e.g., `_mouse_`).
+    fn entity_input(&mut self, block: &'a Json, name: &str, options: &[(&str, &str)]) {
+        match self.menu(block, name) {
+            Some(x) => match options.iter().find(|o| o.0 == x) {
+                Some(o) => self.option(o.1),
+                None => self.text(x),
+            }
+            None => self.input(block, name),
+        }
+    }
+    fn script(&mut self, first: Option<&str>) {
+        self.out.push_str("<script>");
+        self.stmts(first);
+        self.out.push_str("</script>");
+    }
+    fn stmts(&mut self, first: Option<&str>) {
+        let mut next = first;
+        while let Some(id) = next {
+            if self.repeated.is_some() { break }
+            self.block(id);
+            next = self.blocks.get(id).and_then(|x| x["next"].as_str());
+        }
+    }
+    fn substack(&mut self, block: &'a Json, name: &str) {
+        self.script(self.input_value(block, name).and_then(Json::as_str));
+    }
+
+    /// Writes a block (either a statement or a reporter) in place of its NetsBlox equivalent.
+    fn block(&mut self, id: &str) {
+        let (id, block) = match self.blocks.get_key_value(id) {
+            Some((id, block)) => (id.as_str(), block),
+            None => return self.out.push_str("<l></l>"),
+        };
+        if !self.visited.insert(id) {
+            self.repeated.get_or_insert(id);
+            return self.out.push_str("<l></l>");
+        }
+        let opcode = block["opcode"].as_str().unwrap_or_default();
+        let field = |name: &str| block["fields"][name][0].as_str().unwrap_or_default();
+
+        if let Some((_, s, inputs)) = SIMPLE_BLOCKS.iter().find(|x| x.0 == opcode) {
+            write!(self.out, "<block s=\"{s}\">").unwrap();
+            for input in inputs.iter() { self.input(block, input) }
+            return self.out.push_str("</block>");
+        }
+
+        match opcode {
+            "motion_goto" => {
+                self.out.push_str("<block s=\"doGotoObject\">");
+                self.entity_input(block, "TO", &[("_random_", "random position"), ("_mouse_", "mouse-pointer")]);
+            }
+            "motion_pointtowards" if self.menu(block, "TOWARDS") != Some("_mouse_") => {
+                self.out.push_str("<block s=\"doFaceTowards\">");
+                self.input(block, "TOWARDS");
+            }
+            "looks_changeeffectby" | "looks_seteffectto" => {
+                write!(self.out, "<block s=\"{}\">", if opcode == "looks_changeeffectby" { "changeEffect" } else { "setEffect" }).unwrap();
+                self.option(&field("EFFECT").to_lowercase());
+                self.input(block, if opcode == "looks_changeeffectby" { "CHANGE" } else { "VALUE" });
+            }
+            "looks_costumenumbername" => match field("NUMBER_NAME") {
+                "number" => self.out.push_str("<block s=\"getCostumeIdx\">"),
+                _ => self.out.push_str("<block s=\"reportGetImageAttribute\"><l><option>name</option></l><l><option>current</option></l>"),
+            }
+            "control_repeat" => {
+                self.out.push_str("<block s=\"doRepeat\">");
+                self.input(block, "TIMES");
+                self.substack(block, "SUBSTACK");
+            }
+            "control_forever" => {
+                self.out.push_str("<block s=\"doForever\">");
+                self.substack(block, "SUBSTACK");
+            }
+            "control_if" => {
+                self.out.push_str("<block s=\"doIf\">");
+                self.input(block, "CONDITION");
+                self.substack(block, "SUBSTACK");
+            }
+            "control_if_else" => {
+                self.out.push_str("<block s=\"doIfElse\">");
+                self.input(block, "CONDITION");
+                self.substack(block, "SUBSTACK");
+                self.substack(block, "SUBSTACK2");
+            }
+            "control_repeat_until" => {
+                self.out.push_str("<block s=\"doUntil\">");
+                self.input(block, "CONDITION");
+                self.substack(block, "SUBSTACK");
+            }
+            "control_stop" => {
+                self.out.push_str("<block s=\"doStopThis\">");
+                self.option(match field("STOP_OPTION") {
+                    "other scripts in sprite" | "other scripts in stage" => "other scripts in sprite",
+                    "this script" => "this script",
+                    _ => "all",
+                });
+            }
+            "control_create_clone_of" => {
+                self.out.push_str("<block s=\"createClone\">");
+                self.entity_input(block, "CLONE_OPTION", &[("_myself_", "myself")]);
+            }
+            "sensing_touchingobject" => {
+                self.out.push_str("<block s=\"reportTouchingObject\">");
+                self.entity_input(block, "TOUCHINGOBJECTMENU", &[("_mouse_", "mouse-pointer"), ("_edge_", "edge")]);
+            }
+            "sensing_keypressed" => {
+                self.out.push_str("<block s=\"reportKeyPressed\">");
+                match self.menu(block, "KEY_OPTION") {
+                    Some(x) => self.option(key_name(x)),
+                    None => self.input(block, "KEY_OPTION"),
+                }
+            }
+            "sensing_current" => {
+                self.out.push_str("<block s=\"reportDate\">");
+                self.option(&match field("CURRENTMENU") {
+                    "DAYOFWEEK" => "day of week".into(),
+                    x => x.to_lowercase(),
+                });
+            }
+            "operator_add" | "operator_multiply" | "operator_join" => {
+                let (s, a, b) = match opcode {
+                    "operator_add" => ("reportVariadicSum", "NUM1", "NUM2"),
+                    "operator_multiply" => ("reportVariadicProduct", "NUM1", "NUM2"),
+                    _ => ("reportJoinWords", "STRING1", "STRING2"),
+                };
+                write!(self.out, "<block s=\"{s}\"><list>").unwrap();
+                self.input(block, a);
+                self.input(block, b);
+                self.out.push_str("</list>");
+            }
+            "operator_mathop" => {
+                self.out.push_str("<block s=\"reportMonadic\">");
+                self.option(match field("OPERATOR") {
+                    "e ^" => "e^",
+                    "10 ^" => "10^",
+                    x => x,
+                });
+                self.input(block, "NUM");
+            }
+            "data_variable" | "data_listcontents" => return self.var(field(if opcode == "data_variable" { "VARIABLE" } else { "LIST" })),
+            "argument_reporter_string_number" | "argument_reporter_boolean" => return self.var(field("VALUE")),
+            "data_setvariableto" | "data_changevariableby" => {
+                write!(self.out, "<block s=\"{}\">", if opcode == "data_setvariableto" { "doSetVar" } else { "doChangeVar" }).unwrap();
+                self.text(field("VARIABLE"));
+                self.input(block, "VALUE");
+            }
+            "data_showvariable" | "data_hidevariable" | "data_showlist" | "data_hidelist" => {
+                let (s, f) = match opcode {
+                    "data_showvariable" => ("doShowVar", "VARIABLE"),
+                    "data_hidevariable" => ("doHideVar", "VARIABLE"),
+                    "data_showlist" => ("doShowVar", "LIST"),
+                    _ => ("doHideVar", "LIST"),
+                };
+                write!(self.out, "<block s=\"{s}\">").unwrap();
+                self.text(field(f));
+            }
+            "data_addtolist" => {
+                self.out.push_str("<block s=\"doAddToList\">");
+                self.input(block, "ITEM");
+                self.var(field("LIST"));
+            }
+            "data_deleteoflist" => {
+                self.out.push_str("<block s=\"doDeleteFromList\">");
+                self.input(block, "INDEX");
+                self.var(field("LIST"));
+            }
+            "data_deletealloflist" => {
+                self.out.push_str("<block s=\"doDeleteFromList\"><l><option>all</option></l>");
+                self.var(field("LIST"));
+            }
+            "data_insertatlist" => {
+                self.out.push_str("<block s=\"doInsertInList\">");
+                self.input(block, "ITEM");
+                self.input(block, "INDEX");
+                self.var(field("LIST"));
+            }
+            "data_replaceitemoflist" => {
+                self.out.push_str("<block s=\"doReplaceInList\">");
+                self.input(block, "INDEX");
+                self.var(field("LIST"));
+                self.input(block, "ITEM");
+            }
+            "data_itemoflist" => {
+                self.out.push_str("<block s=\"reportListItem\">");
+                self.input(block, "INDEX");
+                self.var(field("LIST"));
+            }
+            "data_itemnumoflist" => {
+                self.out.push_str("<block s=\"reportListIndex\">");
+                self.input(block, "ITEM");
+                self.var(field("LIST"));
+            }
+            "data_lengthoflist" => {
+                self.out.push_str("<block s=\"reportListAttribute\"><l><option>length</option></l>");
+                self.var(field("LIST"));
+            }
+            "data_listcontainsitem" => {
+                self.out.push_str("<block s=\"reportListContainsItem\">");
+                self.var(field("LIST"));
+                self.input(block, "ITEM");
+            }
+            "procedures_call" => {
+                let mutation = &block["mutation"];
+                let proccode = mutation["proccode"].as_str().unwrap_or_default();
+                let arg_ids: Vec<String> = mutation["argumentids"].as_str().and_then(|x| serde_json::from_str(x).ok()).unwrap_or_default();
+                write!(self.out, "<custom-block s=\"{}\">", xml_escape(&proc_words(proccode).collect::<Vec<_>>().join(" "))).unwrap();
+                for arg in arg_ids.iter() { self.input(block, arg) }
+                return self.out.push_str("</custom-block>");
+            }
+            _ => self.generic(id, block, opcode),
+        }
+        self.out.push_str("</block>");
+    }
+    /// Writes (the start of) an untranslated block as an unknown block with the same inputs and fields.
+    /// Nested scripts (e.g., the body of a loop) cannot be held by an unknown block and are dropped.
+    fn generic(&mut self, id: &str, block: &'a Json, opcode: &str) {
+        self.untranslated(id, opcode);
+        write!(self.out, "<block s=\"{}\">", xml_escape(opcode)).unwrap();
+        for (name, input) in block["inputs"].as_object().into_iter().flatten() {
+            if !name.starts_with("SUBSTACK") && input.get(1).is_some() { self.input(block, name) }
+        }
+        for field in block["fields"].as_object().into_iter().flatten().map(|x| x.1) {
+            self.text(&json_text(&field[0]));
+        }
+    }
+    /// Writes a hat block, returning `false` if it has no NetsBlox equivalent.
+    fn hat(&mut self, id: &str, block: &'a Json) -> bool {
+        let field = |name: &str| block["fields"][name][0].as_str().unwrap_or_default();
+        match block["opcode"].as_str().unwrap_or_default() {
+            "event_whenflagclicked" => self.out.push_str("<block s=\"receiveGo\"/>"),
+            "control_start_as_clone" => self.out.push_str("<block s=\"receiveOnClone\"/>"),
+            "event_whenthisspriteclicked" | "event_whenstageclicked" => self.out.push_str("<block s=\"receiveInteraction\"><l><option>clicked</option></l></block>"),
+            "event_whenkeypressed" => {
+                self.out.push_str("<block s=\"receiveKey\">");
+                self.option(key_name(field("KEY_OPTION")));
+                self.out.push_str("</block>");
+            }
+            "event_whenbroadcastreceived" => {
+                self.out.push_str("<block s=\"receiveMessage\">");
+                self.text(field("BROADCAST_OPTION"));
+                self.out.push_str("</block>");
+            }
+            "event_whengreaterthan" if field("WHENGREATERTHANMENU") == "TIMER" => {
+                self.out.push_str("<block s=\"receiveCondition\"><block s=\"reportGreaterThan\"><block s=\"getTimer\"/>");
+                self.input(block, "VALUE");
+                self.out.push_str("</block></block>");
+            }
+            opcode => {
+                self.untranslated(id, opcode);
+                return false;
+            }
+        }
+        true
+    }
+    /// Writes a custom block definition, given the top level `procedures_definition` block.
+    fn definition(&mut self, block: &'a Json) {
+        let prototype = match self.input_value(block, "custom_block").and_then(Json::as_str).and_then(|x| self.blocks.get(x)) {
+            Some(x) => &x["mutation"],
+            None => return,
+        };
+        let arg_names: Vec<String> = prototype["argumentnames"].as_str().and_then(|x| serde_json::from_str(x).ok()).unwrap_or_default();
+        let mut arg_names = arg_names.iter();
+        let mut spec = String::new();
+        let mut inputs = String::new();
+        for word in proc_words(prototype["proccode"].as_str().unwrap_or_default()) {
+            if !spec.is_empty() { spec.push(' ') }
+            match word {
+                "%s" | "%n" | "%b" => {
+                    write!(spec, "%'{}'", arg_names.next().map(String::as_str).unwrap_or_default()).unwrap();
+                    write!(inputs, "<input type=\"{word}\"></input>").unwrap();
+                }
+                _ => spec.push_str(word),
+            }
+        }
+        write!(self.out, "<block-definition s=\"{}\" type=\"command\" category=\"custom\"><header></header><code></code><translations></translations><inputs>{inputs}</inputs>", xml_escape(&spec)).unwrap();
+        let body = block["next"].as_str();
+        match prototype["warp"] == Json::Bool(true) || prototype["warp"] == "true" {
+            true => {
+                self.out.push_str("<script><block s=\"doWarp\">");
+                self.script(body);
+                self.out.push_str("</block></script>");
+            }
+            false => self.script(body),
+        }
+        self.out.push_str("</block-definition>");
+    }
+}
+
+/// Writes the variables (or lists) of a target as NetsBlox variable definitions.
+fn write_vars(out: &mut String, target: &Json) {
+    for var in target["variables"].as_object().into_iter().flatten().map(|x| x.1) {
+        write!(out, "<variable name=\"{}\"><l>{}</l></variable>", xml_escape(&json_text(&var[0])), xml_escape(&json_text(&var[1]))).unwrap();
+    }
+    for list in target["lists"].as_object().into_iter().flatten().map(|x| x.1) {
+        write!(out, "<variable name=\"{}\"><list>", xml_escape(&json_text(&list[0]))).unwrap();
+        for item in list[1].as_array().into_iter().flatten() {
+            write!(out, "<item><l>{}</l></item>", xml_escape(&json_text(item))).unwrap();
+        }
+        out.push_str("</list></variable>");
+    }
+}
+/// Writes the costumes and sounds of a target, along with their entries in the project media.
+/// Costumes and sounds whose files are missing from the archive are skipped.
+fn write_media(out: &mut String, media: &mut String, target: &Json, assets: &mut dyn FnMut(&str) -> Option<Vec<u8>>) -> Option<usize> {
+    let name = json_text(&target["name"]);
+    let current = target["currentCostume"].as_u64().map(|x| x as usize);
+    let (mut active, mut count) = (None, 0);
+    out.push_str("<costumes><list struct=\"atomic\">");
+    for (i, costume) in target["costumes"].as_array().into_iter().flatten().enumerate() {
+        let Some(content) = costume["md5ext"].as_str().and_then(&mut *assets) else { continue };
+        let cst_name = json_text(&costume["name"]);
+        let id = xml_escape(&format_compact!("{name}_cst_{cst_name}"));
+        let resolution = costume["bitmapResolution"].as_f64().unwrap_or(1.0);
+        let center = |attr: &str| costume[attr].as_f64().unwrap_or(0.0) / resolution;
+        let format = match costume["dataFormat"].as_str() { Some("svg") => "svg+xml", Some(x) => x, None => "png" };
+        write!(media, "<costume name=\"{}\" center-x=\"{}\" center-y=\"{}\" image=\"data:image/{format};base64,{}\" mediaID=\"{id}\"/>",
+            xml_escape(&cst_name), center("rotationCenterX"), center("rotationCenterY"), base64::engine::general_purpose::STANDARD.encode(content)).unwrap();
+        write!(out, "<item><ref mediaID=\"{id}\"/></item>").unwrap();
+        count += 1;
+        if current == Some(i) { active = Some(count) }
+    }
+    out.push_str("</list></costumes><sounds><list struct=\"atomic\">");
+    for sound in target["sounds"].as_array().into_iter().flatten() {
+        let Some(content) = sound["md5ext"].as_str().and_then(&mut *assets) else { continue };
+        let snd_name = json_text(&sound["name"]);
+        let id = xml_escape(&format_compact!("{name}_snd_{snd_name}"));
+        let format = match sound["dataFormat"].as_str() { Some("mp3") => "mpeg", Some(x) => x, None => "wav" };
+        write!(media, "<sound name=\"{}\" sound=\"data:audio/{format};base64,{}\" mediaID=\"{id}\"/>", xml_escape(&snd_name), base64::engine::general_purpose::STANDARD.encode(content)).unwrap();
+        write!(out, "<item><ref mediaID=\"{id}\"/></item>").unwrap();
+    }
+    out.push_str("</list></sounds>");
+    active
+}
+
+/// Converts the json of a Scratch project (the `project.json` file of an `.sb3` archive) into NetsBlox project xml.
+/// The files of costumes and sounds are requested from `assets` by name (e.g., `"83a9787d4cb6f3b7632b4ddfebf74367.wav"`).
+pub fn project_json_to_xml(name: &str, json: &str, assets: &mut dyn FnMut(&str) -> Option<Vec<u8>>) -> Result<(String, Vec<Untranslated>), Sb3Error> {
+    let root: Json = serde_json::from_str(json).map_err(Sb3Error::Json)?;
+    let targets = match root["targets"].as_array() {
+        Some(x) => x,
+        None => return Err(Sb3Error::Format { msg: "project has no targets".into() }),
+    };
+    let stage = match targets.iter().find(|x| x["isStage"] == Json::Bool(true)) {
+        Some(x) => x,
+        None => return Err(Sb3Error::Format { msg: "project has no stage".into() }),
+    };
+
+    let mut untranslated = vec![];
+    let mut media = String::new();
+    let mut globals = String::new();
+    write_vars(&mut globals, stage);
+
+    let mut entities = vec![];
+    for target in targets.iter().filter(|x| x["isStage"] != Json::Bool(true)).chain([stage]) {
+        let blocks = match target["blocks"].as_object() {
+            Some(x) => x,
+            None => return Err(Sb3Error::Format { msg: format_compact!("target {} has no blocks", json_text(&target["name"])) }),
+        };
+        let name = target["name"].as_str().unwrap_or_default();
+        let mut entity = String::new();
+        let costume = write_media(&mut entity, &mut media, target, assets);
+        entity.push_str("<variables>");
+        if !core::ptr::eq(target, stage) {
+            write_vars(&mut entity, target);
+        }
+        entity.push_str("</variables>");
+
+        let mut state = Target { name, blocks, untranslated: &mut untranslated, out: String::new(), visited: BTreeSet::new(), repeated: None };
+        state.out.push_str("<blocks>");
+        for block in blocks.values().filter(|x| x["topLevel"] == Json::Bool(true) && x["opcode"] == "procedures_definition") {
+            state.definition(block);
+        }
+        state.out.push_str("</blocks><scripts>");
+        for (id, block) in blocks.iter().filter(|x| x.1["topLevel"] == Json::Bool(true) && x.1["opcode"] != "procedures_definition") {
+            let opcode = block["opcode"].as_str().unwrap_or_default();
+            state.out.push_str("<script>");
+            if opcode.contains("_when") || opcode == "control_start_as_clone" {
+                state.hat(id, block);
+                state.stmts(block["next"].as_str());
+            } else {
+                state.stmts(Some(id));
+            }
+            state.out.push_str("</script>");
+        }
+        state.out.push_str("</scripts>");
+        if let Some(id) = state.repeated {
+            return Err(Sb3Error::Format { msg: format_compact!("block {id} of target {name} is reached more than once (e.g., by a cycle)") });
+        }
+        entity.push_str(&state.out);
+        entities.push((target, costume, entity));
+    }
+
+    let (_, stage_costume, stage_content) = entities.pop().unwrap();
+    let app = "NetsBlox 1.29.1, http://netsblox.org";
+    let name = xml_escape(name);
+    let mut xml = String::new();
+    write!(xml, "<room name=\"{name}\" app=\"{app}\"><role name=\"{name}\"><project name=\"{name}\" app=\"{app}\" version=\"1.29.1\"><notes></notes>").unwrap();
+    write!(xml, "<stage name=\"Stage\" width=\"480\" height=\"360\" costume=\"{}\" tempo=\"{}\">{stage_content}<messageTypes></messageTypes><sprites>",
+        stage_costume.unwrap_or(0), json_text(&stage["tempo"]).parse::<f64>().unwrap_or(60.0)).unwrap();
+    for (target, costume, content) in entities {
+        let num = |attr: &str, default: f64| target[attr].as_f64().unwrap_or(default);
+        write!(xml, "<sprite name=\"{}\" x=\"{}\" y=\"{}\" heading=\"{}\" scale=\"{}\" hidden=\"{}\" costume=\"{}\">{content}</sprite>",
+            xml_escape(&json_text(&target["name"])), num("x", 0.0), num("y", 0.0), num("direction", 90.0), num("size", 100.0) / 100.0,
+            target["visible"] == Json::Bool(false), costume.unwrap_or(0)).unwrap();
+    }
+    write!(xml, "</sprites></stage><hidden></hidden><headers></headers><code></code><blocks></blocks><variables>{globals}</variables></project>").unwrap();
+    write!(xml, "<media name=\"{name}\" app=\"{app}\" version=\"1.29.1\">{media}</media></role></room>").unwrap();
+    Ok((xml, untranslated))
+}
+
+/// Converts a Scratch project (the content of an `.sb3` file) into NetsBlox project xml, as in [`project_json_to_xml`].
+pub fn sb3_to_xml(name: &str, sb3: &[u8]) -> Result<(String, Vec<Untranslated>), Sb3Error> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(sb3)).map_err(Sb3Error::Archive)?;
+    let mut json = String::new();
+    archive.by_name("project.json").map_err(Sb3Error::Archive)?.read_to_string(&mut json).map_err(Sb3Error::Io)?;
+    project_json_to_xml(name, &json, &mut |asset| {
+        let mut content = vec![];
+        archive.by_name(asset).ok()?.read_to_end(&mut content).ok()?;
+        Some(content)
+    })
+}
+
+impl<T: NameTransformer> Parser<T> {
+    /// Parses a Scratch project (the content of an `.sb3` file) into a project with a single role of the given name.
+    /// See the [`scratch`](crate::scratch) module for details of the conversion.
+    pub fn parse_sb3(&self, name: &str, sb3: &[u8]) -> Result<Sb3Import, Sb3Error> {
+        let (xml, untranslated) = sb3_to_xml(name, sb3)?;
+        let (project, warnings) = self.parse_with_diagnostics(&xml).map_err(Sb3Error::Parse)?;
+        Ok(Sb3Import { project, untranslated, warnings })
+    }
+}
+
+/// The result of [`role_to_sb3`].
+#[derive(Debug)]
+pub struct Sb3Export {
+    /// The content of the `.sb3` file.
+    pub sb3: Vec<u8>,
+    /// The blocks which have no Scratch equivalent and were left out of the project.
+    /// For exported projects, [`Untranslated::opcode`] is the NetsBlox block selector (or the name of a custom block) and [`Untranslated::block_id`] is its collab id (empty if not set).
+    pub untranslated: Vec<Untranslated>,
+}
+
+/// The name and content of an image which is used for targets without costumes, since Scratch requires at least one.
+const BLANK_COSTUME: (&str, &[u8]) = ("blank", br#"<svg version="1.1" xmlns="http://www.w3.org/2000/svg" width="2" height="2" viewBox="0 0 2 2"></svg>"#);
+
+/// Gets an id for the content of a media file.
+/// Scratch names these by their md5 hash, but does not check it, so a pair of (64-bit) FNV-1a hashes is used instead.
+fn asset_id(content: &[u8]) -> CompactString {
+    let fnv = |offset: u64| content.iter().fold(offset, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    format_compact!("{:016x}{:016x}", fnv(0xcbf29ce484222325), fnv(0x6c62272e07bb0142))
+}
+fn image_format(content: &[u8]) -> &'static str {
+    match content {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xff, 0xd8, ..] => "jpg",
+        _ if content.starts_with(b"<svg") || content.starts_with(b"<?xml") => "svg",
+        _ => "png",
+    }
+}
+fn scratch_value(value: &Value) -> Json {
+    match value {
+        Value::Number(x) if x.is_finite() => serde_json::Number::from_f64(*x).map(Json::Number).unwrap_or_default(),
+        Value::List(values, _) => Json::Array(values.iter().map(scratch_value).collect()),
+        Value::Bool(_) | Value::Number(_) | Value::Constant(_) | Value::String(_) | Value::SharedString(_) => Json::String(value.to_snap_string().into()),
+        _ => Json::String(String::new()),
+    }
+}
+fn scratch_key(key: &KeyCode) -> CompactString {
+    match key {
+        KeyCode::Char(c) => format_compact!("{c}"),
+        KeyCode::Up => "up arrow".into(),
+        KeyCode::Down => "down arrow".into(),
+        KeyCode::Left => "left arrow".into(),
+        KeyCode::Right => "right arrow".into(),
+        KeyCode::Space => "space".into(),
+        KeyCode::Enter => "enter".into(),
+        KeyCode::Any => "any".into(),
+        KeyCode::Other(x) => x.clone(),
+    }
+}
+fn mutation(entries: &[(&str, Json)]) -> Json {
+    let mut res = Map::new();
+    res.insert("tagName".into(), "mutation".into());
+    res.insert("children".into(), Json::Array(vec![]));
+    for (key, value) in entries {
+        res.insert((*key).into(), value.clone());
+    }
+    Json::Object(res)
+}
+
+/// Gets a new id for a block, variable, or broadcast.
+/// Ids are padded so that they sort in order of creation, which keeps scripts in order when the project is loaded.
+fn new_id(next_id: &mut usize) -> String {
+    *next_id += 1;
+    format!("id{next_id:06}")
+}
+
+/// A variable of a target, as (name, id, initial value).
+type ScratchVar = (CompactString, String, Value);
+
+/// The definition of a custom block, as used by calls and definitions.
+struct ProcInfo {
+    proccode: String,
+    arg_ids: Vec<CompactString>,
+    bools: Vec<bool>,
+}
+impl ProcInfo {
+    /// Gets the Scratch signature of a custom block, or `None` if it has no Scratch equivalent (e.g., because it reports a value).
+    fn new(function: &Function) -> Option<Self> {
+        if function.returns { return None }
+        let mut types = vec![];
+        for info in function.param_info.iter() {
+            if info.kind != ParamKind::Single { return None }
+            types.push(match info.input_type {
+                InputType::Any | InputType::Text | InputType::MultilineText => "%s",
+                InputType::Number => "%n",
+                InputType::Bool => "%b",
+                _ => return None,
+            });
+        }
+        let mut proccode = String::new();
+        for (i, part) in function.name.split('\t').enumerate() {
+            if i > 0 {
+                if !proccode.is_empty() && !proccode.ends_with(' ') { proccode.push(' ') }
+                proccode.push_str(types.get(i - 1)?);
+                if !part.starts_with(' ') && !part.is_empty() { proccode.push(' ') }
+            }
+            proccode.push_str(part);
+        }
+        let arg_ids = function.params.iter().map(|x| x.name.clone()).collect();
+        Some(Self { proccode: proccode.trim().into(), arg_ids, bools: types.iter().map(|x| *x == "%b").collect() })
+    }
+}
+
+struct Exporter<'a> {
+    role: &'a Role,
+    entity: &'a Entity,
+    vars: Vec<ScratchVar>,
+    globals: &'a [ScratchVar],
+    broadcasts: &'a mut Vec<(CompactString, String)>,
+    untranslated: &'a mut Vec<Untranslated>,
+    next_id: &'a mut usize,
+    uses_pen: &'a mut bool,
+    /// The custom block being exported, if any.
+    function: Option<&'a Function>,
+    /// The global custom blocks which have been called, and must be defined on this target.
+    needed_funcs: Vec<CompactString>,
+    blocks: Map<String, Json>,
+}
+impl<'a> Exporter<'a> {
+    fn new_id(&mut self) -> String {
+        new_id(self.next_id)
+    }
+    /// Checks if a name refers to a parameter of the custom block being exported, and if so, whether it is a boolean.
+    fn param(&self, name: &str) -> Option<bool> {
+        let function = self.function?;
+        let index = function.params.iter().position(|x| x.name == name)?;
+        Some(function.param_info.get(index).is_some_and(|x| x.input_type == InputType::Bool))
+    }
+    fn report(&mut self, opcode: Option<&str>, info: &BlockInfo) {
+        self.untranslated.push(Untranslated { entity: self.entity.name.clone(), opcode: opcode.unwrap_or("unknown").into(), block_id: info.location.clone().unwrap_or_default() });
+    }
+    fn add_block(&mut self, id: &str, opcode: &str, inputs: Map<String, Json>, fields: Map<String, Json>) -> &mut Map<String, Json> {
+        let mut block = Map::new();
+        block.insert("opcode".into(), opcode.into());
+        block.insert("next".into(), Json::Null);
+        block.insert("parent".into(), Json::Null);
+        block.insert("inputs".into(), Json::Object(inputs));
+        block.insert("fields".into(), Json::Object(fields));
+        block.insert("shadow".into(), false.into());
+        block.insert("topLevel".into(), false.into());
+        self.blocks.insert(id.into(), Json::Object(block));
+        self.blocks[id].as_object_mut().unwrap()
+    }
+    /// Links a sequence of blocks into a stack below `parent`, returning the id of the first block.
+    fn chain(&mut self, ids: &[String], parent: Option<&str>) -> Option<String> {
+        for (i, id) in ids.iter().enumerate() {
+            let prev = if i == 0 { parent } else { Some(ids[i - 1].as_str()) };
+            self.blocks[id]["parent"] = prev.map(Json::from).unwrap_or_default();
+            self.blocks[id]["next"] = ids.get(i + 1).map(|x| Json::from(x.as_str())).unwrap_or_default();
+        }
+        ids.first().cloned()
+    }
+    fn set_parents(&mut self, inputs: &Map<String, Json>, parent: &str) {
+        for input in inputs.values() {
+            for child in input.as_array().into_iter().flatten().filter_map(Json::as_str) {
+                if let Some(block) = self.blocks.get_mut(child) { block["parent"] = parent.into() }
+            }
+        }
+    }
+    /// Adds a block, setting it as the parent of the blocks in its inputs.
+    fn block(&mut self, opcode: &str, inputs: Vec<(&str, Json)>, fields: Vec<(&str, Json)>) -> String {
+        let id = self.new_id();
+        let inputs: Map<String, Json> = inputs.into_iter().filter(|x| !x.1.is_null()).map(|(k, v)| (k.into(), v)).collect();
+        self.set_parents(&inputs, &id);
+        self.add_block(&id, opcode, inputs, fields.into_iter().map(|(k, v)| (k.into(), v)).collect());
+        id
+    }
+    fn menu(&mut self, opcode: &str, field: &str, value: &str) -> Json {
+        let id = self.block(opcode, vec![], vec![(field, Json::Array(vec![value.into(), Json::Null]))]);
+        self.blocks[&id]["shadow"] = true.into();
+        Json::Array(vec![1.into(), id.into()])
+    }
+    fn lookup_var(&mut self, name: &str) -> (String, bool) {
+        let found = self.vars.iter().chain(self.globals.iter()).find(|x| x.0 == name);
+        match found {
+            Some(var) => (var.1.clone(), matches!(var.2, Value::List(..))),
+            None => {
+                // scratch has no script variables, so they are hoisted into the target
+                let id = self.new_id();
+                self.vars.push((name.into(), id.clone(), 0f64.into()));
+                (id, false)
+            }
+        }
+    }
+    fn var_field(&mut self, var: &VariableRef) -> Json {
+        let (id, _) = self.lookup_var(&var.name);
+        Json::Array(vec![var.name.as_str().into(), id.into()])
+    }
+    /// Gets the list variable referenced by a list input, as a field value.
+    fn list_field(&mut self, list: &Expr) -> Option<Json> {
+        match &list.kind {
+            ExprKind::Variable { var } if self.param(&var.name).is_none() => Some(self.var_field(var)),
+            _ => None,
+        }
+    }
+    fn broadcast(&mut self, name: &str) -> String {
+        if let Some(x) = self.broadcasts.iter().find(|x| x.0 == name) { return x.1.clone() }
+        let id = self.new_id();
+        self.broadcasts.push((name.into(), id.clone()));
+        id
+    }
+
+    fn input(&mut self, expr: &Expr) -> Json {
+        let empty = || Json::Array(vec![10.into(), "".into()]);
+        match &expr.kind {
+            ExprKind::Value(x @ (Value::Number(_) | Value::Constant(_))) => Json::Array(vec![1.into(), Json::Array(vec![4.into(), x.to_snap_string().as_str().into()])]),
+            ExprKind::Value(x @ (Value::Bool(_) | Value::String(_) | Value::SharedString(_))) => Json::Array(vec![1.into(), Json::Array(vec![10.into(), x.to_snap_string().as_str().into()])]),
+            ExprKind::Variable { var } if self.param(&var.name).is_none() => {
+                let (id, list) = self.lookup_var(&var.name);
+                Json::Array(vec![3.into(), Json::Array(vec![if list { 13 } else { 12 }.into(), var.name.as_str().into(), id.into()]), empty()])
+            }
+            _ => match self.reporter(expr) {
+                Some(id) => Json::Array(vec![3.into(), id.into(), empty()]),
+                None => Json::Array(vec![1.into(), empty()]),
+            }
+        }
+    }
+    /// Gets the input of a boolean slot, which has no shadow (and is left empty if the condition could not be translated).
+    fn bool_input(&mut self, expr: &Expr) -> Json {
+        match self.reporter(expr) {
+            Some(id) => Json::Array(vec![2.into(), id.into()]),
+            None => Json::Null,
+        }
+    }
+    fn substack(&mut self, stmts: &[Stmt]) -> Json {
+        let mut ids = vec![];
+        for stmt in stmts { self.stmt(stmt, &mut ids) }
+        match self.chain(&ids, None) {
+            Some(first) => Json::Array(vec![2.into(), first.into()]),
+            None => Json::Null,
+        }
+    }
+    /// Gets an input which takes a sprite name or one of the given special options.
+    fn entity_input(&mut self, menu: &str, field: &str, target: &Expr) -> Option<Json> {
+        match &target.kind {
+            ExprKind::This if menu == "control_create_clone_of_menu" => Some(self.menu(menu, field, "_myself_")),
+            ExprKind::Entity { name, .. } => Some(self.menu(menu, field, name)),
+            ExprKind::Value(x) => x.as_str().map(|x| self.menu(menu, field, x)),
+            _ => None,
+        }
+    }
+
+    /// Exports a reporter, returning the id of its block, or `None` if it has no Scratch equivalent.
+    fn reporter(&mut self, expr: &Expr) -> Option<String> {
+        macro_rules! simple {
+            ($opcode:expr $(, $name:literal => $input:expr)*) => {{
+                let inputs = vec![$(($name, self.input($input))),*];
+                self.block($opcode, inputs, vec![])
+            }};
+        }
+        macro_rules! boolean {
+            ($opcode:expr $(, $name:literal => $input:expr)*) => {{
+                let inputs = vec![$(($name, self.bool_input($input))),*];
+                self.block($opcode, inputs, vec![])
+            }};
+        }
+        let id = match &expr.kind {
+            ExprKind::Variable { var } if self.param(&var.name).is_some() => {
+                let opcode = if self.param(&var.name) == Some(true) { "argument_reporter_boolean" } else { "argument_reporter_string_number" };
+                self.block(opcode, vec![], vec![("VALUE", Json::Array(vec![var.name.as_str().into(), Json::Null]))])
+            }
+            ExprKind::Variable { .. } | ExprKind::Value(_) => {
+                // these are normally written directly into an input, but can appear alone (e.g., in a boolean slot)
+                self.report(expr_opcode(&expr.kind), &expr.info);
+                return None;
+            }
+            ExprKind::Add { values } | ExprKind::Mul { values } | ExprKind::StrCat { values } => {
+                let (opcode, a, b) = match &expr.kind {
+                    ExprKind::Add { .. } => ("operator_add", "NUM1", "NUM2"),
+                    ExprKind::Mul { .. } => ("operator_multiply", "NUM1", "NUM2"),
+                    _ => ("operator_join", "STRING1", "STRING2"),
+                };
+                let values = match &values.kind {
+                    ExprKind::MakeList { values } if !values.is_empty() => values,
+                    _ => {
+                        self.report(expr_opcode(&expr.kind), &expr.info);
+                        return None;
+                    }
+                };
+                // scratch only has the binary forms, so longer inputs are chained from the left
+                let mut acc = self.input(&values[0]);
+                for value in &values[1..] {
+                    let right = self.input(value);
+                    let id = self.block(opcode, vec![(a, acc), (b, right)], vec![]);
+                    acc = Json::Array(vec![3.into(), id.into(), Json::Array(vec![10.into(), "".into()])]);
+                }
+                match acc.get(1).and_then(Json::as_str) {
+                    Some(id) if values.len() > 1 => id.into(),
+                    _ => {
+                        self.report(expr_opcode(&expr.kind), &expr.info);
+                        return None;
+                    }
+                }
+            }
+            ExprKind::Sub { left, right } => simple!("operator_subtract", "NUM1" => left, "NUM2" => right),
+            ExprKind::Neg { value } => simple!("operator_subtract", "NUM1" => &Expr::from(0f64), "NUM2" => value),
+            ExprKind::Div { left, right } => simple!("operator_divide", "NUM1" => left, "NUM2" => right),
+            ExprKind::Mod { left, right } => simple!("operator_mod", "NUM1" => left, "NUM2" => right),
+            ExprKind::Random { a, b } => simple!("operator_random", "FROM" => a, "TO" => b),
+            ExprKind::Round { value } => simple!("operator_round", "NUM" => value),
+            ExprKind::Abs { value } | ExprKind::Floor { value } | ExprKind::Ceil { value } | ExprKind::Sqrt { value }
+            | ExprKind::Sin { value } | ExprKind::Cos { value } | ExprKind::Tan { value }
+            | ExprKind::Asin { value } | ExprKind::Acos { value } | ExprKind::Atan { value } => {
+                let op = match &expr.kind {
+                    ExprKind::Abs { .. } => "abs",
+                    ExprKind::Floor { .. } => "floor",
+                    ExprKind::Ceil { .. } => "ceiling",
+                    ExprKind::Sqrt { .. } => "sqrt",
+                    ExprKind::Sin { .. } => "sin",
+                    ExprKind::Cos { .. } => "cos",
+                    ExprKind::Tan { .. } => "tan",
+                    ExprKind::Asin { .. } => "asin",
+                    ExprKind::Acos { .. } => "acos",
+                    _ => "atan",
+                };
+                let input = self.input(value);
+                self.block("operator_mathop", vec![("NUM", input)], vec![("OPERATOR", Json::Array(vec![op.into(), Json::Null]))])
+            }
+            ExprKind::Log { value, base } | ExprKind::Pow { base, power: value } => {
+                let op = match (&expr.kind, &base.kind) {
+                    (ExprKind::Log { .. }, ExprKind::Value(Value::Constant(Constant::E))) => "ln",
+                    (ExprKind::Log { .. }, ExprKind::Value(Value::Number(x))) if *x == 10.0 => "log",
+                    (ExprKind::Pow { .. }, ExprKind::Value(Value::Constant(Constant::E))) => "e ^",
+                    (ExprKind::Pow { .. }, ExprKind::Value(Value::Number(x))) if *x == 10.0 => "10 ^",
+                    _ => {
+                        self.report(expr_opcode(&expr.kind), &expr.info);
+                        return None;
+                    }
+                };
+                let input = self.input(value);
+                self.block("operator_mathop", vec![("NUM", input)], vec![("OPERATOR", Json::Array(vec![op.into(), Json::Null]))])
+            }
+            ExprKind::Less { left, right } => simple!("operator_lt", "OPERAND1" => left, "OPERAND2" => right),
+            ExprKind::Greater { left, right } => simple!("operator_gt", "OPERAND1" => left, "OPERAND2" => right),
+            ExprKind::Eq { left, right } => simple!("operator_equals", "OPERAND1" => left, "OPERAND2" => right),
+            ExprKind::And { left, right } => boolean!("operator_and", "OPERAND1" => left, "OPERAND2" => right),
+            ExprKind::Or { left, right } => boolean!("operator_or", "OPERAND1" => left, "OPERAND2" => right),
+            ExprKind::Not { value } => boolean!("operator_not", "OPERAND" => value),
+            ExprKind::StrGet { string, index } => simple!("operator_letter_of", "LETTER" => index, "STRING" => string),
+            ExprKind::StrLen { value } => simple!("operator_length", "STRING" => value),
+            ExprKind::ListGet { list, index } | ExprKind::ListFind { list, value: index } | ExprKind::ListContains { list, value: index } => {
+                let (opcode, name) = match &expr.kind {
+                    ExprKind::ListGet { .. } => ("data_itemoflist", "INDEX"),
+                    ExprKind::ListFind { .. } => ("data_itemnumoflist", "ITEM"),
+                    _ => ("data_listcontainsitem", "ITEM"),
+                };
+                let Some(field) = self.list_field(list) else {
+                    self.report(expr_opcode(&expr.kind), &expr.info);
+                    return None;
+                };
+                let input = self.input(index);
+                self.block(opcode, vec![(name, input)], vec![("LIST", field)])
+            }
+            ExprKind::ListLen { value } => match self.list_field(value) {
+                Some(field) => self.block("data_lengthoflist", vec![], vec![("LIST", field)]),
+                None => {
+                    self.report(expr_opcode(&expr.kind), &expr.info);
+                    return None;
+                }
+            }
+            ExprKind::XPos => simple!("motion_xposition"),
+            ExprKind::YPos => simple!("motion_yposition"),
+            ExprKind::Heading => simple!("motion_direction"),
+            ExprKind::Size => simple!("looks_size"),
+            ExprKind::CostumeNumber => self.block("looks_costumenumbername", vec![], vec![("NUMBER_NAME", Json::Array(vec!["number".into(), Json::Null]))]),
+            ExprKind::CostumeName { costume } if matches!(costume.kind, ExprKind::Costume) => {
+                self.block("looks_costumenumbername", vec![], vec![("NUMBER_NAME", Json::Array(vec!["name".into(), Json::Null]))])
+            }
+            ExprKind::MouseX => simple!("sensing_mousex"),
+            ExprKind::MouseY => simple!("sensing_mousey"),
+            ExprKind::Answer => simple!("sensing_answer"),
+            ExprKind::Timer => simple!("sensing_timer"),
+            ExprKind::KeyDown { key } => match &key.kind {
+                ExprKind::Value(x) if x.as_str().is_some() => {
+                    let key = scratch_key(&KeyCode::parse(x.as_str().unwrap_or_default()));
+                    let menu = self.menu("sensing_keyoptions", "KEY_OPTION", &key);
+                    self.block("sensing_keypressed", vec![("KEY_OPTION", menu)], vec![])
+                }
+                _ => {
+                    self.report(expr_opcode(&expr.kind), &expr.info);
+                    return None;
+                }
+            }
+            ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingEntity { .. } => {
+                let menu = match &expr.kind {
+                    ExprKind::IsTouchingMouse => Some(self.menu("sensing_touchingobjectmenu", "TOUCHINGOBJECTMENU", "_mouse_")),
+                    ExprKind::IsTouchingEdge => Some(self.menu("sensing_touchingobjectmenu", "TOUCHINGOBJECTMENU", "_edge_")),
+                    ExprKind::IsTouchingEntity { entity } => self.entity_input("sensing_touchingobjectmenu", "TOUCHINGOBJECTMENU", entity),
+                    _ => None,
+                };
+                match menu {
+                    Some(menu) => self.block("sensing_touchingobject", vec![("TOUCHINGOBJECTMENU", menu)], vec![]),
+                    None => {
+                        self.report(expr_opcode(&expr.kind), &expr.info);
+                        return None;
+                    }
+                }
+            }
+            ExprKind::RealTime { query } => {
+                let query = match query {
+                    TimeQuery::Year => "YEAR",
+                    TimeQuery::Month => "MONTH",
+                    TimeQuery::Date => "DATE",
+                    TimeQuery::DayOfWeek => "DAYOFWEEK",
+                    TimeQuery::Hour => "HOUR",
+                    TimeQuery::Minute => "MINUTE",
+                    TimeQuery::Second => "SECOND",
+                    _ => {
+                        self.report(expr_opcode(&expr.kind), &expr.info);
+                        return None;
+                    }
+                };
+                self.block("sensing_current", vec![], vec![("CURRENTMENU", Json::Array(vec![query.into(), Json::Null]))])
+            }
+            ExprKind::CallFn { function, .. } => {
+                self.report(Some(&function.name), &expr.info);
+                return None;
+            }
+            _ => {
+                self.report(expr_opcode(&expr.kind), &expr.info);
+                return None;
+            }
+        };
+        Some(id)
+    }
+
+    /// Exports a statement, adding the ids of the resulting blocks (zero or more) to `ids`.
+    fn stmt(&mut self, stmt: &Stmt, ids: &mut Vec<String>) {
+        macro_rules! simple {
+            ($opcode:expr $(, $name:literal => $input:expr)*) => {{
+                let inputs = vec![$(($name, self.input($input))),*];
+                self.block($opcode, inputs, vec![])
+            }};
+        }
+        let untranslated = |this: &mut Self| this.report(stmt_opcode(&stmt.kind), &stmt.info);
+        let id = match &stmt.kind {
+            StmtKind::DeclareLocals { .. } => return, // locals are hoisted into the target (see lookup_var)
+            StmtKind::Warp { stmts } => {
+                // scratch only has warp for entire custom blocks, so the body is run normally
+                for stmt in stmts { self.stmt(stmt, ids) }
+                return;
+            }
+            StmtKind::Assign { var, value } | StmtKind::AddAssign { var, value } => {
+                let field = self.var_field(var);
+                let input = self.input(value);
+                let opcode = if matches!(stmt.kind, StmtKind::Assign { .. }) { "data_setvariableto" } else { "data_changevariableby" };
+                self.block(opcode, vec![("VALUE", input)], vec![("VARIABLE", field)])
+            }
+            StmtKind::ShowVar { var } | StmtKind::HideVar { var } => {
+                let field = self.var_field(var);
+                let list = matches!(self.vars.iter().chain(self.globals.iter()).find(|x| x.0 == var.name), Some((_, _, Value::List(..))));
+                let (opcode, name) = match (matches!(stmt.kind, StmtKind::ShowVar { .. }), list) {
+                    (true, false) => ("data_showvariable", "VARIABLE"),
+                    (false, false) => ("data_hidevariable", "VARIABLE"),
+                    (true, true) => ("data_showlist", "LIST"),
+                    (false, true) => ("data_hidelist", "LIST"),
+                };
+                self.block(opcode, vec![], vec![(name, field)])
+            }
+            StmtKind::InfLoop { stmts } => {
+                let body = self.substack(stmts);
+                self.block("control_forever", vec![("SUBSTACK", body)], vec![])
+            }
+            StmtKind::Repeat { times, counter: None, stmts } => {
+                let times = self.input(times);
+                let body = self.substack(stmts);
+                self.block("control_repeat", vec![("TIMES", times), ("SUBSTACK", body)], vec![])
+            }
+            StmtKind::UntilLoop { condition, stmts } => {
+                let condition = self.bool_input(condition);
+                let body = self.substack(stmts);
+                self.block("control_repeat_until", vec![("CONDITION", condition), ("SUBSTACK", body)], vec![])
+            }
+            StmtKind::If { condition, then } => {
+                let condition = self.bool_input(condition);
+                let then = self.substack(then);
+                self.block("control_if", vec![("CONDITION", condition), ("SUBSTACK", then)], vec![])
+            }
+            StmtKind::IfElse { condition, then, otherwise } => {
+                let condition = self.bool_input(condition);
+                let then = self.substack(then);
+                let otherwise = self.substack(otherwise);
+                self.block("control_if_else", vec![("CONDITION", condition), ("SUBSTACK", then), ("SUBSTACK2", otherwise)], vec![])
+            }
+            StmtKind::ListInsert { list, .. } | StmtKind::ListInsertLast { list, .. } | StmtKind::ListInsertRandom { list, .. }
+            | StmtKind::ListRemove { list, .. } | StmtKind::ListRemoveLast { list } | StmtKind::ListRemoveAll { list }
+            | StmtKind::ListAssign { list, .. } | StmtKind::ListAssignLast { list, .. } | StmtKind::ListAssignRandom { list, .. } => {
+                let Some(field) = self.list_field(list) else { return untranslated(self) };
+                let text = |x: &str| Expr::from(Value::String(x.into()));
+                let (opcode, inputs) = match &stmt.kind {
+                    StmtKind::ListInsert { value, index, .. } => ("data_insertatlist", vec![("ITEM", self.input(value)), ("INDEX", self.input(index))]),
+                    StmtKind::ListInsertLast { value, .. } => ("data_addtolist", vec![("ITEM", self.input(value))]),
+                    StmtKind::ListInsertRandom { value, .. } => ("data_insertatlist", vec![("ITEM", self.input(value)), ("INDEX", self.input(&text("random")))]),
+                    StmtKind::ListRemove { index, .. } => ("data_deleteoflist", vec![("INDEX", self.input(index))]),
+                    StmtKind::ListRemoveLast { .. } => ("data_deleteoflist", vec![("INDEX", self.input(&text("last")))]),
+                    StmtKind::ListAssign { value, index, .. } => ("data_replaceitemoflist", vec![("INDEX", self.input(index)), ("ITEM", self.input(value))]),
+                    StmtKind::ListAssignLast { value, .. } => ("data_replaceitemoflist", vec![("INDEX", self.input(&text("last"))), ("ITEM", self.input(value))]),
+                    StmtKind::ListAssignRandom { value, .. } => ("data_replaceitemoflist", vec![("INDEX", self.input(&text("random"))), ("ITEM", self.input(value))]),
+                    _ => ("data_deletealloflist", vec![]),
+                };
+                self.block(opcode, inputs, vec![("LIST", field)])
+            }
+            StmtKind::Sleep { seconds } => simple!("control_wait", "DURATION" => seconds),
+            StmtKind::WaitUntil { condition } => {
+                let condition = self.bool_input(condition);
+                self.block("control_wait_until", vec![("CONDITION", condition)], vec![])
+            }
+            StmtKind::SetCostume { costume } => {
+                let input = match &costume.kind {
+                    ExprKind::Value(x) if x.as_str().is_some_and(|x| !x.is_empty()) => self.menu("looks_costume", "COSTUME", x.as_str().unwrap_or_default()),
+                    ExprKind::Value(_) => return untranslated(self),
+                    _ => self.input(costume),
+                };
+                self.block("looks_switchcostumeto", vec![("COSTUME", input)], vec![])
+            }
+            StmtKind::NextCostume => simple!("looks_nextcostume"),
+            StmtKind::PlaySound { sound, blocking } => {
+                let input = match &sound.kind {
+                    ExprKind::Value(x) if x.as_str().is_some() => self.menu("sound_sounds_menu", "SOUND_MENU", x.as_str().unwrap_or_default()),
+                    _ => self.input(sound),
+                };
+                self.block(if *blocking { "sound_playuntildone" } else { "sound_play" }, vec![("SOUND_MENU", input)], vec![])
+            }
+            StmtKind::StopSounds => simple!("sound_stopallsounds"),
+            StmtKind::Forward { distance } => simple!("motion_movesteps", "STEPS" => distance),
+            StmtKind::SetX { value } => simple!("motion_setx", "X" => value),
+            StmtKind::ChangeX { delta } => simple!("motion_changexby", "DX" => delta),
+            StmtKind::SetY { value } => simple!("motion_sety", "Y" => value),
+            StmtKind::ChangeY { delta } => simple!("motion_changeyby", "DY" => delta),
+            StmtKind::GotoXY { x, y } => simple!("motion_gotoxy", "X" => x, "Y" => y),
+            StmtKind::GotoMouse | StmtKind::GotoRandom | StmtKind::Goto { .. } => {
+                let menu = match &stmt.kind {
+                    StmtKind::GotoMouse => Some(self.menu("motion_goto_menu", "TO", "_mouse_")),
+                    StmtKind::GotoRandom => Some(self.menu("motion_goto_menu", "TO", "_random_")),
+                    StmtKind::Goto { target } => self.entity_input("motion_goto_menu", "TO", target),
+                    _ => None,
+                };
+                match menu {
+                    Some(menu) => self.block("motion_goto", vec![("TO", menu)], vec![]),
+                    None => return untranslated(self),
+                }
+            }
+            StmtKind::PointTowards { target } => match self.entity_input("motion_pointtowards_menu", "TOWARDS", target) {
+                Some(menu) => self.block("motion_pointtowards", vec![("TOWARDS", menu)], vec![]),
+                None => return untranslated(self),
+            }
+            StmtKind::TurnRight { angle } => simple!("motion_turnright", "DEGREES" => angle),
+            StmtKind::TurnLeft { angle } => simple!("motion_turnleft", "DEGREES" => angle),
+            StmtKind::SetHeading { value } => simple!("motion_pointindirection", "DIRECTION" => value),
+            StmtKind::BounceOffEdge => simple!("motion_ifonedgebounce"),
+            StmtKind::SetPenDown { .. } | StmtKind::PenClear | StmtKind::Stamp | StmtKind::ChangePenSize { .. } | StmtKind::SetPenSize { .. } | StmtKind::SetPenColor { .. } => {
+                *self.uses_pen = true;
+                match &stmt.kind {
+                    StmtKind::SetPenDown { value } => simple!(if *value { "pen_penDown" } else { "pen_penUp" }),
+                    StmtKind::PenClear => simple!("pen_clear"),
+                    StmtKind::Stamp => simple!("pen_stamp"),
+                    StmtKind::ChangePenSize { delta } => simple!("pen_changePenSizeBy", "SIZE" => delta),
+                    StmtKind::SetPenSize { value } => simple!("pen_setPenSizeTo", "SIZE" => value),
+                    StmtKind::SetPenColor { color: (r, g, b, _) } => {
+                        let color = Json::Array(vec![1.into(), Json::Array(vec![9.into(), format!("#{r:02x}{g:02x}{b:02x}").into()])]);
+                        self.block("pen_setPenColorToColor", vec![("COLOR", color)], vec![])
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            StmtKind::Say { content, duration: Some(duration) } => simple!("looks_sayforsecs", "MESSAGE" => content, "SECS" => duration),
+            StmtKind::Say { content, duration: None } => simple!("looks_say", "MESSAGE" => content),
+            StmtKind::Think { content, duration: Some(duration) } => simple!("looks_thinkforsecs", "MESSAGE" => content, "SECS" => duration),
+            StmtKind::Think { content, duration: None } => simple!("looks_think", "MESSAGE" => content),
+            StmtKind::SetVisible { value } => simple!(if *value { "looks_show" } else { "looks_hide" }),
+            StmtKind::ChangeSize { delta } => simple!("looks_changesizeby", "CHANGE" => delta),
+            StmtKind::SetSize { value } => simple!("looks_setsizeto", "SIZE" => value),
+            StmtKind::SetEffect { kind, .. } | StmtKind::ChangeEffect { kind, .. } => {
+                let effect = match kind {
+                    EffectKind::Color => "COLOR",
+                    EffectKind::Fisheye => "FISHEYE",
+                    EffectKind::Whirl => "WHIRL",
+                    EffectKind::Pixelate => "PIXELATE",
+                    EffectKind::Mosaic => "MOSAIC",
+                    EffectKind::Brightness => "BRIGHTNESS",
+                    EffectKind::Ghost => "GHOST",
+                    _ => return untranslated(self),
+                };
+                let field = vec![("EFFECT", Json::Array(vec![effect.into(), Json::Null]))];
+                match &stmt.kind {
+                    StmtKind::SetEffect { value, .. } => {
+                        let input = self.input(value);
+                        self.block("looks_seteffectto", vec![("VALUE", input)], field)
+                    }
+                    StmtKind::ChangeEffect { delta, .. } => {
+                        let input = self.input(delta);
+                        self.block("looks_changeeffectby", vec![("CHANGE", input)], field)
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            StmtKind::ClearEffects => simple!("looks_cleargraphiceffects"),
+            StmtKind::Clone { target } => match self.entity_input("control_create_clone_of_menu", "CLONE_OPTION", target) {
+                Some(menu) => self.block("control_create_clone_of", vec![("CLONE_OPTION", menu)], vec![]),
+                None => return untranslated(self),
+            }
+            StmtKind::DeleteClone => simple!("control_delete_this_clone"),
+            StmtKind::SendLocalMessage { target: None, msg_type, wait } => {
+                let Some(name) = (match &msg_type.kind { ExprKind::Value(x) => x.as_str(), _ => None }) else { return untranslated(self) };
+                let id = self.broadcast(name);
+                let input = Json::Array(vec![1.into(), Json::Array(vec![11.into(), name.into(), id.into()])]);
+                self.block(if *wait { "event_broadcastandwait" } else { "event_broadcast" }, vec![("BROADCAST_INPUT", input)], vec![])
+            }
+            StmtKind::Ask { prompt } => simple!("sensing_askandwait", "QUESTION" => prompt),
+            StmtKind::ResetTimer => simple!("sensing_resettimer"),
+            StmtKind::Stop { mode } => {
+                let (option, hasnext) = match mode {
+                    StopMode::All => ("all", false),
+                    StopMode::ThisScript => ("this script", false),
+                    StopMode::OtherScriptsInSprite => ("other scripts in sprite", true),
+                    _ => return untranslated(self),
+                };
+                let id = self.block("control_stop", vec![], vec![("STOP_OPTION", Json::Array(vec![option.into(), Json::Null]))]);
+                self.blocks[&id]["mutation"] = mutation(&[("hasnext", hasnext.to_string().into())]);
+                id
+            }
+            StmtKind::CallFn { function, args, .. } => {
+                let def = self.entity.funcs.iter().find(|x| x.name == function.name).or_else(|| self.role.funcs.iter().find(|x| x.name == function.name));
+                let Some((def, info)) = def.and_then(|x| Some((x, ProcInfo::new(x)?))) else { return self.report(Some(&function.name), &stmt.info) };
+                if def.location == FnLocation::Global && !self.needed_funcs.contains(&def.name) {
+                    self.needed_funcs.push(def.name.clone());
+                }
+                let mut inputs = vec![];
+                for ((arg, id), is_bool) in args.iter().zip(info.arg_ids.iter()).zip(info.bools.iter()) {
+                    inputs.push((id.as_str(), if *is_bool { self.bool_input(arg) } else { self.input(arg) }));
+                }
+                let id = self.block("procedures_call", inputs, vec![]);
+                let arg_ids = serde_json::to_string(&info.arg_ids.iter().map(CompactString::as_str).collect::<Vec<_>>()).unwrap();
+                self.blocks[&id]["mutation"] = mutation(&[("proccode", info.proccode.into()), ("argumentids", arg_ids.into()), ("warp", "false".into())]);
+                id
+            }
+            _ => return untranslated(self),
+        };
+        ids.push(id);
+    }
+
+    /// Exports a hat block, returning the id of its block, or `None` if it has no Scratch equivalent.
+    fn hat(&mut self, hat: &Hat, is_stage: bool) -> Option<String> {
+        let field = |x: &str| vec![Json::from(x), Json::Null];
+        Some(match &hat.kind {
+            HatKind::OnFlag => self.block("event_whenflagclicked", vec![], vec![]),
+            HatKind::OnClone => self.block("control_start_as_clone", vec![], vec![]),
+            HatKind::MouseUp => self.block(if is_stage { "event_whenstageclicked" } else { "event_whenthisspriteclicked" }, vec![], vec![]),
+            HatKind::OnKey { key } => self.block("event_whenkeypressed", vec![], vec![("KEY_OPTION", Json::Array(field(&scratch_key(key))))]),
+            HatKind::LocalMessage { msg_type: Some(msg_type) } => {
+                let id = self.broadcast(msg_type);
+                self.block("event_whenbroadcastreceived", vec![], vec![("BROADCAST_OPTION", Json::Array(vec![msg_type.as_str().into(), id.into()]))])
+            }
+            kind => {
+                self.report(Some(hat_opcode(kind)), &hat.info);
+                return None;
+            }
+        })
+    }
+    fn place(&mut self, id: &str, index: usize) {
+        let block = &mut self.blocks[id];
+        block["topLevel"] = true.into();
+        block["x"] = 0.into();
+        block["y"] = (index * 300).into();
+    }
+    fn definition(&mut self, function: &'a Function, index: usize) {
+        let Some(info) = ProcInfo::new(function) else { return };
+        let (warp, body) = match function.stmts.as_slice() {
+            [Stmt { kind: StmtKind::Warp { stmts }, .. }] => (true, stmts.as_slice()),
+            stmts => (false, stmts),
+        };
+        let mut args = vec![];
+        for ((param, id), is_bool) in function.params.iter().zip(info.arg_ids.iter()).zip(info.bools.iter()) {
+            let opcode = if *is_bool { "argument_reporter_boolean" } else { "argument_reporter_string_number" };
+            let arg = self.block(opcode, vec![], vec![("VALUE", Json::Array(vec![param.name.as_str().into(), Json::Null]))]);
+            self.blocks[&arg]["shadow"] = true.into();
+            args.push((id.as_str(), Json::Array(vec![1.into(), arg.into()])));
+        }
+        let prototype = self.block("procedures_prototype", args, vec![]);
+        self.blocks[&prototype]["shadow"] = true.into();
+        let names = serde_json::to_string(&function.params.iter().map(|x| x.name.as_str()).collect::<Vec<_>>()).unwrap();
+        let arg_ids = serde_json::to_string(&info.arg_ids.iter().map(CompactString::as_str).collect::<Vec<_>>()).unwrap();
+        let defaults = serde_json::to_string(&info.arg_ids.iter().map(|_| "").collect::<Vec<_>>()).unwrap();
+        self.blocks[&prototype]["mutation"] = mutation(&[
+            ("proccode", info.proccode.into()), ("argumentids", arg_ids.into()), ("argumentnames", names.into()),
+            ("argumentdefaults", defaults.into()), ("warp", warp.to_string().into()),
+        ]);
+        let definition = self.block("procedures_definition", vec![("custom_block", Json::Array(vec![1.into(), prototype.into()]))], vec![]);
+        self.place(&definition, index);
+
+        self.function = Some(function);
+        let mut ids = vec![];
+        for stmt in body { self.stmt(stmt, &mut ids) }
+        self.chain(&ids, Some(&definition));
+        self.blocks[&definition]["next"] = ids.first().map(|x| Json::from(x.as_str())).unwrap_or_default();
+        self.function = None;
+    }
+}
+
+/// Adds a media file to the assets of the project, returning its file name (e.g., `"8ad6ae7b7c5bd5e5a5ae2a3fe67c8c2b.png"`).
+fn add_asset(assets: &mut Vec<(String, Vec<u8>)>, content: &[u8], format: &str) -> String {
+    let file = format!("{}.{format}", asset_id(content));
+    if !assets.iter().any(|x| x.0 == file) {
+        assets.push((file.clone(), content.to_vec()));
+    }
+    file
+}
+fn write_costumes(out: &mut Map<String, Json>, entity: &Entity, assets: &mut Vec<(String, Vec<u8>)>) {
+    let mut costumes = vec![];
+    for costume in entity.costumes.iter() {
+        let Value::Image(image) = &costume.init else { continue };
+        let format = image_format(&image.0);
+        let file = add_asset(assets, &image.0, format);
+        let (x, y) = image.1.unwrap_or_default();
+        costumes.push(serde_json::json!({
+            "name": costume.def.name.as_str(), "assetId": &file[..file.len() - format.len() - 1], "md5ext": file,
+            "dataFormat": format, "bitmapResolution": 1, "rotationCenterX": x, "rotationCenterY": y,
+        }));
+    }
+    if costumes.is_empty() {
+        // scratch requires every target to have a costume
+        let file = add_asset(assets, BLANK_COSTUME.1, "svg");
+        costumes.push(serde_json::json!({
+            "name": BLANK_COSTUME.0, "assetId": &file[..file.len() - 4], "md5ext": file,
+            "dataFormat": "svg", "bitmapResolution": 1, "rotationCenterX": 1, "rotationCenterY": 1,
+        }));
+    }
+    let mut sounds = vec![];
+    for sound in entity.sounds.iter() {
+        let Value::Audio(audio) = &sound.init else { continue };
+        let format = if audio.0.starts_with(b"RIFF") { "wav" } else { "mp3" };
+        let file = add_asset(assets, &audio.0, format);
+        sounds.push(serde_json::json!({
+            "name": sound.def.name.as_str(), "assetId": &file[..file.len() - 4], "md5ext": file,
+            "dataFormat": format, "rate": 48000, "sampleCount": 0,
+        }));
+    }
+    out.insert("currentCostume".into(), entity.active_costume.filter(|x| *x < costumes.len()).unwrap_or(0).into());
+    out.insert("costumes".into(), Json::Array(costumes));
+    out.insert("sounds".into(), Json::Array(sounds));
+}
+fn write_scratch_vars(out: &mut Map<String, Json>, vars: &[ScratchVar]) {
+    let (mut variables, mut lists) = (Map::new(), Map::new());
+    for (name, id, value) in vars {
+        match value {
+            Value::List(..) => lists.insert(id.clone(), Json::Array(vec![name.as_str().into(), scratch_value(value)])),
+            _ => variables.insert(id.clone(), Json::Array(vec![name.as_str().into(), scratch_value(value)])),
+        };
+    }
+    out.insert("variables".into(), Json::Object(variables));
+    out.insert("lists".into(), Json::Object(lists));
+}
+
+/// Converts a role into a Scratch project (the content of an `.sb3` file).
+/// The first entity of the role becomes the stage, globals become stage variables and lists, and fields become sprite variables and lists.
+///
+/// Only the subset of NetsBlox which has a Scratch equivalent can be exported; other blocks (and custom blocks which report values or take non-primitive inputs)
+/// are left out of the project and listed in [`Sb3Export::untranslated`].
+/// Script variables are converted into sprite variables, since Scratch has no equivalent.
+pub fn role_to_sb3(role: &Role) -> Result<Sb3Export, Sb3Error> {
+    let mut next_id = 0;
+    let globals: Vec<ScratchVar> = role.globals.iter().map(|x| (x.def.name.clone(), new_id(&mut next_id), x.init.clone())).collect();
+    let fields: Vec<Vec<ScratchVar>> = role.entities.iter().map(|entity| entity.fields.iter().map(|x| (x.def.name.clone(), new_id(&mut next_id), x.init.clone())).collect()).collect();
+
+    let mut untranslated = vec![];
+    let mut uses_pen = false;
+    let mut broadcasts = vec![];
+    let mut assets = vec![];
+    let mut targets = vec![];
+    for (i, (entity, fields)) in role.entities.iter().zip(fields).enumerate() {
+        let is_stage = i == 0;
+        let mut state = Exporter {
+            role, entity, vars: fields, globals: &globals, broadcasts: &mut broadcasts, untranslated: &mut untranslated,
+            next_id: &mut next_id, uses_pen: &mut uses_pen, function: None, needed_funcs: vec![], blocks: Map::new(),
+        };
+
+        let mut index = 0;
+        for script in entity.scripts.iter() {
+            let hat = match &script.hat {
+                Some(hat) => match state.hat(hat, is_stage) {
+                    Some(id) => Some(id),
+                    None => continue,
+                },
+                None => None,
+            };
+            let mut ids = vec![];
+            for stmt in script.stmts.iter() { state.stmt(stmt, &mut ids) }
+            let first = match hat {
+                Some(hat) => {
+                    state.chain(&ids, Some(&hat));
+                    state.blocks[&hat]["next"] = ids.first().map(|x| Json::from(x.as_str())).unwrap_or_default();
+                    hat
+                }
+                None => match state.chain(&ids, None) {
+                    Some(first) => first,
+                    None => continue,
+                }
+            };
+            state.place(&first, index);
+            index += 1;
+        }
+        for function in entity.funcs.iter() {
+            match ProcInfo::new(function) {
+                Some(_) => {
+                    state.definition(function, index);
+                    index += 1;
+                }
+                None => state.report(Some(function.name.as_str()), &BlockInfo::none()),
+            }
+        }
+        // global custom blocks are defined on each target which uses them
+        let mut done = 0;
+        while done < state.needed_funcs.len() {
+            let name = state.needed_funcs[done].clone();
+            if let Some(function) = role.funcs.iter().find(|x| x.name == name) {
+                state.definition(function, index);
+                index += 1;
+            }
+            done += 1;
+        }
+
+        let mut target = Map::new();
+        target.insert("isStage".into(), is_stage.into());
+        target.insert("name".into(), if is_stage { "Stage" } else { entity.name.as_str() }.into());
+        let vars = if is_stage { globals.iter().chain(state.vars.iter()).cloned().collect() } else { core::mem::take(&mut state.vars) };
+        write_scratch_vars(&mut target, &vars);
+        target.insert("broadcasts".into(), Json::Object(Map::new()));
+        target.insert("blocks".into(), Json::Object(core::mem::take(&mut state.blocks)));
+        target.insert("comments".into(), Json::Object(Map::new()));
+        write_costumes(&mut target, entity, &mut assets);
+        target.insert("volume".into(), 100.into());
+        target.insert("layerOrder".into(), i.into());
+        if is_stage {
+            target.insert("tempo".into(), 60.into());
+            target.insert("videoTransparency".into(), 50.into());
+            target.insert("videoState".into(), "on".into());
+            target.insert("textToSpeechLanguage".into(), Json::Null);
+        } else {
+            let num = |x: f64| serde_json::Number::from_f64(x).map(Json::Number).unwrap_or_default();
+            target.insert("visible".into(), entity.visible.into());
+            target.insert("x".into(), num(entity.pos.0));
+            target.insert("y".into(), num(entity.pos.1));
+            target.insert("size".into(), num(entity.scale * 100.0));
+            target.insert("direction".into(), num(entity.heading));
+            target.insert("draggable".into(), false.into());
+            target.insert("rotationStyle".into(), "all around".into());
+        }
+        targets.push(Json::Object(target));
+    }
+    let Some(stage) = targets.first_mut() else { return Err(Sb3Error::Format { msg: "role has no stage".into() }) };
+    stage["broadcasts"] = Json::Object(broadcasts.into_iter().map(|(name, id)| (id, Json::from(name.as_str()))).collect());
+
+    let extensions = if uses_pen { vec![Json::from("pen")] } else { vec![] };
+    let project = serde_json::json!({
+        "targets": targets, "monitors": [], "extensions": extensions,
+        "meta": { "semver": "3.0.0", "vm": "0.2.0", "agent": "netsblox-ast" },
+    });
+
+    let mut sb3 = Cursor::new(vec![]);
+    let mut archive = zip::ZipWriter::new(&mut sb3);
+    let options = zip::write::SimpleFileOptions::default();
+    archive.start_file("project.json", options).map_err(Sb3Error::Archive)?;
+    archive.write_all(project.to_string().as_bytes()).map_err(Sb3Error::Io)?;
+    for (file, content) in assets {
+        archive.start_file(file, options).map_err(Sb3Error::Archive)?;
+        archive.write_all(&content).map_err(Sb3Error::Io)?;
+    }
+    archive.finish().map_err(Sb3Error::Archive)?;
+    Ok(Sb3Export { sb3: sb3.into_inner(), untranslated })
+}
@@ -0,0 +1,150 @@
+//! An alternative representation of [`Value`] where lists have reference semantics, as in Snap!.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::*;
+
+/// A list which may be aliased and mutated in place.
+pub type SharedList = Rc<RefCell<Vec<SharedValue>>>;
+
+/// The contents of an image, as in [`Value::Image`].
+type ImageData = (Vec<u8>, Option<(f64, f64)>, CompactString);
+
+/// A value where lists are shared by reference, which allows interpreters to model aliasing and in-place mutation of lists.
+///
+/// Unlike [`Value`], there is no [`Value::Ref`] variant; references are resolved to the list they refer to (see [`SharedValues`]).
+/// Note that lists may contain themselves, in which case the derived [`Debug`] impl does not terminate.
+#[derive(Debug, Clone)]
+pub enum SharedValue {
+    Bool(bool),
+    Number(f64),
+    Constant(Constant),
+    String(CompactString),
+    Image(Rc<ImageData>),
+    Audio(Rc<(Vec<u8>, CompactString)>),
+    Color { r: u8, g: u8, b: u8, a: u8 },
+    List(SharedList),
+}
+impl SharedValue {
+    /// Creates a new (unaliased) list.
+    pub fn new_list(values: Vec<SharedValue>) -> Self {
+        SharedValue::List(Rc::new(RefCell::new(values)))
+    }
+    /// Checks if two values are identical, as in Snap's `reportIsIdentical` block.
+    /// Lists are identical only if they are the same list, whereas other values are compared by value.
+    pub fn is_identical(&self, other: &SharedValue) -> bool {
+        match (self, other) {
+            (SharedValue::List(a), SharedValue::List(b)) => Rc::ptr_eq(a, b),
+            (SharedValue::Bool(a), SharedValue::Bool(b)) => a == b,
+            (SharedValue::Number(a), SharedValue::Number(b)) => a == b,
+            (SharedValue::Constant(a), SharedValue::Constant(b)) => a == b,
+            (SharedValue::String(a), SharedValue::String(b)) => a == b,
+            (SharedValue::Image(a), SharedValue::Image(b)) => Rc::ptr_eq(a, b),
+            (SharedValue::Audio(a), SharedValue::Audio(b)) => Rc::ptr_eq(a, b),
+            (SharedValue::Color { r, g, b, a }, SharedValue::Color { r: r2, g: g2, b: b2, a: a2 }) => (r, g, b, a) == (r2, g2, b2, a2),
+            _ => false,
+        }
+    }
+    /// Converts back to a [`Value`].
+    /// Lists which appear more than once (including lists which contain themselves) are given a [`RefId`] at their first occurrence,
+    /// and all later occurrences are [`Value::Ref`], so the aliasing is preserved.
+    pub fn to_value(&self) -> Value {
+        fn count(value: &SharedValue, counts: &mut BTreeMap<*const RefCell<Vec<SharedValue>>, usize>) {
+            if let SharedValue::List(list) = value {
+                let entry = counts.entry(Rc::as_ptr(list)).or_insert(0);
+                *entry += 1;
+                if *entry == 1 {
+                    for x in list.borrow().iter() { count(x, counts) }
+                }
+            }
+        }
+        fn convert(value: &SharedValue, counts: &BTreeMap<*const RefCell<Vec<SharedValue>>, usize>, ids: &mut BTreeMap<*const RefCell<Vec<SharedValue>>, RefId>) -> Value {
+            match value {
+                SharedValue::Bool(x) => Value::Bool(*x),
+                SharedValue::Number(x) => Value::Number(*x),
+                SharedValue::Constant(x) => Value::Constant(*x),
+                SharedValue::String(x) => Value::String(x.clone()),
+                SharedValue::Image(x) => Value::Image(x.clone()),
+                SharedValue::Audio(x) => Value::Audio(x.clone()),
+                &SharedValue::Color { r, g, b, a } => Value::Color { r, g, b, a },
+                SharedValue::List(list) => {
+                    let ptr = Rc::as_ptr(list);
+                    if let Some(id) = ids.get(&ptr) {
+                        return Value::Ref(id.clone());
+                    }
+                    let id = if counts[&ptr] > 1 {
+                        let id = RefId(ids.len() + 1);
+                        ids.insert(ptr, id.clone());
+                        Some(id)
+                    } else {
+                        None
+                    };
+                    Value::List(list.borrow().iter().map(|x| convert(x, counts, ids)).collect(), id)
+                }
+            }
+        }
+
+        let mut counts = BTreeMap::new();
+        count(self, &mut counts);
+        convert(self, &counts, &mut BTreeMap::new())
+    }
+}
+impl From<f64> for SharedValue { fn from(v: f64) -> SharedValue { SharedValue::Number(v) } }
+impl From<&str> for SharedValue { fn from(v: &str) -> SharedValue { SharedValue::String(v.into()) } }
+impl From<bool> for SharedValue { fn from(v: bool) -> SharedValue { SharedValue::Bool(v) } }
+impl From<CompactString> for SharedValue { fn from(v: CompactString) -> SharedValue { SharedValue::String(v) } }
+impl From<Constant> for SharedValue { fn from(v: Constant) -> SharedValue { SharedValue::Constant(v) } }
+
+/// Converts [`Value`] into [`SharedValue`], where all lists with the same [`RefId`] (and all [`Value::Ref`] to them) become the same shared list.
+///
+/// Reference ids are scoped to a project, so the same converter should be used for all the values of a project (e.g., all of its variable initializers).
+/// References may appear before the list they refer to.
+#[derive(Debug, Default)]
+pub struct SharedValues {
+    lists: BTreeMap<usize, SharedList>,
+    defined: BTreeSet<usize>,
+}
+impl SharedValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Converts a value, sharing any lists with reference ids that were seen in previous conversions.
+    pub fn share(&mut self, value: &Value) -> SharedValue {
+        match value {
+            Value::Bool(x) => SharedValue::Bool(*x),
+            Value::Number(x) => SharedValue::Number(*x),
+            Value::Constant(x) => SharedValue::Constant(*x),
+            Value::String(x) => SharedValue::String(x.clone()),
+            Value::SharedString(x) => SharedValue::String(CompactString::new(x)),
+            Value::Image(x) => SharedValue::Image(x.clone()),
+            Value::Audio(x) => SharedValue::Audio(x.clone()),
+            &Value::Color { r, g, b, a } => SharedValue::Color { r, g, b, a },
+            Value::Ref(id) => SharedValue::List(self.lists.entry(id.0).or_default().clone()),
+            Value::List(values, None) => SharedValue::new_list(values.iter().map(|x| self.share(x)).collect()),
+            Value::List(values, Some(id)) => {
+                let list = self.lists.entry(id.0).or_default().clone();
+                if self.defined.insert(id.0) {
+                    let items = values.iter().map(|x| self.share(x)).collect();
+                    *list.borrow_mut() = items;
+                }
+                SharedValue::List(list)
+            }
+            Value::RawList(list, id) => self.share(&Value::List(list.to_values(), id.clone())),
+        }
+    }
+    /// Gets the reference ids which were referred to, but never defined by a list.
+    /// These are converted to empty lists.
+    pub fn unresolved(&self) -> impl Iterator<Item = RefId> + '_ {
+        self.lists.keys().filter(|x| !self.defined.contains(x)).map(|x| RefId(*x))
+    }
+}
+
+impl Value {
+    /// Converts a single value into a [`SharedValue`] (see [`SharedValues`] for converting several values with shared references).
+    pub fn to_shared(&self) -> SharedValue {
+        SharedValues::new().share(self)
+    }
+}
@@ -0,0 +1,146 @@
+//! Fingerprinting of code for similarity detection (e.g., finding plagiarism in a collection of student projects).
+
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use core::mem::discriminant;
+
+use crate::*;
+use crate::hash::FnvHasher;
+use crate::visit::*;
+
+/// The kinds of symbols whose names are ignored when fingerprinting.
+#[derive(Hash)]
+enum Leaf {
+    Var(VarLocation),
+    Fn(FnLocation),
+    Body,
+}
+
+/// A summary of the structure of some code which can be compared with [`Fingerprint::similarity`].
+///
+/// This is a multiset of hashes of every sufficiently large subtree of the code,
+/// where the hashes ignore the names of variables and custom blocks, the values of literals (other than their type), and [`BlockInfo`].
+/// Thus, renaming variables or changing constants does not affect the fingerprint.
+/// Hashes are deterministic for a given version of this crate, so fingerprints can be stored and compared later.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Fingerprint {
+    /// The subtree hashes, in sorted order.
+    pub hashes: Vec<u64>,
+}
+impl Fingerprint {
+    /// The minimum number of nodes in a subtree for it to be included in the fingerprint, which avoids matches on trivial code like single literals.
+    pub const MIN_SUBTREE_SIZE: usize = 3;
+
+    /// Computes the similarity of two fingerprints as the (multiset) Jaccard index of their hashes.
+    /// The result ranges from `0.0` (nothing in common) to `1.0` (structurally identical), and two empty fingerprints are considered identical.
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let (mut i, mut j, mut common) = (0, 0, 0);
+        while i < self.hashes.len() && j < other.hashes.len() {
+            match self.hashes[i].cmp(&other.hashes[j]) {
+                core::cmp::Ordering::Less => i += 1,
+                core::cmp::Ordering::Greater => j += 1,
+                core::cmp::Ordering::Equal => {
+                    common += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        let total = self.hashes.len() + other.hashes.len() - common;
+        if total == 0 { 1.0 } else { common as f64 / total as f64 }
+    }
+
+    fn add_stmts(&mut self, stmts: &[Stmt]) -> (u64, usize) {
+        let mut hasher = FnvHasher::new();
+        Leaf::Body.hash(&mut hasher);
+        let mut size = 0;
+        for stmt in stmts {
+            let (hash, n) = self.add_stmt(stmt);
+            hasher.write_u64(hash);
+            size += n;
+        }
+        (hasher.finish(), size)
+    }
+    fn add_stmt(&mut self, stmt: &Stmt) -> (u64, usize) {
+        let mut hasher = FnvHasher::new();
+        discriminant(&stmt.kind).hash(&mut hasher);
+        match &stmt.kind {
+            StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } => Leaf::Var(var.location).hash(&mut hasher),
+            StmtKind::CallFn { function, .. } => Leaf::Fn(function.location).hash(&mut hasher),
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        let mut size = 1;
+        for x in exprs {
+            let (hash, n) = self.add_expr(x);
+            hasher.write_u64(hash);
+            size += n;
+        }
+        for x in bodies {
+            let (hash, n) = self.add_stmts(x);
+            hasher.write_u64(hash);
+            size += n;
+        }
+        self.add_hash(hasher.finish(), size)
+    }
+    fn add_expr(&mut self, expr: &Expr) -> (u64, usize) {
+        let mut hasher = FnvHasher::new();
+        discriminant(&expr.kind).hash(&mut hasher);
+        match &expr.kind {
+            ExprKind::Value(value) => discriminant(value).hash(&mut hasher),
+            ExprKind::Variable { var } => Leaf::Var(var.location).hash(&mut hasher),
+            ExprKind::CallFn { function, .. } => Leaf::Fn(function.location).hash(&mut hasher),
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        let mut size = 1;
+        for x in exprs {
+            let (hash, n) = self.add_expr(x);
+            hasher.write_u64(hash);
+            size += n;
+        }
+        for x in bodies {
+            let (hash, n) = self.add_stmts(x);
+            hasher.write_u64(hash);
+            size += n;
+        }
+        self.add_hash(hasher.finish(), size)
+    }
+    fn add_hash(&mut self, hash: u64, size: usize) -> (u64, usize) {
+        if size >= Self::MIN_SUBTREE_SIZE {
+            self.hashes.push(hash);
+        }
+        (hash, size)
+    }
+}
+
+impl Project {
+    /// Computes a [`Fingerprint`] of all the code in the project (scripts, custom blocks, and hat conditions).
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut res = Fingerprint::default();
+        for role in self.roles.iter() {
+            for func in role.funcs.iter() {
+                res.add_stmts(&func.stmts);
+            }
+            for entity in role.entities.iter() {
+                for func in entity.funcs.iter() {
+                    res.add_stmts(&func.stmts);
+                }
+                for script in entity.scripts.iter() {
+                    if let Some(Hat { kind: HatKind::When { condition }, .. }) = script.hat.as_deref() {
+                        res.add_expr(condition);
+                    }
+                    res.add_stmts(&script.stmts);
+                }
+            }
+        }
+        res.hashes.sort_unstable();
+        res
+    }
+}
@@ -0,0 +1,146 @@
+//! Symbol tables and the name translation used by the parser.
+
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use core::{mem, fmt};
+
+use crate::*;
+
+#[derive(Debug)]
+pub enum SymbolError {
+    NameTransformError { name: CompactString },
+    ConflictingTrans { trans_name: CompactString, names: (CompactString, CompactString) },
+}
+
+#[derive(Clone)]
+pub(crate) struct VecMap<K, V>(Vec<(K, V)>);
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for VecMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{ {:?} }}", self.0)
+    }
+}
+impl<K, V> Default for VecMap<K, V> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+impl<K, V> VecMap<K, V> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub(crate) fn into_iter(self) -> alloc::vec::IntoIter<(K, V)> {
+        self.0.into_iter()
+    }
+    pub(crate) fn get<Q: PartialEq + ?Sized>(&self, key: &Q) -> Option<&V> where K: core::borrow::Borrow<Q> {
+        self.0.iter().find(|x| x.0.borrow() == key).map(|x| &x.1)
+    }
+    pub(crate) fn get_mut<Q: PartialEq + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where K: core::borrow::Borrow<Q> {
+        self.0.iter_mut().find(|x| x.0.borrow() == key).map(|x| &mut x.1)
+    }
+    pub(crate) fn insert(&mut self, k: K, v: V) -> Option<V> where K: PartialEq {
+        match self.get_mut(&k) {
+            Some(x) => Some(mem::replace(x, v)),
+            None => {
+                self.0.push((k, v));
+                None
+            }
+        }
+    }
+}
+
+/// A table of symbol definitions that enforces unique names, both before and after applying [`Parser::name_transformer`].
+///
+/// This is what the parser uses to validate the globals, fields, locals, and custom blocks of a project,
+/// and is exposed for tools that introduce new symbols into an existing AST.
+pub struct SymbolTable<'a, T = DefaultNameTransformer> {
+    parser: &'a Parser<T>,
+    orig_to_def: VecMap<CompactString, VariableDefInit>,
+    trans_to_orig: VecMap<CompactString, CompactString>,
+}
+impl<T> Clone for SymbolTable<'_, T> {
+    fn clone(&self) -> Self {
+        Self { parser: self.parser, orig_to_def: self.orig_to_def.clone(), trans_to_orig: self.trans_to_orig.clone() }
+    }
+}
+impl<T> fmt::Debug for SymbolTable<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SymbolTable {{ orig_to_def: {:?}, trans_to_orig: {:?} }}", self.orig_to_def, self.trans_to_orig)
+    }
+}
+impl<'a, T: NameTransformer> SymbolTable<'a, T> {
+    /// Creates an empty symbol table that transforms names with [`Parser::name_transformer`].
+    pub fn new(parser: &'a Parser<T>) -> Self {
+        Self { parser, orig_to_def: Default::default(), trans_to_orig: Default::default() }
+    }
+    /// Applies [`Parser::name_transformer`] to a name without defining it.
+    pub fn transform_name(&self, name: &str) -> Result<CompactString, SymbolError> {
+        match self.parser.name_transformer.transform_name(name) {
+            Ok(v) => Ok(v),
+            Err(()) => Err(SymbolError::NameTransformError { name: name.into() }),
+        }
+    }
+    /// Defines a new symbol or replaces an existing definition.
+    /// Fails if the name cannot be properly transformed or the transformed name already exists.
+    /// On success, returns the previous definition (if one existed).
+    /// On failure, the symbol table is not modified, and an error context object is returned.
+    pub fn define(&mut self, name: CompactString, value: Value) -> Result<Option<VariableDefInit>, SymbolError> {
+        let trans_name = self.transform_name(&name)?;
+        if let Some(orig) = self.trans_to_orig.get(&trans_name) {
+            let def = self.orig_to_def.get(orig).unwrap();
+            return Err(SymbolError::ConflictingTrans { trans_name, names: (def.def.name.clone(), name) });
+        }
+
+        let entry = VariableDefInit { def: VariableDef { name: name.clone(), trans_name: trans_name.clone() }, init: value };
+        self.trans_to_orig.insert(trans_name, name.clone());
+        Ok(self.orig_to_def.insert(name, entry))
+    }
+    /// Returns the definition of the given variable if it exists.
+    pub fn get(&self, name: &str) -> Option<&VariableDefInit> {
+        self.orig_to_def.get(name)
+    }
+    /// Gets the list of all defined variables.
+    /// This is guaranteed to be in order of definition.
+    pub fn into_defs(self) -> Vec<VariableDef> {
+        self.orig_to_def.into_iter().map(|x| x.1.def).collect()
+    }
+    /// Equivalent to [`SymbolTable::into_defs`] but preserves the initialized value.
+    pub fn into_def_inits(self) -> Vec<VariableDefInit> {
+        self.orig_to_def.into_iter().map(|x| x.1).collect()
+    }
+    pub fn len(&self) -> usize {
+        self.orig_to_def.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.orig_to_def.is_empty()
+    }
+}
+#[test]
+fn test_sym_tab() {
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    let mut sym = SymbolTable::new(&parser);
+    assert!(sym.orig_to_def.is_empty());
+    assert!(sym.trans_to_orig.is_empty());
+    assert!(sym.define("hello world!".into(), 0f64.into()).unwrap().is_none());
+    assert_eq!(sym.orig_to_def.get("hello world!").unwrap().def.name, "hello world!");
+    assert_eq!(sym.orig_to_def.get("hello world!").unwrap().def.trans_name, "hello_world");
+    assert_eq!(sym.trans_to_orig.get("hello_world").unwrap().as_str(), "hello world!");
+}
+
+/// A strategy for converting Snap! symbol names into target names, as used by [`Parser::name_transformer`].
+///
+/// This is implemented for all suitable closures, but can also be implemented directly by types that carry state (e.g., a table of reserved words).
+/// Failing to transform a name results in a [`CompileError::NameTransformError`].
+pub trait NameTransformer {
+    #[allow(clippy::result_unit_err)] // matches the closure signature accepted by Parser::name_transformer
+    fn transform_name(&self, name: &str) -> Result<CompactString, ()>;
+}
+impl<F: Fn(&str) -> Result<CompactString, ()> + ?Sized> NameTransformer for F {
+    fn transform_name(&self, name: &str) -> Result<CompactString, ()> {
+        self(name)
+    }
+}
+/// The (dynamically dispatched) name transformer used by [`Parser`] unless otherwise specified.
+pub type DefaultNameTransformer = Box<dyn Fn(&str) -> Result<CompactString, ()> + Send + Sync>;
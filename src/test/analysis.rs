@@ -0,0 +1,303 @@
+use alloc::vec::Vec;
+use crate::*;
+use crate::analysis::*;
+
+fn find_stmt(cfg: &Cfg, f: fn(&StmtKind) -> bool) -> Vec<usize> {
+    cfg.nodes.iter().enumerate().filter(|x| matches!(x.1.kind, CfgNodeKind::Stmt(stmt) if f(&stmt.kind))).map(|x| x.0).collect()
+}
+
+#[test]
+fn test_reaching_defs_and_dead_stores() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l></list></block><block s="doSetVar"><l>a</l><l>1</l></block><block s="doSetVar"><l>a</l><l>2</l></block><block s="bubble"><block var="a"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let cfg = Cfg::new(stmts);
+    assert_eq!(cfg.nodes.len(), 6);
+
+    let assigns = find_stmt(&cfg, |x| matches!(x, StmtKind::Assign { .. }));
+    let say = find_stmt(&cfg, |x| matches!(x, StmtKind::Say { .. }));
+    assert_eq!(assigns.len(), 2);
+    assert_eq!(say.len(), 1);
+
+    let var = VarKey { trans_name: "a".into(), location: VarLocation::Local };
+    let reaching = ReachingDefs::new(&cfg);
+    assert_eq!(reaching.reaching(say[0], &var).map(|x| x.node).collect::<Vec<_>>(), [assigns[1]]);
+    assert_eq!(reaching.reaching(assigns[0], &var).count(), 1);
+
+    let liveness = Liveness::new(&cfg);
+    assert!(liveness.live_in[say[0]].contains(&var));
+    assert!(!liveness.live_out[say[0]].contains(&var));
+    assert_eq!(liveness.dead_stores(&cfg), [assigns[0]]);
+}
+
+#[test]
+fn test_dataflow_loops() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>i</l></list></block><block s="doSetVar"><l>i</l><l>0</l></block><block s="doRepeat"><l>10</l><script><block s="doChangeVar"><l>i</l><l>1</l></block><block s="doSetVar"><l>g</l><block var="i"/></block></script></block><block s="bubble"><block var="i"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let cfg = Cfg::new(stmts);
+
+    let set = find_stmt(&cfg, |x| matches!(x, StmtKind::Assign { var, .. } if var.name == "i"));
+    let change = find_stmt(&cfg, |x| matches!(x, StmtKind::AddAssign { .. }));
+    let repeat = find_stmt(&cfg, |x| matches!(x, StmtKind::Repeat { .. }));
+    let say = find_stmt(&cfg, |x| matches!(x, StmtKind::Say { .. }));
+    assert_eq!(cfg.nodes[repeat[0]].succs.len(), 2);
+    assert!(cfg.nodes[repeat[0]].preds.contains(&set[0]));
+
+    let var = VarKey { trans_name: "i".into(), location: VarLocation::Local };
+    let reaching = ReachingDefs::new(&cfg);
+    let mut defs = reaching.reaching(say[0], &var).map(|x| x.node).collect::<Vec<_>>();
+    defs.sort();
+    assert_eq!(defs, [set[0], change[0]]);
+
+    let liveness = Liveness::new(&cfg);
+    assert!(liveness.live_out[set[0]].contains(&var));
+    assert!(liveness.live_in[Cfg::EXIT].contains(&VarKey { trans_name: "g".into(), location: VarLocation::Global }));
+    assert!(liveness.dead_stores(&cfg).is_empty());
+}
+
+#[test]
+fn test_dataflow_calls() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l><l>b</l></list></block><block s="doSetVar"><l>g</l><l>1</l></block><block s="doRun"><block s="reifyScript"><script><block s="doSetVar"><l>a</l><l>1</l></block></script><list></list></block><list></list></block><block s="doSetVar"><l>g</l><l>2</l></block><block s="bubble"><block var="a"/></block><block s="bubble"><block var="b"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let cfg = Cfg::new(stmts);
+
+    let declare = find_stmt(&cfg, |x| matches!(x, StmtKind::DeclareLocals { .. }));
+    let assigns = find_stmt(&cfg, |x| matches!(x, StmtKind::Assign { .. }));
+    let run = find_stmt(&cfg, |x| matches!(x, StmtKind::CallClosure { .. }));
+    let say = find_stmt(&cfg, |x| matches!(x, StmtKind::Say { .. }));
+    assert!(NodeEffects::new(&cfg.nodes[run[0]].kind).calls);
+    assert!(!NodeEffects::new(&cfg.nodes[assigns[0]].kind).calls);
+
+    // running the closure may assign the captured local and the global, but may also not assign them
+    let reaching = ReachingDefs::new(&cfg);
+    let a = VarKey { trans_name: "a".into(), location: VarLocation::Local };
+    let b = VarKey { trans_name: "b".into(), location: VarLocation::Local };
+    let g = VarKey { trans_name: "g".into(), location: VarLocation::Global };
+    let mut defs = reaching.reaching(say[0], &a).map(|x| x.node).collect::<Vec<_>>();
+    defs.sort();
+    assert_eq!(defs, [declare[0], run[0]]);
+    assert_eq!(reaching.reaching(say[1], &b).map(|x| x.node).collect::<Vec<_>>(), [declare[0]]);
+    let mut defs = reaching.reaching(assigns[1], &g).map(|x| x.node).collect::<Vec<_>>();
+    defs.sort();
+    assert_eq!(defs, [assigns[0], run[0]]);
+
+    // running the closure may also read the global, so the first assignment is not dead
+    let liveness = Liveness::new(&cfg);
+    assert!(liveness.live_in[run[0]].contains(&g));
+    assert!(liveness.dead_stores(&cfg).is_empty());
+
+    // the closure may have assigned a, but nothing assigns b
+    let lints: Vec<_> = ast.lint(&LintOptions::default()).into_iter().map(|x| (x.kind, x.node)).collect();
+    assert_eq!(lints, [(LintKind::UninitializedRead { var: "b".into() }, stmts[5].info.id)]);
+}
+
+#[test]
+fn test_type_inference() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable><variable name="h"><l>hello</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>h</l><block s="reportNewList"><list><l>1</l></list></block></block><block s="doSetVar"><l>g</l><block s="reportDifference"><block var="g"/><l>1</l></block></block><block s="bubble"><block s="reportLessThan"><block var="g"/><l>3</l></block></block><block s="bubble"><block s="reportJoinWords"><list><l>a</l><l>b</l></list></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    let types = TypeInfo::new(role);
+    assert_eq!(types.global_type("g"), Some(InferredType::Number));
+    assert_eq!(types.global_type("h"), Some(InferredType::Any));
+
+    let stmts = &role.entities[0].scripts[0].stmts;
+    match &stmts[1].kind {
+        StmtKind::Assign { value, .. } => assert_eq!(types.expr_type(value), InferredType::Number),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[2].kind {
+        StmtKind::Say { content, .. } => assert_eq!(types.expr_type(content), InferredType::Bool),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[3].kind {
+        StmtKind::Say { content, .. } => assert_eq!(types.expr_type(content), InferredType::String),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_effects() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportDifference"><l>5</l><l>1</l></block></block><block s="bubble"><block s="reportDifference"><block var="g"/><l>1</l></block></block><block s="bubble"><block s="reportRandom"><l>1</l><l>10</l></block></block><block s="doSetVar"><l>g</l><block s="reportNewList"><list></list></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let content = |i: usize| match &stmts[i].kind {
+        StmtKind::Say { content, .. } => content,
+        x => panic!("{x:?}"),
+    };
+    assert!(content(0).is_pure());
+    assert_eq!(Effects::of_expr(content(1)), Effects { reads: true, ..Effects::NONE });
+    assert_eq!(Effects::of_expr(content(2)), Effects { random: true, ..Effects::NONE });
+    assert_eq!(Effects::of_stmt(&stmts[3]), Effects { writes: true, allocates: true, ..Effects::NONE });
+}
+
+#[test]
+fn test_call_graph() {
+    let def = |name: &str, calls: &str| format!(r#"<block-definition s="{name}" type="command" category="custom"><header></header><code></code><translations></translations><inputs></inputs><script>{calls}</script></block-definition>"#);
+    let funcs = [
+        def("foo", r#"<custom-block s="bar"/>"#),
+        def("bar", r#"<block s="doIf"><l><bool>true</bool></l><script><custom-block s="foo"/></script></block>"#),
+        def("baz", r#"<custom-block s="baz"/><custom-block s="qux"/>"#),
+        def("qux", ""),
+    ].concat();
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = funcs, methods = "",
+        scripts = r#"<script><block s="receiveGo"/><custom-block s="foo"/></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let graph = CallGraph::new(&ast.roles[0]);
+    assert_eq!(graph.nodes.len(), 5);
+
+    let foo = graph.find_function(None, "foo").unwrap();
+    let bar = graph.find_function(None, "bar").unwrap();
+    let baz = graph.find_function(None, "baz").unwrap();
+    let qux = graph.find_function(None, "qux").unwrap();
+    assert_eq!(graph.nodes[foo].succs, [bar]);
+    assert_eq!(graph.nodes[baz].succs, [baz, qux]);
+    assert_eq!(graph.nodes[foo].preds, [bar, 4]);
+
+    assert!(graph.is_recursive(foo));
+    assert!(graph.is_recursive(bar));
+    assert!(graph.is_recursive(baz));
+    assert!(!graph.is_recursive(qux));
+    assert!(!graph.is_recursive(4));
+
+    let sccs = graph.sccs();
+    assert_eq!(sccs.len(), 4);
+    let pos = |node: usize| sccs.iter().position(|x| x.contains(&node)).unwrap();
+    assert_eq!(sccs[pos(foo)], [foo, bar]);
+    assert!(pos(qux) < pos(baz));
+    assert!(pos(foo) < pos(4));
+}
+
+#[test]
+fn test_scopes() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="f %&apos;p&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%s"></input></inputs><script><block s="bubble"><block var="p"/></block></script></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l></list></block><block s="doFor"><l>i</l><l>1</l><l>10</l><script><block s="doSetVar"><l>a</l><block s="reifyReporter"><autolambda><block var="x"/></autolambda><list><l>x</l></list></block></block></script></block><block s="bubble"><block var="i"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    let stmts = &role.entities[0].scripts[0].stmts;
+
+    let scope = Scope::of_script(&role.entities[0].scripts[0]);
+    let say_id = stmts[2].info.id;
+    assert_eq!(scope.kind, ScopeKind::Script);
+    assert_eq!(*scope.extent.start(), role.entities[0].scripts[0].hat.as_ref().unwrap().info.id);
+    assert_eq!(*scope.extent.end(), NodeId(say_id.0 + 1));
+    assert_eq!(scope.vars.iter().map(|x| (x.name.as_str(), x.declared_by)).collect::<Vec<_>>(), [("a", Some(stmts[0].info.id)), ("i", Some(stmts[1].info.id))]);
+    assert_eq!(scope.vars[1].extent, stmts[1].info.id..=*scope.extent.end());
+
+    assert_eq!(scope.children.len(), 1);
+    let closure = &scope.children[0];
+    assert_eq!(closure.kind, ScopeKind::Closure);
+    assert_eq!(closure.vars.len(), 1);
+    assert_eq!(closure.vars[0].name, "x");
+    assert!(closure.extent.end() < &say_id);
+    assert!(core::ptr::eq(scope.innermost(*closure.extent.end()).unwrap(), closure));
+    assert!(core::ptr::eq(scope.innermost(say_id).unwrap(), &scope));
+    assert!(scope.innermost(NodeId(say_id.0 + 2)).is_none());
+
+    let scope = Scope::of_function(&role.funcs[0]);
+    assert_eq!(scope.kind, ScopeKind::Function);
+    assert_eq!(scope.vars.len(), 1);
+    assert_eq!(scope.vars[0].declared_by, None);
+    assert_eq!(scope.vars[0].extent, scope.extent);
+}
+
+#[test]
+fn test_capture_modes() {
+    let inner = r#"<block s="reifyScript"><script><block s="doChangeVar"><l>c</l><l>1</l></block></script><list></list></block>"#;
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l><l>b</l><l>c</l><l>f</l></list></block><block s="doSetVar"><l>f</l><block s="reifyScript"><script><block s="doSetVar"><l>a</l><block var="b"/></block><block s="doSetVar"><l>f</l>{inner}</block></script><list></list></block></block></script>"#),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let closure = match &ast.roles[0].entities[0].scripts[0].stmts[1].kind {
+        StmtKind::Assign { value, .. } => value,
+        x => panic!("{x:?}"),
+    };
+    let captures = captures_of(closure).unwrap();
+    assert_eq!(captures.iter().map(|x| (x.var.name.as_str(), x.mode)).collect::<Vec<_>>(), [
+        ("a", CaptureMode::Mutated), ("b", CaptureMode::ReadOnly), ("f", CaptureMode::Mutated), ("c", CaptureMode::Mutated),
+    ]);
+    assert!(captures_of(&Expr::from(ExprKind::Value(1.0.into()))).is_none());
+}
+
+#[test]
+fn test_loop_bindings() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="count to %&apos;n&apos; as %&apos;k&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n"></input><input type="%upvar"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>f</l></list></block><block s="doFor"><l>i</l><l>1</l><l>10</l><script><block s="doSetVar"><l>f</l><block s="reifyReporter"><autolambda><block var="i"/></autolambda><list></list></block></block></script></block><block s="doFor"><l>j</l><l>1</l><l>10</l><script><block s="bubble"><block var="j"/></block></script></block><block s="bubble"><block var="j"/></block><custom-block s="count to %n as %upvar"><l>10</l><l>k</l></custom-block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let bindings = loop_bindings(stmts);
+    assert_eq!(bindings.iter().map(|x| (x.var.name.as_str(), x.declared_by, x.captured, x.read_after)).collect::<Vec<_>>(), [
+        ("i", stmts[1].info.id, true, false),
+        ("j", stmts[2].info.id, false, true),
+        ("k", stmts[4].info.id, false, false),
+    ]);
+    assert!(bindings[0].needs_fresh_binding());
+    assert!(!bindings[1].needs_fresh_binding());
+}
+
+#[test]
+fn test_symbol_index() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = r#"<block-definition s="f %&apos;p&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%s"></input></inputs><script><block s="bubble"><block var="p"/></block><block s="doSetVar"><l>g</l><block var="p"/></block></script></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l></list></block><block s="doSetVar"><l>a</l><block var="g"/></block><custom-block s="f %s"><block var="a"/></custom-block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    let stmts = &role.entities[0].scripts[0].stmts;
+    let index = SymbolIndex::new(role);
+
+    let g = Symbol::Global { trans_name: "g".into() };
+    assert_eq!(index.definition(&g).unwrap().name, "g");
+    assert_eq!(index.references(&g).map(|x| x.container).collect::<Vec<_>>(), [
+        SymbolContainer::Function { entity: None, func: 0 },
+        SymbolContainer::Script { entity: 0, script: 0 },
+    ]);
+
+    let f = Symbol::Function { entity: None, trans_name: role.funcs[0].trans_name.clone() };
+    assert_eq!(index.definition(&f).unwrap().node, None);
+    assert_eq!(index.references(&f).map(|x| x.node).collect::<Vec<_>>(), [stmts[2].info.id]);
+
+    let p = Symbol::Local { container: SymbolContainer::Function { entity: None, func: 0 }, trans_name: "p".into(), declared_by: None };
+    assert!(index.definition(&p).is_some());
+    assert_eq!(index.references(&p).count(), 2);
+
+    let a = Symbol::Local { container: SymbolContainer::Script { entity: 0, script: 0 }, trans_name: "a".into(), declared_by: Some(stmts[0].info.id) };
+    assert_eq!(index.definition(&a).unwrap().node, Some(stmts[0].info.id));
+    assert_eq!(index.references(&a).count(), 2);
+    assert_eq!(index.references_at(stmts[1].info.id).map(|x| &x.symbol).collect::<Vec<_>>(), [&a]);
+}
@@ -0,0 +1,45 @@
+use alloc::vec::Vec;
+
+use crate::*;
+
+#[test]
+fn test_map_annotations() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="x"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>x</l><block s="reportDifference"><l>1</l><block s="reifyReporter"><autolambda><block s="reportQuotient"><l>2</l><l>3</l></block></autolambda><list></list></block></block></block></script>"#,
+    );
+    let parser = Parser::default();
+    let ast = parser.parse(&script).unwrap();
+
+    let mut next = 0;
+    let ast = ast.map_annotations(|()| { next += 1; next });
+    assert_eq!(next, 9);
+
+    let script = &ast.roles[0].entities[0].scripts[0];
+    assert_eq!(script.hat.as_ref().unwrap().info.annotation, 1);
+    let stmt = &script.stmts[0];
+    assert_eq!(stmt.info.annotation, 2);
+    let (left, right) = match &stmt.kind {
+        StmtKind::Assign { value, .. } => match &value.kind {
+            ExprKind::Sub { left, right } => {
+                assert_eq!(value.info.annotation, 3);
+                (left, right)
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(left.info.annotation, 4);
+    assert_eq!(right.info.annotation, 5);
+    match &right.kind {
+        ExprKind::Closure { stmts, .. } => {
+            let annotations: Vec<_> = stmts.iter().map(|x| x.info.annotation).collect();
+            assert_eq!(annotations, [6]);
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let ast = ast.map_annotations(|x| x * 10);
+    assert_eq!(ast.roles[0].entities[0].scripts[0].stmts[0].info.annotation, 20);
+}
@@ -1306,6 +1306,35 @@ fn test_upvars() {
     assert_eq!(func.upvars[0].name, "i");
 }
 
+#[test]
+fn test_upvars_at_call_site() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="count to %&apos;n&apos; as %&apos;i&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n"></input><input type="%upvar"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><custom-block s="count to %n as %upvar"><l>10</l><l>my counter</l></custom-block><block s="bubble"><block var="my counter"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let upvar = match &stmts[0].kind {
+        StmtKind::CallFn { args, upvars, .. } => {
+            assert_eq!(args.len(), 2);
+            assert_eq!(upvars.len(), 1);
+            upvars[0].clone()
+        }
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(upvar.name, "my counter");
+    assert_eq!(upvar.location, VarLocation::Local);
+    match &stmts[1].kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::Variable { var } => assert_eq!(*var, upvar),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
 #[test]
 fn test_crlf() {
     let script = format!(include_str!("script-template.xml"),
@@ -2119,6 +2148,1572 @@ fn test_replace() {
 
 }
 
+#[test]
+fn test_list_blocks_not_lowered() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportListIsEmpty"><block s="reportNewList"><list></list></block></block></block><block s="bubble"><block s="reportCDR"><block s="reportNewList"><list></list></block></block></block><block s="bubble"><block s="reportListContainsItem"><block s="reportNewList"><list></list></block><l>4</l></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let content = |i: usize| match &stmts[i].kind {
+        StmtKind::Say { content, .. } => &content.kind,
+        x => panic!("{x:?}"),
+    };
+    assert!(matches!(content(0), ExprKind::ListIsEmpty { .. }));
+    assert!(matches!(content(1), ExprKind::ListCdr { .. }));
+    assert!(matches!(content(2), ExprKind::ListContains { .. }));
+}
+
+#[test]
+fn test_lowered_opcode() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportSum"><l>1</l><l>2</l></block></block><block s="bubble"><block s="reportMonadic"><l><option>ln</option></l><l>3</l></block></block><block s="bubble"><block s="reportVariadicSum"><list><l>1</l></list></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let opcode = |i: usize| match &stmts[i].kind {
+        StmtKind::Say { content, .. } => content.info.opcode.as_deref(),
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(opcode(0), Some("reportSum"));
+    assert_eq!(opcode(1), Some("reportMonadic"));
+    assert_eq!(opcode(2), None);
+    assert_eq!(stmts[0].info.opcode, None);
+}
+
+#[test]
+fn test_parse_warnings() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="forward"><l>5</l></block></script><script><block s="reportSum"><l>1</l><l>2</l></block></script><script><block collabId="item_7" s="forward"><l>5</l></block></script>"#,
+    );
+    let (ast, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.roles[0].entities[0].scripts.len(), 1);
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].kind, WarningKind::FloatingReporterOmitted);
+    assert_eq!(warnings[1].kind, WarningKind::NonHatScriptOmitted);
+    assert_eq!(warnings[1].location.entity.as_deref(), Some("Stage"));
+    assert_eq!(warnings[1].location.collab_id.as_deref(), Some("item_7"));
+
+    let parser = Parser { omit_nonhat_scripts: false, ..Default::default() };
+    let (ast, warnings) = parser.parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.roles[0].entities[0].scripts.len(), 2);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::FloatingReporterOmitted);
+}
+
+#[test]
+fn test_strictness() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportMonadic"><l><option>foo</option></l><l>3</l></block></block><block s="bubble"><block s="reportMonadic"><l><option></option></l><l>3</l></block></block></script>"#,
+    );
+    match Parser::default().parse(&script) {
+        Err(e) => assert_eq!(e.kind, ProjectError::BlockOptionUnknown { got: "foo".into() }.into()),
+        Ok(x) => panic!("{x:?}"),
+    }
+
+    let check = |strictness: Strictness| {
+        let (ast, warnings) = Parser { strictness, ..Default::default() }.parse_with_diagnostics(&script).unwrap();
+        for stmt in ast.roles[0].entities[0].scripts[0].stmts.iter() {
+            match &stmt.kind {
+                StmtKind::Say { content, .. } => match &content.kind {
+                    ExprKind::UnknownBlock { name, args } => {
+                        assert_eq!(name, "reportMonadic");
+                        assert_eq!(args.len(), 2);
+                    }
+                    x => panic!("{x:?}"),
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        warnings
+    };
+    let warnings = check(Strictness::Lenient);
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].kind, WarningKind::BlockParsedAsUnknown { error: ProjectError::BlockOptionUnknown { got: "foo".into() }.into() });
+    assert_eq!(warnings[1].kind, WarningKind::BlockParsedAsUnknown { error: CompileError::BlockOptionNotSelected.into() });
+    assert_eq!(check(Strictness::Permissive).len(), 0);
+}
+
+#[test]
+fn test_repeat_counter() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doRepeat"><l>10</l><l>n</l><script><block s="bubble"><block var="n"/></block></script></block><block s="doRepeat"><l>3</l><script></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::Repeat { counter: Some(counter), stmts, .. } => {
+            assert_eq!((counter.name.as_str(), counter.location), ("n", VarLocation::Local));
+            match &stmts[0].kind {
+                StmtKind::Say { content, .. } => assert_eq!(content.kind, ExprKind::Variable { var: counter.clone() }),
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    assert!(matches!(stmts[1].kind, StmtKind::Repeat { counter: None, .. }));
+}
+
+#[test]
+fn test_pipe() {
+    let ring = r#"<block s="reifyReporter"><autolambda><block s="reportProduct"><l></l><l>2</l></block></autolambda><list></list></block>"#;
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportPipe"><l>3</l><list>{ring}{ring}</list></block></block></script>"#),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    match &ast.roles[0].entities[0].scripts[0].stmts[0].kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::Pipe { value, functions } => {
+                assert_eq!(value.kind, ExprKind::Value(Value::String("3".into())));
+                match &functions.kind {
+                    ExprKind::MakeList { values } => {
+                        assert_eq!(values.len(), 2);
+                        assert!(values.iter().all(|x| matches!(x.kind, ExprKind::Closure { .. })));
+                    }
+                    x => panic!("{x:?}"),
+                }
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_metaprogramming() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDefineBlock"><l>blk</l><block s="reifyScript"><script></script><list></list></block></block><block s="doSetBlockAttribute"><l><option>label</option></l><block var="blk"/><l>foo _</l></block><block s="bubble"><block s="reportBlockAttribute"><l><option>definition</option></l><block var="blk"/></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let var = match &stmts[0].kind {
+        StmtKind::DefineBlock { var, definition } => {
+            assert!(matches!(definition.kind, ExprKind::Closure { .. }));
+            assert_eq!((var.name.as_str(), var.location), ("blk", VarLocation::Local));
+            var
+        }
+        x => panic!("{x:?}"),
+    };
+    match &stmts[1].kind {
+        StmtKind::SetBlockAttribute { attr, block, value } => {
+            assert_eq!(attr.kind, ExprKind::Value("label".into()));
+            assert_eq!(block.kind, ExprKind::Variable { var: var.clone() });
+            assert_eq!(value.kind, ExprKind::Value("foo _".into()));
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[2].kind {
+        StmtKind::Say { content, .. } => assert!(matches!(content.kind, ExprKind::BlockAttribute { .. })),
+        x => panic!("{x:?}"),
+    }
+
+    match (Parser { allow_metaprogramming: false, ..Default::default() }).parse(&script) {
+        Err(e) => {
+            assert_eq!(e.kind, CompileError::MetaprogrammingNotAllowed.into());
+            assert_eq!(e.location.block_type.as_deref(), Some("doDefineBlock"));
+        }
+        Ok(x) => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_codification() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="foo %'x'" type="command" category="custom"><header>void foo(int x);</header><code>foo(&lt;#1&gt;);</code><translations></translations><inputs><input type="%s"></input></inputs></block-definition>"#, methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doMapCodeOrHeader"><block s="reifyScript"><script><block s="forward"><l></l></block></script><list></list></block><l><option>code</option></l><l>move(&lt;#1&gt;);</l></block><block s="bubble"><block s="reportMappedCode"><block s="reifyScript"><script><block s="forward"><l>10</l></block></script><list></list></block></block></block></script>"#,
+    ).replace("<code></code>", "<code><forward>move(&lt;#1&gt;);</forward></code>");
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    assert_eq!(role.code_mappings, [("forward".into(), "move(<#1>);".into())]);
+    assert_eq!(role.code_headers, []);
+    assert_eq!(role.funcs[0].code_mapping, "foo(<#1>);");
+    assert_eq!(role.funcs[0].code_header, "void foo(int x);");
+
+    let stmts = &role.entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::MapCode { block, kind, code } => {
+            assert!(matches!(block.kind, ExprKind::Closure { .. }));
+            assert_eq!(*kind, CodeKind::Code);
+            assert_eq!(code.kind, ExprKind::Value("move(<#1>);".into()));
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[1].kind {
+        StmtKind::Say { content, .. } => assert!(matches!(content.kind, ExprKind::MappedCode { .. })),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_palette() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    ).replace("<hidden></hidden>", r#"<hidden>forward turn
+turnLeft</hidden><palette><category name="robots" color="10,20,30"/></palette>"#);
+    let ast = Parser::default().parse(&script).unwrap();
+    let palette = &ast.roles[0].palette;
+    assert_eq!(palette.hidden_blocks, ["forward", "turn", "turnLeft"]);
+    assert_eq!(palette.categories, [Category { name: "robots".into(), color: (10, 20, 30, 255) }]);
+}
+
+#[test]
+fn test_canvas_comments() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<comment x="12.5" y="-4" w="90" collapsed="false">hello
+world</comment><script><block s="receiveGo"/><block s="forward"><l>5</l></block></script><comment x="bad" y="3" w="90" collapsed="false">another</comment>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let entity = &ast.roles[0].entities[0];
+    assert_eq!(entity.scripts.len(), 1);
+    assert_eq!(entity.canvas_comments, [
+        CanvasComment { text: "hello\nworld".into(), pos: (12.5, -4.0) },
+        CanvasComment { text: "another".into(), pos: (0.0, 3.0) },
+    ]);
+}
+
+#[test]
+fn test_skip_history() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    ).replace("<hidden></hidden>", r#"<hidden></hidden><replay><actions><action type="foo">&bogus;</action><empty/></actions></replay>"#);
+    let ast = Parser::default().parse(&script).unwrap();
+    assert_eq!(ast.roles[0].entities[0].name, "Stage");
+
+    match (Parser { skip_history: false, ..Default::default() }).parse(&script) {
+        Err(e) => assert!(matches!(e.kind, ErrorKind::XmlError(XmlError::IllegalSequence { .. }))),
+        Ok(x) => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_thumbnail_pen_trails() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    ).replace("<notes></notes>", "<notes></notes><thumbnail>data:image/png;base64,aGVsbG8=</thumbnail>").replace("<costumes>", "<pentrails>not an image</pentrails><costumes>");
+    let (ast, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.roles[0].thumbnail.as_deref(), Some(b"hello".as_slice()));
+    assert_eq!(ast.roles[0].pen_trails, None);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::InvalidImageIgnored { name: "pentrails".into() });
+}
+
+#[test]
+fn test_project_meta() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    );
+    let (ast, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.meta, ProjectMeta { app_name: Some("NetsBlox".into()), app_version: Some("1.29.1".into()), version: Some("1.29.1".into()) });
+    assert_eq!(warnings, []);
+
+    let script = script.replace(r#"version="1.29.1""#, r#"version="3""#);
+    let (ast, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.meta.version.as_deref(), Some("3"));
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::UnsupportedVersion { version: "3".into() });
+}
+
+#[test]
+fn test_key_hats() {
+    let hat = |key: &str| format!(r#"<script><block s="receiveKey"><l><option>{key}</option></l></block></script>"#);
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = ["a", "7", "up arrow", "right arrow", "space", "enter", "any key", "+"].map(hat).concat(),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let keys: Vec<_> = ast.roles[0].entities[0].scripts.iter().map(|x| match &x.hat.as_ref().unwrap().kind {
+        HatKind::OnKey { key } => key.clone(),
+        x => panic!("{x:?}"),
+    }).collect();
+    assert_eq!(keys, [KeyCode::Char('a'), KeyCode::Char('7'), KeyCode::Up, KeyCode::Right, KeyCode::Space, KeyCode::Enter, KeyCode::Any, KeyCode::Other("+".into())]);
+}
+
+#[test]
+fn test_if_chains() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doIf"><l>1</l><script><block s="forward"><l>1</l></block></script><list><l>2</l><script><block s="forward"><l>2</l></block></script><l>3</l><script></script></list></block><block s="doIfElse"><l>4</l><script></script><list><l>5</l><script></script></list><script><block s="forward"><l>6</l></block></script></block><block s="doSayFor"><block s="reportIfElse"><l>7</l><l>a</l><list><l>8</l><l>b</l></list><l>c</l></block><l>1</l></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts.len(), 3);
+
+    let value = |expr: &Expr| match &expr.kind {
+        ExprKind::Value(Value::String(x)) => x.clone(),
+        x => panic!("{x:?}"),
+    };
+    match &stmts[0].kind {
+        StmtKind::IfElse { condition, then, otherwise } => {
+            assert_eq!(value(condition), "1");
+            assert_eq!(then.len(), 1);
+            assert_eq!(stmts[0].info.opcode.as_deref(), Some("doIf"));
+            match &otherwise[..] {
+                [Stmt { kind: StmtKind::IfElse { condition, then, otherwise }, .. }] => {
+                    assert_eq!(value(condition), "2");
+                    assert_eq!(then.len(), 1);
+                    match &otherwise[..] {
+                        [Stmt { kind: StmtKind::If { condition, then }, .. }] => {
+                            assert_eq!(value(condition), "3");
+                            assert_eq!(then.len(), 0);
+                        }
+                        x => panic!("{x:?}"),
+                    }
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[1].kind {
+        StmtKind::IfElse { condition, otherwise, .. } => {
+            assert_eq!(value(condition), "4");
+            match &otherwise[..] {
+                [Stmt { kind: StmtKind::IfElse { condition, otherwise, .. }, .. }] => {
+                    assert_eq!(value(condition), "5");
+                    assert_eq!(otherwise.len(), 1);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[2].kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::Conditional { condition, then, otherwise } => {
+                assert_eq!((value(condition), value(then)), ("7".into(), "a".into()));
+                match &otherwise.kind {
+                    ExprKind::Conditional { condition, then, otherwise } => assert_eq!((value(condition), value(then), value(otherwise)), ("8".into(), "b".into(), "c".into())),
+                    x => panic!("{x:?}"),
+                }
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_js_function() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSayFor"><block s="reportJSFunction"><list><l>a</l><l>b</l></list><l>return a + b;</l></block><l>1</l></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    match &ast.roles[0].entities[0].scripts[0].stmts[0].kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::JsFunction { params, body } => {
+                assert_eq!(params, &["a", "b"]);
+                assert_eq!(body, "return a + b;");
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_text_functions() {
+    let block = |f: &str| format!(r#"<block s="doSayFor"><block s="reportTextFunction"><l><option>{f}</option></l><l>hello</l></block><l>1</l></block>"#);
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/>{}</script>"#, ["upper case", "trim", "encode URI component", "hex sha512 hash"].map(block).concat()),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let functions: Vec<_> = ast.roles[0].entities[0].scripts[0].stmts.iter().map(|x| match &x.kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::TextFunction { function, value } => {
+                assert_eq!(value.kind, ExprKind::Value("hello".into()));
+                function.clone()
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }).collect();
+    assert_eq!(functions, [TextFunction::Upper, TextFunction::Trim, TextFunction::EncodeUriComponent, TextFunction::HexSha512]);
+}
+
+#[test]
+fn test_variadic_bool() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="x"><list struct="atomic"></list></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doIf"><block s="reportVariadicAnd"><list><block s="reportBoolean"><l><bool>true</bool></l></block><block s="reportBoolean"><l><bool>false</bool></l></block><block var="x"/></list></block><script></script></block><block s="doIf"><block s="reportVariadicOr"><list></list></block><script></script></block><block s="doIf"><block s="reportVariadicOr"><block var="x"/></block><script></script></block><block s="doIf"><block s="reportVariadicAnd" collabId="outer"><list><block var="x" collabId="inner"><comment w="90" collapsed="false">inner</comment></block></list></block><script></script></block><block s="doIf"><block s="reportVariadicOr" collabId="outer"><list><block var="x" collabId="inner"/></list><comment w="90" collapsed="false">outer</comment></block><script></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let conditions: Vec<_> = ast.roles[0].entities[0].scripts[0].stmts.iter().map(|x| match &x.kind {
+        StmtKind::If { condition, .. } => condition,
+        x => panic!("{x:?}"),
+    }).collect();
+
+    match &conditions[0].kind {
+        ExprKind::And { left, right } => {
+            assert!(matches!(right.kind, ExprKind::Variable { .. }));
+            match &left.kind {
+                ExprKind::And { left, right } => {
+                    assert_eq!(left.kind, ExprKind::Value(true.into()));
+                    assert_eq!(right.kind, ExprKind::Value(false.into()));
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(conditions[0].info.opcode.as_deref(), Some("reportVariadicAnd"));
+    assert_eq!(conditions[1].kind, ExprKind::Value(false.into()));
+    match &conditions[2].kind {
+        ExprKind::UnknownBlock { name, args } => {
+            assert_eq!(name, "reportVariadicOr");
+            assert_eq!(args.len(), 1);
+        }
+        x => panic!("{x:?}"),
+    }
+
+    // a single item keeps its own info, as well as the comment of the outer block if it has none of its own
+    for (condition, comment) in [(conditions[3], "inner"), (conditions[4], "outer")] {
+        assert!(matches!(condition.kind, ExprKind::Variable { .. }));
+        assert_eq!((condition.info.location.as_deref(), condition.info.opcode.as_deref(), condition.info.comment.as_deref()), (Some("inner"), None, Some(comment)));
+    }
+}
+
+#[test]
+fn test_relational_ops() {
+    let block = |s: &str| format!(r#"<block s="doIf"><block s="{s}"><l>1</l><l>2</l></block><script></script></block>"#);
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/>{}</script>"#, ["reportLessThanOrEquals", "reportGreaterThanOrEquals", "reportNotEquals"].map(block).concat()),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert!(matches!(&stmts[0].kind, StmtKind::If { condition, .. } if matches!(condition.kind, ExprKind::LessEq { .. })));
+    assert!(matches!(&stmts[1].kind, StmtKind::If { condition, .. } if matches!(condition.kind, ExprKind::GreaterEq { .. })));
+    assert!(matches!(&stmts[2].kind, StmtKind::If { condition, .. } if matches!(condition.kind, ExprKind::Neq { .. })));
+}
+
+#[test]
+fn test_floating_reporters() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="x"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block var="x"/></script><script><block s="receiveGo"/><block s="forward"><l>5</l></block></script><script><block s="reportDifference"><l>3</l><block var="x"/></block></script>"#,
+    );
+    let (ast, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(ast.roles[0].entities[0].floating_reporters, []);
+    assert_eq!(warnings.iter().filter(|x| x.kind == WarningKind::FloatingReporterOmitted).count(), 2);
+
+    let (ast, warnings) = (Parser { keep_floating_reporters: true, ..Default::default() }).parse_with_diagnostics(&script).unwrap();
+    let entity = &ast.roles[0].entities[0];
+    assert_eq!(entity.scripts.len(), 1);
+    assert_eq!(warnings, []);
+    match entity.floating_reporters.as_slice() {
+        [a, b] => {
+            assert!(matches!(&a.kind, ExprKind::Variable { var } if var.name == "x"));
+            assert!(matches!(&b.kind, ExprKind::Sub { .. }));
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_lookup_helpers() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>1</l></variable>"#, fields = r#"<variable name="f"><l>2</l></variable>"#,
+        funcs = "", methods = "",
+        scripts = "",
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    assert!(ast.role("nope").is_none());
+    let role = ast.role("myRole").unwrap();
+    assert_eq!(role.global("g").unwrap().init, Value::from("1"));
+    assert!(role.global("f").is_none());
+    assert!(role.func("foo").is_none());
+    assert!(role.entity("Stage2").is_none());
+    let stage = role.entity("Stage").unwrap();
+    assert_eq!(stage.field("f").unwrap().init, Value::from("2"));
+    assert!(stage.field("g").is_none());
+}
+
+#[test]
+fn test_parser_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Parser>();
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="foo bar"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    );
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    super::std::thread::scope(|s| {
+        let threads: Vec<_> = (0..2).map(|_| s.spawn(|| parser.parse(&script).unwrap().roles[0].globals[0].def.trans_name.clone())).collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), "foo_bar");
+        }
+    });
+}
+
+#[test]
+fn test_custom_name_transformer() {
+    struct Reserved(&'static [&'static str]);
+    impl NameTransformer for Reserved {
+        fn transform_name(&self, name: &str) -> Result<CompactString, ()> {
+            let name = crate::util::c_ident(name)?;
+            Ok(if self.0.contains(&name.as_str()) { format_compact!("{name}_") } else { name })
+        }
+    }
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="int"><l>0</l></variable><variable name="foo bar"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    );
+    let parser = Parser::default().with_name_transformer(Reserved(&["int", "char"]));
+    let ast = parser.parse(&script).unwrap();
+    let trans_names: Vec<_> = ast.roles[0].globals.iter().map(|x| x.def.trans_name.as_str()).collect();
+    assert_eq!(trans_names, ["int_", "foo_bar"]);
+
+    let mut role = RoleBuilder::new(&parser, "role".into());
+    assert_eq!(role.global("char".into(), 0f64.into()).unwrap().trans_name, "char_");
+}
+
+#[test]
+fn test_node_ids() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doIf"><block s="reportLessThan"><l>1</l><l>2</l></block><script><block s="forward"><l>3</l></block></script></block></script><script><block s="receiveGo"/><block s="forward"><l>4</l></block></script>"#,
+    );
+    let parser = Parser::default();
+    let mut ast = parser.parse(&script).unwrap();
+    let scripts = &ast.roles[0].entities[0].scripts;
+    assert_eq!(scripts[0].hat.as_ref().unwrap().info.id, NodeId(1));
+    let stmt = &scripts[0].stmts[0];
+    assert_eq!(stmt.info.id, NodeId(2));
+    match &stmt.kind {
+        StmtKind::If { condition, then } => {
+            assert_eq!(condition.info.id, NodeId(3));
+            assert_eq!(then[0].info.id, NodeId(6));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(scripts[1].hat.as_ref().unwrap().info.id, NodeId(8));
+    assert_eq!(scripts[1].stmts[0].info.id, NodeId(9));
+
+    // ids are deterministic and are not considered by equality comparisons
+    assert_eq!(parser.parse(&script).unwrap().roles[0].entities[0].scripts[1].stmts[0].info.id, NodeId(9));
+    ast.roles[0].entities[0].scripts.remove(0);
+    assert_eq!(ast.roles[0].entities[0].scripts[0].stmts[0].info.id, NodeId(9));
+    ast.assign_node_ids();
+    let script = &ast.roles[0].entities[0].scripts[0];
+    assert_eq!(script.stmts[0].info.id, NodeId(2));
+    assert_eq!(script.stmts[0], Stmt { info: BlockInfo::none(), ..script.stmts[0].clone() });
+}
+
+#[test]
+fn test_content_hash() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="forward"><l>5</l></block><block s="turn"><l>5</l></block></script><script><block s="receiveGo"/><block s="forward"><l>5</l><comment w="90" collapsed="false">hello</comment></block><block s="turn"><l>5</l></block></script><script><block s="receiveGo"/><block s="forward"><l>5</l></block><block s="turn"><l>6</l></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let scripts = &ast.roles[0].entities[0].scripts;
+    assert_eq!(scripts[0].content_hash(), scripts[1].content_hash());
+    assert_ne!(scripts[0].content_hash(), scripts[2].content_hash());
+    assert_eq!(scripts[0].stmts[0].content_hash(), scripts[2].stmts[0].content_hash());
+    assert_ne!(scripts[0].stmts[0].content_hash(), scripts[0].stmts[1].content_hash());
+
+    let zero: Expr = 0f64.into();
+    let neg_zero: Expr = (-0f64).into();
+    assert_eq!(zero.content_hash(), neg_zero.content_hash());
+
+    // lengths and discriminants hash the same regardless of the size of usize
+    use core::hash::Hasher;
+    let hash = |f: &dyn Fn(&mut crate::hash::FnvHasher)| {
+        let mut hasher = crate::hash::FnvHasher::new();
+        f(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(&|h| h.write_usize(300)), hash(&|h| h.write(&[44, 1, 0, 0, 0, 0, 0, 0])));
+    assert_eq!(hash(&|h| h.write_isize(-1)), hash(&|h| h.write_u64(u64::MAX)));
+    assert_eq!(hash(&|h| h.write_u32(300)), hash(&|h| h.write(&[44, 1, 0, 0])));
+}
+
+#[test]
+fn test_fingerprint() {
+    let project = |var: &str, value: &str, extra: &str| {
+        let script = format!(include_str!("script-template.xml"),
+            globals = "", fields = "",
+            funcs = "", methods = "",
+            scripts = format!(r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>{var}</l></list></block><block s="doSetVar"><l>{var}</l><l>{value}</l></block><block s="doRepeat"><l>10</l><script><block s="doChangeVar"><l>{var}</l><block s="reportVariadicProduct"><list><block var="{var}"/><l>2</l></list></block></block></script></block><block s="bubble"><block var="{var}"/></block>{extra}</script>"#),
+        );
+        Parser::default().parse(&script).unwrap().fingerprint()
+    };
+
+    let a = project("total", "1", "");
+    let b = project("sum", "7", "");
+    let c = project("total", "1", r#"<block s="doIf"><block s="reportLessThan"><block var="total"/><l>100</l></block><script><block s="forward"><block s="reportVariadicSum"><list><block var="total"/><l>3</l></list></block></block></script></block>"#);
+    assert!(!a.hashes.is_empty());
+    assert!(a.hashes.windows(2).all(|x| x[0] <= x[1]));
+    assert_eq!(a, b);
+    assert_eq!(a.similarity(&b), 1.0);
+
+    let sim = a.similarity(&c);
+    assert!(sim > 0.0 && sim < 1.0);
+    assert_eq!(sim, c.similarity(&a));
+    assert_eq!(Fingerprint::default().similarity(&Fingerprint::default()), 1.0);
+    assert_eq!(a.similarity(&Fingerprint::default()), 0.0);
+}
+
+#[test]
+fn test_shared_values() {
+    let list = Value::List(vec![1.0.into(), Value::Ref(RefId(7))], Some(RefId(7)));
+    let outer = Value::List(vec![Value::Ref(RefId(3)), list.clone(), Value::Ref(RefId(7)), Value::List(vec!["x".into()], None)], None);
+
+    let mut values = SharedValues::new();
+    let shared = values.share(&outer);
+    let items = match &shared {
+        SharedValue::List(x) => x.borrow().clone(),
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(items.len(), 4);
+    assert!(items[1].is_identical(&items[2]));
+    assert!(!items[1].is_identical(&items[3]));
+    assert!(!items[1].is_identical(&SharedValue::new_list(vec![1.0.into()])));
+    assert!(SharedValue::from("a").is_identical(&"a".into()));
+    match &items[1] {
+        SharedValue::List(x) => {
+            assert!(x.borrow()[1].is_identical(&items[1])); // contains itself
+            x.borrow_mut()[0] = 5.0.into();
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(values.unresolved().collect::<alloc::vec::Vec<_>>(), [RefId(3)]);
+
+    // mutations are visible through every alias, and aliasing survives the round trip
+    let expected = Value::List(vec![Value::List(vec![], None), Value::List(vec![5.0.into(), Value::Ref(RefId(1))], Some(RefId(1))), Value::Ref(RefId(1)), Value::List(vec!["x".into()], None)], None);
+    assert_eq!(shared.to_value(), expected);
+    assert_eq!(list.to_shared().to_value(), Value::List(vec![1.0.into(), Value::Ref(RefId(1))], Some(RefId(1))));
+    assert_eq!(Value::from(4.0).to_shared().to_value(), Value::from(4.0));
+}
+
+#[test]
+fn test_reparse() {
+    let role = |name: &str, dist: usize| {
+        let script = format!(include_str!("script-template.xml"),
+            globals = "", fields = "",
+            funcs = "", methods = "",
+            scripts = format!(r#"<script><block s="receiveGo"/><block s="forward"><l>{dist}</l></block></script>"#),
+        );
+        let start = script.find("<role").unwrap();
+        let stop = script.rfind("</role>").unwrap() + "</role>".len();
+        script[start..stop].replace("myRole", name)
+    };
+    let room = |roles: &[alloc::string::String]| format!(r#"<room name="untitled" app="NetsBlox 1.29.1, http://netsblox.org">{}</room>"#, roles.concat());
+
+    let parser = Parser::default();
+    let v0 = parser.parse(&room(&[role("a", 1), role("b", 2)])).unwrap();
+    assert!(v0.roles.iter().all(|x| x.source_hash.is_none()));
+
+    let empty = Project { name: "untitled".into(), meta: Default::default(), roles: vec![] };
+    let (v1, _) = parser.reparse(&empty, &room(&[role("a", 1), role("b", 2)])).unwrap();
+    assert!(v1.roles.iter().all(|x| x.source_hash.is_some()));
+    assert_eq!(v1.roles.iter().map(|x| Role { source_hash: None, ..x.clone() }).collect::<Vec<_>>(), v0.roles);
+
+    let (v2, _) = parser.reparse(&v1, &room(&[role("a", 1), role("b", 3), role("c", 4)])).unwrap();
+    assert_eq!(v2.roles.len(), 3);
+    assert_eq!(v2.roles[0], v1.roles[0]);
+    assert_ne!(v2.roles[1], v1.roles[1]);
+    assert_ne!(v2.roles[1].source_hash, v1.roles[1].source_hash);
+    assert_eq!(v2, parser.reparse(&empty, &room(&[role("a", 1), role("b", 3), role("c", 4)])).unwrap().0);
+}
+
+#[test]
+fn test_metrics() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><l>hi</l></block><block s="forward"><block s="reportVariadicSum"><list><l>1</l><l>2</l></list></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let metrics = ast.metrics();
+    assert_eq!(metrics.roles.len(), 1);
+    assert_eq!(metrics.roles[0].0, "myRole");
+    let role = metrics.roles[0].1;
+    assert_eq!((role.entities, role.funcs, role.scripts, role.hats, role.stmts, role.exprs), (1, 0, 1, 1, 2, 3));
+    assert!(role.heap_bytes >= role.stmts * core::mem::size_of::<Stmt>() + role.exprs * core::mem::size_of::<Expr>());
+    assert_eq!((metrics.total.stmts, metrics.total.exprs), (role.stmts, role.exprs));
+    assert!(metrics.total.heap_bytes >= role.heap_bytes);
+
+    let long = "a very long string which will not fit inline in a compact string";
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/><block s="bubble"><l>{long}</l></block><block s="forward"><block s="reportVariadicSum"><list><l>1</l><l>2</l></list></block></block></script>"#),
+    );
+    let bigger = Parser::default().parse(&script).unwrap().metrics();
+    assert_eq!(bigger.total.heap_bytes, metrics.total.heap_bytes + long.len());
+}
+
+#[test]
+fn test_complexity() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = r#"<block-definition s="f" type="command" category="custom"><header></header><code></code><translations></translations><inputs></inputs><script><block s="doIf"><block s="reportAnd"><block var="g"/><block var="g"/></block><script><block s="forward"><l>1</l></block></script></block></script></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doRepeat"><l>10</l><script><block s="doIf"><block var="g"/><script><custom-block s="f"/></script></block></script></block><block s="doSetVar"><l>g</l><block s="reifyScript"><script><block s="forward"><l>1</l></block></script><list></list></block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+
+    let script = role.entities[0].scripts[0].complexity();
+    assert_eq!(script, Complexity { cyclomatic: 3, max_depth: 2, blocks: 10, custom_block_calls: 1, closures: 1 });
+    let func = role.funcs[0].complexity();
+    assert_eq!(func, Complexity { cyclomatic: 3, max_depth: 1, blocks: 6, custom_block_calls: 0, closures: 0 });
+    assert_eq!(role.entities[0].complexity(), script);
+    assert_eq!(role.complexity(), Complexity { cyclomatic: 6, max_depth: 2, blocks: 16, custom_block_calls: 1, closures: 1 });
+}
+
+#[test]
+fn test_diagnostics() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block collabId="item_3" s="bubble"><block var="nope"/></block></script>"#,
+    );
+    let diagnostic = Diagnostic::from(Parser::default().parse(&script).unwrap_err());
+    assert_eq!(diagnostic.code, "C0005");
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.message, "undefined variable 'nope'");
+    assert_eq!(diagnostic.span.role.as_deref(), Some("myRole"));
+    assert_eq!(diagnostic.span.entity.as_deref(), Some("Stage"));
+    assert_eq!(diagnostic.span.collab_id.as_deref(), Some("item_3"));
+    assert!(diagnostic.related.is_empty());
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="reportSum"><l>1</l><l>2</l></block></script>"#,
+    );
+    let (_, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    let diagnostics: Vec<Diagnostic> = warnings.into_iter().map(Into::into).collect();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].code, diagnostics[0].severity), ("W0002", Severity::Warning));
+
+    let (code, message) = WarningKind::BlockParsedAsUnknown { error: ProjectError::BlockOptionUnknown { got: "foo".into() }.into() }.describe();
+    assert_eq!(code, "W0008");
+    assert_eq!(message, "block was parsed as an unknown block: unknown dropdown option 'foo'");
+}
+
+#[test]
+fn test_pass_through_unknown_options() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="setEffect"><l><option>sparkle</option></l><l>5</l></block><block s="doSayFor"><block s="reportTextFunction"><l><option>title case</option></l><l>hello</l></block><l>1</l></block></script>"#,
+    );
+    match Parser::default().parse(&script) {
+        Err(e) => assert_eq!(e.kind, CompileError::UnknownEffect { effect: "sparkle".into() }.into()),
+        x => panic!("{x:?}"),
+    }
+
+    let parser = Parser { pass_through_unknown_options: true, ..Default::default() };
+    let (ast, warnings) = parser.parse_with_diagnostics(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::SetEffect { kind, .. } => assert_eq!(*kind, EffectKind::Other("sparkle".into())),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[1].kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::TextFunction { function, .. } => assert_eq!(*function, TextFunction::Other("title case".into())),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].kind, WarningKind::UnknownOptionPassedThrough { option: "sparkle".into() });
+    assert_eq!(warnings[1].kind, WarningKind::UnknownOptionPassedThrough { option: "title case".into() });
+    assert_eq!(warnings[0].location.entity.as_deref(), Some("Stage"));
+}
+
+#[test]
+fn test_empty_slots() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><l></l></block><block s="bubble"><l>x</l></block></script>"#,
+    );
+    let contents = |parser: Parser| {
+        let ast = parser.parse(&script).unwrap();
+        ast.roles[0].entities[0].scripts[0].stmts.iter().map(|x| match &x.kind {
+            StmtKind::Say { content, .. } => content.kind.clone(),
+            x => panic!("{x:?}"),
+        }).collect::<Vec<_>>()
+    };
+    assert_eq!(contents(Parser::default()), [ExprKind::Value("".into()), ExprKind::Value("x".into())]);
+    assert_eq!(contents(Parser { empty_slots: EmptySlots::Marker, ..Default::default() }), [ExprKind::EmptySlot, ExprKind::Value("x".into())]);
+    match (Parser { empty_slots: EmptySlots::Error, ..Default::default() }).parse(&script) {
+        Err(e) => {
+            assert_eq!(e.kind, CompileError::EmptySlot.into());
+            assert_eq!(e.location.block_type.as_deref(), Some("bubble"));
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_variadic_params() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>1</l></variable>"#, fields = "",
+        funcs = r#"<block-definition s="sum %&apos;x&apos; of %&apos;vals&apos;" type="reporter" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n"></input><input type="%mult%n"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><custom-block s="sum %n of %mult%n"><l>1</l><list><l>2</l><block var="g"/></list></custom-block></block><block s="bubble"><custom-block s="sum %n of %mult%n"><l>1</l><list></list></custom-block></block><block s="bubble"><custom-block s="sum %n of %mult%n"><l>1</l><block var="g"/></custom-block></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let func = &ast.roles[0].funcs[0];
+    assert_eq!(func.params.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["x", "vals"]);
+    assert_eq!(func.param_info.iter().map(|x| x.kind).collect::<Vec<_>>(), [ParamKind::Single, ParamKind::Variadic]);
+
+    let args = ast.roles[0].entities[0].scripts[0].stmts.iter().map(|x| match &x.kind {
+        StmtKind::Say { content, .. } => match &content.kind {
+            ExprKind::CallFn { args, .. } => args.clone(),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }).collect::<Vec<_>>();
+    assert_eq!(args[0][0].kind, ExprKind::Value("1".into()));
+    match &args[0][1].kind {
+        ExprKind::MakeList { values } => {
+            assert_eq!(values.len(), 2);
+            assert_eq!(values[0].kind, ExprKind::Value("2".into()));
+            assert!(matches!(values[1].kind, ExprKind::Variable { .. }));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(args[1][1].kind, ExprKind::MakeList { values: vec![] });
+    assert!(matches!(args[2][1].kind, ExprKind::Variable { .. }));
+}
+
+#[test]
+fn test_param_info() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="f %&apos;a&apos; %&apos;b&apos; %&apos;c&apos; %&apos;d&apos; %&apos;e&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n">10</input><input type="%s" readonly="true">red<options>red&#xD;green</options></input><input type="%boolUE"></input><input type="%mult%l"></input><input type="%weird"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = "",
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    assert_eq!(ast.roles[0].funcs[0].param_info, [
+        ParamInfo { kind: ParamKind::Single, input_type: InputType::Number, default: "10".into() },
+        ParamInfo { kind: ParamKind::Single, input_type: InputType::Any, default: "red".into() },
+        ParamInfo { kind: ParamKind::Single, input_type: InputType::UnevaluatedBool, default: "".into() },
+        ParamInfo { kind: ParamKind::Variadic, input_type: InputType::List, default: "".into() },
+        ParamInfo { kind: ParamKind::Single, input_type: InputType::Other("%weird".into()), default: "".into() },
+    ]);
+}
+
+#[test]
+fn test_fn_metadata() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="jump" type="command" category="motion"><comment x="0" y="0" w="90" collapsed="false">moves up and down</comment><header></header><code></code><translations></translations><inputs></inputs></block-definition>"#,
+        methods = r#"<block-definition s="twirl" type="command" category="my category"><header></header><code></code><translations></translations><inputs></inputs></block-definition>"#,
+        scripts = "",
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let global = &ast.roles[0].funcs[0];
+    assert_eq!(global.category, "motion");
+    assert_eq!(global.location, FnLocation::Global);
+    assert_eq!(global.help.as_deref(), Some("moves up and down"));
+    let method = &ast.roles[0].entities[0].funcs[0];
+    assert_eq!(method.category, "my category");
+    assert_eq!(method.location, FnLocation::Method);
+    assert_eq!(method.help, None);
+}
+
+#[test]
+fn test_parse_all() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = "",
+    );
+    let projects = Parser::default().parse_all(&format!("{script}\n{}\n{script}", include_str!("projects/role-export.xml"))).unwrap();
+    assert_eq!(projects.len(), 3);
+    assert_eq!(projects[0].roles[0].name, "myRole");
+    assert_eq!(projects[1], Parser::default().parse(include_str!("projects/role-export.xml")).unwrap());
+    assert_eq!(projects[2], projects[0]);
+
+    match Parser::default().parse_all("<notes>nothing here</notes>") {
+        Err(e) => assert_eq!(e.kind, ProjectError::NoRoot.into()),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_parse_bytes() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="bubble"><l>héllo 😀</l></block></script>"#,
+    );
+    let expected = Parser::default().parse(&script).unwrap();
+
+    let utf8_bom = format!("\u{feff}{script}");
+    let utf16le: Vec<u8> = utf8_bom.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let utf16be: Vec<u8> = script.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    assert_eq!(Parser::default().parse(&utf8_bom).unwrap(), expected);
+    assert_eq!(Parser::default().parse_bytes(utf8_bom.as_bytes()).unwrap(), expected);
+    assert_eq!(Parser::default().parse_bytes(&utf16le).unwrap(), expected);
+    assert_eq!(Parser::default().parse_bytes(&utf16be).unwrap(), expected);
+
+    match Parser::default().parse_bytes(b"<room>\xff</room>") {
+        Err(e) => assert_eq!(e.kind, XmlError::InvalidEncoding { encoding: Encoding::Utf8 }.into()),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_parse_file() {
+    let path = super::std::env::temp_dir().join("netsblox-ast-test-parse-file.xml");
+    let xml: Vec<u8> = include_str!("projects/role-export.xml").encode_utf16().flat_map(u16::to_le_bytes).collect();
+    super::std::fs::write(&path, xml).unwrap();
+    assert_eq!(Parser::default().parse_file(&path).unwrap(), Parser::default().parse(include_str!("projects/role-export.xml")).unwrap());
+    super::std::fs::remove_file(&path).unwrap();
+    assert!(matches!(Parser::default().parse_file(&path), Err(FileError::Io(_))));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_parse_dir() {
+    // a unique directory, so that concurrent test runs do not interfere with each other
+    static COUNTER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+    let id = COUNTER.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    let dir = super::std::env::temp_dir().join(format!("netsblox-ast-test-parse-dir-{}-{id}", super::std::process::id()));
+    super::std::fs::create_dir_all(dir.join("nested")).unwrap();
+    super::std::fs::write(dir.join("b.xml"), include_str!("projects/role-export.xml")).unwrap();
+    super::std::fs::write(dir.join("nested").join("a.XML"), include_str!("projects/role-export.xml")).unwrap();
+    super::std::fs::write(dir.join("c.xml"), "<room>").unwrap();
+    super::std::fs::write(dir.join("notes.txt"), "not a project").unwrap();
+
+    let parser = Parser::default();
+    let expected = parser.parse(include_str!("projects/role-export.xml")).unwrap();
+    let paths = [dir.join("b.xml"), dir.join("c.xml"), dir.join("nested").join("a.XML")];
+
+    let results = parser.parse_dir(&dir).unwrap();
+    assert_eq!(results.files.iter().map(|x| x.0.clone()).collect::<alloc::vec::Vec<_>>(), paths);
+    assert_eq!(results.files[0].1.as_ref().unwrap(), &expected);
+    assert_eq!(results.files[2].1.as_ref().unwrap(), &expected);
+    assert_eq!(results.summary(), DirSummary { files: 3, parsed: 2, io_errors: 0, parse_errors: 1 });
+    assert_eq!(results.failures().map(|x| x.0).collect::<alloc::vec::Vec<_>>(), [paths[1].as_path()]);
+
+    let options = ParallelOptions { threads: 4, stack_size: 4 * 1024 * 1024 };
+    let results = parser.parse_dir_parallel(&dir, &options, |_, x| x.map(|x| x.roles.len()).map_err(|e| matches!(e, FileError::Parse(_)))).unwrap();
+    assert_eq!(results, [(paths[0].clone(), Ok(1)), (paths[1].clone(), Err(true)), (paths[2].clone(), Ok(1))]);
+    let results = parser.parse_dir_parallel(&dir, &ParallelOptions::default(), |_, x| x.is_ok()).unwrap();
+    assert_eq!(results.iter().map(|x| x.1).collect::<alloc::vec::Vec<_>>(), [true, false, true]);
+
+    let err = parser.parse_dir_parallel(&dir, &options, |path, _| assert!(path != paths[1])).unwrap_err();
+    assert_eq!(err.kind(), super::std::io::ErrorKind::Other);
+
+    super::std::fs::remove_dir_all(&dir).unwrap();
+    assert!(parser.parse_dir(&dir).is_err());
+}
+
+#[test]
+fn test_fn_overrides() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = r#"<block-definition s="jump %&apos;h&apos;" type="command" category="motion"><header></header><code></code><translations></translations><inputs><input type="%n"></input></inputs></block-definition><block-definition s="spin" type="command" category="motion"><header></header><code></code><translations></translations><inputs></inputs></block-definition>"#,
+        methods = r#"<block-definition s="jump %&apos;height&apos;" type="command" category="motion"><header></header><code></code><translations></translations><inputs><input type="%n"></input></inputs></block-definition>"#,
+        scripts = r#"<script><block s="receiveGo"/><custom-block s="jump %n"><l>10</l></custom-block><custom-block s="spin"/></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    let entity = &role.entities[0];
+    let calls = entity.scripts[0].stmts.iter().map(|x| match &x.kind {
+        StmtKind::CallFn { function, .. } => function.clone(),
+        x => panic!("{x:?}"),
+    }).collect::<Vec<_>>();
+
+    assert_eq!(calls[0].location, FnLocation::Method);
+    assert!(core::ptr::eq(role.resolve_fn(entity, &calls[0]).unwrap(), &entity.funcs[0]));
+    let overrides = role.fn_overrides(entity, &calls[0]);
+    assert_eq!(overrides.len(), 2);
+    assert!(core::ptr::eq(overrides[0], &entity.funcs[0]));
+    assert!(core::ptr::eq(overrides[1], &role.funcs[0]));
+
+    assert_eq!(calls[1].location, FnLocation::Global);
+    assert!(core::ptr::eq(role.resolve_fn(entity, &calls[1]).unwrap(), &role.funcs[1]));
+    assert_eq!(role.fn_overrides(entity, &calls[1]).len(), 1);
+}
+
+#[test]
+fn test_shadowing_warnings() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>1</l></variable>"#,
+        fields = r#"<variable name="f"><l>2</l></variable>"#,
+        funcs = r#"<block-definition s="count as %&apos;i&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%upvar"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block collabId="item_1" s="doDeclareVariables"><list><l>x</l><l>f</l></list></block><custom-block collabId="item_2" s="count as %upvar"><l>g</l></custom-block></script>"#,
+    );
+    let (_, warnings) = Parser::default().parse_with_diagnostics(&script).unwrap();
+    assert_eq!(warnings, [
+        Warning {
+            kind: WarningKind::LocalShadowsVariable { name: "f".into(), shadowed: VarLocation::Field, shadowed_location: Location { role: Some("myRole".into()), entity: Some("Stage".into()), collab_id: None, block_type: None } },
+            location: Location { role: Some("myRole".into()), entity: Some("Stage".into()), collab_id: Some("item_1".into()), block_type: Some("doDeclareVariables".into()) },
+        },
+        Warning {
+            kind: WarningKind::LocalShadowsVariable { name: "g".into(), shadowed: VarLocation::Global, shadowed_location: Location { role: Some("myRole".into()), entity: None, collab_id: None, block_type: None } },
+            location: Location { role: Some("myRole".into()), entity: Some("Stage".into()), collab_id: Some("item_2".into()), block_type: Some("count as %upvar".into()) },
+        },
+    ]);
+
+    let diagnostic = Diagnostic::from(warnings.into_iter().nth(1).unwrap());
+    assert_eq!(diagnostic.code, "W0010");
+    assert_eq!(diagnostic.related, [Location { role: Some("myRole".into()), entity: None, collab_id: None, block_type: None }]);
+}
+
+#[test]
+fn test_script_locals() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>a</l><l>b</l></list></block><block s="doFor"><l>i</l><l>1</l><l>10</l><script><block s="doDeclareVariables"><list><l>a</l><l>c</l></list></block></script></block><block s="doSetVar"><l>a</l><block s="reifyReporter"><autolambda><block var="x"/></autolambda><list><l>x</l></list></block></block></script><script><block s="receiveGo"/></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let scripts = &ast.roles[0].entities[0].scripts;
+    assert_eq!(scripts[0].locals.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["a", "b", "i", "c"]);
+    assert_eq!(scripts[1].locals, []);
+}
+
+#[test]
+fn test_name_tables() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="my g"><l>0</l></variable>"#,
+        fields = r#"<variable name="f 2"><l>0</l></variable>"#,
+        funcs = r#"<block-definition s="do thing %&apos;p&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%s"></input></inputs></block-definition>"#,
+        methods = "",
+        scripts = "",
+    );
+    let ast = Parser::default().with_name_transformer(crate::util::c_ident).parse(&script).unwrap();
+    let role = &ast.roles[0];
+
+    let tables = role.name_tables();
+    assert_eq!(tables.globals.iter().collect::<Vec<_>>(), [("my g", "my_g")]);
+    assert_eq!(tables.globals.trans_name("my g"), Some("my_g"));
+    assert_eq!(tables.globals.orig_name("my_g"), Some("my g"));
+    assert_eq!(tables.globals.orig_name("my g"), None);
+    assert_eq!(tables.funcs.len(), 1);
+    assert_eq!(tables.funcs.orig_name(&role.funcs[0].trans_name), Some(role.funcs[0].name.as_str()));
+    assert_eq!(tables.entities.trans_name("Stage"), Some("Stage"));
+
+    let tables = role.entities[0].name_tables();
+    assert_eq!(tables.fields.orig_name("f_2"), Some("f 2"));
+    assert!(tables.costumes.is_empty() && tables.sounds.is_empty() && tables.funcs.is_empty());
+
+    let mut table = NameTable::default();
+    table.insert("a".into(), "x".into());
+    table.insert("b".into(), "x".into());
+    assert_eq!(table.iter().collect::<Vec<_>>(), [("b", "x")]);
+    assert_eq!(table.orig_name("x"), Some("b"));
+}
+
+#[test]
+fn test_source_map() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="foo"><l>0</l></variable><variable name="bar"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>foo</l><block s="reportSum"><l>1</l><block var="bar"/></block></block><block s="bubble"><l>hi</l></block></script>"#,
+    );
+    let (ast, source_map) = Parser::default().parse_with_source_map(&script).unwrap();
+    let script_ast = &ast.roles[0].entities[0].scripts[0];
+
+    let hat = source_map.get(script_ast.hat.as_ref().unwrap().info.id).unwrap();
+    assert_eq!(&script[hat], r#"<block s="receiveGo"/>"#);
+    let set = source_map.get(script_ast.stmts[0].info.id).unwrap();
+    assert_eq!(&script[set], r#"<block s="doSetVar"><l>foo</l><block s="reportSum"><l>1</l><block var="bar"/></block></block>"#);
+    match &script_ast.stmts[0].kind {
+        StmtKind::Assign { value, .. } => {
+            let sum = source_map.get(value.info.id).unwrap();
+            assert_eq!(&script[sum], r#"<block s="reportSum"><l>1</l><block var="bar"/></block>"#);
+        }
+        x => panic!("{x:?}"),
+    }
+    let bubble = source_map.get(script_ast.stmts[1].info.id).unwrap();
+    assert_eq!(&script[bubble.clone()], r#"<block s="bubble"><l>hi</l></block>"#);
+    assert!(source_map.iter().all(|(id, _)| id != NodeId::default()));
+
+    let with_bom = format!("\u{feff}{script}");
+    let (_, source_map) = Parser::default().parse_with_source_map(&with_bom).unwrap();
+    assert_eq!(&with_bom[source_map.get(script_ast.stmts[1].info.id).unwrap()], &script[bubble.clone()]);
+
+    // ids are assigned again after a hook adds nodes, and the source map follows them
+    let parser = Parser::default().with_post_hook(|role| {
+        role.entities[0].scripts[0].stmts.insert(0, Stmt { kind: StmtKind::ResetTimer, info: BlockInfo::none() });
+        Ok(())
+    });
+    let (ast, source_map) = parser.parse_with_source_map(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(source_map.get(stmts[0].info.id), None);
+    assert_eq!(&script[source_map.get(stmts[2].info.id).unwrap()], &script[bubble]);
+}
+
+#[test]
+fn test_share_list_strings() {
+    let long = "a string which is too long to be stored inline";
+    let script = format!(include_str!("script-template.xml"),
+        globals = format!(r#"<variable name="x"><list struct="atomic">{long},short,{long},short</list></variable><variable name="y"><list><item><l>{long}</l></item><item><l>{long}</l></item></list></variable>"#), fields = "",
+        funcs = "", methods = "", scripts = "",
+    );
+
+    let ast = Parser::default().parse(&script).unwrap();
+    match &ast.roles[0].globals[0].init {
+        Value::List(values, _) => assert_eq!(*values, [Value::from(long), "short".into(), long.into(), "short".into()]),
+        x => panic!("{x:?}"),
+    }
+
+    let ast = Parser { share_list_strings: true, ..Default::default() }.parse(&script).unwrap();
+    for global in ast.roles[0].globals.iter() {
+        let values = match &global.init {
+            Value::List(values, _) => values,
+            x => panic!("{x:?}"),
+        };
+        let shared = values.iter().filter_map(|x| match x {
+            Value::SharedString(x) => Some(x),
+            Value::String(x) => {
+                assert_eq!(x, "short");
+                None
+            }
+            x => panic!("{x:?}"),
+        }).collect::<Vec<_>>();
+        assert_eq!(shared.len(), 2);
+        assert_eq!(&**shared[0], long);
+        assert!(alloc::rc::Rc::ptr_eq(shared[0], shared[1]));
+    }
+    match &ast.roles[0].globals[0].init {
+        Value::List(values, _) => {
+            assert_eq!(values[0].as_str(), Some(long));
+            assert_eq!(values[1].as_str(), Some("short"));
+            assert_eq!(values[0].to_snap_string(), long);
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_defer_atomic_lists() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="x"><list struct="atomic" id="5">1,"a,b",,c</list></variable><variable name="y"><list struct="atomic">1,2</list></variable>"#, fields = "",
+        funcs = "", methods = "", scripts = "",
+    );
+    let parser = Parser { defer_atomic_lists: Some(5), ..Default::default() };
+    let ast = parser.parse(&script).unwrap();
+    let globals = &ast.roles[0].globals;
+
+    let list = match &globals[0].init {
+        Value::RawList(list, ref_id) => {
+            assert_eq!(*ref_id, Some(RefId(5)));
+            list
+        }
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(list.text(), r#"1,"a,b",,c"#);
+    assert_eq!(list.items().collect::<Vec<_>>(), ["1", "a,b", "", "c"]);
+    match &Parser::default().parse(&script).unwrap().roles[0].globals[0].init {
+        Value::List(values, _) => assert_eq!(list.to_values(), *values),
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(globals[0].init.to_snap_string(), "1,a,b,,c");
+    assert_eq!(globals[1].init, Value::List(vec!["1".into(), "2".into()], None));
+}
+
+#[test]
+fn test_linewise_lists() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "<variable name=\"x\"><list struct=\"linewise\" id=\"3\">1,2,3\na,\"b,c\"\n\nlast</list></variable>", fields = "",
+        funcs = "", methods = "", scripts = "",
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    assert_eq!(ast.roles[0].globals[0].init, Value::List(vec![
+        Value::List(vec!["1".into(), "2".into(), "3".into()], None),
+        Value::List(vec!["a".into(), "b,c".into()], None),
+        Value::List(vec![], None),
+        Value::List(vec!["last".into()], None),
+    ], Some(RefId(3))));
+
+    let parser = Parser { list_limits: Some(ListLimits { max_items: 2, max_bytes: 100, truncate: true }), ..Default::default() };
+    let (ast, warnings) = parser.parse_with_diagnostics(&script).unwrap();
+    match &ast.roles[0].globals[0].init {
+        Value::List(rows, _) => assert_eq!(rows.len(), 2),
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(warnings[0].kind, WarningKind::ListTruncated { kept: 2 });
+}
+
+#[test]
+fn test_list_limits() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="x"><list struct="atomic">1,2,3,4,5</list></variable><variable name="y"><list><item><l>hello</l></item><item><l>world</l></item></list></variable><variable name="z"><list struct="atomic">1,2</list></variable>"#, fields = "",
+        funcs = "", methods = "", scripts = "",
+    );
+
+    let limits = ListLimits { max_items: 3, max_bytes: 8, truncate: true };
+    let parser = Parser { list_limits: Some(limits), defer_atomic_lists: Some(0), ..Default::default() };
+    let (ast, warnings) = parser.parse_with_diagnostics(&script).unwrap();
+    let globals = &ast.roles[0].globals;
+    assert_eq!(globals[0].init, Value::List(vec!["1".into(), "2".into(), "3".into()], None));
+    assert_eq!(globals[1].init, Value::List(vec!["hello".into()], None));
+    assert!(matches!(globals[2].init, Value::RawList(..)));
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].kind, WarningKind::ListTruncated { kept: 3 });
+    assert_eq!(warnings[1].kind, WarningKind::ListTruncated { kept: 1 });
+
+    let parser = Parser { list_limits: Some(ListLimits { truncate: false, ..limits }), ..Default::default() };
+    match parser.parse(&script) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, ErrorKind::CompileError(CompileError::ListTooLarge { max_items: 3, max_bytes: 8 })),
+    }
+
+    let parser = Parser { list_limits: Some(ListLimits { max_items: 5, max_bytes: 10, truncate: false }), ..Default::default() };
+    assert_eq!(parser.parse(&script).unwrap(), Parser::default().parse(&script).unwrap());
+}
+
+#[test]
+fn test_color_literals() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="setColor"><color>240,35,35,1</color></block><block s="doIf"><block s="reportTouchingColor"><color>10,20,30</color></block><script></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts[0].kind, StmtKind::SetPenColor { color: (240, 35, 35, 255) });
+    match &stmts[1].kind {
+        StmtKind::If { condition, .. } => match &condition.kind {
+            ExprKind::UnknownBlock { name, args } => {
+                assert_eq!(name, "reportTouchingColor");
+                assert_eq!(args[0].kind, ExprKind::Value(Value::Color { r: 10, g: 20, b: 30, a: 255 }));
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSayFor"><color>red</color><l>1</l></block></script>"#,
+    );
+    match Parser::default().parse(&script) {
+        Err(e) => assert_eq!(e.kind, ProjectError::ColorUnknownValue { color: "red".into() }.into()),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_try_catch() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doTryCatch"><script><block s="doThrow"><l>oops</l></block></script><l>err</l><script><block s="bubble"><block var="err"/></block></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let script = &ast.roles[0].entities[0].scripts[0];
+    match &script.stmts[0].kind {
+        StmtKind::TryCatch { code, var, handler } => {
+            assert_eq!(code[0].kind, StmtKind::Throw { error: Box::new("oops".into()) });
+            assert_eq!((var.name.as_str(), &var.location), ("err", &VarLocation::Local));
+            match &handler[0].kind {
+                StmtKind::Say { content, duration: None } => assert!(matches!(&content.kind, ExprKind::Variable { var: x } if x.trans_name == var.trans_name)),
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    assert!(script.locals.iter().any(|x| x.name == "err"));
+    assert_eq!(ast.validate(&Parser::default()), []);
+
+    for bad in [r#"<block var="x"/>"#, ""] {
+        let script = format!(include_str!("script-template.xml"),
+            globals = r#"<variable name="x"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+            scripts = format!(r#"<script><block s="receiveGo"/><block s="doTryCatch"><script></script>{bad}<script></script></block></script>"#),
+        );
+        assert!(Parser::default().parse(&script).is_err());
+    }
+}
+
+#[test]
+fn test_block_handlers() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="total"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="logTotal"><l>hello</l></block><block s="bubble"><block s="reportTotal"/></block><block s="doSayFor"><l>hi</l><l>2</l></block><block s="logTotal"><block s="reportUnknown"/></block></script>"#,
+    );
+    let parser = Parser::default()
+        .register_block("logTotal", BlockHandler {
+            stmt: Some(Box::new(|args, info, context| {
+                let var = context.variable("total")?;
+                let value = Box::new(Expr { kind: ExprKind::Variable { var }, info: BlockInfo::none() });
+                let content = Box::new(Expr { kind: ExprKind::StrCat { values: Box::new(Expr { kind: ExprKind::MakeList { values: vec![args[0].clone(), *value] }, info: BlockInfo::none() }) }, info: BlockInfo::none() });
+                Ok(vec![Stmt { kind: StmtKind::Say { content, duration: None }, info }])
+            })),
+            expr: None,
+            hat: None,
+        })
+        .register_block("reportTotal", BlockHandler {
+            stmt: None,
+            expr: Some(Box::new(|_, info, context| {
+                let var = context.variable("total")?;
+                Ok(Box::new(Expr { kind: ExprKind::Variable { var }, info }))
+            })),
+            hat: None,
+        })
+        .register_block("doSayFor", BlockHandler {
+            stmt: Some(Box::new(|mut args, info, _| Ok(vec![Stmt { kind: StmtKind::Say { content: Box::new(args.swap_remove(0)), duration: None }, info }]))),
+            expr: None,
+            hat: None,
+        });
+    let ast = parser.parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts.len(), 4);
+    match &stmts[0].kind {
+        StmtKind::Say { content, duration: None } => match &content.kind {
+            ExprKind::StrCat { values } => assert!(matches!(&values.kind, ExprKind::MakeList { values } if values.len() == 2)),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(stmts[0].info.opcode.as_deref(), Some("logTotal"));
+    match &stmts[1].kind {
+        StmtKind::Say { content, .. } => {
+            assert!(matches!(&content.kind, ExprKind::Variable { var } if var.name == "total" && var.location == VarLocation::Global));
+            assert_eq!(content.info.opcode.as_deref(), Some("reportTotal"));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(stmts[2].kind, StmtKind::Say { content: Box::new("hi".into()), duration: None });
+    assert_eq!(ast.query().blocks("doSayFor").len(), 1);
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="logTotal"><l>hello</l></block></script>"#,
+    );
+    match parser.parse(&script) {
+        Err(e) => assert_eq!(e.kind, CompileError::UndefinedVariable { name: "total".into() }.into()),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_hat_handlers() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveBadge"><l>gold</l><l>who</l></block><block s="bubble"><block var="who"/></block></script><script><block s="receiveTick"/><block s="forward"><l>1</l></block></script>"#,
+    );
+    let parser = Parser::default()
+        .register_block("receiveBadge", BlockHandler {
+            hat: Some(Box::new(|args, info, context| {
+                let level = match &args[0].kind {
+                    ExprKind::Value(x) => x.clone(),
+                    _ => return Err(Box::new(Error { kind: CompileError::BlockOptionNotConst.into(), location: context.location.to_owned() })),
+                };
+                let who = match &args[1].kind {
+                    ExprKind::Value(Value::String(x)) => context.declare(x)?,
+                    _ => return Err(Box::new(Error { kind: ProjectError::UpvarNotConst.into(), location: context.location.to_owned() })),
+                };
+                Ok(Box::new(Hat { kind: HatKind::Custom { name: "badge".into(), args: vec![level], fields: vec![who] }, info }))
+            })),
+            ..Default::default()
+        })
+        .register_block("receiveTick", BlockHandler {
+            hat: Some(Box::new(|_, info, _| Ok(Box::new(Hat { kind: HatKind::LocalMessage { msg_type: Some("tick".into()) }, info })))),
+            ..Default::default()
+        });
+    let ast = parser.parse(&script).unwrap();
+    let scripts = &ast.roles[0].entities[0].scripts;
+    let hat = scripts[0].hat.as_deref().unwrap();
+    match &hat.kind {
+        HatKind::Custom { name, args, fields } => {
+            assert_eq!((name.as_str(), args.as_slice()), ("badge", [Value::from("gold")].as_slice()));
+            assert_eq!(fields[0].name, "who");
+            assert!(matches!(&scripts[0].stmts[0].kind, StmtKind::Say { content, .. } if matches!(&content.kind, ExprKind::Variable { var } if var.trans_name == fields[0].trans_name)));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(hat.opcode(), "receiveBadge");
+    assert_eq!(scripts[1].hat.as_deref().unwrap().kind, HatKind::LocalMessage { msg_type: Some("tick".into()) });
+    assert_eq!(ast.validate(&parser), []);
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveBadge"><block s="reportRandom"><l>1</l><l>2</l></block><l>who</l></block></script>"#,
+    );
+    match parser.parse(&script) {
+        Err(e) => assert_eq!((e.kind, e.location.block_type.as_deref()), (CompileError::BlockOptionNotConst.into(), Some("receiveBadge"))),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_post_hooks() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="forward"><l>1</l></block></script>"#,
+    );
+    let parser = Parser::default()
+        .with_post_hook(|role| {
+            // hooks see the final ids of the parsed nodes rather than temporary ones
+            let script = role.entities.iter().flat_map(|x| &x.scripts).next().unwrap();
+            assert_eq!(script.hat.as_ref().unwrap().info.id, NodeId(1));
+            role.notes = format_compact!("checked {}", role.name);
+            Ok(())
+        })
+        .with_post_hook(|role| {
+            for script in role.entities.iter_mut().flat_map(|x| x.scripts.iter_mut()) {
+                script.stmts.insert(0, Stmt { kind: StmtKind::ResetTimer, info: BlockInfo::none() });
+            }
+            Ok(())
+        })
+        .with_post_hook(|role| match role.entities.iter().map(|x| x.scripts.len()).sum::<usize>() {
+            0..=1 => Ok(()),
+            _ => Err(Box::new(Error { kind: CompileError::CurrentlyUnsupported { msg: "multiple scripts".into() }.into(), location: Location { role: Some(role.name.clone()), entity: None, collab_id: None, block_type: None } })),
+        });
+    let ast = parser.parse(&script).unwrap();
+    assert_eq!(ast.roles[0].notes, "checked myRole");
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert!(matches!(stmts.as_slice(), [Stmt { kind: StmtKind::ResetTimer, .. }, Stmt { kind: StmtKind::Forward { .. }, .. }]));
+    assert_ne!(stmts[0].info.id, NodeId::default());
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/></script><script><block s="receiveKey"><l><option>space</option></l></block></script>"#,
+    );
+    match parser.parse(&script) {
+        Err(e) => assert_eq!((e.kind, e.location.role.as_deref()), (CompileError::CurrentlyUnsupported { msg: "multiple scripts".into() }.into(), Some("myRole"))),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_global_settings() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetFastTracking"><l><bool>true</bool></l></block><block s="doSetGlobalFlag"><l><option>flat line ends</option></l><block s="reportGlobalFlag"><l><option>turbo mode</option></l></block></block><block s="doSetTempo"><l>120</l></block><block s="doChangeTempo"><block s="getTempo"/></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+
+    match &stmts[0].kind {
+        StmtKind::SetGlobalFlag { flag: GlobalFlag::TurboMode, value } => assert_eq!(value.kind, ExprKind::Value(true.into())),
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(stmts[0].info.opcode.as_deref(), Some("doSetFastTracking"));
+    match &stmts[1].kind {
+        StmtKind::SetGlobalFlag { flag: GlobalFlag::FlatLineEnds, value } => assert_eq!(value.kind, ExprKind::GlobalFlag { flag: GlobalFlag::TurboMode }),
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(stmts[1].info.opcode, None);
+    match &stmts[2].kind {
+        StmtKind::SetTempo { value } => assert_eq!(value.kind, ExprKind::Value(Value::String("120".into()))),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[3].kind {
+        StmtKind::ChangeTempo { delta } => assert_eq!(delta.kind, ExprKind::Tempo),
+        x => panic!("{x:?}"),
+    }
+
+    match Parser::default().parse(&script.replace("flat line ends", "hyper mode")) {
+        Err(e) => assert_eq!(e.kind, CompileError::UnknownGlobalFlag { flag: "hyper mode".into() }.into()),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_network_request_reply() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="res"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>res</l><block s="doSocketRequest"><l>ping</l><l>hello</l><block s="reportSum"><l>1</l><l>2</l></block><l><option>others in room</option></l></block></block></script><script><block s="receiveSocketMessage"><l>ping</l><l>msg</l><l>n</l></block><block s="doSocketResponse"><block var="msg"/></block></script>"#,
+    ).replace("<messageTypes></messageTypes>", "<messageTypes><messageType><name>ping</name><fields><field>msg</field><field>n</field></fields></messageType></messageTypes>");
+    let ast = Parser::default().parse(&script).unwrap();
+    let scripts = &ast.roles[0].entities[0].scripts;
+
+    match &scripts[0].stmts[0].kind {
+        StmtKind::Assign { value, .. } => match &value.kind {
+            ExprKind::NetworkMessageReply { target, msg_type, values } => {
+                assert_eq!(msg_type, "ping");
+                assert_eq!(target.kind, ExprKind::Value("others in room".into()));
+                assert_eq!(values.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(), ["msg", "n"]);
+                assert_eq!(values[0].1.kind, ExprKind::Value("hello".into()));
+                assert!(matches!(values[1].1.kind, ExprKind::Add { .. }));
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+    match &scripts[1].stmts[0].kind {
+        StmtKind::SendNetworkReply { value } => match &value.kind {
+            ExprKind::Variable { var } => assert_eq!(var.name, "msg"),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let bad = script.replace("<l>hello</l>", "");
+    match Parser::default().parse(&bad) {
+        Err(e) => assert_eq!(e.kind, ErrorKind::CompileError(CompileError::MessageTypeWrongNumberArgs { msg_type: "ping".into(), got: 1, expected: 2 })),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_stmt_capacity() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><l>1</l></block><block s="doRepeat"><l>3</l><script><block s="doChangeVar"><l>g</l><l>2</l></block><block s="doSayFor"><block var="g"/><l>1</l></block><block s="doChangeVar"><l>g</l><l>-1</l></block></script></block><block s="doForever"><script><block s="forward"><l>5</l></block></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!((stmts.len(), stmts.capacity()), (3, 3));
+    match (&stmts[1].kind, &stmts[2].kind) {
+        (StmtKind::Repeat { stmts: body, .. }, StmtKind::InfLoop { stmts: forever }) => {
+            assert_eq!((body.len(), body.capacity()), (3, 3));
+            assert_eq!((forever.len(), forever.capacity()), (1, 1));
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
 #[test]
 #[allow(unreachable_code)]
 fn test_stack_size_usage() {
@@ -0,0 +1,99 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::*;
+
+#[test]
+fn test_builders() {
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    let mut role = RoleBuilder::new(&parser, "my role".into());
+    let counter = role.global("my counter".into(), 0f64.into()).unwrap();
+    assert!(matches!(role.global("my counter!".into(), 0f64.into()), Err(SymbolError::ConflictingTrans { .. })));
+
+    let mut func = role.func("bump".into(), false).unwrap();
+    let amount = func.param("amount".into()).unwrap();
+    func.body().stmt(StmtKind::AddAssign { var: counter.clone(), value: Box::new(amount.into()) });
+    let bump = func.fn_ref();
+    role.add_func(func.build()).unwrap();
+
+    let mut entity = role.entity("Stage".into()).unwrap();
+    let mut script = entity.script();
+    let temp = script.hat(HatKind::OnFlag).local("temp value".into()).unwrap();
+    script.stmt(StmtKind::Assign { var: temp.clone(), value: Box::new(5f64.into()) });
+    script.stmt(StmtKind::CallFn { function: bump, args: vec![temp.into()], upvars: vec![] });
+    entity.add_script(script.build());
+    role.add_entity(entity.build()).unwrap();
+    let role = role.build();
+
+    assert_eq!(role.globals[0].def.trans_name, "my_counter");
+    assert_eq!(role.funcs[0].params[0].trans_name, "amount");
+    let stmts = &role.entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::DeclareLocals { vars } => assert_eq!(vars[0].trans_name, "temp_value"),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[2].kind {
+        StmtKind::CallFn { function, args, .. } => {
+            assert_eq!(function.trans_name, "bump");
+            assert!(matches!(&args[0].kind, ExprKind::Variable { var } if var.trans_name == "temp_value" && var.location == VarLocation::Local));
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let graph = crate::analysis::CallGraph::new(&role);
+    assert_eq!(graph.nodes[0].preds, [1]);
+}
+
+#[test]
+fn test_validate() {
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    for xml in [include_str!("projects/capture-scopes.xml"), include_str!("projects/field-refs.xml"), include_str!("projects/project-export.xml")] {
+        let project = parser.parse(xml).unwrap();
+        assert_eq!(project.validate(&parser), []);
+    }
+
+    let mut role = RoleBuilder::new(&parser, "my role".into());
+    let counter = role.global("counter".into(), 0f64.into()).unwrap();
+    let mut func = role.func("bump".into(), false).unwrap();
+    let amount = func.param("amount".into()).unwrap();
+    func.body().stmt(StmtKind::AddAssign { var: counter.clone(), value: Box::new(amount.into()) });
+    let bump = func.fn_ref();
+    role.add_func(func.build()).unwrap();
+
+    let mut entity = role.entity("Stage".into()).unwrap();
+    let mut script = entity.script();
+    let temp = script.hat(HatKind::OnFlag).local("temp".into()).unwrap();
+    script.stmt(StmtKind::CallFn { function: bump.clone(), args: vec![temp.clone().into()], upvars: vec![] });
+    entity.add_script(script.build());
+    role.add_entity(entity.build()).unwrap();
+    let mut project = Project { name: "test".into(), meta: Default::default(), roles: vec![role.build()] };
+    assert_eq!(project.validate(&parser), []);
+    let valid = project.clone();
+
+    let role = &mut project.roles[0];
+    role.globals.push(role.globals[0].clone());
+    role.globals.push(VariableDefInit { def: VariableDef { name: "counter!".into(), trans_name: "counter".into() }, init: 0f64.into() });
+    let stmts = &mut role.entities[0].scripts[0].stmts;
+    stmts.push(StmtKind::Assign { var: VariableRef { name: "nope".into(), trans_name: "nope".into(), location: VarLocation::Local, slot: None }, value: Box::new(temp.into()) }.into());
+    stmts.push(StmtKind::CallFn { function: bump, args: vec![], upvars: vec![counter] }.into());
+
+    let errors = project.validate(&parser);
+    assert_eq!(errors.iter().map(|x| x.code).collect::<Vec<_>>(), ["P0009", "C0016", "C0005", "C0030", "C0031", "C0032"]);
+    assert!(errors.iter().all(|x| x.severity == Severity::Error && x.span.role.as_deref() == Some("my role")));
+    assert_eq!(errors[2].message, "undefined variable 'nope'");
+    assert_eq!(errors[2].span.entity.as_deref(), Some("Stage"));
+
+    // problems which are not in a block do not point at the last block that was checked
+    let mut project = valid;
+    let role = &mut project.roles[0];
+    let mut dup = role.funcs[0].clone();
+    let param = VariableDef { name: "x".into(), trans_name: "x".into() };
+    (dup.name, dup.trans_name, dup.params, dup.stmts) = ("dup".into(), "dup".into(), vec![param.clone(), param.clone()], vec![]);
+    dup.upvars = vec![VariableRef { name: "y".into(), trans_name: "y".into(), location: VarLocation::Local, slot: None }];
+    role.funcs.push(dup);
+    let mut stmt: Stmt = StmtKind::DeclareLocals { vars: vec![VariableDef { name: "z".into(), trans_name: "z".into() }] }.into();
+    stmt.info.location = Some("last block".into());
+    role.funcs[0].stmts.push(stmt);
+    let errors = project.validate(&parser);
+    assert_eq!(errors.iter().map(|x| x.message.as_str()).collect::<Vec<_>>(), ["multiple inputs are named 'x'", "'y' is not a valid upvar"]);
+    assert!(errors.iter().all(|x| x.span.collab_id.is_none()));
+}
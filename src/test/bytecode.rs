@@ -0,0 +1,115 @@
+use alloc::vec::Vec;
+use alloc::string::ToString;
+use crate::*;
+use crate::bytecode::*;
+
+fn parse_stmts(scripts: &str) -> Vec<Stmt> {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable><variable name="h"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+        scripts = scripts,
+    );
+    let mut ast = Parser::default().parse(&script).unwrap();
+    core::mem::take(&mut ast.roles[0].entities[0].scripts[0].stmts)
+}
+
+/// Runs the entry chunk of a program which only uses constant reporters, giving the final values of the variables.
+#[cfg(feature = "std")]
+fn run(program: &Program) -> Vec<(CompactString, Value)> {
+    let chunk = &program.chunks[0];
+    let mut vars: Vec<Option<Value>> = vec![None; program.vars.len()];
+    let mut temps: Vec<Option<Value>> = vec![None; chunk.temps];
+    let mut stack = vec![];
+    let mut pos = 0;
+    loop {
+        pos += 1;
+        match chunk.code[pos - 1] {
+            Op::Const(i) => stack.push(program.consts[i].clone()),
+            Op::Load(i) => stack.push(vars[i].clone().unwrap_or_else(|| 0f64.into())),
+            Op::Store(i) => vars[i] = stack.pop(),
+            Op::LoadTemp(i) => stack.push(temps[i].clone().unwrap()),
+            Op::StoreTemp(i) => temps[i] = stack.pop(),
+            Op::Prim(i) => match &program.prims[i] {
+                Prim::Expr { op, args } => {
+                    let mut args = stack.split_off(stack.len() - args).into_iter();
+                    let mut expr = Expr { kind: op.clone(), info: BlockInfo::none() };
+                    crate::visit::expr_children_mut(&mut expr.kind, &mut |x| x.kind = ExprKind::Value(args.next().unwrap()), &mut |_| panic!());
+                    stack.push(crate::eval::eval(&expr).unwrap());
+                }
+                Prim::Stmt { .. } => panic!(),
+            }
+            Op::Jump(x) => pos = x,
+            Op::JumpIfFalse(x) => if stack.pop() == Some(false.into()) { pos = x },
+            Op::End => break,
+            op => panic!("{op:?}"),
+        }
+    }
+    assert!(stack.is_empty());
+    program.vars.iter().zip(vars).map(|(var, value)| (var.name.clone(), value.unwrap())).collect()
+}
+
+#[test]
+fn test_assembler() {
+    let mut asm = Assembler::new();
+    let (start, end) = (asm.label(), asm.label());
+    asm.emit_to(end, Op::JumpIfFalse(0));
+    asm.bind(start);
+    asm.emit(Op::Const(0));
+    asm.emit_to(start, Op::Jump(0));
+    asm.emit_to(end, Op::Try { handler: 0, var: 3 });
+    asm.bind(end);
+    asm.emit(Op::End);
+    assert_eq!(asm.finish(), [Op::JumpIfFalse(4), Op::Const(0), Op::Jump(1), Op::Try { handler: 4, var: 3 }, Op::End]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_compile_loops() {
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doRepeat"><l>3</l><script><block s="doSetVar"><l>g</l><block s="reportSum"><block var="g"/><l>2</l></block></block></script></block></script>"#);
+    let program = compile(&ir::lower(&stmts));
+    assert_eq!(program.chunks.len(), 1);
+    assert!(program.closures.is_empty());
+    assert_eq!(run(&program), [(CompactString::new("g"), Value::from(6f64))]);
+
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doForEach"><l>i</l><block s="reportNewList"><list><l>1</l><l>2</l><l>3</l><l>4</l></list></block><script><block s="doSetVar"><l>h</l><block s="reportSum"><block var="h"/><block var="i"/></block></block></script></block></script>"#);
+    let res = run(&compile(&ir::lower(&stmts)));
+    assert!(res.contains(&(CompactString::new("h"), Value::from(10f64))));
+
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><l>1</l></block><block s="doUntil"><block s="reportGreaterThan"><block var="g"/><l>50</l></block><script><block s="doSetVar"><l>g</l><block s="reportProduct"><block var="g"/><l>3</l></block></block></script></block></script>"#);
+    assert_eq!(run(&compile(&ir::lower(&stmts))), [(CompactString::new("g"), Value::from(81f64))]);
+}
+
+#[test]
+fn test_compile_blocks() {
+    let err = VariableRef { name: "err".into(), trans_name: "err".into(), location: VarLocation::Local, slot: None };
+    let body = ir::Body { temps: 0, instrs: vec![ir::Instr::Loop { body: vec![ir::Instr::Warp { body: vec![ir::Instr::TryCatch {
+        body: vec![ir::Instr::Break],
+        var: err.clone(),
+        handler: vec![ir::Instr::Assign { var: err, value: ir::Operand::Value(1f64.into()) }],
+    }] }] }] };
+    let program = compile(&body);
+    // breaking out of the loop ends the try and warp blocks, innermost first
+    assert_eq!(program.chunks[0].code, [
+        Op::Warp, Op::Try { handler: 7, var: 0 },
+        Op::EndTry, Op::EndWarp, Op::Jump(11),
+        Op::EndTry, Op::Jump(9),
+        Op::Const(0), Op::Store(0),
+        Op::EndWarp, Op::Jump(0),
+        Op::End,
+    ]);
+
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><block s="reifyReporter"><autolambda><block s="reportSum"><block var="h"/><l>7</l></block></autolambda><list></list></block></block></script>"#);
+    let program = compile(&ir::lower(&stmts));
+    assert_eq!(program.chunks.len(), 2);
+    assert_eq!(program.closures[0].chunk, 1);
+    assert_eq!(program.chunks[1].code.last(), Some(&Op::End));
+    assert!(program.chunks[1].code.contains(&Op::Return));
+
+    let text = program.to_string();
+    assert!(text.starts_with("chunk 0 "));
+    assert!(text.contains("chunk 1 "));
+    assert!(text.contains("     4  end\n"));
+    assert!(text.contains("     0  closure 0       ; chunk 1\n"));
+    assert!(text.contains("; reportVariadicSum (1 args)\n"));
+    assert!(text.contains("const 0         ; 7\n"));
+    assert!(text.contains("load 0          ; h\n"));
+}
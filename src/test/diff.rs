@@ -0,0 +1,50 @@
+use crate::*;
+
+fn parse(globals: &str, scripts: &str) -> Project {
+    let script = format!(include_str!("script-template.xml"),
+        globals = globals, fields = "",
+        funcs = "", methods = "",
+        scripts = scripts,
+    );
+    Parser::default().parse(&script).unwrap()
+}
+
+#[test]
+fn test_diff_identical() {
+    let a = parse("", r#"<script><block s="receiveGo"/><block s="forward"><l>10</l></block></script>"#);
+    let b = parse("", r#"<script><block s="receiveGo"/><block s="forward"><l>10</l></block></script>"#);
+    assert!(diff(&a, &b).is_empty());
+}
+
+#[test]
+fn test_diff_stmts() {
+    let a = parse(r#"<variable name="g"><l>0</l></variable>"#, r#"<script><block s="receiveGo"/><block s="forward"><l>10</l></block><block s="doRepeat"><l>3</l><script><block s="turn"><l>90</l></block></script></block><block s="clear"/></script>"#);
+    let b = parse(r#"<variable name="h"><l>0</l></variable>"#, r#"<script><block s="receiveGo"/><block s="forward"><l>10</l></block><block s="doRepeat"><l>3</l><script><block s="turn"><l>45</l></block></script></block><block s="doSayFor"><l>hi</l><l>1</l></block></script>"#);
+    let res = diff(&a, &b);
+    assert_eq!(res.roles.len(), 1);
+    let role = match &res.roles[0] {
+        Change::Modified { diff, .. } => diff,
+        x => panic!("{x:?}"),
+    };
+    assert!(matches!(role.globals.as_slice(), [Change::Removed(x), Change::Added(y)] if x.def.name == "g" && y.def.name == "h"));
+    let entity = match role.entities.as_slice() {
+        [Change::Modified { diff, .. }] => diff,
+        x => panic!("{x:?}"),
+    };
+    let script = match entity.scripts.as_slice() {
+        [Change::Modified { diff, .. }] => diff,
+        x => panic!("{x:?}"),
+    };
+    match script.stmts.as_slice() {
+        [Change::Modified { old, diff, .. }, Change::Removed(removed), Change::Added(added)] => {
+            assert!(matches!(old.kind, StmtKind::Repeat { .. }));
+            assert!(matches!(removed.kind, StmtKind::PenClear));
+            assert!(matches!(added.kind, StmtKind::Say { .. }));
+            match diff.bodies.as_slice() {
+                [body] => assert!(matches!(body.stmts.as_slice(), [Change::Modified { new, .. }] if matches!(new.kind, StmtKind::TurnRight { .. }))),
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::*;
+use crate::eval::*;
+
+fn parse_say(block: &str) -> Vec<Expr> {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>1</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/>{block}</script>"#),
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    ast.roles[0].entities[0].scripts[0].stmts.iter().map(|x| match &x.kind {
+        StmtKind::Say { content, .. } => (**content).clone(),
+        x => panic!("{x:?}"),
+    }).collect()
+}
+fn say(expr: &str) -> alloc::string::String {
+    format!(r#"<block s="bubble">{expr}</block>"#)
+}
+
+#[test]
+fn test_eval_arithmetic() {
+    let exprs = parse_say(&[
+        say(r#"<block s="reportVariadicSum"><list><l>1</l><l>007</l><l></l></list></block>"#),
+        say(r#"<block s="reportModulus"><l>-7</l><l>3</l></block>"#),
+        say(r#"<block s="reportModulus"><l>7</l><l>-3</l></block>"#),
+        say(r#"<block s="reportQuotient"><l>1</l><l>4</l></block>"#),
+        say(r#"<block s="reportRound"><l>-2.5</l></block>"#),
+        say(r#"<block s="reportMonadic"><l><option>sin</option></l><l>90</l></block>"#),
+        say(r#"<block s="reportJoinWords"><list><l>a</l><block s="reportVariadicProduct"><list><l>1.5</l><l>2</l></list></block></list></block>"#),
+    ].concat());
+    let values: Vec<_> = exprs.iter().map(|x| eval(x).unwrap()).collect();
+    assert_eq!(values, [
+        Value::Number(8.0), Value::Number(2.0), Value::Number(-2.0), Value::Number(0.25), Value::Number(-2.0), Value::Number(1.0), Value::from("a3"),
+    ]);
+}
+
+#[test]
+fn test_eval_comparison_and_lists() {
+    let exprs = parse_say(&[
+        say(r#"<block s="reportEquals"><l>10</l><l>1e1</l></block>"#),
+        say(r#"<block s="reportEquals"><l>Hello</l><l>hELLO</l></block>"#),
+        say(r#"<block s="reportLessThan"><l>9</l><l>10</l></block>"#),
+        say(r#"<block s="reportLessThan"><l>b</l><l>A</l></block>"#),
+        say(r#"<block s="reportListItem"><l>2</l><block s="reportNewList"><list><l>x</l><l>y</l></list></block></block>"#),
+        say(r#"<block s="reportListItem"><l>5</l><block s="reportNewList"><list><l>x</l><l>y</l></list></block></block>"#),
+        say(r#"<block s="reportLetter"><l>1</l><l>hello</l></block>"#),
+        say(r#"<block s="reportListIndex"><l>Y</l><block s="reportNewList"><list><l>x</l><l>y</l></list></block></block>"#),
+    ].concat());
+    let values: Vec<_> = exprs.iter().map(|x| eval(x).unwrap()).collect();
+    assert_eq!(values, [
+        Value::Bool(true), Value::Bool(true), Value::Bool(true), Value::Bool(false), Value::from("y"), Value::from(""), Value::from("h"), Value::Number(2.0),
+    ]);
+}
+
+#[test]
+fn test_eval_errors_and_folding() {
+    let mut exprs = parse_say(&[
+        say(r#"<block s="reportVariadicSum"><list><block var="g"/><l>2</l></list></block>"#),
+        say(r#"<block s="reportRandom"><l>1</l><l>10</l></block>"#),
+        say(r#"<block s="reportDifference"><l>abc</l><l>2</l></block>"#),
+        say(r#"<block s="reportVariadicSum"><list><block var="g"/><block s="reportVariadicProduct"><list><l>2</l><l>3</l></list></block></list></block>"#),
+    ].concat());
+    assert_eq!(eval(&exprs[0]), Err(EvalError::NotConstant));
+    assert_eq!(eval(&exprs[1]), Err(EvalError::NotConstant));
+    assert_eq!(eval(&exprs[2]), Err(EvalError::ExpectedNumber { got: "abc".into() }));
+
+    fold_constants(&mut exprs[3]);
+    match &exprs[3].kind {
+        ExprKind::Add { values } => match &values.kind {
+            ExprKind::MakeList { values } => {
+                assert!(matches!(values[0].kind, ExprKind::Variable { .. }));
+                assert_eq!(values[1].kind, ExprKind::Value(Value::Number(6.0)));
+            }
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let mut expr = Expr::from(ExprKind::Not { value: Box::new(true.into()) });
+    fold_constants(&mut expr);
+    assert_eq!(expr.kind, ExprKind::Value(false.into()));
+}
+
+#[test]
+fn test_fold_constants_allocation() {
+    let mut exprs = parse_say(&[
+        say(r#"<block s="reportNewList"><list><block s="reportVariadicProduct"><list><l>2</l><l>3</l></list></block></list></block>"#),
+        say(r#"<block s="reportVariadicSum"><list><l>1</l><l>2</l></list></block>"#),
+    ].concat());
+    for expr in exprs.iter_mut() {
+        fold_constants(expr);
+    }
+
+    // each evaluation creates a new list, so it cannot be replaced by a single literal
+    match &exprs[0].kind {
+        ExprKind::MakeList { values } => assert_eq!(values[0].kind, ExprKind::Value(Value::Number(6.0))),
+        x => panic!("{x:?}"),
+    }
+    // but the argument list of a variadic operator is not observable
+    assert_eq!(exprs[1].kind, ExprKind::Value(Value::Number(3.0)));
+}
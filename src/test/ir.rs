@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+use crate::*;
+use crate::ir::*;
+
+fn parse_stmts(scripts: &str) -> Vec<Stmt> {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable><variable name="h"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+        scripts = scripts,
+    );
+    let mut ast = Parser::default().parse(&script).unwrap();
+    core::mem::take(&mut ast.roles[0].entities[0].scripts[0].stmts)
+}
+/// Checks that every primitive in some lowered code has only empty slots as inputs, with one argument per slot.
+fn check_flat(instrs: &[Instr]) {
+    for instr in instrs {
+        match instr {
+            Instr::Eval { op, args, .. } => {
+                let mut slots = 0;
+                crate::visit::expr_children(op, &mut |x| { assert_eq!(x.kind, ExprKind::EmptySlot); slots += 1; }, &mut |_| panic!());
+                assert_eq!(slots, args.len());
+            }
+            Instr::Exec { op, args, .. } => {
+                let mut slots = 0;
+                crate::visit::stmt_children(op, &mut |x| { assert_eq!(x.kind, ExprKind::EmptySlot); slots += 1; }, &mut |_| panic!());
+                assert_eq!(slots, args.len());
+            }
+            Instr::Closure { body, .. } => check_flat(&body.instrs),
+            Instr::If { then, otherwise, .. } => { check_flat(then); check_flat(otherwise); }
+            Instr::Loop { body } | Instr::Warp { body } => check_flat(body),
+            Instr::TryCatch { body, handler, .. } => { check_flat(body); check_flat(handler); }
+            Instr::Move { .. } | Instr::Assign { .. } | Instr::Break | Instr::Return { .. } => (),
+        }
+    }
+}
+
+#[test]
+fn test_lower_exprs() {
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><block s="reportSum"><block var="h"/><block s="reportProduct"><block var="g"/><l>2</l></block></block></block></script>"#);
+    let body = lower(&stmts);
+    check_flat(&body.instrs);
+    assert_eq!(body.temps, 4);
+    match body.instrs.as_slice() {
+        [Instr::Eval { dest: Temp(0), op: ExprKind::MakeList { .. }, args: a0, .. }, Instr::Eval { dest: Temp(1), op: ExprKind::Mul { .. }, args: a1, .. },
+         Instr::Eval { dest: Temp(2), op: ExprKind::MakeList { .. }, args: a2, .. }, Instr::Eval { dest: Temp(3), op: ExprKind::Add { .. }, args: a3, .. },
+         Instr::Assign { var, value: Operand::Temp(Temp(3)) }] => {
+            assert!(matches!(a0.as_slice(), [Operand::Var(x), Operand::Value(_)] if x.name == "g"));
+            assert_eq!(a1, &[Operand::Temp(Temp(0))]);
+            assert!(matches!(a2.as_slice(), [Operand::Var(x), Operand::Temp(Temp(1))] if x.name == "h"));
+            assert_eq!(a3, &[Operand::Temp(Temp(2))]);
+            assert_eq!(var.name, "g");
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><block s="reportAnd"><block var="h"/><block s="reportIfElse"><block var="g"/><l>1</l><l>2</l></block></block></block></script>"#);
+    let body = lower(&stmts);
+    check_flat(&body.instrs);
+    match body.instrs.as_slice() {
+        [Instr::Move { dest: Temp(0), value: Operand::Var(h) }, Instr::If { condition: Operand::Temp(Temp(0)), then, otherwise }, Instr::Assign { value: Operand::Temp(Temp(0)), .. }] => {
+            assert_eq!(h.name, "h");
+            assert!(otherwise.is_empty());
+            assert!(matches!(then.as_slice(), [Instr::If { condition: Operand::Var(_), then: a, otherwise: b }, Instr::Move { dest: Temp(0), value: Operand::Temp(Temp(1)) }]
+                if matches!(a.as_slice(), [Instr::Move { dest: Temp(1), value: Operand::Value(_) }]) && matches!(b.as_slice(), [Instr::Move { dest: Temp(1), value: Operand::Value(_) }])));
+        }
+        x => panic!("{x:?}"),
+    }
+
+    // the variable must be read before the custom block call, which could change it
+    let (body, value) = lower_expr(&Expr { kind: ExprKind::MakeList { values: vec![
+        Expr { kind: ExprKind::Variable { var: VariableRef { name: "g".into(), trans_name: "g".into(), location: VarLocation::Global, slot: None } }, info: BlockInfo::none() },
+        Expr { kind: ExprKind::CallFn { function: FnRef { name: "f".into(), trans_name: "f".into(), location: FnLocation::Global }, args: vec![], upvars: vec![] }, info: BlockInfo::none() },
+    ] }, info: BlockInfo::none() });
+    assert_eq!(value, Operand::Temp(Temp(2)));
+    assert!(matches!(body.instrs.as_slice(), [Instr::Move { dest: Temp(0), value: Operand::Var(_) }, Instr::Eval { dest: Temp(1), op: ExprKind::CallFn { .. }, .. }, Instr::Eval { args, .. }]
+        if args == &[Operand::Temp(Temp(0)), Operand::Temp(Temp(1))]));
+}
+
+#[test]
+fn test_lower_loops() {
+    let stmts = parse_stmts(r#"<script><block s="receiveGo"/><block s="doRepeat"><l>3</l><script><block s="doChangeVar"><l>g</l><l>1</l></block></script></block><block s="doUntil"><block s="reportGreaterThan"><block var="g"/><l>10</l></block><script><block s="forward"><l>1</l></block></script></block><block s="doForEach"><l>item</l><block var="h"/><script><block s="doWarp"><script><block s="forward"><block var="item"/></block></script></block></script></block></script>"#);
+    let body = lower(&stmts);
+    check_flat(&body.instrs);
+    let loops: Vec<_> = body.instrs.iter().filter_map(|x| match x { Instr::Loop { body } => Some(body), _ => None }).collect();
+    assert_eq!(loops.len(), 3);
+
+    // repeat: increment the counter, exit once it passes the limit, then run the body
+    assert!(matches!(body.instrs[0], Instr::Move { dest: Temp(0), value: Operand::Value(_) }));
+    match loops[0].as_slice() {
+        [Instr::Eval { op: ExprKind::MakeList { .. }, .. }, Instr::Eval { op: ExprKind::Add { .. }, .. }, Instr::Move { dest: Temp(0), .. },
+         Instr::Eval { op: ExprKind::Greater { .. }, args, .. }, Instr::If { then, .. }, Instr::Exec { op: StmtKind::AddAssign { .. }, .. }] => {
+            assert_eq!(args[0], Operand::Temp(Temp(0)));
+            assert!(matches!(args[1], Operand::Value(_)));
+            assert_eq!(then, &[Instr::Break]);
+        }
+        x => panic!("{x:?}"),
+    }
+
+    // until: the condition is checked at the start of every iteration
+    assert!(matches!(loops[1].as_slice(), [Instr::Eval { op: ExprKind::Greater { .. }, .. }, Instr::If { condition: Operand::Temp(_), then, .. }, Instr::Exec { op: StmtKind::Forward { .. }, .. }] if then == &[Instr::Break]));
+
+    // foreach: the list is stored in a temporary (since the body could change the variable), and the item is assigned on each iteration
+    assert!(body.instrs.iter().any(|x| matches!(x, Instr::Move { value: Operand::Var(v), .. } if v.name == "h")));
+    assert!(loops[2].iter().any(|x| matches!(x, Instr::Eval { op: ExprKind::ListGet { .. }, .. })));
+    assert!(matches!(loops[2].as_slice(), [.., Instr::Assign { var, .. }, Instr::Warp { body }] if var.name == "item" && matches!(body.as_slice(), [Instr::Exec { args, .. }] if matches!(&args[0], Operand::Var(x) if x.name == "item"))));
+}
@@ -0,0 +1,49 @@
+use alloc::vec::Vec;
+use crate::*;
+
+#[test]
+fn test_lints() {
+    let say = |x: &str| format!(r#"<block s="bubble" collabId="say">{x}</block>"#);
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = [
+            format!(r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>v</l></list></block>{}<block s="doSetVar" collabId="set"><l>v</l><block s="reportEquals"><block var="v"/><l>5</l></block></block><block s="doWarp"><script><block s="doForever" collabId="loop"><script><block s="forward"><l>10</l></block></script></block></script></block>{}</script>"#,
+                say(r#"<block var="v"/>"#), say("<l>7</l>").repeat(3)),
+            format!(r#"<script>{}</script>"#, say("<l>hi</l>")),
+            r#"<script><block s="reportEquals"><l>1</l><l>2</l></block></script>"#.into(),
+        ].concat(),
+    );
+    let parser = Parser { omit_nonhat_scripts: false, keep_floating_reporters: true, ..Default::default() };
+    let ast = parser.parse(&script).unwrap();
+    let entity = &ast.roles[0].entities[0];
+    let stmts = &entity.scripts[0].stmts;
+
+    let lints = ast.lint(&LintOptions::default());
+    let found: Vec<_> = lints.iter().map(|x| (x.kind.clone(), x.node)).collect();
+    let seven = match &stmts[4].kind {
+        StmtKind::Say { content, .. } => content.info.id,
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(found, [
+        (LintKind::AssignComparison { var: "v".into() }, stmts[2].info.id),
+        (LintKind::WarpedInfLoop, NodeId(stmts[3].info.id.0 + 1)),
+        (LintKind::MagicNumber { value: 7.0, count: 3 }, seven),
+        (LintKind::UninitializedRead { var: "v".into() }, stmts[1].info.id),
+        (LintKind::UninitializedRead { var: "v".into() }, stmts[2].info.id),
+        (LintKind::ScriptWithoutHat, entity.scripts[1].stmts[0].info.id),
+        (LintKind::FloatingComparison, entity.floating_reporters[0].info.id),
+    ]);
+    assert!(lints.iter().all(|x| x.location.role.as_deref() == Some("myRole") && x.location.entity.as_deref() == Some("Stage")));
+    let blocks: Vec<_> = lints.iter().map(|x| (x.location.collab_id.as_deref(), x.location.block_type.as_deref())).collect();
+    assert_eq!(blocks, [
+        (Some("set"), Some("doSetVar")), (Some("loop"), Some("doForever")), (Some("say"), Some("bubble")), (Some("say"), Some("bubble")),
+        (Some("set"), Some("doSetVar")), (Some("say"), Some("bubble")), (None, None),
+    ]);
+
+    let diagnostic = Diagnostic::from(lints[1].clone());
+    assert_eq!((diagnostic.code, diagnostic.severity, diagnostic.node), ("L0001", Severity::Lint, Some(lints[1].node)));
+    assert_eq!(diagnostic.span.collab_id.as_deref(), Some("loop"));
+
+    let options = LintOptions { magic_numbers: None, uninitialized_reads: false, ..Default::default() };
+    assert_eq!(ast.lint(&options).len(), 4);
+}
@@ -0,0 +1,60 @@
+use alloc::vec::Vec;
+use crate::*;
+
+fn parse(globals: &str, scripts: &str) -> Project {
+    let script = format!(include_str!("script-template.xml"),
+        globals = globals, fields = "",
+        funcs = r#"<block-definition s="foo" type="command" category="custom"><header></header><code></code><translations></translations><inputs></inputs></block-definition>"#, methods = "",
+        scripts = scripts,
+    );
+    Parser::default().parse(&script).unwrap()
+}
+
+#[test]
+fn test_merge_policies() {
+    let base = parse(r#"<variable name="g"><l>1</l></variable>"#, "");
+    let incoming = parse(r#"<variable name="g"><l>2</l></variable><variable name="h"><l>3</l></variable>"#, r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><l>5</l></block><custom-block s="foo"/></script>"#);
+    let parser = Parser::default();
+
+    let mut skip = base.clone();
+    parser.merge(&mut skip, incoming.clone(), MergePolicy::Skip).unwrap();
+    let role = &skip.roles[0];
+    assert_eq!(role.globals.iter().map(|x| (x.def.name.as_str(), x.init.clone())).collect::<Vec<_>>(), [("g", Value::from("1")), ("h", Value::from("3"))]);
+    assert_eq!(role.funcs.len(), 1);
+    assert_eq!(role.entities.len(), 1);
+    assert_eq!(role.entities[0].scripts.len(), 0);
+
+    let mut overwrite = base.clone();
+    parser.merge(&mut overwrite, incoming.clone(), MergePolicy::Overwrite).unwrap();
+    let role = &overwrite.roles[0];
+    assert_eq!(role.globals[0].init, Value::from("2"));
+    assert_eq!(role.entities.len(), 1);
+    assert_eq!(role.entities[0].scripts.len(), 1);
+
+    let mut rename = base.clone();
+    parser.merge(&mut rename, incoming, MergePolicy::Rename).unwrap();
+    let role = &rename.roles[0];
+    assert_eq!(role.globals.iter().map(|x| x.def.name.as_str()).collect::<Vec<_>>(), ["g", "g(2)", "h"]);
+    assert_eq!(role.funcs.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["foo", "foo(2)"]);
+    assert_eq!(role.entities.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["Stage", "Stage(2)"]);
+    let stmts = &role.entities[1].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::Assign { var, .. } => assert_eq!((var.name.as_str(), var.trans_name.as_str()), ("g(2)", "g(2)")),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[1].kind {
+        StmtKind::CallFn { function, .. } => assert_eq!(function.trans_name, "foo(2)"),
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_merge_validation() {
+    let mut base = parse(r#"<variable name="g"><l>1</l></variable>"#, "");
+    let incoming = parse(r#"<variable name="g?"><l>2</l></variable>"#, "");
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    match parser.merge(&mut base, incoming, MergePolicy::Skip).unwrap_err().kind {
+        ErrorKind::CompileError(CompileError::GlobalsWithSameTransName { trans_name, .. }) => assert_eq!(trans_name, "g"),
+        x => panic!("{x:?}"),
+    }
+}
@@ -1,3 +1,21 @@
 extern crate std;
 
 mod ast;
+mod analysis;
+mod annotate;
+mod builder;
+#[cfg(feature = "bytecode")] mod bytecode;
+mod diff;
+#[cfg(feature = "std")] mod eval;
+mod ir;
+mod lint;
+mod merge;
+mod normalize;
+mod opt;
+mod pattern;
+mod query;
+#[cfg(feature = "raw")] mod raw;
+mod refactor;
+#[cfg(feature = "scratch")] mod scratch;
+#[cfg(feature = "testing")] mod testing;
+mod unparse;
@@ -0,0 +1,32 @@
+use crate::*;
+
+#[test]
+fn test_semantically_eq() {
+    let project = |scripts: &str| {
+        let script = format!(include_str!("script-template.xml"),
+            globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+            scripts = scripts,
+        );
+        Parser::default().parse(&script).unwrap()
+    };
+    let original = project(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><block s="reportSum"><block var="g"/><l>1</l></block></block><block s="doIf"><block s="reportEquals"><block var="g"/><l>5</l></block><script><block s="bubble"><block s="reportVariadicProduct"><list><l>2</l><l>3</l></list></block></block></script></block></script>"#);
+    let reordered = project(r#"<script><block s="receiveGo"/><block collabId="item_3" s="doSetVar"><l>g</l><block s="reportVariadicSum"><list><l>1</l><block var="g"/></list></block><comment w="90" collapsed="false">increment</comment></block><block s="doIf"><block s="reportEquals"><l>5</l><block var="g"/></block><script><block s="bubble"><block s="reportProduct"><l>3</l><l>2</l></block></block></script></block></script>"#);
+    let different = project(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><block s="reportDifference"><block var="g"/><l>1</l></block></block><block s="doIf"><block s="reportEquals"><block var="g"/><l>5</l></block><script><block s="bubble"><block s="reportVariadicProduct"><list><l>2</l><l>3</l></list></block></block></script></block></script>"#);
+    assert_ne!(original, reordered);
+    assert!(semantically_eq(&original, &reordered));
+    assert!(!semantically_eq(&original, &different));
+
+    let random = |order: &str| project(&format!(r#"<script><block s="receiveGo"/><block s="bubble"><block s="reportVariadicSum"><list>{order}</list></block></block></script>"#));
+    let (a, b) = (r#"<block s="reportRandom"><l>1</l><l>10</l></block>"#, r#"<block var="g"/>"#);
+    assert!(!semantically_eq(&random(&format!("{a}{b}")), &random(&format!("{b}{a}"))));
+
+    let mut normalized = reordered.clone();
+    normalize(&mut normalized);
+    let stmts = &normalized.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts[0].info.comment, None);
+    assert_eq!(stmts[0].info.location, None);
+    match &stmts[0].kind {
+        StmtKind::Assign { value, .. } => assert_eq!(value.info.opcode, None),
+        x => panic!("{x:?}"),
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::boxed::Box;
+use crate::*;
+
+#[test]
+fn test_hoist_loop_invariants() {
+    let sqrt = r#"<block s="reportMonadic"><l><option>sqrt</option></l><l>2</l></block>"#;
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>x</l><l>tmp 1</l></list></block><block s="doRepeat"><l>10</l><script><block s="bubble"><block s="reportVariadicSum"><list>{sqrt}<block var="x"/></list></block></block><block s="doChangeVar"><l>x</l>{sqrt}</block><block s="bubble"><block s="reportRandom"><l>1</l><l>10</l></block></block></script></block></script>"#),
+    );
+    let parser = Parser::default();
+    let mut ast = parser.parse(&script).unwrap();
+    let original = ast.roles[0].entities[0].scripts[0].stmts[1].clone();
+    ast.hoist_loop_invariants(&parser);
+
+    let script = &ast.roles[0].entities[0].scripts[0];
+    assert_eq!(script.stmts.len(), 4);
+    let tmp = match &script.stmts[1].kind {
+        StmtKind::DeclareLocals { vars } => {
+            assert_eq!(vars.len(), 1);
+            assert_eq!(vars[0].name, "tmp 2");
+            vars[0].clone()
+        }
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(script.locals.last(), Some(&tmp));
+    let tmp_ref = VariableRef { name: tmp.name.clone(), trans_name: tmp.trans_name.clone(), location: VarLocation::Local, slot: None };
+    match &script.stmts[2].kind {
+        StmtKind::Assign { var, value } => {
+            assert_eq!(*var, tmp_ref);
+            assert!(matches!(value.kind, ExprKind::Sqrt { .. }));
+        }
+        x => panic!("{x:?}"),
+    }
+    let expected = match original.kind {
+        StmtKind::Repeat { times, counter, mut stmts } => {
+            match &mut stmts[0].kind {
+                StmtKind::Say { content, .. } => match &mut content.kind {
+                    ExprKind::Add { values } => match &mut values.kind {
+                        ExprKind::MakeList { values } => values[0] = tmp_ref.clone().into(),
+                        x => panic!("{x:?}"),
+                    }
+                    x => panic!("{x:?}"),
+                }
+                x => panic!("{x:?}"),
+            }
+            match &mut stmts[1].kind {
+                StmtKind::AddAssign { value, .. } => **value = tmp_ref.clone().into(),
+                x => panic!("{x:?}"),
+            }
+            StmtKind::Repeat { times, counter, stmts }
+        }
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(script.stmts[3].kind, expected);
+}
+
+#[test]
+fn test_hoist_loop_invariant_reads() {
+    let double = |x: &str| format!(r#"<block s="reportVariadicProduct"><list><block var="{x}"/><l>2</l></list></block>"#);
+    let hoist = |stmts: &str| {
+        let script = format!(include_str!("script-template.xml"),
+            globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+            scripts = format!(r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>x</l><l>l</l></list></block>{stmts}</script>"#),
+        );
+        let parser = Parser::default();
+        let mut ast = parser.parse(&script).unwrap();
+        ast.hoist_loop_invariants(&parser);
+        ast.roles[0].entities[0].scripts[0].stmts.clone()
+    };
+
+    let stmts = hoist(&format!(r#"<block s="doRepeat"><l>10</l><script><block s="bubble">{}</block><block s="doSetVar"><l>l</l><l>5</l></block></script></block>"#, double("x")));
+    assert_eq!(stmts.len(), 4);
+    match &stmts[2].kind {
+        StmtKind::Assign { var, value } => {
+            assert_eq!(var.name, "tmp 1");
+            match &value.kind {
+                ExprKind::Mul { values } => match &values.kind {
+                    ExprKind::MakeList { values } => assert!(matches!(&values[0].kind, ExprKind::Variable { var } if var.name == "x")),
+                    x => panic!("{x:?}"),
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[3].kind {
+        StmtKind::Repeat { stmts, .. } => match &stmts[0].kind {
+            StmtKind::Say { content, .. } => assert!(matches!(&content.kind, ExprKind::Variable { var } if var.name == "tmp 1")),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+
+    // the same read is not invariant if the loop (or a nested loop) writes the variable
+    let stmts = hoist(&format!(r#"<block s="doRepeat"><l>10</l><script><block s="bubble">{}</block><block s="doForever"><script><block s="doChangeVar"><l>x</l><l>1</l></block></script></block></script></block>"#, double("x")));
+    assert_eq!(stmts.len(), 2);
+    let stmts = hoist(&format!(r#"<block s="doFor"><l>x</l><l>1</l><l>10</l><script><block s="bubble">{}</block></script></block>"#, double("x")));
+    assert_eq!(stmts.len(), 2);
+
+    // globals can be changed by other scripts
+    let stmts = hoist(&format!(r#"<block s="doRepeat"><l>10</l><script><block s="bubble">{}</block></script></block>"#, double("g")));
+    assert_eq!(stmts.len(), 2);
+
+    // a closure which assigns the variable could be run at any time
+    let stmts = hoist(&format!(r#"<block s="doSetVar"><l>l</l><block s="reifyScript"><script><block s="doSetVar"><l>x</l><l>1</l></block></script><list></list></block></block><block s="doRepeat"><l>10</l><script><block s="bubble">{}</block></script></block>"#, double("x")));
+    assert_eq!(stmts.len(), 3);
+
+    // mutating a list could change the value of any variable which refers to it
+    let stmts = hoist(&format!(r#"<block s="doRepeat"><l>10</l><script><block s="bubble">{}</block><block s="doAddToList"><l>1</l><block var="l"/></block></script></block>"#, double("x")));
+    assert_eq!(stmts.len(), 2);
+}
+
+#[test]
+fn test_simplify_conditions() {
+    let t = r#"<block s="reportBoolean"><l><bool>true</bool></l></block>"#;
+    let f = r#"<block s="reportBoolean"><l><bool>false</bool></l></block>"#;
+    let eq = r#"<block s="reportEquals"><block var="a"/><block var="b"/></block>"#;
+    let key = r#"<block s="reportKeyPressed"><l><option>space</option></l></block>"#;
+    let not = |x: &str| format!(r#"<block s="reportNot">{x}</block>"#);
+    let say = |x: &str| format!(r#"<block s="bubble">{x}</block>"#);
+    let parse = |code: &str| {
+        let script = format!(include_str!("script-template.xml"),
+            globals = r#"<variable name="a"><l>0</l></variable><variable name="b"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+            scripts = format!(r#"<script><block s="receiveGo"/>{code}</script>"#),
+        );
+        Parser::default().parse(&script).unwrap()
+    };
+
+    let mut ast = parse(&[
+        format!(r#"<block s="doIf">{}<script>{}</script></block>"#, not(&not(eq)), say("<l>1</l>")),
+        format!(r#"<block s="doIf">{t}<script>{}</script></block>"#, say("<l>2</l>")),
+        format!(r#"<block s="doIf">{f}<script>{}</script></block>"#, say("<l>3</l>")),
+        say(&not(&format!(r#"<block s="reportAnd">{eq}{key}</block>"#))),
+        say(&format!(r#"<block s="reportEquals">{eq}{t}</block>"#)),
+        say(&format!(r#"<block s="reportEquals">{f}{eq}</block>"#)),
+        say(&format!(r#"<block s="reportAnd">{f}{key}</block>"#)),
+        say(&format!(r#"<block s="reportOr">{key}{t}</block>"#)),
+        say(&not(&not(r#"<block var="a"/>"#))),
+        format!(r#"<block s="doUntil">{f}<script>{}</script></block>"#, say("<l>4</l>")),
+    ].concat());
+    let expected = parse(&[
+        format!(r#"<block s="doIf">{eq}<script>{}</script></block>"#, say("<l>1</l>")),
+        say("<l>2</l>"),
+        say(&format!(r#"<block s="reportOr">{}{}</block>"#, r#"<block s="reportNotEquals"><block var="a"/><block var="b"/></block>"#, not(key))),
+        say(eq),
+        say(r#"<block s="reportNotEquals"><block var="a"/><block var="b"/></block>"#),
+        say(f),
+        say(&format!(r#"<block s="reportOr">{key}{t}</block>"#)),
+        say(&not(&not(r#"<block var="a"/>"#))),
+        format!(r#"<block s="doForever"><script>{}</script></block>"#, say("<l>4</l>")),
+    ].concat());
+
+    ast.simplify_conditions();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let expected = &expected.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts.len(), expected.len());
+    for (stmt, expected) in stmts.iter().zip(expected) {
+        assert_eq!(stmt.kind, expected.kind);
+    }
+}
+
+#[test]
+fn test_parser_passes() {
+    struct Rename(&'static str);
+    impl AstPass for Rename {
+        fn name(&self) -> &str { "rename" }
+        fn run(&self, project: &mut Project, name_transformer: &dyn NameTransformer) {
+            project.name = format_compact!("{}{}", project.name, name_transformer.transform_name(self.0).unwrap());
+        }
+    }
+
+    for name in BUILTIN_PASSES {
+        assert_eq!(builtin_pass(name).unwrap().name(), *name);
+    }
+    assert!(builtin_pass("unknown").is_none());
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doIf"><block s="reportBoolean"><l><bool>true</bool></l></block><script><block s="bubble"><l>hi</l></block></script></block></script>"#,
+    );
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident)
+        .with_pass(builtin_pass("simplify-conditions").unwrap())
+        .with_pass(Box::new(Rename("a b")))
+        .with_pass(Box::new(Rename("c d")));
+    let ast = parser.parse(&script).unwrap();
+    assert_eq!(ast.name, "untitleda_bc_d");
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts.len(), 1);
+    assert!(matches!(stmts[0].kind, StmtKind::Say { .. }));
+    assert_ne!(stmts[0].info.id, NodeId::default());
+}
+
+#[test]
+fn test_shrink_to_fit() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doRepeat"><l>3</l><script><block s="bubble"><block s="reportVariadicSum"><list><l>1</l><block s="reportRandom"><l>1</l><l>10</l></block></list></block></block></script></block></script>"#,
+    );
+    let mut ast = Parser::default().parse(&script).unwrap();
+    let original = ast.clone();
+    let stmts = &mut ast.roles[0].entities[0].scripts[0].stmts;
+    stmts.reserve(10);
+    let values = match &mut stmts[0].kind {
+        StmtKind::Repeat { stmts: body, .. } => {
+            body.reserve(10);
+            match &mut body[0].kind {
+                StmtKind::Say { content, .. } => match &mut content.kind {
+                    ExprKind::Add { values } => match &mut values.kind {
+                        ExprKind::MakeList { values } => values,
+                        x => panic!("{x:?}"),
+                    }
+                    x => panic!("{x:?}"),
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    };
+    values.reserve(10);
+
+    ast.shrink_to_fit();
+    assert_eq!(ast, original);
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    assert_eq!(stmts.capacity(), 1);
+    match &stmts[0].kind {
+        StmtKind::Repeat { stmts: body, .. } => {
+            assert_eq!(body.capacity(), 1);
+            match &body[0].kind {
+                StmtKind::Say { content, .. } => match &content.kind {
+                    ExprKind::Add { values } => match &values.kind {
+                        ExprKind::MakeList { values } => assert_eq!(values.capacity(), 2),
+                        x => panic!("{x:?}"),
+                    }
+                    x => panic!("{x:?}"),
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+}
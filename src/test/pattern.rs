@@ -0,0 +1,39 @@
+use alloc::vec::Vec;
+use crate::*;
+
+#[test]
+fn test_expr_pat() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSayFor"><block s="reportDifference"><l>5</l><block var="g"/></block><block s="reportNot"><block s="reportNot"><block s="reportShown"/></block></block></block><block s="doRun"><block s="reifyScript"><script></script><list></list></block><list></list></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+    let (content, duration, closure) = match (&stmts[0].kind, &stmts[1].kind) {
+        (StmtKind::Say { content, duration: Some(duration) }, StmtKind::CallClosure { closure, .. }) => (content, duration, closure),
+        x => panic!("{x:?}"),
+    };
+
+    assert!(expr_pat!(content, Sub { left: Value(_), right: Variable { .. } }));
+    assert!(expr_pat!(content, Sub {}));
+    assert!(!expr_pat!(content, Sub { right: Value(_) }));
+    assert!(!expr_pat!(duration, Sub {}));
+    assert_eq!(expr_pat!(content, Sub { left: Value(Value::String(x)), right: Variable { var } } => (x.as_str(), var.name.as_str())), Some(("5", "g")));
+    let right = match &content.kind {
+        ExprKind::Sub { right, .. } => right.info.id,
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(expr_pat!(content, Sub { left: Value(Value::String(x)), right } if x == "5" => right.info.id), Some(right));
+    assert_eq!(expr_pat!(content, Sub { left: Value(Value::String(x)) } if x == "6" => ()), None);
+
+    assert!(expr_pat!(duration, Not { value: Not { value: IsVisible {} } }));
+    let inner = expr_pat!(duration, Not { value: Not { value: inner } } => inner).unwrap();
+    assert!(matches!(inner.kind, ExprKind::IsVisible));
+    assert!(expr_pat!(duration, whole if whole.info.id == duration.info.id));
+
+    assert!(expr_pat!(closure, Closure { kind: ClosureKind::Command, params, stmts } if params.is_empty() && stmts.is_empty()));
+    assert!(!expr_pat!(closure, Closure { kind: ClosureKind::Reporter, .. }));
+
+    let negated: Vec<_> = [content, duration].iter().filter_map(|x| expr_pat!(x, Not { value } => value.info.id)).collect();
+    assert_eq!(negated.len(), 1);
+}
@@ -0,0 +1,62 @@
+use alloc::vec::Vec;
+use crate::*;
+
+#[test]
+fn test_query() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "",
+        funcs = r#"<block-definition s="bump %&apos;n&apos;" type="command" category="custom"><header></header><code></code><inputs><input type="%n"></input></inputs><script><block s="doChangeVar"><l>g</l><block var="n"/></block></script></block-definition>"#,
+        methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><l>1</l></block><block s="doRepeat"><l>10</l><script><block s="doSetVar"><l>g</l><block s="reportSum"><block var="g"/><l>2</l></block></block><custom-block s="bump %n"><l>3</l></custom-block></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let role = &ast.roles[0];
+    let stmts = &role.entities[0].scripts[0].stmts;
+    let body = match &stmts[1].kind {
+        StmtKind::Repeat { stmts, .. } => stmts,
+        x => panic!("{x:?}"),
+    };
+
+    assert_eq!(ast.query().blocks("doSetVar").ids(), [stmts[0].info.id, body[0].info.id]);
+    assert_eq!(ast.query().blocks("doSetVar").inside("doRepeat").ids(), [body[0].info.id]);
+    assert_eq!(ast.query().blocks("doSetVar").in_sprite("Stage").len(), 2);
+    assert!(ast.query().blocks("doSetVar").in_sprite("Player").is_empty());
+    assert!(ast.query().blocks("doSetVar").in_role("otherRole").is_empty());
+    assert_eq!(ast.query().hats().blocks("receiveGo").len(), 1);
+    assert_eq!(ast.query().calls("bump \t").ids(), [body[1].info.id]);
+
+    let lowered = ast.query().blocks("reportSum");
+    assert_eq!(lowered.len(), 1);
+    assert!(matches!(lowered.nodes().next(), Some(QueryNode::Expr(Expr { kind: ExprKind::Add { .. }, .. }))));
+    assert_eq!(lowered.iter().next().unwrap().location().block_type.as_deref(), Some("reportSum"));
+
+    let uses: Vec<_> = ast.query().variable("g").iter().map(|x| (x.node.opcode(), x.func.map(|x| x.name.as_str()))).collect();
+    assert_eq!(uses, [(Some("doChangeVar"), Some("bump \t")), (Some("doSetVar"), None), (Some("doSetVar"), None), (None, None)]);
+    assert_eq!(ast.query().variable("g").in_scripts().exprs().len(), 1);
+    assert_eq!(ast.query().in_function("bump \t").len(), 2);
+
+    let constants = ast.query().matching(|x| matches!(x.node, QueryNode::Expr(Expr { kind: ExprKind::Value(Value::String(v)), .. }) if v == "2"));
+    assert_eq!(constants.len(), 1);
+    assert_eq!(constants.iter().next().unwrap().ancestors.iter().filter_map(|x| x.opcode()).collect::<Vec<_>>(), ["receiveGo", "doRepeat", "doSetVar", "reportSum", "reportNewList"]);
+}
+
+#[test]
+fn test_node_accessors() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "", funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveCondition"><block s="reportLessThan"><block var="g"/><l>5</l></block></block><block s="doIf"><block s="reportBoolean"><l><bool>true</bool></l></block><script><block s="doSetVar"><l>g</l><l>1</l><comment w="90" collapsed="false">set it</comment></block><block s="forward"><l>2</l></block></script></block></script>"#,
+    );
+    let ast = Parser::default().parse(&script).unwrap();
+    let script = &ast.roles[0].entities[0].scripts[0];
+
+    let hat = script.hat.as_deref().unwrap();
+    assert_eq!(hat.comment(), None);
+    assert_eq!(hat.children().iter().map(|x| x.opcode()).collect::<Vec<_>>(), [Some("reportLessThan")]);
+    assert_eq!(hat.children()[0].children().len(), 2);
+
+    let children = script.stmts[0].children();
+    assert_eq!(children.iter().map(|x| x.opcode()).collect::<Vec<_>>(), [None, Some("doSetVar"), Some("forward")]);
+    assert_eq!(children.iter().map(|x| x.comment()).collect::<Vec<_>>(), [None, Some("set it"), None]);
+    assert_eq!(children[1].children().len(), 1);
+    assert!(matches!(children[1], QueryNode::Stmt(x) if x.comment() == Some("set it")));
+}
@@ -0,0 +1,79 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::*;
+use crate::raw::*;
+
+#[test]
+fn test_raw_project() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = r#"<variable name="f"><list><l>a &amp; b</l></list></variable>"#,
+        funcs = r#"<block-definition s="foo %'x'" type="reporter" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n"></input></inputs><script><block s="doReport"><block var="x"/></block></script></block-definition>"#, methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doRepeat"><l>10</l><script><block s="forward"><custom-block s="foo %n"><block s="reportMonadic"><l><option>sqrt</option></l><l>2</l></block></custom-block></block></script></block><block s="setColor"><color>255,0,0,1</color><comment w="90" collapsed="false">red</comment></block></script>"#,
+    );
+    let project = Parser::default().parse_raw(&script).unwrap();
+    let owned = Parser::default().parse(&script).unwrap();
+    assert_eq!(project.name, owned.name);
+    assert_eq!(project.roles.len(), 1);
+    let role = &project.roles[0];
+    assert_eq!(role.name, owned.roles[0].name);
+    assert_eq!(role.entities.iter().map(|x| &*x.name).collect::<Vec<_>>(), owned.roles[0].entities.iter().map(|x| x.name.as_str()).collect::<Vec<_>>());
+
+    // strings refer to the source unless they had to be unescaped
+    assert_eq!(role.globals, [Variable { name: "g".into(), init: Some(Input::Text("0".into())) }]);
+    assert!(matches!(role.globals[0].name, Cow::Borrowed(_)));
+    let fields: Vec<_> = role.entities.iter().flat_map(|x| &x.fields).collect();
+    assert_eq!(fields.len(), 1);
+    match &fields[0].init {
+        Some(Input::List(values)) => match values.as_slice() {
+            [Input::Text(Cow::Owned(x))] => assert_eq!(x, "a & b"),
+            x => panic!("{x:?}"),
+        }
+        x => panic!("{x:?}"),
+    }
+
+    assert_eq!(role.funcs.len(), 1);
+    assert_eq!((&*role.funcs[0].spec, &*role.funcs[0].kind), ("foo %'x'", "reporter"));
+    assert_eq!(role.funcs[0].script.blocks[0].inputs, [Input::Block(Box::new(Block { kind: BlockKind::Variable("x".into()), inputs: vec![], comment: None }))]);
+
+    let script = role.entities.iter().flat_map(|x| &x.scripts).next().unwrap();
+    let kinds: Vec<_> = script.blocks.iter().map(|x| &x.kind).collect();
+    assert_eq!(kinds, [&BlockKind::Primitive("receiveGo".into()), &BlockKind::Primitive("doRepeat".into()), &BlockKind::Primitive("setColor".into())]);
+    assert_eq!(script.blocks[2].inputs, [Input::Color("255,0,0,1".into())]);
+    assert_eq!(script.blocks[2].comment.as_deref(), Some("red"));
+    match script.blocks[1].inputs.as_slice() {
+        [Input::Text(times), Input::Script(body)] => {
+            assert_eq!(times, "10");
+            let sqrt = Block { kind: BlockKind::Primitive("reportMonadic".into()), inputs: vec![Input::Option("sqrt".into()), Input::Text("2".into())], comment: None };
+            let call = Block { kind: BlockKind::Custom("foo %n".into()), inputs: vec![Input::Block(Box::new(sqrt))], comment: None };
+            assert_eq!(body.blocks, [Block { kind: BlockKind::Primitive("forward".into()), inputs: vec![Input::Block(Box::new(call))], comment: None }]);
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_raw_media() {
+    let xml = include_str!("projects/media.xml");
+    let project = Parser::default().parse_raw(xml).unwrap();
+    let owned = Parser::default().parse(xml).unwrap();
+    let media = &project.roles[0].media;
+    assert_eq!(media.iter().map(|x| (x.kind, &*x.id, &*x.name)).collect::<Vec<_>>(), [(MediaKind::Costume, "Sprite_cst_airplane2", "airplane2"), (MediaKind::Sound, "Sprite_snd_Dog 2", "Dog 2")]);
+    assert!(matches!(media[0].data, Cow::Borrowed(_)));
+
+    let (image, audio) = match (&owned.roles[0].entities[1].costumes[0].init, &owned.roles[0].entities[1].sounds[0].init) {
+        (Value::Image(image), Value::Audio(audio)) => (&image.0, &audio.0),
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(media[0].decode().as_ref(), Some(image));
+    assert_eq!(media[1].decode().as_ref(), Some(audio));
+    assert_eq!(Media { data: "not a data url".into(), ..media[0].clone() }.decode(), None);
+}
+
+#[test]
+fn test_raw_errors() {
+    assert_eq!(Parser::default().parse_raw("").unwrap_err().kind, ProjectError::NoRoot.into());
+    assert_eq!(Parser::default().parse_raw(r#"<room name="x"><role></role></room>"#).unwrap_err().kind, ProjectError::RoleNoName.into());
+    assert_eq!(Parser::default().parse_raw(r#"<room name="x"><role name="r"></role></room>"#).unwrap_err().kind, ProjectError::RoleNoContent.into());
+    assert!(matches!(Parser::default().parse_raw(r#"<room name="x"><role name="r">"#).unwrap_err().kind, ErrorKind::XmlError(_)));
+}
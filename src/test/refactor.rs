@@ -0,0 +1,239 @@
+use crate::*;
+
+#[test]
+fn test_rename_variable() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable><variable name="h"><l>0</l></variable>"#, fields = "",
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>x</l></list></block><block s="doSetVar"><l>g</l><block var="x"/></block><block s="doSetVar"><l>x</l><block s="reifyReporter"><autolambda><block s="reportSum"><block var="x"/><block var="g"/></block></autolambda><list></list></block></block></script>"#,
+    );
+    let parser = Parser::default();
+    let mut ast = parser.parse(&script).unwrap();
+
+    match ast.rename_variable(&parser, VarScope::Global { role: "myRole" }, "g", "h").unwrap_err().kind {
+        ErrorKind::CompileError(CompileError::GlobalsWithSameTransName { trans_name, .. }) => assert_eq!(trans_name, "h"),
+        x => panic!("{x:?}"),
+    }
+    match ast.rename_variable(&parser, VarScope::Global { role: "myRole" }, "nope", "y").unwrap_err().kind {
+        ErrorKind::CompileError(CompileError::UndefinedVariable { name }) => assert_eq!(name, "nope"),
+        x => panic!("{x:?}"),
+    }
+
+    ast.rename_variable(&parser, VarScope::Global { role: "myRole" }, "g", "total").unwrap();
+    ast.rename_variable(&parser, VarScope::ScriptLocal { role: "myRole", entity: "Stage", script: 0 }, "x", "y").unwrap();
+
+    let role = &ast.roles[0];
+    assert_eq!(role.globals[0].def.name, "total");
+    let stmts = &role.entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::DeclareLocals { vars } => assert_eq!(vars[0].name, "y"),
+        x => panic!("{x:?}"),
+    }
+    match &stmts[1].kind {
+        StmtKind::Assign { var, value } => {
+            assert_eq!(var.trans_name, "total");
+            assert!(matches!(&value.kind, ExprKind::Variable { var } if var.trans_name == "y"));
+        }
+        x => panic!("{x:?}"),
+    }
+    match &stmts[2].kind {
+        StmtKind::Assign { var, value } => {
+            assert_eq!(var.name, "y");
+            match &value.kind {
+                ExprKind::Closure { captures, stmts, .. } => {
+                    assert_eq!(captures[0].name, "y");
+                    match &stmts[0].kind {
+                        StmtKind::Return { value } => match &value.kind {
+                            ExprKind::Add { values } => match &values.kind {
+                                ExprKind::MakeList { values } => {
+                                    assert!(matches!(&values[0].kind, ExprKind::Variable { var } if var.name == "y" && var.location == VarLocation::Local));
+                                    assert!(matches!(&values[1].kind, ExprKind::Variable { var } if var.name == "total" && var.location == VarLocation::Global));
+                                }
+                                x => panic!("{x:?}"),
+                            }
+                            x => panic!("{x:?}"),
+                        }
+                        x => panic!("{x:?}"),
+                    }
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+}
+
+#[test]
+fn test_anonymize() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="score"><l>0</l></variable>"#, fields = "",
+        funcs = r#"<block-definition s="double %'num'" type="reporter" category="custom"><comment w="90" collapsed="false">by alice</comment><header></header><code></code><translations></translations><inputs><input type="%n"></input></inputs><script><block s="doReport"><block s="reportVariadicProduct"><list><block var="num"/><l>2</l></list></block></block></script></block-definition>"#, methods = "",
+        scripts = r#"<script><block s="receiveGo" collabId="hat"/><block s="doDeclareVariables"><list><l>score</l><l>tmp</l></list></block><block s="doSetVar" collabId="alice_set"><l>tmp</l><custom-block s="double %n" collabId="alice_call"><block var="score"/></custom-block><comment w="90" collapsed="false">alice wrote this</comment></block></script>"#,
+    );
+    let parser = Parser::default();
+    let original = parser.parse(&script).unwrap();
+    let mut ast = original.clone();
+    let mapping = ast.anonymize(&parser).unwrap();
+
+    assert_eq!(ast.name, "project");
+    assert_eq!(ast.roles[0].name, "role 1");
+    assert_eq!(mapping.roles, [("role 1".into(), "myRole".into())]);
+    assert_eq!(mapping.project, original.name);
+    assert_eq!(mapping.vars.len(), 3);
+    assert_eq!(mapping.original_var("var 1"), Some("score"));
+    assert_eq!(mapping.funcs.len(), 1);
+    assert_eq!(mapping.original_func(&mapping.funcs[0].0), Some("double \t"));
+
+    let role = &ast.roles[0];
+    assert_eq!(role.globals[0].def.name, "var 1");
+    assert_eq!(role.funcs[0].name, mapping.funcs[0].0);
+    assert_eq!(role.funcs[0].help, None);
+    let stmts = &role.entities[0].scripts[0].stmts;
+    match &stmts[0].kind {
+        StmtKind::DeclareLocals { vars } => assert_eq!(vars.iter().map(|x| x.name.as_str()).collect::<alloc::vec::Vec<_>>(), ["var 1", "var 3"]),
+        x => panic!("{x:?}"),
+    }
+    assert_eq!(role.entities[0].scripts[0].hat.as_ref().unwrap().info.location, None);
+    assert_eq!(stmts[1].info.comment, None);
+    assert_eq!(stmts[1].info.location, None);
+    assert_eq!(original.roles[0].entities[0].scripts[0].stmts[1].info.location.as_deref(), Some("alice_set"));
+    match &stmts[1].kind {
+        StmtKind::Assign { var, value } => {
+            assert_eq!(var.name, "var 3");
+            assert_eq!(value.info.location, None);
+            match &value.kind {
+                ExprKind::CallFn { function, args, .. } => {
+                    assert_eq!(function.trans_name, role.funcs[0].trans_name);
+                    assert!(matches!(&args[0].kind, ExprKind::Variable { var } if var.name == "var 1" && var.location == VarLocation::Local));
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        x => panic!("{x:?}"),
+    }
+
+    let fingerprint = original.fingerprint();
+    assert_eq!(ast.fingerprint(), fingerprint);
+
+    ast.deanonymize(&parser, &mapping).unwrap();
+    assert_eq!(ast.name, original.name);
+    assert_eq!(ast.roles[0].name, "myRole");
+    assert_eq!(ast.roles[0].funcs[0].name, original.roles[0].funcs[0].name);
+    assert_eq!(ast.roles[0].funcs[0].trans_name, original.roles[0].funcs[0].trans_name);
+    assert_eq!(ast.roles[0].globals, original.roles[0].globals);
+    assert_eq!(ast.roles[0].entities[0].scripts[0].stmts[0], original.roles[0].entities[0].scripts[0].stmts[0]);
+}
+
+#[test]
+fn test_resolve_slots() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable><variable name="h"><l>0</l></variable>"#,
+        fields = r#"<variable name="f"><l>0</l></variable>"#,
+        funcs = r#"<block-definition s="foo %'a' %'b'" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%s"></input><input type="%s"></input></inputs><script><block s="doDeclareVariables"><list><l>t</l></list></block><block s="doSetVar"><l>t</l><block var="b"/></block><block s="doSetVar"><l>h</l><block var="a"/></block></script></block-definition>"#, methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>x</l><l>y</l></list></block><block s="doSetVar"><l>y</l><block s="reifyReporter"><autolambda><block s="reportVariadicSum"><list><block var="f"/><block var="y"/><block var="p"/></list></block></autolambda><list><l>p</l></list></block></block><block s="doSetVar"><l>h</l><block var="f"/></block></script>"#,
+    );
+    let mut ast = Parser::default().parse(&script).unwrap();
+    let slot = |expr: &Expr| match &expr.kind {
+        ExprKind::Variable { var } => var.slot,
+        x => panic!("{x:?}"),
+    };
+
+    let check = |ast: &Project| {
+        let func = &ast.roles[0].funcs[0].stmts;
+        match (&func[1].kind, &func[2].kind) {
+            (StmtKind::Assign { var: t, value: b }, StmtKind::Assign { var: h, value: a }) => {
+                assert_eq!((t.slot, slot(b), h.slot, slot(a)), (Some(2), Some(1), Some(1), Some(0)));
+            }
+            x => panic!("{x:?}"),
+        }
+
+        let stmts = &ast.roles[0].entities[0].scripts[0].stmts;
+        match (&stmts[1].kind, &stmts[2].kind) {
+            (StmtKind::Assign { var: y, value: closure }, StmtKind::Assign { var: h, value: f }) => {
+                assert_eq!((y.slot, h.slot, slot(f)), (Some(1), Some(1), Some(0)));
+                match &closure.kind {
+                    ExprKind::Closure { captures, stmts, .. } => {
+                        assert_eq!(captures.iter().map(|x| (x.name.as_str(), x.slot)).collect::<alloc::vec::Vec<_>>(), [("f", Some(0)), ("y", Some(1))]);
+                        match &stmts[0].kind {
+                            StmtKind::Return { value } => match &value.kind {
+                                ExprKind::Add { values } => match &values.kind {
+                                    ExprKind::MakeList { values } => assert_eq!(values.iter().map(slot).collect::<alloc::vec::Vec<_>>(), [Some(1), Some(2), Some(0)]),
+                                    x => panic!("{x:?}"),
+                                }
+                                x => panic!("{x:?}"),
+                            }
+                            x => panic!("{x:?}"),
+                        }
+                    }
+                    x => panic!("{x:?}"),
+                }
+            }
+            x => panic!("{x:?}"),
+        }
+    };
+    check(&ast);
+
+    ast.roles[0].globals.swap(0, 1);
+    ast.resolve_slots();
+    match &ast.roles[0].entities[0].scripts[0].stmts[2].kind {
+        StmtKind::Assign { var, .. } => assert_eq!(var.slot, Some(0)),
+        x => panic!("{x:?}"),
+    }
+    ast.roles[0].globals.swap(0, 1);
+    ast.resolve_slots();
+    check(&ast);
+
+    // slots are not part of the identity of a reference
+    let (resolved, unresolved) = match &ast.roles[0].entities[0].scripts[0].stmts[2].kind {
+        StmtKind::Assign { var, .. } => (var.clone(), VariableRef { slot: None, ..var.clone() }),
+        x => panic!("{x:?}"),
+    };
+    assert_eq!(resolved, unresolved);
+    let hash = |x: &VariableRef| {
+        let mut hasher = super::std::collections::hash_map::DefaultHasher::new();
+        core::hash::Hash::hash(x, &mut hasher);
+        core::hash::Hasher::finish(&hasher)
+    };
+    assert_eq!(hash(&resolved), hash(&unresolved));
+}
+
+#[test]
+fn test_var_index() {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="my g"><l>0</l></variable><variable name="h"><l>0</l></variable>"#,
+        fields = r#"<variable name="f"><l>0</l></variable>"#,
+        funcs = "", methods = "",
+        scripts = r#"<script><block s="receiveGo"/><block s="doSetVar"><l>h</l><block var="f"/></block></script>"#,
+    );
+    let parser = Parser::default().with_name_transformer(crate::util::c_ident);
+    let mut ast = parser.parse(&script).unwrap();
+
+    let role = &ast.roles[0];
+    assert_eq!(role.global_index().len(), 2);
+    assert_eq!(role.global_index().get("h"), Some(1));
+    assert_eq!(role.global_index().get_trans("my_g"), Some(0));
+    assert_eq!(role.global_index().get("my_g"), None);
+    assert_eq!(role.global("h").unwrap().def.name, "h");
+    assert_eq!(role.entities[0].field_index().get("f"), Some(0));
+    assert!(role.entities[0].field_index().get("h").is_none());
+
+    ast.rename_variable(&parser, VarScope::Global { role: "myRole" }, "h", "total").unwrap();
+    let role = &ast.roles[0];
+    assert_eq!(role.global_index().get("h"), None);
+    assert_eq!(role.global_index().get("total"), Some(1));
+    assert_eq!(role.global("total").unwrap().def.trans_name, "total");
+
+    ast.roles[0].globals.remove(0);
+    assert_eq!(ast.roles[0].global("total").unwrap().def.name, "total"); // stale index falls back to a search
+    ast.roles[0].reindex();
+    assert_eq!(ast.roles[0].global_index().get("total"), Some(0));
+
+    // the cached indices do not affect equality
+    let mut unindexed = ast.clone();
+    unindexed.roles[0].global_index = VarIndexCache::default();
+    unindexed.roles[0].entities[0].field_index = VarIndexCache::default();
+    assert!(!unindexed.roles[0].global_index.is_built() && ast.roles[0].global_index.is_built());
+    assert_eq!(unindexed, ast);
+    unindexed.roles[0].reindex();
+    assert_eq!(unindexed, ast);
+}
@@ -0,0 +1,164 @@
+use alloc::vec::Vec;
+use std::io::{Cursor, Write};
+use crate::*;
+use crate::scratch::*;
+
+fn make_sb3(project: &str, assets: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in [("project.json", project.as_bytes())].into_iter().chain(assets.iter().copied()) {
+        zip.start_file(name, zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+const PROJECT: &str = r##"{"targets": [
+    {"isStage": true, "name": "Stage", "variables": {"v1": ["score", 0]}, "lists": {"l1": ["items", ["a", 2]]}, "broadcasts": {}, "blocks": {}, "costumes": [], "sounds": [], "currentCostume": 0, "tempo": 60},
+    {"isStage": false, "name": "Cat", "variables": {"v2": ["speed", 5]}, "lists": {}, "costumes": [{"name": "cat-a", "md5ext": "abc.png", "dataFormat": "png", "rotationCenterX": 96, "rotationCenterY": 100, "bitmapResolution": 2}],
+        "sounds": [{"name": "meow", "md5ext": "missing.wav", "dataFormat": "wav"}], "currentCostume": 0, "x": 10, "y": -20, "direction": 45, "size": 50, "visible": false,
+        "blocks": {
+            "a": {"opcode": "event_whenflagclicked", "next": "b", "parent": null, "inputs": {}, "fields": {}, "shadow": false, "topLevel": true},
+            "b": {"opcode": "data_setvariableto", "next": "c", "parent": "a", "inputs": {"VALUE": [1, [10, "0"]]}, "fields": {"VARIABLE": ["score", "v1"]}, "shadow": false, "topLevel": false},
+            "c": {"opcode": "control_repeat", "next": "g", "parent": "b", "inputs": {"TIMES": [1, [6, "10"]], "SUBSTACK": [2, "d"]}, "fields": {}, "shadow": false, "topLevel": false},
+            "d": {"opcode": "motion_movesteps", "next": "e", "parent": "c", "inputs": {"STEPS": [3, [12, "speed", "v2"], [4, "10"]]}, "fields": {}, "shadow": false, "topLevel": false},
+            "e": {"opcode": "data_changevariableby", "next": null, "parent": "d", "inputs": {"VALUE": [1, "f"]}, "fields": {"VARIABLE": ["score", "v1"]}, "shadow": false, "topLevel": false},
+            "f": {"opcode": "operator_add", "next": null, "parent": "e", "inputs": {"NUM1": [1, [4, "1"]], "NUM2": [3, "f2", [4, ""]]}, "fields": {}, "shadow": false, "topLevel": false},
+            "f2": {"opcode": "data_itemoflist", "next": null, "parent": "f", "inputs": {"INDEX": [1, [7, "2"]]}, "fields": {"LIST": ["items", "l1"]}, "shadow": false, "topLevel": false},
+            "g": {"opcode": "motion_goto", "next": "h", "parent": "c", "inputs": {"TO": [1, "g2"]}, "fields": {}, "shadow": false, "topLevel": false},
+            "g2": {"opcode": "motion_goto_menu", "next": null, "parent": "g", "inputs": {}, "fields": {"TO": ["_random_", null]}, "shadow": true, "topLevel": false},
+            "h": {"opcode": "motion_glidesecstoxy", "next": "i", "parent": "g", "inputs": {"SECS": [1, [4, "1"]], "X": [1, [4, "0"]], "Y": [1, [4, "0"]]}, "fields": {}, "shadow": false, "topLevel": false},
+            "i": {"opcode": "procedures_call", "next": null, "parent": "h", "inputs": {"arg1": [1, [4, "3"]]}, "fields": {}, "shadow": false, "topLevel": false, "mutation": {"tagName": "mutation", "children": [], "proccode": "jump %n times", "argumentids": "[\"arg1\"]", "warp": "false"}},
+            "p": {"opcode": "procedures_definition", "next": "q", "parent": null, "inputs": {"custom_block": [1, "p2"]}, "fields": {}, "shadow": false, "topLevel": true},
+            "p2": {"opcode": "procedures_prototype", "next": null, "parent": "p", "inputs": {"arg1": [1, "p3"]}, "fields": {}, "shadow": true, "topLevel": false, "mutation": {"tagName": "mutation", "children": [], "proccode": "jump %n times", "argumentids": "[\"arg1\"]", "argumentnames": "[\"n\"]", "argumentdefaults": "[\"\"]", "warp": "true"}},
+            "p3": {"opcode": "argument_reporter_string_number", "next": null, "parent": "p2", "inputs": {}, "fields": {"VALUE": ["n", null]}, "shadow": true, "topLevel": false},
+            "q": {"opcode": "motion_changeyby", "next": null, "parent": "p", "inputs": {"DY": [3, "q2", [4, "10"]]}, "fields": {}, "shadow": false, "topLevel": false},
+            "q2": {"opcode": "argument_reporter_string_number", "next": null, "parent": "q", "inputs": {}, "fields": {"VALUE": ["n", null]}, "shadow": false, "topLevel": false},
+            "r": {"opcode": "event_whenbackdropswitchesto", "next": "s", "parent": null, "inputs": {}, "fields": {"BACKDROP": ["backdrop1", null]}, "shadow": false, "topLevel": true},
+            "s": {"opcode": "looks_hide", "next": null, "parent": "r", "inputs": {}, "fields": {}, "shadow": false, "topLevel": false},
+            "t": {"opcode": "event_whenkeypressed", "next": "u", "parent": null, "inputs": {}, "fields": {"KEY_OPTION": ["any", null]}, "shadow": false, "topLevel": true},
+            "u": {"opcode": "looks_seteffectto", "next": null, "parent": "t", "inputs": {"VALUE": [1, [4, "25"]]}, "fields": {"EFFECT": ["GHOST", null]}, "shadow": false, "topLevel": false}
+        }}
+], "monitors": [], "extensions": [], "meta": {"semver": "3.0.0"}}"##;
+
+#[test]
+fn test_sb3_import() {
+    let sb3 = make_sb3(PROJECT, &[("abc.png", b"not really a png")]);
+    let res = Parser::default().parse_sb3("cats", &sb3).unwrap();
+    assert_eq!(res.untranslated, [
+        Untranslated { entity: "Cat".into(), opcode: "motion_glidesecstoxy".into(), block_id: "h".into() },
+        Untranslated { entity: "Cat".into(), opcode: "event_whenbackdropswitchesto".into(), block_id: "r".into() },
+    ]);
+
+    let role = &res.project.roles[0];
+    assert_eq!(role.name, "cats");
+    assert_eq!(role.globals.iter().map(|x| x.def.name.as_str()).collect::<Vec<_>>(), ["score", "items"]);
+    assert_eq!(role.entities.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["Stage", "Cat"]);
+
+    let cat = &role.entities[1];
+    assert_eq!(cat.fields[0].def.name, "speed");
+    assert_eq!((cat.pos, cat.heading, cat.scale, cat.visible), ((10.0, -20.0), 45.0, 0.5, false));
+    assert_eq!(cat.costumes.len(), 1);
+    assert_eq!(cat.costumes[0].def.name, "cat-a");
+    assert_eq!(cat.active_costume, Some(0));
+    assert!(cat.sounds.is_empty());
+
+    assert_eq!(cat.funcs.len(), 1);
+    assert_eq!(cat.funcs[0].name, "jump \t times");
+    assert!(matches!(&cat.funcs[0].stmts[0].kind, StmtKind::Warp { stmts } if matches!(stmts[0].kind, StmtKind::ChangeY { .. })));
+
+    // the script under the untranslated hat is omitted by default
+    assert_eq!(cat.scripts.len(), 2);
+    assert!(matches!(cat.scripts[0].hat.as_ref().unwrap().kind, HatKind::OnFlag));
+    let stmts = &cat.scripts[0].stmts;
+    assert!(matches!(&stmts[0].kind, StmtKind::Assign { var, .. } if var.name == "score" && var.location == VarLocation::Global));
+    match &stmts[1].kind {
+        StmtKind::Repeat { stmts, .. } => {
+            assert!(matches!(&stmts[0].kind, StmtKind::Forward { distance } if matches!(&distance.kind, ExprKind::Variable { var } if var.location == VarLocation::Field)));
+            assert!(matches!(&stmts[1].kind, StmtKind::AddAssign { value, .. } if matches!(&value.kind, ExprKind::Add { .. })));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert!(matches!(stmts[2].kind, StmtKind::GotoRandom));
+    assert!(matches!(&stmts[3].kind, StmtKind::UnknownBlock { name, args } if name == "motion_glidesecstoxy" && args.len() == 3));
+    assert!(matches!(&stmts[4].kind, StmtKind::CallFn { function, args, .. } if function.name == "jump \t times" && args.len() == 1));
+
+    assert!(matches!(cat.scripts[1].hat.as_ref().unwrap().kind, HatKind::OnKey { key: KeyCode::Any }));
+    assert!(matches!(cat.scripts[1].stmts[0].kind, StmtKind::SetEffect { kind: EffectKind::Ghost, .. }));
+
+    let mut parser = Parser::default();
+    parser.omit_nonhat_scripts = false;
+    let res = parser.parse_sb3("cats", &sb3).unwrap();
+    assert_eq!(res.project.roles[0].entities[1].scripts.len(), 3);
+}
+
+#[test]
+fn test_sb3_errors() {
+    assert!(matches!(Parser::default().parse_sb3("bad", b"not a zip"), Err(Sb3Error::Archive(_))));
+    assert!(matches!(Parser::default().parse_sb3("bad", &make_sb3("{", &[])), Err(Sb3Error::Json(_))));
+    assert!(matches!(Parser::default().parse_sb3("bad", &make_sb3(r#"{"targets": []}"#, &[])), Err(Sb3Error::Format { .. })));
+
+    let project = |blocks: &str| format!(r#"{{"targets": [{{"isStage": true, "name": "Stage", "costumes": [], "sounds": [], "blocks": {{{blocks}}}}}]}}"#);
+    let cycle = project(r#""a": {"opcode": "motion_movesteps", "next": "a", "parent": null, "inputs": {}, "fields": {}, "topLevel": true}"#);
+    assert!(matches!(project_json_to_xml("bad", &cycle, &mut |_| None), Err(Sb3Error::Format { .. })));
+    let cycle = project(r#""a": {"opcode": "motion_movesteps", "next": null, "parent": null, "inputs": {"STEPS": [3, "a", [4, "10"]]}, "fields": {}, "topLevel": true}"#);
+    assert!(matches!(project_json_to_xml("bad", &cycle, &mut |_| None), Err(Sb3Error::Format { .. })));
+
+    // malformed primitives are treated as empty inputs
+    let short = project(r#""a": {"opcode": "motion_movesteps", "next": null, "parent": null, "inputs": {"STEPS": [1, [4]]}, "fields": {}, "topLevel": true}"#);
+    let (xml, _) = project_json_to_xml("short", &short, &mut |_| None).unwrap();
+    assert!(xml.contains(r#"<script><block s="forward"><l></l></block></script>"#));
+}
+
+#[test]
+fn test_sb3_export() {
+    let sb3 = make_sb3(PROJECT, &[("abc.png", b"\x89PNG not really a png")]);
+    let role = &Parser::default().parse_sb3("cats", &sb3).unwrap().project.roles[0];
+    let res = role_to_sb3(role).unwrap();
+    assert_eq!(res.untranslated, [Untranslated { entity: "Cat".into(), opcode: "motion_glidesecstoxy".into(), block_id: "".into() }]);
+
+    let role = &Parser::default().parse_sb3("cats", &res.sb3).unwrap().project.roles[0];
+    assert_eq!(role.globals.iter().map(|x| x.def.name.as_str()).collect::<Vec<_>>(), ["score", "items"]);
+    let cat = &role.entities[1];
+    assert_eq!((cat.pos, cat.heading, cat.scale, cat.visible), ((10.0, -20.0), 45.0, 0.5, false));
+    assert_eq!(cat.costumes[0].def.name, "cat-a");
+    assert!(matches!(&cat.costumes[0].init, Value::Image(x) if x.0 == b"\x89PNG not really a png" && x.1 == Some((48.0, 50.0))));
+    assert_eq!(cat.funcs.len(), 1);
+    assert_eq!(cat.funcs[0].name, "jump \t times");
+    assert!(matches!(&cat.funcs[0].stmts[0].kind, StmtKind::Warp { stmts } if matches!(stmts[0].kind, StmtKind::ChangeY { .. })));
+
+    assert_eq!(cat.scripts.len(), 2);
+    let stmts = &cat.scripts[0].stmts;
+    assert_eq!(stmts.len(), 4);
+    assert!(matches!(&stmts[0].kind, StmtKind::Assign { var, .. } if var.name == "score" && var.location == VarLocation::Global));
+    match &stmts[1].kind {
+        StmtKind::Repeat { stmts, .. } => {
+            assert!(matches!(&stmts[0].kind, StmtKind::Forward { distance } if matches!(&distance.kind, ExprKind::Variable { var } if var.location == VarLocation::Field)));
+            assert!(matches!(&stmts[1].kind, StmtKind::AddAssign { value, .. } if matches!(&value.kind, ExprKind::Add { .. })));
+        }
+        x => panic!("{x:?}"),
+    }
+    assert!(matches!(stmts[2].kind, StmtKind::GotoRandom));
+    assert!(matches!(&stmts[3].kind, StmtKind::CallFn { function, args, .. } if function.name == "jump \t times" && args.len() == 1));
+    assert!(matches!(cat.scripts[1].hat.as_ref().unwrap().kind, HatKind::OnKey { key: KeyCode::Any }));
+
+    let script = format!(include_str!("script-template.xml"),
+        globals = "", fields = "", methods = "",
+        funcs = r#"<block-definition s="twice %&apos;x&apos;" type="reporter" category="custom"><header></header><code></code><translations></translations><inputs><input type="%n"></input></inputs><script><block s="doReport"><block s="reportVariadicProduct"><list><block var="x"/><l>2</l></list></block></block></script></block-definition><block-definition s="greet %&apos;who&apos;" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%s"></input></inputs><script><block s="bubble"><block s="reportJoinWords"><list><l>hi </l><block var="who"/></list></block></block></script></block-definition>"#,
+        scripts = r#"<script><block s="receiveGo"/><block s="doDeclareVariables"><list><l>t</l></list></block><block s="doSetVar"><l>t</l><custom-block s="twice %n"><l>3</l></custom-block></block><block s="down"/><block s="doBroadcast"><l>ping</l><list></list></block><custom-block s="greet %s"><l>world</l></custom-block></script><script><block s="receiveMessage"><l>ping</l></block><block s="doSayFor"><block s="reportJSFunction"><list></list><l>return 1;</l></block><l>1</l></block></script><script><block s="receiveCondition"><block s="reportBoolean"><l><bool>true</bool></l></block></block><block s="clear"/></script>"#,
+    );
+    let project = Parser::default().parse(&script).unwrap();
+    let res = role_to_sb3(&project.roles[0]).unwrap();
+    assert_eq!(res.untranslated.iter().map(|x| x.opcode.as_str()).collect::<Vec<_>>(), ["twice \t", "reportJSFunction", "receiveCondition"]);
+
+    let role = &Parser::default().parse_sb3("export", &res.sb3).unwrap().project.roles[0];
+    let stage = &role.entities[0];
+    // script variables are hoisted into the target, and stage variables are imported as globals
+    assert_eq!(role.globals.iter().map(|x| x.def.name.as_str()).collect::<Vec<_>>(), ["t"]);
+    assert_eq!(stage.funcs.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["greet \t"]);
+    assert_eq!(stage.scripts.len(), 2);
+    assert!(matches!(&stage.scripts[0].stmts[1].kind, StmtKind::SetPenDown { value: true }));
+    assert!(matches!(&stage.scripts[0].stmts[2].kind, StmtKind::SendLocalMessage { msg_type, wait: false, .. } if matches!(&msg_type.kind, ExprKind::Value(x) if x.as_str() == Some("ping"))));
+    assert!(matches!(&stage.scripts[0].stmts[3].kind, StmtKind::CallFn { function, .. } if function.name == "greet \t"));
+    assert!(matches!(&stage.scripts[1].hat.as_ref().unwrap().kind, HatKind::LocalMessage { msg_type: Some(x) } if x == "ping"));
+    assert!(matches!(&stage.scripts[1].stmts[0].kind, StmtKind::Say { duration: Some(_), .. }));
+}
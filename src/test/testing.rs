@@ -0,0 +1,28 @@
+use crate::*;
+use crate::testing::*;
+
+#[test]
+fn test_sample_projects() {
+    assert!(!SAMPLE_PROJECTS.is_empty());
+    for (name, xml) in SAMPLE_PROJECTS {
+        let project = assert_parse_stable(&Parser::default(), xml);
+        assert!(!project.roles.is_empty(), "{name}");
+    }
+}
+
+#[test]
+fn test_normalize() {
+    let script = |collab_id: &str| format!(include_str!("script-template.xml"),
+        globals = "", fields = "",
+        funcs = "", methods = "",
+        scripts = format!(r#"<script><block s="receiveGo"/><block collabId="{collab_id}" s="forward"><l>5</l></block></script>"#),
+    );
+    let mut a = Parser::default().parse(&script("item_1")).unwrap();
+    let mut b = Parser::default().parse(&script("item_2")).unwrap();
+    assert_ne!(a, b);
+    normalize(&mut a);
+    normalize(&mut b);
+    assert_eq!(a, b);
+    assert_eq!(a.roles[0].source_hash, None);
+    assert_eq!(a.roles[0].entities[0].scripts[0].stmts[0].info.id, NodeId::default());
+}
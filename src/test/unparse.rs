@@ -0,0 +1,58 @@
+use alloc::vec::Vec;
+use crate::*;
+
+fn parse_project(methods: &str, scripts: &str) -> Project {
+    let script = format!(include_str!("script-template.xml"),
+        globals = r#"<variable name="g"><l>0</l></variable>"#, fields = "", funcs = "", methods = methods,
+        scripts = scripts,
+    ).replace("<messageTypes></messageTypes>", "<messageTypes><messageType><name>pong</name><fields><field>msg</field></fields></messageType></messageTypes>");
+    Parser::default().parse(&script).unwrap()
+}
+fn parse_scripts(scripts: &str) -> Vec<Script> {
+    core::mem::take(&mut parse_project("", scripts).roles[0].entities[0].scripts)
+}
+fn assert_round_trip(scripts: &str) {
+    let original = parse_scripts(scripts);
+    let xml: CompactString = original.iter().map(|x| x.to_xml()).collect();
+    let reparsed = parse_scripts(&xml);
+    assert_eq!(original, reparsed, "{xml}");
+    assert_eq!(reparsed.iter().map(|x| x.to_xml()).collect::<CompactString>(), xml);
+}
+
+#[test]
+fn test_to_xml_simple() {
+    let scripts = parse_scripts(r#"<script><block s="receiveGo"/><block s="doSetVar"><l>g</l><l>5 &lt; 6</l></block><block s="doIfElse"><block s="reportLessThan"><block var="g"/><l>3</l></block><script><block s="forward"><l>10</l></block></script><script></script></block></script>"#);
+    assert_eq!(scripts[0].to_xml(), r#"<script><block s="receiveGo"></block><block s="doSetVar"><l>g</l><l>5 &lt; 6</l></block><block s="doIfElse"><block s="reportLessThan"><block var="g"></block><l>3</l></block><script><block s="forward"><l>10</l></block></script><script></script></block></script>"#);
+}
+
+#[test]
+fn test_to_xml_round_trip() {
+    assert_round_trip(r#"<script><block s="receiveKey"><l><option>up arrow</option></l></block><block s="doDeclareVariables"><list><l>a</l><l>b</l></list></block><block s="doSetVar" collabId="item_7"><l>a</l><block s="reportVariadicSum"><list><l>1</l><block var="g"/><l>-2.5</l></list></block><comment w="90" collapsed="false">add them up</comment></block><block s="doRepeat"><l>4</l><script><block s="doChangeVar"><l>b</l><block s="reportMonadic"><l><option>sqrt</option></l><block var="a"/></block></block><block s="doInsertInList"><block var="b"/><l><option>random</option></l><block var="g"/></block></script></block><block s="doSayFor"><block s="reportJoinWords"><list><l>hello</l><l>world</l></list></block><l>2</l></block><block s="doForEach"><l>item</l><block s="reportNewList"><list><l>x</l><l><bool>true</bool></l></list></block><script><block s="doIf"><block s="reportAnd"><block s="reportIsA"><block var="item"/><l><option>Boolean</option></l></block><block s="reportListContainsItem"><block var="g"/><block var="item"/></block></block><script><block s="doStopThis"><l><option>this script</option></l></block></script></block></script></block></script>"#);
+    assert_round_trip(r#"<script><block s="receiveMessage"><l><option>any message</option></l></block><block s="doWarp"><script><block s="doTryCatch"><script><block s="doThrow"><l>oops</l></block></script><l>err</l><script><block s="doSetVar"><l>g</l><block s="reportListItem"><l><option>last</option></l><block s="reportTextSplit"><block var="err"/><l><option>word</option></l></block></block></block></script></block></script></block><block s="doRun"><block s="reifyScript"><script><block s="setColor"><color>255,0,10,0.5</color></block></script><list><l>x</l></list></block><list><l>3</l></list></block><block s="doSetVar"><l>g</l><block s="reportMap"><block s="reifyReporter"><autolambda><block s="reportMonadic"><l><option>ln</option></l><l></l></block></autolambda><list></list></block><block var="g"/></block></block><block s="doGotoObject"><l><option>random position</option></l></block><block s="createClone"><l><option>myself</option></l></block></script><script><block s="receiveSocketMessage"><l>ping</l><l>value</l></block><block s="doSocketMessage"><l>pong</l><block var="value"/><l><option>everyone in room</option></l></block><block s="doRunRPC" inputNames="row;column"><l>TicTacToe</l><l>play</l><l>1</l><l>2</l></block></script>"#);
+    assert_round_trip(r#"<script><block s="receiveInteraction"><l><option>clicked</option></l></block><block s="setEffect"><l><option>ghost</option></l><l>50</l></block><block s="doSetGlobalFlag"><l><option>turbo mode</option></l><l><bool>true</bool></l></block><block s="doSayFor"><block s="reportGetImageAttribute"><l><option>width</option></l><l><option>current</option></l></block><block s="reportDate"><l><option>day of week</option></l></block></block><block s="bubble"><block s="reportTextFunction"><l><option>upper case</option></l><block s="reportGetSoundAttribute"><l><option>sample rate</option></l><l></l></block></block></block><block s="doFaceTowards"><l><option>center</option></l></block><block s="doSwitchToCostume"><l><option>Turtle</option></l></block></script>"#);
+}
+
+#[test]
+fn test_to_xml_custom_blocks() {
+    let methods = r#"<block-definition s="run %'expr' if %'conds' then %'f' as %'item'" type="command" category="custom"><header></header><code></code><translations></translations><inputs><input type="%anyUE"></input><input type="%mult%boolUE"></input><input type="%repRing"></input><input type="%upvar"></input></inputs></block-definition>"#;
+    let scripts = r#"<script><block s="receiveGo"/><custom-block s="run %anyUE if %mult%boolUE then %repRing as %upvar" scope="local"><block s="reportVariadicSum"><list><block var="g"/><l>1</l></list></block><list><block s="reportLessThan"><block var="g"/><l>3</l></block><l><bool>true</bool></l></list><block s="reifyReporter"><autolambda><block var="g"/></autolambda><list></list></block><l>item</l></custom-block><block s="bubble"><block var="item"/></block></script>"#;
+    let original = parse_project(methods, scripts);
+    let (role, entity) = (&original.roles[0], &original.roles[0].entities[0]);
+    let xml = entity.scripts[0].to_xml_in(role, entity);
+    assert!(xml.contains(r#"<custom-block s="run %anyUE if %mult%boolUE then %repRing as %upvar" scope="local">"#), "{xml}");
+
+    let reparsed = parse_project(methods, &xml);
+    assert_eq!(entity.scripts, reparsed.roles[0].entities[0].scripts, "{xml}");
+    assert_eq!(reparsed.roles[0].entities[0].scripts[0].to_xml_in(&reparsed.roles[0], &reparsed.roles[0].entities[0]), xml);
+
+    match &entity.scripts[0].stmts[0].kind {
+        StmtKind::CallFn { function, args, upvars } => {
+            assert_eq!(function.location, FnLocation::Method);
+            assert!(matches!(&args[0].kind, ExprKind::Closure { params, .. } if params.is_empty()));
+            assert!(matches!(&args[1].kind, ExprKind::MakeList { values } if values.len() == 2 && values.iter().all(|x| matches!(x.kind, ExprKind::Closure { .. }))));
+            assert!(matches!(&args[2].kind, ExprKind::Closure { .. }));
+            assert_eq!(upvars[0].name, "item");
+        }
+        x => panic!("{x:?}"),
+    }
+}
@@ -0,0 +1,48 @@
+//! Utilities for writing tests against this crate, such as a corpus of sample projects and helpers for comparing ASTs.
+//!
+//! This module is only available with the `testing` feature.
+//! Note that there is currently no serializer, so stability is checked by reparsing rather than by round-tripping through xml.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Sample projects bundled with the crate, as pairs of file name and xml source.
+/// These cover a range of features (media, variable scoping, role and project exports, deeply nested code, etc.),
+/// and all of them can be parsed by [`Parser::default`].
+pub const SAMPLE_PROJECTS: &[(&str, &str)] = &[
+    ("capture-scopes.xml", include_str!("test/projects/capture-scopes.xml")),
+    ("field-refs.xml", include_str!("test/projects/field-refs.xml")),
+    ("media.xml", include_str!("test/projects/media.xml")),
+    ("project-export.xml", include_str!("test/projects/project-export.xml")),
+    ("raw-role-export.xml", include_str!("test/projects/raw-role-export.xml")),
+    ("role-export.xml", include_str!("test/projects/role-export.xml")),
+    ("stack-size-1.xml", include_str!("test/projects/stack-size-1.xml")),
+    ("stack-size-2.xml", include_str!("test/projects/stack-size-2.xml")),
+    ("stack-size-3.xml", include_str!("test/projects/stack-size-3.xml")),
+    ("unevaluated.xml", include_str!("test/projects/unevaluated.xml")),
+];
+
+/// Re-exported from the crate root, where it lives alongside [`semantically_eq`](crate::semantically_eq).
+pub use crate::normalize;
+
+/// Parses `xml` and asserts that the result is stable, returning the parsed project.
+///
+/// This checks that parsing the same source twice gives equal projects, and that [`Parser::reparse`] (which reuses unchanged roles) agrees with a fresh parse.
+///
+/// # Panics
+/// Panics if parsing fails or if any of the results differ.
+pub fn assert_parse_stable<T: NameTransformer>(parser: &Parser<T>, xml: &str) -> Project {
+    let first = parser.parse(xml).unwrap_or_else(|e| panic!("failed to parse: {e:?}"));
+    let second = parser.parse(xml).unwrap_or_else(|e| panic!("failed to parse: {e:?}"));
+    assert_eq!(first, second, "parsing is not deterministic");
+
+    // only reparse computes the hashes which allow roles to be reused
+    let empty = Project { name: first.name.clone(), meta: first.meta.clone(), roles: vec![] };
+    let (fresh, _) = parser.reparse(&empty, xml).unwrap_or_else(|e| panic!("failed to reparse: {e:?}"));
+    let unhashed: Vec<_> = fresh.roles.iter().map(|x| Role { source_hash: None, ..x.clone() }).collect();
+    assert_eq!(first.roles, unhashed, "reparsing does not match parsing");
+    let (reparsed, _) = parser.reparse(&fresh, xml).unwrap_or_else(|e| panic!("failed to reparse: {e:?}"));
+    assert_eq!(fresh, reparsed, "reparsing does not match parsing");
+    first
+}
@@ -0,0 +1,683 @@
+//! Conversion of code back into Snap XML (see [`Script::to_xml`]).
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::*;
+use crate::util::*;
+
+fn key_name(key: &KeyCode) -> CompactString {
+    match key {
+        KeyCode::Char(c) => format_compact!("{c}"),
+        KeyCode::Up => "up arrow".into(),
+        KeyCode::Down => "down arrow".into(),
+        KeyCode::Left => "left arrow".into(),
+        KeyCode::Right => "right arrow".into(),
+        KeyCode::Space => "space".into(),
+        KeyCode::Enter => "enter".into(),
+        KeyCode::Any => "any key".into(),
+        KeyCode::Other(x) => x.clone(),
+    }
+}
+fn effect_name(kind: &EffectKind) -> &str {
+    match kind {
+        EffectKind::Color => "color",
+        EffectKind::Saturation => "saturation",
+        EffectKind::Brightness => "brightness",
+        EffectKind::Ghost => "ghost",
+        EffectKind::Fisheye => "fisheye",
+        EffectKind::Whirl => "whirl",
+        EffectKind::Pixelate => "pixelate",
+        EffectKind::Mosaic => "mosaic",
+        EffectKind::Negative => "negative",
+        EffectKind::Other(x) => x,
+    }
+}
+fn pen_attr_name(attr: &PenAttribute) -> &str {
+    match attr {
+        PenAttribute::Size => "size",
+        PenAttribute::Hue => "hue",
+        PenAttribute::Saturation => "saturation",
+        PenAttribute::Brightness => "brightness",
+        PenAttribute::Transparency => "transparency",
+        PenAttribute::Other(x) => x,
+    }
+}
+fn global_flag_name(flag: &GlobalFlag) -> &str {
+    match flag {
+        GlobalFlag::TurboMode => "turbo mode",
+        GlobalFlag::FlatLineEnds => "flat line ends",
+        GlobalFlag::LogPenVectors => "log pen vectors",
+        GlobalFlag::VideoCapture => "video capture",
+        GlobalFlag::MirrorVideo => "mirror video",
+        GlobalFlag::Other(x) => x,
+    }
+}
+fn text_function_name(function: &TextFunction) -> &str {
+    match function {
+        TextFunction::Upper => "upper case",
+        TextFunction::Lower => "lower case",
+        TextFunction::Trim => "trim",
+        TextFunction::EncodeUri => "encode URI",
+        TextFunction::DecodeUri => "decode URI",
+        TextFunction::EncodeUriComponent => "encode URI component",
+        TextFunction::DecodeUriComponent => "decode URI component",
+        TextFunction::XmlEscape => "XML escape",
+        TextFunction::XmlUnescape => "XML unescape",
+        TextFunction::JsEscape => "JS escape",
+        TextFunction::HexSha512 => "hex sha512 hash",
+        TextFunction::Other(x) => x,
+    }
+}
+fn value_type_name(ty: &ValueType) -> &str {
+    match ty {
+        ValueType::Number => "number",
+        ValueType::Text => "text",
+        ValueType::Bool => "Boolean",
+        ValueType::List => "list",
+        ValueType::Sprite => "sprite",
+        ValueType::Costume => "costume",
+        ValueType::Sound => "sound",
+        ValueType::Command => "command",
+        ValueType::Reporter => "reporter",
+        ValueType::Predicate => "predicate",
+        ValueType::Other(x) => x,
+    }
+}
+fn time_query_name(query: &TimeQuery) -> &str {
+    match query {
+        TimeQuery::Year => "year",
+        TimeQuery::Month => "month",
+        TimeQuery::Date => "date",
+        TimeQuery::DayOfWeek => "day of week",
+        TimeQuery::Hour => "hour",
+        TimeQuery::Minute => "minute",
+        TimeQuery::Second => "second",
+        TimeQuery::UnixTimestampMs => "time in milliseconds",
+        TimeQuery::Other(x) => x,
+    }
+}
+fn stop_mode_name(mode: &StopMode) -> &str {
+    match mode {
+        StopMode::All => "all",
+        StopMode::AllScenes => "all scenes",
+        StopMode::ThisScript => "this script",
+        StopMode::ThisBlock => "this block",
+        StopMode::AllButThisScript => "all but this script",
+        StopMode::OtherScriptsInSprite => "other scripts in sprite",
+    }
+}
+
+fn input_type_code(ty: &InputType) -> &str {
+    match ty {
+        InputType::Any => "%s",
+        InputType::Number => "%n",
+        InputType::Bool => "%b",
+        InputType::List => "%l",
+        InputType::Text => "%txt",
+        InputType::MultilineText => "%mlt",
+        InputType::Code => "%code",
+        InputType::Object => "%obj",
+        InputType::UnevaluatedAny => "%anyUE",
+        InputType::UnevaluatedBool => "%boolUE",
+        InputType::CommandRing => "%cmdRing",
+        InputType::ReporterRing => "%repRing",
+        InputType::PredicateRing => "%predRing",
+        InputType::Command => "%cmd",
+        InputType::CommandSlot => "%cs",
+        InputType::LoopSlot => "%ca",
+        InputType::Upvar => "%upvar",
+        InputType::Other(x) => x,
+    }
+}
+
+struct Writer<'a> {
+    out: String,
+    /// The global and sprite-local custom block definitions used to look up the parameters of custom block calls.
+    funcs: &'a [Function],
+    methods: &'a [Function],
+}
+impl Writer<'_> {
+    fn open(&mut self, s: &str, info: &BlockInfo) {
+        write!(self.out, "<block s=\"{}\"", xml_escape(s)).unwrap();
+        if let Some(id) = &info.location {
+            write!(self.out, " collabId=\"{}\"", xml_escape(id)).unwrap();
+        }
+        self.out.push('>');
+    }
+    fn close(&mut self, info: &BlockInfo) {
+        if let Some(comment) = &info.comment {
+            write!(self.out, "<comment w=\"90\" collapsed=\"false\">{}</comment>", xml_escape(comment)).unwrap();
+        }
+        self.out.push_str("</block>");
+    }
+    fn block(&mut self, s: &str, info: &BlockInfo, inputs: impl FnOnce(&mut Self)) {
+        self.open(s, info);
+        inputs(self);
+        self.close(info);
+    }
+    fn text(&mut self, text: &str) {
+        write!(self.out, "<l>{}</l>", xml_escape(text)).unwrap();
+    }
+    fn option(&mut self, option: &str) {
+        write!(self.out, "<l><option>{}</option></l>", xml_escape(option)).unwrap();
+    }
+    /// Writes a list of names, as used for script variables and ring parameters.
+    fn names<'a>(&mut self, names: impl Iterator<Item = &'a CompactString>) {
+        self.out.push_str("<list>");
+        for name in names { self.text(name) }
+        self.out.push_str("</list>");
+    }
+    /// Writes a list of inputs, as used for variadic slots and the arguments of rings.
+    fn inputs<'a>(&mut self, exprs: impl Iterator<Item = &'a Expr>) {
+        self.out.push_str("<list>");
+        for expr in exprs { self.expr(expr) }
+        self.out.push_str("</list>");
+    }
+    fn script(&mut self, stmts: &[Stmt]) {
+        self.out.push_str("<script>");
+        for stmt in stmts { self.stmt(stmt) }
+        self.out.push_str("</script>");
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Bool(x) => write!(self.out, "<l><bool>{x}</bool></l>").unwrap(),
+            Value::Number(_) | Value::Constant(_) => self.text(&value.to_snap_string()),
+            Value::String(x) => self.text(x),
+            Value::SharedString(x) => self.text(x),
+            Value::Color { r, g, b, a } => write!(self.out, "<color>{r},{g},{b},{}</color>", Value::from(*a as f64 / 255.0)).unwrap(),
+            Value::List(values, _) => self.list(values.iter()),
+            Value::RawList(list, _) => self.list(list.to_values().iter()),
+            Value::Image(_) | Value::Audio(_) | Value::Ref(_) => self.out.push_str("<l></l>"),
+        }
+    }
+    fn list<'a>(&mut self, values: impl Iterator<Item = &'a Value>) {
+        self.out.push_str("<block s=\"reportNewList\"><list>");
+        for value in values { self.value(value) }
+        self.out.push_str("</list></block>");
+    }
+    /// Writes the input of a variadic slot, which is a list of inputs unless some other reporter was dropped onto the slot.
+    fn variadic(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::MakeList { values } if expr.info.opcode.is_none() && expr.info.comment.is_none() => self.inputs(values.iter()),
+            ExprKind::Value(Value::List(values, None)) => {
+                self.out.push_str("<list>");
+                for value in values { self.value(value) }
+                self.out.push_str("</list>");
+            }
+            _ => self.expr(expr),
+        }
+    }
+    /// Writes the input of a slot which accepts a sprite name from a dropdown, as in [`ExprKind::This`] and [`ExprKind::Entity`].
+    fn entity(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::This => self.option("myself"),
+            ExprKind::Entity { name, .. } => self.text(name),
+            _ => self.expr(expr),
+        }
+    }
+    fn rpc(&mut self, s: &str, info: &BlockInfo, host: &Option<CompactString>, service: &str, rpc: &str, args: &[(CompactString, Expr)]) {
+        write!(self.out, "<block s=\"{s}\" inputNames=\"").unwrap();
+        for (name, _) in args {
+            write!(self.out, "{};", xml_escape(name)).unwrap();
+        }
+        self.out.push('"');
+        if let Some(id) = &info.location {
+            write!(self.out, " collabId=\"{}\"", xml_escape(id)).unwrap();
+        }
+        self.out.push('>');
+        match host {
+            Some(host) => self.text(&format_compact!("{host}/{service}")),
+            None => self.text(service),
+        }
+        self.text(rpc);
+        for (_, arg) in args { self.expr(arg) }
+        self.close(info);
+    }
+    /// Writes the input of an unevaluated slot (`%anyUE` or `%boolUE`), which the parser implicitly wraps in a ring.
+    fn unevaluated(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Closure { kind: ClosureKind::Reporter, params, stmts, .. } if params.is_empty() && expr.info.location.is_none() && expr.info.comment.is_none() => match stmts.as_slice() {
+                [Stmt { kind: StmtKind::Return { value }, .. }] => self.expr(value),
+                _ => self.expr(expr),
+            }
+            _ => self.expr(expr),
+        }
+    }
+    fn call_fn(&mut self, function: &FnRef, args: &[Expr], info: &BlockInfo) {
+        let funcs = match function.location {
+            FnLocation::Global => self.funcs,
+            FnLocation::Method => self.methods,
+        };
+        // if the definition is not known, every input is written as an evaluated any-type slot
+        let params = funcs.iter().find(|x| x.name == function.name).map(|x| x.param_info.as_slice()).filter(|x| x.len() == args.len()).unwrap_or_default();
+
+        let mut s = String::new();
+        for (i, part) in function.name.split('\t').enumerate() {
+            if i != 0 {
+                match params.get(i - 1) {
+                    Some(param) => {
+                        if param.kind == ParamKind::Variadic { s.push_str("%mult") }
+                        s.push_str(input_type_code(&param.input_type));
+                    }
+                    None => s.push_str("%s"),
+                }
+            }
+            s.push_str(part);
+        }
+        write!(self.out, "<custom-block s=\"{}\"", xml_escape(&s)).unwrap();
+        if function.location == FnLocation::Method {
+            self.out.push_str(" scope=\"local\"");
+        }
+        if let Some(id) = &info.location {
+            write!(self.out, " collabId=\"{}\"", xml_escape(id)).unwrap();
+        }
+        self.out.push('>');
+        for (i, arg) in args.iter().enumerate() {
+            let unevaluated = params.get(i).map(|x| matches!(x.input_type, InputType::UnevaluatedAny | InputType::UnevaluatedBool)).unwrap_or(false);
+            match (params.get(i).map(|x| &x.kind), unevaluated, &arg.kind) {
+                (Some(ParamKind::Variadic), true, ExprKind::MakeList { values }) if arg.info.opcode.is_none() && arg.info.comment.is_none() => {
+                    self.out.push_str("<list>");
+                    for value in values { self.unevaluated(value) }
+                    self.out.push_str("</list>");
+                }
+                (Some(ParamKind::Variadic), true, _) => self.expr(arg),
+                (Some(ParamKind::Variadic), false, _) => self.variadic(arg),
+                (_, true, _) => self.unevaluated(arg),
+                (_, false, _) => self.expr(arg),
+            }
+        }
+        if let Some(comment) = &info.comment {
+            write!(self.out, "<comment w=\"90\" collapsed=\"false\">{}</comment>", xml_escape(comment)).unwrap();
+        }
+        self.out.push_str("</custom-block>");
+    }
+    fn message(&mut self, s: &str, info: &BlockInfo, target: &Expr, msg_type: &str, values: &[(CompactString, Expr)]) {
+        self.block(s, info, |w| {
+            w.text(msg_type);
+            for (_, value) in values { w.expr(value) }
+            match &target.kind {
+                ExprKind::Value(x) if x.as_str().is_some() => w.option(x.as_str().unwrap_or_default()),
+                _ => w.expr(target),
+            }
+        });
+    }
+
+    fn hat(&mut self, hat: &Hat) {
+        let info = &hat.info;
+        match &hat.kind {
+            HatKind::OnFlag => self.block("receiveGo", info, |_| ()),
+            HatKind::OnClone => self.block("receiveOnClone", info, |_| ()),
+            HatKind::OnKey { key } => self.block("receiveKey", info, |w| w.option(&key_name(key))),
+            HatKind::MouseDown => self.block("receiveInteraction", info, |w| w.option("pressed")),
+            HatKind::MouseUp => self.block("receiveInteraction", info, |w| w.option("clicked")),
+            HatKind::MouseEnter => self.block("receiveInteraction", info, |w| w.option("mouse-entered")),
+            HatKind::MouseLeave => self.block("receiveInteraction", info, |w| w.option("mouse-departed")),
+            HatKind::ScrollUp => self.block("receiveInteraction", info, |w| w.option("scrolled-up")),
+            HatKind::ScrollDown => self.block("receiveInteraction", info, |w| w.option("scrolled-down")),
+            HatKind::Dropped => self.block("receiveInteraction", info, |w| w.option("dropped")),
+            HatKind::Stopped => self.block("receiveInteraction", info, |w| w.option("stopped")),
+            HatKind::When { condition } => self.block("receiveCondition", info, |w| w.expr(condition)),
+            HatKind::LocalMessage { msg_type } => self.block("receiveMessage", info, |w| match msg_type {
+                Some(x) => w.text(x),
+                None => w.option("any message"),
+            }),
+            HatKind::NetworkMessage { msg_type, fields } => self.block("receiveSocketMessage", info, |w| {
+                w.text(msg_type);
+                for field in fields { w.text(&field.name) }
+            }),
+            HatKind::Unknown { name, fields } => self.block(name, info, |w| for field in fields { w.text(&field.name) }),
+            HatKind::Custom { name, args, .. } => self.block(name, info, |w| for arg in args { w.value(arg) }),
+        }
+    }
+    fn stmt(&mut self, stmt: &Stmt) {
+        let info = &stmt.info;
+        match &stmt.kind {
+            StmtKind::DeclareLocals { vars } => self.block("doDeclareVariables", info, |w| w.names(vars.iter().map(|x| &x.name))),
+            StmtKind::Assign { var, value } => self.block("doSetVar", info, |w| { w.text(&var.name); w.expr(value); }),
+            StmtKind::AddAssign { var, value } => self.block("doChangeVar", info, |w| { w.text(&var.name); w.expr(value); }),
+            StmtKind::ShowVar { var } => self.block("doShowVar", info, |w| w.text(&var.name)),
+            StmtKind::HideVar { var } => self.block("doHideVar", info, |w| w.text(&var.name)),
+            StmtKind::Warp { stmts } => self.block("doWarp", info, |w| w.script(stmts)),
+            StmtKind::InfLoop { stmts } => self.block("doForever", info, |w| w.script(stmts)),
+            StmtKind::ForeachLoop { var, items, stmts } => self.block("doForEach", info, |w| { w.text(&var.name); w.expr(items); w.script(stmts); }),
+            StmtKind::ForLoop { var, start, stop, stmts } => self.block("doFor", info, |w| { w.text(&var.name); w.expr(start); w.expr(stop); w.script(stmts); }),
+            StmtKind::UntilLoop { condition, stmts } => self.block("doUntil", info, |w| { w.expr(condition); w.script(stmts); }),
+            StmtKind::Repeat { times, counter, stmts } => self.block("doRepeat", info, |w| {
+                w.expr(times);
+                if let Some(counter) = counter { w.text(&counter.name) }
+                w.script(stmts);
+            }),
+            StmtKind::If { condition, then } => self.block("doIf", info, |w| { w.expr(condition); w.script(then); }),
+            StmtKind::IfElse { condition, then, otherwise } => self.block("doIfElse", info, |w| { w.expr(condition); w.script(then); w.script(otherwise); }),
+            StmtKind::TryCatch { code, var, handler } => self.block("doTryCatch", info, |w| { w.script(code); w.text(&var.name); w.script(handler); }),
+            StmtKind::Throw { error } => self.block("doThrow", info, |w| w.expr(error)),
+            StmtKind::ListInsert { list, value, index } => self.block("doInsertInList", info, |w| { w.expr(value); w.expr(index); w.expr(list); }),
+            StmtKind::ListInsertLast { list, value } => self.block("doAddToList", info, |w| { w.expr(value); w.expr(list); }),
+            StmtKind::ListInsertRandom { list, value } => self.block("doInsertInList", info, |w| { w.expr(value); w.option("random"); w.expr(list); }),
+            StmtKind::ListRemove { list, index } => self.block("doDeleteFromList", info, |w| { w.expr(index); w.expr(list); }),
+            StmtKind::ListRemoveLast { list } => self.block("doDeleteFromList", info, |w| { w.option("last"); w.expr(list); }),
+            StmtKind::ListRemoveAll { list } => self.block("doDeleteFromList", info, |w| { w.option("all"); w.expr(list); }),
+            StmtKind::ListAssign { list, value, index } => self.block("doReplaceInList", info, |w| { w.expr(index); w.expr(list); w.expr(value); }),
+            StmtKind::ListAssignLast { list, value } => self.block("doReplaceInList", info, |w| { w.option("last"); w.expr(list); w.expr(value); }),
+            StmtKind::ListAssignRandom { list, value } => self.block("doReplaceInList", info, |w| { w.option("random"); w.expr(list); w.expr(value); }),
+            StmtKind::Return { value } => self.block("doReport", info, |w| w.expr(value)),
+            StmtKind::Sleep { seconds } => self.block("doWait", info, |w| w.expr(seconds)),
+            StmtKind::WaitUntil { condition } => self.block("doWaitUntil", info, |w| w.expr(condition)),
+            StmtKind::SetCostume { costume } => self.block("doSwitchToCostume", info, |w| match &costume.kind {
+                ExprKind::Value(x) if x.as_str() == Some("") => w.option("Turtle"),
+                _ => w.expr(costume),
+            }),
+            StmtKind::NextCostume => self.block("doWearNextCostume", info, |_| ()),
+            StmtKind::PlaySound { sound, blocking } => self.block(if *blocking { "doPlaySoundUntilDone" } else { "playSound" }, info, |w| w.expr(sound)),
+            StmtKind::PlayNotes { notes, beats, .. } => self.block("doPlayNote", info, |w| { w.expr(notes); w.expr(beats); }),
+            StmtKind::Rest { beats } => self.block("doRest", info, |w| w.expr(beats)),
+            StmtKind::StopSounds => self.block("doStopAllSounds", info, |_| ()),
+            StmtKind::Forward { distance } => self.block("forward", info, |w| w.expr(distance)),
+            StmtKind::SetX { value } => self.block("setXPosition", info, |w| w.expr(value)),
+            StmtKind::ChangeX { delta } => self.block("changeXPosition", info, |w| w.expr(delta)),
+            StmtKind::SetY { value } => self.block("setYPosition", info, |w| w.expr(value)),
+            StmtKind::ChangeY { delta } => self.block("changeYPosition", info, |w| w.expr(delta)),
+            StmtKind::GotoXY { x, y } => self.block("gotoXY", info, |w| { w.expr(x); w.expr(y); }),
+            StmtKind::GotoMouse => self.block("doGotoObject", info, |w| w.option("mouse-pointer")),
+            StmtKind::GotoRandom => self.block("doGotoObject", info, |w| w.option("random position")),
+            StmtKind::Goto { target } => self.block("doGotoObject", info, |w| w.entity(target)),
+            StmtKind::PointTowards { target } => self.block("doFaceTowards", info, |w| w.entity(target)),
+            StmtKind::PointTowardsXY { x, y } if [x, y].iter().all(|v| matches!(&v.kind, ExprKind::Value(Value::Number(n)) if *n == 0.0)) => self.block("doFaceTowards", info, |w| w.option("center")),
+            // snap can face towards a list of coordinates, but has no block which takes them separately
+            StmtKind::PointTowardsXY { x, y } => self.block("doFaceTowards", info, |w| {
+                w.out.push_str("<block s=\"reportNewList\">");
+                w.inputs([&**x, &**y].into_iter());
+                w.out.push_str("</block>");
+            }),
+            StmtKind::TurnRight { angle } => self.block("turn", info, |w| w.expr(angle)),
+            StmtKind::TurnLeft { angle } => self.block("turnLeft", info, |w| w.expr(angle)),
+            StmtKind::SetHeading { value } => self.block("setHeading", info, |w| w.expr(value)),
+            StmtKind::SetHeadingRandom => self.block("setHeading", info, |w| w.option("random")),
+            StmtKind::BounceOffEdge => self.block("bounceOffEdge", info, |_| ()),
+            StmtKind::SetPenDown { value } => self.block(if *value { "down" } else { "up" }, info, |_| ()),
+            StmtKind::PenClear => self.block("clear", info, |_| ()),
+            StmtKind::Stamp => self.block("doStamp", info, |_| ()),
+            StmtKind::Write { content, font_size } => self.block("write", info, |w| { w.expr(content); w.expr(font_size); }),
+            StmtKind::SetPenColor { color: (r, g, b, a) } => self.block("setColor", info, |w| w.value(&Value::Color { r: *r, g: *g, b: *b, a: *a })),
+            StmtKind::Say { content, duration } => match duration {
+                Some(duration) => self.block("doSayFor", info, |w| { w.expr(content); w.expr(duration); }),
+                None => self.block("bubble", info, |w| w.expr(content)),
+            }
+            StmtKind::Think { content, duration } => match duration {
+                Some(duration) => self.block("doThinkFor", info, |w| { w.expr(content); w.expr(duration); }),
+                None => self.block("doThink", info, |w| w.expr(content)),
+            }
+            StmtKind::SetVisible { value } => self.block(if *value { "show" } else { "hide" }, info, |_| ()),
+            StmtKind::ChangeSize { delta } => self.block("changeScale", info, |w| w.expr(delta)),
+            StmtKind::SetSize { value } => self.block("setScale", info, |w| w.expr(value)),
+            StmtKind::ChangePenSize { delta } => self.block("changeSize", info, |w| w.expr(delta)),
+            StmtKind::SetPenSize { value } => self.block("setSize", info, |w| w.expr(value)),
+            StmtKind::CallRpc { host, service, rpc, args } => self.rpc("doRunRPC", info, host, service, rpc, args),
+            StmtKind::CallFn { function, args, .. } => self.call_fn(function, args, info),
+            StmtKind::CallClosure { new_entity, closure, args } => match new_entity {
+                Some(entity) => self.block("doTellTo", info, |w| { w.entity(entity); w.expr(closure); w.inputs(args.iter()); }),
+                None => self.block("doRun", info, |w| { w.expr(closure); w.inputs(args.iter()); }),
+            }
+            StmtKind::ForkClosure { closure, args } => self.block("fork", info, |w| { w.expr(closure); w.inputs(args.iter()); }),
+            StmtKind::Clone { target } => self.block("createClone", info, |w| w.entity(target)),
+            StmtKind::DeleteClone => self.block("removeClone", info, |_| ()),
+            StmtKind::SendLocalMessage { target, msg_type, wait } => match target {
+                Some(target) => self.block("doSend", info, |w| { w.expr(msg_type); w.entity(target); }),
+                None => self.block(if *wait { "doBroadcastAndWait" } else { "doBroadcast" }, info, |w| w.expr(msg_type)),
+            }
+            StmtKind::SendNetworkMessage { target, msg_type, values } => self.message("doSocketMessage", info, target, msg_type, values),
+            StmtKind::SendNetworkReply { value } => self.block("doSocketResponse", info, |w| w.expr(value)),
+            StmtKind::Ask { prompt } => self.block("doAsk", info, |w| w.expr(prompt)),
+            StmtKind::ResetTimer => self.block("doResetTimer", info, |_| ()),
+            StmtKind::Pause => self.block("doPauseAll", info, |_| ()),
+            StmtKind::SetEffect { kind, value } => self.block("setEffect", info, |w| { w.option(effect_name(kind)); w.expr(value); }),
+            StmtKind::ChangeEffect { kind, delta } => self.block("changeEffect", info, |w| { w.option(effect_name(kind)); w.expr(delta); }),
+            StmtKind::ClearEffects => self.block("clearEffects", info, |_| ()),
+            StmtKind::SetPenAttr { attr, value } => self.block("setPenHSVA", info, |w| { w.option(pen_attr_name(attr)); w.expr(value); }),
+            StmtKind::ChangePenAttr { attr, delta } => self.block("changePenHSVA", info, |w| { w.option(pen_attr_name(attr)); w.expr(delta); }),
+            StmtKind::SetGlobalFlag { flag, value } => self.block("doSetGlobalFlag", info, |w| { w.option(global_flag_name(flag)); w.expr(value); }),
+            StmtKind::SetTempo { value } => self.block("doSetTempo", info, |w| w.expr(value)),
+            StmtKind::ChangeTempo { delta } => self.block("doChangeTempo", info, |w| w.expr(delta)),
+            StmtKind::Stop { mode } => self.block("doStopThis", info, |w| w.option(stop_mode_name(mode))),
+            StmtKind::DefineBlock { var, definition } => self.block("doDefineBlock", info, |w| { w.text(&var.name); w.expr(definition); }),
+            StmtKind::SetBlockAttribute { attr, block, value } => self.block("doSetBlockAttribute", info, |w| { w.expr(attr); w.expr(block); w.expr(value); }),
+            StmtKind::MapCode { block, kind, code } => self.block("doMapCodeOrHeader", info, |w| {
+                w.expr(block);
+                w.option(match kind { CodeKind::Code => "code", CodeKind::Header => "header" });
+                w.expr(code);
+            }),
+            StmtKind::UnknownBlock { name, args } => self.block(name, info, |w| for arg in args { w.expr(arg) }),
+        }
+    }
+    fn expr(&mut self, expr: &Expr) {
+        let info = &expr.info;
+        match &expr.kind {
+            ExprKind::Value(x) => self.value(x),
+            ExprKind::Variable { var } => {
+                write!(self.out, "<block var=\"{}\"", xml_escape(&var.name)).unwrap();
+                if let Some(id) = &info.location {
+                    write!(self.out, " collabId=\"{}\"", xml_escape(id)).unwrap();
+                }
+                self.out.push('>');
+                self.close(info);
+            }
+            ExprKind::EmptySlot => self.out.push_str("<l></l>"),
+            ExprKind::Add { values } => self.block("reportVariadicSum", info, |w| w.variadic(values)),
+            ExprKind::Mul { values } => self.block("reportVariadicProduct", info, |w| w.variadic(values)),
+            ExprKind::Min { values } => self.block("reportVariadicMin", info, |w| w.variadic(values)),
+            ExprKind::Max { values } => self.block("reportVariadicMax", info, |w| w.variadic(values)),
+            ExprKind::Sub { left, right } => self.block("reportDifference", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Div { left, right } => self.block("reportQuotient", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Mod { left, right } => self.block("reportModulus", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Pow { base, power } => self.block("reportPower", info, |w| { w.expr(base); w.expr(power); }),
+            ExprKind::Atan2 { y, x } => self.block("reportAtan2", info, |w| { w.expr(y); w.expr(x); }),
+            ExprKind::Log { value, base } => match &base.kind {
+                ExprKind::Value(Value::Constant(Constant::E)) => self.block("reportMonadic", info, |w| { w.option("ln"); w.expr(value); }),
+                ExprKind::Value(Value::Number(x)) if *x == 2.0 => self.block("reportMonadic", info, |w| { w.option("lg"); w.expr(value); }),
+                ExprKind::Value(Value::Number(x)) if *x == 10.0 => self.block("reportMonadic", info, |w| { w.option("log"); w.expr(value); }),
+                // snap has no block for other bases, so use the change of base formula
+                _ => self.block("reportQuotient", info, |w| {
+                    w.out.push_str("<block s=\"reportMonadic\"><l><option>ln</option></l>");
+                    w.expr(value);
+                    w.out.push_str("</block><block s=\"reportMonadic\"><l><option>ln</option></l>");
+                    w.expr(base);
+                    w.out.push_str("</block>");
+                }),
+            }
+            ExprKind::Neg { value } => self.block("reportMonadic", info, |w| { w.option("neg"); w.expr(value); }),
+            ExprKind::Abs { value } => self.block("reportMonadic", info, |w| { w.option("abs"); w.expr(value); }),
+            ExprKind::Sign { value } => self.block("reportMonadic", info, |w| { w.option("sign"); w.expr(value); }),
+            ExprKind::Sqrt { value } => self.block("reportMonadic", info, |w| { w.option("sqrt"); w.expr(value); }),
+            ExprKind::Floor { value } => self.block("reportMonadic", info, |w| { w.option("floor"); w.expr(value); }),
+            ExprKind::Ceil { value } => self.block("reportMonadic", info, |w| { w.option("ceiling"); w.expr(value); }),
+            ExprKind::Sin { value } => self.block("reportMonadic", info, |w| { w.option("sin"); w.expr(value); }),
+            ExprKind::Cos { value } => self.block("reportMonadic", info, |w| { w.option("cos"); w.expr(value); }),
+            ExprKind::Tan { value } => self.block("reportMonadic", info, |w| { w.option("tan"); w.expr(value); }),
+            ExprKind::Asin { value } => self.block("reportMonadic", info, |w| { w.option("asin"); w.expr(value); }),
+            ExprKind::Acos { value } => self.block("reportMonadic", info, |w| { w.option("acos"); w.expr(value); }),
+            ExprKind::Atan { value } => self.block("reportMonadic", info, |w| { w.option("atan"); w.expr(value); }),
+            ExprKind::And { left, right } => self.block("reportAnd", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Or { left, right } => self.block("reportOr", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Conditional { condition, then, otherwise } => self.block("reportIfElse", info, |w| { w.expr(condition); w.expr(then); w.expr(otherwise); }),
+            ExprKind::Identical { left, right } => self.block("reportIsIdentical", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Eq { left, right } => self.block("reportEquals", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Neq { left, right } => self.block("reportNotEquals", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Less { left, right } => self.block("reportLessThan", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::LessEq { left, right } => self.block("reportLessThanOrEquals", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Greater { left, right } => self.block("reportGreaterThan", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::GreaterEq { left, right } => self.block("reportGreaterThanOrEquals", info, |w| { w.expr(left); w.expr(right); }),
+            ExprKind::Random { a, b } => self.block("reportRandom", info, |w| { w.expr(a); w.expr(b); }),
+            ExprKind::Range { start, stop } => self.block("reportNumbers", info, |w| { w.expr(start); w.expr(stop); }),
+            ExprKind::MakeList { values } => self.block("reportNewList", info, |w| w.inputs(values.iter())),
+            ExprKind::CopyList { list } => self.block("reportNewList", info, |w| w.expr(list)),
+            ExprKind::ListCat { lists } => self.block("reportConcatenatedLists", info, |w| w.variadic(lists)),
+            ExprKind::ListLen { value } => self.block("reportListAttribute", info, |w| { w.option("length"); w.expr(value); }),
+            ExprKind::ListRank { value } => self.block("reportListAttribute", info, |w| { w.option("rank"); w.expr(value); }),
+            ExprKind::ListDims { value } => self.block("reportListAttribute", info, |w| { w.option("dimensions"); w.expr(value); }),
+            ExprKind::ListFlatten { value } => self.block("reportListAttribute", info, |w| { w.option("flatten"); w.expr(value); }),
+            ExprKind::ListColumns { value } => self.block("reportListAttribute", info, |w| { w.option("columns"); w.expr(value); }),
+            ExprKind::ListRev { value } => self.block("reportListAttribute", info, |w| { w.option("reverse"); w.expr(value); }),
+            ExprKind::ListLines { value } => self.block("reportListAttribute", info, |w| { w.option("lines"); w.expr(value); }),
+            ExprKind::ListCsv { value } => self.block("reportListAttribute", info, |w| { w.option("csv"); w.expr(value); }),
+            ExprKind::ListJson { value } => self.block("reportListAttribute", info, |w| { w.option("json"); w.expr(value); }),
+            ExprKind::ListReshape { value, dims } => self.block("reportReshape", info, |w| { w.expr(value); w.variadic(dims); }),
+            ExprKind::ListCombinations { sources } => self.block("reportCrossproduct", info, |w| w.variadic(sources)),
+            ExprKind::ListIsEmpty { value } => self.block("reportListIsEmpty", info, |w| w.expr(value)),
+            ExprKind::ListCdr { value } => self.block("reportCDR", info, |w| w.expr(value)),
+            ExprKind::ListCons { item, list } => self.block("reportCONS", info, |w| { w.expr(item); w.expr(list); }),
+            ExprKind::ListFind { list, value } => self.block("reportListIndex", info, |w| { w.expr(value); w.expr(list); }),
+            ExprKind::ListContains { list, value } => self.block("reportListContainsItem", info, |w| { w.expr(list); w.expr(value); }),
+            ExprKind::ListGet { list, index } => self.block("reportListItem", info, |w| { w.expr(index); w.expr(list); }),
+            ExprKind::ListGetLast { list } => self.block("reportListItem", info, |w| { w.option("last"); w.expr(list); }),
+            ExprKind::ListGetRandom { list } => self.block("reportListItem", info, |w| { w.option("any"); w.expr(list); }),
+            ExprKind::StrGet { string, index } => self.block("reportLetter", info, |w| { w.expr(index); w.expr(string); }),
+            ExprKind::StrGetLast { string } => self.block("reportLetter", info, |w| { w.option("last"); w.expr(string); }),
+            ExprKind::StrGetRandom { string } => self.block("reportLetter", info, |w| { w.option("any"); w.expr(string); }),
+            ExprKind::StrCat { values } => self.block("reportJoinWords", info, |w| w.variadic(values)),
+            ExprKind::StrLen { value } => self.block("reportStringSize", info, |w| w.expr(value)),
+            ExprKind::UnicodeToChar { value } => self.block("reportUnicodeAsLetter", info, |w| w.expr(value)),
+            ExprKind::CharToUnicode { value } => self.block("reportUnicode", info, |w| w.expr(value)),
+            ExprKind::Not { value } => self.block("reportNot", info, |w| w.expr(value)),
+            ExprKind::Round { value } => self.block("reportRound", info, |w| w.expr(value)),
+            ExprKind::CallRpc { host, service, rpc, args } => self.rpc("getJSFromRPCStruct", info, host, service, rpc, args),
+            ExprKind::CallFn { function, args, .. } => self.call_fn(function, args, info),
+            ExprKind::CallClosure { new_entity, closure, args } => match new_entity {
+                Some(entity) => self.block("reportAskFor", info, |w| { w.entity(entity); w.expr(closure); w.inputs(args.iter()); }),
+                None => self.block("evaluate", info, |w| { w.expr(closure); w.inputs(args.iter()); }),
+            }
+            ExprKind::StageWidth => self.block("reportStageWidth", info, |_| ()),
+            ExprKind::StageHeight => self.block("reportStageHeight", info, |_| ()),
+            ExprKind::MouseX => self.block("reportMouseX", info, |_| ()),
+            ExprKind::MouseY => self.block("reportMouseY", info, |_| ()),
+            ExprKind::Latitude => self.block("reportLatitude", info, |_| ()),
+            ExprKind::Longitude => self.block("reportLongitude", info, |_| ()),
+            ExprKind::KeyDown { key } => self.block("reportKeyPressed", info, |w| match &key.kind {
+                ExprKind::Value(x) if x.as_str().is_some() => w.option(x.as_str().unwrap_or_default()),
+                _ => w.expr(key),
+            }),
+            ExprKind::YPos => self.block("yPosition", info, |_| ()),
+            ExprKind::XPos => self.block("xPosition", info, |_| ()),
+            ExprKind::Heading => self.block("direction", info, |_| ()),
+            ExprKind::PenDown => self.block("getPenDown", info, |_| ()),
+            ExprKind::Size => self.block("getScale", info, |_| ()),
+            ExprKind::IsVisible => self.block("reportShown", info, |_| ()),
+            ExprKind::This | ExprKind::Entity { .. } => self.block("reportObject", info, |w| w.entity(expr)),
+            ExprKind::ImageOfEntity { entity } => self.block("reportImageOfObject", info, |w| w.entity(entity)),
+            ExprKind::ImageOfDrawings => self.block("reportPenTrailsAsCostume", info, |_| ()),
+            ExprKind::IsTouchingEntity { entity } => self.block("reportTouchingObject", info, |w| w.entity(entity)),
+            ExprKind::IsTouchingMouse => self.block("reportTouchingObject", info, |w| w.option("mouse-pointer")),
+            ExprKind::IsTouchingEdge => self.block("reportTouchingObject", info, |w| w.option("edge")),
+            ExprKind::IsTouchingDrawings => self.block("reportTouchingObject", info, |w| w.option("pen trails")),
+            ExprKind::RpcError => self.block("reportRPCError", info, |_| ()),
+            ExprKind::Closure { kind, params, stmts, .. } => {
+                let params = params.iter().map(|x| &x.name);
+                match (kind, stmts.as_slice()) {
+                    (ClosureKind::Reporter, [Stmt { kind: StmtKind::Return { value }, .. }]) => self.block("reifyReporter", info, |w| {
+                        w.out.push_str("<autolambda>");
+                        w.expr(value);
+                        w.out.push_str("</autolambda>");
+                        w.names(params);
+                    }),
+                    (ClosureKind::Predicate, [Stmt { kind: StmtKind::Return { value }, .. }]) => self.block("reifyPredicate", info, |w| {
+                        w.out.push_str("<autolambda>");
+                        w.expr(value);
+                        w.out.push_str("</autolambda>");
+                        w.names(params);
+                    }),
+                    _ => self.block("reifyScript", info, |w| { w.script(stmts); w.names(params); }),
+                }
+            }
+            ExprKind::TextSplit { text, mode } => self.block("reportTextSplit", info, |w| {
+                w.expr(text);
+                match mode {
+                    TextSplitMode::Letter => w.option("letter"),
+                    TextSplitMode::Word => w.option("word"),
+                    TextSplitMode::Tab => w.option("tab"),
+                    TextSplitMode::CR => w.option("cr"),
+                    TextSplitMode::LF => w.option("line"),
+                    TextSplitMode::Csv => w.option("csv"),
+                    TextSplitMode::Json => w.option("json"),
+                    TextSplitMode::Custom(x) => w.expr(x),
+                }
+            }),
+            ExprKind::TextFunction { function, value } => self.block("reportTextFunction", info, |w| { w.option(text_function_name(function)); w.expr(value); }),
+            ExprKind::Answer => self.block("getLastAnswer", info, |_| ()),
+            ExprKind::Message => self.block("getLastMessage", info, |_| ()),
+            ExprKind::Timer => self.block("getTimer", info, |_| ()),
+            ExprKind::Map { f, list } => self.block("reportMap", info, |w| { w.expr(f); w.expr(list); }),
+            ExprKind::Keep { f, list } => self.block("reportKeep", info, |w| { w.expr(f); w.expr(list); }),
+            ExprKind::FindFirst { f, list } => self.block("reportFindFirst", info, |w| { w.expr(f); w.expr(list); }),
+            ExprKind::Combine { f, list } => self.block("reportCombine", info, |w| { w.expr(list); w.expr(f); }),
+            ExprKind::Pipe { value, functions } => self.block("reportPipe", info, |w| { w.expr(value); w.variadic(functions); }),
+            ExprKind::NetworkMessageReply { target, msg_type, values } => self.message("doSocketRequest", info, target, msg_type, values),
+            ExprKind::Effect { kind } => self.block("getEffect", info, |w| w.option(effect_name(kind))),
+            ExprKind::PenAttr { attr } => self.block("getPenAttribute", info, |w| w.option(pen_attr_name(attr))),
+            ExprKind::GlobalFlag { flag } => self.block("reportGlobalFlag", info, |w| w.option(global_flag_name(flag))),
+            ExprKind::Tempo => self.block("getTempo", info, |_| ()),
+            ExprKind::CostumeList => self.block("reportGet", info, |w| w.option("costumes")),
+            ExprKind::Costume => self.block("reportGet", info, |w| w.option("costume")),
+            ExprKind::SoundList => self.block("reportGet", info, |w| w.option("sounds")),
+            ExprKind::CostumeNumber => self.block("getCostumeIdx", info, |_| ()),
+            ExprKind::CostumeName { costume } => self.costume_attr("name", costume, info),
+            ExprKind::CostumeWidth { costume } => self.costume_attr("width", costume, info),
+            ExprKind::CostumeHeight { costume } => self.costume_attr("height", costume, info),
+            ExprKind::CostumePixels { costume } => self.costume_attr("pixels", costume, info),
+            ExprKind::SoundName { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("name"); w.expr(sound); }),
+            ExprKind::SoundDuration { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("duration"); w.expr(sound); }),
+            ExprKind::SoundSampleRate { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("sample rate"); w.expr(sound); }),
+            ExprKind::SoundSamples { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("samples"); w.expr(sound); }),
+            ExprKind::SoundSamplesLength { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("length"); w.expr(sound); }),
+            ExprKind::SoundChannelCount { sound } => self.block("reportGetSoundAttribute", info, |w| { w.option("number of channels"); w.expr(sound); }),
+            ExprKind::Clone { target } => self.block("newClone", info, |w| w.entity(target)),
+            ExprKind::TypeQuery { value, ty } => self.block("reportIsA", info, |w| { w.expr(value); w.option(value_type_name(ty)); }),
+            ExprKind::RealTime { query } => self.block("reportDate", info, |w| w.option(time_query_name(query))),
+            ExprKind::BlockAttribute { attr, block } => self.block("reportBlockAttribute", info, |w| { w.expr(attr); w.expr(block); }),
+            ExprKind::MappedCode { value } => self.block("reportMappedCode", info, |w| w.expr(value)),
+            ExprKind::JsFunction { params, body } => self.block("reportJSFunction", info, |w| { w.names(params.iter()); w.text(body); }),
+            ExprKind::UnknownBlock { name, args } => self.block(name, info, |w| for arg in args { w.expr(arg) }),
+        }
+    }
+    fn costume_attr(&mut self, attr: &str, costume: &Expr, info: &BlockInfo) {
+        self.block("reportGetImageAttribute", info, |w| {
+            w.option(attr);
+            match &costume.kind {
+                ExprKind::Costume => w.option("current"),
+                ExprKind::Value(x) if x.as_str() == Some("") => w.option("Turtle"),
+                _ => w.expr(costume),
+            }
+        });
+    }
+}
+
+impl Script {
+    /// Converts the script into a standalone Snap `<script>` element, which can be pasted into the NetsBlox editor or wrapped in a project.
+    ///
+    /// Parsing the result gives an equivalent script, although blocks which the parser lowers into a different shape (see [`BlockInfo::opcode`]) are written in their lowered form,
+    /// and values which have no text form in a script (images, sounds, and list references) are written as empty slots.
+    /// Custom block definitions are not known here, so custom block calls are written with an evaluated `%s` (any type) slot for each input.
+    /// Use [`Script::to_xml_in`] to write calls to blocks with unevaluated or variadic parameters.
+    pub fn to_xml(&self) -> CompactString {
+        self.to_xml_with(&[], &[])
+    }
+    /// Converts the script into a standalone Snap `<script>` element, as in [`Script::to_xml`].
+    ///
+    /// Custom block calls are written according to their definitions in the given role and entity (the entity the script belongs to),
+    /// so that unevaluated, variadic, and upvar inputs have the same shape when parsed again.
+    pub fn to_xml_in(&self, role: &Role, entity: &Entity) -> CompactString {
+        self.to_xml_with(&role.funcs, &entity.funcs)
+    }
+    fn to_xml_with(&self, funcs: &[Function], methods: &[Function]) -> CompactString {
+        let mut writer = Writer { out: String::new(), funcs, methods };
+        writer.out.push_str("<script>");
+        if let Some(hat) = &self.hat {
+            writer.hat(hat);
+        }
+        for stmt in self.stmts.iter() {
+            writer.stmt(stmt);
+        }
+        writer.out.push_str("</script>");
+        writer.out.into()
+    }
+}
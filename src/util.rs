@@ -1,5 +1,8 @@
 use core::fmt::{self, Debug, Display};
 use alloc::string::ToString;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::borrow::Cow;
 
 #[cfg(test)]
 use proptest::prelude::*;
@@ -111,6 +114,148 @@ fn test_c_ident() {
     assert_eq!(c_ident("[ 6foo").unwrap(), "var_6foo");
 }
 
+fn transliterate_lower(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        // latin
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĳ' => "ij",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+
+        // greek
+        'α' | 'ά' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' | 'έ' => "e",
+        'ζ' => "z",
+        'η' | 'ή' | 'ι' | 'ί' | 'ϊ' | 'ΐ' => "i",
+        'θ' => "th",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ό' | 'ω' | 'ώ' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+
+        // cyrillic
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' | 'ё' | 'э' => "e",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'ї' => "yi",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+
+        _ => return None,
+    })
+}
+/// Transliterates latin (with diacritics), greek, and cyrillic letters into ASCII.
+/// Non-ASCII whitespace is replaced with a space, and all other non-ASCII characters (e.g., emoji) are removed.
+pub fn transliterate(raw: &str) -> CompactString {
+    let mut res = alloc::string::String::with_capacity(raw.len());
+    for ch in raw.chars() {
+        if ch.is_ascii() {
+            res.push(ch);
+        } else if ch.is_whitespace() {
+            res.push(' ');
+        } else {
+            let mut lower = ch.to_lowercase();
+            let ascii = match (lower.next(), lower.next()) {
+                (Some(x), None) => transliterate_lower(x),
+                _ => None,
+            };
+            if let Some(ascii) = ascii {
+                let mut ascii = ascii.chars();
+                if let Some(first) = ascii.next() {
+                    res.push(if ch.is_uppercase() { first.to_ascii_uppercase() } else { first });
+                    res.extend(ascii);
+                }
+            }
+        }
+    }
+    res.into()
+}
+#[test]
+fn test_transliterate() {
+    assert_eq!(transliterate("hello world"), "hello world");
+    assert_eq!(transliterate("επιτάχυνση"), "epitachynsi");
+    assert_eq!(transliterate("Ταχύτητα"), "Tachytita");
+    assert_eq!(transliterate("скорость"), "skorost");
+    assert_eq!(transliterate("Щука"), "Shchuka");
+    assert_eq!(transliterate("Ärger über Straße"), "Arger uber Strasse");
+    assert_eq!(transliterate("größe\u{a0}🚀 x"), "grosse  x");
+    assert_eq!(transliterate("🚀🎉"), "");
+}
+
+/// Converts a Snap! identifier into a valid C-like identifier, transliterating non-ASCII letters (see [`transliterate`]) rather than dropping them.
+/// This returns `None` if the identifier has no letters or digits that can be kept.
+/// To use this as a [`Parser::name_transformer`], wrap it as `|x: &str| ascii_c_ident(x).ok_or(())`.
+pub fn ascii_c_ident(raw: &str) -> Option<CompactString> {
+    c_ident(&transliterate(raw)).ok()
+}
+#[test]
+fn test_ascii_c_ident() {
+    assert_eq!(ascii_c_ident("foo bar").unwrap(), "foo_bar");
+    assert_eq!(ascii_c_ident("επιτάχυνση").unwrap(), "epitachynsi");
+    assert_eq!(ascii_c_ident("μέγεθος 🚀 2").unwrap(), "megethos_2");
+    assert_eq!(ascii_c_ident("2χ").unwrap(), "var_2ch");
+    assert!(ascii_c_ident("🚀🎉").is_none());
+    assert!(c_ident("επιτάχυνση").is_err());
+}
+
 // source: https://docs.babelmonkeys.de/RustyXML/src/xml/lib.rs.html#41-55
 #[inline(never)]
 pub fn xml_escape(input: &str) -> CompactString {
@@ -128,8 +273,13 @@ pub fn xml_escape(input: &str) -> CompactString {
     result.into()
 }
 
-#[inline(never)]
 pub fn xml_unescape(input: &str) -> Result<CompactString, XmlError> {
+    xml_unescape_cow(input).map(Into::into)
+}
+/// Equivalent to [`xml_unescape`], but borrows from `input` (rather than copying it) if it contains no escape sequences.
+#[inline(never)]
+pub fn xml_unescape_cow(input: &str) -> Result<Cow<'_, str>, XmlError> {
+    if !input.contains('&') { return Ok(Cow::Borrowed(input)) }
     let mut result = alloc::string::String::with_capacity(input.len());
 
     let mut chars = input.char_indices().fuse();
@@ -167,7 +317,58 @@ pub fn xml_unescape(input: &str) -> Result<CompactString, XmlError> {
         }
     }
 
-    Ok(result.into())
+    Ok(Cow::Owned(result))
+}
+
+/// Detects the encoding of raw xml from its byte order mark or (failing that) the encoding of its first `<` character.
+/// Input which is not recognized as UTF-16 is assumed to be UTF-8.
+pub fn detect_encoding(raw: &[u8]) -> Encoding {
+    match raw {
+        [0xff, 0xfe, ..] | [b'<', 0, ..] => Encoding::Utf16Le,
+        [0xfe, 0xff, ..] | [0, b'<', ..] => Encoding::Utf16Be,
+        _ => Encoding::Utf8,
+    }
+}
+
+/// Decodes raw xml in the given encoding, removing the byte order mark (if present).
+pub fn decode_xml(raw: &[u8], encoding: Encoding) -> Result<alloc::string::String, XmlError> {
+    let res = match encoding {
+        Encoding::Utf8 => core::str::from_utf8(raw).ok().map(Into::into),
+        Encoding::Utf16Le | Encoding::Utf16Be => match raw.len() % 2 {
+            0 => {
+                let units = raw.chunks_exact(2).map(|x| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([x[0], x[1]]),
+                    _ => u16::from_be_bytes([x[0], x[1]]),
+                });
+                char::decode_utf16(units).collect::<Result<alloc::string::String, _>>().ok()
+            }
+            _ => None,
+        }
+    };
+    match res {
+        Some(x) => Ok(match x.strip_prefix('\u{feff}') {
+            Some(x) => x.into(),
+            None => x,
+        }),
+        None => Err(XmlError::InvalidEncoding { encoding }),
+    }
+}
+#[test]
+fn test_decode_xml() {
+    let utf16 = |s: &str, big_endian: bool| s.encode_utf16().flat_map(|x| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() }).collect::<alloc::vec::Vec<_>>();
+
+    assert_eq!(detect_encoding(b"<room/>"), Encoding::Utf8);
+    assert_eq!(detect_encoding(b"\xef\xbb\xbf<room/>"), Encoding::Utf8);
+    assert_eq!(detect_encoding(&utf16("<room/>", false)), Encoding::Utf16Le);
+    assert_eq!(detect_encoding(&utf16("\u{feff}<room/>", false)), Encoding::Utf16Le);
+    assert_eq!(detect_encoding(&utf16("<room/>", true)), Encoding::Utf16Be);
+    assert_eq!(detect_encoding(&utf16("\u{feff}<room/>", true)), Encoding::Utf16Be);
+
+    assert_eq!(decode_xml(b"\xef\xbb\xbf<room/>", Encoding::Utf8).unwrap(), "<room/>");
+    assert_eq!(decode_xml(&utf16("\u{feff}<a>π</a>", false), Encoding::Utf16Le).unwrap(), "<a>π</a>");
+    assert_eq!(decode_xml(&utf16("<a>😀</a>", true), Encoding::Utf16Be).unwrap(), "<a>😀</a>");
+    assert_eq!(decode_xml(b"<a>\xff</a>", Encoding::Utf8), Err(XmlError::InvalidEncoding { encoding: Encoding::Utf8 }));
+    assert_eq!(decode_xml(b"<\0a", Encoding::Utf16Le), Err(XmlError::InvalidEncoding { encoding: Encoding::Utf16Le }));
 }
 
 #[cfg(test)]
@@ -176,13 +377,16 @@ proptest! {
     fn test_xml_enc_dec(raw in r".*") {
         let encoded = xml_escape(&raw);
         let back = xml_unescape(&encoded).unwrap();
-        prop_assert_eq!(raw, back);
+        prop_assert_eq!(&raw, &back);
+        prop_assert_eq!(raw, xml_unescape_cow(&encoded).unwrap());
     }
 }
 
 #[test]
 fn test_xml_dec() {
     assert_eq!(xml_unescape("hello world").unwrap(), "hello world");
+    assert!(matches!(xml_unescape_cow("hello world").unwrap(), Cow::Borrowed("hello world")));
+    assert!(matches!(xml_unescape_cow("hello &amp; world").unwrap(), Cow::Owned(x) if x == "hello & world"));
     assert_eq!(xml_unescape("hello &quot; world").unwrap(), "hello \" world");
     assert_eq!(xml_unescape("hello &apos; world").unwrap(), "hello ' world");
     assert_eq!(xml_unescape("hello &gt; world").unwrap(), "hello > world");
@@ -207,3 +411,38 @@ fn test_xml_dec() {
     assert_eq!(xml_unescape("&&he&llo &&& world&").unwrap(), "&&he&llo &&& world&");
     assert_eq!(xml_unescape("&&he&llo &&& world&&").unwrap(), "&&he&llo &&& world&&");
 }
+
+pub(crate) trait BoxExt<T> {
+    fn new_with<F: FnOnce() -> T>(f: F) -> Self;
+    fn try_new_with<E, F: FnOnce() -> Result<T, E>>(f: F) -> Result<Self, E> where Self: Sized;
+}
+impl<T> BoxExt<T> for Box<T> {
+    #[inline(never)]
+    fn new_with<F: FnOnce() -> T>(f: F) -> Self {
+        Box::new(f())
+    }
+    #[inline(never)]
+    fn try_new_with<E, F: FnOnce() -> Result<T, E>>(f: F) -> Result<Self, E> {
+        f().map(Box::new)
+    }
+}
+
+pub(crate) trait VecExt<T> {
+    fn new_with_single<F: FnOnce() -> T>(f: F) -> Self;
+    fn push_with<F: FnOnce() -> T>(&mut self, f: F);
+    fn push_boxed(&mut self, value: Box<T>);
+}
+impl<T> VecExt<T> for Vec<T> {
+    #[inline(never)]
+    fn new_with_single<F: FnOnce() -> T>(f: F) -> Self {
+        vec![f()]
+    }
+    #[inline(never)]
+    fn push_with<F: FnOnce() -> T>(&mut self, f: F) {
+        self.push(f());
+    }
+    #[inline(never)]
+    fn push_boxed(&mut self, value: Box<T>) {
+        self.push(*value);
+    }
+}
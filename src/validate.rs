@@ -0,0 +1,219 @@
+//! Validation of projects which were constructed or modified in code, rather than produced by the parser.
+//!
+//! The parser rejects invalid projects as it goes, but an AST built by hand (or by [`RoleBuilder`]) can violate the same rules.
+//! [`Project::validate`] re-runs those checks over an existing AST.
+
+use alloc::vec::Vec;
+
+use crate::*;
+use crate::visit::*;
+
+/// Gets a block spec (in the format used by the xml) for a custom block, for use in diagnostics.
+fn signature(func: &Function) -> CompactString {
+    let mut res = func.name.clone();
+    for param in func.params.iter() {
+        res.push_str(&format_compact!(" %'{}'", param.name));
+    }
+    res
+}
+
+struct Validator<'a, T: NameTransformer> {
+    parser: &'a Parser<T>,
+    role: &'a Role,
+    entity: Option<&'a Entity>,
+    location: Location,
+    res: Vec<Diagnostic>,
+}
+impl<'a, T: NameTransformer> Validator<'a, T> {
+    fn push(&mut self, kind: ErrorKind, node: Option<NodeId>) {
+        let mut diagnostic = Diagnostic::from(Error { kind, location: self.location.clone() });
+        diagnostic.node = node;
+        self.res.push(diagnostic);
+    }
+    /// Checks that a list of names has no duplicates, and that the names can be transformed without conflicts.
+    /// Since these names do not belong to a block, the problems found do not have a collab id.
+    fn names<'b>(&mut self, names: impl Iterator<Item = &'b CompactString>, same_name: &dyn Fn(CompactString) -> ErrorKind, same_trans: fn(CompactString, (CompactString, CompactString)) -> CompileError) {
+        self.location.collab_id = None;
+        let mut table = SymbolTable::new(self.parser);
+        for name in names {
+            match table.define(name.clone(), Value::from(0.0f64)) {
+                Ok(None) => (),
+                Ok(Some(prev)) => self.push(same_name(prev.def.name), None),
+                Err(SymbolError::ConflictingTrans { names, .. }) if names.0 == names.1 => self.push(same_name(names.0), None),
+                Err(SymbolError::ConflictingTrans { trans_name, names }) => self.push(same_trans(trans_name, names).into(), None),
+                Err(SymbolError::NameTransformError { name }) => self.push(CompileError::NameTransformError { name }.into(), None),
+            }
+        }
+    }
+    fn funcs(&mut self, funcs: &[Function]) {
+        self.names(funcs.iter().map(|x| &x.name), &|name| {
+            let mut sigs = funcs.iter().filter(|x| x.name == name).map(signature);
+            let sigs = (sigs.next().unwrap_or_default(), sigs.next().unwrap_or_default());
+            CompileError::BlocksWithSameName { name, sigs }.into()
+        }, |trans_name, names| CompileError::BlocksWithSameTransName { trans_name, names });
+
+        for func in funcs {
+            self.names(func.params.iter().map(|x| &x.name), &|name| CompileError::InputsWithSameName { name }.into(), |trans_name, names| CompileError::LocalsWithSameTransName { trans_name, names });
+            self.location.collab_id = None;
+            for upvar in func.upvars.iter() {
+                if upvar.location != VarLocation::Local || !func.params.iter().any(|x| x.trans_name == upvar.trans_name) {
+                    self.push(CompileError::InvalidUpvar { name: upvar.name.clone() }.into(), None);
+                }
+            }
+
+            let mut locals = SymbolTable::new(self.parser);
+            for param in func.params.iter() {
+                let _ = locals.define(param.name.clone(), Value::from(0.0f64));
+            }
+            self.stmts(&func.stmts, &mut locals);
+        }
+    }
+    fn script(&mut self, script: &Script) {
+        let mut locals = SymbolTable::new(self.parser);
+        if let Some(hat) = script.hat.as_deref() {
+            match &hat.kind {
+                HatKind::When { condition } => self.expr(condition, &mut locals),
+                HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields {
+                    self.declare(&field.name, &mut locals, &hat.info);
+                }
+                _ => (),
+            }
+        }
+        self.stmts(&script.stmts, &mut locals);
+    }
+
+    fn declare(&mut self, name: &CompactString, locals: &mut SymbolTable<'a, T>, info: &BlockInfo) {
+        self.location.collab_id = info.location.clone();
+        match locals.define(name.clone(), Value::from(0.0f64)) {
+            Ok(_) => (), // redefining locals is fine
+            Err(SymbolError::ConflictingTrans { trans_name, names }) => if names.0 != names.1 { // redefining locals is fine
+                self.push(CompileError::LocalsWithSameTransName { trans_name, names }.into(), Some(info.id));
+            }
+            Err(SymbolError::NameTransformError { name }) => self.push(CompileError::NameTransformError { name }.into(), Some(info.id)),
+        }
+    }
+    /// Checks that a variable reference refers to a variable which is defined (with the same translated name) where it is used.
+    fn reference(&mut self, var: &VariableRef, locals: &SymbolTable<'a, T>, info: &BlockInfo) {
+        let def = match var.location {
+            VarLocation::Local => locals.get(&var.name).map(|x| &x.def),
+            VarLocation::Field => self.entity.and_then(|x| x.field(&var.name)).map(|x| &x.def),
+            VarLocation::Global => self.role.globals.iter().find(|x| x.def.name == var.name).map(|x| &x.def),
+        };
+        match def {
+            Some(def) if def.trans_name == var.trans_name => (),
+            _ => {
+                self.location.collab_id = info.location.clone();
+                self.push(CompileError::UndefinedVariable { name: var.name.clone() }.into(), Some(info.id));
+            }
+        }
+    }
+    /// Checks that a custom block call refers to a block which is defined, and that it has the right number of arguments and upvars.
+    fn call(&mut self, function: &FnRef, args: &[Expr], upvars: &[VariableRef], locals: &mut SymbolTable<'a, T>, info: &BlockInfo) {
+        self.location.collab_id = info.location.clone();
+        let funcs = match function.location {
+            FnLocation::Global => Some(&self.role.funcs),
+            FnLocation::Method => self.entity.map(|x| &x.funcs),
+        };
+        let func = match funcs.and_then(|x| x.iter().find(|x| x.name == function.name && x.trans_name == function.trans_name)) {
+            Some(x) => x,
+            None => return self.push(CompileError::UndefinedFn { name: function.name.clone() }.into(), Some(info.id)),
+        };
+        if args.len() != func.params.len() {
+            self.push(CompileError::FnWrongNumberArgs { name: func.name.clone(), got: args.len(), expected: func.params.len() }.into(), Some(info.id));
+        }
+        if upvars.len() != func.upvars.len() {
+            self.push(CompileError::FnWrongNumberUpvars { name: func.name.clone(), got: upvars.len(), expected: func.upvars.len() }.into(), Some(info.id));
+        }
+        for upvar in upvars {
+            match upvar.location {
+                VarLocation::Local => self.declare(&upvar.name, locals, info),
+                _ => self.push(CompileError::InvalidUpvar { name: upvar.name.clone() }.into(), Some(info.id)),
+            }
+        }
+    }
+    fn stmts(&mut self, stmts: &[Stmt], locals: &mut SymbolTable<'a, T>) {
+        for stmt in stmts {
+            let info = &stmt.info;
+            match &stmt.kind {
+                StmtKind::DeclareLocals { vars } => for var in vars {
+                    self.declare(&var.name, locals, info);
+                }
+                StmtKind::ForLoop { var, .. } | StmtKind::ForeachLoop { var, .. } | StmtKind::Repeat { counter: Some(var), .. }
+                | StmtKind::DefineBlock { var, .. } | StmtKind::TryCatch { var, .. } => match var.location {
+                    VarLocation::Local => self.declare(&var.name, locals, info),
+                    _ => self.reference(var, locals, info),
+                }
+                StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var } => self.reference(var, locals, info),
+                StmtKind::CallFn { function, args, upvars } => self.call(function, args, upvars, locals, info),
+                _ => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            stmt_children(&stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for x in exprs { self.expr(x, locals) }
+            for x in bodies { self.stmts(x, locals) }
+        }
+    }
+    fn expr(&mut self, expr: &Expr, locals: &mut SymbolTable<'a, T>) {
+        let info = &expr.info;
+        match &expr.kind {
+            ExprKind::Variable { var } => self.reference(var, locals, info),
+            ExprKind::CallFn { function, args, upvars } => self.call(function, args, upvars, locals, info),
+            ExprKind::Closure { params, captures, stmts, .. } => {
+                for var in captures {
+                    self.reference(var, locals, info);
+                }
+                let mut inner = locals.clone();
+                for var in captures {
+                    let _ = inner.define(var.name.clone(), Value::from(0.0f64)); // captures are locals within the closure
+                }
+                for param in params {
+                    self.declare(&param.name, &mut inner, info);
+                }
+                return self.stmts(stmts, &mut inner);
+            }
+            _ => (),
+        }
+
+        let mut exprs = Vec::new();
+        let mut bodies = Vec::new();
+        expr_children(&expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+        for x in exprs { self.expr(x, locals) }
+        for x in bodies { self.stmts(x, locals) }
+    }
+}
+
+impl Project {
+    /// Checks a project for the problems which the parser would have rejected, such as duplicate names, conflicting translated names (using [`Parser::name_transformer`]),
+    /// references to undefined variables or custom blocks, and malformed upvars.
+    ///
+    /// This is intended for projects which were constructed or modified in code, since projects produced by the parser are always valid.
+    /// Each problem is reported as a [`Diagnostic`] with [`Severity::Error`], where [`Diagnostic::node`] is the offending block (if any).
+    pub fn validate<T: NameTransformer>(&self, parser: &Parser<T>) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        for role in self.roles.iter() {
+            let location = Location { role: Some(role.name.clone()), entity: None, collab_id: None, block_type: None };
+            let mut validator = Validator { parser, role, entity: None, location, res };
+
+            validator.names(role.globals.iter().map(|x| &x.def.name), &|name| ProjectError::GlobalsWithSameName { name }.into(), |trans_name, names| CompileError::GlobalsWithSameTransName { trans_name, names });
+            validator.names(role.entities.iter().map(|x| &x.name), &|name| ProjectError::EntitiesWithSameName { name }.into(), |trans_name, names| CompileError::EntitiesWithSameTransName { trans_name, names });
+            validator.funcs(&role.funcs);
+
+            for entity in role.entities.iter() {
+                validator.entity = Some(entity);
+                validator.location = Location { role: Some(role.name.clone()), entity: Some(entity.name.clone()), collab_id: None, block_type: None };
+
+                validator.names(entity.fields.iter().map(|x| &x.def.name), &|name| ProjectError::FieldsWithSameName { name }.into(), |trans_name, names| CompileError::FieldsWithSameTransName { trans_name, names });
+                validator.names(entity.costumes.iter().map(|x| &x.def.name), &|name| ProjectError::CostumesWithSameName { name }.into(), |trans_name, names| CompileError::CostumesWithSameTransName { trans_name, names });
+                validator.names(entity.sounds.iter().map(|x| &x.def.name), &|name| ProjectError::SoundsWithSameName { name }.into(), |trans_name, names| CompileError::SoundsWithSameTransName { trans_name, names });
+                validator.funcs(&entity.funcs);
+                for script in entity.scripts.iter() {
+                    validator.script(script);
+                }
+            }
+            res = validator.res;
+        }
+        res
+    }
+}
@@ -0,0 +1,273 @@
+//! Shallow traversal helpers shared by the analyses and transforms in this crate.
+//!
+//! These only enumerate the *direct* children of a node; callers are responsible for recursing.
+//! They are written as a macro so that shared and mutable flavors can be generated from the same source and never disagree.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+macro_rules! impl_children {
+    ($expr_fn:ident, $stmt_fn:ident $(, $m:tt)?) => {
+        /// Calls `exprs` on every direct sub-expression of `kind`, and `stmts` on every nested statement list (closure bodies).
+        pub(crate) fn $expr_fn<'a>(kind: &'a $($m)? ExprKind, exprs: &mut dyn FnMut(&'a $($m)? Expr), stmts: &mut dyn FnMut(&'a $($m)? Vec<Stmt>)) {
+            match kind {
+                ExprKind::Value(_) | ExprKind::Variable { .. } => (),
+
+                ExprKind::Add { values } | ExprKind::Mul { values } | ExprKind::Min { values } | ExprKind::Max { values } | ExprKind::StrCat { values } => exprs(values),
+                ExprKind::ListCat { lists } => exprs(lists),
+                ExprKind::ListCombinations { sources } => exprs(sources),
+
+                ExprKind::Sub { left, right } | ExprKind::Div { left, right } | ExprKind::Mod { left, right }
+                | ExprKind::And { left, right } | ExprKind::Or { left, right }
+                | ExprKind::Identical { left, right } | ExprKind::Eq { left, right } | ExprKind::Neq { left, right }
+                | ExprKind::Less { left, right } | ExprKind::LessEq { left, right } | ExprKind::Greater { left, right } | ExprKind::GreaterEq { left, right } => {
+                    exprs(left);
+                    exprs(right);
+                }
+
+                ExprKind::Pow { base, power } => { exprs(base); exprs(power); }
+                ExprKind::Log { value, base } => { exprs(value); exprs(base); }
+                ExprKind::Atan2 { y, x } => { exprs(y); exprs(x); }
+                ExprKind::Conditional { condition, then, otherwise } => { exprs(condition); exprs(then); exprs(otherwise); }
+                ExprKind::Random { a, b } => { exprs(a); exprs(b); }
+                ExprKind::Range { start, stop } => { exprs(start); exprs(stop); }
+
+                ExprKind::MakeList { values } => for value in values { exprs(value) },
+                ExprKind::CopyList { list } | ExprKind::ListGetLast { list } | ExprKind::ListGetRandom { list } => exprs(list),
+
+                ExprKind::ListLen { value } | ExprKind::ListRank { value } | ExprKind::ListDims { value } | ExprKind::ListFlatten { value }
+                | ExprKind::ListColumns { value } | ExprKind::ListRev { value } | ExprKind::ListLines { value } | ExprKind::ListCsv { value }
+                | ExprKind::ListJson { value } | ExprKind::ListIsEmpty { value } | ExprKind::ListCdr { value }
+                | ExprKind::StrLen { value } | ExprKind::UnicodeToChar { value } | ExprKind::CharToUnicode { value }
+                | ExprKind::Not { value } | ExprKind::Neg { value } | ExprKind::Abs { value } | ExprKind::Sign { value } | ExprKind::Sqrt { value }
+                | ExprKind::Floor { value } | ExprKind::Ceil { value } | ExprKind::Round { value }
+                | ExprKind::Sin { value } | ExprKind::Cos { value } | ExprKind::Tan { value }
+                | ExprKind::Asin { value } | ExprKind::Acos { value } | ExprKind::Atan { value }
+                | ExprKind::TypeQuery { value, .. } => exprs(value),
+
+                ExprKind::ListReshape { value, dims } => { exprs(value); exprs(dims); }
+                ExprKind::ListCons { item, list } => { exprs(item); exprs(list); }
+                ExprKind::ListFind { list, value } | ExprKind::ListContains { list, value } => { exprs(list); exprs(value); }
+                ExprKind::ListGet { list, index } => { exprs(list); exprs(index); }
+
+                ExprKind::StrGet { string, index } => { exprs(string); exprs(index); }
+                ExprKind::StrGetLast { string } | ExprKind::StrGetRandom { string } => exprs(string),
+
+                ExprKind::CallRpc { args, .. } => for (_, arg) in args { exprs(arg) },
+                ExprKind::CallFn { args, .. } => for arg in args { exprs(arg) },
+                ExprKind::CallClosure { new_entity, closure, args } => {
+                    if let Some(new_entity) = new_entity { exprs(new_entity) }
+                    exprs(closure);
+                    for arg in args { exprs(arg) }
+                }
+
+                ExprKind::StageWidth | ExprKind::StageHeight | ExprKind::MouseX | ExprKind::MouseY | ExprKind::Latitude | ExprKind::Longitude
+                | ExprKind::YPos | ExprKind::XPos | ExprKind::Heading | ExprKind::PenDown | ExprKind::Size | ExprKind::IsVisible
+                | ExprKind::This | ExprKind::Entity { .. } | ExprKind::ImageOfDrawings
+                | ExprKind::IsTouchingMouse | ExprKind::IsTouchingEdge | ExprKind::IsTouchingDrawings | ExprKind::RpcError
+                | ExprKind::Answer | ExprKind::Message | ExprKind::Timer | ExprKind::Effect { .. } | ExprKind::PenAttr { .. } | ExprKind::GlobalFlag { .. } | ExprKind::Tempo
+                | ExprKind::CostumeList | ExprKind::Costume | ExprKind::CostumeNumber | ExprKind::SoundList | ExprKind::RealTime { .. }
+                | ExprKind::JsFunction { .. } | ExprKind::EmptySlot => (),
+
+                ExprKind::KeyDown { key } => exprs(key),
+                ExprKind::ImageOfEntity { entity } | ExprKind::IsTouchingEntity { entity } => exprs(entity),
+
+                ExprKind::Closure { stmts: body, .. } => stmts(body),
+
+                ExprKind::TextSplit { text, mode } => {
+                    exprs(text);
+                    if let TextSplitMode::Custom(x) = mode { exprs(x) }
+                }
+
+                ExprKind::Map { f, list } | ExprKind::Keep { f, list } | ExprKind::FindFirst { f, list } | ExprKind::Combine { f, list } => { exprs(f); exprs(list); }
+                ExprKind::Pipe { value, functions } => { exprs(value); exprs(functions); }
+
+                ExprKind::NetworkMessageReply { target, values, .. } => {
+                    exprs(target);
+                    for (_, value) in values { exprs(value) }
+                }
+
+                ExprKind::CostumeName { costume } | ExprKind::CostumeWidth { costume } | ExprKind::CostumeHeight { costume } | ExprKind::CostumePixels { costume } => exprs(costume),
+                ExprKind::SoundName { sound } | ExprKind::SoundDuration { sound } | ExprKind::SoundSampleRate { sound }
+                | ExprKind::SoundSamples { sound } | ExprKind::SoundSamplesLength { sound } | ExprKind::SoundChannelCount { sound } => exprs(sound),
+
+                ExprKind::Clone { target } => exprs(target),
+
+                ExprKind::BlockAttribute { attr, block } => { exprs(attr); exprs(block); }
+                ExprKind::MappedCode { value } | ExprKind::TextFunction { value, .. } => exprs(value),
+
+                ExprKind::UnknownBlock { args, .. } => for arg in args { exprs(arg) },
+            }
+        }
+
+        /// Calls `exprs` on every expression directly held by `kind`, and `stmts` on every nested statement list (loop bodies, branches, etc.).
+        /// Lists are visited in evaluation order (e.g., a loop's bounds before its body).
+        pub(crate) fn $stmt_fn<'a>(kind: &'a $($m)? StmtKind, exprs: &mut dyn FnMut(&'a $($m)? Expr), stmts: &mut dyn FnMut(&'a $($m)? Vec<Stmt>)) {
+            match kind {
+                StmtKind::DeclareLocals { .. } | StmtKind::ShowVar { .. } | StmtKind::HideVar { .. } => (),
+                StmtKind::Assign { value, .. } | StmtKind::AddAssign { value, .. } => exprs(value),
+
+                StmtKind::Warp { stmts: body } | StmtKind::InfLoop { stmts: body } => stmts(body),
+                StmtKind::ForeachLoop { items, stmts: body, .. } => { exprs(items); stmts(body); }
+                StmtKind::ForLoop { start, stop, stmts: body, .. } => { exprs(start); exprs(stop); stmts(body); }
+                StmtKind::UntilLoop { condition, stmts: body } => { exprs(condition); stmts(body); }
+                StmtKind::Repeat { times, stmts: body, .. } => { exprs(times); stmts(body); }
+
+                StmtKind::If { condition, then } => { exprs(condition); stmts(then); }
+                StmtKind::IfElse { condition, then, otherwise } => { exprs(condition); stmts(then); stmts(otherwise); }
+
+                StmtKind::TryCatch { code, handler, .. } => { stmts(code); stmts(handler); }
+                StmtKind::Throw { error } => exprs(error),
+
+                StmtKind::ListInsert { list, value, index } | StmtKind::ListAssign { list, value, index } => { exprs(list); exprs(value); exprs(index); }
+                StmtKind::ListInsertLast { list, value } | StmtKind::ListInsertRandom { list, value }
+                | StmtKind::ListAssignLast { list, value } | StmtKind::ListAssignRandom { list, value } => { exprs(list); exprs(value); }
+                StmtKind::ListRemove { list, index } => { exprs(list); exprs(index); }
+                StmtKind::ListRemoveLast { list } | StmtKind::ListRemoveAll { list } => exprs(list),
+
+                StmtKind::Return { value } => exprs(value),
+
+                StmtKind::Sleep { seconds } => exprs(seconds),
+                StmtKind::WaitUntil { condition } => exprs(condition),
+
+                StmtKind::SetCostume { costume } => exprs(costume),
+                StmtKind::NextCostume => (),
+
+                StmtKind::PlaySound { sound, .. } => exprs(sound),
+                StmtKind::PlayNotes { notes, beats, .. } => { exprs(notes); exprs(beats); }
+                StmtKind::Rest { beats } => exprs(beats),
+                StmtKind::StopSounds => (),
+
+                StmtKind::Forward { distance } => exprs(distance),
+                StmtKind::SetX { value } | StmtKind::SetY { value } | StmtKind::SetHeading { value } | StmtKind::SetSize { value } | StmtKind::SetPenSize { value } => exprs(value),
+                StmtKind::ChangeX { delta } | StmtKind::ChangeY { delta } | StmtKind::ChangeSize { delta } | StmtKind::ChangePenSize { delta } => exprs(delta),
+                StmtKind::GotoXY { x, y } | StmtKind::PointTowardsXY { x, y } => { exprs(x); exprs(y); }
+                StmtKind::Goto { target } | StmtKind::PointTowards { target } | StmtKind::Clone { target } => exprs(target),
+                StmtKind::TurnRight { angle } | StmtKind::TurnLeft { angle } => exprs(angle),
+
+                StmtKind::GotoMouse | StmtKind::GotoRandom | StmtKind::SetHeadingRandom | StmtKind::BounceOffEdge
+                | StmtKind::SetPenDown { .. } | StmtKind::PenClear | StmtKind::Stamp | StmtKind::SetPenColor { .. } | StmtKind::SetVisible { .. }
+                | StmtKind::DeleteClone | StmtKind::ResetTimer | StmtKind::Pause | StmtKind::ClearEffects | StmtKind::Stop { .. } => (),
+
+                StmtKind::Write { content, font_size } => { exprs(content); exprs(font_size); }
+                StmtKind::Say { content, duration } | StmtKind::Think { content, duration } => {
+                    exprs(content);
+                    if let Some(duration) = duration { exprs(duration) }
+                }
+
+                StmtKind::CallRpc { args, .. } => for (_, arg) in args { exprs(arg) },
+                StmtKind::CallFn { args, .. } => for arg in args { exprs(arg) },
+                StmtKind::CallClosure { new_entity, closure, args } => {
+                    if let Some(new_entity) = new_entity { exprs(new_entity) }
+                    exprs(closure);
+                    for arg in args { exprs(arg) }
+                }
+                StmtKind::ForkClosure { closure, args } => {
+                    exprs(closure);
+                    for arg in args { exprs(arg) }
+                }
+
+                StmtKind::SendLocalMessage { target, msg_type, .. } => {
+                    if let Some(target) = target { exprs(target) }
+                    exprs(msg_type);
+                }
+                StmtKind::SendNetworkMessage { target, values, .. } => {
+                    exprs(target);
+                    for (_, value) in values { exprs(value) }
+                }
+                StmtKind::SendNetworkReply { value } => exprs(value),
+
+                StmtKind::Ask { prompt } => exprs(prompt),
+
+                StmtKind::SetEffect { value, .. } | StmtKind::SetPenAttr { value, .. } | StmtKind::SetGlobalFlag { value, .. } | StmtKind::SetTempo { value } => exprs(value),
+                StmtKind::ChangeEffect { delta, .. } | StmtKind::ChangePenAttr { delta, .. } | StmtKind::ChangeTempo { delta } => exprs(delta),
+
+                StmtKind::DefineBlock { definition, .. } => exprs(definition),
+                StmtKind::SetBlockAttribute { attr, block, value } => { exprs(attr); exprs(block); exprs(value); }
+                StmtKind::MapCode { block, code, .. } => { exprs(block); exprs(code); }
+
+                StmtKind::UnknownBlock { args, .. } => for arg in args { exprs(arg) },
+            }
+        }
+    };
+}
+impl_children! { expr_children, stmt_children }
+impl_children! { expr_children_mut, stmt_children_mut, mut }
+
+macro_rules! impl_refs {
+    ($ref:ident, $role_fn:ident, $stmts_fn:ident, $expr_fn:ident, $expr_children:ident, $stmt_children:ident $(, $m:tt)?) => {
+        /// A reference to a symbol from within some code.
+        pub(crate) enum $ref<'a> {
+            Var(&'a $($m)? VariableRef),
+            Fn(&'a $($m)? FnRef),
+            Entity { name: &'a $($m)? CompactString, trans_name: &'a $($m)? CompactString },
+        }
+
+        /// Calls `f` on every symbol reference in the code of `role`, along with the index of the entity containing the code (or `None` for global blocks).
+        pub(crate) fn $role_fn(role: &$($m)? Role, f: &mut dyn FnMut(Option<usize>, $ref)) {
+            for func in &$($m)? role.funcs {
+                $stmts_fn(&$($m)? func.stmts, &mut |x: $ref<'_>| f(None, x));
+            }
+            for (i, entity) in (&$($m)? role.entities).into_iter().enumerate() {
+                let f: &mut dyn FnMut($ref) = &mut |x: $ref<'_>| f(Some(i), x);
+                for func in &$($m)? entity.funcs {
+                    $stmts_fn(&$($m)? func.stmts, f);
+                }
+                for script in &$($m)? entity.scripts {
+                    if let Some(hat) = &$($m)? script.hat {
+                        match &$($m)? hat.kind {
+                            HatKind::When { condition } => $expr_fn(condition, f),
+                            HatKind::NetworkMessage { fields, .. } | HatKind::Unknown { fields, .. } | HatKind::Custom { fields, .. } => for field in fields { f($ref::Var(field)) },
+                            _ => (),
+                        }
+                    }
+                    $stmts_fn(&$($m)? script.stmts, f);
+                }
+                for expr in &$($m)? entity.floating_reporters {
+                    $expr_fn(expr, f);
+                }
+            }
+        }
+        pub(crate) fn $stmts_fn(stmts: &$($m)? Vec<Stmt>, f: &mut dyn FnMut($ref)) {
+            for stmt in stmts {
+                match &$($m)? stmt.kind {
+                    StmtKind::Assign { var, .. } | StmtKind::AddAssign { var, .. } | StmtKind::ShowVar { var } | StmtKind::HideVar { var }
+                    | StmtKind::ForeachLoop { var, .. } | StmtKind::ForLoop { var, .. } | StmtKind::TryCatch { var, .. } => f($ref::Var(var)),
+                    StmtKind::Repeat { counter: Some(var), .. } | StmtKind::DefineBlock { var, .. } => f($ref::Var(var)),
+                    StmtKind::CallFn { function, upvars, .. } => {
+                        f($ref::Fn(function));
+                        for var in upvars { f($ref::Var(var)) }
+                    }
+                    _ => (),
+                }
+
+                let mut exprs = Vec::new();
+                let mut bodies = Vec::new();
+                $stmt_children(&$($m)? stmt.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+                for expr in exprs { $expr_fn(expr, f) }
+                for body in bodies { $stmts_fn(body, f) }
+            }
+        }
+        pub(crate) fn $expr_fn(expr: &$($m)? Expr, f: &mut dyn FnMut($ref)) {
+            match &$($m)? expr.kind {
+                ExprKind::Variable { var } => f($ref::Var(var)),
+                ExprKind::CallFn { function, upvars, .. } => {
+                    f($ref::Fn(function));
+                    for var in upvars { f($ref::Var(var)) }
+                }
+                ExprKind::Closure { captures, .. } => for var in captures { f($ref::Var(var)) },
+                ExprKind::Entity { name, trans_name } => f($ref::Entity { name, trans_name }),
+                _ => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut bodies = Vec::new();
+            $expr_children(&$($m)? expr.kind, &mut |x| exprs.push(x), &mut |x| bodies.push(x));
+            for expr in exprs { $expr_fn(expr, f) }
+            for body in bodies { $stmts_fn(body, f) }
+        }
+    };
+}
+impl_refs! { SymbolRef, role_refs, stmts_refs, expr_refs, expr_children, stmt_children }
+impl_refs! { SymbolRefMut, role_refs_mut, stmts_refs_mut, expr_refs_mut, expr_children_mut, stmt_children_mut, mut }